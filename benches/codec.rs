@@ -0,0 +1,135 @@
+/*
+    Benchmarks for the MFM codec, track scanner, sector reads, CRC and image
+    loading hot paths, and sector reads from a shared image under concurrent
+    writes.
+
+    Run with `cargo bench`. Image loading benchmarks use the sample images in
+    tests/images.
+*/
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::shared::SharedDiskImage;
+use fluxfox::structure_parsers::system34::System34Parser;
+use fluxfox::structure_parsers::DiskStructureParser;
+use fluxfox::testutil::{track_stream, TestImage};
+use fluxfox::util::crc_ccitt;
+use fluxfox::{DiskCh, DiskChs, DiskImage, StandardFormat};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn bench_mfm_decode(c: &mut Criterion) {
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut stream = track_stream(&image, DiskCh::new(0, 0)).unwrap();
+    let markers = System34Parser::scan_track_markers(&mut stream);
+    System34Parser::create_clock_map(&markers, stream.clock_map_mut().unwrap());
+
+    c.bench_function("mfm_decode_track", |b| {
+        b.iter(|| {
+            let mut sum = 0u32;
+            for i in (0..stream.len()).step_by(16) {
+                sum = sum.wrapping_add(stream.read_decoded_byte(i).unwrap_or(0) as u32);
+            }
+            black_box(sum)
+        })
+    });
+}
+
+fn bench_scan_track(c: &mut Criterion) {
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut stream = track_stream(&image, DiskCh::new(0, 0)).unwrap();
+
+    c.bench_function("scan_track_markers", |b| {
+        b.iter(|| black_box(System34Parser::scan_track_markers(&mut stream)))
+    });
+
+    let markers = System34Parser::scan_track_markers(&mut stream);
+    System34Parser::create_clock_map(&markers, stream.clock_map_mut().unwrap());
+
+    c.bench_function("scan_track_metadata", |b| {
+        b.iter(|| black_box(System34Parser::scan_track_metadata(&mut stream, markers.clone())))
+    });
+}
+
+fn bench_read_sectors(c: &mut Criterion) {
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    c.bench_function("read_sector_track", |b| {
+        b.iter(|| {
+            for s in 1..=9 {
+                let rsr = image
+                    .read_sector(DiskChs::new(0, 0, s), None, RwSectorScope::DataOnly, false)
+                    .unwrap();
+                black_box(rsr.read_buf);
+            }
+        })
+    });
+}
+
+fn bench_shared_read_sectors(c: &mut Criterion) {
+    let shared = SharedDiskImage::new(TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap());
+    let stop = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        // Keep another track busy with writes, which should not slow reads of track 0.
+        scope.spawn(|| {
+            while !stop.load(Ordering::Relaxed) {
+                shared
+                    .write_sector(
+                        DiskChs::new(5, 0, 1),
+                        None,
+                        &[0xE5; 512],
+                        RwSectorScope::DataOnly,
+                        false,
+                        false,
+                    )
+                    .unwrap();
+            }
+        });
+
+        c.bench_function("shared_read_sector_track", |b| {
+            b.iter(|| {
+                for s in 1..=9 {
+                    let rsr = shared
+                        .read_sector(DiskChs::new(0, 0, s), None, RwSectorScope::DataOnly, false)
+                        .unwrap();
+                    black_box(rsr.read_buf);
+                }
+            })
+        });
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+fn bench_crc(c: &mut Criterion) {
+    let data = vec![0xE5u8; 512];
+
+    c.bench_function("crc_ccitt_512", |b| {
+        b.iter(|| black_box(crc_ccitt(black_box(&data), None)))
+    });
+}
+
+fn bench_load(c: &mut Criterion) {
+    for name in ["Transylvania.imd", "Transylvania.img"] {
+        let image_buf = std::fs::read(Path::new("tests").join("images").join(name)).unwrap();
+
+        c.bench_function(&format!("load_{}", name), |b| {
+            b.iter(|| {
+                let mut cursor = Cursor::new(&image_buf);
+                black_box(DiskImage::load(&mut cursor).unwrap())
+            })
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_mfm_decode,
+    bench_scan_track,
+    bench_read_sectors,
+    bench_shared_read_sectors,
+    bench_crc,
+    bench_load
+);
+criterion_main!(benches);
@@ -0,0 +1,185 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    examples/common/src/lib.rs
+
+    Code shared by the example utilities, including the structured JSON output
+    produced when the --json option is given.
+*/
+use bpaf::*;
+use fluxfox::diskimage::{SectorMapEntry, TrackMapEntry};
+use fluxfox::DiskImage;
+use serde::Serialize;
+
+/// The `--json` switch shared by all the example utilities.
+pub fn json_switch() -> impl Parser<bool> {
+    long("json").help("Print results as JSON instead of text").switch()
+}
+
+/// Routes the output of an example utility either to human-readable text or to a single JSON
+/// document on stdout.
+#[derive(Copy, Clone, Debug)]
+pub struct Output {
+    json: bool,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Self { json }
+    }
+
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    /// Print a line of human-readable text. Suppressed in JSON mode, so that stdout contains only
+    /// the JSON document.
+    pub fn text(&self, msg: impl AsRef<str>) {
+        if !self.json {
+            println!("{}", msg.as_ref());
+        }
+    }
+
+    /// Print `value` as the JSON result of the utility.
+    pub fn result<T: Serialize>(&self, value: &T) {
+        match serde_json::to_string_pretty(value) {
+            Ok(s) => println!("{}", s),
+            Err(e) => self.fail(format!("Error serializing result: {}", e)),
+        }
+    }
+
+    /// Report an error and exit. In JSON mode, the error is printed to stdout as
+    /// `{"error": "<message>"}`.
+    pub fn fail(&self, msg: impl AsRef<str>) -> ! {
+        if self.json {
+            println!("{}", serde_json::json!({ "error": msg.as_ref() }));
+        } else {
+            eprintln!("{}", msg.as_ref());
+        }
+        std::process::exit(1);
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SectorInfo {
+    pub cylinder: u16,
+    pub head: u8,
+    pub sector: u8,
+    pub n: u8,
+    pub size: usize,
+    pub address_crc_valid: bool,
+    pub data_crc_valid: bool,
+    pub deleted: bool,
+    pub no_dam: bool,
+}
+
+impl From<&SectorMapEntry> for SectorInfo {
+    fn from(entry: &SectorMapEntry) -> Self {
+        SectorInfo {
+            cylinder: entry.chsn.c(),
+            head: entry.chsn.h(),
+            sector: entry.chsn.s(),
+            n: entry.chsn.n(),
+            size: entry.chsn.n_size(),
+            address_crc_valid: entry.address_crc_valid,
+            data_crc_valid: entry.data_crc_valid,
+            deleted: entry.deleted_mark,
+            no_dam: entry.no_dam,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackInfo {
+    pub cylinder: u16,
+    pub head: u8,
+    pub encoding: String,
+    pub data_rate: String,
+    pub bitcells: usize,
+    pub len_bytes: usize,
+    pub sectors: Vec<SectorInfo>,
+}
+
+impl From<&TrackMapEntry> for TrackInfo {
+    fn from(entry: &TrackMapEntry) -> Self {
+        TrackInfo {
+            cylinder: entry.ch.c(),
+            head: entry.ch.h(),
+            encoding: entry.encoding.to_string(),
+            data_rate: entry.data_rate.to_string(),
+            bitcells: entry.bitcells,
+            len_bytes: entry.len_bytes,
+            sectors: entry.sectors.iter().map(SectorInfo::from).collect(),
+        }
+    }
+}
+
+/// A summary of a disk image, as printed by `imginfo --json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ImageInfo {
+    pub source_format: Option<String>,
+    pub cylinders: u16,
+    pub heads: u8,
+    pub data_rate: String,
+    pub data_encoding: String,
+    pub rpm: Option<String>,
+    pub volume_name: Option<String>,
+    pub comment: Option<String>,
+    pub has_weak_bits: bool,
+    /// The health score of the image, from 0 to 100, and its letter grade.
+    pub health_score: u8,
+    pub health_grade: String,
+    /// Every track of the image, by head and then by cylinder, if requested.
+    pub tracks: Option<Vec<TrackInfo>>,
+}
+
+impl ImageInfo {
+    pub fn new(image: &DiskImage, with_tracks: bool) -> Self {
+        let descriptor = image.image_format();
+        let health = image.health();
+        let tracks = with_tracks.then(|| image.get_sector_map().iter().flatten().map(TrackInfo::from).collect());
+
+        ImageInfo {
+            source_format: image.source_format().map(|f| f.to_string()),
+            cylinders: descriptor.geometry.c(),
+            heads: descriptor.geometry.h(),
+            data_rate: descriptor.data_rate.to_string(),
+            data_encoding: descriptor.data_encoding.to_string(),
+            rpm: descriptor.rpm.map(|rpm| rpm.to_string()),
+            volume_name: image.volume_name().map(String::from),
+            comment: image.get_comment().map(String::from),
+            has_weak_bits: image.has_weak_bits(),
+            health_score: health.score(),
+            health_grade: health.grade().to_string(),
+            tracks,
+        }
+    }
+}
+
+/// Format `data` as a string of hex digits, for including sector and track data in JSON output.
+pub fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -36,8 +36,10 @@
 mod prompt;
 
 use bpaf::*;
+use common::{json_switch, Output};
 use fluxfox::diskimage::DiskImageFlags;
 use fluxfox::{format_from_ext, DiskImage, ImageParser, ParserWriteCompatibility};
+use serde::Serialize;
 use std::io::Cursor;
 use std::path::PathBuf;
 
@@ -52,6 +54,17 @@ struct Out {
     out_filename: PathBuf,
     prolok: bool,
     debug: bool,
+    json: bool,
+}
+
+/// The result of a conversion, as printed with `--json`.
+#[derive(Serialize)]
+struct ConvertResult {
+    input_format: String,
+    output_format: String,
+    output_filename: PathBuf,
+    output_len: usize,
+    has_weak_bits: bool,
 }
 
 /// Set up bpaf argument parsing.
@@ -72,11 +85,14 @@ fn opts() -> OptionParser<Out> {
         .help("Create PROLOK holes for compatible formats")
         .switch();
 
+    let json = json_switch();
+
     construct!(Out {
         in_filename,
         out_filename,
         prolok,
         debug,
+        json,
     })
     .to_options()
     .descr("imgconvert: display info about disk image")
@@ -87,80 +103,67 @@ fn main() {
 
     // Get the command line options.
     let opts = opts().run();
+    let out = Output::new(opts.json);
     let disk_image = match std::fs::File::open(&opts.in_filename) {
         Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error opening file: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error opening file: {}", e)),
     };
 
     let mut reader = std::io::BufReader::new(disk_image);
 
     let disk_image_type = match DiskImage::detect_format(&mut reader) {
         Ok(disk_image_type) => disk_image_type,
-        Err(e) => {
-            eprintln!("Error detecting input disk image type: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error detecting input disk image type: {}", e)),
     };
 
-    println!("Input disk image type: {}", disk_image_type);
+    out.text(format!("Input disk image type: {}", disk_image_type));
 
     // Get extension from output filename
     let output_extension = match opts.out_filename.extension() {
         Some(ext) => ext,
-        None => {
-            eprintln!("Error: A file extension is required for the output file!");
-            std::process::exit(1);
-        }
+        None => out.fail("Error: A file extension is required for the output file!"),
     };
 
     let ext_str = match output_extension.to_str() {
         Some(ext) => ext,
-        None => {
-            eprintln!("Error: Invalid output file extension!");
-            std::process::exit(1);
-        }
+        None => out.fail("Error: Invalid output file extension!"),
     };
     let output_format = match format_from_ext(ext_str) {
         Some(format) => format,
-        None => {
-            eprintln!("Error: Unknown output file extension: {}", ext_str);
-            std::process::exit(1);
-        }
+        None => out.fail(format!("Error: Unknown output file extension: {}", ext_str)),
     };
 
-    println!("Output disk image type: {}", output_format);
+    out.text(format!("Output disk image type: {}", output_format));
     //std::process::exit(0);
 
     // Load disk image
     let mut in_disk = match DiskImage::load(&mut reader) {
         Ok(disk) => disk,
-        Err(e) => {
-            eprintln!("Error loading disk image: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error loading disk image: {}", e)),
     };
 
     if in_disk.has_weak_bits() {
-        println!("Input disk image contains a weak bit mask.");
+        out.text("Input disk image contains a weak bit mask.");
     }
 
     if opts.prolok {
         in_disk.set_flag(DiskImageFlags::PROLOK);
-        println!("PROLOK holes will be created in output image.");
+        out.text("PROLOK holes will be created in output image.");
     }
 
     match output_format.can_write(&in_disk) {
         ParserWriteCompatibility::Ok => {
-            println!("Output format is compatible with input image.");
-        }
-        ParserWriteCompatibility::Incompatible | ParserWriteCompatibility::UnsupportedFormat => {
-            eprintln!("Error: Output format {} cannot write specified image!", output_format);
-            std::process::exit(1);
+            out.text("Output format is compatible with input image.");
         }
+        ParserWriteCompatibility::Incompatible | ParserWriteCompatibility::UnsupportedFormat => out.fail(format!(
+            "Error: Output format {} cannot write specified image!",
+            output_format
+        )),
         ParserWriteCompatibility::DataLoss => {
+            // There is no one to answer the prompt when producing JSON for a pipeline.
+            if out.json() {
+                out.fail(format!("Error: Output format {} may lose data!", output_format));
+            }
             eprintln!("Warning: Output format {} may lose data!", output_format);
             prompt::prompt("Continue with potential data loss? (y/n)");
         }
@@ -171,19 +174,23 @@ fn main() {
     match output_format.save_image(&mut in_disk, &mut out_buffer) {
         Ok(_) => {
             let out_inner: Vec<u8> = out_buffer.into_inner();
+            let output_len = out_inner.len();
             match std::fs::write(opts.out_filename.clone(), out_inner) {
                 Ok(_) => {
-                    println!("Output image saved to {}", opts.out_filename.display());
-                }
-                Err(e) => {
-                    eprintln!("Error saving output image: {}", e);
-                    std::process::exit(1);
+                    out.text(format!("Output image saved to {}", opts.out_filename.display()));
+                    if out.json() {
+                        out.result(&ConvertResult {
+                            input_format: disk_image_type.to_string(),
+                            output_format: output_format.to_string(),
+                            output_filename: opts.out_filename.clone(),
+                            output_len,
+                            has_weak_bits: in_disk.has_weak_bits(),
+                        });
+                    }
                 }
+                Err(e) => out.fail(format!("Error saving output image: {}", e)),
             }
         }
-        Err(e) => {
-            eprintln!("Error saving output image: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error saving output image: {}", e)),
     }
 }
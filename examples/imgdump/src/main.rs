@@ -31,8 +31,10 @@
     format.
 */
 use bpaf::*;
+use common::{hex_string, json_switch, Output};
 use fluxfox::diskimage::RwSectorScope;
 use fluxfox::{DiskCh, DiskChs, DiskImage};
+use serde::Serialize;
 use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 
@@ -47,6 +49,33 @@ struct Out {
     n: Option<u8>,
     row_size: usize,
     structure: bool,
+    timeline: Option<String>,
+    json: bool,
+}
+
+/// The result of dumping a sector, as printed with `--json`.
+#[derive(Serialize)]
+struct SectorDump {
+    cylinder: u16,
+    head: u8,
+    sector: u8,
+    data_len: usize,
+    deleted: bool,
+    address_crc_error: bool,
+    data_crc_error: bool,
+    calculated_crc: Option<u16>,
+    data: String,
+}
+
+/// The result of dumping a track, as printed with `--json`.
+#[derive(Serialize)]
+struct TrackDump {
+    cylinder: u16,
+    head: u8,
+    sectors_read: u16,
+    address_crc_error: bool,
+    data_crc_error: bool,
+    data: String,
 }
 
 /// Set up bpaf argument parsing.
@@ -87,6 +116,13 @@ fn opts() -> OptionParser<Out> {
         .help("Dump IDAM header and data CRC in addition to data.")
         .switch();
 
+    let timeline = long("timeline")
+        .help("Print the layout of the track as 'text', 'svg' or 'dot' (GraphViz) instead of dumping it")
+        .argument::<String>("FORMAT")
+        .optional();
+
+    let json = json_switch();
+
     construct!(Out {
         debug,
         filename,
@@ -95,7 +131,9 @@ fn opts() -> OptionParser<Out> {
         sector,
         n,
         row_size,
-        structure
+        structure,
+        timeline,
+        json
     })
     .to_options()
     .descr("imginfo: display info about disk image")
@@ -106,32 +144,24 @@ fn main() {
 
     // Get the command line options.
     let opts = opts().run();
+    let out = Output::new(opts.json);
     let disk_image = match std::fs::File::open(&opts.filename) {
         Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error opening file: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error opening file: {}", e)),
     };
 
     let mut reader = std::io::BufReader::new(disk_image);
 
     let disk_image_type = match DiskImage::detect_format(&mut reader) {
         Ok(disk_image_type) => disk_image_type,
-        Err(e) => {
-            eprintln!("Error detecting disk image type: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error detecting disk image type: {}", e)),
     };
 
-    println!("Detected disk image type: {}", disk_image_type);
+    out.text(format!("Detected disk image type: {}", disk_image_type));
 
     let mut disk = match DiskImage::load(&mut reader) {
         Ok(disk) => disk,
-        Err(e) => {
-            eprintln!("Error loading disk image: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => out.fail(format!("Error loading disk image: {}", e)),
     };
 
     let handle = std::io::stdout();
@@ -147,45 +177,81 @@ fn main() {
             false => (RwSectorScope::DataOnly, false),
         };
 
-        println!("Dumping sector {} in hex format, with scope {:?}:", chs, scope);
+        out.text(format!("Dumping sector {} in hex format, with scope {:?}:", chs, scope));
 
         let rsr = match disk.read_sector(chs, opts.n, scope, true) {
             Ok(rsr) => rsr,
-            Err(e) => {
-                eprintln!("Error reading sector: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => out.fail(format!("Error reading sector: {}", e)),
         };
 
-        _ = writeln!(&mut buf, "Data length: {}", rsr.data_len);
-
         let data_slice = match scope {
             RwSectorScope::DataOnly => &rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len],
             RwSectorScope::DataBlock => &rsr.read_buf,
         };
 
-        _ = fluxfox::util::dump_slice(data_slice, 0, opts.row_size, &mut buf);
-
         // If we requested DataBlock scope, we can independently calculate the CRC, so do that now.
-        if calc_crc {
-            let calculated_crc = fluxfox::util::crc_ccitt(&data_slice[0..0x104], None);
+        let calculated_crc = calc_crc.then(|| fluxfox::util::crc_ccitt(&data_slice[0..0x104], None));
+
+        if out.json() {
+            out.result(&SectorDump {
+                cylinder: chs.c(),
+                head: chs.h(),
+                sector: chs.s(),
+                data_len: rsr.data_len,
+                deleted: rsr.deleted_mark,
+                address_crc_error: rsr.address_crc_error,
+                data_crc_error: rsr.data_crc_error,
+                calculated_crc,
+                data: hex_string(data_slice),
+            });
+            return;
+        }
+
+        _ = writeln!(&mut buf, "Data length: {}", rsr.data_len);
+        _ = fluxfox::util::dump_slice(data_slice, 0, opts.row_size, &mut buf);
+        if let Some(calculated_crc) = calculated_crc {
             _ = writeln!(&mut buf, "Calculated CRC: {:04X}", calculated_crc);
         }
+    } else if let Some(format) = &opts.timeline {
+        if out.json() {
+            out.fail("--timeline cannot be combined with --json");
+        }
+        let ch = DiskCh::new(opts.cylinder, opts.head);
+        let timeline = match disk.track_timeline(ch) {
+            Ok(timeline) => timeline,
+            Err(e) => out.fail(format!("Error reading track layout: {}", e)),
+        };
+        let rendered = match format.as_str() {
+            "text" => timeline.render_text(100),
+            "svg" => timeline.render_svg(1600),
+            "dot" => timeline.render_dot(),
+            _ => out.fail(format!("Unknown timeline format: {}", format)),
+        };
+        _ = write!(&mut buf, "{}", rendered);
     } else {
         // No sector was provided, dump the whole track.
 
         let ch = DiskCh::new(opts.cylinder, opts.head);
 
-        println!("Dumping track {} in hex format:", ch);
+        out.text(format!("Dumping track {} in hex format:", ch));
 
         let rtr = match disk.read_track(ch) {
             Ok(rtr) => rtr,
-            Err(e) => {
-                eprintln!("Error reading track: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => out.fail(format!("Error reading track: {}", e)),
         };
 
+        if out.json() {
+            out.result(&TrackDump {
+                cylinder: ch.c(),
+                head: ch.h(),
+                sectors_read: rtr.sectors_read,
+                address_crc_error: rtr.address_crc_error,
+                data_crc_error: rtr.data_crc_error,
+                data: hex_string(&rtr.read_buf),
+            });
+            return;
+        }
+
         _ = fluxfox::util::dump_slice(&rtr.read_buf, 0, opts.row_size, &mut buf);
     }
 }
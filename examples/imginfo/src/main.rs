@@ -31,6 +31,7 @@
 */
 use bpaf::*;
 
+use common::{json_switch, ImageInfo, Output};
 use fluxfox::DiskImage;
 use std::path::PathBuf;
 
@@ -39,6 +40,10 @@ use std::path::PathBuf;
 struct Out {
     debug: bool,
     sector_list: bool,
+    sector_map: bool,
+    sector_grid: bool,
+    layout: bool,
+    json: bool,
     filename: PathBuf,
 }
 
@@ -51,6 +56,23 @@ fn opts() -> OptionParser<Out> {
         .help("List all sectors in the image")
         .switch();
 
+    let sector_map = short('m')
+        .long("sector-map")
+        .help("Print a compact sector map, one track per line")
+        .switch();
+
+    let sector_grid = short('g')
+        .long("sector-grid")
+        .help("Print a grid of sector status, one track per row")
+        .switch();
+
+    let layout = short('l')
+        .long("layout")
+        .help("Print the sector order, interleave, skew and GAP3 of each track, and the duplicator signature")
+        .switch();
+
+    let json = json_switch();
+
     let filename = short('t')
         .long("filename")
         .help("Filename of image to read")
@@ -59,6 +81,10 @@ fn opts() -> OptionParser<Out> {
     construct!(Out {
         debug,
         sector_list,
+        sector_map,
+        sector_grid,
+        layout,
+        json,
         filename
     })
     .to_options()
@@ -70,39 +96,40 @@ fn main() {
 
     // Get the command line options.
     let opts = opts().run();
+    let out = Output::new(opts.json);
 
     let disk_image = match std::fs::File::open(&opts.filename) {
         Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error opening file: {}", e);
-            return;
-        }
+        Err(e) => out.fail(format!("Error opening file: {}", e)),
     };
 
     let mut reader = std::io::BufReader::new(disk_image);
 
     let disk_image_type = match DiskImage::detect_format(&mut reader) {
         Ok(disk_image_type) => disk_image_type,
-        Err(e) => {
-            eprintln!("Error detecting disk image type: {}", e);
-            return;
-        }
+        Err(e) => out.fail(format!("Error detecting disk image type: {}", e)),
     };
 
-    println!("Detected disk image type: {}", disk_image_type);
+    out.text(format!("Detected disk image type: {}", disk_image_type));
 
     let mut disk = match DiskImage::load(&mut reader) {
         Ok(disk) => disk,
-        Err(e) => {
-            eprintln!("Error loading disk image: {}", e);
-            return;
-        }
+        Err(e) => out.fail(format!("Error loading disk image: {}", e)),
     };
 
+    if out.json() {
+        out.result(&ImageInfo::new(&disk, opts.sector_list || opts.sector_map));
+        return;
+    }
+
     println!("Disk image info:");
     println!("--------------------------------------------------------------------------------");
     let _ = disk.dump_info(&mut std::io::stdout());
     println!();
+    print!("{}", disk.health());
+    println!();
+    print!("{}", disk.detect_platform());
+    println!();
 
     if let Some(bootsector) = disk.boot_sector() {
         println!("Boot sector detected:");
@@ -115,6 +142,21 @@ fn main() {
         let _ = disk.dump_sector_map(&mut std::io::stdout());
     }
 
+    if opts.sector_map {
+        let _ = disk.dump_sector_map_compact(&mut std::io::stdout());
+    }
+
+    if opts.sector_grid {
+        print!("{}", disk.render_sector_map_text(80));
+    }
+
+    if opts.layout {
+        for layout in disk.track_layouts() {
+            println!("{}", layout);
+        }
+        print!("{}", disk.duplicator_report());
+    }
+
     /*    for track in disk.track_pool.iter_mut() {
         match &mut track.data {
             TrackData::BitStream { data, .. } => {
@@ -0,0 +1,389 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    examples/imgmaster/src/main.rs
+
+    This is an example of how to use FluxFox to master a copy-protected disk
+    from scratch. A formatted 360K bitstream image is created, and several of
+    its tracks are replaced with tracks assembled by a TrackBuilder:
+
+      Cylinder 1: a sector with a run of weak bits in its data
+      Cylinder 2: a long track holding 10 sectors
+      Cylinder 3: a sector with a bad data CRC, and one with a bad ID CRC
+      Cylinder 4: two sectors with the same ID and different data
+
+    The mastered disk is read back to check each feature, then exported as
+    86F and HFE images, which can be used as test vectors for emulators and
+    copy tools.
+*/
+use bpaf::*;
+use common::{json_switch, Output};
+use fluxfox::bitstream::mfm::{MfmCodec, MfmEncodingType};
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::util::crc_ccitt;
+use fluxfox::{
+    DiskCh, DiskChs, DiskDataResolution, DiskImage, DiskImageError, DiskImageFormat, ImageParser,
+    ParserWriteCompatibility, StandardFormat,
+};
+use serde::Serialize;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::PathBuf;
+
+const SECTOR_N: u8 = 2;
+const SECTOR_SIZE: usize = 512;
+const SECTORS: u8 = 9;
+const GAP3: usize = 80;
+
+const WEAK_TRACK: u16 = 1;
+const WEAK_SECTOR: u8 = 3;
+const WEAK_RANGE: Range<usize> = 128..160;
+
+const LONG_TRACK: u16 = 2;
+const LONG_TRACK_SECTORS: u8 = 10;
+// A standard 300 RPM double density track is 100,000 bitcells long.
+const LONG_TRACK_BITCELLS: usize = 110_000;
+
+const BAD_CRC_TRACK: u16 = 3;
+const BAD_DATA_CRC_SECTOR: u8 = 2;
+const BAD_ID_CRC_SECTOR: u8 = 5;
+
+const DUPLICATE_TRACK: u16 = 4;
+const DUPLICATE_SECTOR: u8 = 1;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+struct Out {
+    debug: bool,
+    out_dir: PathBuf,
+    name: String,
+    json: bool,
+}
+
+/// The result of mastering, as printed with `--json`.
+#[derive(Serialize)]
+struct MasterResult {
+    checks: Vec<String>,
+    images: Vec<WrittenImage>,
+}
+
+#[derive(Serialize)]
+struct WrittenImage {
+    format: String,
+    filename: PathBuf,
+    len: usize,
+    data_loss: bool,
+}
+
+/// A sector to be mastered, and the ways in which it is damaged.
+struct MasterSector {
+    id: u8,
+    data: Vec<u8>,
+    bad_id_crc: bool,
+    bad_data_crc: bool,
+    /// A range of bytes of the data written as weak bits.
+    weak: Option<Range<usize>>,
+}
+
+impl MasterSector {
+    fn new(c: u16, s: u8) -> Self {
+        MasterSector {
+            id: s,
+            data: sector_data(c, s, 0),
+            bad_id_crc: false,
+            bad_data_crc: false,
+            weak: None,
+        }
+    }
+}
+
+/// Return recognizable contents for sector `s` of cylinder `c`. Copies of a duplicated sector
+/// are distinguished by `copy`.
+fn sector_data(c: u16, s: u8, copy: u8) -> Vec<u8> {
+    let mut data = format!("C:{} S:{} COPY:{}", c, s, copy).into_bytes();
+    data.extend((data.len()..SECTOR_SIZE).map(|i| (i as u8) ^ s ^ copy.wrapping_mul(0x55)));
+    data
+}
+
+/// Set up bpaf argument parsing.
+fn opts() -> OptionParser<Out> {
+    let debug = short('d').long("debug").help("Print debug messages").switch();
+
+    let out_dir = short('o')
+        .long("out_dir")
+        .help("Directory to write the mastered images to")
+        .argument::<PathBuf>("DIR")
+        .fallback(PathBuf::from("."));
+
+    let name = short('n')
+        .long("name")
+        .help("Base filename of the mastered images")
+        .argument::<String>("NAME")
+        .fallback("protected".to_string());
+
+    let json = json_switch();
+
+    construct!(Out {
+        debug,
+        out_dir,
+        name,
+        json
+    })
+    .to_options()
+    .descr("imgmaster: master a copy-protected test disk and export it as 86F and HFE")
+}
+
+/// Begin a track with the standard IBM preamble: GAP4A, a sync field, the index address mark
+/// and GAP1.
+fn track_preamble() -> TrackBuilder {
+    TrackBuilder::new()
+        .with_gap(0x4E, 80)
+        .with_gap(0x00, 12)
+        .with_encoded(&[0xC2, 0xC2, 0xC2, 0xFC], MfmEncodingType::AddressMark)
+        .with_gap(0x4E, 50)
+}
+
+/// Append the ID and data fields of `sector` to `builder`, followed by GAP3.
+fn push_sector(builder: TrackBuilder, ch: DiskCh, sector: &MasterSector) -> Result<TrackBuilder, DiskImageError> {
+    let id = [0xA1, 0xA1, 0xA1, 0xFE, ch.c() as u8, ch.h(), sector.id, SECTOR_N];
+    let mut id_crc = crc_ccitt(&id, None);
+    if sector.bad_id_crc {
+        id_crc ^= 0xFFFF;
+    }
+
+    let mut builder = builder
+        .with_gap(0x00, 12)
+        .with_encoded(&id[..4], MfmEncodingType::AddressMark)
+        .with_encoded(&id[4..], MfmEncodingType::Data)
+        .with_encoded(&id_crc.to_be_bytes(), MfmEncodingType::Data)
+        .with_gap(0x4E, 22)
+        .with_gap(0x00, 12)
+        .with_encoded(&[0xA1, 0xA1, 0xA1, 0xFB], MfmEncodingType::AddressMark);
+
+    let mut field = vec![0xA1, 0xA1, 0xA1, 0xFB];
+    field.extend(&sector.data);
+    let mut data_crc = crc_ccitt(&field, None);
+    if sector.bad_data_crc {
+        data_crc ^= 0xFFFF;
+    }
+
+    match &sector.weak {
+        Some(range) => {
+            // Weak bits can only be appended as raw bitcells, so encode the weak region here,
+            // continuing the clock from the last bit of the preceding byte.
+            let prev_byte = field[range.start + 3];
+            let bits = MfmCodec::encode_mfm(&sector.data[range.clone()], prev_byte & 1 != 0, MfmEncodingType::Data);
+            builder = builder
+                .with_encoded(&sector.data[..range.start], MfmEncodingType::Data)
+                .with_bits(&bits.to_bytes(), bits.len(), Some(&vec![0xFF; range.len() * 2]))?
+                .with_encoded(&sector.data[range.end..], MfmEncodingType::Data);
+        }
+        None => builder = builder.with_encoded(&sector.data, MfmEncodingType::Data),
+    }
+
+    Ok(builder
+        .with_encoded(&data_crc.to_be_bytes(), MfmEncodingType::Data)
+        .with_gap(0x4E, GAP3))
+}
+
+/// Replace the track `ch` of `image` with a track holding `sectors` in order. The track keeps
+/// its length unless `bitcell_ct` is specified.
+fn master_track(
+    image: &mut DiskImage,
+    ch: DiskCh,
+    sectors: &[MasterSector],
+    bitcell_ct: Option<usize>,
+) -> Result<(), DiskImageError> {
+    let mut builder = track_preamble();
+    if let Some(bitcell_ct) = bitcell_ct {
+        builder = builder.with_bitcell_ct(bitcell_ct);
+    }
+    for sector in sectors {
+        builder = push_sector(builder, ch, sector)?;
+    }
+    log::debug!("master_track(): Track {} uses {} bitcells", ch, builder.bitcell_len());
+    image.splice_track(ch, &builder)
+}
+
+/// Create the protected disk image.
+fn master_image() -> Result<DiskImage, DiskImageError> {
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()?;
+    let standard_sectors = |c: u16| (1..=SECTORS).map(|s| MasterSector::new(c, s)).collect::<Vec<_>>();
+
+    let mut sectors = standard_sectors(WEAK_TRACK);
+    sectors[WEAK_SECTOR as usize - 1].weak = Some(WEAK_RANGE);
+    master_track(&mut image, DiskCh::new(WEAK_TRACK, 0), &sectors, None)?;
+
+    let sectors = (1..=LONG_TRACK_SECTORS)
+        .map(|s| MasterSector::new(LONG_TRACK, s))
+        .collect::<Vec<_>>();
+    master_track(
+        &mut image,
+        DiskCh::new(LONG_TRACK, 0),
+        &sectors,
+        Some(LONG_TRACK_BITCELLS),
+    )?;
+
+    let mut sectors = standard_sectors(BAD_CRC_TRACK);
+    sectors[BAD_DATA_CRC_SECTOR as usize - 1].bad_data_crc = true;
+    sectors[BAD_ID_CRC_SECTOR as usize - 1].bad_id_crc = true;
+    master_track(&mut image, DiskCh::new(BAD_CRC_TRACK, 0), &sectors, None)?;
+
+    // Drop the last sector to make room for the second copy of the duplicated sector.
+    let mut sectors = standard_sectors(DUPLICATE_TRACK);
+    sectors.pop();
+    sectors.insert(
+        sectors.len() / 2,
+        MasterSector {
+            data: sector_data(DUPLICATE_TRACK, DUPLICATE_SECTOR, 1),
+            ..MasterSector::new(DUPLICATE_TRACK, DUPLICATE_SECTOR)
+        },
+    );
+    master_track(&mut image, DiskCh::new(DUPLICATE_TRACK, 0), &sectors, None)?;
+
+    Ok(image)
+}
+
+/// Read back each protection feature of the mastered image, returning a line describing each.
+fn check_image(image: &mut DiskImage) -> Result<Vec<String>, DiskImageError> {
+    let mut checks = Vec::new();
+    let read = |image: &mut DiskImage, c: u16, s: u8| {
+        image.read_sector(DiskChs::new(c, 0, s), Some(SECTOR_N), RwSectorScope::DataOnly, false)
+    };
+
+    let first = read(image, WEAK_TRACK, WEAK_SECTOR)?;
+    let second = read(image, WEAK_TRACK, WEAK_SECTOR)?;
+    let weak_bytes = first
+        .weak_mask
+        .as_ref()
+        .map_or(0, |mask| mask.iter().filter(|&&b| b != 0).count());
+    checks.push(format!(
+        "Weak sector C:{} S:{}: {} weak bytes, repeated reads {}",
+        WEAK_TRACK,
+        WEAK_SECTOR,
+        weak_bytes,
+        if first.read_buf == second.read_buf {
+            "match"
+        } else {
+            "differ"
+        }
+    ));
+
+    let long_ch = DiskCh::new(LONG_TRACK, 0);
+    let bitcell_ct = image.get_track_ch(long_ch).map_or(0, |track| track.bitcell_ct());
+    let sector_ct = image.get_sector_map()[0][LONG_TRACK as usize].sectors.len();
+    checks.push(format!(
+        "Long track {}: {} bitcells, {} sectors",
+        long_ch, bitcell_ct, sector_ct
+    ));
+
+    for s in [BAD_DATA_CRC_SECTOR, BAD_ID_CRC_SECTOR] {
+        let result = read(image, BAD_CRC_TRACK, s)?;
+        checks.push(format!(
+            "Bad CRC sector C:{} S:{}: address CRC error: {}, data CRC error: {}",
+            BAD_CRC_TRACK, s, result.address_crc_error, result.data_crc_error
+        ));
+    }
+
+    let sector_map = image.get_sector_map();
+    let duplicate = &sector_map[0][DUPLICATE_TRACK as usize];
+    let copies = duplicate
+        .sectors
+        .iter()
+        .filter(|sector| sector.chsn.s() == DUPLICATE_SECTOR)
+        .count();
+    checks.push(format!(
+        "Duplicate sector C:{} S:{}: {} copies in sector order {}",
+        DUPLICATE_TRACK, DUPLICATE_SECTOR, copies, duplicate
+    ));
+
+    Ok(checks)
+}
+
+fn main() {
+    env_logger::init();
+
+    // Get the command line options.
+    let opts = opts().run();
+    let out = Output::new(opts.json);
+
+    let mut image = match master_image() {
+        Ok(image) => image,
+        Err(e) => out.fail(format!("Error mastering disk image: {}", e)),
+    };
+
+    let checks = match check_image(&mut image) {
+        Ok(checks) => checks,
+        Err(e) => out.fail(format!("Error reading back mastered disk image: {}", e)),
+    };
+    for check in &checks {
+        out.text(check);
+    }
+
+    let mut images = Vec::new();
+    for (format, ext) in [(DiskImageFormat::F86Image, "86f"), (DiskImageFormat::HfeImage, "hfe")] {
+        let data_loss = match format.can_write(&image) {
+            ParserWriteCompatibility::Ok => false,
+            ParserWriteCompatibility::DataLoss => {
+                out.text(format!("Warning: {} cannot store every feature of the disk.", format));
+                true
+            }
+            _ => {
+                out.text(format!("Skipping {}: format cannot store this disk.", format));
+                continue;
+            }
+        };
+
+        let mut buf = Cursor::new(Vec::new());
+        if let Err(e) = format.save_image(&image, &mut buf) {
+            out.fail(format!("Error saving {} image: {}", format, e));
+        }
+        let buf = buf.into_inner();
+
+        let filename = opts.out_dir.join(format!("{}.{}", opts.name, ext));
+        if let Err(e) = std::fs::write(&filename, &buf) {
+            out.fail(format!("Error writing {}: {}", filename.display(), e));
+        }
+        out.text(format!("Wrote {} ({} bytes)", filename.display(), buf.len()));
+
+        images.push(WrittenImage {
+            format: format.to_string(),
+            filename,
+            len: buf.len(),
+            data_loss,
+        });
+    }
+
+    if out.json() {
+        out.result(&MasterResult { checks, images });
+    }
+}
@@ -0,0 +1,160 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+
+    src/analysis.rs
+
+    Analysis of the flux timing of captured tracks. Measures how far each flux
+    transition strays from the bitcell grid, by angular position, to expose
+    degraded regions of a disk and the splice points of written tracks.
+*/
+
+use crate::flux::FluxRevolution;
+use crate::{DiskCh, DiskImage};
+
+#[cfg(feature = "viz")]
+use crate::DiskImageError;
+#[cfg(feature = "viz")]
+use image::{Rgba, RgbaImage};
+
+/// A [`TimingMap`] holds the bitcell timing deviation of each track of one head of a disk, as
+/// returned by [`timing_map`]. Each track is divided into bins of equal angle starting at the
+/// index, and each bin holds the RMS deviation of the flux transitions within it.
+///
+/// The deviation of a transition is the difference between its interval and the nearest whole
+/// number of bitcells, relative to that number of bitcells. Drive speed variation, weak or
+/// degraded media and splice points all show as increased deviation.
+#[derive(Clone, Debug)]
+pub struct TimingMap {
+    pub head: u8,
+    pub bins: usize,
+    /// The deviation of each bin of each cylinder of the head, or `None` for tracks with no flux.
+    pub tracks: Vec<Option<Vec<f64>>>,
+}
+
+impl TimingMap {
+    /// Return the largest deviation of any bin of the map, or 0.0 if there are none.
+    pub fn max_deviation(&self) -> f64 {
+        self.tracks.iter().flatten().flatten().copied().fold(0.0, f64::max)
+    }
+}
+
+/// Measure the bitcell timing deviation of each track of `head` with flux, divided into `bins`
+/// bins per revolution. See [`TimingMap`].
+pub fn timing_map(disk_image: &DiskImage, head: u8, bins: usize) -> TimingMap {
+    let bins = bins.max(1);
+    let tracks = (0..disk_image.get_track_ct(head as usize))
+        .map(|c| {
+            let track = disk_image.get_track_ch(DiskCh::new(c as u16, head))?;
+            flux_deviation(track.flux()?, track.bitcell_ct(), bins)
+        })
+        .collect();
+    TimingMap { head, bins, tracks }
+}
+
+/// Calculate the RMS deviation of the transitions in `flux` in each of `bins` bins. The bitcell
+/// time is taken as the duration of the revolution over `bitcell_ct`, the length of the track
+/// resolved from it, so that a drive running uniformly fast or slow shows no deviation.
+fn flux_deviation(flux: &FluxRevolution, bitcell_ct: usize, bins: usize) -> Option<Vec<f64>> {
+    let duration = flux.duration();
+    if duration <= 0.0 || bitcell_ct == 0 {
+        return None;
+    }
+    let cell_time = duration / bitcell_ct as f64;
+
+    let mut sums = vec![0.0; bins];
+    let mut counts = vec![0usize; bins];
+    let mut time = 0.0;
+    for interval in flux.iter_seconds() {
+        time += interval;
+        let cells = (interval / cell_time).round().max(1.0);
+        let deviation = interval / (cells * cell_time) - 1.0;
+        let bin = ((time / duration * bins as f64) as usize).min(bins - 1);
+        sums[bin] += deviation * deviation;
+        counts[bin] += 1;
+    }
+
+    Some(
+        sums.iter()
+            .zip(&counts)
+            .map(|(sum, &ct)| if ct > 0 { (sum / ct as f64).sqrt() } else { 0.0 })
+            .collect(),
+    )
+}
+
+/// Render a heatmap of the bitcell timing deviation of `head` as an image of `image_size`. The
+/// angle from the index runs left to right, and cylinders top to bottom. Deviation is colored
+/// from blue through yellow to red, reaching red at `max_deviation`, or at the largest deviation
+/// of the map if `max_deviation` is `None`. Tracks without flux are left transparent.
+///
+/// The result can be saved as a PNG with [`RgbaImage::save`].
+///
+/// # Returns
+/// - `Err(DiskImageError::UnsupportedFormat)` if no track of `head` has flux.
+/// - `Err(DiskImageError::ParameterError)` if `image_size` is empty.
+#[cfg(feature = "viz")]
+pub fn render_timing_map(
+    disk_image: &DiskImage,
+    head: u8,
+    image_size: (u32, u32),
+    max_deviation: Option<f64>,
+) -> Result<RgbaImage, DiskImageError> {
+    let (width, height) = image_size;
+    if width == 0 || height == 0 {
+        return Err(DiskImageError::ParameterError);
+    }
+    let map = timing_map(disk_image, head, width as usize);
+    if map.tracks.iter().all(Option::is_none) {
+        return Err(DiskImageError::UnsupportedFormat);
+    }
+    let max_deviation = max_deviation.unwrap_or_else(|| map.max_deviation());
+
+    let mut image = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 0]));
+    let track_ct = map.tracks.len();
+    for y in 0..height {
+        let c = (y as usize * track_ct) / height as usize;
+        let Some(bins) = &map.tracks[c] else {
+            continue;
+        };
+        for (x, deviation) in bins.iter().enumerate() {
+            let level = match max_deviation > 0.0 {
+                true => (deviation / max_deviation).min(1.0),
+                false => 0.0,
+            };
+            image.put_pixel(x as u32, y, heat_color(level));
+        }
+    }
+    Ok(image)
+}
+
+/// Map `level`, from 0.0 to 1.0, to a color ramp from blue through yellow to red.
+#[cfg(feature = "viz")]
+fn heat_color(level: f64) -> Rgba<u8> {
+    let (from, to, t) = match level < 0.5 {
+        true => ([0.0, 0.0, 255.0], [255.0, 255.0, 0.0], level * 2.0),
+        false => ([255.0, 255.0, 0.0], [255.0, 0.0, 0.0], level * 2.0 - 1.0),
+    };
+    let mix = |i: usize| (from[i] + (to[i] - from[i]) * t).round() as u8;
+    Rgba([mix(0), mix(1), mix(2), 255])
+}
@@ -0,0 +1,356 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+
+    --------------------------------------------------------------------------
+
+    src/batch.rs
+
+    Batch conversion of disk image files, producing a manifest of the result
+    of each conversion for archive migration projects.
+*/
+
+use crate::convert::{ConvertIssue, ConvertPolicy};
+use crate::diskimage::LoadOptions;
+use crate::file_parsers::{ImageParser, ParserWriteCompatibility};
+use crate::progress::Progress;
+use crate::{DiskImage, DiskImageError, DiskImageFormat};
+use std::fmt::{self, Display, Formatter};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A single conversion of an input image file to the specified format.
+#[derive(Clone, Debug)]
+pub struct ConvertJob {
+    pub input: PathBuf,
+    pub format: DiskImageFormat,
+    /// The path of the output file. If not set, the input path is used with its extension replaced
+    /// by the default extension of `format`.
+    pub output: Option<PathBuf>,
+}
+
+impl ConvertJob {
+    pub fn new(input: impl Into<PathBuf>, format: DiskImageFormat) -> Self {
+        Self {
+            input: input.into(),
+            format,
+            output: None,
+        }
+    }
+
+    pub fn with_output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Return the path the job will write to, or `None` if no output path was given and `format`
+    /// has no file extension.
+    pub fn output_path(&self) -> Option<PathBuf> {
+        match &self.output {
+            Some(output) => Some(output.clone()),
+            None => self
+                .format
+                .extensions()
+                .first()
+                .map(|ext| self.input.with_extension(ext)),
+        }
+    }
+}
+
+/// Options for [`convert_batch`].
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    /// The number of jobs to run in parallel. Values less than 1 are treated as 1.
+    pub threads: usize,
+    /// The options used to load each input image.
+    pub load_options: LoadOptions,
+    /// The policy applied to features an output format cannot represent.
+    pub policy: ConvertPolicy,
+    /// Treat conversions the output format reports as lossy as failures instead of warnings.
+    pub fail_on_data_loss: bool,
+    /// Replace output files that already exist. By default such jobs fail, so that a batch can
+    /// never overwrite its own inputs.
+    pub overwrite: bool,
+    /// Progress is reported per job. If cancelled, jobs not yet started fail with
+    /// [`DiskImageError::Cancelled`].
+    pub progress: Progress,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            load_options: LoadOptions::default(),
+            policy: ConvertPolicy::default(),
+            fail_on_data_loss: false,
+            overwrite: false,
+            progress: Progress::default(),
+        }
+    }
+}
+
+/// The step of a conversion job at which it failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BatchStage {
+    /// Opening or loading the input image.
+    Load,
+    /// Checking that the output format can represent the image.
+    Check,
+    /// Encoding the image in the output format.
+    Save,
+    /// Writing the output file.
+    Write,
+}
+
+impl Display for BatchStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchStage::Load => write!(f, "load"),
+            BatchStage::Check => write!(f, "check"),
+            BatchStage::Save => write!(f, "save"),
+            BatchStage::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// The cause of a failed conversion job.
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub stage: BatchStage,
+    pub error: DiskImageError,
+}
+
+impl Display for BatchFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.stage, self.error)
+    }
+}
+
+/// Something lost in a conversion that otherwise succeeded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BatchWarning {
+    /// The output format reported that it cannot represent everything in the image.
+    DataLoss,
+    /// A sector was altered by the conversion policy.
+    Issue(ConvertIssue),
+}
+
+impl Display for BatchWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchWarning::DataLoss => write!(f, "output format may lose data"),
+            BatchWarning::Issue(issue) => write!(f, "{}", issue),
+        }
+    }
+}
+
+/// The result of a single conversion job.
+#[derive(Debug)]
+pub struct BatchEntry {
+    pub job: ConvertJob,
+    /// The path written, if the job got as far as resolving one.
+    pub output: Option<PathBuf>,
+    pub warnings: Vec<BatchWarning>,
+    pub result: Result<(), BatchFailure>,
+}
+
+impl BatchEntry {
+    pub fn succeeded(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl Display for BatchEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let output = self
+            .output
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "?".to_string());
+        match &self.result {
+            Ok(()) if self.warnings.is_empty() => write!(f, "OK   {} -> {}", self.job.input.display(), output),
+            Ok(()) => {
+                write!(f, "WARN {} -> {}", self.job.input.display(), output)?;
+                for warning in &self.warnings {
+                    write!(f, "\n\t{}", warning)?;
+                }
+                Ok(())
+            }
+            Err(failure) => write!(f, "FAIL {}: {}", self.job.input.display(), failure),
+        }
+    }
+}
+
+/// The manifest of a batch conversion, with one entry per job in the order the jobs were given.
+#[derive(Debug, Default)]
+pub struct BatchManifest {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchManifest {
+    /// Return an iterator over the jobs that succeeded without warnings.
+    pub fn clean(&self) -> impl Iterator<Item = &BatchEntry> {
+        self.entries.iter().filter(|e| e.succeeded() && e.warnings.is_empty())
+    }
+
+    /// Return an iterator over the jobs that succeeded with warnings.
+    pub fn warned(&self) -> impl Iterator<Item = &BatchEntry> {
+        self.entries.iter().filter(|e| e.succeeded() && !e.warnings.is_empty())
+    }
+
+    /// Return an iterator over the jobs that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &BatchEntry> {
+        self.entries.iter().filter(|e| !e.succeeded())
+    }
+
+    /// Return true if every job succeeded without warnings.
+    pub fn is_clean(&self) -> bool {
+        self.entries.iter().all(|e| e.succeeded() && e.warnings.is_empty())
+    }
+}
+
+impl Display for BatchManifest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        write!(
+            f,
+            "{} converted, {} with warnings, {} failed",
+            self.clean().count(),
+            self.warned().count(),
+            self.failed().count()
+        )
+    }
+}
+
+/// Run each of `jobs`, converting its input image file to the requested format, and return a
+/// manifest of the results. A failed job does not stop the batch.
+pub fn convert_batch(jobs: &[ConvertJob], options: &BatchOptions) -> BatchManifest {
+    let total = jobs.len();
+    let next_job = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<BatchEntry>>> = Mutex::new((0..total).map(|_| None).collect());
+
+    let worker = || loop {
+        let ji = next_job.fetch_add(1, Ordering::Relaxed);
+        if ji >= total {
+            break;
+        }
+
+        let entry = match options
+            .progress
+            .report("batch", completed.load(Ordering::Relaxed), total)
+        {
+            Ok(()) => convert_job(&jobs[ji], options),
+            Err(error) => BatchEntry {
+                job: jobs[ji].clone(),
+                output: None,
+                warnings: Vec::new(),
+                result: Err(BatchFailure {
+                    stage: BatchStage::Load,
+                    error,
+                }),
+            },
+        };
+        completed.fetch_add(1, Ordering::Relaxed);
+        results.lock().unwrap()[ji] = Some(entry);
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 1..options.threads.min(total) {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+    _ = options.progress.report("batch", total, total);
+
+    BatchManifest {
+        entries: results.into_inner().unwrap().into_iter().flatten().collect(),
+    }
+}
+
+fn convert_job(job: &ConvertJob, options: &BatchOptions) -> BatchEntry {
+    let mut entry = BatchEntry {
+        job: job.clone(),
+        output: job.output_path(),
+        warnings: Vec::new(),
+        result: Ok(()),
+    };
+
+    entry.result = run_job(job, entry.output.as_deref(), options, &mut entry.warnings);
+    if let Err(failure) = &entry.result {
+        log::warn!("convert_batch(): {}: {}", job.input.display(), failure);
+    }
+    entry
+}
+
+fn run_job(
+    job: &ConvertJob,
+    output: Option<&Path>,
+    options: &BatchOptions,
+    warnings: &mut Vec<BatchWarning>,
+) -> Result<(), BatchFailure> {
+    let fail = |stage| move |error| BatchFailure { stage, error };
+
+    let mut image =
+        DiskImage::load_from_path(&job.input, options.load_options.clone()).map_err(fail(BatchStage::Load))?;
+
+    let output = output.ok_or(fail(BatchStage::Check)(DiskImageError::UnsupportedFormat))?;
+    // Formats that don't implement can_write() report UnsupportedFormat, and are left to fail on
+    // save if they truly can't be written.
+    match job.format.can_write(&image) {
+        ParserWriteCompatibility::Incompatible => {
+            return Err(fail(BatchStage::Check)(DiskImageError::IncompatibleImage));
+        }
+        ParserWriteCompatibility::DataLoss if options.fail_on_data_loss => {
+            return Err(fail(BatchStage::Check)(DiskImageError::IncompatibleImage));
+        }
+        ParserWriteCompatibility::DataLoss => warnings.push(BatchWarning::DataLoss),
+        ParserWriteCompatibility::Ok | ParserWriteCompatibility::UnsupportedFormat => {}
+    }
+
+    let mut out_buf = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(job.format, options.policy, &mut out_buf)
+        .map_err(fail(BatchStage::Save))?;
+    warnings.extend(report.issues.into_iter().map(BatchWarning::Issue));
+
+    let mut open_options = std::fs::OpenOptions::new();
+    open_options.write(true);
+    if options.overwrite {
+        open_options.create(true).truncate(true);
+    } else {
+        open_options.create_new(true);
+    }
+    open_options
+        .open(output)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, out_buf.get_ref()))
+        .map_err(|e| {
+            log::error!("convert_batch(): Failed to write {}: {}", output.display(), e);
+            fail(BatchStage::Write)(DiskImageError::IoError)
+        })
+}
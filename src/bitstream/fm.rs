@@ -0,0 +1,477 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/bitstream/fm.rs
+
+    Implements a wrapper around a BitVec to provide FM encoding and decoding.
+
+    FM interleaves a clock bitcell before each data bitcell, as MFM does, so a
+    byte occupies 16 bitcells in both. Unlike MFM, every clock bitcell is set
+    except within address marks, which are distinguished by clock patterns with
+    missing clock bits rather than by sync bytes.
+*/
+use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use crate::random::RandomSource;
+use crate::EncodingPhase;
+use bit_vec::BitVec;
+use std::ops::Index;
+
+pub const FM_BYTE_LEN: usize = 16;
+pub const FM_MARKER_LEN: usize = 64;
+/// The encoded bitcells of an 0x00 sync byte, with every clock bit set.
+pub const FM_SYNC: u16 = 0xAAAA;
+/// The clock pattern of the index address mark.
+pub const FM_IAM_CLOCK: u8 = 0xD7;
+/// The clock pattern of the ID and data address marks.
+pub const FM_AM_CLOCK: u8 = 0xC7;
+
+#[derive(Clone, Debug)]
+pub struct FmCodec {
+    bit_vec: BitVec,
+    clock_map: BitVec,
+    weak_mask: BitVec,
+    initial_phase: usize,
+    bit_cursor: usize,
+    /// The source of the random data returned for weak bits.
+    rng: RandomSource,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FmEncodingType {
+    Data,
+    AddressMark,
+}
+
+/// Return the phase of the first run of sync bytes in the track, if any.
+pub fn get_fm_sync_offset(track: &BitVec) -> Option<EncodingPhase> {
+    let mut shift_reg: u32 = 0;
+    for (i, bit) in track.iter().enumerate() {
+        shift_reg = shift_reg << 1 | (bit as u32);
+        if i >= 32 && shift_reg == 0xAAAA_AAAA {
+            return match (i - 31) % 2 {
+                0 => Some(EncodingPhase::Even),
+                _ => Some(EncodingPhase::Odd),
+            };
+        }
+    }
+    None
+}
+
+impl FmCodec {
+    pub fn new(mut bit_vec: BitVec, bit_ct: Option<usize>, weak_mask: Option<BitVec>) -> Self {
+        // If a bit count was provided, we can trim the bit vector to that length.
+        if let Some(bit_ct) = bit_ct {
+            bit_vec.truncate(bit_ct);
+        }
+
+        let encoding_sync = get_fm_sync_offset(&bit_vec).unwrap_or(EncodingPhase::Even);
+        let sync = encoding_sync.into();
+
+        let clock_map = BitVec::from_elem(bit_vec.len(), encoding_sync.into());
+        let weak_mask = match weak_mask {
+            Some(mask) => mask,
+            None => BitVec::from_elem(bit_vec.len(), false),
+        };
+
+        if weak_mask.len() < bit_vec.len() {
+            panic!("Weak mask must be the same length as the bit vector");
+        }
+
+        FmCodec {
+            bit_vec,
+            clock_map,
+            weak_mask,
+            initial_phase: sync,
+            bit_cursor: sync,
+            rng: RandomSource::default(),
+        }
+    }
+
+    /// Return the source of the random data returned when reading weak bits.
+    pub(crate) fn rng(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    /// Set the source of the random data returned when reading weak bits.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        self.rng = rng;
+    }
+
+    pub fn replace(&mut self, new_bits: BitVec) {
+        self.bit_vec = new_bits;
+    }
+
+    pub fn len(&self) -> usize {
+        self.bit_vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bit_vec.is_empty()
+    }
+
+    /// Return true if any bits are marked in the weak bit mask.
+    pub fn has_weak_bits(&self) -> bool {
+        self.weak_mask.any()
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        self.bit_vec.to_bytes()
+    }
+
+    pub fn weak_data(&self) -> Vec<u8> {
+        self.weak_mask.to_bytes()
+    }
+
+    pub fn get_sync(&self) -> Option<EncodingPhase> {
+        match self.initial_phase {
+            0 => Some(EncodingPhase::Even),
+            _ => Some(EncodingPhase::Odd),
+        }
+    }
+
+    pub fn set_clock_map(&mut self, clock_map: BitVec) {
+        self.clock_map = clock_map;
+    }
+
+    pub fn clock_map_mut(&mut self) -> &mut BitVec {
+        &mut self.clock_map
+    }
+
+    pub fn set_weak_mask(&mut self, weak_mask: BitVec) -> Result<()> {
+        if weak_mask.len() != self.bit_vec.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Weak mask must be the same length as the bit vector",
+            ));
+        }
+        self.weak_mask = weak_mask;
+
+        Ok(())
+    }
+
+    pub fn get_weak_mask(&self) -> &BitVec {
+        &self.weak_mask
+    }
+
+    /// Return the raw bitcells of the track.
+    pub fn bits(&self) -> &BitVec {
+        &self.bit_vec
+    }
+
+    /// Return the bytes allocated for the bitstream, clock map and weak bit mask.
+    pub(crate) fn memory_usage(&self) -> (usize, usize, usize) {
+        (
+            self.bit_vec.capacity() / 8,
+            self.clock_map.capacity() / 8,
+            self.weak_mask.capacity() / 8,
+        )
+    }
+
+    /// Return the clock byte used to encode `byte`. Data is always written with every clock bit
+    /// set. If `encoding_type` is [`FmEncodingType::AddressMark`], the address mark bytes 0xFC,
+    /// 0xFE, 0xFB and 0xF8 are given their characteristic clock patterns instead.
+    fn clock_for(byte: u8, encoding_type: FmEncodingType) -> u8 {
+        match (encoding_type, byte) {
+            (FmEncodingType::AddressMark, 0xFC) => FM_IAM_CLOCK,
+            (FmEncodingType::AddressMark, 0xFE | 0xFB | 0xF8) => FM_AM_CLOCK,
+            _ => 0xFF,
+        }
+    }
+
+    /// Encode a byte slice as FM, returning the encoded bitcells as a BitVec.
+    pub fn encode_fm(data: &[u8], encoding_type: FmEncodingType) -> BitVec {
+        let mut bitvec = BitVec::with_capacity(data.len() * FM_BYTE_LEN);
+
+        for &byte in data {
+            let clock = Self::clock_for(byte, encoding_type);
+            for i in (0..8).rev() {
+                bitvec.push(clock & (1 << i) != 0);
+                bitvec.push(byte & (1 << i) != 0);
+            }
+        }
+
+        bitvec
+    }
+
+    /// Encode an FM address mark preceded by sync bytes. `data` must be a 4-byte slice, and its
+    /// last byte is encoded with the clock pattern of an address mark.
+    /// Returns the encoded value in a u64 suitable for comparison to a shift register used to search
+    /// a BitVec.
+    pub fn encode_marker(data: &[u8]) -> u64 {
+        assert_eq!(data.len(), 4);
+
+        let mut accum: u64 = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            let encoding_type = match i {
+                3 => FmEncodingType::AddressMark,
+                _ => FmEncodingType::Data,
+            };
+            let clock = Self::clock_for(byte, encoding_type);
+            for bit in (0..8).rev() {
+                accum = (accum << 2) | (((clock >> bit) & 1) as u64) << 1 | ((byte >> bit) & 1) as u64;
+            }
+        }
+        accum
+    }
+
+    pub fn find_next_marker(&self, marker: u64, mask: u64, start: usize) -> Option<(usize, u16)> {
+        let mut shift_reg: u64 = 0;
+        let mut shift_ct: u32 = 0;
+
+        for bi in start..self.bit_vec.len() {
+            shift_reg = (shift_reg << 1) | self.bit_vec[bi] as u64;
+            shift_ct += 1;
+
+            if shift_ct >= 64 && ((shift_reg & mask) == marker) {
+                return Some(((bi - 64) + 1, (shift_reg & 0xFFFF) as u16));
+            }
+        }
+        log::trace!("find_next_marker(): Failed to find marker!");
+        None
+    }
+
+    pub fn find_marker(&self, marker: u64, start: usize, limit: Option<usize>) -> Option<usize> {
+        let search_limit = std::cmp::min(limit.unwrap_or(self.bit_vec.len()), self.bit_vec.len());
+        let mut shift_reg: u64 = 0;
+        let mut shift_ct: u32 = 0;
+
+        for bi in start..search_limit {
+            shift_reg = (shift_reg << 1) | self.bit_vec[bi] as u64;
+            shift_ct += 1;
+
+            if shift_ct >= 64 && (shift_reg == marker) {
+                return Some((bi - 64) + 1);
+            }
+        }
+        log::trace!("find_marker(): Failed to find marker!");
+        None
+    }
+
+    pub fn debug_marker(&self, index: usize) -> String {
+        let mut shift_reg: u64 = 0;
+        for bi in index..std::cmp::min(index + 64, self.bit_vec.len()) {
+            shift_reg = (shift_reg << 1) | self.bit_vec[bi] as u64;
+        }
+        format!("{:16X}/{:064b}", shift_reg, shift_reg)
+    }
+
+    /// Read the byte of undecoded bitcells starting at decoded bit `index`.
+    pub fn read_byte(&self, index: usize) -> Option<u8> {
+        if index + 8 > self.bit_vec.len() / 2 {
+            return None;
+        }
+
+        let mut byte_val = 0;
+        for i in 0..8 {
+            byte_val = (byte_val << 1) | self[index + i] as u8;
+        }
+        Some(byte_val)
+    }
+
+    /// Decode the byte whose bitcells begin at the raw bitcell `index`, using the clock map to
+    /// skip the clock bitcells.
+    pub fn read_decoded_byte(&self, index: usize) -> Option<u8> {
+        if index >= self.bit_vec.len() || index >= self.clock_map.len() {
+            log::error!(
+                "read_decoded_byte(): index out of bounds: {} vec: {} clock_map:{}",
+                index,
+                self.bit_vec.len(),
+                self.clock_map.len()
+            );
+            return None;
+        }
+        let p_off: usize = self.clock_map[index] as usize;
+        let mut byte = 0;
+        for bi in (index..std::cmp::min(index + FM_BYTE_LEN, self.bit_vec.len()))
+            .skip(p_off)
+            .step_by(2)
+        {
+            byte = (byte << 1) | self.bit_vec[bi] as u8;
+        }
+        Some(byte)
+    }
+
+    /// Encode `buf` as FM data and write it into the track at the raw bitcell index `offset`,
+    /// adjusting `offset` to the clock phase given by the clock map.
+    ///
+    /// Returns the number of bitcells written.
+    pub(crate) fn write_buf(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        if offset >= self.bit_vec.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "offset is past end of track"));
+        }
+
+        let phase = !self.clock_map[offset] as usize;
+        let encoded_buf = Self::encode_fm(buf, FmEncodingType::Data);
+        let copy_len = std::cmp::min(encoded_buf.len(), self.bit_vec.len().saturating_sub(offset + phase));
+
+        for (i, bit) in encoded_buf.into_iter().enumerate().take(copy_len) {
+            self.bit_vec.set(offset + phase + i, bit);
+        }
+
+        Ok(copy_len)
+    }
+
+    /// Encode `buf` as FM and write the resulting bitcells directly into the track, starting at
+    /// the raw bitcell index `offset`, which should point to the clock bit of the first byte.
+    ///
+    /// Specifying [`FmEncodingType::AddressMark`] encodes address mark bytes with their missing
+    /// clock bits. The track's clock map and metadata are not updated; the track must be
+    /// re-scanned after modification.
+    ///
+    /// Returns the number of bitcells written. Writes extending past the end of the track are
+    /// truncated.
+    pub fn write_encoded_buf(&mut self, buf: &[u8], offset: usize, encoding_type: FmEncodingType) -> Result<usize> {
+        if offset >= self.bit_vec.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "offset is past end of track"));
+        }
+
+        let encoded_buf = Self::encode_fm(buf, encoding_type);
+        let copy_len = std::cmp::min(encoded_buf.len(), self.bit_vec.len() - offset);
+
+        for (i, bit) in encoded_buf.into_iter().enumerate().take(copy_len) {
+            self.bit_vec.set(offset + i, bit);
+        }
+
+        Ok(copy_len)
+    }
+}
+
+impl Iterator for FmCodec {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bit_cursor >= (self.bit_vec.len() - 1) {
+            return None;
+        }
+
+        // The bit cursor should always be aligned to a clock bit.
+        // So retrieve the next bit which is the data bit, then point to the next clock.
+        let data_idx = self.bit_cursor + 1;
+        let decoded_bit = if self.weak_mask[data_idx] {
+            // Weak bits return random data
+            self.rng.next_bool()
+        } else {
+            self.bit_vec[data_idx]
+        };
+
+        let new_cursor = data_idx + 1;
+        if new_cursor >= self.bit_vec.len() {
+            // Wrap around to the beginning of the track
+            self.bit_cursor = 0;
+        } else {
+            self.bit_cursor = new_cursor;
+        }
+
+        Some(decoded_bit)
+    }
+}
+
+impl Seek for FmCodec {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as isize),
+            SeekFrom::End(offset) => (self.bit_vec.len() as isize, offset as isize),
+            SeekFrom::Current(offset) => (self.bit_cursor as isize, offset as isize),
+        };
+
+        let new_pos = base.checked_add(offset).ok_or(Error::new(
+            ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowed position",
+        ))?;
+
+        let mut new_cursor = (new_pos as usize) << 1;
+        if new_cursor >= self.bit_vec.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowed position",
+            ));
+        }
+
+        // If we have seeked to a data bit, nudge the bit cursor to the next clock bit.
+        if !self.clock_map[new_cursor] {
+            new_cursor += 1;
+        }
+
+        self.bit_cursor = new_cursor;
+        Ok(self.bit_cursor as u64)
+    }
+}
+
+impl Read for FmCodec {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut bytes_read = 0;
+        for byte in buf.iter_mut() {
+            let mut byte_val = 0;
+            for _ in 0..8 {
+                if let Some(bit) = self.next() {
+                    byte_val = (byte_val << 1) | bit as u8;
+                } else {
+                    break;
+                }
+            }
+            *byte = byte_val;
+            bytes_read += 1;
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl Index<usize> for FmCodec {
+    type Output = bool;
+
+    /// Return the decoded data bit at `index`.
+    fn index(&self, index: usize) -> &Self::Output {
+        let cell = index << 1;
+        if cell >= self.bit_vec.len() {
+            panic!("index out of bounds");
+        }
+        let p_off = self.clock_map[cell] as usize;
+        &self.bit_vec[std::cmp::min(cell + p_off, self.bit_vec.len() - 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits_to_u16(bits: &BitVec) -> u16 {
+        bits.iter().fold(0u16, |acc, bit| (acc << 1) | bit as u16)
+    }
+
+    #[test]
+    fn encode_fm_address_marks() {
+        let idam = FmCodec::encode_fm(&[0xFE], FmEncodingType::AddressMark);
+        assert_eq!(bits_to_u16(&idam), 0xF57E);
+
+        let iam = FmCodec::encode_fm(&[0xFC], FmEncodingType::AddressMark);
+        assert_eq!(bits_to_u16(&iam), 0xF77A);
+
+        let sync = FmCodec::encode_fm(&[0x00], FmEncodingType::AddressMark);
+        assert_eq!(bits_to_u16(&sync), FM_SYNC);
+
+        assert_eq!(FmCodec::encode_marker(&[0x00, 0x00, 0x00, 0xFB]), 0xAAAA_AAAA_AAAA_F56F);
+    }
+}
@@ -0,0 +1,520 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/bitstream/gcr.rs
+
+    Implements a wrapper around a BitVec to provide Apple II 6-and-2 GCR
+    decoding.
+
+    GCR has no clock bitcells. Every bitcell is a data bit, and data is
+    written as 8-bit disk nibbles that always have their high bit set and
+    never contain more than two consecutive zero bits. The disk controller
+    shifts bits into a latch until the high bit is set, so zero bits between
+    nibbles are skipped; self-sync nibbles take advantage of this by writing
+    0xFF followed by two zero bits.
+
+    Each disk nibble represents 6 bits of data. A 256-byte sector is written
+    as 342 nibbles followed by a checksum nibble: 86 nibbles holding the low
+    two bits of each byte, then 256 nibbles holding the high six bits, each
+    XORed with the previous value before translation.
+*/
+use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use crate::random::RandomSource;
+use crate::EncodingPhase;
+use bit_vec::BitVec;
+use std::ops::Index;
+
+/// The length of a disk nibble in bitcells, excluding any trailing self-sync zero bits.
+pub const GCR_NIBBLE_LEN: usize = 8;
+/// The number of bytes in a 6-and-2 encoded sector.
+pub const GCR_SECTOR_SIZE: usize = 256;
+/// The number of nibbles holding the low two bits of each byte of a 6-and-2 encoded sector.
+pub const GCR_62_AUX_LEN: usize = 86;
+/// The number of nibbles in a 6-and-2 encoded sector, including its checksum nibble.
+pub const GCR_62_ENCODED_LEN: usize = GCR_62_AUX_LEN + GCR_SECTOR_SIZE + 1;
+/// The prologue of an address field.
+pub const GCR_ADDRESS_PROLOGUE: [u8; 3] = [0xD5, 0xAA, 0x96];
+/// The prologue of a data field.
+pub const GCR_DATA_PROLOGUE: [u8; 3] = [0xD5, 0xAA, 0xAD];
+/// The epilogue of both address and data fields.
+pub const GCR_EPILOGUE: [u8; 3] = [0xDE, 0xAA, 0xEB];
+
+/// The translation table from 6-bit values to the 64 valid disk nibbles.
+#[rustfmt::skip]
+pub const GCR_62_ENCODE: [u8; 64] = [
+    0x96, 0x97, 0x9A, 0x9B, 0x9D, 0x9E, 0x9F, 0xA6,
+    0xA7, 0xAB, 0xAC, 0xAD, 0xAE, 0xAF, 0xB2, 0xB3,
+    0xB4, 0xB5, 0xB6, 0xB7, 0xB9, 0xBA, 0xBB, 0xBC,
+    0xBD, 0xBE, 0xBF, 0xCB, 0xCD, 0xCE, 0xCF, 0xD3,
+    0xD6, 0xD7, 0xD9, 0xDA, 0xDB, 0xDC, 0xDD, 0xDE,
+    0xDF, 0xE5, 0xE6, 0xE7, 0xE9, 0xEA, 0xEB, 0xEC,
+    0xED, 0xEE, 0xEF, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6,
+    0xF7, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE, 0xFF,
+];
+
+/// Return the 6-bit value of the disk nibble `nibble`, or None if it is not a valid 6-and-2
+/// nibble.
+pub fn gcr_62_decode(nibble: u8) -> Option<u8> {
+    GCR_62_ENCODE.iter().position(|&n| n == nibble).map(|v| v as u8)
+}
+
+/// Return the low two bits of `byte` swapped, as stored in the auxiliary nibbles.
+fn swap_low_bits(byte: u8) -> u8 {
+    ((byte & 0x01) << 1) | ((byte & 0x02) >> 1)
+}
+
+#[derive(Clone, Debug)]
+pub struct GcrCodec {
+    bit_vec: BitVec,
+    weak_mask: BitVec,
+    bit_cursor: usize,
+    /// The source of the random data returned for weak bits.
+    rng: RandomSource,
+}
+
+impl GcrCodec {
+    pub fn new(mut bit_vec: BitVec, bit_ct: Option<usize>, weak_mask: Option<BitVec>) -> Self {
+        // If a bit count was provided, we can trim the bit vector to that length.
+        if let Some(bit_ct) = bit_ct {
+            bit_vec.truncate(bit_ct);
+        }
+
+        let weak_mask = match weak_mask {
+            Some(mask) => mask,
+            None => BitVec::from_elem(bit_vec.len(), false),
+        };
+
+        if weak_mask.len() < bit_vec.len() {
+            panic!("Weak mask must be the same length as the bit vector");
+        }
+
+        GcrCodec {
+            bit_vec,
+            weak_mask,
+            bit_cursor: 0,
+            rng: RandomSource::default(),
+        }
+    }
+
+    /// Return the source of the random data returned when reading weak bits.
+    pub(crate) fn rng(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    /// Set the source of the random data returned when reading weak bits.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        self.rng = rng;
+    }
+
+    pub fn replace(&mut self, new_bits: BitVec) {
+        self.bit_vec = new_bits;
+    }
+
+    pub fn len(&self) -> usize {
+        self.bit_vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bit_vec.is_empty()
+    }
+
+    /// Return true if any bits are marked in the weak bit mask.
+    pub fn has_weak_bits(&self) -> bool {
+        self.weak_mask.any()
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        self.bit_vec.to_bytes()
+    }
+
+    pub fn weak_data(&self) -> Vec<u8> {
+        self.weak_mask.to_bytes()
+    }
+
+    /// GCR has no clock bitcells, so there is no encoding phase to synchronize to.
+    pub fn get_sync(&self) -> Option<EncodingPhase> {
+        None
+    }
+
+    pub fn set_weak_mask(&mut self, weak_mask: BitVec) -> Result<()> {
+        if weak_mask.len() != self.bit_vec.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Weak mask must be the same length as the bit vector",
+            ));
+        }
+        self.weak_mask = weak_mask;
+
+        Ok(())
+    }
+
+    pub fn get_weak_mask(&self) -> &BitVec {
+        &self.weak_mask
+    }
+
+    /// Return the raw bitcells of the track.
+    pub fn bits(&self) -> &BitVec {
+        &self.bit_vec
+    }
+
+    /// Return the bytes allocated for the bitstream, clock map and weak bit mask. GCR tracks have
+    /// no clock map.
+    pub(crate) fn memory_usage(&self) -> (usize, usize, usize) {
+        (self.bit_vec.capacity() / 8, 0, self.weak_mask.capacity() / 8)
+    }
+
+    /// Return the bit at `index`, returning random data for weak bits.
+    fn read_bit(&self, index: usize) -> bool {
+        if self.weak_mask[index] {
+            self.rng.next_bool()
+        } else {
+            self.bit_vec[index]
+        }
+    }
+
+    /// Read the disk nibble at or after the bitcell `index`, as the disk controller's latch would:
+    /// zero bits are skipped until a one bit is found, then the following 8 bits are shifted in.
+    /// The track wraps around at the index.
+    ///
+    /// Returns the nibble and the bitcell index following it, or None if the track holds no one
+    /// bits to synchronize to.
+    pub fn read_nibble(&self, index: usize) -> Option<(u8, usize)> {
+        let len = self.bit_vec.len();
+        if len == 0 {
+            return None;
+        }
+        let mut bi = index % len;
+        let mut skipped = 0;
+        while !self.bit_vec[bi] {
+            bi = (bi + 1) % len;
+            skipped += 1;
+            if skipped >= len {
+                return None;
+            }
+        }
+
+        let mut nibble = 0;
+        for _ in 0..GCR_NIBBLE_LEN {
+            nibble = (nibble << 1) | self.read_bit(bi) as u8;
+            bi = (bi + 1) % len;
+        }
+        Some((nibble, bi))
+    }
+
+    /// Read the disk nibbles of one revolution from the index with latch semantics. A nibble that
+    /// straddles the index is included.
+    pub fn read_nibbles(&self) -> Vec<u8> {
+        let len = self.bit_vec.len();
+        let mut nibbles = Vec::with_capacity(len / GCR_NIBBLE_LEN);
+        let mut bi = 0;
+        while let Some((nibble, next)) = self.read_nibble(bi) {
+            let nibble_start = (next + len - GCR_NIBBLE_LEN) % len;
+            if nibble_start < bi {
+                break;
+            }
+            nibbles.push(nibble);
+            if next <= nibble_start {
+                break;
+            }
+            bi = next;
+        }
+        nibbles
+    }
+
+    /// Return the bitcell index of the first occurrence of the nibble sequence `pattern` at or
+    /// after the bitcell index `start`, read with latch semantics from any bit alignment. The
+    /// search stops at the end of the track.
+    pub fn find_nibbles(&self, pattern: &[u8], start: usize) -> Option<usize> {
+        let len = self.bit_vec.len();
+        if pattern.is_empty() || pattern.len() > 8 {
+            return None;
+        }
+        let mask = u64::MAX >> (64 - pattern.len() * 8);
+        let target = pattern.iter().fold(0u64, |acc, &n| (acc << 8) | n as u64);
+
+        let mut bi = start;
+        let mut window = 0u64;
+        let mut nibble_starts = Vec::with_capacity(pattern.len());
+        while bi < len {
+            let (nibble, next) = self.read_nibble(bi)?;
+            // The start of the nibble is 8 bitcells before the bit following it.
+            let nibble_start = (next + len - GCR_NIBBLE_LEN) % len;
+            if nibble_start < bi {
+                // The zero bits before the nibble ran past the index.
+                break;
+            }
+            nibble_starts.push(nibble_start);
+            if nibble_starts.len() > pattern.len() {
+                nibble_starts.remove(0);
+            }
+            window = (window << 8) | nibble as u64;
+            if nibble_starts.len() == pattern.len() && (window & mask) == target {
+                return Some(nibble_starts[0]);
+            }
+            if next <= nibble_start {
+                // The nibble straddles the index.
+                break;
+            }
+            bi = next;
+        }
+        None
+    }
+
+    /// Read the 8 bits starting at the bitcell `index`, without skipping zero bits.
+    pub fn read_byte(&self, index: usize) -> Option<u8> {
+        if index + GCR_NIBBLE_LEN > self.bit_vec.len() {
+            return None;
+        }
+
+        let mut byte_val = 0;
+        for i in 0..GCR_NIBBLE_LEN {
+            byte_val = (byte_val << 1) | self.bit_vec[index + i] as u8;
+        }
+        Some(byte_val)
+    }
+
+    /// Read the disk nibble at or after the bitcell `index` with latch semantics.
+    pub fn read_decoded_byte(&self, index: usize) -> Option<u8> {
+        if index >= self.bit_vec.len() {
+            log::error!(
+                "read_decoded_byte(): index out of bounds: {} vec: {}",
+                index,
+                self.bit_vec.len()
+            );
+            return None;
+        }
+        self.read_nibble(index).map(|(nibble, _)| nibble)
+    }
+
+    /// Write the bits of `buf` into the track at the bitcell index `offset`. The bytes are expected
+    /// to be disk nibbles; no encoding is applied.
+    ///
+    /// Returns the number of bitcells written. Writes extending past the end of the track are
+    /// truncated.
+    pub(crate) fn write_buf(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        if offset >= self.bit_vec.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "offset is past end of track"));
+        }
+
+        let bits = BitVec::from_bytes(buf);
+        let copy_len = std::cmp::min(bits.len(), self.bit_vec.len() - offset);
+
+        for (i, bit) in bits.into_iter().enumerate().take(copy_len) {
+            self.bit_vec.set(offset + i, bit);
+        }
+
+        Ok(copy_len)
+    }
+
+    /// Encode a 256-byte sector as 342 6-and-2 disk nibbles followed by a checksum nibble.
+    pub fn encode_62(data: &[u8; GCR_SECTOR_SIZE]) -> Vec<u8> {
+        let mut values = [0u8; GCR_62_ENCODED_LEN - 1];
+
+        for (i, &byte) in data.iter().enumerate() {
+            let aux = i % GCR_62_AUX_LEN;
+            let shift = (i / GCR_62_AUX_LEN) * 2;
+            values[aux] |= swap_low_bits(byte) << shift;
+            values[GCR_62_AUX_LEN + i] = byte >> 2;
+        }
+
+        let mut nibbles = Vec::with_capacity(GCR_62_ENCODED_LEN);
+        let mut last = 0;
+        for &value in values.iter() {
+            nibbles.push(GCR_62_ENCODE[((value ^ last) & 0x3F) as usize]);
+            last = value;
+        }
+        nibbles.push(GCR_62_ENCODE[(last & 0x3F) as usize]);
+        nibbles
+    }
+
+    /// Decode 343 6-and-2 disk nibbles into a 256-byte sector.
+    ///
+    /// Returns the decoded sector and true if the checksum nibble matched, or None if `nibbles`
+    /// is too short or contains an invalid nibble.
+    pub fn decode_62(nibbles: &[u8]) -> Option<(Vec<u8>, bool)> {
+        if nibbles.len() < GCR_62_ENCODED_LEN {
+            return None;
+        }
+
+        let mut values = [0u8; GCR_62_ENCODED_LEN - 1];
+        let mut last = 0;
+        for (value, &nibble) in values.iter_mut().zip(nibbles.iter()) {
+            last ^= gcr_62_decode(nibble)?;
+            *value = last;
+        }
+        let checksum_ok = gcr_62_decode(nibbles[GCR_62_ENCODED_LEN - 1])? == last;
+
+        let data = (0..GCR_SECTOR_SIZE)
+            .map(|i| {
+                let aux = values[i % GCR_62_AUX_LEN] >> ((i / GCR_62_AUX_LEN) * 2);
+                (values[GCR_62_AUX_LEN + i] << 2) | swap_low_bits(aux & 0x03)
+            })
+            .collect();
+        Some((data, checksum_ok))
+    }
+
+    /// Encode `value` as the two 4-and-4 nibbles used in address fields: the odd bits followed by
+    /// the even bits, each with every other bit set.
+    pub fn encode_44(value: u8) -> [u8; 2] {
+        [(value >> 1) | 0xAA, value | 0xAA]
+    }
+
+    /// Decode a pair of 4-and-4 nibbles from an address field.
+    pub fn decode_44(nibbles: [u8; 2]) -> u8 {
+        ((nibbles[0] << 1) | 0x01) & nibbles[1]
+    }
+}
+
+impl Iterator for GcrCodec {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bit_cursor >= self.bit_vec.len() {
+            return None;
+        }
+
+        let bit = self.read_bit(self.bit_cursor);
+
+        let new_cursor = self.bit_cursor + 1;
+        if new_cursor >= self.bit_vec.len() {
+            // Wrap around to the beginning of the track
+            self.bit_cursor = 0;
+        } else {
+            self.bit_cursor = new_cursor;
+        }
+
+        Some(bit)
+    }
+}
+
+impl Seek for GcrCodec {
+    /// Seek to a bitcell position. GCR has no clock bitcells, so positions are raw bitcell
+    /// indices.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as isize),
+            SeekFrom::End(offset) => (self.bit_vec.len() as isize, offset as isize),
+            SeekFrom::Current(offset) => (self.bit_cursor as isize, offset as isize),
+        };
+
+        let new_pos = base.checked_add(offset).ok_or(Error::new(
+            ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowed position",
+        ))?;
+
+        if new_pos < 0 || new_pos as usize >= self.bit_vec.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowed position",
+            ));
+        }
+
+        self.bit_cursor = new_pos as usize;
+        Ok(self.bit_cursor as u64)
+    }
+}
+
+impl Read for GcrCodec {
+    /// Read disk nibbles from the bit cursor with latch semantics, skipping the zero bits between
+    /// nibbles. Reading wraps around at the index.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut bytes_read = 0;
+        for byte in buf.iter_mut() {
+            match self.read_nibble(self.bit_cursor) {
+                Some((nibble, next)) => {
+                    *byte = nibble;
+                    self.bit_cursor = next;
+                    bytes_read += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl Index<usize> for GcrCodec {
+    type Output = bool;
+
+    /// Return the bit at `index`. Every GCR bitcell is a data bit.
+    fn index(&self, index: usize) -> &Self::Output {
+        if index >= self.bit_vec.len() {
+            panic!("index out of bounds");
+        }
+        &self.bit_vec[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcr_62_round_trip() {
+        let mut sector = [0u8; GCR_SECTOR_SIZE];
+        for (i, byte) in sector.iter_mut().enumerate() {
+            *byte = (i * 7 + 3) as u8;
+        }
+
+        let nibbles = GcrCodec::encode_62(&sector);
+        assert_eq!(nibbles.len(), GCR_62_ENCODED_LEN);
+        assert!(nibbles.iter().all(|&n| n & 0x80 != 0));
+
+        let (decoded, checksum_ok) = GcrCodec::decode_62(&nibbles).unwrap();
+        assert!(checksum_ok);
+        assert_eq!(decoded, sector);
+
+        let mut bad = nibbles.clone();
+        bad[100] = GCR_62_ENCODE[(gcr_62_decode(bad[100]).unwrap() ^ 1) as usize];
+        assert!(!GcrCodec::decode_62(&bad).unwrap().1);
+    }
+
+    #[test]
+    fn gcr_44_round_trip() {
+        for value in [0x00, 0x11, 0xFE, 0xA5] {
+            assert_eq!(GcrCodec::decode_44(GcrCodec::encode_44(value)), value);
+        }
+    }
+
+    #[test]
+    fn gcr_read_skips_sync_bits() {
+        // Two 10-bit self-sync nibbles, then an address prologue.
+        let mut bits = BitVec::new();
+        for _ in 0..2 {
+            bits.extend(BitVec::from_bytes(&[0xFF]).iter());
+            bits.push(false);
+            bits.push(false);
+        }
+        bits.extend(BitVec::from_bytes(&GCR_ADDRESS_PROLOGUE).iter());
+
+        let mut codec = GcrCodec::new(bits, None, None);
+        assert_eq!(codec.find_nibbles(&GCR_ADDRESS_PROLOGUE, 0), Some(20));
+
+        let mut buf = [0u8; 5];
+        codec.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 0xFF, 0xD5, 0xAA, 0x96]);
+    }
+}
@@ -31,12 +31,15 @@
 */
 use crate::diskimage::TrackRegion;
 use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use crate::random::RandomSource;
 use crate::EncodingPhase;
 use bit_vec::BitVec;
 use std::ops::Index;
 
 pub const MFM_BYTE_LEN: usize = 16;
 pub const MFM_MARKER_LEN: usize = 64;
+/// The encoded bitcells of an 0xA1 sync byte with its missing clock bit.
+pub const MFM_A1_SYNC: u16 = 0x4489;
 
 #[macro_export]
 macro_rules! mfm_offset {
@@ -45,7 +48,7 @@ macro_rules! mfm_offset {
     };
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MfmCodec {
     bit_vec: BitVec,
     clock_map: BitVec,
@@ -54,8 +57,11 @@ pub struct MfmCodec {
     bit_cursor: usize,
     track_padding: usize,
     random_offset: usize,
+    /// The source of the random data returned for weak bits.
+    rng: RandomSource,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum MfmEncodingType {
     Data,
     AddressMark,
@@ -117,9 +123,20 @@ impl MfmCodec {
             bit_cursor: sync,
             track_padding: 0,
             random_offset: 0,
+            rng: RandomSource::default(),
         }
     }
 
+    /// Return the source of the random data returned when reading weak bits.
+    pub(crate) fn rng(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    /// Set the source of the random data returned when reading weak bits.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        self.rng = rng;
+    }
+
     pub fn replace(&mut self, new_bits: BitVec) {
         self.bit_vec = new_bits;
     }
@@ -132,8 +149,10 @@ impl MfmCodec {
         self.bit_vec.is_empty()
     }
 
+    /// Return true if any bits are marked in the weak bit mask, or if weak bits can be detected
+    /// in the bitstream.
     pub fn has_weak_bits(&self) -> bool {
-        !self.detect_weak_bits(6).is_empty()
+        self.weak_mask.any() || !self.detect_weak_bits(6).is_empty()
     }
 
     pub fn data(&self) -> Vec<u8> {
@@ -175,6 +194,20 @@ impl MfmCodec {
         &self.weak_mask
     }
 
+    /// Return the raw bitcells of the track.
+    pub fn bits(&self) -> &BitVec {
+        &self.bit_vec
+    }
+
+    /// Return the bytes allocated for the bitstream, clock map and weak bit mask.
+    pub(crate) fn memory_usage(&self) -> (usize, usize, usize) {
+        (
+            self.bit_vec.capacity() / 8,
+            self.clock_map.capacity() / 8,
+            self.weak_mask.capacity() / 8,
+        )
+    }
+
     pub fn set_track_padding(&mut self) {
         let mut wrap_buffer: [u8; 4] = [0; 4];
 
@@ -218,12 +251,25 @@ impl MfmCodec {
         }
     }
 
+    /// Encode a byte slice as MFM, returning the encoded bitcells as a BitVec.
+    /// `prev_bit` should be set to the value of the data bit immediately preceding the encoded
+    /// data so that the clock bit of the first encoded bit can be computed correctly.
+    ///
+    /// If `encoding_type` is [`MfmEncodingType::AddressMark`], any 0xA1 or 0xC2 sync bytes are
+    /// encoded with their characteristic missing clock bit.
     pub fn encode_mfm(data: &[u8], prev_bit: bool, encoding_type: MfmEncodingType) -> BitVec {
         let mut bitvec = BitVec::new();
-        let mut bit_count = 0;
 
         for &byte in data {
-            for i in (0..8).rev() {
+            // Address marks are distinguished from data by a missing clock bit. 0xA1 is missing
+            // the clock between source bits 4 and 5, 0xC2 between source bits 3 and 4.
+            let missing_clock = match (encoding_type, byte) {
+                (MfmEncodingType::AddressMark, 0xA1) => Some(5),
+                (MfmEncodingType::AddressMark, 0xC2) => Some(4),
+                _ => None,
+            };
+
+            for (bit_idx, i) in (0..8).rev().enumerate() {
                 let bit = (byte & (1 << i)) != 0;
                 if bit {
                     // 1 is encoded as 01
@@ -245,19 +291,11 @@ impl MfmCodec {
                     bitvec.push(false);
                 }
 
-                bit_count += 1;
-
-                // Omit clock bit between source bits 3 and 4 for address marks
-                if let MfmEncodingType::AddressMark = encoding_type {
-                    if bit_count == 4 {
-                        // Clear the previous clock bit (which is between bit 3 and 4)
-                        bitvec.set(bitvec.len() - 2, false);
-                    }
+                if missing_clock == Some(bit_idx) {
+                    // Clear the clock bit we just wrote.
+                    bitvec.set(bitvec.len() - 2, false);
                 }
             }
-
-            // Reset bit_count for the next byte
-            bit_count = 0;
         }
 
         bitvec
@@ -310,7 +348,7 @@ impl MfmCodec {
             }*/
 
             if shift_ct >= 64 && ((shift_reg & mask) == marker) {
-                return Some(((bi - 64) + 1, (shift_reg & 0xFFFF) as u16));
+                return Some((bi + 1 - 64, (shift_reg & 0xFFFF) as u16));
             }
         }
         log::trace!("find_next_marker(): Failed to find marker!");
@@ -338,7 +376,7 @@ impl MfmCodec {
             }*/
 
             if shift_ct >= 64 && (shift_reg == marker) {
-                return Some((bi - 64) + 1);
+                return Some(bi + 1 - 64);
             }
         }
         log::trace!("find_marker(): Failed to find marker!");
@@ -366,7 +404,7 @@ impl MfmCodec {
     fn read_bit(self) -> Option<bool> {
         if self.weak_mask[self.bit_cursor] {
             // Weak bits return random data
-            Some(rand::random())
+            Some(self.rng.next_bool())
         } else {
             Some(self.bit_vec[self.bit_cursor])
         }
@@ -375,7 +413,7 @@ impl MfmCodec {
     fn read_bit_at(&self, index: usize) -> Option<bool> {
         if self.weak_mask[self.initial_phase + (index << 1)] {
             // Weak bits return random data
-            Some(rand::random())
+            Some(self.rng.next_bool())
         } else {
             Some(self.bit_vec[self.initial_phase + (index << 1)])
         }
@@ -452,7 +490,39 @@ impl MfmCodec {
         Ok(bytes_written)
     }
 
+    /// Encode `buf` as MFM and write the resulting bitcells directly into the track, starting at
+    /// the raw bitcell index `offset`. Unlike `write_buf`, no clock phase adjustment is performed,
+    /// so `offset` should point to the clock bit of the first byte to be written.
+    ///
+    /// Specifying [`MfmEncodingType::AddressMark`] encodes 0xA1 and 0xC2 bytes with missing clock
+    /// bits, so that address marks can be mastered at arbitrary positions on the track. Note that
+    /// the track's clock map and metadata are not updated; the track must be re-scanned after
+    /// modification.
+    ///
+    /// Returns the number of bitcells written. Writes extending past the end of the track are
+    /// truncated.
+    pub fn write_encoded_buf(&mut self, buf: &[u8], offset: usize, encoding_type: MfmEncodingType) -> Result<usize> {
+        if offset >= self.bit_vec.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "offset is past end of track"));
+        }
+
+        // The clock bit of the first encoded bit depends on the data bit preceding it.
+        let prev_bit = offset > 0 && self.bit_vec[offset - 1];
+        let encoded_buf = Self::encode_mfm(buf, prev_bit, encoding_type);
+        let copy_len = std::cmp::min(encoded_buf.len(), self.bit_vec.len() - offset);
+
+        for (i, bit) in encoded_buf.into_iter().enumerate().take(copy_len) {
+            self.bit_vec.set(offset + i, bit);
+        }
+
+        Ok(copy_len)
+    }
+
     pub(crate) fn write_raw_buf(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        if offset + buf.len() * 8 > self.bit_vec.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "write extends past end of track"));
+        }
+
         let mut bytes_written = 0;
         let mut offset = offset;
 
@@ -546,7 +616,7 @@ impl Iterator for MfmCodec {
 
         let decoded_bit = if self.weak_mask[data_idx] {
             // Weak bits return random data
-            rand::random()
+            self.rng.next_bool()
         } else {
             self.bit_vec[data_idx]
         };
@@ -643,3 +713,63 @@ impl Index<usize> for MfmCodec {
         self.ref_bit_at(index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitstream::raw::RawCodec;
+    use crate::bitstream::TrackDataStream;
+    use crate::DiskImageError;
+
+    fn bits_to_u16(bits: &BitVec) -> u16 {
+        bits.iter().fold(0u16, |acc, bit| (acc << 1) | bit as u16)
+    }
+
+    #[test]
+    fn encode_mfm_address_mark_omits_clock() {
+        let a1 = MfmCodec::encode_mfm(&[0xA1], false, MfmEncodingType::AddressMark);
+        assert_eq!(bits_to_u16(&a1), 0x4489);
+
+        let c2 = MfmCodec::encode_mfm(&[0xC2], false, MfmEncodingType::AddressMark);
+        assert_eq!(bits_to_u16(&c2), 0x5224);
+
+        let a1_data = MfmCodec::encode_mfm(&[0xA1], false, MfmEncodingType::Data);
+        assert_eq!(bits_to_u16(&a1_data), 0x44A9);
+    }
+
+    #[test]
+    fn write_encoded_buf_writes_at_offset() {
+        let mut codec = MfmCodec::new(BitVec::from_elem(256, false), None, None);
+        let written = codec
+            .write_encoded_buf(&[0xA1, 0xA1], 64, MfmEncodingType::AddressMark)
+            .unwrap();
+        assert_eq!(written, 32);
+        assert_eq!(codec.find_marker(0x4489_4489, 0, None).map(|i| i + 32), Some(64));
+
+        // Writes past the end of the track are truncated.
+        let written = codec.write_encoded_buf(&[0xFF; 4], 240, MfmEncodingType::Data).unwrap();
+        assert_eq!(written, 16);
+    }
+
+    #[test]
+    fn write_raw_buf_writes_bits_verbatim() {
+        let mut stream = TrackDataStream::Mfm(MfmCodec::new(BitVec::from_elem(64, false), None, None));
+        assert_eq!(stream.write_raw_buf(&[0x44, 0x89], 16).unwrap(), 2);
+        let TrackDataStream::Mfm(codec) = &stream else {
+            unreachable!();
+        };
+        let bits = codec.bit_vec.iter().skip(16).take(16).collect::<BitVec>();
+        assert_eq!(bits_to_u16(&bits), 0x4489);
+
+        assert!(matches!(
+            stream.write_raw_buf(&[0; 2], 56),
+            Err(DiskImageError::SeekError)
+        ));
+
+        let mut raw_stream = TrackDataStream::Raw(RawCodec::new(BitVec::from_elem(64, false), None));
+        assert!(matches!(
+            raw_stream.write_raw_buf(&[0], 0),
+            Err(DiskImageError::UnsupportedFormat)
+        ));
+    }
+}
@@ -25,24 +25,30 @@
     --------------------------------------------------------------------------
 */
 
+pub mod fm;
+pub mod gcr;
 pub mod mfm;
+pub mod pll;
 pub mod raw;
 
-use crate::bitstream::mfm::MfmCodec;
+use crate::bitstream::fm::FmCodec;
+use crate::bitstream::gcr::GcrCodec;
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType};
 use crate::bitstream::raw::RawCodec;
-use crate::io::{Read, Seek};
-use crate::EncodingPhase;
+use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use crate::random::RandomSource;
+use crate::{DiskImageError, EncodingPhase};
 use bit_vec::BitVec;
 use std::ops::Index;
 
 pub trait TrackDataStreamT: Iterator + Seek + Index<usize> {}
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum TrackDataStream {
     Raw(RawCodec),
     Mfm(MfmCodec),
-    Fm(BitVec),
-    Gcr(BitVec),
+    Fm(FmCodec),
+    Gcr(GcrCodec),
 }
 
 impl Iterator for TrackDataStream {
@@ -52,7 +58,8 @@ impl Iterator for TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.next(),
             TrackDataStream::Mfm(data) => data.next(),
-            _ => None,
+            TrackDataStream::Fm(data) => data.next(),
+            TrackDataStream::Gcr(data) => data.next(),
         }
     }
 }
@@ -64,7 +71,19 @@ impl Index<usize> for TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => &data[index],
             TrackDataStream::Mfm(data) => &data[index],
-            _ => &false,
+            TrackDataStream::Fm(data) => &data[index],
+            TrackDataStream::Gcr(data) => &data[index],
+        }
+    }
+}
+
+impl Seek for TrackDataStream {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match self {
+            TrackDataStream::Raw(data) => data.seek(pos),
+            TrackDataStream::Mfm(data) => data.seek(pos),
+            TrackDataStream::Fm(data) => data.seek(pos),
+            TrackDataStream::Gcr(data) => data.seek(pos),
         }
     }
 }
@@ -74,7 +93,8 @@ impl TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.len(),
             TrackDataStream::Mfm(data) => data.len(),
-            _ => 0,
+            TrackDataStream::Fm(data) => data.len(),
+            TrackDataStream::Gcr(data) => data.len(),
         }
     }
 
@@ -82,15 +102,52 @@ impl TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.is_empty(),
             TrackDataStream::Mfm(data) => data.is_empty(),
-            _ => true,
+            TrackDataStream::Fm(data) => data.is_empty(),
+            TrackDataStream::Gcr(data) => data.is_empty(),
+        }
+    }
+
+    /// Return the bytes allocated for the bitstream, clock map and weak bit mask.
+    pub(crate) fn memory_usage(&self) -> (usize, usize, usize) {
+        match self {
+            TrackDataStream::Raw(data) => {
+                let (bits, weak) = data.memory_usage();
+                (bits, 0, weak)
+            }
+            TrackDataStream::Mfm(data) => data.memory_usage(),
+            TrackDataStream::Fm(data) => data.memory_usage(),
+            TrackDataStream::Gcr(data) => data.memory_usage(),
         }
     }
 
     pub fn replace(&mut self, new_bits: BitVec) {
+        let rng = self.rng().clone();
         match self {
             TrackDataStream::Raw(data) => *data = RawCodec::new(new_bits, None),
             TrackDataStream::Mfm(data) => *data = MfmCodec::new(new_bits, None, None),
-            _ => {}
+            TrackDataStream::Fm(data) => *data = FmCodec::new(new_bits, None, None),
+            TrackDataStream::Gcr(data) => *data = GcrCodec::new(new_bits, None, None),
+        }
+        self.set_rng(rng);
+    }
+
+    /// Return the source of the random data returned when reading weak bits.
+    pub(crate) fn rng(&self) -> &RandomSource {
+        match self {
+            TrackDataStream::Raw(data) => data.rng(),
+            TrackDataStream::Mfm(data) => data.rng(),
+            TrackDataStream::Fm(data) => data.rng(),
+            TrackDataStream::Gcr(data) => data.rng(),
+        }
+    }
+
+    /// Set the source of the random data returned when reading weak bits.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        match self {
+            TrackDataStream::Raw(data) => data.set_rng(rng),
+            TrackDataStream::Mfm(data) => data.set_rng(rng),
+            TrackDataStream::Fm(data) => data.set_rng(rng),
+            TrackDataStream::Gcr(data) => data.set_rng(rng),
         }
     }
 
@@ -101,13 +158,15 @@ impl TrackDataStream {
                 //let data_len = data.len() / 8;
                 data.data()
             }
-            _ => panic!("Unsupported operation"),
+            TrackDataStream::Fm(data) => data.data(),
+            TrackDataStream::Gcr(data) => data.data(),
         }
     }
 
     pub fn set_clock_map(&mut self, clock_map: BitVec) {
         match self {
             TrackDataStream::Mfm(data) => data.set_clock_map(clock_map),
+            TrackDataStream::Fm(data) => data.set_clock_map(clock_map),
             _ => {}
         }
     }
@@ -115,6 +174,7 @@ impl TrackDataStream {
     pub fn clock_map_mut(&mut self) -> Option<&mut BitVec> {
         match self {
             TrackDataStream::Mfm(data) => Some(data.clock_map_mut()),
+            TrackDataStream::Fm(data) => Some(data.clock_map_mut()),
             _ => None,
         }
     }
@@ -122,13 +182,37 @@ impl TrackDataStream {
     pub fn get_sync(&self) -> Option<EncodingPhase> {
         match self {
             TrackDataStream::Mfm(data) => data.get_sync(),
+            TrackDataStream::Fm(data) => data.get_sync(),
+            TrackDataStream::Gcr(data) => data.get_sync(),
             _ => None,
         }
     }
 
+    /// Replace the weak bit mask of the track. The mask must be the same length as the track.
+    pub fn set_weak_mask(&mut self, weak_mask: BitVec) -> Result<()> {
+        match self {
+            TrackDataStream::Raw(_) => Err(Error::new(ErrorKind::Unsupported, "Raw tracks have no weak bit mask")),
+            TrackDataStream::Mfm(data) => data.set_weak_mask(weak_mask),
+            TrackDataStream::Fm(data) => data.set_weak_mask(weak_mask),
+            TrackDataStream::Gcr(data) => data.set_weak_mask(weak_mask),
+        }
+    }
+
+    /// Return the raw bitcells of the track.
+    pub fn bits(&self) -> &BitVec {
+        match self {
+            TrackDataStream::Raw(data) => data.bits(),
+            TrackDataStream::Mfm(data) => data.bits(),
+            TrackDataStream::Fm(data) => data.bits(),
+            TrackDataStream::Gcr(data) => data.bits(),
+        }
+    }
+
     pub fn get_weak_mask(&self) -> Option<&BitVec> {
         match self {
             TrackDataStream::Mfm(data) => Some(data.get_weak_mask()),
+            TrackDataStream::Fm(data) => Some(data.get_weak_mask()),
+            TrackDataStream::Gcr(data) => Some(data.get_weak_mask()),
             _ => None,
         }
     }
@@ -140,11 +224,26 @@ impl TrackDataStream {
         }
     }
 
+    /// Return the bitcell at `index`, wrapping around the end of the track. Weak bitcells return
+    /// random data. Returns `None` if the stream is empty.
+    pub fn bit_at(&self, index: usize) -> Option<bool> {
+        let bits = self.bits();
+        if bits.is_empty() {
+            return None;
+        }
+        let index = index % bits.len();
+        match self.get_weak_mask() {
+            Some(weak_mask) if weak_mask.get(index) == Some(true) => Some(self.rng().next_bool()),
+            _ => bits.get(index),
+        }
+    }
+
     pub fn read_byte(&self, index: usize) -> Option<u8> {
         match self {
             TrackDataStream::Raw(data) => data.read_byte(index),
             TrackDataStream::Mfm(data) => data.read_byte(index),
-            _ => None,
+            TrackDataStream::Fm(data) => data.read_byte(index),
+            TrackDataStream::Gcr(data) => data.read_byte(index),
         }
     }
 
@@ -152,7 +251,8 @@ impl TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.read_byte(index),
             TrackDataStream::Mfm(data) => data.read_decoded_byte(index),
-            _ => None,
+            TrackDataStream::Fm(data) => data.read_decoded_byte(index),
+            TrackDataStream::Gcr(data) => data.read_decoded_byte(index),
         }
     }
 
@@ -160,7 +260,8 @@ impl TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.read_exact(buf).ok().map(|_| buf.len()),
             TrackDataStream::Mfm(data) => data.read_exact(buf).ok().map(|_| buf.len()),
-            _ => None,
+            TrackDataStream::Fm(data) => data.read_exact(buf).ok().map(|_| buf.len()),
+            TrackDataStream::Gcr(data) => data.read_exact(buf).ok().map(|_| buf.len()),
         }
     }
 
@@ -168,13 +269,48 @@ impl TrackDataStream {
         match self {
             TrackDataStream::Raw(data) => data.write_buf(buf, offset).ok().map(|_| buf.len()),
             TrackDataStream::Mfm(data) => data.write_buf(buf, offset).ok().map(|_| buf.len()),
-            _ => None,
+            TrackDataStream::Fm(data) => data.write_buf(buf, offset).ok().map(|_| buf.len()),
+            TrackDataStream::Gcr(data) => data.write_buf(buf, offset).ok().map(|_| buf.len()),
+        }
+    }
+
+    /// Encode `buf` and write it at the raw bitcell index `offset`, returning the number of
+    /// bitcells written. Only supported for MFM streams.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the stream is not MFM-encoded.
+    /// - `Err(DiskImageError::SeekError)` if `offset` is past the end of the stream.
+    pub fn write_encoded_buf(
+        &mut self,
+        buf: &[u8],
+        offset: usize,
+        encoding_type: MfmEncodingType,
+    ) -> std::result::Result<usize, DiskImageError> {
+        match self {
+            TrackDataStream::Mfm(data) => data
+                .write_encoded_buf(buf, offset, encoding_type)
+                .map_err(|_| DiskImageError::SeekError),
+            _ => Err(DiskImageError::UnsupportedFormat),
+        }
+    }
+
+    /// Write the bits of `buf` verbatim, without encoding, starting at the raw bitcell index
+    /// `offset`, and return the number of bytes written. Only supported for MFM streams.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the stream is not MFM-encoded.
+    /// - `Err(DiskImageError::SeekError)` if the write would extend past the end of the stream.
+    pub fn write_raw_buf(&mut self, buf: &[u8], offset: usize) -> std::result::Result<usize, DiskImageError> {
+        match self {
+            TrackDataStream::Mfm(data) => data.write_raw_buf(buf, offset).map_err(|_| DiskImageError::SeekError),
+            _ => Err(DiskImageError::UnsupportedFormat),
         }
     }
 
     pub fn debug_marker(&self, index: usize) -> String {
         match self {
             TrackDataStream::Mfm(data) => data.debug_marker(index),
+            TrackDataStream::Fm(data) => data.debug_marker(index),
             _ => String::new(),
         }
     }
@@ -0,0 +1,201 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/bitstream/pll.rs
+
+    A software phase-locked loop to separate clocked bitcells from a stream of
+    flux transition intervals.
+
+*/
+use crate::flux::{cell_time_of, FluxRevolution};
+use crate::DiskDataRate;
+use bit_vec::BitVec;
+
+/// The default fraction of the phase error of each flux transition used to adjust the clock
+/// period.
+const DEFAULT_ADJUST_RATE: f64 = 0.05;
+/// The fraction of the phase error of each flux transition carried into the next interval.
+const PHASE_CARRY: f64 = 0.5;
+/// The default maximum deviation of the clock period from the nominal bitcell time.
+const DEFAULT_CLOCK_TOLERANCE: f64 = 0.1;
+/// The default maximum phase error of a flux transition, as a fraction of the clock period, for
+/// it to be considered within the data window.
+const DEFAULT_JITTER_TOLERANCE: f64 = 0.25;
+/// The number of consecutive out-of-band transitions after which the PLL is considered to have
+/// lost lock.
+const LOCK_LOSS_RUN: usize = 4;
+
+/// Statistics collected by a [`Pll`] while decoding a revolution, for diagnosing poor quality or
+/// unusually recorded flux data.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PllStats {
+    /// The number of flux transitions decoded.
+    pub transitions: usize,
+    /// The number of bitcells produced.
+    pub bitcells: usize,
+    /// The number of transitions whose phase error exceeded the jitter tolerance.
+    pub out_of_band: usize,
+    /// The number of times the PLL lost lock, on a run of consecutive out-of-band transitions.
+    pub lock_losses: usize,
+    /// The largest phase error seen, as a fraction of the clock period.
+    pub max_phase_error: f64,
+    /// The average clock period over the revolution, in seconds.
+    pub mean_clock: f64,
+}
+
+/// A [`Pll`] converts flux transition intervals into bitcells by tracking the bitcell clock of the
+/// recorded data, so that variations in drive speed over a revolution do not shift transitions
+/// into the wrong cell.
+///
+/// All flux-level image formats resolve their flux data into bitcells with a [`Pll`], so its
+/// parameters behave the same whatever format a track was captured in.
+#[derive(Copy, Clone, Debug)]
+pub struct Pll {
+    /// The nominal duration of a bitcell, in seconds.
+    cell_time: f64,
+    /// The fraction of the phase error of each flux transition used to adjust the clock period.
+    adjust_rate: f64,
+    /// The maximum deviation of the clock period from the nominal bitcell time, as a fraction of
+    /// the nominal bitcell time.
+    clock_tolerance: f64,
+    /// The maximum phase error of a flux transition, as a fraction of the clock period, for it to
+    /// be considered within the data window.
+    jitter_tolerance: f64,
+}
+
+impl Pll {
+    /// Create a PLL with a nominal bitcell time of `cell_time` seconds.
+    pub fn new(cell_time: f64) -> Self {
+        Self {
+            cell_time,
+            adjust_rate: DEFAULT_ADJUST_RATE,
+            clock_tolerance: DEFAULT_CLOCK_TOLERANCE,
+            jitter_tolerance: DEFAULT_JITTER_TOLERANCE,
+        }
+    }
+
+    /// Create a PLL for MFM data recorded at `data_rate`.
+    pub fn from_data_rate(data_rate: DiskDataRate) -> Self {
+        Self::new(cell_time_of(data_rate))
+    }
+
+    /// Set the fraction of the phase error of each flux transition used to adjust the clock
+    /// period. Higher rates follow speed variations more quickly, but are more easily disturbed
+    /// by jitter.
+    pub fn with_adjust_rate(mut self, adjust_rate: f64) -> Self {
+        self.adjust_rate = adjust_rate;
+        self
+    }
+
+    /// Set the maximum deviation of the clock period from the nominal bitcell time, as a fraction
+    /// of the nominal bitcell time.
+    pub fn with_clock_tolerance(mut self, clock_tolerance: f64) -> Self {
+        self.clock_tolerance = clock_tolerance;
+        self
+    }
+
+    /// Set the maximum phase error of a flux transition, as a fraction of the clock period, for it
+    /// to be considered within the data window. Transitions outside the window are still decoded,
+    /// but are counted in [`PllStats::out_of_band`].
+    pub fn with_jitter_tolerance(mut self, jitter_tolerance: f64) -> Self {
+        self.jitter_tolerance = jitter_tolerance;
+        self
+    }
+
+    /// Return the nominal duration of a bitcell, in seconds.
+    pub fn cell_time(&self) -> f64 {
+        self.cell_time
+    }
+
+    /// Return the fraction of the phase error of each flux transition used to adjust the clock
+    /// period.
+    pub fn adjust_rate(&self) -> f64 {
+        self.adjust_rate
+    }
+
+    /// Return the maximum deviation of the clock period from the nominal bitcell time.
+    pub fn clock_tolerance(&self) -> f64 {
+        self.clock_tolerance
+    }
+
+    /// Return the maximum phase error of a flux transition within the data window.
+    pub fn jitter_tolerance(&self) -> f64 {
+        self.jitter_tolerance
+    }
+
+    /// Decode a revolution of flux transitions into bitcells. Each transition is placed in the
+    /// cell closest to it, and produces a set bit preceded by a clear bit for each empty cell.
+    pub fn decode(&self, flux: &FluxRevolution) -> BitVec {
+        self.decode_with_stats(flux).0
+    }
+
+    /// Decode a revolution of flux transitions into bitcells as [`Pll::decode`] does, returning
+    /// statistics about the decode along with the bitcells.
+    pub fn decode_with_stats(&self, flux: &FluxRevolution) -> (BitVec, PllStats) {
+        let min_clock = self.cell_time * (1.0 - self.clock_tolerance);
+        let max_clock = self.cell_time * (1.0 + self.clock_tolerance);
+
+        let mut bits = BitVec::with_capacity((flux.duration() / self.cell_time) as usize + 1);
+        let mut stats = PllStats::default();
+        let mut clock = self.cell_time;
+        let mut clock_sum = 0.0;
+        let mut phase_error = 0.0;
+        let mut out_of_band_run = 0;
+
+        for interval in flux.iter_seconds() {
+            let time = interval + phase_error;
+            let cells = (time / clock).round().max(1.0);
+            for _ in 1..cells as usize {
+                bits.push(false);
+            }
+            bits.push(true);
+
+            let error = time - cells * clock;
+            let relative_error = (error / clock).abs();
+            stats.max_phase_error = stats.max_phase_error.max(relative_error);
+            if relative_error > self.jitter_tolerance {
+                stats.out_of_band += 1;
+                out_of_band_run += 1;
+                if out_of_band_run == LOCK_LOSS_RUN {
+                    stats.lock_losses += 1;
+                }
+            } else {
+                out_of_band_run = 0;
+            }
+
+            clock = (clock + error / cells * self.adjust_rate).clamp(min_clock, max_clock);
+            clock_sum += clock;
+            phase_error = error * PHASE_CARRY;
+        }
+
+        stats.transitions = flux.transition_ct();
+        stats.bitcells = bits.len();
+        if stats.transitions > 0 {
+            stats.mean_clock = clock_sum / stats.transitions as f64;
+        }
+        (bits, stats)
+    }
+}
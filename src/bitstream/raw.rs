@@ -31,15 +31,18 @@
 */
 
 use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use crate::random::RandomSource;
 use crate::EncodingPhase;
 use bit_vec::BitVec;
 use std::ops::Index;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RawCodec {
     bit_vec: BitVec,
     weak_mask: BitVec,
     bit_cursor: usize,
+    /// The source of the random data returned for weak bits.
+    rng: RandomSource,
 }
 
 impl RawCodec {
@@ -57,9 +60,20 @@ impl RawCodec {
             bit_vec,
             weak_mask,
             bit_cursor: 0,
+            rng: RandomSource::default(),
         }
     }
 
+    /// Return the source of the random data returned when reading weak bits.
+    pub(crate) fn rng(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    /// Set the source of the random data returned when reading weak bits.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        self.rng = rng;
+    }
+
     pub fn len(&self) -> usize {
         self.bit_vec.len()
     }
@@ -145,11 +159,21 @@ impl Index<usize> for RawCodec {
 }
 
 impl RawCodec {
+    /// Return the raw bitcells of the track.
+    pub fn bits(&self) -> &BitVec {
+        &self.bit_vec
+    }
+
+    /// Return the bytes allocated for the bitstream and weak bit mask.
+    pub(crate) fn memory_usage(&self) -> (usize, usize) {
+        (self.bit_vec.capacity() / 8, self.weak_mask.capacity() / 8)
+    }
+
     #[allow(dead_code)]
     fn read_bit(self) -> Option<bool> {
         if self.weak_mask[self.bit_cursor] {
             // Weak bits return random data
-            Some(rand::random())
+            Some(self.rng.next_bool())
         } else {
             Some(self.bit_vec[self.bit_cursor])
         }
@@ -158,7 +182,7 @@ impl RawCodec {
     fn read_bit_at(&self, index: usize) -> Option<bool> {
         if self.weak_mask[index] {
             // Weak bits return random data
-            Some(rand::random())
+            Some(self.rng.next_bool())
         } else {
             Some(self.bit_vec[index])
         }
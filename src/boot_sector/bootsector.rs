@@ -30,7 +30,10 @@
 
 */
 
-use crate::boot_sector::bpb::{BiosParameterBlock2, BiosParameterBlock3, BPB_OFFSET};
+use crate::boot_sector::bpb::{
+    BiosParameterBlock2, BiosParameterBlock3, BPB_END, BPB_OFFSET, EBPB_END, EBPB_FS_TYPE_OFFSET, EBPB_LABEL_OFFSET,
+    EBPB_SERIAL_OFFSET, EBPB_SIGNATURE, EBPB_SIGNATURE_OFFSET,
+};
 use crate::io::{Cursor, ReadSeek, ReadWriteSeek, Seek, SeekFrom, Write};
 use crate::{DiskImageError, StandardFormat};
 use binrw::{binrw, BinRead, BinWrite};
@@ -144,6 +147,80 @@ impl BootSector {
             .write(&mut self.sector_buf)
             .map_err(|_e| DiskImageError::IoError)?;
 
+        // Clear the DOS 3.31 large sector count, which no standard floppy format needs.
+        let bpb3_end = self.sector_buf.position() as usize;
+        self.sector_buf.get_mut()[bpb3_end..BPB_END].fill(0);
+
+        Ok(())
+    }
+
+    /// Replace the boot code of the sector with that of `code`, keeping the current BPB. The
+    /// extended BPB, holding the volume serial number and label, is also kept if `code` has one
+    /// or leaves space for one; otherwise `code` may place its own code where it would be.
+    pub(crate) fn replace_code(&mut self, code: &[u8; 512]) {
+        let keep_ebpb = has_extended_bpb(code) || code_start(code) >= EBPB_END;
+        let keep_end = match keep_ebpb && self.has_extended_bpb() {
+            true => EBPB_END,
+            // Older boot code may start before the end of the DOS 3.31 BPB.
+            false => code_start(code).clamp(BPB_OFFSET as usize, BPB_END),
+        };
+        let mut sector = *code;
+        sector[BPB_OFFSET as usize..keep_end].copy_from_slice(&self.as_bytes()[BPB_OFFSET as usize..keep_end]);
+        self.marker = [sector[510], sector[511]];
+        *self.sector_buf.get_mut() = sector;
+    }
+
+    /// Return true if the boot sector has a DOS 4.0 extended BPB.
+    pub(crate) fn has_extended_bpb(&self) -> bool {
+        has_extended_bpb(self.as_bytes())
+    }
+
+    /// Add an extended BPB to the boot sector if it doesn't have one, provided the jump at the
+    /// start of the sector skips over the space it occupies.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the boot code occupies the space of the
+    ///   extended BPB.
+    fn ensure_extended_bpb(&mut self) -> Result<(), DiskImageError> {
+        if self.has_extended_bpb() {
+            return Ok(());
+        }
+        let sector = self.sector_buf.get_mut();
+        if code_start(sector) < EBPB_END {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+        sector[BPB_END..EBPB_END].fill(0);
+        sector[EBPB_SIGNATURE_OFFSET] = EBPB_SIGNATURE;
+        sector[EBPB_LABEL_OFFSET..EBPB_FS_TYPE_OFFSET].copy_from_slice(b"NO NAME    ");
+        sector[EBPB_FS_TYPE_OFFSET..EBPB_END].copy_from_slice(b"FAT12   ");
+        Ok(())
+    }
+
+    /// Return the volume serial number from the extended BPB, if present.
+    pub(crate) fn volume_serial(&self) -> Option<u32> {
+        let sector = self.as_bytes();
+        self.has_extended_bpb()
+            .then(|| u32::from_le_bytes(sector[EBPB_SERIAL_OFFSET..EBPB_LABEL_OFFSET].try_into().unwrap()))
+    }
+
+    /// Set the volume serial number in the extended BPB, adding one if needed.
+    pub(crate) fn set_volume_serial(&mut self, serial: u32) -> Result<(), DiskImageError> {
+        self.ensure_extended_bpb()?;
+        self.sector_buf.get_mut()[EBPB_SERIAL_OFFSET..EBPB_LABEL_OFFSET].copy_from_slice(&serial.to_le_bytes());
+        Ok(())
+    }
+
+    /// Return the volume label from the extended BPB, if present.
+    pub(crate) fn volume_label(&self) -> Option<[u8; 11]> {
+        let sector = self.as_bytes();
+        self.has_extended_bpb()
+            .then(|| sector[EBPB_LABEL_OFFSET..EBPB_FS_TYPE_OFFSET].try_into().unwrap())
+    }
+
+    /// Set the volume label in the extended BPB, adding one if needed.
+    pub(crate) fn set_volume_label(&mut self, label: &[u8; 11]) -> Result<(), DiskImageError> {
+        self.ensure_extended_bpb()?;
+        self.sector_buf.get_mut()[EBPB_LABEL_OFFSET..EBPB_FS_TYPE_OFFSET].copy_from_slice(label);
         Ok(())
     }
 
@@ -206,3 +283,17 @@ impl BootSector {
         Ok(())
     }
 }
+
+fn has_extended_bpb(sector: &[u8; 512]) -> bool {
+    sector[EBPB_SIGNATURE_OFFSET] == EBPB_SIGNATURE
+}
+
+/// Return the offset of the boot code, the target of the jump at the start of the sector, or 0 if
+/// the sector does not start with a jump.
+fn code_start(sector: &[u8; 512]) -> usize {
+    match sector[0] {
+        0xEB => 2 + sector[1] as usize,
+        0xE9 => 3 + u16::from_le_bytes([sector[1], sector[2]]) as usize,
+        _ => 0,
+    }
+}
@@ -41,6 +41,16 @@ use binrw::binrw;
 
 // Offset of the bios parameter block in the boot sector.
 pub const BPB_OFFSET: u64 = 0x0B;
+// Offset of the end of the DOS 3.31 bios parameter block, which adds a 32-bit sector count.
+pub const BPB_END: usize = 0x24;
+
+// The extended BPB introduced in DOS 4.0 follows the BPB, and is identified by a signature byte.
+pub const EBPB_SIGNATURE_OFFSET: usize = 0x26;
+pub const EBPB_SIGNATURE: u8 = 0x29;
+pub const EBPB_SERIAL_OFFSET: usize = 0x27;
+pub const EBPB_LABEL_OFFSET: usize = 0x2B;
+pub const EBPB_FS_TYPE_OFFSET: usize = 0x36;
+pub const EBPB_END: usize = 0x3E;
 
 #[derive(Debug, Default)]
 #[binrw]
@@ -0,0 +1,256 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/canonical.rs
+
+    Builds a normalized copy of a disk image, re-mastering each track with
+    a fixed layout so that images of the same disk produced by different
+    tools can be compared by hash.
+*/
+
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType, MFM_BYTE_LEN};
+use crate::bitstream::TrackDataStream;
+use crate::structure_parsers::system34::{
+    System34Element, System34Marker, DAM_MARKER_BYTES, DDAM_MARKER_BYTES, GAP_BYTE, IAM_MARKER_BYTES, IBM_GAP1,
+    IBM_GAP4A, IDAM_MARKER_BYTES, ISO_GAP1, ISO_GAP2, SYNC_BYTE, SYNC_LEN,
+};
+use crate::structure_parsers::DiskStructureElement;
+use crate::track_builder::TrackBuilder;
+use crate::trackdata::TrackData;
+use crate::{DiskImage, DiskImageError, DiskRpm};
+
+/// The GAP3 length used when the image does not have a standard format.
+const CANONICAL_GAP3: usize = 0x54;
+
+/// How weak bits are treated by [`DiskImage::canonicalize`]. Weak bits read differently each
+/// time, so they are always written as zeros in the canonical image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WeakBitPolicy {
+    /// Keep the zeroed bits marked as weak, so that they still read as random data.
+    #[default]
+    Mark,
+    /// Clear the zeroed bits from the weak bit mask, so that they read back as zeros.
+    Clear,
+}
+
+/// A field of a sector as read from a track, with any weak bits zeroed.
+struct Field {
+    bytes: Vec<u8>,
+    /// A mask of the weak bits of `bytes`.
+    weak: Vec<u8>,
+}
+
+/// A sector as read from a track: its ID field and CRC, and its data field, if one follows.
+struct Sector {
+    id: Field,
+    /// The data address mark, data and CRC.
+    data: Option<(u8, Field)>,
+}
+
+/// Build a canonical copy of `image`. See [`DiskImage::canonicalize`].
+pub(crate) fn build(image: &DiskImage, policy: WeakBitPolicy) -> Result<DiskImage, DiskImageError> {
+    let mut result = DiskImage {
+        standard_format: image.standard_format,
+        resolution: image.resolution,
+        descriptor: image.descriptor,
+        consistency: image.consistency.clone(),
+        volume_name: image.volume_name.clone(),
+        comment: image.comment.clone(),
+        track_pool: image.track_pool.clone(),
+        track_map: image.track_map.clone(),
+        sub_track_map: image.sub_track_map.clone(),
+        match_policy: image.match_policy,
+        crc_params: image.crc_params,
+        ..Default::default()
+    };
+
+    let rpm = image
+        .descriptor
+        .rpm
+        .or(image.standard_format.map(|format| format.get_rpm()))
+        .unwrap_or(DiskRpm::Rpm300);
+    let gap3 = image.standard_format.map_or(CANONICAL_GAP3, |format| format.get_gap3());
+
+    for track in result.track_pool.iter_mut() {
+        match track {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(_),
+                data_rate,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(_),
+                data_rate,
+                ..
+            } => {
+                let bitcell_ct = rpm.track_bitcells(*data_rate);
+                canonicalize_bitstream(track, bitcell_ct, gap3, policy)?;
+            }
+            TrackData::BitStream { cylinder, head, .. } | TrackData::FluxStream { cylinder, head, .. } => {
+                log::warn!(
+                    "canonicalize(): Track c:{} h:{} is not an MFM track, and was copied unchanged.",
+                    cylinder,
+                    head
+                );
+            }
+            TrackData::ByteStream { data, weak_mask, .. } => {
+                for (byte, weak) in data.iter_mut().zip(weak_mask.iter()) {
+                    *byte &= !weak;
+                }
+                if policy == WeakBitPolicy::Clear {
+                    weak_mask.clear();
+                }
+            }
+        }
+    }
+
+    if let Ok(buf) = result.read_boot_sector() {
+        _ = result.parse_boot_sector(&buf);
+    }
+    Ok(result)
+}
+
+/// Re-master the MFM `track` from its index, with standard gaps and sync fields of fixed length,
+/// keeping each sector's ID, data and recorded CRCs. The track is made `bitcell_ct` bitcells
+/// long, unless its sectors need more. Data fields not preceded by an ID field can't be read and
+/// are dropped.
+fn canonicalize_bitstream(
+    track: &mut TrackData,
+    bitcell_ct: usize,
+    gap3: usize,
+    policy: WeakBitPolicy,
+) -> Result<(), DiskImageError> {
+    let (TrackData::BitStream {
+        data: TrackDataStream::Mfm(mfm_codec),
+        metadata,
+        ..
+    }
+    | TrackData::FluxStream {
+        data: TrackDataStream::Mfm(mfm_codec),
+        metadata,
+        ..
+    }) = &*track
+    else {
+        return Err(DiskImageError::UnsupportedFormat);
+    };
+
+    let mut iam = false;
+    let mut sectors: Vec<Sector> = Vec::new();
+    for mdi in &metadata.items {
+        let DiskStructureElement::System34(element) = mdi.elem_type else {
+            continue;
+        };
+        match element {
+            System34Element::Marker(System34Marker::Iam, _) => iam = true,
+            System34Element::Marker(System34Marker::Idam, _) => sectors.push(Sector {
+                id: read_field(mfm_codec, mdi.start + 4 * MFM_BYTE_LEN, 6),
+                data: None,
+            }),
+            System34Element::Data { deleted, .. } => {
+                let Some(sector) = sectors.last_mut().filter(|s| s.data.is_none()) else {
+                    continue;
+                };
+                let mark = match deleted {
+                    true => DDAM_MARKER_BYTES,
+                    false => DAM_MARKER_BYTES,
+                };
+                let len = (mdi.end - mdi.start) / MFM_BYTE_LEN - 4;
+                sector.data = Some((mark[3], read_field(mfm_codec, mdi.start + 4 * MFM_BYTE_LEN, len + 2)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut builder = TrackBuilder::new();
+    builder = match iam {
+        true => builder
+            .with_gap(GAP_BYTE, IBM_GAP4A)
+            .with_gap(SYNC_BYTE, SYNC_LEN)
+            .with_encoded(&IAM_MARKER_BYTES, MfmEncodingType::AddressMark)
+            .with_gap(GAP_BYTE, IBM_GAP1),
+        false => builder.with_gap(GAP_BYTE, ISO_GAP1),
+    };
+    for sector in &sectors {
+        builder = builder
+            .with_gap(SYNC_BYTE, SYNC_LEN)
+            .with_encoded(&IDAM_MARKER_BYTES, MfmEncodingType::AddressMark);
+        builder = with_field(builder, &sector.id, IDAM_MARKER_BYTES[3], policy)?.with_gap(GAP_BYTE, ISO_GAP2);
+        if let Some((mark, data)) = &sector.data {
+            builder = builder
+                .with_gap(SYNC_BYTE, SYNC_LEN)
+                .with_encoded(&[0xA1, 0xA1, 0xA1, *mark], MfmEncodingType::AddressMark);
+            builder = with_field(builder, data, *mark, policy)?.with_gap(GAP_BYTE, gap3);
+        }
+    }
+
+    let bitcell_ct = std::cmp::max(bitcell_ct, builder.bitcell_len());
+    let (bits, weak) = builder.build(bitcell_ct)?;
+    track.replace_bits(bits, weak)
+}
+
+/// Read `len` bytes from the track at the bitcell index `start`, zeroing any weak bits. A data
+/// bit is weak if either its clock or data bitcell is weak.
+fn read_field(mfm_codec: &MfmCodec, start: usize, len: usize) -> Field {
+    let weak_mask = mfm_codec.get_weak_mask();
+    let mut bytes = Vec::with_capacity(len);
+    let mut weak = vec![0u8; len];
+    for (i, weak_byte) in weak.iter_mut().enumerate() {
+        for bit in 0..8 {
+            let cell = start + (i * 8 + bit) * 2;
+            if weak_mask.get(cell).unwrap_or(false) || weak_mask.get(cell + 1).unwrap_or(false) {
+                *weak_byte |= 0x80 >> bit;
+            }
+        }
+        let byte = mfm_codec.read_decoded_byte(start + i * MFM_BYTE_LEN).unwrap_or(0);
+        bytes.push(byte & !*weak_byte);
+    }
+    Field { bytes, weak }
+}
+
+/// Append `field` to `builder`, following the byte `prev`. Under [`WeakBitPolicy::Mark`], both
+/// bitcells of each weak bit are marked weak.
+fn with_field(
+    builder: TrackBuilder,
+    field: &Field,
+    prev: u8,
+    policy: WeakBitPolicy,
+) -> Result<TrackBuilder, DiskImageError> {
+    if policy == WeakBitPolicy::Clear || field.weak.iter().all(|w| *w == 0) {
+        return Ok(builder.with_encoded(&field.bytes, MfmEncodingType::Data));
+    }
+
+    let bits = MfmCodec::encode_mfm(&field.bytes, prev & 1 != 0, MfmEncodingType::Data);
+    let mut weak = vec![0u8; field.weak.len() * 2];
+    for (i, weak_byte) in field.weak.iter().enumerate() {
+        for bit in 0..8 {
+            if weak_byte & (0x80 >> bit) != 0 {
+                // Each data bit is two bitcells, so each source bit is two bits of the mask.
+                weak[i * 2 + bit / 4] |= 0xC0 >> ((bit % 4) * 2);
+            }
+        }
+    }
+    builder.with_bits(&bits.to_bytes(), bits.len(), Some(&weak))
+}
@@ -249,6 +249,20 @@ impl DiskChs {
         (self.c as usize * hpc + (self.h as usize)) * spt + (self.s as usize - 1)
     }
 
+    /// Convert an LBA sector address to a DiskChs struct, the inverse of [`DiskChs::to_lba`].
+    /// Returns None if the address is beyond the end of the reference drive geometry.
+    pub fn from_lba(lba: usize, geom: &DiskChs) -> Option<DiskChs> {
+        let hpc = geom.h as usize;
+        let spt = geom.s as usize;
+        if hpc == 0 || spt == 0 || lba >= geom.get_sector_count() as usize {
+            return None;
+        }
+        let c = lba / (hpc * spt);
+        let h = (lba / spt) % hpc;
+        let s = lba % spt + 1;
+        Some(DiskChs::from((c as u16, h as u8, s as u8)))
+    }
+
     /// Return a new CHS that is the next sector on the disk.
     /// If the current CHS is the last sector on the disk, the next CHS will be the first sector on the disk.
     pub(crate) fn get_next_sector(&self, geom: &DiskChs) -> DiskChs {
@@ -347,6 +361,53 @@ impl DiskCh {
     }
 }
 
+/// A head position in quarter-track steps. Whole cylinders are at multiples of four; the
+/// positions between them hold the half-tracks and quarter-tracks captured by some formats, such
+/// as those used for Apple II copy protection.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Default)]
+pub struct QuarterTrack(pub u16);
+
+impl From<u16> for QuarterTrack {
+    /// Return the position of whole cylinder `c`.
+    fn from(c: u16) -> Self {
+        Self(c * 4)
+    }
+}
+
+impl Display for QuarterTrack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.quarter() {
+            0 => write!(f, "{}", self.c()),
+            1 => write!(f, "{}.25", self.c()),
+            2 => write!(f, "{}.5", self.c()),
+            _ => write!(f, "{}.75", self.c()),
+        }
+    }
+}
+
+impl QuarterTrack {
+    /// Return the position `quarter` quarter-tracks past cylinder `c`.
+    pub fn new(c: u16, quarter: u8) -> Self {
+        Self(c * 4 + (quarter & 0x03) as u16)
+    }
+    /// Return the position of half-track `half`, where even half-tracks are whole cylinders.
+    pub fn from_half(half: u16) -> Self {
+        Self(half * 2)
+    }
+    /// Return the whole cylinder at or below this position.
+    pub fn c(&self) -> u16 {
+        self.0 / 4
+    }
+    /// Return the number of quarter-tracks past the whole cylinder.
+    pub fn quarter(&self) -> u8 {
+        (self.0 % 4) as u8
+    }
+    /// Return true if this position is on a whole cylinder.
+    pub fn is_whole(&self) -> bool {
+        self.quarter() == 0
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -384,6 +445,14 @@ mod tests {
         assert_eq!(chs.to_lba(&geom), 49);
     }
 
+    #[test]
+    fn diskchs_from_lba_inverts_to_lba() {
+        let geom = DiskChs::new(40, 2, 9);
+        assert_eq!(DiskChs::from_lba(49, &geom), Some(DiskChs::new(2, 1, 5)));
+        assert_eq!(DiskChs::from_lba(719, &geom), Some(DiskChs::new(39, 1, 9)));
+        assert_eq!(DiskChs::from_lba(720, &geom), None);
+    }
+
     #[test]
     fn diskchs_get_next_sector_wraps_correctly() {
         let chs = DiskChs::new(1, 1, 2);
@@ -400,4 +469,12 @@ mod tests {
         let next_ch = ch.get_next_track(&geom);
         assert_eq!(next_ch, DiskCh::new(0, 0));
     }
+
+    #[test]
+    fn quarter_track_displays_fractional_position() {
+        assert_eq!(QuarterTrack::new(17, 2).to_string(), "17.5");
+        assert_eq!(QuarterTrack::from_half(35).to_string(), "17.5");
+        assert_eq!(QuarterTrack::new(3, 1).to_string(), "3.25");
+        assert_eq!(QuarterTrack::from(3).to_string(), "3");
+    }
 }
@@ -0,0 +1,270 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/consensus.rs
+
+    Builds a best-of disk image from several dumps of the same disk, by voting
+    on the contents of each sector.
+*/
+
+use crate::diskimage::{MatchPolicy, RwSectorScope, TrackMapEntry};
+use crate::structure_parsers::system34::{DAM_MARKER_BYTES, DDAM_MARKER_BYTES};
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskChs, DiskChsn, DiskImage, DiskImageError};
+use std::collections::HashSet;
+
+/// Where the data of a sector of a consensus image came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConsensusSource {
+    /// The data was read with a good CRC from the image at `index`, the first of `votes` images
+    /// that read identical good data. Where good copies differ, the data read by the most images
+    /// wins, and the earliest image breaks a tie.
+    Image { index: usize, votes: usize },
+    /// No image read the sector with a good CRC, but a majority vote of each bit across the
+    /// `copies` read produced data with a good CRC. Only possible for BitStream images.
+    Voted { copies: usize },
+    /// No good data could be found among the `copies` read. The data of the first image is kept,
+    /// with its bad CRC.
+    Unresolved { copies: usize },
+}
+
+/// The source of the data of one sector of a consensus image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConsensusSector {
+    /// The physical track holding the sector.
+    pub ch: DiskCh,
+    pub chsn: DiskChsn,
+    pub source: ConsensusSource,
+}
+
+/// A report of which source won each sector of an image built by [`DiskImage::consensus`].
+#[derive(Clone, Debug, Default)]
+pub struct ConsensusReport {
+    pub sectors: Vec<ConsensusSector>,
+}
+
+impl ConsensusReport {
+    /// Return true if every sector of the consensus image has good data.
+    pub fn is_complete(&self) -> bool {
+        self.unresolved().next().is_none()
+    }
+
+    /// Return an iterator over the sectors for which no good data could be found.
+    pub fn unresolved(&self) -> impl Iterator<Item = &ConsensusSector> {
+        self.sectors
+            .iter()
+            .filter(|s| matches!(s.source, ConsensusSource::Unresolved { .. }))
+    }
+
+    /// Return the number of sectors whose data was taken from the image at `index`.
+    pub fn from_image(&self, index: usize) -> usize {
+        self.sectors
+            .iter()
+            .filter(|s| matches!(s.source, ConsensusSource::Image { index: i, .. } if i == index))
+            .count()
+    }
+}
+
+/// A copy of a sector as read from one of the source images.
+struct SectorCopy {
+    index: usize,
+    data: Vec<u8>,
+    /// The recorded data CRC, for copies read from BitStream tracks.
+    crc: Option<[u8; 2]>,
+    good: bool,
+    deleted: bool,
+}
+
+/// Build a consensus image from `images`. See [`DiskImage::consensus`].
+pub(crate) fn build(images: &[DiskImage]) -> Result<(DiskImage, ConsensusReport), DiskImageError> {
+    let template = images.first().ok_or(DiskImageError::ParameterError)?;
+    if images.iter().any(|image| image.resolution != template.resolution) {
+        return Err(DiskImageError::IncompatibleImage);
+    }
+
+    let mut result = DiskImage {
+        standard_format: template.standard_format,
+        resolution: template.resolution,
+        descriptor: template.descriptor,
+        consistency: template.consistency.clone(),
+        volume_name: template.volume_name.clone(),
+        comment: template.comment.clone(),
+        track_pool: template.track_pool.clone(),
+        track_map: template.track_map.clone(),
+        sub_track_map: template.sub_track_map.clone(),
+        match_policy: template.match_policy,
+        crc_params: template.crc_params,
+        ..Default::default()
+    };
+
+    let mut report = ConsensusReport::default();
+    for (h, cylinders) in template.track_map.iter().enumerate() {
+        for (c, ti) in cylinders.iter().enumerate() {
+            let ch = DiskCh::new(c as u16, h as u8);
+            // Sector reads seek the track, so read from copies of each source track.
+            let mut sources: Vec<Option<TrackData>> =
+                images.iter().map(|image| image.get_track_ch(ch).cloned()).collect();
+
+            let mut seen = HashSet::new();
+            for sector in TrackMapEntry::from(&template.track_pool[*ti]).sectors {
+                // Only the first of several sectors with the same ID can be read.
+                if !seen.insert(sector.chsn) {
+                    continue;
+                }
+                let source = vote_sector(&mut result.track_pool[*ti], &mut sources, sector.chsn)?;
+                report.sectors.push(ConsensusSector {
+                    ch,
+                    chsn: sector.chsn,
+                    source,
+                });
+            }
+        }
+    }
+
+    if let Ok(buf) = result.read_boot_sector() {
+        _ = result.parse_boot_sector(&buf);
+    }
+    Ok((result, report))
+}
+
+/// Read the sector `chsn` from each of `sources`, choose its data, and write it to `track`.
+fn vote_sector(
+    track: &mut TrackData,
+    sources: &mut [Option<TrackData>],
+    chsn: DiskChsn,
+) -> Result<ConsensusSource, DiskImageError> {
+    let chs = DiskChs::from(chsn);
+    let mut copies = Vec::new();
+    for (index, source) in sources.iter_mut().enumerate() {
+        let Some(source) = source else { continue };
+        // Read the address mark and CRC along with the data where the track records them.
+        let scope = match source {
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => RwSectorScope::DataBlock,
+            TrackData::ByteStream { .. } => RwSectorScope::DataOnly,
+        };
+        let Ok(rsr) = source.read_sector(chs, Some(chsn.n()), scope, MatchPolicy::Chsn, false) else {
+            continue;
+        };
+        if rsr.address_crc_error || rsr.no_dam || rsr.data_len == 0 {
+            continue;
+        }
+        let data = rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len].to_vec();
+        let crc = rsr
+            .read_buf
+            .get(rsr.data_idx + rsr.data_len..rsr.data_idx + rsr.data_len + 2)
+            .map(|crc| [crc[0], crc[1]]);
+        copies.push(SectorCopy {
+            index,
+            data,
+            crc,
+            good: !rsr.data_crc_error,
+            deleted: rsr.deleted_mark,
+        });
+    }
+
+    // Prefer the good data read by the most images.
+    let mut winner: Option<(&SectorCopy, usize)> = None;
+    for copy in copies.iter().filter(|copy| copy.good) {
+        let votes = copies.iter().filter(|c| c.good && c.data == copy.data).count();
+        if winner.is_none_or(|(_, best)| votes > best) {
+            winner = Some((copy, votes));
+        }
+    }
+    if let Some((copy, votes)) = winner {
+        write_sector(track, chs, chsn, &copy.data, copy.deleted)?;
+        return Ok(ConsensusSource::Image {
+            index: copy.index,
+            votes,
+        });
+    }
+
+    // Vote on each bit of the bad copies, and of their CRCs. Ties go to the earliest copy.
+    let Some(first) = copies.first() else {
+        return Ok(ConsensusSource::Unresolved { copies: 0 });
+    };
+    let voters: Vec<&SectorCopy> = copies
+        .iter()
+        .filter(|c| c.crc.is_some() && c.data.len() == first.data.len())
+        .collect();
+    if let (TrackData::BitStream { crc: crc_params, .. } | TrackData::FluxStream { crc: crc_params, .. }, true) =
+        (&*track, voters.len() > 1)
+    {
+        let vote = |bytes: &dyn Fn(&SectorCopy) -> &[u8]| {
+            let first = bytes(voters[0]);
+            let mut voted = vec![0u8; first.len()];
+            for (i, byte) in voted.iter_mut().enumerate() {
+                for bit in 0..8 {
+                    let mask = 0x80 >> bit;
+                    let ones = voters.iter().filter(|c| bytes(c)[i] & mask != 0).count();
+                    if ones * 2 > voters.len() || (ones * 2 == voters.len() && first[i] & mask != 0) {
+                        *byte |= mask;
+                    }
+                }
+            }
+            voted
+        };
+        let data = vote(&|c| &c.data);
+        let crc = vote(&|c| c.crc.as_ref().map_or(&[], |crc| &crc[..]));
+
+        let mark = match voters[0].deleted {
+            true => DDAM_MARKER_BYTES,
+            false => DAM_MARKER_BYTES,
+        };
+        if crc_params.crc(&mark, &data) == u16::from_be_bytes([crc[0], crc[1]]) {
+            write_sector(track, chs, chsn, &data, voters[0].deleted)?;
+            return Ok(ConsensusSource::Voted { copies: voters.len() });
+        }
+    }
+
+    Ok(ConsensusSource::Unresolved { copies: copies.len() })
+}
+
+/// Write the chosen data of a sector. On ByteStream tracks, the sector's CRC status is recorded
+/// separately from its data, so it is cleared.
+fn write_sector(
+    track: &mut TrackData,
+    chs: DiskChs,
+    chsn: DiskChsn,
+    data: &[u8],
+    deleted: bool,
+) -> Result<(), DiskImageError> {
+    track.write_sector(
+        chs,
+        Some(chsn.n()),
+        data,
+        RwSectorScope::DataOnly,
+        MatchPolicy::Chsn,
+        deleted,
+        false,
+        None,
+    )?;
+    if let TrackData::ByteStream { sectors, .. } = track {
+        if let Some(si) = sectors.iter_mut().find(|si| si.chsn() == chsn) {
+            si.data_crc_error = false;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/convert.rs
+
+    Policy and reporting types used when exporting a disk image to a format
+    that cannot represent all of its features, such as a raw sector image.
+*/
+
+use crate::DiskChsn;
+use std::fmt::{self, Display, Formatter};
+
+/// The action to take when a feature of a disk image cannot be represented in the target format.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConvertAction {
+    /// Abort the conversion with an error.
+    Fail,
+    /// Write the sector as zeros.
+    ZeroFill,
+    /// Write whatever data can be read from the sector, padded or truncated as needed.
+    #[default]
+    BestEffort,
+}
+
+/// How unformatted tracks at the end of a disk image are handled on export.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TrailingTracks {
+    /// Trim unformatted trailing cylinders for fixed-size sector formats, where they would be
+    /// written as missing sectors, and keep them for all other formats.
+    #[default]
+    Auto,
+    /// Write every cylinder of the image.
+    Keep,
+    /// Drop cylinders after the last cylinder with any sectors.
+    Trim,
+    /// Append unformatted tracks up to the nominal cylinder count of the disk, for formats such
+    /// as HFE that expect every track of the drive to be present.
+    Synthesize,
+}
+
+/// A [`ConvertPolicy`] controls how features that cannot be represented in a target format are
+/// handled when exporting a disk image.
+///
+/// The default policy is best-effort for every feature, which matches the behavior of a plain
+/// `save_image()` call.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConvertPolicy {
+    /// Sectors containing weak bits.
+    pub weak_bits: ConvertAction,
+    /// Sectors with a bad address or data CRC.
+    pub bad_crc: ConvertAction,
+    /// Sectors with a deleted data address mark.
+    pub deleted: ConvertAction,
+    /// Sectors with a size other than the target format's sector size.
+    pub nonstandard_size: ConvertAction,
+    /// Sectors expected by the target geometry but not found on the track.
+    pub missing: ConvertAction,
+    /// Drop formatted cylinders beyond the nominal cylinder count of the disk, such as tracks 80
+    /// to 83 of an overdumped 80-track disk. Overdumped cylinders without any sectors are always
+    /// dropped.
+    pub trim_overdump: bool,
+    /// Unformatted cylinders at the end of the image.
+    pub trailing_tracks: TrailingTracks,
+}
+
+impl ConvertPolicy {
+    /// Return a policy that fails on any feature that cannot be represented in the target format.
+    pub fn strict() -> Self {
+        Self {
+            weak_bits: ConvertAction::Fail,
+            bad_crc: ConvertAction::Fail,
+            deleted: ConvertAction::Fail,
+            nonstandard_size: ConvertAction::Fail,
+            missing: ConvertAction::Fail,
+            trim_overdump: false,
+            trailing_tracks: TrailingTracks::Auto,
+        }
+    }
+
+    /// Return true if `action` is taken for any feature.
+    pub(crate) fn has_action(&self, action: ConvertAction) -> bool {
+        [
+            self.weak_bits,
+            self.bad_crc,
+            self.deleted,
+            self.nonstandard_size,
+            self.missing,
+        ]
+        .contains(&action)
+    }
+}
+
+/// The kind of feature that could not be represented in the target format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConvertIssueKind {
+    WeakBits,
+    BadAddressCrc,
+    BadDataCrc,
+    Deleted,
+    NonstandardSize,
+    Missing,
+}
+
+impl Display for ConvertIssueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertIssueKind::WeakBits => write!(f, "weak bits"),
+            ConvertIssueKind::BadAddressCrc => write!(f, "bad address CRC"),
+            ConvertIssueKind::BadDataCrc => write!(f, "bad data CRC"),
+            ConvertIssueKind::Deleted => write!(f, "deleted data"),
+            ConvertIssueKind::NonstandardSize => write!(f, "nonstandard size"),
+            ConvertIssueKind::Missing => write!(f, "missing sector"),
+        }
+    }
+}
+
+/// A single feature of a sector that could not be represented in the target format, and the
+/// action taken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConvertIssue {
+    /// The ID of the sector. For missing sectors, N is the size expected by the target format.
+    pub chsn: DiskChsn,
+    pub kind: ConvertIssueKind,
+    pub action: ConvertAction,
+}
+
+impl Display for ConvertIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {:?}", self.chsn, self.kind, self.action)
+    }
+}
+
+/// A report of the features dropped or altered during an export.
+#[derive(Clone, Debug, Default)]
+pub struct ConvertReport {
+    pub issues: Vec<ConvertIssue>,
+}
+
+impl ConvertReport {
+    /// Return true if the export was lossless.
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Return an iterator over the issues of the specified kind.
+    pub fn issues_of(&self, kind: ConvertIssueKind) -> impl Iterator<Item = &ConvertIssue> {
+        self.issues.iter().filter(move |i| i.kind == kind)
+    }
+}
@@ -27,13 +27,26 @@
 use crate::chs::DiskChs;
 use crate::containers::zip::{detect_zip, extract_first_file};
 use crate::containers::DiskImageContainer;
-use crate::file_parsers::{ImageParser, IMAGE_FORMATS};
+use crate::file_parsers::ImageParser;
 use crate::io::ReadSeek;
+use crate::registry;
 use crate::standard_format::StandardFormat;
-use crate::DiskImageError;
+use crate::{DiskImageError, DiskImageFormat};
+use std::path::Path;
 
 /// Attempt to detect the format of a disk image. If the format cannot be determined, UnknownFormat is returned.
 pub fn detect_image_format<T: ReadSeek>(image_io: &mut T) -> Result<DiskImageContainer, DiskImageError> {
+    detect_image_format_with_hint(image_io, None)
+}
+
+/// Attempt to detect the format of a disk image, checking the `hint` format first. As detection by
+/// file size is ambiguous, a hint such as a file extension allows an image that several detectors
+/// accept to be identified as the expected format. If the image is not of the hinted format, all
+/// formats are tried in the order given by [`registry::detection_order`].
+pub(crate) fn detect_image_format_with_hint<T: ReadSeek>(
+    image_io: &mut T,
+    hint: Option<DiskImageFormat>,
+) -> Result<DiskImageContainer, DiskImageError> {
     // If the zip feature is present, we can look into and identify images in zip files.
     // Most common of these are WinImage's "Compressed Disk Image" format, IMZ, which is simply
     // a zip file containing a single raw disk image with .IMA extension.
@@ -63,9 +76,9 @@ pub fn detect_image_format<T: ReadSeek>(image_io: &mut T) -> Result<DiskImageCon
 
             // Wrap buffer in Cursor, and send it through all the format detectors.
             let mut file_io = std::io::Cursor::new(file_buf);
-            for format in IMAGE_FORMATS.iter() {
+            for format in hint.into_iter().chain(registry::detection_order()) {
                 if format.detect(&mut file_io) {
-                    return Ok(DiskImageContainer::Zip(*format));
+                    return Ok(DiskImageContainer::Zip(format));
                 }
             }
 
@@ -73,20 +86,32 @@ pub fn detect_image_format<T: ReadSeek>(image_io: &mut T) -> Result<DiskImageCon
         }
     }
 
-    for format in IMAGE_FORMATS.iter() {
+    for format in hint.into_iter().chain(registry::detection_order()) {
         if format.detect(&mut *image_io) {
-            return Ok(DiskImageContainer::Raw(*format));
+            return Ok(DiskImageContainer::Raw(format));
         }
     }
     Err(DiskImageError::UnknownFormat)
 }
 
+/// Attempt to detect the format of a disk image stored as a set of files in the directory `dir`,
+/// such as a KryoFlux stream set of one `trackNN.H.raw` file per track. Returns None if the
+/// directory does not hold a recognized set.
+pub(crate) fn detect_image_set(dir: &Path) -> Option<DiskImageFormat> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let stream_rex = regex::Regex::new(r"(?i)^track\d{2}\.[01]\.raw$").unwrap();
+
+    entries
+        .flatten()
+        .any(|entry| stream_rex.is_match(&entry.file_name().to_string_lossy()))
+        .then_some(DiskImageFormat::KryofluxStream)
+}
+
 /// Attempt to return a DiskChs structure representing the geometry of a disk image from the size of a raw sector image.
-/// Returns None if the size does not match a known raw disk image size.
+/// Returns None if the size does not match a known raw disk image size. Images of a standard format with a few
+/// overdumped cylinders are recognized, with the cylinder count of the image.
 pub fn chs_from_raw_size(size: usize) -> Option<DiskChs> {
-    let raw_size_fmt = StandardFormat::from(size);
-    if raw_size_fmt != StandardFormat::Invalid {
-        return Some(raw_size_fmt.get_chs());
-    }
-    None
+    let (format, c) = StandardFormat::from_raw_size(size)?;
+    let chs = format.get_chs();
+    Some(DiskChs::new(c, chs.h(), chs.s()))
 }
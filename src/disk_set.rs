@@ -0,0 +1,284 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/disk_set.rs
+
+    A manifest describing the disks of a multi-disk software title, stored as
+    a small JSON file next to the disk images.
+
+    Disks are numbered from 1 in the order they appear in the manifest. Image
+    paths are relative to the directory holding the manifest, so a set can be
+    moved as a whole. Emulator front-ends can use the manifest to offer
+    "insert disk 2" prompts, and check that a set is complete before use.
+*/
+
+use crate::diskimage::LoadOptions;
+use crate::{DiskCh, DiskDensity, DiskImage, DiskImageError};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::mem::Discriminant;
+use std::path::{Path, PathBuf};
+
+/// A single disk of a [`DiskSetManifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSetEntry {
+    /// The path of the disk image, relative to the directory holding the manifest.
+    pub path: PathBuf,
+    /// The label of the disk, as printed on the original media.
+    pub label: String,
+    /// The SHA1 hash of the image file, as a lowercase hex string. If set, the image file is
+    /// checked against it when the set is validated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+}
+
+/// The disks of a multi-disk title, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSetManifest {
+    /// The name of the title.
+    pub title: String,
+    /// The disks of the set. Disk 1 is the first entry.
+    pub disks: Vec<DiskSetEntry>,
+    /// The directory image paths are resolved against.
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+/// A problem found with a disk of a set by [`DiskSetManifest::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiskSetIssue {
+    /// The image file does not exist.
+    Missing,
+    /// The image file exists, but could not be loaded.
+    Unreadable(String),
+    /// The image file does not match the SHA1 hash recorded in the manifest.
+    ChecksumMismatch,
+    /// The geometry or density of the disk differs from that of disk 1.
+    Inconsistent,
+    /// The disk uses the same image file as an earlier disk of the set.
+    Duplicate { of: usize },
+}
+
+impl Display for DiskSetIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskSetIssue::Missing => write!(f, "image file is missing"),
+            DiskSetIssue::Unreadable(e) => write!(f, "image could not be loaded: {}", e),
+            DiskSetIssue::ChecksumMismatch => write!(f, "image does not match its recorded SHA1 hash"),
+            DiskSetIssue::Inconsistent => write!(f, "geometry or density differs from disk 1"),
+            DiskSetIssue::Duplicate { of } => write!(f, "same image file as disk {}", of),
+        }
+    }
+}
+
+/// A report of the problems found with a disk set, built by [`DiskSetManifest::validate`].
+#[derive(Clone, Debug, Default)]
+pub struct DiskSetReport {
+    /// Each problem found, with the number of the disk it was found on.
+    pub issues: Vec<(usize, DiskSetIssue)>,
+}
+
+impl DiskSetReport {
+    /// Return true if no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Return the numbers of the disks whose image files are missing.
+    pub fn missing(&self) -> Vec<usize> {
+        self.issues
+            .iter()
+            .filter(|(_, issue)| *issue == DiskSetIssue::Missing)
+            .map(|(disk, _)| *disk)
+            .collect()
+    }
+}
+
+impl Display for DiskSetReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (disk, issue) in &self.issues {
+            writeln!(f, "Disk {}: {}", disk, issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl DiskSetManifest {
+    /// Create an empty manifest for the title `title`, with image paths resolved against the
+    /// current directory.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Append a disk to the set, returning its disk number.
+    pub fn add_disk(&mut self, path: impl Into<PathBuf>, label: impl Into<String>) -> usize {
+        self.disks.push(DiskSetEntry {
+            path: path.into(),
+            label: label.into(),
+            sha1: None,
+        });
+        self.disks.len()
+    }
+
+    /// Return the number of disks in the set.
+    pub fn disk_ct(&self) -> usize {
+        self.disks.len()
+    }
+
+    /// Return the entry for disk `number`, counting from 1.
+    pub fn disk(&self, number: usize) -> Option<&DiskSetEntry> {
+        number.checked_sub(1).and_then(|i| self.disks.get(i))
+    }
+
+    /// Return the directory image paths are resolved against.
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    /// Set the directory image paths are resolved against. This is set by
+    /// [`DiskSetManifest::load`] to the directory holding the manifest.
+    pub fn set_base_dir(&mut self, base_dir: impl Into<PathBuf>) {
+        self.base_dir = base_dir.into();
+    }
+
+    /// Return the full path of the image of disk `number`, counting from 1.
+    pub fn disk_path(&self, number: usize) -> Option<PathBuf> {
+        self.disk(number).map(|entry| self.base_dir.join(&entry.path))
+    }
+
+    /// Parse a manifest from its JSON representation.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::FormatParseError)` if `json` is not a valid manifest.
+    pub fn from_json(json: &str) -> Result<Self, DiskImageError> {
+        serde_json::from_str(json).map_err(|e| {
+            log::error!("DiskSetManifest::from_json(): {}", e);
+            DiskImageError::FormatParseError
+        })
+    }
+
+    /// Return the JSON representation of the manifest.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if an image path is not valid UTF-8.
+    pub fn to_json(&self) -> Result<String, DiskImageError> {
+        serde_json::to_string_pretty(self).map_err(|_| DiskImageError::ParameterError)
+    }
+
+    /// Load the manifest at `path`. Image paths are resolved against the directory holding it.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IoError)` if the file could not be read.
+    /// - `Err(DiskImageError::FormatParseError)` if the file is not a valid manifest.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DiskImageError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|_| DiskImageError::IoError)?;
+        let mut manifest = DiskSetManifest::from_json(&json)?;
+        manifest.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(manifest)
+    }
+
+    /// Save the manifest to `path`. Image paths are written as given, so the manifest should be
+    /// saved in the directory its paths are relative to.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if an image path is not valid UTF-8.
+    /// - `Err(DiskImageError::IoError)` if the file could not be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DiskImageError> {
+        std::fs::write(path, self.to_json()? + "\n").map_err(|_| DiskImageError::IoError)
+    }
+
+    /// Load the image of disk `number`, counting from 1, with the specified [`LoadOptions`].
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if the set has no disk `number`.
+    /// - Any error returned by [`DiskImage::load_from_path`].
+    pub fn load_disk(&self, number: usize, options: LoadOptions) -> Result<DiskImage, DiskImageError> {
+        let path = self.disk_path(number).ok_or(DiskImageError::ParameterError)?;
+        DiskImage::load_from_path(path, options)
+    }
+
+    /// Record the SHA1 hash of each disk's image file in the manifest, so that later validation
+    /// can detect a replaced or modified image.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IoError)` if an image file could not be read.
+    pub fn record_hashes(&mut self) -> Result<(), DiskImageError> {
+        for i in 0..self.disks.len() {
+            let bytes = std::fs::read(self.base_dir.join(&self.disks[i].path)).map_err(|_| DiskImageError::IoError)?;
+            self.disks[i].sha1 = Some(sha1_smol::Sha1::from(&bytes).digest().to_string());
+        }
+        Ok(())
+    }
+
+    /// Check that the image of every disk of the set is present, loads, matches its recorded
+    /// hash, and has the same geometry and density as disk 1. Each disk is loaded with default
+    /// [`LoadOptions`].
+    pub fn validate(&self) -> DiskSetReport {
+        let mut report = DiskSetReport::default();
+        let mut first_media: Option<(DiskCh, Discriminant<DiskDensity>)> = None;
+
+        for (i, entry) in self.disks.iter().enumerate() {
+            let number = i + 1;
+            if let Some(of) = self.disks[..i].iter().position(|other| other.path == entry.path) {
+                report.issues.push((number, DiskSetIssue::Duplicate { of: of + 1 }));
+            }
+
+            let path = self.base_dir.join(&entry.path);
+            let bytes = match std::fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    report.issues.push((number, DiskSetIssue::Missing));
+                    continue;
+                }
+            };
+            if let Some(sha1) = &entry.sha1 {
+                if !sha1.eq_ignore_ascii_case(&sha1_smol::Sha1::from(&bytes).digest().to_string()) {
+                    report.issues.push((number, DiskSetIssue::ChecksumMismatch));
+                }
+            }
+
+            let image = match DiskImage::load_from_path(&path, LoadOptions::default()) {
+                Ok(image) => image,
+                Err(e) => {
+                    report.issues.push((number, DiskSetIssue::Unreadable(e.to_string())));
+                    continue;
+                }
+            };
+            let descriptor = image.image_format();
+            let media = (descriptor.geometry, std::mem::discriminant(&descriptor.density));
+            match first_media {
+                None if number == 1 => first_media = Some(media),
+                Some(first) if first != media => report.issues.push((number, DiskSetIssue::Inconsistent)),
+                _ => {}
+            }
+        }
+        report
+    }
+}
@@ -24,26 +24,55 @@
 
     --------------------------------------------------------------------------
 */
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
-use std::io::Cursor;
-
-use crate::bitstream::mfm::MfmCodec;
+use std::io::{Cursor, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::bitstream::fm::FmCodec;
+use crate::bitstream::gcr::GcrCodec;
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType};
+use crate::bitstream::pll::Pll;
 use crate::bitstream::raw::RawCodec;
 use crate::bitstream::TrackDataStream;
 use crate::boot_sector::BootSector;
-use crate::chs::{DiskCh, DiskChs, DiskChsn};
+use crate::canonical::{self, WeakBitPolicy};
+use crate::chs::{DiskCh, DiskChs, DiskChsn, QuarterTrack};
+use crate::consensus::{self, ConsensusReport};
 use crate::containers::zip::extract_first_file;
 use crate::containers::DiskImageContainer;
-use crate::detect::detect_image_format;
-use crate::file_parsers::{FormatCaps, ImageParser};
-use crate::io::ReadSeek;
+use crate::convert::{ConvertAction, ConvertPolicy, ConvertReport, TrailingTracks};
+use crate::detect::{detect_image_format, detect_image_format_with_hint, detect_image_set};
+use crate::duplicator::{self, DuplicatorReport};
+use crate::file_parsers::raw::RawFormat;
+use crate::file_parsers::{format_from_ext, FormatCaps, ImageParser, ParserWriteCompatibility};
+use crate::flux::FluxRevolution;
+use crate::handle::{SectorId, TrackId};
+use crate::health::HealthReport;
+use crate::image_builder::ImageBuilder;
+use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::linear::LinearView;
+use crate::media::MediaProfile;
+use crate::packed::{self, PackedRegion};
+use crate::platform::{self, PlatformReport};
+use crate::progress::Progress;
+use crate::random::RandomSource;
+use crate::registry;
 use crate::standard_format::StandardFormat;
-use crate::structure_parsers::system34::{System34Element, System34Parser, System34Standard};
+use crate::structure_parsers::amiga::AmigaParser;
+use crate::structure_parsers::system34::{System34CrcParams, System34Element, System34Parser, System34Standard};
 use crate::structure_parsers::{DiskStructureElement, DiskStructureMetadata, DiskStructureParser};
+use crate::timeline::TrackTimeline;
+use crate::track_builder::TrackBuilder;
 use crate::trackdata::TrackData;
+use crate::verify::{self, VerifyReport};
+use crate::virus::{self, VirusMatch};
+use crate::waveform::{self, WaveformOptions};
 use crate::{
-    util, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskDensity, DiskImageError, DiskRpm,
-    FoxHashMap, DEFAULT_SECTOR_SIZE,
+    util, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskDensity, DiskImageError, DiskRpm, FoxHashMap,
+    DEFAULT_SECTOR_SIZE,
 };
 use bit_vec::BitVec;
 use bitflags::bitflags;
@@ -51,6 +80,14 @@ use sha1_smol::Digest;
 
 pub const DEFAULT_BOOT_SECTOR: &[u8] = include_bytes!("../resources/bootsector.bin");
 
+/// The percentage by which a track may exceed its nominal length before it is considered too long
+/// for a target medium.
+pub const TRACK_LENGTH_TOLERANCE_PCT: usize = 5;
+
+/// The percentage by which a track's data rate may differ from the data rate of a target medium,
+/// such as for a rate measured from flux, before the track is considered incompatible.
+pub const DATA_RATE_TOLERANCE_PCT: u32 = 5;
+
 bitflags! {
     /// Bit flags that can be applied to a disk image.
     #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -78,9 +115,47 @@ pub enum DiskImageFormat {
     HfeImage,
     F86Image, // 86F
     TransCopyImage,
+    SuperCardPro,
+    AmigaDiskFile,
+    WozImage,
+    PastiImage,
+    CopyQm,
+    DmkImage,
+    CpcDskImage,
+    A2rImage,
+    /// A format registered at runtime with [`crate::registry::register_format`].
+    Custom(u16),
 }
 
 impl DiskImageFormat {
+    /// Determine the format of the disk image at `path`. Image content takes precedence, but the
+    /// file extension is used as a hint where the content alone is ambiguous, such as a raw sector
+    /// image of the same size as a different format. For images in a zip container, the format of
+    /// the contained image is returned.
+    ///
+    /// If `path` is a directory, it is checked for a multi-file image set such as a KryoFlux
+    /// stream set.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IoError)` if the path could not be read.
+    /// - `Err(DiskImageError::UnknownFormat)` if the format could not be determined.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<DiskImageFormat, DiskImageError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return detect_image_set(path).ok_or(DiskImageError::UnknownFormat);
+        }
+
+        let mut file = std::io::BufReader::new(std::fs::File::open(path).map_err(|_| DiskImageError::IoError)?);
+        match detect_image_format_with_hint(&mut file, DiskImageFormat::hint_from_path(path))? {
+            DiskImageContainer::Raw(format) | DiskImageContainer::Zip(format) => Ok(format),
+        }
+    }
+
+    /// Return the format suggested by the extension of `path`, if any.
+    fn hint_from_path(path: &Path) -> Option<DiskImageFormat> {
+        path.extension().and_then(|ext| format_from_ext(&ext.to_string_lossy()))
+    }
+
     pub fn resolution(self) -> DiskDataResolution {
         match self {
             DiskImageFormat::RawSectorImage => DiskDataResolution::ByteStream,
@@ -93,6 +168,17 @@ impl DiskImageFormat {
             DiskImageFormat::HfeImage => DiskDataResolution::BitStream,
             DiskImageFormat::F86Image => DiskDataResolution::BitStream,
             DiskImageFormat::TransCopyImage => DiskDataResolution::BitStream,
+            DiskImageFormat::SuperCardPro => DiskDataResolution::FluxStream,
+            DiskImageFormat::AmigaDiskFile => DiskDataResolution::BitStream,
+            DiskImageFormat::WozImage => DiskDataResolution::BitStream,
+            DiskImageFormat::PastiImage => DiskDataResolution::ByteStream,
+            DiskImageFormat::CopyQm => DiskDataResolution::ByteStream,
+            DiskImageFormat::DmkImage => DiskDataResolution::BitStream,
+            DiskImageFormat::CpcDskImage => DiskDataResolution::ByteStream,
+            DiskImageFormat::A2rImage => DiskDataResolution::FluxStream,
+            DiskImageFormat::Custom(id) => registry::custom_format(id)
+                .map(|f| f.resolution)
+                .unwrap_or(DiskDataResolution::ByteStream),
         }
     }
 }
@@ -110,6 +196,17 @@ impl Display for DiskImageFormat {
             DiskImageFormat::HfeImage => "HFEv1 Bitstream Image".to_string(),
             DiskImageFormat::F86Image => "86F Bitstream Image".to_string(),
             DiskImageFormat::TransCopyImage => "TransCopy Bitstream Image".to_string(),
+            DiskImageFormat::SuperCardPro => "SuperCard Pro Flux Image".to_string(),
+            DiskImageFormat::AmigaDiskFile => "Amiga Disk File".to_string(),
+            DiskImageFormat::WozImage => "WOZ Bitstream Image".to_string(),
+            DiskImageFormat::PastiImage => "Pasti STX Image".to_string(),
+            DiskImageFormat::CopyQm => "CopyQM Image".to_string(),
+            DiskImageFormat::DmkImage => "DMK Image".to_string(),
+            DiskImageFormat::CpcDskImage => "CPCEMU DSK Image".to_string(),
+            DiskImageFormat::A2rImage => "A2R Flux Image".to_string(),
+            DiskImageFormat::Custom(id) => registry::custom_format(*id)
+                .map(|f| f.name)
+                .unwrap_or_else(|| format!("Unregistered Format {}", id)),
         };
         write!(f, "{}", str)
     }
@@ -133,6 +230,15 @@ pub(crate) struct SectorDescriptor {
     pub address_crc_error: bool,
     pub data_crc_error: bool,
     pub deleted_mark: bool,
+    /// The sector has an ID field but no data field.
+    pub no_dam: bool,
+    /// The offset of the sector from the index, in bits, if recorded by the source image.
+    pub position: Option<u32>,
+    /// The time taken to read the sector, in bit clocks, if recorded by the source image.
+    pub read_time: Option<u32>,
+    /// The relative read time of each 16-byte block of the sector data, for sectors recorded with
+    /// variable bit timing.
+    pub timing: Option<Vec<u16>>,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -141,10 +247,182 @@ pub struct SectorMapEntry {
     pub address_crc_valid: bool,
     pub data_crc_valid: bool,
     pub deleted_mark: bool,
+    /// The sector has an ID field but no data field.
+    pub no_dam: bool,
+    /// The offset of the sector from the index, in bits, if recorded by the source image.
+    pub position: Option<u32>,
+    /// The time taken to read the sector, in bit clocks, if recorded by the source image.
+    pub read_time: Option<u32>,
+}
+
+impl Display for SectorMapEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} address_crc_valid: {} data_crc_valid: {} deleted: {}",
+            self.chsn, self.address_crc_valid, self.data_crc_valid, self.deleted_mark
+        )
+    }
+}
+
+impl SectorMapEntry {
+    /// Return a compact representation of the sector consisting of its sector ID, followed by
+    /// 'd' if the sector has a deleted data mark, 'm' if the sector has no data field, and '!' if
+    /// the sector has a CRC error.
+    pub fn compact(&self) -> String {
+        let mut out = self.chsn.s().to_string();
+        if self.no_dam {
+            out.push('m');
+        }
+        if self.deleted_mark {
+            out.push('d');
+        }
+        if !self.address_crc_valid || !self.data_crc_valid {
+            out.push('!');
+        }
+        out
+    }
+}
+
+/// The legend for the symbols used by [`DiskImage::render_sector_map_text`].
+pub const SECTOR_MAP_LEGEND: &str = "+ good  D deleted  W weak bits  X bad CRC or no data  - missing";
+
+/// A [`TrackSource`] holds the undecoded payload of a track exactly as it was read from the
+/// source image file, for diagnostic comparison against other tools.
+#[derive(Clone, Debug)]
+pub struct TrackSource {
+    /// The byte offset of the payload within the source image file. For images loaded from an
+    /// archive, this is the offset within the extracted file.
+    pub offset: u64,
+    /// The raw track payload, before any decoding or bit reversal.
+    pub data: Vec<u8>,
+}
+
+/// Bytes of memory held by a disk image or one of its tracks, by kind of data. Only heap
+/// allocations for track data are counted. See [`DiskImage::memory_usage`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Encoded BitStream track data.
+    pub bitstream: usize,
+    /// Weak bit masks of BitStream and ByteStream tracks.
+    pub weak_mask: usize,
+    /// Clock maps of MFM BitStream tracks.
+    pub clock_map: usize,
+    /// Track structure metadata, sector ID lists and ByteStream sector indexes.
+    pub metadata: usize,
+    /// Decoded ByteStream track data.
+    pub bytestream: usize,
+    /// Undecoded track payloads retained from the source image file.
+    pub source: usize,
+    /// Flux transitions of FluxStream tracks.
+    pub flux: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.bitstream + self.weak_mask + self.clock_map + self.metadata + self.bytestream + self.source + self.flux
+    }
+}
+
+impl std::ops::AddAssign for MemoryUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.bitstream += rhs.bitstream;
+        self.weak_mask += rhs.weak_mask;
+        self.clock_map += rhs.clock_map;
+        self.metadata += rhs.metadata;
+        self.bytestream += rhs.bytestream;
+        self.source += rhs.source;
+        self.flux += rhs.flux;
+    }
+}
+
+impl Display for MemoryUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes (bitstream: {} weak mask: {} clock map: {} metadata: {} bytestream: {} source: {} flux: {})",
+            self.total(),
+            self.bitstream,
+            self.weak_mask,
+            self.clock_map,
+            self.metadata,
+            self.bytestream,
+            self.source,
+            self.flux
+        )
+    }
+}
+
+/// A [`MemoryReport`] itemizes the memory held by a disk image. See [`DiskImage::memory_usage`].
+#[derive(Clone, Debug, Default)]
+pub struct MemoryReport {
+    /// The memory used by each track in the image, including sub-tracks, in track pool order.
+    pub tracks: Vec<(DiskCh, MemoryUsage)>,
+    /// The bytes of the original image file retained for a [`BackupPolicy`].
+    pub original_bytes: usize,
+}
+
+impl MemoryReport {
+    /// Return the memory used by all tracks, by kind of data.
+    pub fn tracks_total(&self) -> MemoryUsage {
+        let mut total = MemoryUsage::default();
+        for (_, usage) in &self.tracks {
+            total += *usage;
+        }
+        total
+    }
+
+    /// Return the total bytes held by the image.
+    pub fn total(&self) -> usize {
+        self.tracks_total().total() + self.original_bytes
+    }
+}
+
+/// A [`TrackMapEntry`] describes a single track in a disk image, including its encoding, data rate
+/// and length, and a list of the sectors found on the track.
+#[derive(Clone, Debug)]
+pub struct TrackMapEntry {
+    pub ch: DiskCh,
+    pub encoding: DiskDataEncoding,
+    pub data_rate: DiskDataRate,
+    /// The data rate the track would be read at in a 300 RPM drive. See
+    /// [`TrackData::nominal_data_rate`].
+    pub nominal_data_rate: DiskDataRate,
+    /// The rotation rate of the drive the track was captured in, if known from its flux.
+    pub capture_rpm: Option<DiskRpm>,
+    /// The length of the track in bitcells. For ByteStream tracks this is an estimate.
+    pub bitcells: usize,
+    /// The length of the track in decoded bytes.
+    pub len_bytes: usize,
+    pub sectors: Vec<SectorMapEntry>,
+}
+
+impl From<&TrackData> for TrackMapEntry {
+    fn from(track: &TrackData) -> Self {
+        TrackMapEntry {
+            ch: track.ch(),
+            encoding: track.encoding(),
+            data_rate: track.data_rate(),
+            nominal_data_rate: track.nominal_data_rate(),
+            capture_rpm: track.capture_rpm(),
+            bitcells: track.bitcell_ct(),
+            len_bytes: track.byte_len(),
+            sectors: track.get_sector_list(),
+        }
+    }
+}
+
+impl Display for TrackMapEntry {
+    /// Format the track's sector list as a single line of sector IDs in physical order, using
+    /// the compact sector format, e.g. `1 2 3 4! 5d 6`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sectors: Vec<String> = self.sectors.iter().map(|s| s.compact()).collect();
+        write!(f, "{}", sectors.join(" "))
+    }
 }
 
 /// A DiskConsistency structure maintains information about the consistency of a disk image.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct DiskConsistency {
     // A field to hold image format capability flags that this image requires in order to be represented.
     pub image_caps: FormatCaps,
@@ -164,6 +442,7 @@ pub struct DiskConsistency {
     pub consistent_track_length: Option<u8>,
 }
 
+#[derive(Clone)]
 pub struct TrackSectorIndex {
     pub sector_id: u8,
     pub cylinder_id: u16,
@@ -174,6 +453,17 @@ pub struct TrackSectorIndex {
     pub address_crc_error: bool,
     pub data_crc_error: bool,
     pub deleted_mark: bool,
+    pub no_dam: bool,
+    pub position: Option<u32>,
+    pub read_time: Option<u32>,
+    pub timing: Option<Vec<u16>>,
+}
+
+impl TrackSectorIndex {
+    /// Return the sector ID of this sector as a [`DiskChsn`].
+    pub fn chsn(&self) -> DiskChsn {
+        DiskChsn::new(self.cylinder_id, self.head_id, self.sector_id, self.n)
+    }
 }
 
 #[derive(Copy, Clone, Default)]
@@ -200,17 +490,180 @@ pub enum RwSectorScope {
     DataOnly,
 }
 
+/// A [`BackupPolicy`] controls whether a copy of the original image file is kept before a loaded
+/// image is first modified, protecting archival masters from accidental writes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum BackupPolicy {
+    /// No backup is kept.
+    #[default]
+    None,
+    /// The original image file is kept in memory, and is available from [`DiskImage::backup`].
+    InMemory,
+    /// The original image file is written to the specified path when the image is first modified.
+    /// An existing file at the path is never overwritten; if the backup can't be written, the
+    /// modification is refused.
+    File(PathBuf),
+}
+
+/// The order in which the sides of a disk are stored in a raw sector image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RawSideOrder {
+    /// Tracks alternate between sides: c0h0, c0h1, c1h0, c1h1, ... This is the usual order for
+    /// PC disk images.
+    #[default]
+    Interleaved,
+    /// All tracks of head 0 are stored, followed by all tracks of head 1.
+    Sequential,
+}
+
+/// The order in which the cylinders of each side are stored in a raw sector image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RawTrackOrder {
+    /// Cylinders are stored from the outside in, starting at cylinder 0.
+    #[default]
+    OutToIn,
+    /// Cylinders are stored from the inside out, starting at the last cylinder.
+    InToOut,
+    /// Cylinders of head 0 are stored from the outside in, and cylinders of head 1 from the inside
+    /// out. Usually combined with [`RawSideOrder::Sequential`].
+    OutAndBack,
+}
+
+/// Options controlling how a disk image is loaded. See [`DiskImage::load_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    pub backup_policy: BackupPolicy,
+    /// The side order of a raw sector image. Ignored for other formats.
+    pub raw_side_order: RawSideOrder,
+    /// The track order of a raw sector image. Ignored for other formats.
+    pub raw_track_order: RawTrackOrder,
+    /// The CRC parameters to check BitStream tracks with. Ignored for ByteStream images.
+    pub crc_params: System34CrcParams,
+    /// How strictly the image file and its track structures are checked against their
+    /// specifications.
+    pub parse_mode: ParseMode,
+    /// The seed for random data returned by the image, as set by [`DiskImage::set_random_seed`].
+    /// Random data is seeded from entropy if None.
+    pub random_seed: Option<u64>,
+    /// Keep the undecoded payload of each track as read from the image file, available through
+    /// [`DiskImage::track_source`]. Only the 86F, MFM and TransCopy parsers retain payloads.
+    pub retain_track_sources: bool,
+}
+
+/// A [`ParseMode`] controls how file parsers and the track structure scanner respond to a
+/// violation of a format's specification, such as a chunk with a bad CRC or a sector ID field cut
+/// off by the end of a track.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Recover as much of the image as possible, recording each violation as a [`LoadWarning`].
+    /// Suited to archival, where a damaged image is better than none.
+    #[default]
+    Lenient,
+    /// Fail with [`DiskImageError::SpecViolation`] on the first violation. Suited to validation
+    /// tooling.
+    Strict,
+}
+
+/// A [`LoadWarning`] records an anomaly found while loading a disk image that did not prevent the
+/// image from loading, such as a truncated track or sectors with bad CRCs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadWarning {
+    /// The track the anomaly was found on, if it concerns a single track.
+    pub ch: Option<DiskCh>,
+    pub message: String,
+}
+
+impl Display for LoadWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ch {
+            Some(ch) => write!(f, "Track {}: {}", ch, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The result of [`DiskImage::load_with_warnings`]: the loaded image, and any anomalies found
+/// while loading it.
+pub struct LoadResult {
+    pub image: DiskImage,
+    pub warnings: Vec<LoadWarning>,
+}
+
+/// A [`MatchPolicy`] controls which fields of a sector ID are compared against the requested
+/// sector address when searching a track for a sector to read or write.
+///
+/// The NEC µPD765 compares all four ID fields (C, H, R and N) in a Read Data command, but some
+/// controllers compare fewer. Selecting a policy allows a particular controller to be emulated.
+/// The N field is only compared if a sector size is supplied with the request.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MatchPolicy {
+    /// Match as [`MatchPolicy::Chsn`] on BitStream and FluxStream tracks, and as
+    /// [`MatchPolicy::SectorOnly`] on ByteStream tracks. Sector images often record cylinder and
+    /// head IDs that differ from the physical track, so such sectors are found and reported with
+    /// `wrong_cylinder` or `wrong_head` set.
+    #[default]
+    Auto,
+    /// Match the sector ID (R) only.
+    SectorOnly,
+    /// Match the cylinder, head and sector IDs.
+    Chs,
+    /// Match the cylinder, head, sector and size IDs. This is the behavior of the µPD765.
+    Chsn,
+    /// Match the cylinder, sector and size IDs, ignoring the head ID.
+    IgnoreHead,
+}
+
+impl MatchPolicy {
+    /// Return true if the sector ID `id` satisfies a request for the sector at `chs` with optional
+    /// size `n` under this policy, on a track of the given `resolution`.
+    pub fn matches(&self, id: DiskChsn, chs: DiskChs, n: Option<u8>, resolution: DiskDataResolution) -> bool {
+        let n_match = match n {
+            Some(n) => id.n() == n,
+            None => true,
+        };
+        match self.resolve(resolution) {
+            MatchPolicy::SectorOnly => id.s() == chs.s(),
+            MatchPolicy::Chs => DiskChs::from(id) == chs,
+            MatchPolicy::Chsn => DiskChs::from(id) == chs && n_match,
+            MatchPolicy::IgnoreHead => id.c() == chs.c() && id.s() == chs.s() && n_match,
+            MatchPolicy::Auto => unreachable!("MatchPolicy::Auto is resolved"),
+        }
+    }
+
+    /// Return the policy applied to a track of the given resolution, resolving
+    /// [`MatchPolicy::Auto`].
+    pub fn resolve(self, resolution: DiskDataResolution) -> MatchPolicy {
+        match (self, resolution) {
+            (MatchPolicy::Auto, DiskDataResolution::ByteStream) => MatchPolicy::SectorOnly,
+            (MatchPolicy::Auto, _) => MatchPolicy::Chsn,
+            (policy, _) => policy,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ReadSectorResult {
     pub data_idx: usize,
     pub data_len: usize,
     pub read_buf: Vec<u8>,
     pub deleted_mark: bool,
+    /// Set if the type of data address mark found did not match the type requested, equivalent
+    /// to the Control Mark (CM) bit in ST2 of the µPD765.
+    pub control_mark: bool,
+    /// Set if the sector has an ID field but no data field, equivalent to the Missing Data Address
+    /// Mark (MD) bit in ST2 of the µPD765.
+    pub no_dam: bool,
+    /// The weak bit mask of the sector data, if any bits of the sector data are weak. A set bit
+    /// indicates the corresponding bit of the sector data is weak.
+    pub weak_mask: Option<Vec<u8>>,
     pub not_found: bool,
     pub address_crc_error: bool,
     pub data_crc_error: bool,
     pub wrong_cylinder: bool,
     pub wrong_head: bool,
+    /// The number of bytes at the end of `read_buf` read from beyond the end of the sector data:
+    /// the data CRC followed by the gap. Only nonzero for [`DiskImage::read_sector_overread`].
+    pub overread_len: usize,
 }
 
 #[derive(Clone)]
@@ -231,6 +684,256 @@ pub struct WriteSectorResult {
     pub wrong_head: bool,
 }
 
+/// The result of a Read Address operation.
+#[derive(Clone)]
+pub struct ReadAddressResult {
+    /// The sector ID read from the ID field.
+    pub chsn: DiskChsn,
+    /// The six bytes of the ID field as read from the track: C, H, R, N and the two CRC bytes.
+    pub id_field: [u8; 6],
+    pub address_crc_error: bool,
+    /// The bitcell index of the ID address mark.
+    pub bit_index: usize,
+}
+
+#[derive(Clone)]
+pub struct WriteTrackResult {
+    /// The number of bytes of the write buffer consumed before the index was reached.
+    pub bytes_written: usize,
+    /// Set if the write buffer ran out before the index, equivalent to the Lost Data bit of the
+    /// WD177x. The remainder of the track is written with zeros.
+    pub lost_data: bool,
+}
+
+/// A channel for writing bitcells to a track at its rotational position, returned by
+/// [`DiskImage::write_bits`]. This mirrors [`TrackData::bits_iter`] for emulators that clock
+/// bits to a drive's write head while the write gate is open.
+///
+/// Bitcells are recorded at consecutive positions from where the write started, wrapping around
+/// the index. The track is not re-scanned until the write ends, when the channel is finished or
+/// dropped, and the position where the write ended is then recorded as the track's splice.
+pub struct TrackBitWriter<'a> {
+    track: &'a mut TrackData,
+    start: usize,
+    bits: BitVec,
+    ended: bool,
+}
+
+impl TrackBitWriter<'_> {
+    /// Record `bit` at the next rotational position.
+    pub fn write_bit(&mut self, bit: bool) {
+        self.bits.push(bit);
+    }
+
+    /// Record each of `bits` at consecutive rotational positions.
+    pub fn write_bits(&mut self, bits: impl IntoIterator<Item = bool>) {
+        self.bits.extend(bits);
+    }
+
+    /// Return the rotational position, counted from the index, the next bitcell will be written at.
+    pub fn position(&self) -> usize {
+        (self.start + self.bits.len()) % self.track.bitcell_ct()
+    }
+
+    /// Return the number of bitcells written so far.
+    pub fn bits_written(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// End the write, splicing the written bitcells into the track and re-scanning it.
+    ///
+    /// # Returns
+    /// - The bitcell index of the splice, where the write ended.
+    pub fn finish(mut self) -> Result<usize, DiskImageError> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<usize, DiskImageError> {
+        self.ended = true;
+        let bits = std::mem::take(&mut self.bits);
+        self.track.splice_bits(self.start, &bits)
+    }
+}
+
+impl Drop for TrackBitWriter<'_> {
+    fn drop(&mut self) {
+        if !self.ended {
+            if let Err(e) = self.end() {
+                log::error!("TrackBitWriter::drop(): Failed to end write: {}", e);
+            }
+        }
+    }
+}
+
+/// The rotational timing of a sector read, as returned by [`DiskImage::sector_read_time`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectorReadTime {
+    /// The number of bitcells passing under the head before the sector's ID address mark arrives.
+    pub wait_bitcells: usize,
+    /// The number of bitcells from the ID address mark to the end of the data field CRC.
+    pub transfer_bitcells: usize,
+    /// The bitcell index of the head when the read completes.
+    pub end_position: usize,
+    /// The data rate the durations are calculated from.
+    pub data_rate: DiskDataRate,
+}
+
+impl SectorReadTime {
+    fn bitcells_to_duration(&self, bitcells: usize) -> Duration {
+        // There are two bitcells per data bit.
+        let cell_rate = u32::from(self.data_rate) as u64 * 2;
+        Duration::from_nanos(bitcells as u64 * 1_000_000_000 / cell_rate)
+    }
+
+    /// Return the rotational delay until the sector's ID address mark reaches the head.
+    pub fn wait_time(&self) -> Duration {
+        self.bitcells_to_duration(self.wait_bitcells)
+    }
+
+    /// Return the time taken to read the sector from its ID address mark to its data CRC.
+    pub fn transfer_time(&self) -> Duration {
+        self.bitcells_to_duration(self.transfer_bitcells)
+    }
+
+    /// Return the time from the start of the operation until the read completes.
+    pub fn total_time(&self) -> Duration {
+        self.bitcells_to_duration(self.wait_bitcells + self.transfer_bitcells)
+    }
+}
+
+/// The measured gap and sync field lengths around a single sector, in bytes.
+#[derive(Clone, Debug)]
+pub struct SectorGaps {
+    /// The ID of the sector.
+    pub chsn: DiskChsn,
+    /// The length of the sync field preceding the IDAM.
+    pub id_sync: usize,
+    /// The length of GAP2, between the ID CRC and the data sync field. `None` if the sector has
+    /// no data field.
+    pub gap2: Option<usize>,
+    /// The length of the sync field preceding the DAM.
+    pub data_sync: Option<usize>,
+    /// The length of GAP3, between the data CRC and the sync field of the next sector. `None` for
+    /// the last sector on the track, and for sectors with no data field.
+    pub gap3: Option<usize>,
+}
+
+/// The measured gap lengths of a track, in bytes, as returned by [`DiskImage::track_gaps`].
+#[derive(Clone, Debug)]
+pub struct TrackGaps {
+    pub ch: DiskCh,
+    /// The length of the region from the index to the sync field of the first sector. On tracks
+    /// with an IAM this spans GAP4A, the IAM and GAP1.
+    pub gap1: usize,
+    /// The length of GAP4B, from the end of the last sector to the index.
+    pub gap4b: usize,
+    pub sectors: Vec<SectorGaps>,
+}
+
+impl TrackGaps {
+    /// Return the most common GAP3 length on the track, preferring the smaller length in a tie.
+    /// This is usually the GAP3 the track was formatted with.
+    pub fn common_gap3(&self) -> Option<usize> {
+        let mut counts = BTreeMap::new();
+        for gap3 in self.sectors.iter().filter_map(|s| s.gap3) {
+            *counts.entry(gap3).or_insert(0usize) += 1;
+        }
+        counts
+            .into_iter()
+            .max_by(|(g1, c1), (g2, c2)| c1.cmp(c2).then(g2.cmp(g1)))
+            .map(|(gap3, _)| gap3)
+    }
+}
+
+/// The observed layout of a track, as returned by [`DiskImage::track_layouts`]. The interleave and
+/// skew a disk was formatted with are often characteristic of the formatter or duplicator that
+/// produced it.
+#[derive(Clone, Debug)]
+pub struct TrackLayout {
+    pub ch: DiskCh,
+    /// The sector IDs of the track, in physical order from the index.
+    pub sector_order: Vec<u8>,
+    /// The number of physical sector positions from each logical sector to the next. For
+    /// example, 1 for sectors in sequential order, or 2 for every other sector. If the spacing
+    /// varies, the most common spacing is given. `None` if the track has no consecutive IDs.
+    pub interleave: Option<usize>,
+    /// The number of sector positions the lowest sector ID is rotated relative to head 0 of the
+    /// same cylinder. `None` for head 0, or if the tracks hold a different number of sectors.
+    pub head_skew: Option<usize>,
+    /// The number of sector positions the lowest sector ID is rotated relative to the same head
+    /// of the previous cylinder. `None` for cylinder 0, or if the tracks hold a different number
+    /// of sectors.
+    pub cylinder_skew: Option<usize>,
+    /// The measured gap lengths of the track, for MFM BitStream tracks.
+    pub gaps: Option<TrackGaps>,
+}
+
+impl Display for TrackLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let opt = |v: Option<usize>| v.map_or("-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "{} interleave: {} head skew: {} cylinder skew: {} gap3: {} order:",
+            self.ch,
+            opt(self.interleave),
+            opt(self.head_skew),
+            opt(self.cylinder_skew),
+            opt(self.gaps.as_ref().and_then(|g| g.common_gap3())),
+        )?;
+        for id in &self.sector_order {
+            write!(f, " {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// The field of a sector protected by a CRC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CrcField {
+    /// The sector ID field, following the IDAM.
+    Address,
+    /// The sector data field, following the DAM or DDAM.
+    Data,
+}
+
+/// A CRC rewritten by [`DiskImage::fix_sector_crcs`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CrcFix {
+    /// The ID of the sector, as read from its ID field.
+    pub chsn: DiskChsn,
+    pub field: CrcField,
+    /// The CRC that was recorded on the track.
+    pub recorded: u16,
+    /// The CRC calculated from the field, which now replaces the recorded CRC.
+    pub calculated: u16,
+}
+
+/// The index address mark (IAM) of a track and the gaps around it, in bytes, as returned by
+/// [`DiskImage::index_mark`]. The IAM is optional; ISO formats omit it, and some protection
+/// schemes check for its presence or position.
+#[derive(Clone, Debug)]
+pub struct IndexMark {
+    pub ch: DiskCh,
+    /// The bitcell offset of the IAM from the index, or `None` if the track has no IAM.
+    pub bit_index: Option<usize>,
+    /// The length of GAP4A, the post-index gap from the index to the sync field preceding the IAM.
+    pub gap4a: Option<usize>,
+    /// The length of the sync field preceding the IAM.
+    pub sync: Option<usize>,
+    /// The length of GAP1, from the end of the IAM to the sync field of the first sector. `None`
+    /// if the track has no IAM or no sectors.
+    pub gap1: Option<usize>,
+    /// The length of GAP4B, the pre-index gap from the end of the last sector to the index.
+    pub gap4b: usize,
+}
+
+impl IndexMark {
+    /// Return true if the track has an IAM.
+    pub fn is_present(&self) -> bool {
+        self.bit_index.is_some()
+    }
+}
+
 pub struct TrackRegion {
     pub start: usize,
     pub end: usize,
@@ -264,11 +967,36 @@ pub struct DiskImage {
     pub(crate) volume_name: Option<String>,
     // An ASCII comment embedded in the disk image, if any.
     pub(crate) comment: Option<String>,
+    /// Metadata recorded by the source image format, such as the tool and drive used to create it.
+    pub(crate) source_metadata: BTreeMap<String, String>,
     /// A pool of track data structures, potentially in any order.
     pub(crate) track_pool: Vec<TrackData>,
     /// An array of vectors containing indices into the track pool. The first index is the head
     /// number, the second is the cylinder number.
     pub(crate) track_map: [Vec<usize>; 2],
+    /// Indices into the track pool of tracks recorded between whole cylinders, by head and
+    /// position. Whole cylinders are only ever stored in `track_map`.
+    pub(crate) sub_track_map: [BTreeMap<QuarterTrack, usize>; 2],
+    /// The policy used to match sector IDs when reading or writing sectors.
+    pub(crate) match_policy: MatchPolicy,
+    /// The CRC parameters used to check and write ID and data fields on BitStream tracks.
+    pub(crate) crc_params: System34CrcParams,
+    /// The policy for backing up the original image file on first modification.
+    pub(crate) backup_policy: BackupPolicy,
+    /// The bytes of the original image file, retained if a backup policy is set.
+    pub(crate) original_bytes: Option<Vec<u8>>,
+    /// Set once the backup policy has been applied by the first modification.
+    pub(crate) backup_taken: bool,
+    /// Progress reporting and cancellation for long-running operations.
+    pub(crate) progress: Progress,
+    /// Anomalies found while loading the image.
+    pub(crate) load_warnings: Vec<LoadWarning>,
+    /// How strictly the image was checked against its format's specification while loading.
+    pub(crate) parse_mode: ParseMode,
+    /// The seed of the random data returned by the image, if set for reproducible runs.
+    pub(crate) random_seed: Option<u64>,
+    /// The source of random data drawn by the image itself, such as for volume serial numbers.
+    pub(crate) rng: RandomSource,
 }
 
 // impl Default for DiskImage {
@@ -295,7 +1023,8 @@ impl DiskImage {
     }
 
     /// Create a new [`DiskImage`] with the specified disk format. This function should not be called
-    /// directly - use an [`ImageBuilder]` if you wish to create a new [`DiskImage`] from a specified format.
+    /// directly - use an [`ImageBuilder`] or [`DiskImage::create_formatted`] if you wish to create a
+    /// new [`DiskImage`] from a specified format.
     pub fn create(disk_format: StandardFormat) -> Self {
         Self {
             flags: DiskImageFlags::empty(),
@@ -316,11 +1045,44 @@ impl DiskImage {
             boot_sector: None,
             volume_name: None,
             comment: None,
+            source_metadata: BTreeMap::new(),
             track_pool: Vec::new(),
             track_map: [Vec::new(), Vec::new()],
+            sub_track_map: [BTreeMap::new(), BTreeMap::new()],
+            match_policy: MatchPolicy::default(),
+            crc_params: System34CrcParams::default(),
+            backup_policy: BackupPolicy::None,
+            original_bytes: None,
+            backup_taken: false,
+            progress: Progress::default(),
+            load_warnings: Vec::new(),
+            parse_mode: ParseMode::default(),
+            random_seed: None,
+            rng: RandomSource::default(),
         }
     }
 
+    /// Create a new, fully formatted [`DiskImage`] of the specified disk format. Every track is
+    /// created and formatted with the standard sector layout, gaps and fill byte of `disk_format`,
+    /// and a default boot sector is written. `resolution` selects whether the tracks are created
+    /// as ByteStream or BitStream tracks.
+    ///
+    /// This is a shortcut for building the image with an [`ImageBuilder`] using `with_formatted()`.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if `resolution` is not ByteStream or BitStream,
+    ///   or the format's encoding cannot be created at that resolution.
+    pub fn create_formatted(
+        disk_format: StandardFormat,
+        resolution: DiskDataResolution,
+    ) -> Result<Self, DiskImageError> {
+        ImageBuilder::new()
+            .with_standard_format(disk_format)
+            .with_resolution(resolution)
+            .with_formatted()
+            .build()
+    }
+
     pub fn track_iter(&self) -> impl Iterator<Item = &TrackData> {
         // Find the maximum number of tracks among all heads
         let max_tracks = self.track_map.iter().map(|tracks| tracks.len()).max().unwrap_or(0);
@@ -349,8 +1111,24 @@ impl DiskImage {
         self.track_pool.get(track_idx)
     }
 
-    pub fn get_track_mut(&mut self, track_idx: usize) -> Option<&mut TrackData> {
-        self.track_pool.get_mut(track_idx)
+    /// Return the track at `track_idx` in the track pool for modification. The backup policy is
+    /// applied and the image is marked dirty, as the caller may change the track.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if there is no track at `track_idx`.
+    pub fn get_track_mut(&mut self, track_idx: usize) -> Result<&mut TrackData, DiskImageError> {
+        if track_idx >= self.track_pool.len() {
+            return Err(DiskImageError::SeekError);
+        }
+        self.begin_write()?;
+        self.set_flag(DiskImageFlags::DIRTY);
+        Ok(&mut self.track_pool[track_idx])
+    }
+
+    /// Return the track identified by `ch`, if present.
+    pub fn get_track_ch(&self, ch: DiskCh) -> Option<&TrackData> {
+        let ti = *self.track_map.get(ch.h() as usize)?.get(ch.c() as usize)?;
+        self.track_pool.get(ti)
     }
 
     pub fn set_resolution(&mut self, resolution: DiskDataResolution) {
@@ -370,35 +1148,201 @@ impl DiskImage {
     }
 
     pub fn required_caps(&self) -> FormatCaps {
-        self.consistency.image_caps
+        let mut caps = self.consistency.image_caps;
+        if self.has_sub_tracks() {
+            caps |= FormatCaps::CAP_SUB_TRACKS;
+        }
+        if self.has_mixed_encodings() {
+            caps |= FormatCaps::CAP_TRACK_ENCODING;
+        }
+        if self.has_weak_bits() {
+            caps |= FormatCaps::CAP_WEAK_BITS;
+        }
+        caps
     }
 
     pub fn load<RS: ReadSeek>(image_io: &mut RS) -> Result<Self, DiskImageError> {
-        let container = DiskImage::detect_format(image_io)?;
+        DiskImage::load_with_options(image_io, LoadOptions::default())
+    }
+
+    /// Load an image of the specified format, applying any format-specific load options.
+    fn load_format<RS: ReadSeek>(
+        format: DiskImageFormat,
+        image_io: RS,
+        options: &LoadOptions,
+    ) -> Result<Self, DiskImageError> {
+        let mut image = match format {
+            DiskImageFormat::RawSectorImage => RawFormat::load_image_ordered(
+                image_io,
+                options.raw_side_order,
+                options.raw_track_order,
+                options.parse_mode,
+            )?,
+            _ => format.load_image_with_mode(image_io, options.parse_mode)?,
+        };
+        image.set_source_format(format);
+        if !options.retain_track_sources {
+            image.clear_track_sources();
+        }
+        image.post_load_process();
+        Ok(image)
+    }
+
+    /// Load a disk image with the specified [`LoadOptions`].
+    ///
+    /// If a backup policy is set, the entire image file is read into memory so that it can be
+    /// backed up when the image is first modified.
+    pub fn load_with_options<RS: ReadSeek>(image_io: &mut RS, options: LoadOptions) -> Result<Self, DiskImageError> {
+        DiskImage::load_with_hint(image_io, options, None)
+    }
+
+    /// Load the disk image at `path` with the specified [`LoadOptions`]. The file extension is used
+    /// as a hint to resolve ambiguous content, as described in [`DiskImageFormat::from_path`].
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IoError)` if the file could not be read.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if `path` is a directory holding a multi-file
+    ///   image set that cannot be loaded yet.
+    /// - `Err(DiskImageError::UnknownFormat)` if the format could not be determined.
+    pub fn load_from_path(path: impl AsRef<Path>, options: LoadOptions) -> Result<Self, DiskImageError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return match detect_image_set(path) {
+                Some(_) => Err(DiskImageError::UnsupportedFormat),
+                None => Err(DiskImageError::UnknownFormat),
+            };
+        }
+
+        let mut file = std::io::BufReader::new(std::fs::File::open(path).map_err(|_| DiskImageError::IoError)?);
+        DiskImage::load_with_hint(&mut file, options, DiskImageFormat::hint_from_path(path))
+    }
 
-        match container {
-            DiskImageContainer::Raw(format) => {
-                let mut image = format.load_image(image_io)?;
-                image.post_load_process();
-                Ok(image)
+    fn load_with_hint<RS: ReadSeek>(
+        image_io: &mut RS,
+        options: LoadOptions,
+        hint: Option<DiskImageFormat>,
+    ) -> Result<Self, DiskImageError> {
+        let original_bytes = match options.backup_policy {
+            BackupPolicy::None => None,
+            _ => {
+                let mut buf = Vec::new();
+                image_io.seek(SeekFrom::Start(0)).map_err(|_| DiskImageError::IoError)?;
+                image_io.read_to_end(&mut buf).map_err(|_| DiskImageError::IoError)?;
+                image_io.seek(SeekFrom::Start(0)).map_err(|_| DiskImageError::IoError)?;
+                Some(buf)
             }
+        };
+
+        let container = detect_image_format_with_hint(image_io, hint)?;
+
+        let mut image = match container {
+            DiskImageContainer::Raw(format) => DiskImage::load_format(format, image_io, &options)?,
             DiskImageContainer::Zip(format) => {
                 #[cfg(feature = "zip")]
                 {
                     let file_vec = extract_first_file(image_io)?;
                     let file_cursor = std::io::Cursor::new(file_vec);
-                    let mut image = format.load_image(file_cursor)?;
-                    image.post_load_process();
-                    Ok(image)
+                    DiskImage::load_format(format, file_cursor, &options)?
                 }
                 #[cfg(not(feature = "zip"))]
                 {
-                    Err(DiskImageError::UnknownFormat);
+                    return Err(DiskImageError::UnknownFormat);
                 }
             }
+        };
+
+        if options.crc_params != image.crc_params {
+            image.set_crc_params(options.crc_params)?;
+        }
+        if let Some(seed) = options.random_seed {
+            image.set_random_seed(seed);
+        }
+        image.backup_policy = options.backup_policy;
+        image.original_bytes = original_bytes;
+        Ok(image)
+    }
+
+    /// Load a disk image with the specified [`LoadOptions`], returning any anomalies found while
+    /// loading it alongside the image. The warnings are also available from
+    /// [`DiskImage::load_warnings`] on an image loaded by any other method.
+    pub fn load_with_warnings<RS: ReadSeek>(
+        image_io: &mut RS,
+        options: LoadOptions,
+    ) -> Result<LoadResult, DiskImageError> {
+        let image = DiskImage::load_with_options(image_io, options)?;
+        let warnings = image.load_warnings.clone();
+        Ok(LoadResult { image, warnings })
+    }
+
+    /// Return the anomalies found while loading this image. Empty for images that were not loaded
+    /// from a file.
+    pub fn load_warnings(&self) -> &[LoadWarning] {
+        &self.load_warnings
+    }
+
+    /// Record an anomaly found while loading the image. The warning is also logged.
+    pub(crate) fn add_load_warning(&mut self, ch: Option<DiskCh>, message: String) {
+        let warning = LoadWarning { ch, message };
+        log::warn!("{}", warning);
+        self.load_warnings.push(warning);
+    }
+
+    /// Report a violation of the image format's specification found while loading. In
+    /// [`ParseMode::Strict`] the violation is returned as an error. Otherwise it is recorded as a
+    /// load warning, and the caller should recover from it.
+    pub(crate) fn spec_violation(&mut self, ch: Option<DiskCh>, message: String) -> Result<(), DiskImageError> {
+        match self.parse_mode {
+            ParseMode::Strict => {
+                let violation = LoadWarning { ch, message };
+                log::error!("{}", violation);
+                Err(DiskImageError::SpecViolation(violation.to_string()))
+            }
+            ParseMode::Lenient => {
+                self.add_load_warning(ch, message);
+                Ok(())
+            }
         }
     }
 
+    /// Return the original image file as it was loaded, if the image was loaded with
+    /// [`BackupPolicy::InMemory`].
+    pub fn backup(&self) -> Option<&[u8]> {
+        match self.backup_policy {
+            BackupPolicy::InMemory => self.original_bytes.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Apply the backup policy before the first modification of the image. Every operation that
+    /// modifies track data must call this before making changes.
+    pub(crate) fn begin_write(&mut self) -> Result<(), DiskImageError> {
+        if self.backup_taken {
+            return Ok(());
+        }
+
+        if let (BackupPolicy::File(path), Some(original_bytes)) = (&self.backup_policy, &self.original_bytes) {
+            let write_result = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .and_then(|mut file| {
+                    file.write_all(original_bytes)?;
+                    file.sync_all()
+                });
+
+            if let Err(e) = write_result {
+                log::error!("begin_write(): Failed to write backup to {}: {}", path.display(), e);
+                return Err(DiskImageError::IoError);
+            }
+            log::debug!("begin_write(): Wrote backup of original image to {}", path.display());
+            // The file on disk is the backup now, so there's no need to hold the bytes.
+            self.original_bytes = None;
+        }
+
+        self.backup_taken = true;
+        Ok(())
+    }
+
     pub fn set_volume_name(&mut self, name: String) {
         self.volume_name = Some(name);
     }
@@ -415,6 +1359,13 @@ impl DiskImage {
         self.comment = Some(comment);
     }
 
+    /// Return the metadata recorded by the source image format, such as the software that created
+    /// the image and the drive it was captured with. Keys are specific to each format, and the map
+    /// is empty if the format records no metadata.
+    pub fn source_metadata(&self) -> &BTreeMap<String, String> {
+        &self.source_metadata
+    }
+
     pub fn set_data_rate(&mut self, rate: DiskDataRate) {
         self.descriptor.data_rate = rate;
     }
@@ -505,6 +1456,7 @@ impl DiskImage {
             sectors: Vec::new(),
             data: Vec::new(),
             weak_mask: Vec::new(),
+            source: None,
         });
 
         self.track_map[ch.h() as usize].push(self.track_pool.len() - 1);
@@ -541,6 +1493,57 @@ impl DiskImage {
         data: &[u8],
         weak: Option<&[u8]>,
     ) -> Result<(), DiskImageError> {
+        let ti = self.push_track_bitstream(encoding, data_rate, ch, data_clock, bitcell_ct, data, weak)?;
+        self.track_map[ch.h() as usize].push(ti);
+        Ok(())
+    }
+
+    /// Add a new `BitStream` track recorded between whole cylinders, such as a half-track or
+    /// quarter-track, at `position` on `head`. Parameters are otherwise as for
+    /// [`DiskImage::add_track_bitstream`].
+    ///
+    /// Sub-tracks are not part of the disk geometry, and are only read through the sub-track
+    /// accessors. Only formats with the [`FormatCaps::CAP_SUB_TRACKS`] capability can export them.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `position` is a whole cylinder or a track is
+    ///   already present at `position`.
+    pub fn add_sub_track_bitstream(
+        &mut self,
+        encoding: DiskDataEncoding,
+        data_rate: DiskDataRate,
+        head: u8,
+        position: QuarterTrack,
+        data_clock: u32,
+        bitcell_ct: Option<usize>,
+        data: &[u8],
+        weak: Option<&[u8]>,
+    ) -> Result<(), DiskImageError> {
+        if position.is_whole()
+            || self
+                .sub_track_map
+                .get(head as usize)
+                .is_some_and(|m| m.contains_key(&position))
+        {
+            return Err(DiskImageError::ParameterError);
+        }
+        let ch = DiskCh::new(position.c(), head);
+        let ti = self.push_track_bitstream(encoding, data_rate, ch, data_clock, bitcell_ct, data, weak)?;
+        self.sub_track_map[head as usize].insert(position, ti);
+        Ok(())
+    }
+
+    /// Create a new `BitStream` track and add it to the track pool, returning its index.
+    fn push_track_bitstream(
+        &mut self,
+        encoding: DiskDataEncoding,
+        data_rate: DiskDataRate,
+        ch: DiskCh,
+        data_clock: u32,
+        bitcell_ct: Option<usize>,
+        data: &[u8],
+        weak: Option<&[u8]>,
+    ) -> Result<usize, DiskImageError> {
         if ch.h() >= 2 {
             return Err(DiskImageError::SeekError);
         }
@@ -556,12 +1559,32 @@ impl DiskImage {
             _ => return Err(DiskImageError::IncompatibleImage),
         }
 
-        let data = BitVec::from_bytes(data);
-        let weak_bitvec_opt = weak.map(BitVec::from_bytes);
+        let mut track = self.build_track_bitstream(encoding, data_rate, ch, data_clock, bitcell_ct, data, weak)?;
+        if let Some(rng) = self.track_rng(self.track_pool.len()) {
+            track.set_rng(rng);
+        }
+        self.track_pool.push(track);
 
-        log::trace!("add_track_bitstream(): Encoding is {:?}", encoding);
-        let (mut data_stream, markers) = match encoding {
-            DiskDataEncoding::Mfm => {
+        Ok(self.track_pool.len() - 1)
+    }
+
+    /// Create a new `BitStream` track from `data`, scanning it for metadata.
+    fn build_track_bitstream(
+        &self,
+        encoding: DiskDataEncoding,
+        data_rate: DiskDataRate,
+        ch: DiskCh,
+        data_clock: u32,
+        bitcell_ct: Option<usize>,
+        data: &[u8],
+        weak: Option<&[u8]>,
+    ) -> Result<TrackData, DiskImageError> {
+        let data = BitVec::from_bytes(data);
+        let weak_bitvec_opt = weak.map(BitVec::from_bytes);
+
+        log::trace!("add_track_bitstream(): Encoding is {:?}", encoding);
+        let (mut data_stream, markers) = match encoding {
+            DiskDataEncoding::Mfm => {
                 let mut codec;
 
                 // If a weak bit mask was provided by the file format, we will honor it.
@@ -595,10 +1618,22 @@ impl DiskImage {
                 (data_stream, markers)
             }
             DiskDataEncoding::Fm => {
-                // TODO: Handle FM encoding sync
-                (TrackDataStream::Raw(RawCodec::new(data, weak_bitvec_opt)), Vec::new())
+                // Weak bits are not detected in FM bitstreams, but a weak bit mask is honored if
+                // provided by the file format.
+                let codec = FmCodec::new(data, bitcell_ct, weak_bitvec_opt);
+
+                let mut data_stream = TrackDataStream::Fm(codec);
+                let markers = System34Parser::scan_track_markers(&mut data_stream);
+
+                System34Parser::create_clock_map(&markers, data_stream.clock_map_mut().unwrap());
+
+                (data_stream, markers)
+            }
+            DiskDataEncoding::Gcr => {
+                // GCR tracks have no clock bitcells or System34 address marks to scan for.
+                let codec = GcrCodec::new(data, bitcell_ct, weak_bitvec_opt);
+                (TrackDataStream::Gcr(codec), Vec::new())
             }
-            _ => (TrackDataStream::Raw(RawCodec::new(data, weak_bitvec_opt)), Vec::new()),
         };
 
         // let format = TrackFormat {
@@ -607,9 +1642,14 @@ impl DiskImage {
         //     data_rate,
         // };
 
-        let metadata = DiskStructureMetadata::new(System34Parser::scan_track_metadata(&mut data_stream, markers));
-        let sector_ids = metadata.get_sector_ids();
-        if sector_ids.is_empty() {
+        let mut metadata = DiskStructureMetadata::new(System34Parser::scan_track_metadata_mode(
+            &mut data_stream,
+            markers,
+            &self.crc_params,
+            self.parse_mode,
+        )?);
+        AmigaParser::rescan_if_amiga(&mut data_stream, &mut metadata);
+        if metadata.get_sector_ids().is_empty() {
             log::warn!(
                 "add_track_bitstream(): No sectors ids found in track {} metadata.",
                 ch.c()
@@ -634,7 +1674,8 @@ impl DiskImage {
             sector_offsets.len()
         );
 
-        self.track_pool.push(TrackData::BitStream {
+        let source_bitcell_ct = Some(data_stream.len());
+        Ok(TrackData::BitStream {
             encoding,
             data_rate,
             cylinder: ch.c(),
@@ -642,11 +1683,224 @@ impl DiskImage {
             data_clock,
             data: data_stream,
             metadata,
-            sector_ids,
-        });
+            crc: self.crc_params,
+            source: None,
+            source_bitcell_ct,
+            splice: None,
+        })
+    }
 
-        self.track_map[ch.h() as usize].push(self.track_pool.len() - 1);
+    /// Add a new `FluxStream` track to the disk image from `revolutions`, each a revolution of
+    /// flux transitions captured from the track starting at the index pulse. A bitstream is
+    /// resolved from the first revolution with a [`Pll`] set to the nominal bitcell time of
+    /// `data_rate`, and scanned for metadata as for [`DiskImage::add_track_bitstream`].
+    ///
+    /// Every other revolution is resolved in the same way and compared against the first. Any
+    /// bitcell of a sector data field that reads differently on another revolution is marked weak.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the track was successfully added.
+    /// - `Err(DiskImageError::ParameterError)` if `revolutions` is empty.
+    /// - `Err(DiskImageError::SeekError)` if the head value in `ch` is greater than or equal to 2.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk image is not compatible with `BitStream` resolution.
+    pub fn add_track_fluxstream(
+        &mut self,
+        encoding: DiskDataEncoding,
+        data_rate: DiskDataRate,
+        ch: DiskCh,
+        revolutions: Vec<FluxRevolution>,
+    ) -> Result<(), DiskImageError> {
+        let Some(first) = revolutions.first() else {
+            return Err(DiskImageError::ParameterError);
+        };
+        let pll = Pll::from_data_rate(data_rate);
+        let (bits, stats) = pll.decode_with_stats(first);
+        log::trace!(
+            "add_track_fluxstream(): Resolved {} flux transitions into {} bitcells on track {}",
+            stats.transitions,
+            stats.bitcells,
+            ch
+        );
+        if stats.lock_losses > 0 {
+            log::debug!(
+                "add_track_fluxstream(): PLL lost lock {} times on track {}, with {} out-of-band transitions",
+                stats.lock_losses,
+                ch,
+                stats.out_of_band
+            );
+        }
+        let ti = self.push_track_bitstream(
+            encoding,
+            data_rate,
+            ch,
+            data_rate.into(),
+            Some(bits.len()),
+            &bits.to_bytes(),
+            None,
+        )?;
+        let mut track = self.track_pool.swap_remove(ti);
+
+        for (i, revolution) in revolutions.iter().enumerate().skip(1) {
+            let bits = pll.decode(revolution);
+            let other = self.build_track_bitstream(
+                encoding,
+                data_rate,
+                ch,
+                data_rate.into(),
+                Some(bits.len()),
+                &bits.to_bytes(),
+                None,
+            )?;
+            let weak_ct = track.mark_weak_from(&other);
+            if weak_ct > 0 {
+                log::debug!(
+                    "add_track_fluxstream(): Revolution {} of track {} differs in {} bitcells, marked weak",
+                    i,
+                    ch,
+                    weak_ct
+                );
+            }
+        }
+
+        let track = track.with_flux(revolutions, 0);
+        if track.nominal_data_rate() != data_rate {
+            log::debug!(
+                "add_track_fluxstream(): Track {} was captured at {} in a 360RPM drive, a {} track at 300RPM",
+                ch,
+                data_rate,
+                track.nominal_data_rate()
+            );
+        }
+        self.track_pool.push(track);
+        self.track_map[ch.h() as usize].push(ti);
+        Ok(())
+    }
+
+    /// Return true if the image contains any tracks recorded between whole cylinders.
+    pub fn has_sub_tracks(&self) -> bool {
+        self.sub_track_map.iter().any(|m| !m.is_empty())
+    }
+
+    /// Return true if the tracks of the image are not all of the same encoding, as on hybrid disks
+    /// that carry both MFM and GCR tracks.
+    pub fn has_mixed_encodings(&self) -> bool {
+        let mut encodings = self.track_iter().map(|track| std::mem::discriminant(&track.encoding()));
+        match encodings.next() {
+            Some(first) => encodings.any(|encoding| encoding != first),
+            None => false,
+        }
+    }
+
+    /// Return the positions of the sub-tracks recorded on `head`, in ascending order.
+    pub fn sub_track_positions(&self, head: u8) -> Vec<QuarterTrack> {
+        match self.sub_track_map.get(head as usize) {
+            Some(map) => map.keys().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Return the track at `position` on `head`, whether a whole cylinder or a sub-track.
+    pub fn get_track_at(&self, head: u8, position: QuarterTrack) -> Option<&TrackData> {
+        let ti = match position.is_whole() {
+            true => self.track_map.get(head as usize)?.get(position.c() as usize)?,
+            false => self.sub_track_map.get(head as usize)?.get(&position)?,
+        };
+        self.track_pool.get(*ti)
+    }
 
+    /// Return the handles of every track of the image, whole cylinders and sub-tracks, in order
+    /// of position, then head.
+    pub fn track_ids(&self) -> Vec<TrackId> {
+        let mut ids: Vec<TrackId> = Vec::new();
+        for (h, cylinders) in self.track_map.iter().enumerate() {
+            ids.extend((0..cylinders.len()).map(|c| TrackId::from(DiskCh::new(c as u16, h as u8))));
+        }
+        for (h, map) in self.sub_track_map.iter().enumerate() {
+            ids.extend(map.keys().map(|position| TrackId::new(h as u8, *position)));
+        }
+        ids.sort_by_key(|id| (id.position(), id.head()));
+        ids
+    }
+
+    /// Return the track referred to by `id`, if present.
+    pub fn get_track_by_id(&self, id: TrackId) -> Option<&TrackData> {
+        self.get_track_at(id.head(), id.position())
+    }
+
+    /// Return the track referred to by `id` for modification, as [`DiskImage::get_track_mut`].
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track is not present.
+    pub fn get_track_by_id_mut(&mut self, id: TrackId) -> Result<&mut TrackData, DiskImageError> {
+        let ti = match id.position().is_whole() {
+            true => self
+                .track_map
+                .get(id.head() as usize)
+                .and_then(|head_tracks| head_tracks.get(id.position().c() as usize)),
+            false => self
+                .sub_track_map
+                .get(id.head() as usize)
+                .and_then(|head_tracks| head_tracks.get(&id.position())),
+        };
+        let ti = *ti.ok_or(DiskImageError::SeekError)?;
+        self.get_track_mut(ti)
+    }
+
+    /// Return the handles of the sectors of the track referred to by `id`, in physical order, or
+    /// an empty list if the track is not present.
+    pub fn sector_ids(&self, id: TrackId) -> Vec<SectorId> {
+        let Some(track) = self.get_track_by_id(id) else {
+            return Vec::new();
+        };
+        let sectors = track.get_sector_list();
+        sectors
+            .iter()
+            .enumerate()
+            .map(|(i, sector)| {
+                let occurrence = sectors[..i].iter().filter(|s| s.chsn == sector.chsn).count();
+                SectorId::new(id, sector.chsn, occurrence as u8)
+            })
+            .collect()
+    }
+
+    /// Return the sector referred to by `id`, if present.
+    pub fn get_sector_by_id(&self, id: SectorId) -> Option<SectorMapEntry> {
+        self.get_track_by_id(id.track())?
+            .get_sector_list()
+            .into_iter()
+            .filter(|sector| sector.chsn == id.chsn())
+            .nth(id.occurrence() as usize)
+    }
+
+    /// Reinterpret an image of a 48 tpi disk captured in a 96 tpi drive, where every track of
+    /// the disk was captured twice. Even tracks become the whole cylinders of the disk, and odd
+    /// tracks become the half-tracks between them, which are usually blank or crosstalk, but may
+    /// hold protection data.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the image already contains sub-tracks.
+    pub fn remap_double_stepped(&mut self) -> Result<(), DiskImageError> {
+        if self.has_sub_tracks() {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+        self.begin_write()?;
+
+        for head in 0..2 {
+            let tracks = std::mem::take(&mut self.track_map[head]);
+            for (i, ti) in tracks.into_iter().enumerate() {
+                let position = QuarterTrack::from_half(i as u16);
+                self.track_pool[ti].set_cylinder(position.c());
+                if position.is_whole() {
+                    self.track_map[head].push(ti);
+                } else {
+                    self.sub_track_map[head].insert(position, ti);
+                }
+            }
+        }
+
+        let cylinders = self.track_map[0].len() as u16;
+        self.descriptor.geometry = DiskCh::new(cylinders, self.descriptor.geometry.h());
+        self.set_flag(DiskImageFlags::DIRTY);
         Ok(())
     }
 
@@ -662,7 +1916,7 @@ impl DiskImage {
     /// - `Err(DiskImageError::SeekError)` if the head value in `chs` is greater than 1 or the track map does not contain the specified cylinder.
     /// - `Err(DiskImageError::UnsupportedFormat)` if the track data is not of `ByteStream` resolution.
     pub(crate) fn master_sector(&mut self, chs: DiskChs, sd: &SectorDescriptor) -> Result<(), DiskImageError> {
-        if chs.h() > 1 || self.track_map[chs.h() as usize].len() < chs.c() as usize {
+        if chs.h() > 1 || self.track_map[chs.h() as usize].len() <= chs.c() as usize {
             return Err(DiskImageError::SeekError);
         }
 
@@ -696,11 +1950,15 @@ impl DiskImage {
                     address_crc_error: sd.address_crc_error,
                     data_crc_error: sd.data_crc_error,
                     deleted_mark: sd.deleted_mark,
+                    no_dam: sd.no_dam,
+                    position: sd.position,
+                    read_time: sd.read_time,
+                    timing: sd.timing.clone(),
                 });
                 data.extend(&sd.data);
                 weak_mask.extend(weak_buf_vec);
             }
-            TrackData::BitStream { .. } => {
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => {
                 return Err(DiskImageError::UnsupportedFormat);
             }
         }
@@ -747,9 +2005,266 @@ impl DiskImage {
         let ti = self.track_map[chs.h() as usize][chs.c() as usize];
         let track = &mut self.track_pool[ti];
 
-        track.read_sector(chs, n, scope, debug)
+        track.read_sector(chs, n, scope, self.match_policy, debug)
+    }
+
+    /// Read the sector data from the sector identified by 'chs' into `buf`, as
+    /// [`DiskImage::read_sector`] does, but without allocating a new buffer for each read. `buf`
+    /// is cleared and resized to the length of the read, so once it has grown to the largest
+    /// sector read, subsequent reads reuse its allocation. The `read_buf` of the returned
+    /// [`ReadSectorResult`] is left empty; its `data_idx` and `data_len` give the position of the
+    /// sector data within `buf`.
+    ///
+    /// Emulators reading sectors at a high rate should prefer this method over `read_sector`.
+    pub fn read_sector_into(
+        &mut self,
+        chs: DiskChs,
+        n: Option<u8>,
+        scope: RwSectorScope,
+        debug: bool,
+        buf: &mut Vec<u8>,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        self.track_pool[ti].read_sector_into(chs, n, scope, self.match_policy, debug, buf)
+    }
+
+    /// Read the sector data from the sector identified by 'chs', applying the deleted data
+    /// semantics of the µPD765 Read Data and Read Deleted Data commands.
+    ///
+    /// If `deleted` is false, a normal data address mark is expected (Read Data). If `deleted` is
+    /// true, a deleted data address mark is expected (Read Deleted Data). When the address mark
+    /// found does not match the type expected, `control_mark` is set in the result. In that case,
+    /// if `skip` is true (the SK flag), the sector's data is not returned and the result's
+    /// `read_buf` is empty, so the caller may continue on to the next sector. If `skip` is false
+    /// the data is returned and the caller should terminate a multi-sector read after this sector.
+    pub fn read_sector_sk(
+        &mut self,
+        chs: DiskChs,
+        n: Option<u8>,
+        scope: RwSectorScope,
+        deleted: bool,
+        skip: bool,
+        debug: bool,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        let mut rsr = self.read_sector(chs, n, scope, debug)?;
+
+        if rsr.address_crc_error {
+            // No data was read, so we don't know what kind of address mark the sector has.
+            return Ok(rsr);
+        }
+
+        rsr.control_mark = rsr.deleted_mark != deleted;
+        if rsr.control_mark && skip {
+            log::trace!("read_sector_sk(): Skipping sector {} with control mark set", chs.s());
+            rsr.read_buf.clear();
+            rsr.data_idx = 0;
+            rsr.data_len = 0;
+        }
+
+        Ok(rsr)
+    }
+
+    /// Read the sector identified by 'chs' as a controller commanded with a size code of `n`
+    /// larger than the sector's actual size would: the data field is read in full, then reading
+    /// continues through the data CRC and into the gap that follows, until `n` bytes have been
+    /// read. Some copy protection schemes check the bytes found past the CRC this way.
+    ///
+    /// The sector is matched by ID without regard to its size, and its CRC status reflects the
+    /// data field as recorded. `data_len` in the result is the recorded size of the data field,
+    /// and `overread_len` the number of bytes following it. The read stops at the end of the
+    /// track. If `n` is not larger than the recorded size, the sector data is returned truncated
+    /// to `n` bytes.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track, as
+    ///   other tracks do not record the gaps between sectors.
+    pub fn read_sector_overread(&mut self, chs: DiskChs, n: u8) -> Result<ReadSectorResult, DiskImageError> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        self.track_pool[ti].read_sector_overread(chs, DiskChsn::n_to_bytes(n), self.match_policy)
+    }
+
+    /// Return the nominal geometry of the disk, with the number of sectors per track, if known.
+    /// This is the geometry of the disk's standard format, or if the format is not known, the
+    /// geometry of the image with its consistent track length.
+    pub fn nominal_geometry(&self) -> Option<DiskChs> {
+        if let Some(format) = self.standard_format {
+            return Some(format.get_chs());
+        }
+        let spt = self.consistency.consistent_track_length?;
+        Some(DiskChs::new(
+            self.descriptor.geometry.c(),
+            self.descriptor.geometry.h(),
+            spt,
+        ))
+    }
+
+    /// Return the nominal sector size of the disk in bytes. This is the sector size of the disk's
+    /// standard format, or if the format is not known, the consistent sector size of the image.
+    pub(crate) fn nominal_sector_size(&self) -> usize {
+        match (self.standard_format, self.consistency.consistent_sector_size) {
+            (Some(format), _) => format.get_chsn().n_size(),
+            (None, Some(size)) => size as usize,
+            (None, None) => self.descriptor.default_sector_size,
+        }
+    }
+
+    /// Read the sector at the logical block address `lba`, counting sectors across the
+    /// [`nominal_geometry`](DiskImage::nominal_geometry) of the disk in cylinder, head, sector
+    /// order, as DOS and most other filesystems do.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<u8>)` with the data of the sector.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the geometry of the disk is not known.
+    /// - `Err(DiskImageError::SeekError)` if `lba` is beyond the end of the disk.
+    /// - `Err(DiskImageError::DataError)` if the sector is not present or has no data field.
+    /// - `Err(DiskImageError::CrcError)` if the sector's ID or data has a bad CRC.
+    pub fn read_lba(&mut self, lba: usize) -> Result<Vec<u8>, DiskImageError> {
+        let chs = self.lba_to_chs(lba)?;
+        let rsr = self.read_sector(chs, None, RwSectorScope::DataOnly, false)?;
+        if rsr.address_crc_error || rsr.data_crc_error {
+            return Err(DiskImageError::CrcError);
+        }
+        if rsr.no_dam {
+            return Err(DiskImageError::DataError);
+        }
+        Ok(rsr.read_buf)
+    }
+
+    /// Write `data` to the sector at the logical block address `lba`, addressed as by
+    /// [`DiskImage::read_lba`]. The sector is written with a normal data address mark.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the geometry of the disk is not known.
+    /// - `Err(DiskImageError::SeekError)` if `lba` is beyond the end of the disk.
+    /// - `Err(DiskImageError::ParameterError)` if `data` is not the nominal sector size.
+    /// - `Err(DiskImageError::DataError)` if the sector is not present.
+    /// - `Err(DiskImageError::CrcError)` if the sector's ID has a bad CRC.
+    pub fn write_lba(&mut self, lba: usize, data: &[u8]) -> Result<(), DiskImageError> {
+        let chs = self.lba_to_chs(lba)?;
+        if data.len() != self.nominal_sector_size() {
+            return Err(DiskImageError::ParameterError);
+        }
+        let wsr = self.write_sector(chs, None, data, RwSectorScope::DataOnly, false, false)?;
+        if wsr.not_found {
+            return Err(DiskImageError::DataError);
+        }
+        if wsr.address_crc_error {
+            return Err(DiskImageError::CrcError);
+        }
+        Ok(())
+    }
+
+    /// Return a [`LinearView`] of the sectors of the disk as a single stream of bytes in logical
+    /// block order, implementing `Read`, `Write` and `Seek`. See [`DiskImage::read_lba`] for the
+    /// order of the sectors.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the geometry of the disk is not known.
+    pub fn linear_view(&mut self) -> Result<LinearView<'_>, DiskImageError> {
+        LinearView::new(self)
+    }
+
+    fn lba_to_chs(&self, lba: usize) -> Result<DiskChs, DiskImageError> {
+        let geometry = self.nominal_geometry().ok_or(DiskImageError::IncompatibleImage)?;
+        let chs = DiskChs::from_lba(lba, &geometry).ok_or(DiskImageError::SeekError)?;
+        Ok(DiskChs::new(chs.c(), chs.h(), chs.s() - 1 + self.first_sector_id()))
+    }
+
+    /// Return the ID of the first sector of each track. IBM sectors are numbered from 1, but
+    /// Amiga sectors are numbered from 0.
+    fn first_sector_id(&self) -> u8 {
+        match self.track_map[0].first() {
+            Some(&ti) if self.track_pool[ti].is_amiga() => 0,
+            _ => 1,
+        }
+    }
+
+    /// Set the [`MatchPolicy`] used to match sector IDs in subsequent sector read and write
+    /// operations.
+    pub fn set_match_policy(&mut self, policy: MatchPolicy) {
+        self.match_policy = policy;
+    }
+
+    /// Set the CRC parameters used to check and write ID and data fields, for disks written by
+    /// controllers with a nonstandard CRC. BitStream tracks are re-scanned so that their CRC
+    /// status reflects the new parameters; the track data itself is not modified.
+    pub fn set_crc_params(&mut self, params: System34CrcParams) -> Result<(), DiskImageError> {
+        self.crc_params = params;
+        let total = self.track_pool.len();
+        for (i, track) in self.track_pool.iter_mut().enumerate() {
+            self.progress.report("set_crc_params", i, total)?;
+            if let TrackData::BitStream { crc, .. } | TrackData::FluxStream { crc, .. } = track {
+                *crc = params;
+                track.rescan()?;
+            }
+        }
+        self.progress.report("set_crc_params", total, total)?;
+        Ok(())
+    }
+
+    pub fn crc_params(&self) -> System34CrcParams {
+        self.crc_params
+    }
+
+    /// Seed all random data returned by the image, such as the data read from weak bits, so that
+    /// runs can be reproduced. Each track draws from its own sequence derived from `seed`, so the
+    /// data read from a track does not depend on reads from other tracks. Tracks added to the
+    /// image later are seeded as they are added.
+    ///
+    /// Without a seed, random data is seeded from entropy.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random_seed = Some(seed);
+        self.rng = RandomSource::new(seed);
+        for (ti, track) in self.track_pool.iter_mut().enumerate() {
+            track.set_rng(RandomSource::derive(seed, ti));
+        }
+    }
+
+    /// Return the seed set by [`DiskImage::set_random_seed`], if any.
+    pub fn random_seed(&self) -> Option<u64> {
+        self.random_seed
+    }
+
+    /// Return the random source for the track at `ti` in the track pool, if a seed is set.
+    fn track_rng(&self, ti: usize) -> Option<RandomSource> {
+        self.random_seed.map(|seed| RandomSource::derive(seed, ti))
     }
 
+    /// Set the [`Progress`] observed by long-running operations on this image: [`DiskImage::format`],
+    /// [`DiskImage::resample`], [`DiskImage::set_crc_params`] and [`DiskImage::save_with_policy`].
+    /// Each reports progress per track, and returns `Err(DiskImageError::Cancelled)` if the
+    /// progress's [`crate::progress::CancellationToken`] is cancelled.
+    ///
+    /// A cancelled operation does not roll back. Tracks already processed keep their changes.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Return the [`MatchPolicy`] currently used to match sector IDs.
+    pub fn match_policy(&self) -> MatchPolicy {
+        self.match_policy
+    }
+
+    /// Write `data` to the sector identified by `chs`, matched according to the current
+    /// [`MatchPolicy`]. If `n` is provided, the sector's size must match it. The data address mark
+    /// of the sector is rewritten as a deleted mark if `deleted` is set, or a normal mark
+    /// otherwise, as by the Write Deleted Data and Write Data commands of a floppy controller.
+    /// If `debug` is set, the data is written even if the sector's ID field has a bad CRC.
+    ///
+    /// The consistency information of the image is updated to reflect the sector written, so
+    /// that a deleted sector written to the image is accounted for when saving it.
     pub fn write_sector(
         &mut self,
         chs: DiskChs,
@@ -763,11 +2278,97 @@ impl DiskImage {
             return Err(DiskImageError::SeekError);
         }
 
+        self.begin_write()?;
+
         let ti = self.track_map[chs.h() as usize][chs.c() as usize];
         let track = &mut self.track_pool[ti];
 
         log::trace!("TrackData::write_sector(): data len is now: {}", data.len());
-        track.write_sector(chs, n, data, scope, deleted, debug)
+        let result = track.write_sector(chs, n, data, scope, self.match_policy, deleted, debug, None)?;
+        self.set_flag(DiskImageFlags::DIRTY);
+        self.update_sector_consistency(chs);
+        Ok(result)
+    }
+
+    /// Write a sector in debug mode, allowing the size of the data block written to differ from
+    /// the size specified by the sector header. This can be used to reproduce mastering quirks seen
+    /// on some protected disks, where a sector's data overflows into the following gap and sector,
+    /// or is shorter than its header indicates.
+    ///
+    /// The sector is matched according to the current [`MatchPolicy`], ignoring the size. The size of the data block written is taken
+    /// from `n` if provided, otherwise from the length of `data`. If `data` is shorter than the
+    /// requested size, the remainder is filled with `pad_byte`. A valid CRC is written immediately
+    /// after the data block, so reading the sector back with its header size will produce a CRC
+    /// error unless the sizes match.
+    pub fn write_sector_debug(
+        &mut self,
+        chs: DiskChs,
+        n: Option<u8>,
+        data: &[u8],
+        pad_byte: u8,
+        deleted: bool,
+    ) -> Result<WriteSectorResult, DiskImageError> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        self.begin_write()?;
+
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        let track = &mut self.track_pool[ti];
+
+        let result = track.write_sector(
+            chs,
+            n,
+            data,
+            RwSectorScope::DataOnly,
+            self.match_policy,
+            deleted,
+            true,
+            Some(pad_byte),
+        )?;
+        self.set_flag(DiskImageFlags::DIRTY);
+        self.update_sector_consistency(chs);
+        Ok(result)
+    }
+
+    /// Record the state of the sector `chs` after a write in the consistency information of the
+    /// image, and the format capabilities needed to represent it. Flags are only ever set, since
+    /// other sectors of the image may still require them.
+    fn update_sector_consistency(&mut self, chs: DiskChs) {
+        let Some((sectors, resolution)) = self
+            .get_track_ch(DiskCh::from(chs))
+            .map(|track| (track.get_sector_list(), track.resolution()))
+        else {
+            return;
+        };
+        self.record_sector_consistency(chs, &sectors, resolution);
+    }
+
+    /// Record the state of the sector `chs` from `sectors`, the sector list of its track of the
+    /// given `resolution`, as [`DiskImage::update_sector_consistency`] does, for callers holding
+    /// the track elsewhere.
+    pub(crate) fn record_sector_consistency(
+        &mut self,
+        chs: DiskChs,
+        sectors: &[SectorMapEntry],
+        resolution: DiskDataResolution,
+    ) {
+        let policy = self.match_policy;
+        for sector in sectors.iter().filter(|s| policy.matches(s.chsn, chs, None, resolution)) {
+            if sector.deleted_mark {
+                self.consistency.deleted = true;
+                self.consistency.image_caps |= FormatCaps::CAP_DATA_DELETED;
+            }
+            if !sector.address_crc_valid {
+                self.consistency.bad_address_crc = true;
+                self.consistency.image_caps |= FormatCaps::CAP_ADDRESS_CRC;
+            }
+            if !sector.data_crc_valid {
+                self.consistency.bad_data_crc = true;
+                self.consistency.image_caps |= FormatCaps::CAP_DATA_CRC;
+            }
+        }
     }
 
     /// Read all sectors from the track identified by 'ch'. The data is returned within a
@@ -800,6 +2401,79 @@ impl DiskImage {
         track.read_track(ch)
     }
 
+    /// Read the entire track identified by `ch` as a WD177x/WD179x Read Track command would,
+    /// resynchronizing only on 0xA1 sync marks so that gaps decode as unsynchronized garbage.
+    /// GCR tracks are read as the disk nibbles an Apple II disk controller would return.
+    pub fn read_track_raw(&self, ch: DiskCh) -> Result<Vec<u8>, DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        self.track_pool[ti].read_track_raw()
+    }
+
+    /// Read the first ID field at or after the rotational position `bit_index` (in bitcells from
+    /// the index) on the track identified by `ch`, including ID fields with bad CRCs.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::DataError)` if the track has no ID fields.
+    pub fn read_address(&mut self, ch: DiskCh, bit_index: usize) -> Result<ReadAddressResult, DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        self.track_pool[ti].read_address(bit_index)
+    }
+
+    /// Rewrite the track identified by `ch` from a WD177x/WD179x Write Track data stream in MFM
+    /// mode. 0xF5 writes an 0xA1 sync mark and presets the CRC, 0xF6 writes an 0xC2 sync mark and
+    /// 0xF7 writes the two CRC bytes. The track keeps its length; if `buf` is too short to reach
+    /// the index, the rest of the track is zero-filled and `lost_data` is set in the result.
+    pub fn write_track(&mut self, ch: DiskCh, buf: &[u8]) -> Result<WriteTrackResult, DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        self.begin_write()?;
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        let result = self.track_pool[ti].write_track(buf)?;
+        self.set_flag(DiskImageFlags::DIRTY);
+
+        Ok(result)
+    }
+
+    /// Open a channel for writing bitcells to the track specified by `ch`, starting at the
+    /// rotational position `pos` counted from the index. Positions past the end of the track wrap
+    /// around to its start. See [`TrackBitWriter`].
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is a ByteStream track or is empty.
+    pub fn write_bits(&mut self, ch: DiskCh, pos: usize) -> Result<TrackBitWriter<'_>, DiskImageError> {
+        let ti = *self
+            .track_map
+            .get(ch.h() as usize)
+            .and_then(|head| head.get(ch.c() as usize))
+            .ok_or(DiskImageError::SeekError)?;
+        if self.track_pool[ti].bit_at(0).is_none() {
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        self.begin_write()?;
+        self.set_flag(DiskImageFlags::DIRTY);
+
+        let track = &mut self.track_pool[ti];
+        Ok(TrackBitWriter {
+            start: pos % track.bitcell_ct(),
+            track,
+            bits: BitVec::new(),
+            ended: false,
+        })
+    }
+
     pub fn add_empty_track(
         &mut self,
         ch: DiskCh,
@@ -820,7 +2494,7 @@ impl DiskImage {
                     return Err(DiskImageError::ParameterError);
                 }
 
-                let stream = match encoding {
+                let mut stream = match encoding {
                     DiskDataEncoding::Mfm => {
                         TrackDataStream::Mfm(MfmCodec::new(BitVec::from_elem(bitcells, false), None, None))
                     }
@@ -829,6 +2503,9 @@ impl DiskImage {
                     }
                     _ => return Err(DiskImageError::UnsupportedFormat),
                 };
+                if let Some(rng) = self.track_rng(self.track_pool.len()) {
+                    stream.set_rng(rng);
+                }
 
                 self.track_pool.push(TrackData::BitStream {
                     encoding,
@@ -838,7 +2515,10 @@ impl DiskImage {
                     data_clock: 0,
                     data: stream,
                     metadata: DiskStructureMetadata::default(),
-                    sector_ids: Vec::new(),
+                    crc: self.crc_params,
+                    source: None,
+                    source_bitcell_ct: None,
+                    splice: None,
                 });
 
                 self.track_map[ch.h() as usize].push(self.track_pool.len() - 1);
@@ -857,6 +2537,7 @@ impl DiskImage {
                     sectors: Vec::new(),
                     data: vec![0; bitcell_bytes],
                     weak_mask: Vec::new(),
+                    source: None,
                 });
 
                 self.track_map[ch.h() as usize].push(self.track_pool.len() - 1);
@@ -867,26 +2548,245 @@ impl DiskImage {
         Ok(())
     }
 
+    /// Format the track identified by `ch`, as with the FDC Format Track command. A sector is
+    /// written for each ID in `format_buffer`, in order, filled with `fill_byte` and followed by
+    /// `gap3` bytes of GAP3. IDs are written exactly as given: they may repeat, skip numbers, vary
+    /// in size, or name a different cylinder and head. `standard` selects the layout of the
+    /// index area and gaps of a BitStream track.
+    ///
+    /// A drive can step past the last track of a disk, so the cylinder following the last track
+    /// of a head may also be formatted. A track is added for it, with the encoding, data rate and
+    /// length of the track before it.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist and does not follow the
+    ///   last track of its head.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is a BitStream track not encoded
+    ///   as MFM.
+    /// - `Err(DiskImageError::ParameterError)` if the sectors do not fit on the track.
     pub fn format_track(
         &mut self,
         ch: DiskCh,
+        standard: System34Standard,
         format_buffer: Vec<DiskChsn>,
         fill_byte: u8,
-        sector_gap: usize,
+        gap3: usize,
     ) -> Result<(), DiskImageError> {
-        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+        if ch.h() > 1 {
             return Err(DiskImageError::SeekError);
         }
+        let head_tracks = self.track_map[ch.h() as usize].len();
+        let extend = match ch.c() as usize {
+            c if c < head_tracks => false,
+            c if c == head_tracks && c > 0 => true,
+            _ => return Err(DiskImageError::SeekError),
+        };
+
+        self.begin_write()?;
+
+        if extend {
+            let prev = &self.track_pool[self.track_map[ch.h() as usize][head_tracks - 1]];
+            let (encoding, data_rate, bitcell_ct) = (prev.encoding(), prev.data_rate(), prev.bitcell_ct());
+            self.add_empty_track(ch, encoding, data_rate, bitcell_ct)?;
+            if ch.c() >= self.descriptor.geometry.c() {
+                self.descriptor.geometry = DiskCh::new(ch.c() + 1, self.descriptor.geometry.h());
+            }
+        }
 
         let ti = self.track_map[ch.h() as usize][ch.c() as usize];
         let track = &mut self.track_pool[ti];
 
-        // TODO: How would we support other structures here?
-        track.format(System34Standard::Iso, format_buffer, fill_byte, sector_gap)?;
+        track.format(standard, format_buffer, fill_byte, gap3)?;
+        self.set_flag(DiskImageFlags::DIRTY);
+
+        Ok(())
+    }
+
+    /// Encode `data` as MFM and write it to the track specified by `ch` at the raw bitcell index
+    /// `bit_offset`. Encoding with [`MfmEncodingType::AddressMark`] will produce sync bytes with
+    /// missing clock bits, allowing address marks to be written at arbitrary positions.
+    ///
+    /// This is a low-level operation intended for hand-crafting copy protection tracks. The track
+    /// is re-scanned after the write so that any new markers or sectors can be read.
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the number of bitcells written.
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn write_encoded_data(
+        &mut self,
+        ch: DiskCh,
+        data: &[u8],
+        bit_offset: usize,
+        encoding_type: MfmEncodingType,
+    ) -> Result<usize, DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        self.begin_write()?;
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        let bits_written = self.track_pool[ti].write_encoded_buf(data, bit_offset, encoding_type)?;
+        self.set_flag(DiskImageFlags::DIRTY);
+
+        Ok(bits_written)
+    }
+
+    /// Recompute the address and data CRCs of the sectors on the track identified by `ch`, and
+    /// rewrite each one that does not match, such as after hand-editing the sector bytes with
+    /// [`DiskImage::write_encoded_data`]. If `sectors` is `Some`, only sectors with the listed IDs
+    /// are fixed. Sectors with IDs listed in `keep_bad` are never fixed, so that intentionally bad
+    /// CRCs survive.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<CrcFix>)` with each CRC that was rewritten, in track order.
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn fix_sector_crcs(
+        &mut self,
+        ch: DiskCh,
+        sectors: Option<&[u8]>,
+        keep_bad: &[u8],
+    ) -> Result<Vec<CrcFix>, DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        self.begin_write()?;
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        let select =
+            |chsn: DiskChsn| sectors.is_none_or(|ids| ids.contains(&chsn.s())) && !keep_bad.contains(&chsn.s());
+        let fixes = self.track_pool[ti].fix_sector_crcs(&select)?;
+        if !fixes.is_empty() {
+            self.set_flag(DiskImageFlags::DIRTY);
+        }
+
+        Ok(fixes)
+    }
+
+    /// Replace the bitstream of the track identified by `ch` with the composite track assembled
+    /// by `builder`. Unless the builder specifies a length, the track keeps its length in
+    /// bitcells, and any space after the last segment is filled. The track metadata is regenerated
+    /// from the new bitstream.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::ParameterError)` if the segments do not fit on the track.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn splice_track(&mut self, ch: DiskCh, builder: &TrackBuilder) -> Result<(), DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        let (bits, weak) = builder.build(self.track_pool[ti].bitcell_ct())?;
+
+        self.begin_write()?;
+        self.track_pool[ti].replace_bits(bits, weak)?;
+        self.set_flag(DiskImageFlags::DIRTY);
 
         Ok(())
     }
 
+    /// Measure the gap and sync field lengths on the track identified by `ch`, so that a disk
+    /// can be re-mastered with its original layout parameters.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn track_gaps(&self, ch: DiskCh) -> Result<TrackGaps, DiskImageError> {
+        let track = self.get_track_ch(ch).ok_or(DiskImageError::SeekError)?;
+        track.measure_gaps()
+    }
+
+    /// Analyze the layout of each track of the disk image: the physical order of its sectors, its
+    /// interleave, its skew relative to the neighboring tracks, and its gap lengths. Sub-tracks are
+    /// not included.
+    pub fn track_layouts(&self) -> Vec<TrackLayout> {
+        let head_map = self.get_sector_map();
+        let orders: Vec<Vec<Vec<u8>>> = head_map
+            .iter()
+            .map(|tracks| {
+                tracks
+                    .iter()
+                    .map(|t| t.sectors.iter().map(|s| s.chsn.s()).collect())
+                    .collect()
+            })
+            .collect();
+
+        let mut layouts = Vec::new();
+        for (h, tracks) in head_map.iter().enumerate() {
+            for (c, track) in tracks.iter().enumerate() {
+                let order = &orders[h][c];
+                layouts.push(TrackLayout {
+                    ch: track.ch,
+                    sector_order: order.clone(),
+                    interleave: layout_interleave(order),
+                    head_skew: (h > 0)
+                        .then(|| orders[0].get(c))
+                        .flatten()
+                        .and_then(|from| layout_skew(from, order)),
+                    cylinder_skew: c.checked_sub(1).and_then(|prev| layout_skew(&orders[h][prev], order)),
+                    gaps: self.track_gaps(track.ch).ok(),
+                });
+            }
+        }
+        layouts
+    }
+
+    /// Fingerprint the layout of the tracks of the disk image, and match it against the built-in
+    /// signatures of controller formats and mastering machines. Only MFM BitStream tracks with
+    /// sectors are examined.
+    pub fn duplicator_report(&self) -> DuplicatorReport {
+        DuplicatorReport::new(duplicator::fingerprint(self), &duplicator::builtin_signatures())
+    }
+
+    /// Report whether the track identified by `ch` has an index address mark, where it is, and
+    /// the lengths of the gaps that precede and follow the index.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn index_mark(&self, ch: DiskCh) -> Result<IndexMark, DiskImageError> {
+        let track = self.get_track_ch(ch).ok_or(DiskImageError::SeekError)?;
+        track.index_mark()
+    }
+
+    /// Lay out the fields of the track identified by `ch` as a [`TrackTimeline`], which can be
+    /// rendered as text, SVG or a GraphViz graph.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not an MFM BitStream track.
+    pub fn track_timeline(&self, ch: DiskCh) -> Result<TrackTimeline, DiskImageError> {
+        let track = self.get_track_ch(ch).ok_or(DiskImageError::SeekError)?;
+        track.timeline()
+    }
+
+    /// Render the flux transitions of the track identified by `ch` as a WAV waveform, written to
+    /// `out`. See [`waveform::render`] for how the waveform is produced. BitStream tracks without
+    /// captured flux are rendered from their bitcells at the track's data rate.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is a ByteStream track.
+    /// - `Err(DiskImageError::ParameterError)` if `options` has a sample rate or slowdown of zero.
+    pub fn export_track_wav<W: crate::io::Write>(
+        &self,
+        ch: DiskCh,
+        options: &WaveformOptions,
+        out: W,
+    ) -> Result<(), DiskImageError> {
+        if options.sample_rate == 0 || options.slowdown <= 0.0 {
+            return Err(DiskImageError::ParameterError);
+        }
+        let track = self.get_track_ch(ch).ok_or(DiskImageError::SeekError)?;
+        let samples = waveform::render(&track.flux_revolution()?, options);
+        waveform::write_wav(&samples, options.sample_rate, out).map_err(|_| DiskImageError::IoError)
+    }
+
     pub fn is_id_valid(&self, chs: DiskChs) -> bool {
         if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
             return false;
@@ -895,7 +2795,7 @@ impl DiskImage {
         let track = &self.track_pool[ti];
 
         match &track {
-            TrackData::BitStream { .. } => return track.has_sector_id(chs.s()),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => return track.has_sector_id(chs.s()),
             TrackData::ByteStream { sectors, .. } => {
                 for si in sectors {
                     if si.sector_id == chs.s() {
@@ -917,11 +2817,26 @@ impl DiskImage {
             standard_format: self.standard_format,
             descriptor: self.descriptor,
             source_format: self.source_format,
+            source_metadata: std::mem::take(&mut self.source_metadata),
             resolution: self.resolution,
+            match_policy: self.match_policy,
+            crc_params: self.crc_params,
+            backup_policy: std::mem::take(&mut self.backup_policy),
+            original_bytes: self.original_bytes.take(),
+            backup_taken: self.backup_taken,
+            progress: std::mem::take(&mut self.progress),
+            random_seed: self.random_seed,
+            rng: self.rng.clone(),
             ..Default::default()
         }
     }
 
+    /// Format the whole disk as `format`, replacing all tracks of the image. Every track is
+    /// created anew and formatted with the sector layout of `format`, and a boot sector with a
+    /// BPB describing the format is written. The boot sector is built from `boot_sector` if
+    /// provided, or a built-in default otherwise, and `creator` sets its OEM name.
+    ///
+    /// An image with no resolution set is formatted as a ByteStream image.
     pub fn format(
         &mut self,
         format: StandardFormat,
@@ -933,8 +2848,13 @@ impl DiskImage {
         let data_rate = format.get_data_rate();
         let bitcell_size = format.get_bitcell_ct();
 
+        self.begin_write()?;
+
         // Drop all previous data as we will be overwriting the entire disk.
         self.reset_image();
+        self.standard_format = Some(format);
+        self.descriptor = format.get_descriptor();
+        self.resolution = Some(self.resolution());
 
         // Attempt to load the boot sector if provided, or fall back to our built-in default.
         let boot_sector_buf = boot_sector.unwrap_or(DEFAULT_BOOT_SECTOR);
@@ -958,9 +2878,12 @@ impl DiskImage {
         }
 
         // Format each track with the specified format
+        let total = chsn.h() as usize * chsn.c() as usize;
         for head in 0..chsn.h() {
             for cylinder in 0..chsn.c() {
                 let ch = DiskCh::new(cylinder, head);
+                self.progress
+                    .report("format", head as usize * chsn.c() as usize + cylinder as usize, total)?;
 
                 // Build the format buffer we provide to format_track() that specifies the sector
                 // layout parameters.
@@ -969,25 +2892,426 @@ impl DiskImage {
                     format_buffer.push(DiskChsn::new(ch.c(), ch.h(), s + 1, chsn.n()));
                 }
 
-                let gap3 = format.get_gap3();
-                self.format_track(ch, format_buffer, 0x00, gap3)?;
+                let gap3 = format.get_gap3();
+                self.format_track(ch, System34Standard::Iso, format_buffer, 0x00, gap3)?;
+            }
+        }
+
+        // Write the boot sector to the disk image
+        self.write_boot_sector(bootsector.as_bytes())?;
+        self.update_consistency();
+        self.progress.report("format", total, total)?;
+
+        Ok(())
+    }
+
+    /// Calculate how long a read of the sector identified by `chs` takes if started with the head
+    /// at the rotational position `from_position` (in bitcells from the index). If the sector ID
+    /// appears more than once on the track, the first occurrence to reach the head is used.
+    ///
+    /// Emulators can use this to schedule DMA and interrupt timing from the actual layout of the
+    /// track rather than a fixed delay.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::DataError)` if the sector was not found.
+    pub fn sector_read_time(&self, chs: DiskChs, from_position: usize) -> Result<SectorReadTime, DiskImageError> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+
+        self.track_pool[ti]
+            .sector_read_time(chs, None, self.match_policy, from_position)
+            .ok_or(DiskImageError::DataError)
+    }
+
+    /// Return the variable bit timing of the sector identified by `chs`, as recorded by image
+    /// formats that preserve it, such as Pasti STX. Each value is the relative read time of one
+    /// 16-byte block of the sector data.
+    ///
+    /// # Returns
+    /// - `Ok(None)` if the sector was recorded with regular timing.
+    /// - `Err(DiskImageError::SeekError)` if `chs` is outside the image.
+    /// - `Err(DiskImageError::DataError)` if the sector was not found.
+    pub fn sector_timing(&self, chs: DiskChs) -> Result<Option<&[u16]>, DiskImageError> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+        let track = &self.track_pool[self.track_map[chs.h() as usize][chs.c() as usize]];
+
+        if !track.has_sector_id(chs.s()) {
+            return Err(DiskImageError::DataError);
+        }
+        Ok(track.sector_timing(chs, None, self.match_policy))
+    }
+
+    /// Return the ID of the sector following the sector identified by `chs` on its track, in
+    /// rotational order. If the track contains duplicate sector IDs, the sector following the
+    /// first occurrence of the ID is returned; use [`DiskImage::get_next_id_at`] to visit every
+    /// sector header on a track.
+    pub fn get_next_id(&self, chs: DiskChs) -> Option<DiskChsn> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return None;
+        }
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        let track = &self.track_pool[ti];
+
+        track.get_next_id(chs)
+    }
+
+    /// Return the ID of the first valid sector header at or after the rotational position
+    /// `bit_index` (in bitcells from the index) on the track identified by `ch`, wrapping around
+    /// the track if necessary, along with the bitcell index of the header.
+    ///
+    /// Passing the returned index plus one back in will return the following sector header, so
+    /// all sector headers on a track can be visited in order, including duplicate IDs.
+    pub fn get_next_id_at(&self, ch: DiskCh, bit_index: usize) -> Option<(DiskChsn, usize)> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return None;
+        }
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        let track = &self.track_pool[ti];
+
+        track.get_next_id_at(bit_index)
+    }
+
+    /// Re-encode every track of the disk image for the specified data rate and rotation rate, for
+    /// example to export an image captured on a 360RPM drive for a 300RPM target.
+    ///
+    /// See [`TrackData::resample`] for details of how each track is regenerated. The image's
+    /// descriptor is updated to match. Tracks are resampled into copies that replace the originals
+    /// only once every track has succeeded, so the image is unchanged on error or cancellation.
+    pub fn resample(&mut self, new_rate: DiskDataRate, new_rpm: DiskRpm) -> Result<(), DiskImageError> {
+        let total = self.track_pool.len();
+        let mut resampled = Vec::with_capacity(total);
+        for (i, track) in self.track_pool.iter().enumerate() {
+            self.progress.report("resample", i, total)?;
+            let mut track = track.clone();
+            track.resample(new_rate, new_rpm)?;
+            resampled.push(track);
+        }
+        self.progress.report("resample", total, total)?;
+
+        self.begin_write()?;
+        self.track_pool = resampled;
+        self.descriptor.data_rate = new_rate;
+        self.descriptor.density = DiskDensity::from(new_rate);
+        self.descriptor.rpm = Some(new_rpm);
+        self.set_flag(DiskImageFlags::DIRTY);
+        Ok(())
+    }
+
+    /// Check that every track of the disk image can be written to a medium of the specified data
+    /// rate and, if `rpm` is provided, that no track is longer than a track on that medium. Data
+    /// rates are compared numerically, within [`DATA_RATE_TOLERANCE_PCT`].
+    ///
+    /// Exporters to formats that store a single data rate for the whole image use this to refuse
+    /// mixed-density images. Tracks recorded at a different rate can be converted with
+    /// [`DiskImage::resample`].
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleTracks)` listing the offending tracks.
+    pub fn check_medium(&self, data_rate: DiskDataRate, rpm: Option<DiskRpm>) -> Result<(), DiskImageError> {
+        // Allow for tracks written by drives running slightly slow.
+        let max_bitcells = rpm.map(|rpm| {
+            let nominal = rpm.track_bitcells(data_rate);
+            nominal + nominal * TRACK_LENGTH_TOLERANCE_PCT / 100
+        });
+
+        let target_rate = u32::from(data_rate);
+        let rate_tolerance = target_rate * DATA_RATE_TOLERANCE_PCT / 100;
+
+        let mut bad_tracks = Vec::new();
+        for track in self.track_iter() {
+            if u32::from(track.data_rate()).abs_diff(target_rate) > rate_tolerance {
+                log::error!(
+                    "check_medium(): Track {} data rate {} does not match target data rate {}",
+                    track.ch(),
+                    track.data_rate(),
+                    data_rate
+                );
+                bad_tracks.push(track.ch());
+            } else if max_bitcells.is_some_and(|max| track.bitcell_ct() > max) {
+                log::error!(
+                    "check_medium(): Track {} length of {} bitcells exceeds target track length of {} bitcells",
+                    track.ch(),
+                    track.bitcell_ct(),
+                    max_bitcells.unwrap_or(0)
+                );
+                bad_tracks.push(track.ch());
+            }
+        }
+
+        if bad_tracks.is_empty() {
+            Ok(())
+        } else {
+            Err(DiskImageError::IncompatibleTracks(bad_tracks))
+        }
+    }
+
+    /// Check that every track of the disk image can be written to the physical medium described
+    /// by `profile`. Tracks on a head the medium does not have are reported along with tracks of
+    /// the wrong data rate or length.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleTracks)` listing the offending tracks.
+    pub fn check_media_profile(&self, profile: MediaProfile) -> Result<(), DiskImageError> {
+        let mut bad_tracks = match self.check_medium(profile.data_rate(), Some(profile.rpm())) {
+            Ok(()) => Vec::new(),
+            Err(DiskImageError::IncompatibleTracks(tracks)) => tracks,
+            Err(e) => return Err(e),
+        };
+
+        for track in self.track_iter() {
+            if track.ch().h() >= profile.sides() && !bad_tracks.contains(&track.ch()) {
+                log::error!(
+                    "check_media_profile(): Track {} is not on a side of a {} diskette",
+                    track.ch(),
+                    profile
+                );
+                bad_tracks.push(track.ch());
+            }
+        }
+
+        if bad_tracks.is_empty() {
+            Ok(())
+        } else {
+            Err(DiskImageError::IncompatibleTracks(bad_tracks))
+        }
+    }
+
+    /// Return the ID of the sector matching `chs` under the current match policy, if found.
+    pub(crate) fn get_sector_id(&self, chs: DiskChs) -> Option<DiskChsn> {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return None;
+        }
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        let resolution = self.track_pool[ti].resolution();
+        self.track_pool[ti]
+            .get_sector_list()
+            .iter()
+            .find(|entry| self.match_policy.matches(entry.chsn, chs, None, resolution))
+            .map(|entry| entry.chsn)
+    }
+
+    /// Return true if the data of the sector matching `chs` contains weak bits.
+    pub(crate) fn sector_has_weak_bits(&self, chs: DiskChs) -> bool {
+        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+            return false;
+        }
+        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
+        self.track_pool[ti].sector_has_weak_bits(chs, None, self.match_policy)
+    }
+
+    /// Return a new disk image containing only the tracks of the cylinders in `cylinders`, on
+    /// `head` if specified or on all heads otherwise. The new image can be saved in any format
+    /// that can represent the original, for sharing a single protected track or building a
+    /// minimal test case.
+    ///
+    /// Tracks keep their physical position, so cylinders before the selection, and head 0 when
+    /// only head 1 is selected, are filled with unformatted tracks. Selecting only head 0 produces
+    /// a single-sided image. Sub-tracks between selected cylinders are kept.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the selection is empty or extends past the image.
+    pub fn extract_tracks(
+        &self,
+        cylinders: RangeInclusive<u16>,
+        head: Option<u8>,
+    ) -> Result<DiskImage, DiskImageError> {
+        let (start, end) = (*cylinders.start(), *cylinders.end());
+        let head_ct = if self.track_map[1].is_empty() { 1 } else { 2 };
+        if start > end
+            || head.is_some_and(|h| h >= head_ct)
+            || (0..head_ct as usize).any(|h| end as usize >= self.track_map[h].len())
+        {
+            return Err(DiskImageError::SeekError);
+        }
+        let head_ct = if head == Some(0) { 1 } else { head_ct };
+        let selected = |c: u16, h: u8| c >= start && head.is_none_or(|head| head == h);
+
+        let mut image = DiskImage {
+            descriptor: DiskDescriptor {
+                geometry: DiskCh::new(end + 1, head_ct),
+                ..self.descriptor
+            },
+            resolution: self.resolution,
+            comment: self.comment.clone(),
+            match_policy: self.match_policy,
+            crc_params: self.crc_params,
+            ..Default::default()
+        };
+
+        for c in 0..=end {
+            for h in 0..head_ct {
+                let track = &self.track_pool[self.track_map[h as usize][c as usize]];
+                if selected(c, h) {
+                    image.track_pool.push(track.clone());
+                    image.track_map[h as usize].push(image.track_pool.len() - 1);
+                    continue;
+                }
+
+                image.add_unformatted_track(track, DiskCh::new(c, h))?;
+            }
+        }
+
+        for h in 0..head_ct {
+            for (position, ti) in &self.sub_track_map[h as usize] {
+                if position.c() < end && selected(position.c(), h) {
+                    image.track_pool.push(self.track_pool[*ti].clone());
+                    image.sub_track_map[h as usize].insert(*position, image.track_pool.len() - 1);
+                }
+            }
+        }
+
+        if selected(0, 0) {
+            if let Ok(buf) = image.read_boot_sector() {
+                _ = image.parse_boot_sector(&buf);
+            }
+            image.volume_name = self.volume_name.clone();
+        }
+        image.update_consistency();
+        Ok(image)
+    }
+
+    /// Save the disk image in the specified format, applying `policy` to any feature of the image
+    /// that the format cannot represent, and return a report of what was dropped or altered.
+    ///
+    /// Unformatted cylinders at the end of the image are kept, trimmed or synthesized as set by
+    /// `policy.trailing_tracks` for every format. Otherwise, only raw sector images apply a
+    /// conversion policy per sector; other formats are saved as with [`ImageParser::save_image`],
+    /// and an empty report is returned. If such a format cannot store the image without data loss,
+    /// a policy with any [`ConvertAction::Fail`] action fails the save with
+    /// `DiskImageError::IncompatibleImage`, and one with any [`ConvertAction::ZeroFill`] action,
+    /// which cannot be applied, fails it with `DiskImageError::UnsupportedFormat`.
+    pub fn save_with_policy<RWS: ReadWriteSeek>(
+        &mut self,
+        format: DiskImageFormat,
+        policy: ConvertPolicy,
+        output: &mut RWS,
+    ) -> Result<ConvertReport, DiskImageError> {
+        let cylinder_ct = self.policy_cylinder_ct(format, &policy)?;
+        if cylinder_ct != self.track_map[0].len() {
+            log::debug!(
+                "save_with_policy(): Exporting {} cylinders of {}",
+                cylinder_ct,
+                self.track_map[0].len()
+            );
+            let mut image = self.with_cylinder_ct(cylinder_ct)?;
+            image.progress = std::mem::take(&mut self.progress);
+            let result = image.save_all_cylinders(format, policy, output);
+            self.progress = std::mem::take(&mut image.progress);
+            return result;
+        }
+        self.save_all_cylinders(format, policy, output)
+    }
+
+    fn save_all_cylinders<RWS: ReadWriteSeek>(
+        &mut self,
+        format: DiskImageFormat,
+        policy: ConvertPolicy,
+        output: &mut RWS,
+    ) -> Result<ConvertReport, DiskImageError> {
+        match format {
+            DiskImageFormat::RawSectorImage => RawFormat::save_image_with_policy(self, policy, output),
+            _ => {
+                if matches!(format.can_write(self), ParserWriteCompatibility::DataLoss) {
+                    if policy.has_action(ConvertAction::Fail) {
+                        log::error!(
+                            "save_with_policy(): {} cannot represent this image without loss.",
+                            format
+                        );
+                        return Err(DiskImageError::IncompatibleImage);
+                    }
+                    if policy.has_action(ConvertAction::ZeroFill) {
+                        log::error!("save_with_policy(): Zero fill cannot be applied to {}.", format);
+                        return Err(DiskImageError::UnsupportedFormat);
+                    }
+                }
+                format.save_image(self, output)?;
+                Ok(ConvertReport::default())
             }
         }
+    }
 
-        // Write the boot sector to the disk image
-        self.write_boot_sector(bootsector.as_bytes())?;
+    /// Save the disk image to the file at `path` if it has been modified, then clear the DIRTY
+    /// flag. The format is chosen by the file extension of `path`, falling back to the format the
+    /// image was loaded from.
+    ///
+    /// The save is atomic: the image is written to a temporary file in the same directory, which
+    /// then replaces `path`. If any step fails, the existing file at `path` is left untouched.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the image was written, `Ok(false)` if it was not dirty.
+    /// - `Err(DiskImageError::UnknownFormat)` if no output format could be determined.
+    pub fn flush_to(&mut self, path: impl AsRef<Path>) -> Result<bool, DiskImageError> {
+        if !self.has_flag(DiskImageFlags::DIRTY) {
+            return Ok(false);
+        }
 
-        Ok(())
+        let path = path.as_ref();
+        let format = path
+            .extension()
+            .and_then(|ext| format_from_ext(&ext.to_string_lossy()))
+            .or(self.source_format)
+            .ok_or(DiskImageError::UnknownFormat)?;
+
+        // Serialize the whole image first, so a format error never touches the filesystem.
+        let mut image_buf = Cursor::new(Vec::new());
+        self.save_with_policy(format, ConvertPolicy::default(), &mut image_buf)?;
+
+        let mut temp_name = path.file_name().ok_or(DiskImageError::ParameterError)?.to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let write_result = std::fs::File::create(&temp_path).and_then(|mut file| {
+            file.write_all(image_buf.get_ref())?;
+            file.sync_all()?;
+            std::fs::rename(&temp_path, path)
+        });
+
+        if let Err(e) = write_result {
+            log::error!("flush_to(): Failed to save image to {}: {}", path.display(), e);
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(DiskImageError::IoError);
+        }
+
+        self.clear_flag(DiskImageFlags::DIRTY);
+        Ok(true)
     }
 
-    pub fn get_next_id(&self, chs: DiskChs) -> Option<DiskChsn> {
-        if chs.h() > 1 || chs.c() as usize >= self.track_map[chs.h() as usize].len() {
+    /// Return the undecoded payload of the track identified by `ch` as it was read from the source
+    /// image file, along with its offset in the file. Returns `None` if the track does not exist,
+    /// the image was not loaded with [`LoadOptions::retain_track_sources`] set, or the loader for
+    /// the source format does not retain track payloads. Only the 86F, MFM and TransCopy loaders
+    /// currently retain them.
+    ///
+    /// The payload reflects the image as loaded and is not updated by subsequent writes.
+    pub fn track_source(&self, ch: DiskCh) -> Option<&TrackSource> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
             return None;
         }
-        let ti = self.track_map[chs.h() as usize][chs.c() as usize];
-        let track = &self.track_pool[ti];
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        self.track_pool[ti].source_bytes()
+    }
 
-        track.get_next_id(chs)
+    /// Attach the undecoded payload read from the source image file to the track identified by
+    /// `ch`. Called by file parsers after adding a track, handing over the buffer the track was
+    /// built from.
+    pub(crate) fn set_track_source(&mut self, ch: DiskCh, offset: u64, data: Vec<u8>) -> Result<(), DiskImageError> {
+        if ch.h() > 1 || ch.c() as usize >= self.track_map[ch.h() as usize].len() {
+            return Err(DiskImageError::SeekError);
+        }
+        let ti = self.track_map[ch.h() as usize][ch.c() as usize];
+        self.track_pool[ti].set_source(TrackSource { offset, data });
+        Ok(())
+    }
+
+    /// Discard the source payloads of all tracks.
+    pub(crate) fn clear_track_sources(&mut self) {
+        for track in self.track_pool.iter_mut() {
+            track.clear_source();
+        }
     }
 
     pub(crate) fn read_boot_sector(&mut self) -> Result<Vec<u8>, DiskImageError> {
@@ -995,9 +3319,16 @@ impl DiskImage {
             return Err(DiskImageError::IncompatibleImage);
         }
         let ti = self.track_map[0][0];
+        let first_sector_id = self.first_sector_id();
         let track = &mut self.track_pool[ti];
 
-        match track.read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, true) {
+        match track.read_sector(
+            DiskChs::new(0, 0, first_sector_id),
+            None,
+            RwSectorScope::DataOnly,
+            self.match_policy,
+            true,
+        ) {
             Ok(result) => Ok(result.read_buf),
             Err(e) => Err(e),
         }
@@ -1039,11 +3370,212 @@ impl DiskImage {
         Ok(())
     }
 
+    /// Install the boot code of the 512-byte boot sector `code` on the disk, keeping the disk's
+    /// BPB so that its filesystem remains readable. The volume serial number and label are kept
+    /// if `code` leaves space for them. If the disk's BPB is not valid, a BPB is generated from
+    /// its standard format.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `code` is not 512 bytes long.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk has no 512-byte boot sector, or has
+    ///   no valid BPB and no standard format.
+    pub fn install_boot_sector(&mut self, code: &[u8]) -> Result<(), DiskImageError> {
+        let code: &[u8; 512] = code.try_into().map_err(|_| DiskImageError::ParameterError)?;
+        let mut boot_sector = self.load_boot_sector()?;
+        if !boot_sector.has_valid_bpb() {
+            let format = self.standard_format.ok_or(DiskImageError::IncompatibleImage)?;
+            boot_sector.update_bpb_from_format(format)?;
+        }
+        boot_sector.replace_code(code);
+        self.store_boot_sector(boot_sector)
+    }
+
+    /// Return the volume serial number of a FAT disk, if its boot sector has an extended BPB.
+    pub fn volume_serial(&self) -> Option<u32> {
+        self.boot_sector.as_ref()?.volume_serial()
+    }
+
+    /// Set the volume serial number of a FAT disk to `serial`, or to a new random serial number
+    /// if `serial` is None. If the boot sector has no extended BPB to hold the serial number, one
+    /// is added if the boot code leaves space for it.
+    ///
+    /// # Returns
+    /// - `Ok(u32)` with the serial number set.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk has no 512-byte boot sector, or no
+    ///   space for an extended BPB.
+    pub fn set_volume_serial(&mut self, serial: Option<u32>) -> Result<u32, DiskImageError> {
+        let serial = serial.unwrap_or_else(|| self.rng.next_u32());
+        let mut boot_sector = self.load_boot_sector()?;
+        boot_sector.set_volume_serial(serial)?;
+        self.store_boot_sector(boot_sector)?;
+        Ok(serial)
+    }
+
+    /// Set the volume label of a FAT disk. The label is converted to upper case, and written to
+    /// the label entry of the root directory, which is created if needed, and to the extended BPB
+    /// of the boot sector if it has one or has space for one. An empty label removes the label.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `label` is longer than 11 characters, or
+    ///   contains characters not allowed in a FAT label.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk has no valid BPB.
+    /// - `Err(DiskImageError::DataError)` if the root directory is full.
+    pub fn set_volume_label(&mut self, label: &str) -> Result<(), DiskImageError> {
+        const INVALID: &[u8] = b"\"*+,./:;<=>?[\\]|";
+        if label.len() > 11
+            || label
+                .bytes()
+                .any(|b| !(b.is_ascii_graphic() || b == b' ') || INVALID.contains(&b))
+        {
+            return Err(DiskImageError::ParameterError);
+        }
+        let mut name = [b' '; 11];
+        name[..label.len()].copy_from_slice(label.to_ascii_uppercase().as_bytes());
+
+        let mut boot_sector = self.load_boot_sector()?;
+        if !boot_sector.has_valid_bpb() {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+        self.set_root_dir_label(&boot_sector, (!label.is_empty()).then_some(&name))?;
+
+        let ebpb_label = match label.is_empty() {
+            true => b"NO NAME    ",
+            false => &name,
+        };
+        if boot_sector.set_volume_label(ebpb_label).is_ok() {
+            self.store_boot_sector(boot_sector)?;
+        }
+        self.volume_name = label_string(&name);
+        Ok(())
+    }
+
+    /// Write `name` to the volume label entry of the FAT root directory described by the BPB of
+    /// `boot_sector`, using the first free entry if there is no label entry. If `name` is None,
+    /// the label entry is deleted.
+    fn set_root_dir_label(&mut self, boot_sector: &BootSector, name: Option<&[u8; 11]>) -> Result<(), DiskImageError> {
+        let (label, free) = self.find_root_dir_label(boot_sector)?;
+        let (lba, entry) = match (label, name) {
+            (Some(location), _) => location,
+            (None, Some(_)) => free.ok_or(DiskImageError::DataError)?,
+            (None, None) => return Ok(()),
+        };
+
+        let mut sector = self.read_lba(lba)?;
+        match name {
+            Some(name) if label.is_some() => sector[entry..entry + 11].copy_from_slice(name),
+            Some(name) => {
+                sector[entry..entry + DIR_ENTRY_LEN].fill(0);
+                sector[entry..entry + 11].copy_from_slice(name);
+                sector[entry + 11] = DIR_ATTR_VOLUME_ID;
+            }
+            None => sector[entry] = 0xE5,
+        }
+        self.write_lba(lba, &sector)
+    }
+
+    /// Search the FAT root directory described by the BPB of `boot_sector` for its volume label
+    /// entry.
+    ///
+    /// # Returns
+    /// - `Ok((label, free))` with the LBA and offset of the label entry, and of the first free
+    ///   entry, if found.
+    fn find_root_dir_label(&mut self, boot_sector: &BootSector) -> Result<DirEntrySearch, DiskImageError> {
+        let bpb = &boot_sector.bpb2;
+        let first = bpb.reserved_sectors as usize + bpb.number_of_fats as usize * bpb.sectors_per_fat as usize;
+        let sector_len = bpb.bytes_per_sector as usize;
+        let sector_ct = (bpb.root_entries as usize * DIR_ENTRY_LEN).div_ceil(sector_len);
+
+        let mut free = None;
+        for lba in first..first + sector_ct {
+            let sector = self.read_lba(lba)?;
+            for entry in (0..sector.len()).step_by(DIR_ENTRY_LEN) {
+                let attr = sector[entry + 11];
+                match sector[entry] {
+                    // The end of the directory.
+                    0x00 => return Ok((None, free.or(Some((lba, entry))))),
+                    0xE5 => {
+                        free.get_or_insert((lba, entry));
+                    }
+                    _ if attr & DIR_ATTR_VOLUME_ID != 0 && attr != DIR_ATTR_LONG_NAME => {
+                        return Ok((Some((lba, entry)), free));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok((None, free))
+    }
+
+    /// Read the volume name of the disk from its filesystem: the label entry of the root
+    /// directory or the label of the extended BPB of a FAT disk, as used by DOS and the Atari ST,
+    /// or the name in the root block of an AmigaDOS disk.
+    fn read_filesystem_label(&mut self) -> Option<String> {
+        let boot_sector = self.load_boot_sector().ok()?;
+        if boot_sector.as_bytes().starts_with(b"DOS") {
+            return self.read_amiga_label();
+        }
+        if !boot_sector.has_valid_bpb() {
+            return None;
+        }
+
+        let name = match self.find_root_dir_label(&boot_sector) {
+            Ok((Some((lba, entry)), _)) => {
+                let sector = self.read_lba(lba).ok()?;
+                let mut name: [u8; 11] = sector[entry..entry + 11].try_into().ok()?;
+                // A leading 0xE5 is stored as 0x05, as 0xE5 marks a deleted entry.
+                if name[0] == 0x05 {
+                    name[0] = 0xE5;
+                }
+                name
+            }
+            _ => boot_sector.volume_label().filter(|label| label != b"NO NAME    ")?,
+        };
+        label_string(&name)
+    }
+
+    /// Read the volume name from the root block of an AmigaDOS disk, at the middle of the disk.
+    fn read_amiga_label(&mut self) -> Option<String> {
+        // The primary type T_HEADER and secondary type ST_ROOT of a root block.
+        const T_HEADER: u32 = 2;
+        const ST_ROOT: u32 = 1;
+        const NAME_OFFSET: usize = 432;
+        const NAME_MAX: usize = 30;
+
+        let block_ct = self.nominal_geometry()?.get_sector_count() as usize;
+        let block = self.read_lba(block_ct / 2).ok()?;
+        let long = |offset: usize| u32::from_be_bytes(block[offset..offset + 4].try_into().unwrap());
+        if block.len() != 512 || long(0) != T_HEADER || long(508) != ST_ROOT {
+            return None;
+        }
+        let len = std::cmp::min(block[NAME_OFFSET] as usize, NAME_MAX);
+        label_string(&block[NAME_OFFSET + 1..NAME_OFFSET + 1 + len])
+    }
+
+    /// Read and parse the boot sector of the disk.
+    fn load_boot_sector(&mut self) -> Result<BootSector, DiskImageError> {
+        let buf = self.read_boot_sector()?;
+        if buf.len() != 512 {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+        BootSector::new(&mut Cursor::new(buf))
+    }
+
+    /// Write `boot_sector` to the disk, and keep it as the disk's parsed boot sector.
+    fn store_boot_sector(&mut self, boot_sector: BootSector) -> Result<(), DiskImageError> {
+        self.write_boot_sector(boot_sector.as_bytes())?;
+        self.boot_sector = Some(boot_sector);
+        Ok(())
+    }
+
     /// Called after loading a disk image to perform any post-load operations.
     pub(crate) fn post_load_process(&mut self) {
         // Normalize the disk image
         self.normalize();
 
+        // Most image formats don't record whether the disk has a consistent layout, so determine
+        // it from the tracks.
+        self.update_consistency();
+
         // Examine the boot sector if present. Use this to determine if this image is a standard
         // format disk image (but do not rely on this as the sole method of determining the disk
         // format)
@@ -1064,10 +3596,77 @@ impl DiskImage {
                 if self.standard_format.is_none() {
                     self.standard_format = Some(format);
                 } else if self.standard_format != Some(format) {
-                    log::warn!("post_load_process(): Boot sector format does not match image format.");
+                    self.add_load_warning(None, "Boot sector format does not match image format".to_string());
+                }
+            }
+        }
+
+        self.check_sector_crcs();
+
+        // Take the volume name from the filesystem, unless the image format recorded one.
+        if self.volume_name.is_none() {
+            self.volume_name = self.read_filesystem_label();
+        }
+    }
+
+    /// Add a load warning for each track with sectors that have bad address or data CRCs.
+    fn check_sector_crcs(&mut self) {
+        let mut warnings = Vec::new();
+        for head in 0..2 {
+            for (c, ti) in self.track_map[head].iter().enumerate() {
+                let sectors = self.track_pool[*ti].get_sector_list();
+                let bad_address = sectors.iter().filter(|s| !s.address_crc_valid).count();
+                let bad_data = sectors
+                    .iter()
+                    .filter(|s| s.address_crc_valid && !s.no_dam && !s.data_crc_valid)
+                    .count();
+                let ch = DiskCh::new(c as u16, head as u8);
+                if bad_address > 0 {
+                    warnings.push((ch, format!("{} sector(s) had bad header CRCs", bad_address)));
                 }
+                if bad_data > 0 {
+                    warnings.push((ch, format!("{} sector(s) had bad data CRCs", bad_data)));
+                }
+            }
+        }
+        for (ch, message) in warnings {
+            self.add_load_warning(Some(ch), message);
+        }
+    }
+
+    /// Classify the disk as DOS, Amiga, Atari ST, CP/M or PC-98 from its content: its boot
+    /// sector, its filesystem structures and the encoding and layout of its first track. The
+    /// report lists each piece of evidence found, so a caller can select a structure parser and
+    /// filesystem handler, or decide that the evidence is too weak to rely on.
+    pub fn detect_platform(&mut self) -> PlatformReport {
+        platform::detect(self)
+    }
+
+    /// Record the sector count and sector size shared by every track with sectors, if there are
+    /// such, in the consistency information of the image. Values already recorded by the image
+    /// format are kept.
+    fn update_consistency(&mut self) {
+        let mut track_lengths = BTreeSet::new();
+        let mut sector_sizes = BTreeSet::new();
+        for track in self.get_sector_map().iter().flatten() {
+            if !track.sectors.is_empty() {
+                track_lengths.insert(track.sectors.len());
+                sector_sizes.extend(track.sectors.iter().map(|s| s.chsn.n_size()));
             }
         }
+
+        if let (None, [len]) = (
+            self.consistency.consistent_track_length,
+            track_lengths.into_iter().collect::<Vec<_>>().as_slice(),
+        ) {
+            self.consistency.consistent_track_length = u8::try_from(*len).ok();
+        }
+        if let (None, [size]) = (
+            self.consistency.consistent_sector_size,
+            sector_sizes.into_iter().collect::<Vec<_>>().as_slice(),
+        ) {
+            self.consistency.consistent_sector_size = Some(*size as u32);
+        }
     }
 
     /// Retrieve the DOS boot sector of the disk image, if present.
@@ -1079,6 +3678,123 @@ impl DiskImage {
         self.track_map[head].len()
     }
 
+    /// Return the number of cylinders of the disk, not counting any overdumped cylinders beyond
+    /// the last cylinder of its format. This is the cylinder count of the disk's standard format
+    /// if known, or otherwise the standard count of 40, 77 or 80 cylinders closest to the number
+    /// of tracks in the image. Returns `None` if the image is not near a standard count.
+    pub fn nominal_cylinders(&self) -> Option<usize> {
+        if let Some(format) = self.standard_format {
+            return Some(format.get_chs().c() as usize);
+        }
+        match self.track_map[0].len() {
+            39..=50 => Some(40),
+            // 8" disks have 77 tracks.
+            77 => Some(77),
+            79..=90 => Some(80),
+            _ => None,
+        }
+    }
+
+    /// Return the number of cylinders to write when exporting to a format with a fixed geometry.
+    /// This is the cylinder count of the image's standard format if it has one, otherwise the
+    /// number of cylinders up to the last cylinder that holds sectors. Cylinders beyond the
+    /// standard format are written only if they hold sectors and `trim` is not set.
+    pub(crate) fn export_cylinder_ct(&self, trim: bool) -> usize {
+        let formatted = (0..self.track_map[0].len())
+            .rev()
+            .find(|&c| self.cylinder_has_sectors(c))
+            .map_or(0, |c| c + 1);
+        let Some(nominal) = self.standard_format.map(|format| format.get_chs().c() as usize) else {
+            return formatted;
+        };
+        if trim {
+            return nominal;
+        }
+        if formatted > nominal {
+            log::debug!(
+                "export_cylinder_ct(): Keeping {} overdumped cylinders with sectors",
+                formatted - nominal
+            );
+        }
+        formatted.max(nominal)
+    }
+
+    /// Return the number of cylinders to write when saving the image as `format` under `policy`.
+    fn policy_cylinder_ct(&self, format: DiskImageFormat, policy: &ConvertPolicy) -> Result<usize, DiskImageError> {
+        let cylinder_ct = self.track_map[0].len();
+        let export_ct = match policy.trailing_tracks {
+            TrailingTracks::Auto if matches!(format, DiskImageFormat::RawSectorImage) => {
+                return Ok(self.export_cylinder_ct(policy.trim_overdump));
+            }
+            TrailingTracks::Auto | TrailingTracks::Keep => cylinder_ct,
+            TrailingTracks::Trim => (0..cylinder_ct)
+                .rev()
+                .find(|&c| self.cylinder_has_sectors(c))
+                .map_or(1, |c| c + 1),
+            TrailingTracks::Synthesize => cylinder_ct.max(self.nominal_cylinders().unwrap_or(0)),
+        };
+        match self.nominal_cylinders() {
+            Some(nominal) if policy.trim_overdump => Ok(export_ct.min(nominal)),
+            _ => Ok(export_ct),
+        }
+    }
+
+    /// Return true if any track of cylinder `c` holds sectors.
+    fn cylinder_has_sectors(&self, c: usize) -> bool {
+        self.track_map
+            .iter()
+            .filter_map(|head| head.get(c))
+            .any(|&ti| self.track_pool[ti].get_sector_ct() > 0)
+    }
+
+    /// Return a copy of the image with exactly `cylinder_ct` cylinders. Cylinders past the count
+    /// are dropped, and missing cylinders are filled with unformatted tracks shaped like the last
+    /// track of each head.
+    fn with_cylinder_ct(&self, cylinder_ct: usize) -> Result<DiskImage, DiskImageError> {
+        let last = self.track_map[0]
+            .len()
+            .min(cylinder_ct)
+            .checked_sub(1)
+            .ok_or(DiskImageError::IncompatibleImage)?;
+        let mut image = self.extract_tracks(0..=last as u16, None)?;
+        image.standard_format = self.standard_format;
+        image.source_format = self.source_format;
+
+        let head_ct = image.descriptor.geometry.h();
+        for c in last + 1..cylinder_ct {
+            for h in 0..head_ct {
+                let template = &self.track_pool[self.track_map[h as usize][last]];
+                image.add_unformatted_track(template, DiskCh::new(c as u16, h))?;
+            }
+        }
+        image.descriptor.geometry = DiskCh::new(cylinder_ct as u16, head_ct);
+        image.update_consistency();
+        Ok(image)
+    }
+
+    /// Add an unformatted track at `ch` with the same resolution, encoding and bitcell count as
+    /// `template`.
+    fn add_unformatted_track(&mut self, template: &TrackData, ch: DiskCh) -> Result<(), DiskImageError> {
+        match template {
+            TrackData::ByteStream { .. } => {
+                self.add_track_bytestream(template.encoding(), template.data_rate(), ch)?;
+            }
+            _ => {
+                let bitcell_ct = template.bitcell_ct();
+                self.add_track_bitstream(
+                    template.encoding(),
+                    template.data_rate(),
+                    ch,
+                    u32::from(template.data_rate()),
+                    Some(bitcell_ct),
+                    &vec![0; bitcell_ct.div_ceil(8)],
+                    None,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     /// Normalize a disk image by detecting and correcting typical image issues.
     /// This includes:
     /// 40 track images encoded as 80 tracks with empty tracks
@@ -1285,7 +4001,7 @@ impl DiskImage {
                         }
                         *cylinder = logical_cylinder as u16;
                     }
-                    TrackData::BitStream { ref mut cylinder, .. } => {
+                    TrackData::BitStream { ref mut cylinder, .. } | TrackData::FluxStream { ref mut cylinder, .. } => {
                         if *cylinder != logical_cylinder as u16 {
                             log::trace!(
                                 "remap_tracks(): Remapping track idx {}, head: {} from c:{} to c:{}",
@@ -1311,13 +4027,18 @@ impl DiskImage {
         if let Some(comment) = &self.comment {
             out.write_fmt(format_args!("Comment: {:?}\n", comment))?;
         }
+        for (key, value) in &self.source_metadata {
+            out.write_fmt(format_args!("Metadata: {}: {:?}\n", key, value))?;
+        }
 
         out.write_fmt(format_args!("Data Rate: {}\n", self.descriptor.data_rate))?;
         out.write_fmt(format_args!("Data Encoding: {}\n", self.descriptor.data_encoding))?;
         Ok(())
     }
 
-    pub fn get_sector_map(&self) -> Vec<Vec<Vec<SectorMapEntry>>> {
+    /// Return a map of the disk image, indexed by head and then by cylinder. Each track entry
+    /// contains the track's encoding, data rate and length, and the list of sectors on the track.
+    pub fn get_sector_map(&self) -> Vec<Vec<TrackMapEntry>> {
         let mut head_map = Vec::new();
 
         let geom = self.geometry();
@@ -1327,8 +4048,7 @@ impl DiskImage {
             let mut track_map = Vec::new();
 
             for track_idx in &self.track_map[head as usize] {
-                let track = &self.track_pool[*track_idx];
-                track_map.push(track.get_sector_list());
+                track_map.push(TrackMapEntry::from(&self.track_pool[*track_idx]));
             }
 
             head_map.push(track_map);
@@ -1337,18 +4057,46 @@ impl DiskImage {
         head_map
     }
 
+    /// Return a list of the sub-tracks on each head, with their positions, in the same form as
+    /// [`DiskImage::get_sector_map`].
+    pub fn get_sub_track_map(&self) -> Vec<Vec<(QuarterTrack, TrackMapEntry)>> {
+        self.sub_track_map
+            .iter()
+            .map(|map| {
+                map.iter()
+                    .map(|(position, ti)| (*position, TrackMapEntry::from(&self.track_pool[*ti])))
+                    .collect()
+            })
+            .collect()
+    }
+
     pub fn dump_sector_map<W: crate::io::Write>(&self, mut out: W) -> Result<(), crate::io::Error> {
         let head_map = self.get_sector_map();
+        let sub_track_map = self.get_sub_track_map();
 
         for (head_idx, head) in head_map.iter().enumerate() {
             out.write_fmt(format_args!("Head {}\n", head_idx))?;
-            for (track_idx, track) in head.iter().enumerate() {
-                out.write_fmt(format_args!("\tTrack {}\n", track_idx))?;
-                for sector in track {
-                    out.write_fmt(format_args!(
-                        "\t\t{} address_crc_valid: {} data_crc_valid: {} deleted: {}\n",
-                        sector.chsn, sector.address_crc_valid, sector.data_crc_valid, sector.deleted_mark
-                    ))?;
+
+            // Merge sub-tracks into the list of whole tracks by position.
+            let mut tracks: Vec<(QuarterTrack, &TrackMapEntry)> = head
+                .iter()
+                .enumerate()
+                .map(|(track_idx, track)| (QuarterTrack::from(track_idx as u16), track))
+                .collect();
+            tracks.extend(
+                sub_track_map[head_idx]
+                    .iter()
+                    .map(|(position, track)| (*position, track)),
+            );
+            tracks.sort_by_key(|(position, _)| *position);
+
+            for (position, track) in tracks {
+                out.write_fmt(format_args!(
+                    "\tTrack {} {} {} bitcells: {} bytes: {}\n",
+                    position, track.encoding, track.data_rate, track.bitcells, track.len_bytes
+                ))?;
+                for sector in &track.sectors {
+                    out.write_fmt(format_args!("\t\t{}\n", sector))?;
                 }
             }
         }
@@ -1356,6 +4104,326 @@ impl DiskImage {
         Ok(())
     }
 
+    /// Dump the sector map, one line per track, using the compact sector format.
+    pub fn dump_sector_map_compact<W: crate::io::Write>(&self, mut out: W) -> Result<(), crate::io::Error> {
+        let head_map = self.get_sector_map();
+
+        for head in head_map.iter() {
+            for track in head.iter() {
+                out.write_fmt(format_args!("{} {}\n", track.ch, track))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return a report of the memory held by the disk image, itemized by track and by kind of data.
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            tracks: self
+                .track_pool
+                .iter()
+                .map(|track| (track.ch(), track.memory_usage()))
+                .collect(),
+            original_bytes: self.original_bytes.as_ref().map_or(0, |b| b.capacity()),
+        }
+    }
+
+    /// Build a best-of image from several dumps of the same disk. The layout of the result is
+    /// taken from the first image: its tracks, and the sectors on them. The data of each sector
+    /// is chosen by majority vote among the images that read it with a good CRC. If none did, a
+    /// majority vote of each bit of the sector's data and CRC is tried on BitStream images, which
+    /// may recover a sector damaged in a different place in each dump.
+    ///
+    /// The returned [`ConsensusReport`] records which image won each sector.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `images` is empty.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the images are not all of the same
+    ///   resolution.
+    pub fn consensus(images: &[DiskImage]) -> Result<(DiskImage, ConsensusReport), DiskImageError> {
+        consensus::build(images)
+    }
+
+    /// Compare this image against `other`, an independent capture of the same disk, to verify a
+    /// dump. Every sector found on either image is read from both and the results compared. A
+    /// sector that reads bad with identical data in both captures is counted as agreeing, since
+    /// it was most likely written bad on purpose.
+    ///
+    /// A dump should only be marked verified if [`VerifyReport::is_verified`] is true.
+    pub fn verify_dump(&self, other: &DiskImage) -> Result<VerifyReport, DiskImageError> {
+        verify::compare(self, other)
+    }
+
+    /// Decode every BitStream or FluxStream track of the image into a ByteStream track, and set
+    /// the image resolution to ByteStream. Only the sectors of each track are kept, with their
+    /// address CRC, data CRC and deleted status and any weak bits, so that sector reads no longer
+    /// need to decode the track. Gaps, sync fields and any data outside of sectors are discarded.
+    ///
+    /// The image is left unchanged if any track cannot be flattened.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the image has sub-tracks, or a track is not
+    ///   an MFM or FM System34 track.
+    pub fn flatten(&mut self) -> Result<(), DiskImageError> {
+        if self.has_sub_tracks() {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+
+        let track_pool = self
+            .track_pool
+            .iter()
+            .map(|track| track.to_bytestream())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.begin_write()?;
+        self.track_pool = track_pool;
+        self.resolution = Some(DiskDataResolution::ByteStream);
+        self.set_flag(DiskImageFlags::DIRTY);
+        Ok(())
+    }
+
+    /// Build a normalized copy of the disk image, so that images of the same disk produced by
+    /// different tools can be compared with [`DiskImage::get_hash`]. Each MFM BitStream track is
+    /// re-mastered from the index with gaps and sync fields of standard length, and the nominal
+    /// length for its data rate, keeping the ID, data and recorded CRCs of each sector in order.
+    /// Weak bits are zeroed, and kept marked as weak or cleared according to `policy`.
+    ///
+    /// ByteStream tracks keep their layout, with weak bits treated the same way. Tracks of other
+    /// encodings are copied unchanged.
+    pub fn canonicalize(&self, policy: WeakBitPolicy) -> Result<DiskImage, DiskImageError> {
+        canonical::build(self, policy)
+    }
+
+    /// Return a hash of the data of every track of the disk image, in order of head and cylinder.
+    /// Sub-tracks are not included.
+    pub fn get_hash(&self) -> Digest {
+        let mut hasher = sha1_smol::Sha1::new();
+        for ti in self.track_map.iter().flatten() {
+            hasher.update(&self.track_pool[*ti].get_hash().bytes());
+        }
+        hasher.digest()
+    }
+
+    /// Summarize the condition of the disk image as a [`HealthReport`], counting damaged and weak
+    /// sectors, and the sectors and tracks missing compared to the disk's format. If the format
+    /// is not known, the sectors per track are taken from the image if consistent, and no tracks
+    /// are considered missing. Sub-tracks are not included.
+    pub fn health(&self) -> HealthReport {
+        let (nominal, spt) = match self.standard_format {
+            Some(format) => (Some(DiskCh::from(format.get_chs())), format.get_chs().s()),
+            None => (None, self.consistency.consistent_track_length.unwrap_or(0)),
+        };
+
+        let mut report = HealthReport {
+            sectors_per_track: spt as usize,
+            ..Default::default()
+        };
+        let mut tracks = 0;
+        for track in self.get_sector_map().iter().flatten() {
+            tracks += 1;
+            let ti = self.track_map[track.ch.h() as usize][track.ch.c() as usize];
+            for sector in &track.sectors {
+                report.sectors += 1;
+                if self.track_pool[ti].sector_has_weak_bits(
+                    DiskChs::from(sector.chsn),
+                    Some(sector.chsn.n()),
+                    MatchPolicy::Chsn,
+                ) {
+                    report.weak_sectors += 1;
+                } else if !sector.address_crc_valid {
+                    report.address_crc_errors += 1;
+                } else if sector.no_dam {
+                    report.no_data += 1;
+                } else if !sector.data_crc_valid {
+                    report.data_crc_errors += 1;
+                }
+            }
+
+            // Tracks beyond the disk's format are extra, and not expected to hold any sectors.
+            if nominal.is_none_or(|g| track.ch.c() < g.c() && track.ch.h() < g.h()) {
+                report.missing_sectors += (1..=spt)
+                    .filter(|id| !track.sectors.iter().any(|s| s.chsn.s() == *id))
+                    .count();
+            }
+        }
+
+        if let Some(g) = nominal {
+            report.missing_tracks = (0..g.h() as usize)
+                .map(|h| (g.c() as usize).saturating_sub(self.track_map[h].len()))
+                .sum();
+            tracks = g.c() as usize * g.h() as usize;
+        }
+        report.expected_sectors = tracks * spt as usize;
+        report
+    }
+
+    /// Scan the sectors of the disk for compressed executables and archives, such as PKLITE and
+    /// LZEXE packed programs and ZIP and ARJ archives, reporting each as a [`PackedRegion`] of
+    /// sectors. Sectors are read in logical block order, as by [`DiskImage::read_lba`]; sectors
+    /// that cannot be read are scanned as zeros.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<PackedRegion>)` with the regions found, in logical block order.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the geometry of the disk is not known.
+    pub fn scan_packed(&mut self) -> Result<Vec<PackedRegion>, DiskImageError> {
+        let geometry = self.nominal_geometry().ok_or(DiskImageError::IncompatibleImage)?;
+        let sector_size = self.nominal_sector_size();
+        let lba_ct = geometry.get_sector_count() as usize;
+
+        let mut volume = Vec::with_capacity(lba_ct * sector_size);
+        for lba in 0..lba_ct {
+            match self.read_lba(lba) {
+                Ok(mut data) => {
+                    data.resize(sector_size, 0);
+                    volume.extend(data);
+                }
+                Err(e) => {
+                    log::debug!("scan_packed(): Couldn't read sector {}: {}", lba, e);
+                    volume.resize(volume.len() + sector_size, 0);
+                }
+            }
+        }
+        Ok(packed::scan(&volume, sector_size))
+    }
+
+    /// Scan the sectors of cylinder 0 for the classic boot sector viruses in
+    /// [`virus::SIGNATURES`]. Cylinder 0 holds the boot sector, FATs and root directory of every
+    /// standard DOS floppy format, which is where these viruses live and where several of them
+    /// hide the boot sector they replace. Sectors that cannot be read are skipped.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<VirusMatch>)` with the signatures found, in order of head and sector ID.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk has no cylinder 0.
+    pub fn scan_viruses(&mut self) -> Result<Vec<VirusMatch>, DiskImageError> {
+        if self.track_map[0].is_empty() {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+
+        let mut matches = Vec::new();
+        for h in 0..self.heads() {
+            let Some(track) = self.get_track_ch(DiskCh::new(0, h)) else {
+                continue;
+            };
+            let sector_ids = track
+                .get_sector_list()
+                .iter()
+                .map(|s| s.chsn.s())
+                .collect::<BTreeSet<_>>();
+
+            for s in sector_ids {
+                let chs = DiskChs::new(0, h, s);
+                match self.read_sector(chs, None, RwSectorScope::DataOnly, false) {
+                    Ok(rsr) => matches.extend(virus::scan_sector(chs, &rsr.read_buf)),
+                    Err(e) => log::debug!("scan_viruses(): Couldn't read sector {}: {}", chs, e),
+                }
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Restore a standard boot sector if the boot sector matches a virus signature, by installing
+    /// the default boot code with [`DiskImage::install_boot_sector`]. The disk's BPB is kept if
+    /// the virus left it intact. Only the boot sector is cleaned; the rest of a virus, and any
+    /// copy of the original boot sector it hid elsewhere on the disk, are left in place.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<VirusMatch>)` with the signatures found in the boot sector, which is only
+    ///   rewritten if there were any.
+    /// - `Err(DiskImageError::IncompatibleImage)` if the disk has no 512-byte boot sector, or the
+    ///   infected boot sector has no valid BPB and the disk has no standard format.
+    pub fn clean_boot_sector(&mut self) -> Result<Vec<VirusMatch>, DiskImageError> {
+        let chs = DiskChs::new(0, 0, self.first_sector_id());
+        let matches = virus::scan_sector(chs, &self.read_boot_sector()?);
+        if !matches.is_empty() {
+            for m in &matches {
+                log::info!("clean_boot_sector(): Removing {}", m);
+            }
+            self.install_boot_sector(DEFAULT_BOOT_SECTOR)?;
+        }
+        Ok(matches)
+    }
+
+    /// Render a grid of the sectors of the disk image with one row per track and one column per
+    /// sector ID, followed by [`SECTOR_MAP_LEGEND`]. The columns are every sector ID found on the
+    /// disk, plus the IDs expected by the disk's format so that a sector missing from every track
+    /// still shows up. Where a track has several sectors with the same ID, the worst is shown.
+    /// Sub-tracks are not included.
+    ///
+    /// If the grid would be wider than `width` characters, it is split into several blocks of
+    /// columns, one after the other.
+    pub fn render_sector_map_text(&self, width: usize) -> String {
+        let head_map = self.get_sector_map();
+
+        let expected_spt = self
+            .standard_format
+            .map(|format| format.get_chs().s())
+            .or(self.consistency.consistent_track_length)
+            .unwrap_or(0);
+        let mut id_set: BTreeSet<u8> = (1..=expected_spt).collect();
+        for track in head_map.iter().flatten() {
+            id_set.extend(track.sectors.iter().map(|s| s.chsn.s()));
+        }
+        let ids: Vec<u8> = id_set.into_iter().collect();
+
+        let c_digits = self.geometry().c().saturating_sub(1).to_string().len();
+        let label_width = c_digits + 3;
+        let id_digits = ids.last().map_or(1, |id| id.to_string().len());
+
+        let mut out = String::new();
+        for block in ids.chunks(width.saturating_sub(label_width).max(1)) {
+            // Sector IDs are written vertically, most significant digit first.
+            for digit in (0..id_digits as u32).rev() {
+                out.push_str(&" ".repeat(label_width));
+                for id in block {
+                    let place = 10u32.pow(digit);
+                    out.push(match digit == 0 || *id as u32 >= place {
+                        true => char::from_digit((*id as u32 / place) % 10, 10).unwrap_or(' '),
+                        false => ' ',
+                    });
+                }
+                out.push('\n');
+            }
+
+            for track in head_map.iter().flatten() {
+                out.push_str(&format!("{:>w$}:{} ", track.ch.c(), track.ch.h(), w = c_digits));
+                out.extend(block.iter().map(|id| self.sector_map_symbol(track, *id)));
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        out.push_str(SECTOR_MAP_LEGEND);
+        out.push('\n');
+        out
+    }
+
+    /// Return the symbol for sector `id` of `track` in the grid drawn by
+    /// [`DiskImage::render_sector_map_text`].
+    fn sector_map_symbol(&self, track: &TrackMapEntry, id: u8) -> char {
+        const SEVERITY: [char; 5] = ['-', '+', 'D', 'X', 'W'];
+
+        let ti = self.track_map[track.ch.h() as usize][track.ch.c() as usize];
+        let mut worst = 0;
+        for sector in track.sectors.iter().filter(|s| s.chsn.s() == id) {
+            // Weak bits usually cause a bad data CRC as well, so take precedence over it.
+            let symbol = if self.track_pool[ti].sector_has_weak_bits(
+                DiskChs::from(sector.chsn),
+                Some(sector.chsn.n()),
+                MatchPolicy::Chsn,
+            ) {
+                'W'
+            } else if !sector.address_crc_valid || !sector.data_crc_valid || sector.no_dam {
+                'X'
+            } else if sector.deleted_mark {
+                'D'
+            } else {
+                '+'
+            };
+            worst = worst.max(SEVERITY.iter().position(|c| *c == symbol).unwrap_or(0));
+        }
+        SEVERITY[worst]
+    }
+
     pub fn dump_sector_hex<W: crate::io::Write>(
         &mut self,
         chs: DiskChs,
@@ -1383,3 +4451,56 @@ impl DiskImage {
         false
     }
 }
+
+const DIR_ENTRY_LEN: usize = 32;
+const DIR_ATTR_VOLUME_ID: u8 = 0x08;
+const DIR_ATTR_LONG_NAME: u8 = 0x0F;
+
+/// The locations of a directory entry found in a search, and of the first free entry, as pairs
+/// of LBA and offset within the sector.
+type DirEntrySearch = (Option<(usize, usize)>, Option<(usize, usize)>);
+
+/// Convert a space padded volume label to a string, or None if it is blank. Characters outside
+/// of printable ASCII are replaced.
+fn label_string(name: &[u8]) -> Option<String> {
+    let label: String = name
+        .iter()
+        .map(|&b| match b {
+            0x20..0x7F => b as char,
+            _ => char::REPLACEMENT_CHARACTER,
+        })
+        .collect();
+    let label = label.trim_end();
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+/// Return the most common number of positions from each sector ID to the next in `order`,
+/// preferring the smaller spacing in a tie.
+fn layout_interleave(order: &[u8]) -> Option<usize> {
+    let n = order.len();
+    let position = |id: u8| order.iter().position(|s| *s == id);
+    let mut counts = BTreeMap::new();
+    for (pos, id) in order.iter().enumerate() {
+        if order[..pos].contains(id) {
+            continue;
+        }
+        if let Some(next) = id.checked_add(1).and_then(position) {
+            *counts.entry((next + n - pos) % n).or_insert(0usize) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|(d1, c1), (d2, c2)| c1.cmp(c2).then(d2.cmp(d1)))
+        .map(|(d, _)| d)
+}
+
+/// Return the number of positions the lowest sector ID of `from` is rotated in `to`.
+fn layout_skew(from: &[u8], to: &[u8]) -> Option<usize> {
+    if from.is_empty() || from.len() != to.len() {
+        return None;
+    }
+    let first = *from.iter().min()?;
+    let from_pos = from.iter().position(|s| *s == first)?;
+    let to_pos = to.iter().position(|s| *s == first)?;
+    Some((to_pos + to.len() - from_pos) % to.len())
+}
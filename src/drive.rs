@@ -0,0 +1,218 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/drive.rs
+
+    Optional modeling of the mechanical state of a floppy drive: motor
+    spin-up, head load and settle delays, and seek step timing.
+
+    A DriveState does not keep its own clock. The emulator passes its current
+    time, as a Duration since any fixed epoch, to each call, and is told when
+    the drive will be ready. This keeps the model deterministic and lets it be
+    driven by emulated rather than wall-clock time.
+*/
+
+use crate::media::MediaProfile;
+use std::time::Duration;
+
+/// The mechanical timing characteristics of a floppy drive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DriveTiming {
+    /// The time from the motor being switched on until the disk is rotating at speed.
+    pub spin_up: Duration,
+    /// The time for the head to load against the disk. Zero for drives whose heads are loaded
+    /// whenever the motor is on.
+    pub head_load: Duration,
+    /// The time for the head to stop vibrating after a seek.
+    pub head_settle: Duration,
+    /// The time to step the head by one cylinder.
+    pub step_rate: Duration,
+}
+
+impl DriveTiming {
+    /// Return typical timing for a drive reading the specified media.
+    pub fn for_profile(profile: MediaProfile) -> Self {
+        match profile {
+            MediaProfile::Dd5_25 => Self {
+                spin_up: Duration::from_millis(500),
+                head_load: Duration::ZERO,
+                head_settle: Duration::from_millis(15),
+                step_rate: Duration::from_millis(6),
+            },
+            MediaProfile::Hd5_25 => Self {
+                spin_up: Duration::from_millis(500),
+                head_load: Duration::ZERO,
+                head_settle: Duration::from_millis(15),
+                step_rate: Duration::from_millis(3),
+            },
+            MediaProfile::Dd3_5 | MediaProfile::Hd3_5 | MediaProfile::Ed3_5 => Self {
+                spin_up: Duration::from_millis(500),
+                head_load: Duration::ZERO,
+                head_settle: Duration::from_millis(15),
+                step_rate: Duration::from_millis(3),
+            },
+            MediaProfile::Sd8 | MediaProfile::Dd8 => Self {
+                spin_up: Duration::from_millis(1000),
+                head_load: Duration::from_millis(35),
+                head_settle: Duration::from_millis(10),
+                step_rate: Duration::from_millis(8),
+            },
+            MediaProfile::Amstrad3 => Self {
+                spin_up: Duration::from_millis(1000),
+                head_load: Duration::ZERO,
+                head_settle: Duration::from_millis(15),
+                step_rate: Duration::from_millis(12),
+            },
+        }
+    }
+}
+
+/// The modeled mechanical state of a single floppy drive. All times are Durations since an epoch
+/// chosen by the caller.
+#[derive(Clone, Debug)]
+pub struct DriveState {
+    profile: MediaProfile,
+    timing: DriveTiming,
+    motor_on_at: Option<Duration>,
+    head_loaded_at: Option<Duration>,
+    cylinder: u16,
+    seek_done_at: Duration,
+}
+
+impl DriveState {
+    /// Create a new drive with the typical timing for `profile`. The motor is off and the head is
+    /// at cylinder 0.
+    pub fn new(profile: MediaProfile) -> Self {
+        Self::with_timing(profile, DriveTiming::for_profile(profile))
+    }
+
+    pub fn with_timing(profile: MediaProfile, timing: DriveTiming) -> Self {
+        Self {
+            profile,
+            timing,
+            motor_on_at: None,
+            head_loaded_at: None,
+            cylinder: 0,
+            seek_done_at: Duration::ZERO,
+        }
+    }
+
+    pub fn profile(&self) -> MediaProfile {
+        self.profile
+    }
+
+    pub fn timing(&self) -> &DriveTiming {
+        &self.timing
+    }
+
+    pub fn cylinder(&self) -> u16 {
+        self.cylinder
+    }
+
+    /// Switch the motor on or off at time `now`. Switching on a motor that is already running has
+    /// no effect. Switching the motor off also unloads the head.
+    pub fn set_motor(&mut self, on: bool, now: Duration) {
+        match (on, self.motor_on_at) {
+            (true, None) => self.motor_on_at = Some(now),
+            (false, _) => {
+                self.motor_on_at = None;
+                self.head_loaded_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn motor_on(&self) -> bool {
+        self.motor_on_at.is_some()
+    }
+
+    /// Return the time at which the disk will be rotating at speed, or None if the motor is off.
+    pub fn spun_up_at(&self) -> Option<Duration> {
+        self.motor_on_at.map(|t| t + self.timing.spin_up)
+    }
+
+    /// Load the head at time `now`. Loading an already loaded head has no effect.
+    pub fn load_head(&mut self, now: Duration) {
+        if self.head_loaded_at.is_none() {
+            self.head_loaded_at = Some(now);
+        }
+    }
+
+    pub fn unload_head(&mut self) {
+        self.head_loaded_at = None;
+    }
+
+    /// Begin a seek to `cylinder` at time `now`. Each step takes the drive's step rate, and the
+    /// head settle delay is added at the end of a seek that moved the head.
+    ///
+    /// Returns the time at which the seek completes.
+    pub fn seek(&mut self, cylinder: u16, now: Duration) -> Duration {
+        let steps = self.cylinder.abs_diff(cylinder) as u32;
+        // A new seek can't start until the previous one has finished.
+        let start = now.max(self.seek_done_at);
+        self.seek_done_at = if steps > 0 {
+            start + self.timing.step_rate * steps + self.timing.head_settle
+        } else {
+            start
+        };
+        self.cylinder = cylinder;
+        self.seek_done_at
+    }
+
+    /// Return the time at which the drive can next read or write: the motor is at speed, the head
+    /// is loaded and settled, and any seek has completed. Returns None if the motor is off.
+    pub fn ready_at(&self) -> Option<Duration> {
+        let spun_up = self.spun_up_at()?;
+        let head_ready = self
+            .head_loaded_at
+            .map(|t| t + self.timing.head_load)
+            .unwrap_or(Duration::ZERO);
+        Some(spun_up.max(head_ready).max(self.seek_done_at))
+    }
+
+    /// Return true if the drive can read or write at time `now`.
+    pub fn is_ready(&self, now: Duration) -> bool {
+        self.ready_at().is_some_and(|t| now >= t)
+    }
+
+    /// Return the time remaining at `now` until the drive is ready, or None if the motor is off.
+    pub fn operation_delay(&self, now: Duration) -> Option<Duration> {
+        self.ready_at().map(|t| t.saturating_sub(now))
+    }
+
+    /// Return the rotational position of the disk at time `now`, as a bitcell index into a track
+    /// of `track_bitcells` bitcells. The index hole is taken to pass the sensor at the moment the
+    /// motor was switched on. Returns None if the motor is off.
+    ///
+    /// Combined with [`crate::DiskImage::sector_read_time`], this gives the time at which a
+    /// sector read started at `now` will complete.
+    pub fn bit_position(&self, now: Duration, track_bitcells: usize) -> Option<usize> {
+        let motor_on_at = self.motor_on_at?;
+        let period_ns = 60_000_000_000u128 / u32::from(self.profile.rpm()) as u128;
+        let elapsed_ns = now.saturating_sub(motor_on_at).as_nanos();
+        Some(((elapsed_ns % period_ns) * track_bitcells as u128 / period_ns) as usize)
+    }
+}
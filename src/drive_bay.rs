@@ -0,0 +1,259 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/drive_bay.rs
+
+    A DriveBay manages the set of floppy drives attached to an emulated
+    controller, usually two to four, and the DiskImages mounted in them.
+
+    It tracks write-protect and disk change state per drive, queues insert
+    and eject events for the emulator to consume, and centralizes flushing
+    modified images back to storage.
+*/
+
+use crate::diskimage::DiskImageFlags;
+use crate::drive::DriveState;
+use crate::media::MediaProfile;
+use crate::{DiskImage, DiskImageError};
+use std::path::Path;
+
+/// An event generated by a change to the media in a [`DriveBay`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DriveEvent {
+    /// A disk was inserted into the drive.
+    Inserted(usize),
+    /// A disk was ejected from the drive. `dirty` is set if the disk had unsaved modifications.
+    Ejected { drive: usize, dirty: bool },
+    /// The write-protect state of the drive changed.
+    WriteProtect(usize, bool),
+}
+
+/// A single drive in a [`DriveBay`].
+pub struct Drive {
+    /// The mechanical state of the drive.
+    pub state: DriveState,
+    image: Option<DiskImage>,
+    write_protect: bool,
+    disk_changed: bool,
+}
+
+impl Drive {
+    fn new(profile: MediaProfile) -> Self {
+        Self {
+            state: DriveState::new(profile),
+            image: None,
+            write_protect: false,
+            // Drives report a disk change at power on.
+            disk_changed: true,
+        }
+    }
+
+    pub fn profile(&self) -> MediaProfile {
+        self.state.profile()
+    }
+
+    pub fn image(&self) -> Option<&DiskImage> {
+        self.image.as_ref()
+    }
+
+    pub fn image_mut(&mut self) -> Option<&mut DiskImage> {
+        self.image.as_mut()
+    }
+
+    pub fn has_disk(&self) -> bool {
+        self.image.is_some()
+    }
+
+    /// Return true if the drive is write protected. An empty drive is reported as write protected,
+    /// as most drives do.
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protect || self.image.is_none()
+    }
+
+    /// Return the state of the drive's disk change line. It is set when a disk is inserted or
+    /// ejected and stays set until cleared by [`DriveBay::clear_disk_change`].
+    pub fn disk_changed(&self) -> bool {
+        self.disk_changed
+    }
+
+    /// Return true if the mounted image has been modified since it was last flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.image
+            .as_ref()
+            .is_some_and(|image| image.has_flag(DiskImageFlags::DIRTY))
+    }
+}
+
+/// A [`DriveBay`] holds a set of drives, each of which may have a [`DiskImage`] inserted.
+pub struct DriveBay {
+    drives: Vec<Drive>,
+    events: Vec<DriveEvent>,
+}
+
+impl DriveBay {
+    /// Create a drive bay with one empty drive for each profile in `profiles`.
+    pub fn new(profiles: &[MediaProfile]) -> Self {
+        Self {
+            drives: profiles.iter().map(|&profile| Drive::new(profile)).collect(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.drives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drives.is_empty()
+    }
+
+    pub fn drive(&self, drive: usize) -> Option<&Drive> {
+        self.drives.get(drive)
+    }
+
+    pub fn drive_mut(&mut self, drive: usize) -> Option<&mut Drive> {
+        self.drives.get_mut(drive)
+    }
+
+    pub fn drives(&self) -> impl Iterator<Item = &Drive> {
+        self.drives.iter()
+    }
+
+    /// Return the image inserted in `drive`, if any.
+    pub fn image(&self, drive: usize) -> Option<&DiskImage> {
+        self.drives.get(drive)?.image()
+    }
+
+    /// Return the image inserted in `drive` for modification. Returns None if the drive is empty.
+    /// Callers should check [`Drive::is_write_protected`] before writing.
+    pub fn image_mut(&mut self, drive: usize) -> Option<&mut DiskImage> {
+        self.drives.get_mut(drive)?.image_mut()
+    }
+
+    /// Insert `image` into `drive`, ejecting any disk already present. The write-protect state is
+    /// taken from the image's READONLY flag.
+    ///
+    /// # Returns
+    /// - The previously inserted image, if any.
+    /// - `Err(DiskImageError::ParameterError)` if `drive` does not exist.
+    pub fn insert(&mut self, drive: usize, image: DiskImage) -> Result<Option<DiskImage>, DiskImageError> {
+        let ejected = self.eject(drive)?;
+
+        let write_protect = image.has_flag(DiskImageFlags::READONLY);
+        let d = &mut self.drives[drive];
+        d.image = Some(image);
+        d.disk_changed = true;
+        self.events.push(DriveEvent::Inserted(drive));
+        self.set_write_protect(drive, write_protect)?;
+
+        Ok(ejected)
+    }
+
+    /// Eject the disk in `drive`, returning it. The caller is responsible for saving the image if
+    /// it is dirty.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `drive` does not exist.
+    pub fn eject(&mut self, drive: usize) -> Result<Option<DiskImage>, DiskImageError> {
+        let d = self.drives.get_mut(drive).ok_or(DiskImageError::ParameterError)?;
+        let dirty = d.is_dirty();
+        let ejected = d.image.take();
+        if ejected.is_some() {
+            d.disk_changed = true;
+            self.events.push(DriveEvent::Ejected { drive, dirty });
+        }
+        Ok(ejected)
+    }
+
+    /// Save the disk in `drive` to `path` if it has been modified, then eject it. The save is
+    /// atomic, as with [`DiskImage::flush_to`]. If the save fails, the disk remains inserted.
+    ///
+    /// # Returns
+    /// - The ejected image, if the drive held one.
+    /// - `Err(DiskImageError::ParameterError)` if `drive` does not exist.
+    pub fn eject_to(&mut self, drive: usize, path: impl AsRef<Path>) -> Result<Option<DiskImage>, DiskImageError> {
+        let d = self.drives.get_mut(drive).ok_or(DiskImageError::ParameterError)?;
+        if let Some(image) = d.image.as_mut() {
+            image.flush_to(path)?;
+        }
+        self.eject(drive)
+    }
+
+    /// Set the write-protect state of `drive`.
+    pub fn set_write_protect(&mut self, drive: usize, write_protect: bool) -> Result<(), DiskImageError> {
+        let d = self.drives.get_mut(drive).ok_or(DiskImageError::ParameterError)?;
+        if d.write_protect != write_protect {
+            d.write_protect = write_protect;
+            self.events.push(DriveEvent::WriteProtect(drive, write_protect));
+        }
+        Ok(())
+    }
+
+    /// Clear the disk change line of `drive`. Drives clear the line when the head is stepped with
+    /// a disk present.
+    pub fn clear_disk_change(&mut self, drive: usize) {
+        if let Some(d) = self.drives.get_mut(drive) {
+            if d.has_disk() {
+                d.disk_changed = false;
+            }
+        }
+    }
+
+    /// Return the events generated since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<DriveEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Return the indices of drives holding images with unsaved modifications.
+    pub fn dirty_drives(&self) -> Vec<usize> {
+        self.drives
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.is_dirty())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Call `save` for each drive holding a modified image, clearing the image's DIRTY flag if the
+    /// save succeeds. Stops at the first error, leaving that and any remaining images dirty.
+    ///
+    /// # Returns
+    /// - The number of images saved.
+    pub fn flush<F>(&mut self, mut save: F) -> Result<usize, DiskImageError>
+    where
+        F: FnMut(usize, &DiskImage) -> Result<(), DiskImageError>,
+    {
+        let mut saved = 0;
+        for drive in self.dirty_drives() {
+            if let Some(image) = self.drives[drive].image.as_mut() {
+                save(drive, image)?;
+                image.clear_flag(DiskImageFlags::DIRTY);
+                saved += 1;
+            }
+        }
+        Ok(saved)
+    }
+}
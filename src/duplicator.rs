@@ -0,0 +1,314 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/duplicator.rs
+
+    Detection of the mastering machine that produced a disk, from the layout
+    of its tracks.
+*/
+
+use crate::bitstream::mfm::MFM_BYTE_LEN;
+use crate::timeline::TimelineElement;
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskImage};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// The number of bytes at the start of each gap skipped when sampling the gap fill byte, as the
+/// bytes following a CRC are often disturbed by the write splice.
+const GAP_SKIP: usize = 2;
+/// The number of bytes of each gap sampled for the gap fill byte.
+const GAP_SAMPLE: usize = 16;
+
+/// The layout features of a single track that identify how it was written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackFingerprint {
+    pub ch: DiskCh,
+    /// Whether the track has an index address mark.
+    pub iam: bool,
+    /// The length of GAP4A before the IAM, if present.
+    pub gap4a: Option<usize>,
+    /// The length of the gap before the sync field of the first sector: from the end of the IAM
+    /// if present, otherwise from the index.
+    pub gap1: usize,
+    /// The offset of the sync field of the first sector from the index, in bytes.
+    pub first_sector: usize,
+    /// The most common byte found in the gaps of the track.
+    pub gap_byte: Option<u8>,
+}
+
+/// The layout features of a disk, summarized from the fingerprints of its tracks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiskFingerprint {
+    pub tracks: Vec<TrackFingerprint>,
+}
+
+impl DiskFingerprint {
+    /// Return true if most tracks have an index address mark.
+    pub fn iam(&self) -> bool {
+        self.tracks.iter().filter(|t| t.iam).count() * 2 > self.tracks.len()
+    }
+
+    /// Return the most common GAP4A length among tracks with an IAM.
+    pub fn gap4a(&self) -> Option<usize> {
+        most_common(self.tracks.iter().filter_map(|t| t.gap4a))
+    }
+
+    /// Return the most common GAP1 length.
+    pub fn gap1(&self) -> Option<usize> {
+        most_common(self.tracks.iter().map(|t| t.gap1))
+    }
+
+    /// Return the most common gap fill byte.
+    pub fn gap_byte(&self) -> Option<u8> {
+        most_common(self.tracks.iter().filter_map(|t| t.gap_byte))
+    }
+
+    /// Return true if the first sector of every track starts within a byte of the same offset
+    /// from the index, as when every track is written starting at the index pulse.
+    pub fn index_aligned(&self) -> bool {
+        let offsets = self.tracks.iter().map(|t| t.first_sector);
+        match (offsets.clone().min(), offsets.max()) {
+            (Some(min), Some(max)) => max - min <= 1,
+            _ => false,
+        }
+    }
+}
+
+/// The characteristic track layout of a mastering machine or formatter. Each criterion that is
+/// `Some` must be met by a disk for the signature to match.
+#[derive(Clone, Debug)]
+pub struct DuplicatorSignature {
+    pub name: &'static str,
+    pub iam: Option<bool>,
+    pub gap4a: Option<RangeInclusive<usize>>,
+    pub gap1: Option<RangeInclusive<usize>>,
+    pub gap_byte: Option<u8>,
+    pub index_aligned: Option<bool>,
+}
+
+impl DuplicatorSignature {
+    /// Return the number of criteria of the signature met by `fingerprint`, and the number of
+    /// criteria the signature specifies.
+    pub fn score(&self, fingerprint: &DiskFingerprint) -> (usize, usize) {
+        let checks = [
+            self.iam.map(|iam| iam == fingerprint.iam()),
+            self.gap4a
+                .as_ref()
+                .map(|r| fingerprint.gap4a().is_some_and(|g| r.contains(&g))),
+            self.gap1
+                .as_ref()
+                .map(|r| fingerprint.gap1().is_some_and(|g| r.contains(&g))),
+            self.gap_byte.map(|b| fingerprint.gap_byte() == Some(b)),
+            self.index_aligned.map(|a| a == fingerprint.index_aligned()),
+        ];
+        (
+            checks.iter().filter(|c| **c == Some(true)).count(),
+            checks.iter().filter(|c| c.is_some()).count(),
+        )
+    }
+}
+
+/// The built-in signatures checked by [`DiskImage::duplicator_report`].
+///
+/// The two controller formats are exact: they are the layouts written by the Format Track command
+/// of a standard floppy disk controller. The duplicator signatures are heuristics. Mastering
+/// machines wrote each track in one pass starting at the index, without the long gap before the
+/// first sector that a controller leaves for the IAM: Formaster machines omitted the IAM, while
+/// Trace machines wrote it after a shortened GAP4A. They separate a mastered disk from one
+/// formatted in a PC more reliably than they separate the machines from each other.
+pub fn builtin_signatures() -> Vec<DuplicatorSignature> {
+    vec![
+        DuplicatorSignature {
+            name: "IBM System 34 format (controller)",
+            iam: Some(true),
+            gap4a: Some(80..=80),
+            gap1: Some(50..=50),
+            gap_byte: Some(0x4E),
+            index_aligned: Some(true),
+        },
+        DuplicatorSignature {
+            name: "ISO format (controller)",
+            iam: Some(false),
+            gap4a: None,
+            gap1: Some(32..=32),
+            gap_byte: Some(0x4E),
+            index_aligned: Some(true),
+        },
+        DuplicatorSignature {
+            name: "Formaster",
+            iam: Some(false),
+            gap4a: None,
+            gap1: Some(0..=31),
+            gap_byte: Some(0x4E),
+            index_aligned: Some(true),
+        },
+        DuplicatorSignature {
+            name: "Trace",
+            iam: Some(true),
+            gap4a: Some(0..=79),
+            gap1: None,
+            gap_byte: Some(0x4E),
+            index_aligned: Some(true),
+        },
+    ]
+}
+
+/// How well a [`DuplicatorSignature`] matches a disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureMatch {
+    pub name: &'static str,
+    /// The number of criteria met.
+    pub matched: usize,
+    /// The number of criteria the signature specifies.
+    pub criteria: usize,
+}
+
+impl SignatureMatch {
+    /// Return true if every criterion of the signature was met.
+    pub fn is_full(&self) -> bool {
+        self.matched == self.criteria
+    }
+}
+
+/// The result of matching a disk against a set of duplicator signatures, as returned by
+/// [`DiskImage::duplicator_report`].
+#[derive(Clone, Debug, Default)]
+pub struct DuplicatorReport {
+    pub fingerprint: DiskFingerprint,
+    /// Every signature, from the best match to the worst.
+    pub matches: Vec<SignatureMatch>,
+}
+
+impl DuplicatorReport {
+    /// Match `fingerprint` against `signatures`.
+    pub fn new(fingerprint: DiskFingerprint, signatures: &[DuplicatorSignature]) -> Self {
+        let mut matches: Vec<SignatureMatch> = signatures
+            .iter()
+            .map(|sig| {
+                let (matched, criteria) = sig.score(&fingerprint);
+                SignatureMatch {
+                    name: sig.name,
+                    matched,
+                    criteria,
+                }
+            })
+            .collect();
+        // Order by the fraction of criteria met, then by the number met.
+        matches.sort_by(|a, b| {
+            (b.matched * a.criteria.max(1))
+                .cmp(&(a.matched * b.criteria.max(1)))
+                .then(b.matched.cmp(&a.matched))
+        });
+        DuplicatorReport { fingerprint, matches }
+    }
+
+    /// Return the signature that fully matches the disk, if any.
+    pub fn best(&self) -> Option<&SignatureMatch> {
+        self.matches
+            .first()
+            .filter(|m| m.is_full() && !self.fingerprint.tracks.is_empty())
+    }
+}
+
+impl Display for DuplicatorReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let fp = &self.fingerprint;
+        let opt = |v: Option<usize>| v.map_or("-".to_string(), |v| v.to_string());
+        writeln!(
+            f,
+            "{} tracks: IAM: {} GAP4A: {} GAP1: {} gap byte: {} index aligned: {}",
+            fp.tracks.len(),
+            fp.iam(),
+            opt(fp.gap4a()),
+            opt(fp.gap1()),
+            fp.gap_byte().map_or("-".to_string(), |b| format!("{:02X}", b)),
+            fp.index_aligned()
+        )?;
+        match self.best() {
+            Some(m) => writeln!(f, "Signature: {}", m.name),
+            None => writeln!(f, "Signature: unknown"),
+        }
+    }
+}
+
+/// Fingerprint the MFM BitStream tracks of `image` that hold at least one sector.
+pub(crate) fn fingerprint(image: &DiskImage) -> DiskFingerprint {
+    let mut tracks = Vec::new();
+    for (h, cylinders) in image.track_map.iter().enumerate() {
+        for c in 0..cylinders.len() {
+            let ch = DiskCh::new(c as u16, h as u8);
+            if let Some(fingerprint) = fingerprint_track(image, ch) {
+                tracks.push(fingerprint);
+            }
+        }
+    }
+    DiskFingerprint { tracks }
+}
+
+fn fingerprint_track(image: &DiskImage, ch: DiskCh) -> Option<TrackFingerprint> {
+    let gaps = image.track_gaps(ch).ok()?;
+    if gaps.sectors.is_empty() {
+        return None;
+    }
+    let index_mark = image.index_mark(ch).ok()?;
+    let timeline = image.track_timeline(ch).ok()?;
+    let (TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. }) = image.get_track_ch(ch)? else {
+        return None;
+    };
+
+    let mut counts = BTreeMap::new();
+    for span in timeline.spans.iter().filter(|s| s.element == TimelineElement::Gap) {
+        for i in (GAP_SKIP..span.byte_len()).take(GAP_SAMPLE) {
+            if let Some(byte) = data.read_decoded_byte(span.start + i * MFM_BYTE_LEN) {
+                *counts.entry(byte).or_insert(0usize) += 1;
+            }
+        }
+    }
+    let gap_byte = counts.into_iter().max_by_key(|(_, count)| *count).map(|(byte, _)| byte);
+
+    Some(TrackFingerprint {
+        ch,
+        iam: index_mark.is_present(),
+        gap4a: index_mark.gap4a,
+        gap1: index_mark.gap1.unwrap_or(gaps.gap1),
+        first_sector: gaps.gap1,
+        gap_byte,
+    })
+}
+
+/// Return the most common value, preferring the smaller value in a tie.
+fn most_common<T: Ord + Copy>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts = BTreeMap::new();
+    for v in values {
+        *counts.entry(v).or_insert(0usize) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(v1, c1), (v2, c2)| c1.cmp(c2).then(v2.cmp(v1)))
+        .map(|(v, _)| v)
+}
@@ -0,0 +1,295 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/fdc.rs
+
+    Helpers to translate the status of fluxfox sector operations into the
+    status registers of common floppy disk controllers, so that emulators
+    do not each need to maintain their own mapping.
+
+    Two controller families are supported:
+
+        µPD765 (and compatibles such as the Intel 8272 and the PC's 82077AA)
+            ST0, ST1 and ST2 result bytes.
+        WD177x/WD179x
+            Type II/III command status register bits.
+
+    A sector that could not be found at all is reported by the DiskImage as
+    an error rather than a result. Use the `not_found()` constructors for
+    this case.
+
+    The WD-specific Read Address, Read Track and Write Track behaviors are
+    implemented by DiskImage::read_address(), read_track_raw() and
+    write_track().
+*/
+
+use crate::diskimage::{ReadAddressResult, ReadSectorResult, ReadTrackResult, WriteSectorResult, WriteTrackResult};
+use crate::drive::DriveState;
+
+/// ST0: Interrupt code - abnormal termination.
+pub const ST0_ABNORMAL_TERMINATION: u8 = 0b0100_0000;
+/// ST0: Not ready - the drive was not ready when the command was issued.
+pub const ST0_NOT_READY: u8 = 0b0000_1000;
+/// ST0: Head address at the time of the interrupt.
+pub const ST0_HEAD: u8 = 0b0000_0100;
+/// ST0: Unit select bits.
+pub const ST0_UNIT_SELECT: u8 = 0b0000_0011;
+
+/// ST1: End of cylinder.
+pub const ST1_END_OF_CYLINDER: u8 = 0b1000_0000;
+/// ST1: Data error - a CRC error in the ID field or the data field.
+pub const ST1_DATA_ERROR: u8 = 0b0010_0000;
+/// ST1: No data - the requested sector could not be found.
+pub const ST1_NO_DATA: u8 = 0b0000_0100;
+/// ST1: Not writable - the disk is write protected.
+pub const ST1_NOT_WRITABLE: u8 = 0b0000_0010;
+/// ST1: Missing address mark.
+pub const ST1_MISSING_ADDRESS_MARK: u8 = 0b0000_0001;
+
+/// ST2: Control mark - a data address mark of the type not requested was encountered.
+pub const ST2_CONTROL_MARK: u8 = 0b0100_0000;
+/// ST2: Data error in data field - a CRC error in the data field.
+pub const ST2_DATA_ERROR_IN_DATA: u8 = 0b0010_0000;
+/// ST2: Wrong cylinder - the cylinder in the sector ID did not match.
+pub const ST2_WRONG_CYLINDER: u8 = 0b0001_0000;
+/// ST2: Missing address mark in data field.
+pub const ST2_MISSING_DATA_MARK: u8 = 0b0000_0001;
+
+/// WD177x/WD179x: Busy.
+pub const WD_BUSY: u8 = 0b0000_0001;
+/// WD177x/WD179x: Lost data.
+pub const WD_LOST_DATA: u8 = 0b0000_0100;
+/// WD177x/WD179x: CRC error in the ID field or the data field.
+pub const WD_CRC_ERROR: u8 = 0b0000_1000;
+/// WD177x/WD179x: Record not found.
+pub const WD_RECORD_NOT_FOUND: u8 = 0b0001_0000;
+/// WD177x/WD179x: Record type - set when a deleted data mark was read.
+pub const WD_RECORD_TYPE: u8 = 0b0010_0000;
+/// WD177x/WD179x: Write protect.
+pub const WD_WRITE_PROTECT: u8 = 0b0100_0000;
+/// WD177x: Motor on. On the WD179x this bit is Not Ready.
+pub const WD_MOTOR_ON: u8 = 0b1000_0000;
+
+/// The result bytes ST0, ST1 and ST2 of a µPD765 read or write command.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Upd765Status {
+    pub st0: u8,
+    pub st1: u8,
+    pub st2: u8,
+}
+
+impl Upd765Status {
+    fn new(head: u8, unit: u8, st1: u8, st2: u8) -> Self {
+        let mut st0 = ((head & 0x01) << 2) | (unit & ST0_UNIT_SELECT);
+        if st1 != 0 || st2 != 0 {
+            st0 |= ST0_ABNORMAL_TERMINATION;
+        }
+        Self { st0, st1, st2 }
+    }
+
+    /// Return the status of a Read Data or Read Deleted Data command on physical `head` of drive
+    /// `unit`.
+    pub fn from_read_sector(result: &ReadSectorResult, head: u8, unit: u8) -> Self {
+        let mut st1 = 0;
+        let mut st2 = 0;
+
+        if result.not_found || result.wrong_cylinder || result.wrong_head {
+            st1 |= ST1_NO_DATA;
+        }
+        if result.wrong_cylinder {
+            st2 |= ST2_WRONG_CYLINDER;
+        }
+        if result.address_crc_error {
+            st1 |= ST1_DATA_ERROR;
+        }
+        if result.data_crc_error {
+            st1 |= ST1_DATA_ERROR;
+            st2 |= ST2_DATA_ERROR_IN_DATA;
+        }
+        if result.control_mark {
+            st2 |= ST2_CONTROL_MARK;
+        }
+        if result.no_dam {
+            st1 |= ST1_MISSING_ADDRESS_MARK;
+            st2 |= ST2_MISSING_DATA_MARK;
+        }
+
+        Self::new(head, unit, st1, st2)
+    }
+
+    /// Return the status of a Read Track command on physical `head` of drive `unit`.
+    pub fn from_read_track(result: &ReadTrackResult, head: u8, unit: u8) -> Self {
+        let mut st1 = 0;
+        let mut st2 = 0;
+
+        if result.not_found {
+            st1 |= ST1_NO_DATA;
+        }
+        if result.address_crc_error {
+            st1 |= ST1_DATA_ERROR;
+        }
+        if result.data_crc_error {
+            st1 |= ST1_DATA_ERROR;
+            st2 |= ST2_DATA_ERROR_IN_DATA;
+        }
+
+        Self::new(head, unit, st1, st2)
+    }
+
+    /// Return the status of a Write Data command on physical `head` of drive `unit`.
+    /// A write to a write-protected disk fails before any sector is searched for.
+    pub fn from_write_sector(result: &WriteSectorResult, head: u8, unit: u8, write_protect: bool) -> Self {
+        if write_protect {
+            return Self::new(head, unit, ST1_NOT_WRITABLE, 0);
+        }
+
+        let mut st1 = 0;
+        let mut st2 = 0;
+
+        if result.not_found || result.wrong_cylinder || result.wrong_head {
+            st1 |= ST1_NO_DATA;
+        }
+        if result.wrong_cylinder {
+            st2 |= ST2_WRONG_CYLINDER;
+        }
+        if result.address_crc_error {
+            st1 |= ST1_DATA_ERROR;
+        }
+
+        Self::new(head, unit, st1, st2)
+    }
+
+    /// Return the status of a command whose sector could not be found on the track. If
+    /// `no_marks` is set, no address marks were found at all.
+    pub fn not_found(head: u8, unit: u8, no_marks: bool) -> Self {
+        let st1 = if no_marks {
+            ST1_NO_DATA | ST1_MISSING_ADDRESS_MARK
+        } else {
+            ST1_NO_DATA
+        };
+        Self::new(head, unit, st1, 0)
+    }
+
+    /// Return the status of a command issued to a drive that was not ready, for example because
+    /// its motor was still spinning up. See [`DriveState::is_ready`].
+    pub fn not_ready(head: u8, unit: u8) -> Self {
+        let mut status = Self::new(head, unit, 0, 0);
+        status.st0 |= ST0_ABNORMAL_TERMINATION | ST0_NOT_READY;
+        status
+    }
+
+    /// Return true if the command terminated abnormally.
+    pub fn is_error(&self) -> bool {
+        self.st0 & ST0_ABNORMAL_TERMINATION != 0
+    }
+}
+
+/// The status register of a WD177x/WD179x controller at the completion of a Type II (Read/Write
+/// Sector) or Type III (Read Track) command. The Busy and DRQ bits are always clear. The Motor On
+/// bit can be set from a [`DriveState`] with [`Wd177xStatus::with_motor`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Wd177xStatus(pub u8);
+
+impl Wd177xStatus {
+    pub fn from_read_sector(result: &ReadSectorResult) -> Self {
+        let mut status = 0;
+        // The WD177x reports a missing data address mark as Record Not Found.
+        if result.not_found || result.wrong_cylinder || result.wrong_head || result.no_dam {
+            status |= WD_RECORD_NOT_FOUND;
+        }
+        if result.address_crc_error || result.data_crc_error {
+            status |= WD_CRC_ERROR;
+        }
+        if result.deleted_mark {
+            status |= WD_RECORD_TYPE;
+        }
+        Self(status)
+    }
+
+    pub fn from_read_track(result: &ReadTrackResult) -> Self {
+        // Read Track performs no CRC checking on the WD177x, so only a missing track is reported.
+        if result.not_found {
+            Self(WD_RECORD_NOT_FOUND)
+        } else {
+            Self(0)
+        }
+    }
+
+    pub fn from_write_sector(result: &WriteSectorResult, write_protect: bool) -> Self {
+        if write_protect {
+            return Self(WD_WRITE_PROTECT);
+        }
+        let mut status = 0;
+        if result.not_found || result.wrong_cylinder || result.wrong_head {
+            status |= WD_RECORD_NOT_FOUND;
+        }
+        if result.address_crc_error {
+            status |= WD_CRC_ERROR;
+        }
+        Self(status)
+    }
+
+    pub fn from_read_address(result: &ReadAddressResult) -> Self {
+        if result.address_crc_error {
+            Self(WD_CRC_ERROR)
+        } else {
+            Self(0)
+        }
+    }
+
+    pub fn from_write_track(result: &WriteTrackResult, write_protect: bool) -> Self {
+        if write_protect {
+            Self(WD_WRITE_PROTECT)
+        } else if result.lost_data {
+            Self(WD_LOST_DATA)
+        } else {
+            Self(0)
+        }
+    }
+
+    /// Return the status of a command whose sector could not be found on the track.
+    pub fn not_found() -> Self {
+        Self(WD_RECORD_NOT_FOUND)
+    }
+
+    /// Set the Motor On bit from the state of `drive`.
+    pub fn with_motor(self, drive: &DriveState) -> Self {
+        if drive.motor_on() {
+            Self(self.0 | WD_MOTOR_ON)
+        } else {
+            Self(self.0 & !WD_MOTOR_ON)
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Return true if any error bit is set.
+    pub fn is_error(&self) -> bool {
+        self.0 & (WD_LOST_DATA | WD_CRC_ERROR | WD_RECORD_NOT_FOUND | WD_WRITE_PROTECT) != 0
+    }
+}
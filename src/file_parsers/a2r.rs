@@ -0,0 +1,578 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/a2r.rs
+
+    A parser for the A2R v2 and v3 formats.
+
+    A2R images store the raw flux captures made by the Applesauce. Like WOZ,
+    the file is a series of chunks following an 8-byte header. Version 2 images
+    hold their captures in STRM chunks, timed in 125ns ticks, with an estimate
+    of the loop point of each capture. Version 3 images hold them in RWCP
+    chunks, with a capture resolution and the times of the index signals seen
+    during each capture.
+
+    Timing data is stored as one byte per flux interval. A byte of 255 adds 255
+    ticks to the following byte.
+
+    Captures are split into revolutions at their index signals, or at their
+    loop point, and loaded as FluxStream tracks. For 5.25" drives, captures are
+    made at each quarter-track; only those at whole tracks are loaded.
+
+    The INFO and META chunks are exposed through the image's source metadata.
+*/
+
+use std::collections::BTreeMap;
+
+use crate::bitstream::gcr::GCR_SECTOR_SIZE;
+use crate::diskimage::{DiskDescriptor, ParseMode};
+use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
+use crate::flux::FluxRevolution;
+use crate::io::{Cursor, ReadSeek, ReadWriteSeek};
+use crate::{
+    DiskCh, DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, DiskRpm,
+    DEFAULT_SECTOR_SIZE,
+};
+use binrw::{binrw, BinRead};
+
+pub const A2R_HEADER_LEN: usize = 8;
+pub const A2R_CHUNK_HEADER_LEN: usize = 8;
+/// The period of the capture clock of version 2 images, in seconds.
+pub const A2R_V2_TICK: f64 = 125e-9;
+/// Marks the end of the captures in a STRM chunk.
+pub const A2R_STRM_END: u8 = 0xFF;
+/// Marks a capture in a RWCP chunk.
+pub const A2R_RWCP_CAPTURE: u8 = b'C';
+/// Marks the end of the captures in a RWCP chunk.
+pub const A2R_RWCP_END: u8 = b'X';
+
+pub const A2R_CAPTURE_TIMING: u8 = 1;
+#[allow(dead_code)]
+pub const A2R_CAPTURE_BITS: u8 = 2;
+pub const A2R_CAPTURE_XTIMING: u8 = 3;
+
+pub const A2R_DRIVE_525_SS_QUARTER: u8 = 1;
+pub const A2R_DRIVE_35_DS_CLV: u8 = 2;
+pub const A2R_DRIVE_525_DS_80: u8 = 3;
+pub const A2R_DRIVE_525_DS_40: u8 = 4;
+pub const A2R_DRIVE_35_DS_80: u8 = 5;
+pub const A2R_DRIVE_8_DS: u8 = 6;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rFileHeader {
+    pub id: [u8; 4],
+    pub high_bit: u8,
+    pub line_ends: [u8; 3],
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rChunkHeader {
+    pub id: [u8; 4],
+    pub size: u32,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rInfoChunk {
+    pub version: u8,
+    pub creator: [u8; 32],
+    pub drive_type: u8,
+    pub write_protected: u8,
+    pub synchronized: u8,
+}
+
+/// The header of a capture in a STRM chunk, followed by its timing data.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rStrmCapture {
+    pub location: u8,
+    pub capture_type: u8,
+    pub data_len: u32,
+    /// The estimated duration of one revolution of the capture, in ticks.
+    pub loop_point: u32,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rRwcpHeader {
+    pub version: u8,
+    /// The period of the capture clock, in picoseconds.
+    pub resolution: u32,
+    pub reserved: [u8; 11],
+}
+
+/// The header of a capture in a RWCP chunk, following its capture mark.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct A2rRwcpCapture {
+    pub capture_type: u8,
+    pub location: u16,
+    pub index_ct: u8,
+    /// The times at which index signals were seen, in ticks from the start of the capture.
+    #[br(count = index_ct)]
+    pub index_ticks: Vec<u32>,
+    pub data_len: u32,
+}
+
+pub struct A2rFormat;
+
+impl A2rFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::A2rImage
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["a2r"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        bitstream_flags()
+            | FormatCaps::CAP_TRACK_ENCODING
+            | FormatCaps::CAP_TRACK_DATA_RATE
+            | FormatCaps::CAP_ENCODING_FM
+            | FormatCaps::CAP_ENCODING_MFM
+            | FormatCaps::CAP_ENCODING_GCR
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        match A2rFileHeader::read(&mut image) {
+            Ok(header) => (header.id == *b"A2R2" || header.id == *b"A2R3") && header.high_bit == 0xFF,
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+
+        let mut cursor = Cursor::new(&image_data);
+        let header = A2rFileHeader::read(&mut cursor).map_err(|_| DiskImageError::UnknownFormat)?;
+        match &header.id {
+            b"A2R2" | b"A2R3" => {}
+            b"A2R1" => {
+                log::error!("A2R version 1 images are not supported.");
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+            _ => return Err(DiskImageError::UnknownFormat),
+        }
+
+        let mut info = None;
+        // Revolutions captured at each location, in capture order.
+        let mut captures: BTreeMap<u16, Vec<FluxRevolution>> = BTreeMap::new();
+        let mut chunk_offset = A2R_HEADER_LEN;
+        while chunk_offset + A2R_CHUNK_HEADER_LEN <= image_data.len() {
+            cursor.set_position(chunk_offset as u64);
+            let chunk = A2rChunkHeader::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+            let data_start = chunk_offset + A2R_CHUNK_HEADER_LEN;
+            let Some(chunk_data) = image_data.get(data_start..data_start + chunk.size as usize) else {
+                disk_image.spec_violation(
+                    None,
+                    format!(
+                        "Chunk {} extends beyond end of image",
+                        String::from_utf8_lossy(&chunk.id)
+                    ),
+                )?;
+                break;
+            };
+
+            log::trace!(
+                "load_image(): Chunk {} at offset {:X}, size {}",
+                String::from_utf8_lossy(&chunk.id),
+                chunk_offset,
+                chunk.size
+            );
+            match &chunk.id {
+                b"INFO" => {
+                    let info_chunk = A2rInfoChunk::read(&mut Cursor::new(chunk_data))
+                        .map_err(|_| DiskImageError::FormatParseError)?;
+                    // Version 3 adds the number of hard sectors of the disk.
+                    let hard_sectors = chunk_data.get(36).copied().unwrap_or(0);
+                    a2r_info_metadata(&mut disk_image, &info_chunk, hard_sectors);
+                    info = Some(info_chunk);
+                }
+                b"META" => {
+                    a2r_meta_metadata(&mut disk_image, chunk_data);
+                }
+                b"STRM" => {
+                    a2r_read_strm(&mut disk_image, chunk_data, &mut captures)?;
+                }
+                b"RWCP" => {
+                    a2r_read_rwcp(&mut disk_image, chunk_data, &mut captures)?;
+                }
+                _ => {}
+            }
+            chunk_offset = data_start + chunk.size as usize;
+        }
+
+        let Some(info) = info else {
+            log::error!("Image is missing a required INFO chunk.");
+            return Err(DiskImageError::FormatParseError);
+        };
+
+        log::trace!(
+            "load_image(): A2R INFO version: {} drive type: {} creator: {}",
+            info.version,
+            info.drive_type,
+            String::from_utf8_lossy(&info.creator).trim_end()
+        );
+
+        // The PLL is configured by the data rate of MFM, which has two bitcells per data bit; the
+        // 4us and 2us GCR bitcells of Apple drives are resolved as 125Kbps and 250Kbps.
+        let (encoding, gcr_rate, disk_rpm) = match info.drive_type {
+            A2R_DRIVE_525_SS_QUARTER => (
+                DiskDataEncoding::Gcr,
+                Some(DiskDataRate::Rate125Kbps),
+                Some(DiskRpm::Rpm300),
+            ),
+            // Apple 3.5" drives vary their speed by zone, which has no DiskRpm.
+            A2R_DRIVE_35_DS_CLV => (DiskDataEncoding::Gcr, Some(DiskDataRate::Rate250Kbps), None),
+            A2R_DRIVE_525_DS_80 | A2R_DRIVE_525_DS_40 | A2R_DRIVE_35_DS_80 | A2R_DRIVE_8_DS => {
+                (DiskDataEncoding::Mfm, None, None)
+            }
+            _ => {
+                log::error!("Unsupported drive type: {}", info.drive_type);
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+        };
+
+        // Revolutions captured at each whole track, by cylinder and head.
+        let mut tracks: BTreeMap<(u16, u8), Vec<FluxRevolution>> = BTreeMap::new();
+        for (location, revolutions) in captures {
+            let (c, h) = match info.drive_type {
+                A2R_DRIVE_525_SS_QUARTER if location % 4 != 0 => {
+                    log::trace!("load_image(): Skipping capture at quarter-track {}", location);
+                    continue;
+                }
+                A2R_DRIVE_525_SS_QUARTER => (location / 4, 0),
+                _ => (location >> 1, (location & 1) as u8),
+            };
+            tracks.insert((c, h), revolutions);
+        }
+
+        let Some(last_cylinder) = tracks.keys().map(|&(c, _)| c).max() else {
+            log::error!("Image contains no tracks.");
+            return Err(DiskImageError::FormatParseError);
+        };
+        let head_ct = if tracks.keys().any(|&(_, h)| h == 1) { 2 } else { 1 };
+
+        let mut rate_counts: Vec<(DiskDataRate, usize)> = Vec::new();
+        let mut track_data_rate = gcr_rate.unwrap_or_default();
+        let mut flux_rpm = None;
+        for c in 0..=last_cylinder {
+            for h in 0..head_ct {
+                let ch = DiskCh::new(c, h);
+                let revolutions = tracks.remove(&(c, h)).unwrap_or_default();
+                let data_rate = match revolutions.first() {
+                    Some(flux) if flux.transition_ct() > 0 => {
+                        if flux_rpm.is_none() {
+                            flux_rpm = flux.rpm();
+                        }
+                        gcr_rate.or_else(|| flux.estimate_data_rate())
+                    }
+                    _ => None,
+                };
+
+                match data_rate {
+                    Some(data_rate) => {
+                        log::trace!(
+                            "load_image(): Adding {:?} track {}: {} revolutions at {}",
+                            encoding,
+                            ch,
+                            revolutions.len(),
+                            data_rate
+                        );
+                        track_data_rate = data_rate;
+                        match rate_counts.iter_mut().find(|(rate, _)| *rate == data_rate) {
+                            Some((_, count)) => *count += 1,
+                            None => rate_counts.push((data_rate, 1)),
+                        }
+                        disk_image.add_track_fluxstream(encoding, data_rate, ch, revolutions)?;
+                    }
+                    None => {
+                        // Tracks between captured tracks, or with no flux transitions, are
+                        // unformatted. Add them as empty tracks of nominal length to keep the
+                        // track map contiguous.
+                        disk_image.add_load_warning(
+                            Some(ch),
+                            "No flux transitions captured, added as an unformatted track".to_string(),
+                        );
+                        let bitcell_ct = disk_rpm.unwrap_or_default().track_bitcells(track_data_rate);
+                        disk_image.add_track_bitstream(
+                            encoding,
+                            track_data_rate,
+                            ch,
+                            track_data_rate.into(),
+                            Some(bitcell_ct),
+                            &vec![0; bitcell_ct.div_ceil(8)],
+                            None,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        let disk_data_rate = rate_counts
+            .iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(rate, _)| *rate)
+            .unwrap_or(track_data_rate);
+        let sector_size = match info.drive_type {
+            A2R_DRIVE_525_SS_QUARTER => GCR_SECTOR_SIZE,
+            _ => DEFAULT_SECTOR_SIZE,
+        };
+
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(last_cylinder + 1, head_ct),
+            data_rate: disk_data_rate,
+            data_encoding: encoding,
+            density: DiskDensity::from(disk_data_rate),
+            default_sector_size: sector_size,
+            rpm: disk_rpm.or(flux_rpm),
+            write_protect: Some(info.write_protected != 0),
+        };
+
+        Ok(disk_image)
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
+
+/// Add the fields of the INFO chunk to the source metadata of `disk_image`.
+fn a2r_info_metadata(disk_image: &mut DiskImage, info: &A2rInfoChunk, hard_sectors: u8) {
+    let drive = match info.drive_type {
+        A2R_DRIVE_525_SS_QUARTER => "5.25\" SS 40-track, quarter-track steps",
+        A2R_DRIVE_35_DS_CLV => "3.5\" DS 80-track, Apple CLV",
+        A2R_DRIVE_525_DS_80 => "5.25\" DS 80-track",
+        A2R_DRIVE_525_DS_40 => "5.25\" DS 40-track",
+        A2R_DRIVE_35_DS_80 => "3.5\" DS 80-track",
+        A2R_DRIVE_8_DS => "8\" DS",
+        _ => "Unknown",
+    };
+
+    let metadata = &mut disk_image.source_metadata;
+    metadata.insert(
+        "creator".to_string(),
+        String::from_utf8_lossy(&info.creator).trim_end().to_string(),
+    );
+    metadata.insert("drive_type".to_string(), drive.to_string());
+    metadata.insert("write_protected".to_string(), (info.write_protected != 0).to_string());
+    metadata.insert("synchronized".to_string(), (info.synchronized != 0).to_string());
+    if hard_sectors > 0 {
+        metadata.insert("hard_sectors".to_string(), hard_sectors.to_string());
+    }
+}
+
+/// Add the rows of the META chunk to the source metadata of `disk_image`. Each row is a key and a
+/// value separated by a tab.
+fn a2r_meta_metadata(disk_image: &mut DiskImage, data: &[u8]) {
+    for row in String::from_utf8_lossy(data).lines() {
+        match row.split_once('\t') {
+            Some((key, value)) if !key.is_empty() => {
+                disk_image.source_metadata.insert(key.to_string(), value.to_string());
+            }
+            _ if row.is_empty() => {}
+            _ => {
+                disk_image.add_load_warning(None, format!("Malformed META row: {:?}", row));
+            }
+        }
+    }
+}
+
+/// Read the captures of a version 2 STRM chunk into `captures`.
+fn a2r_read_strm(
+    disk_image: &mut DiskImage,
+    data: &[u8],
+    captures: &mut BTreeMap<u16, Vec<FluxRevolution>>,
+) -> Result<(), DiskImageError> {
+    let mut cursor = Cursor::new(data);
+    while let Some(&location) = data.get(cursor.position() as usize) {
+        if location == A2R_STRM_END {
+            break;
+        }
+        let capture = A2rStrmCapture::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+        let start = cursor.position() as usize;
+        let Some(timing) = data.get(start..start + capture.data_len as usize) else {
+            disk_image.spec_violation(
+                None,
+                format!("Capture at location {} extends beyond end of chunk", location),
+            )?;
+            break;
+        };
+        cursor.set_position((start + timing.len()) as u64);
+
+        if !matches!(capture.capture_type, A2R_CAPTURE_TIMING | A2R_CAPTURE_XTIMING) {
+            log::debug!(
+                "a2r_read_strm(): Skipping capture of type {} at location {}",
+                capture.capture_type,
+                location
+            );
+            continue;
+        }
+
+        let intervals = a2r_read_timing(timing);
+        let total = intervals.iter().map(|&i| i as u64).sum::<u64>();
+        let loop_point = capture.loop_point as u64;
+        let boundaries = match loop_point {
+            0 => Vec::new(),
+            _ => (0..=total / loop_point).map(|k| k * loop_point).collect(),
+        };
+        captures
+            .entry(location as u16)
+            .or_default()
+            .extend(a2r_revolutions(&intervals, &boundaries, A2R_V2_TICK));
+    }
+    Ok(())
+}
+
+/// Read the captures of a version 3 RWCP chunk into `captures`.
+fn a2r_read_rwcp(
+    disk_image: &mut DiskImage,
+    data: &[u8],
+    captures: &mut BTreeMap<u16, Vec<FluxRevolution>>,
+) -> Result<(), DiskImageError> {
+    let mut cursor = Cursor::new(data);
+    let header = A2rRwcpHeader::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+    if header.resolution == 0 {
+        disk_image.spec_violation(None, "RWCP chunk has a capture resolution of 0".to_string())?;
+        return Ok(());
+    }
+    let tick = header.resolution as f64 * 1e-12;
+
+    loop {
+        match data.get(cursor.position() as usize) {
+            Some(&A2R_RWCP_CAPTURE) => cursor.set_position(cursor.position() + 1),
+            Some(&A2R_RWCP_END) => break,
+            _ => {
+                disk_image.spec_violation(None, "RWCP chunk has no end mark".to_string())?;
+                break;
+            }
+        }
+        let capture = A2rRwcpCapture::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+        let start = cursor.position() as usize;
+        let Some(timing) = data.get(start..start + capture.data_len as usize) else {
+            disk_image.spec_violation(
+                None,
+                format!("Capture at location {} extends beyond end of chunk", capture.location),
+            )?;
+            break;
+        };
+        cursor.set_position((start + timing.len()) as u64);
+
+        if !matches!(capture.capture_type, A2R_CAPTURE_TIMING | A2R_CAPTURE_XTIMING) {
+            log::debug!(
+                "a2r_read_rwcp(): Skipping capture of type {} at location {}",
+                capture.capture_type,
+                capture.location
+            );
+            continue;
+        }
+
+        let intervals = a2r_read_timing(timing);
+        let boundaries = capture.index_ticks.iter().map(|&t| t as u64).collect::<Vec<_>>();
+        captures
+            .entry(capture.location)
+            .or_default()
+            .extend(a2r_revolutions(&intervals, &boundaries, tick));
+    }
+    Ok(())
+}
+
+/// Decode A2R timing data into a vector of flux intervals in ticks. A byte of 255 adds 255 ticks
+/// to the following byte.
+fn a2r_read_timing(data: &[u8]) -> Vec<u32> {
+    let mut intervals = Vec::with_capacity(data.len());
+    let mut overflow = 0u32;
+    for &byte in data {
+        if byte == 0xFF {
+            overflow = overflow.saturating_add(0xFF);
+        } else {
+            intervals.push(overflow.saturating_add(byte as u32));
+            overflow = 0;
+        }
+    }
+    intervals
+}
+
+/// Split a capture into revolutions between successive `boundaries`, in ticks from the start of
+/// the capture. With fewer than two boundaries, the whole capture is returned as one revolution.
+fn a2r_revolutions(intervals: &[u32], boundaries: &[u64], tick: f64) -> Vec<FluxRevolution> {
+    if boundaries.len() < 2 {
+        let total = intervals.iter().map(|&i| i as u64).sum();
+        return vec![FluxRevolution::new(intervals.to_vec(), tick, total)];
+    }
+
+    let mut revolutions = Vec::with_capacity(boundaries.len() - 1);
+    let mut time = 0u64;
+    let mut remaining = intervals.iter().peekable();
+    for window in boundaries.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let mut revolution = Vec::new();
+        let mut last = start;
+        while let Some(&&interval) = remaining.peek() {
+            let transition = time + interval as u64;
+            if transition > end {
+                break;
+            }
+            remaining.next();
+            time = transition;
+            // Transitions before the first boundary precede the index, and are dropped.
+            if transition > start {
+                revolution.push((transition - last) as u32);
+                last = transition;
+            }
+        }
+        revolutions.push(FluxRevolution::new(revolution, tick, end.saturating_sub(start)));
+    }
+    revolutions
+}
@@ -0,0 +1,213 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/adf.rs
+
+    A parser for the Amiga Disk File (ADF) format.
+
+    An ADF file is a raw dump of the 512-byte sectors of an AmigaDOS disk, in
+    cylinder, head, sector order, with no header. As Amiga disks cannot be
+    represented as IBM sectors, each track is encoded to an MFM bitstream in the
+    Amiga trackdisk layout on load, and decoded back to sectors on save.
+*/
+use crate::bitstream::TrackDataStream;
+use crate::chs::DiskCh;
+use crate::diskimage::{DiskDescriptor, ParseMode};
+use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
+use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::structure_parsers::amiga::{AmigaElement, AmigaParser, AMIGA_SECTOR_SIZE};
+use crate::structure_parsers::DiskStructureElement;
+use crate::trackdata::TrackData;
+use crate::util::get_length;
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, DiskRpm};
+
+const ADF_CYLINDERS: usize = 80;
+const ADF_HEADS: usize = 2;
+/// Double density disks hold 11 sectors per track, and high density disks 22. High density
+/// drives spin at half speed, so the data rate is the same.
+const ADF_DD_SECTORS: usize = 11;
+const ADF_HD_SECTORS: usize = 22;
+/// The number of bitcells in a track of a double density disk, at 300 RPM.
+const ADF_DD_BITCELLS: usize = 100_000;
+
+pub struct AdfFormat;
+
+impl AdfFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::AmigaDiskFile
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["adf"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        FormatCaps::empty()
+    }
+
+    /// Return the number of sectors per track of an ADF image of `len` bytes, if it is the size of
+    /// a double or high density disk.
+    fn sectors_from_len(len: usize) -> Option<usize> {
+        [ADF_DD_SECTORS, ADF_HD_SECTORS]
+            .into_iter()
+            .find(|spt| len == ADF_CYLINDERS * ADF_HEADS * spt * AMIGA_SECTOR_SIZE)
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        let len = get_length(&mut image).map_or(0, |l| l as usize);
+        AdfFormat::sectors_from_len(len).is_some()
+    }
+
+    pub(crate) fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
+        if !image.track_pool.is_empty() && image.track_pool.iter().all(|track| track.is_amiga()) {
+            ParserWriteCompatibility::Ok
+        } else {
+            ParserWriteCompatibility::Incompatible
+        }
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let len = get_length(&mut image).map_err(|_| DiskImageError::UnknownFormat)? as usize;
+        let spt = AdfFormat::sectors_from_len(len).ok_or(DiskImageError::UnknownFormat)?;
+        let bitcell_ct = ADF_DD_BITCELLS * spt / ADF_DD_SECTORS;
+        let data_rate = DiskDataRate::Rate250Kbps;
+
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        let mut track_buf = vec![0; spt * AMIGA_SECTOR_SIZE];
+        for c in 0..ADF_CYLINDERS {
+            for h in 0..ADF_HEADS {
+                image.read_exact(&mut track_buf).map_err(|_| DiskImageError::IoError)?;
+
+                let bits = AmigaParser::encode_track(c as u16, h as u8, &track_buf, bitcell_ct);
+                log::trace!(
+                    "load_image(): Adding Amiga track C:{} H:{} Bitcells: {}",
+                    c,
+                    h,
+                    bits.len()
+                );
+                disk_image.add_track_bitstream(
+                    DiskDataEncoding::Mfm,
+                    data_rate,
+                    DiskCh::new(c as u16, h as u8),
+                    u32::from(data_rate),
+                    Some(bitcell_ct),
+                    &bits.to_bytes(),
+                    None,
+                )?;
+            }
+        }
+
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(ADF_CYLINDERS as u16, ADF_HEADS as u8),
+            data_rate,
+            data_encoding: DiskDataEncoding::Mfm,
+            density: match spt {
+                ADF_DD_SECTORS => DiskDensity::Double,
+                _ => DiskDensity::High,
+            },
+            default_sector_size: AMIGA_SECTOR_SIZE,
+            // High density drives spin at 150 RPM, which has no DiskRpm.
+            rpm: match spt {
+                ADF_DD_SECTORS => Some(DiskRpm::Rpm300),
+                _ => None,
+            },
+            write_protect: None,
+        };
+
+        Ok(disk_image)
+    }
+
+    /// Save the image as an ADF file, decoding the sectors of each track in sector number order.
+    /// Missing sectors are written as zeros. Only images of Amiga tracks can be saved.
+    pub(crate) fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        let spt = match image.consistency.consistent_track_length.map(|spt| spt as usize) {
+            Some(spt @ (ADF_DD_SECTORS | ADF_HD_SECTORS)) => spt,
+            _ => {
+                log::error!("save_image(): Image does not have a consistent Amiga sector count per track.");
+                return Err(DiskImageError::IncompatibleImage);
+            }
+        };
+
+        for c in 0..ADF_CYLINDERS {
+            for h in 0..ADF_HEADS {
+                let track = image.track_map[h]
+                    .get(c)
+                    .map(|&ti| &image.track_pool[ti])
+                    .ok_or(DiskImageError::IncompatibleImage)?;
+                let (TrackData::BitStream {
+                    data: TrackDataStream::Mfm(codec),
+                    metadata,
+                    ..
+                }
+                | TrackData::FluxStream {
+                    data: TrackDataStream::Mfm(codec),
+                    metadata,
+                    ..
+                }) = track
+                else {
+                    return Err(DiskImageError::IncompatibleImage);
+                };
+                if !track.is_amiga() {
+                    log::error!("save_image(): Track C:{} H:{} is not an Amiga track.", c, h);
+                    return Err(DiskImageError::IncompatibleImage);
+                }
+
+                for s in 0..spt {
+                    let data = metadata
+                        .items
+                        .iter()
+                        .find_map(|item| match (item.elem_type, item.chsn) {
+                            (DiskStructureElement::Amiga(AmigaElement::Data { .. }), Some(chsn))
+                                if chsn.s() as usize == s =>
+                            {
+                                Some(AmigaParser::decode_sector_data(codec.bits(), item.start))
+                            }
+                            _ => None,
+                        });
+                    let data = data.unwrap_or_else(|| {
+                        log::warn!(
+                            "save_image(): Sector C:{} H:{} S:{} is missing, writing zeros.",
+                            c,
+                            h,
+                            s
+                        );
+                        vec![0; AMIGA_SECTOR_SIZE]
+                    });
+                    output.write_all(&data).map_err(|_| DiskImageError::IoError)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
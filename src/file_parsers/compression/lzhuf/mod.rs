@@ -57,4 +57,19 @@ pub const TD0_READ_OPTIONS: Options = Options {
     precursor: b' ',
 };
 
+/// Options to compress the data following the header of a TeleDisk image, leaving room in the
+/// output for the header.
+#[cfg(test)]
+pub const TD0_WRITE_OPTIONS: Options = Options {
+    header: false,
+    in_offset: 12,
+    out_offset: 12,
+    window_size: 4096,
+    threshold: 2,
+    lookahead: 60,
+    precursor: b' ',
+};
+
+#[cfg(test)]
+pub use lzhuf::compress;
 pub use lzhuf::expand;
@@ -0,0 +1,390 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/cpc.rs
+
+    A parser for the CPCEMU DSK disk image format, in both its standard and
+    extended variants.
+
+    DSK images were created for the Amstrad CPC emulator CPCEMU, and are the
+    usual format for Amstrad CPC and Spectrum +3 disks. The image consists of
+    a disk information block, followed by a track information block and the
+    sector data of each track.
+
+    The extended variant records the size of each track, and the length of
+    the data stored for each sector, which may differ from the size given by
+    its ID. A sector stored with several times its declared size holds
+    multiple reads of a sector with weak bits; bits that differ between the
+    copies are loaded as weak bits.
+
+    The µPD765 status registers ST1 and ST2 returned when each sector was
+    read are stored with the sector, and are mapped to CRC errors, deleted
+    marks and missing data marks.
+*/
+
+use crate::chs::{DiskCh, DiskChs, DiskChsn};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor};
+use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
+use crate::io::{Cursor, ReadSeek, ReadWriteSeek};
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat};
+use binrw::{binrw, BinRead};
+
+pub const DSK_EXTENDED_ID: &[u8; 8] = b"EXTENDED";
+pub const DSK_STANDARD_ID: &[u8; 8] = b"MV - CPC";
+pub const DSK_TRACK_ID: &[u8; 10] = b"Track-Info";
+
+pub const DSK_DISK_INFO_LEN: usize = 256;
+pub const DSK_TRACK_INFO_LEN: usize = 256;
+pub const DSK_TRACK_HEADER_LEN: usize = 24;
+pub const DSK_SECTOR_INFO_LEN: usize = 8;
+pub const DSK_MAX_SECTORS: usize = (DSK_TRACK_INFO_LEN - DSK_TRACK_HEADER_LEN) / DSK_SECTOR_INFO_LEN;
+
+/// ST1: Missing Address Mark.
+pub const DSK_ST1_MA: u8 = 0x01;
+/// ST1: Data Error, a CRC error in the ID field, or in the data field if ST2 DD is also set.
+pub const DSK_ST1_DE: u8 = 0x20;
+/// ST2: Missing Data Address Mark.
+pub const DSK_ST2_MD: u8 = 0x01;
+/// ST2: Data Error in Data Field.
+pub const DSK_ST2_DD: u8 = 0x20;
+/// ST2: Control Mark, set when a deleted data mark was read.
+pub const DSK_ST2_CM: u8 = 0x40;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct DskDiskInfo {
+    pub id: [u8; 34],
+    pub creator: [u8; 14],
+    pub tracks: u8,
+    pub sides: u8,
+    /// The size of every track of a standard DSK image. Unused by extended images.
+    pub track_size: u16,
+    /// The size of each track of an extended DSK image, divided by 256. A size of 0 marks an
+    /// unformatted track.
+    pub track_sizes: [u8; 204],
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct DskTrackHeader {
+    pub id: [u8; 12],
+    pub unused: [u8; 4],
+    pub track: u8,
+    pub side: u8,
+    /// The data rate of the track: 0 if unknown, 1 for single or double density, 2 for high
+    /// density and 3 for extended density.
+    pub data_rate: u8,
+    /// The recording mode of the track: 0 if unknown, 1 for FM and 2 for MFM.
+    pub recording_mode: u8,
+    pub n: u8,
+    pub sector_ct: u8,
+    pub gap3: u8,
+    pub filler: u8,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct DskSectorInfo {
+    pub c: u8,
+    pub h: u8,
+    pub r: u8,
+    pub n: u8,
+    pub st1: u8,
+    pub st2: u8,
+    /// The length of the sector data stored in the image. Unused by standard images.
+    pub data_len: u16,
+}
+
+pub struct CpcDskFormat;
+
+impl CpcDskFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::CpcDskImage
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["dsk", "edsk"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        FormatCaps::CAP_VARIABLE_SPT
+            | FormatCaps::CAP_VARIABLE_SSPT
+            | FormatCaps::CAP_ADDRESS_CRC
+            | FormatCaps::CAP_DATA_CRC
+            | FormatCaps::CAP_DATA_DELETED
+            | FormatCaps::CAP_SID_OVERRIDE
+            | FormatCaps::CAP_TRACK_ENCODING
+            | FormatCaps::CAP_TRACK_DATA_RATE
+            | FormatCaps::CAP_WEAK_BITS
+            | FormatCaps::CAP_ENCODING_FM
+            | FormatCaps::CAP_ENCODING_MFM
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        let mut id = [0u8; 8];
+        image.read_exact(&mut id).is_ok() && (id == *DSK_EXTENDED_ID || id == *DSK_STANDARD_ID)
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+
+        let disk_info = DskDiskInfo::read(&mut Cursor::new(&image_data)).map_err(|_| DiskImageError::UnknownFormat)?;
+        let extended = match &disk_info.id[..8] {
+            id if id == DSK_EXTENDED_ID => true,
+            id if id == DSK_STANDARD_ID => false,
+            _ => return Err(DiskImageError::UnknownFormat),
+        };
+
+        let heads = disk_info.sides as usize;
+        let track_ct = disk_info.tracks as usize * heads;
+        if !(1..=2).contains(&heads) || track_ct == 0 || track_ct > disk_info.track_sizes.len() {
+            log::error!(
+                "Unsupported DSK geometry: {} tracks, {} sides",
+                disk_info.tracks,
+                disk_info.sides
+            );
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+        log::trace!(
+            "load_image(): {} DSK image. Tracks: {} Sides: {} Creator: {}",
+            if extended { "Extended" } else { "Standard" },
+            disk_info.tracks,
+            disk_info.sides,
+            String::from_utf8_lossy(&disk_info.creator)
+                .trim_end_matches('\0')
+                .trim()
+        );
+
+        let mut first_track: Option<(DiskDataEncoding, DiskDataRate)> = None;
+        let mut track_offset = DSK_DISK_INFO_LEN;
+
+        for t in 0..track_ct {
+            let ch = DiskCh::new((t / heads) as u16, (t % heads) as u8);
+            let track_size = if extended {
+                disk_info.track_sizes[t] as usize * 256
+            } else {
+                disk_info.track_size as usize
+            };
+
+            // An unformatted track is stored with no track information block.
+            if track_size == 0 {
+                log::trace!("load_image(): Track {} is unformatted", ch);
+                disk_image.add_track_bytestream(DiskDataEncoding::Mfm, DiskDataRate::Rate250Kbps, ch)?;
+                continue;
+            }
+
+            let track_end = std::cmp::min(track_offset + track_size, image_data.len());
+            if track_offset + DSK_TRACK_INFO_LEN > track_end {
+                disk_image.spec_violation(Some(ch), "Image ends before the last track".to_string())?;
+                break;
+            }
+            let record = &image_data[track_offset..track_end];
+            track_offset += track_size;
+
+            let track_format = CpcDskFormat::read_track(&mut disk_image, ch, record, extended)?;
+            if first_track.is_none() {
+                first_track = Some(track_format);
+            }
+        }
+
+        let (data_encoding, data_rate) = first_track.unwrap_or((DiskDataEncoding::Mfm, DiskDataRate::Rate250Kbps));
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(disk_info.tracks as u16, heads as u8),
+            data_rate,
+            data_encoding,
+            density: DiskDensity::from(data_rate),
+            default_sector_size: 512,
+            rpm: None,
+            write_protect: None,
+        };
+
+        Ok(disk_image)
+    }
+
+    /// Add the track `ch` from its track record, which starts with the track information block,
+    /// and master its sectors. Returns the encoding and data rate of the track.
+    fn read_track(
+        disk_image: &mut DiskImage,
+        ch: DiskCh,
+        record: &[u8],
+        extended: bool,
+    ) -> Result<(DiskDataEncoding, DiskDataRate), DiskImageError> {
+        let mut cursor = Cursor::new(record);
+        let track_header = DskTrackHeader::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+        if track_header.id[..DSK_TRACK_ID.len()] != *DSK_TRACK_ID {
+            disk_image.spec_violation(Some(ch), "Track information block has no signature".to_string())?;
+        }
+        if track_header.track as u16 != ch.c() || track_header.side != ch.h() {
+            disk_image.add_load_warning(
+                Some(ch),
+                format!(
+                    "Track information block is for track {} side {}",
+                    track_header.track, track_header.side
+                ),
+            );
+        }
+
+        let encoding = match track_header.recording_mode {
+            1 => DiskDataEncoding::Fm,
+            _ => DiskDataEncoding::Mfm,
+        };
+        let data_rate = match (encoding, track_header.data_rate) {
+            (DiskDataEncoding::Fm, 2) => DiskDataRate::Rate250Kbps,
+            (DiskDataEncoding::Fm, 3) => DiskDataRate::Rate500Kbps,
+            (DiskDataEncoding::Fm, _) => DiskDataRate::Rate125Kbps,
+            (_, 2) => DiskDataRate::Rate500Kbps,
+            (_, 3) => DiskDataRate::Rate1000Kbps,
+            _ => DiskDataRate::Rate250Kbps,
+        };
+        log::trace!(
+            "read_track(): Track {} {:?} {} Sectors: {} GAP3: {} Filler: {:02X}",
+            ch,
+            encoding,
+            data_rate,
+            track_header.sector_ct,
+            track_header.gap3,
+            track_header.filler
+        );
+        disk_image.add_track_bytestream(encoding, data_rate, ch)?;
+
+        let mut sector_ct = track_header.sector_ct as usize;
+        if sector_ct > DSK_MAX_SECTORS {
+            disk_image.spec_violation(
+                Some(ch),
+                format!("Track lists {} sectors, more than fit its information block", sector_ct),
+            )?;
+            sector_ct = DSK_MAX_SECTORS;
+        }
+        let mut sector_infos = Vec::with_capacity(sector_ct);
+        for _ in 0..sector_ct {
+            sector_infos.push(DskSectorInfo::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?);
+        }
+
+        let mut data_offset = DSK_TRACK_INFO_LEN;
+        for info in &sector_infos {
+            if info.n > 7 {
+                disk_image.spec_violation(Some(ch), format!("Sector {} has invalid size code {}", info.r, info.n))?;
+            }
+            let size = DiskChsn::n_to_bytes(info.n);
+            // Standard images store every sector with the size given by the track.
+            let stored_len = if extended {
+                info.data_len as usize
+            } else {
+                DiskChsn::n_to_bytes(track_header.n)
+            };
+            let Some(stored) = record.get(data_offset..data_offset + stored_len) else {
+                disk_image.spec_violation(Some(ch), format!("Sector {} data exceeds the track", info.r))?;
+                break;
+            };
+            data_offset += stored_len;
+
+            // A sector stored as a whole multiple of its declared size holds several reads of a
+            // sector with weak bits. Any other length is stored as the sector data, as for
+            // protections with sectors shorter or longer than their ID declares.
+            let copies = if size > 0 && stored_len > size && stored_len % size == 0 {
+                stored_len / size
+            } else {
+                1
+            };
+            let (data, weak) = match copies {
+                1 => (stored.to_vec(), None),
+                _ => {
+                    let (first, rest) = stored.split_at(size);
+                    let mut weak = vec![0u8; size];
+                    for copy in rest.chunks_exact(size) {
+                        for ((w, &a), &b) in weak.iter_mut().zip(first).zip(copy) {
+                            *w |= a ^ b;
+                        }
+                    }
+                    (first.to_vec(), Some(weak))
+                }
+            };
+
+            let data_error = info.st1 & DSK_ST1_DE != 0;
+            let no_dam = info.st2 & DSK_ST2_MD != 0;
+            log::trace!(
+                "read_track(): Sector ID: {} {} {} {} ST1: {:02X} ST2: {:02X} Stored: {} Copies: {}",
+                info.c,
+                info.h,
+                info.r,
+                info.n,
+                info.st1,
+                info.st2,
+                stored_len,
+                copies
+            );
+            if info.st1 & DSK_ST1_MA != 0 && !no_dam {
+                disk_image.add_load_warning(
+                    Some(ch),
+                    format!("Sector {} was read with a missing address mark", info.r),
+                );
+            }
+
+            let sd = SectorDescriptor {
+                id: info.r,
+                cylinder_id: Some(info.c as u16),
+                head_id: Some(info.h),
+                n: info.n,
+                data: if no_dam { Vec::new() } else { data },
+                weak: if no_dam { None } else { weak },
+                address_crc_error: data_error && info.st2 & DSK_ST2_DD == 0,
+                data_crc_error: data_error && info.st2 & DSK_ST2_DD != 0,
+                deleted_mark: info.st2 & DSK_ST2_CM != 0,
+                no_dam,
+                ..Default::default()
+            };
+            disk_image.master_sector(DiskChs::from((ch, info.r)), &sd)?;
+        }
+
+        Ok((encoding, data_rate))
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
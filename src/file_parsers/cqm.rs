@@ -0,0 +1,300 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/cqm.rs
+
+    A parser for the CopyQM disk image format.
+
+    CopyQM images are produced by Sydex CopyQM, a disk duplication program.
+    The file is a 133-byte header, modeled on a DOS boot sector BPB, followed
+    by a comment and the RLE-compressed contents of every sector of the disk
+    in track order.
+
+    In DOS mode, the size of the disk is taken from the BPB. Disks copied in
+    blind mode, which have no DOS file system, record their cylinder count in
+    the header instead.
+
+    The CRC of the sector data is not checked.
+*/
+
+use crate::chs::{DiskCh, DiskChs, DiskChsn};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor};
+use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
+use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat};
+use binrw::{binrw, BinRead};
+
+pub const CQM_HEADER_LEN: usize = 133;
+pub const CQM_ID: [u8; 3] = [b'C', b'Q', 0x14];
+
+pub const CQM_MODE_DOS: u8 = 0;
+pub const CQM_MODE_BLIND: u8 = 1;
+pub const CQM_MODE_HFS: u8 = 2;
+
+/// Limits on the geometry read from the header, to reject corrupt headers before allocating the disk.
+pub const CQM_MAX_CYLINDERS: usize = 255;
+pub const CQM_MAX_SECTORS_PER_TRACK: usize = 255;
+pub const CQM_MAX_TRACK_SIZE: usize = 0x20000;
+/// The most data a single RLE block can expand to, per byte of compressed input.
+const CQM_MAX_EXPANSION: usize = 0x8000 / 3 + 1;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct CqmHeader {
+    pub id: [u8; 3],
+    pub sector_size: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_ct: u8,
+    pub root_entries: u16,
+    pub total_sectors: u16,
+    pub media_descriptor: u8,
+    pub sectors_per_fat: u16,
+    pub sectors_per_track: u16,
+    pub heads: u16,
+    pub hidden_sectors: u32,
+    pub total_sectors_large: u32,
+    pub description: [u8; 60],
+    /// The copy mode: DOS, blind or HFS.
+    pub mode: u8,
+    /// The density of the disk: 0 for double, 1 for high and 2 for extended density.
+    pub density: u8,
+    /// The number of cylinders holding data.
+    pub used_cylinders: u8,
+    /// The number of cylinders on the disk.
+    pub total_cylinders: u8,
+    pub data_crc: u32,
+    pub volume_label: [u8; 11],
+    pub time: u16,
+    pub date: u16,
+    pub comment_len: u16,
+    /// One less than the ID of the first sector of each track.
+    pub sector_base: u8,
+    pub reserved_1: [u8; 2],
+    pub interleave: u8,
+    pub skew: u8,
+    pub drive_type: u8,
+    pub reserved_2: [u8; 13],
+    /// A checksum making the sum of all header bytes zero.
+    pub checksum: u8,
+}
+
+pub struct CqmFormat;
+
+/// Expand the RLE-compressed data of a CopyQM image. Each block starts with a signed 16-bit
+/// length. A positive length is followed by that many literal bytes; a negative length is
+/// followed by a single byte that is repeated that many times.
+fn cqm_decompress(mut data: &[u8], limit: usize) -> Vec<u8> {
+    let limit = std::cmp::min(limit, data.len().saturating_mul(CQM_MAX_EXPANSION));
+    let mut out = Vec::with_capacity(limit);
+    while data.len() >= 2 && out.len() < limit {
+        let len = i16::from_le_bytes([data[0], data[1]]);
+        data = &data[2..];
+        match len {
+            0 => {}
+            len if len > 0 => {
+                let (literal, rest) = data.split_at(std::cmp::min(len as usize, data.len()));
+                out.extend_from_slice(literal);
+                data = rest;
+            }
+            len => {
+                let Some((&byte, rest)) = data.split_first() else {
+                    break;
+                };
+                let run = std::cmp::min(len.unsigned_abs() as usize, limit - out.len());
+                out.resize(out.len() + run, byte);
+                data = rest;
+            }
+        }
+    }
+    out.truncate(limit);
+    out
+}
+
+impl CqmFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::CopyQm
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["cqm"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        FormatCaps::CAP_COMMENT
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        let mut id = [0u8; 3];
+        image.read_exact(&mut id).is_ok() && id == CQM_ID
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+        if image_data.len() < CQM_HEADER_LEN {
+            return Err(DiskImageError::UnknownFormat);
+        }
+
+        let header =
+            CqmHeader::read(&mut std::io::Cursor::new(&image_data)).map_err(|_| DiskImageError::UnknownFormat)?;
+        if header.id != CQM_ID {
+            return Err(DiskImageError::UnknownFormat);
+        }
+        let checksum = image_data[..CQM_HEADER_LEN]
+            .iter()
+            .fold(0u8, |sum, &b| sum.wrapping_add(b));
+        if checksum != 0 {
+            disk_image.spec_violation(None, format!("Header checksum is off by {:02X}", checksum))?;
+        }
+
+        let sector_size = header.sector_size as usize;
+        let spt = header.sectors_per_track as usize;
+        let heads = header.heads as usize;
+        if !(1..=2).contains(&heads)
+            || !(1..=CQM_MAX_SECTORS_PER_TRACK).contains(&spt)
+            || !(128..=8192).contains(&sector_size)
+            || spt * sector_size > CQM_MAX_TRACK_SIZE
+        {
+            log::error!(
+                "Unsupported CopyQM geometry: {} heads, {} sectors of {} bytes",
+                heads,
+                spt,
+                sector_size
+            );
+            return Err(DiskImageError::FormatParseError);
+        }
+
+        // Blind and HFS copies have no DOS BPB to take the size of the disk from.
+        let cylinders = match header.mode {
+            CQM_MODE_DOS => {
+                let total_sectors = match header.total_sectors {
+                    0 => header.total_sectors_large as usize,
+                    total => total as usize,
+                };
+                total_sectors.div_ceil(spt * heads)
+            }
+            CQM_MODE_BLIND | CQM_MODE_HFS => header.total_cylinders as usize,
+            mode => {
+                disk_image.spec_violation(None, format!("Unknown copy mode: {}", mode))?;
+                header.total_cylinders as usize
+            }
+        };
+        log::trace!(
+            "load_image(): Mode: {} Cylinders: {} (used: {}) Heads: {} Sectors: {} Size: {}",
+            header.mode,
+            cylinders,
+            header.used_cylinders,
+            heads,
+            spt,
+            sector_size
+        );
+        if !(1..=CQM_MAX_CYLINDERS).contains(&cylinders) {
+            log::error!("Unsupported CopyQM cylinder count: {}", cylinders);
+            return Err(DiskImageError::FormatParseError);
+        }
+
+        let comment_start = CQM_HEADER_LEN;
+        let comment_end = std::cmp::min(comment_start + header.comment_len as usize, image_data.len());
+        let comment = String::from_utf8_lossy(&image_data[comment_start..comment_end])
+            .trim_end_matches('\0')
+            .trim()
+            .to_string();
+        if !comment.is_empty() {
+            disk_image.comment = Some(comment);
+        }
+
+        let track_size = spt * sector_size;
+        let disk_size = cylinders * heads * track_size;
+        let mut data = cqm_decompress(&image_data[comment_end..], disk_size);
+        let used_size = std::cmp::min(header.used_cylinders as usize * heads * track_size, disk_size);
+        if data.len() < used_size {
+            disk_image.spec_violation(
+                None,
+                format!("Image holds {} of {} bytes of sector data", data.len(), used_size),
+            )?;
+        }
+        // Cylinders past the last used cylinder are not stored.
+        data.resize(disk_size, 0);
+
+        let data_rate = match header.density {
+            1 => DiskDataRate::Rate500Kbps,
+            2 => DiskDataRate::Rate1000Kbps,
+            _ => DiskDataRate::Rate250Kbps,
+        };
+        let first_sector = header.sector_base.wrapping_add(1);
+
+        for (t, track) in data.chunks_exact(track_size).enumerate() {
+            let ch = DiskCh::new((t / heads) as u16, (t % heads) as u8);
+            disk_image.add_track_bytestream(DiskDataEncoding::Mfm, data_rate, ch)?;
+
+            for (s, sector) in track.chunks_exact(sector_size).enumerate() {
+                let sd = SectorDescriptor {
+                    id: first_sector.wrapping_add(s as u8),
+                    n: DiskChsn::bytes_to_n(sector_size),
+                    data: sector.to_vec(),
+                    ..Default::default()
+                };
+                disk_image.master_sector(DiskChs::from((ch, sd.id)), &sd)?;
+            }
+        }
+
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(cylinders as u16, heads as u8),
+            data_rate,
+            data_encoding: DiskDataEncoding::Mfm,
+            density: DiskDensity::from(data_rate),
+            default_sector_size: sector_size,
+            rpm: None,
+            write_protect: None,
+        };
+
+        Ok(disk_image)
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
@@ -0,0 +1,380 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/dmk.rs
+
+    A parser for the DMK disk image format.
+
+    DMK was created by David Keil for his TRS-80 emulator, and is used by
+    several other emulators. A DMK image stores the decoded bytes of each
+    track, gaps and address marks included, preceded by a table of pointers
+    to the IDAMs of the track. Each pointer carries a flag marking its ID as
+    single or double density.
+
+    The clock bits of address marks are not stored, so they are restored
+    when the track is encoded to a bitstream: the sync bytes before each
+    IDAM, the first data mark following it, and the index mark before the
+    first IDAM are encoded as address marks.
+
+    Unless the image is flagged otherwise, each byte of a single density
+    track is stored twice, so that every track has the same length.
+
+    DMK images of real disks, flagged with the signature 0x12345678, contain
+    no track data and are not supported.
+*/
+
+use crate::bitstream::fm::{FmCodec, FmEncodingType};
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType};
+use crate::chs::DiskCh;
+use crate::diskimage::{DiskDescriptor, ParseMode};
+use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
+use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat};
+use binrw::{binrw, BinRead};
+use bit_vec::BitVec;
+
+pub const DMK_HEADER_LEN: usize = 16;
+pub const DMK_IDAM_TABLE_LEN: usize = 128;
+pub const DMK_MAX_TRACK_LEN: usize = 0x4000;
+pub const DMK_NATIVE_SIGNATURE: u32 = 0x12345678;
+
+pub const DMK_FLAG_SINGLE_SIDED: u8 = 0x10;
+pub const DMK_FLAG_SINGLE_DENSITY: u8 = 0x40;
+pub const DMK_FLAG_IGNORE_DENSITY: u8 = 0x80;
+
+pub const DMK_IDAM_DOUBLE_DENSITY: u16 = 0x8000;
+pub const DMK_IDAM_OFFSET_MASK: u16 = 0x3FFF;
+
+/// The number of bytes after an ID field in which to look for its data address mark.
+const DMK_DAM_WINDOW: usize = 64;
+/// The number of MFM bytes per track above which a track is taken to be high density.
+const DMK_HD_TRACK_LEN: usize = 8000;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct DmkHeader {
+    /// 0xFF if the disk is write protected, otherwise 0x00.
+    pub write_protect: u8,
+    pub tracks: u8,
+    /// The length of each track, including the IDAM table.
+    pub track_len: u16,
+    pub flags: u8,
+    pub reserved: [u8; 7],
+    pub native: u32,
+}
+
+impl DmkHeader {
+    fn heads(&self) -> usize {
+        if self.flags & DMK_FLAG_SINGLE_SIDED != 0 {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Return true if single density bytes are stored once rather than twice.
+    fn single_byte_fm(&self) -> bool {
+        self.flags & (DMK_FLAG_SINGLE_DENSITY | DMK_FLAG_IGNORE_DENSITY) != 0
+    }
+}
+
+pub struct DmkFormat;
+
+/// Encode the bytes of a track, giving the bytes flagged in `marks` the clock pattern of an
+/// address mark.
+fn encode_track(data: &[u8], marks: &[bool], encoding: DiskDataEncoding) -> BitVec {
+    let mut bits = BitVec::with_capacity(data.len() * 16);
+    let mut start = 0;
+    while start < data.len() {
+        let is_mark = marks[start];
+        let end = start + marks[start..].iter().take_while(|&&m| m == is_mark).count();
+        let encoded = match encoding {
+            DiskDataEncoding::Fm => FmCodec::encode_fm(
+                &data[start..end],
+                if is_mark {
+                    FmEncodingType::AddressMark
+                } else {
+                    FmEncodingType::Data
+                },
+            ),
+            _ => {
+                let prev_bit = !bits.is_empty() && bits[bits.len() - 1];
+                MfmCodec::encode_mfm(
+                    &data[start..end],
+                    prev_bit,
+                    if is_mark {
+                        MfmEncodingType::AddressMark
+                    } else {
+                        MfmEncodingType::Data
+                    },
+                )
+            }
+        };
+        bits.extend(encoded.iter());
+        start = end;
+    }
+    bits
+}
+
+/// Return a mask of the bytes of `data` that are part of an address mark, given the offsets of
+/// the track's IDAMs.
+fn find_marks(data: &[u8], idams: &[usize], encoding: DiskDataEncoding) -> Vec<bool> {
+    let mut marks = vec![false; data.len()];
+    let is_fm = matches!(encoding, DiskDataEncoding::Fm);
+
+    // Search for the sync bytes or preceding zero byte of a mark in `range`, ending in a byte
+    // matched by `is_mark`.
+    let mut mark_first = |range: std::ops::Range<usize>, is_mark: &dyn Fn(u8) -> bool, sync: u8| {
+        let sync_len = if is_fm { 1 } else { 3 };
+        let end = std::cmp::min(range.end, data.len());
+        for i in range.start.max(sync_len)..end {
+            if is_mark(data[i]) && data[i - sync_len..i].iter().all(|&b| b == sync) {
+                if is_fm {
+                    marks[i] = true;
+                } else {
+                    marks[i - sync_len..i].fill(true);
+                }
+                return;
+            }
+        }
+    };
+    let (am_sync, iam_sync) = if is_fm { (0x00, 0x00) } else { (0xA1, 0xC2) };
+
+    if let Some(&first) = idams.iter().min() {
+        mark_first(0..first, &|b| b == 0xFC, iam_sync);
+    }
+    for &idam in idams {
+        mark_first(idam..idam + 1, &|b| b == 0xFE, am_sync);
+        let id_end = idam + 7;
+        mark_first(
+            id_end..id_end + DMK_DAM_WINDOW,
+            &|b| (0xF8..=0xFB).contains(&b),
+            am_sync,
+        );
+    }
+    marks
+}
+
+impl DmkFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::DmkImage
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["dmk"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        bitstream_flags() | FormatCaps::CAP_TRACK_ENCODING | FormatCaps::CAP_ENCODING_FM | FormatCaps::CAP_ENCODING_MFM
+    }
+
+    /// DMK images have no signature, so check that the header is plausible, the file is the size
+    /// it describes, and the IDAM table of the first track points within the track.
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        let header = match DmkHeader::read(&mut image) {
+            Ok(header) => header,
+            Err(_) => return false,
+        };
+        let track_len = header.track_len as usize;
+        if !matches!(header.write_protect, 0x00 | 0xFF)
+            || header.tracks == 0
+            || header.reserved.iter().any(|&b| b != 0)
+            || header.native != 0
+            || !(DMK_IDAM_TABLE_LEN + 1..=DMK_MAX_TRACK_LEN).contains(&track_len)
+        {
+            return false;
+        }
+
+        let image_len = match image.seek(std::io::SeekFrom::End(0)) {
+            Ok(len) => len as usize,
+            Err(_) => return false,
+        };
+        if image_len != DMK_HEADER_LEN + header.tracks as usize * header.heads() * track_len {
+            return false;
+        }
+
+        let mut table = [0u8; DMK_IDAM_TABLE_LEN];
+        if image.seek(std::io::SeekFrom::Start(DMK_HEADER_LEN as u64)).is_err() || image.read_exact(&mut table).is_err()
+        {
+            return false;
+        }
+        table
+            .chunks_exact(2)
+            .map(|p| u16::from_le_bytes([p[0], p[1]]) & DMK_IDAM_OFFSET_MASK)
+            .take_while(|&offset| offset != 0)
+            .all(|offset| (DMK_IDAM_TABLE_LEN..track_len).contains(&(offset as usize)))
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        let header = DmkHeader::read(&mut image).map_err(|_| DiskImageError::UnknownFormat)?;
+        if header.native == DMK_NATIVE_SIGNATURE {
+            log::error!("DMK images of real disks are not supported.");
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+        let track_len = header.track_len as usize;
+        if !(DMK_IDAM_TABLE_LEN + 1..=DMK_MAX_TRACK_LEN).contains(&track_len) {
+            log::error!("Invalid DMK track length: {}", track_len);
+            return Err(DiskImageError::FormatParseError);
+        }
+        let heads = header.heads();
+        log::trace!(
+            "load_image(): Tracks: {} Heads: {} Track length: {} Flags: {:02X}",
+            header.tracks,
+            heads,
+            track_len,
+            header.flags
+        );
+
+        let mut first_track: Option<(DiskDataEncoding, DiskDataRate)> = None;
+        let mut cylinders = 0;
+        // Read all the tracks of a cylinder at once, so that a short image ends on a whole cylinder.
+        let mut cylinder_buf = vec![0u8; track_len * heads];
+        for c in 0..header.tracks as u16 {
+            if image.read_exact(&mut cylinder_buf).is_err() {
+                disk_image.spec_violation(Some(DiskCh::new(c, 0)), "Image ends before the last track".to_string())?;
+                break;
+            }
+
+            for (h, track_buf) in cylinder_buf.chunks_exact(track_len).enumerate() {
+                let ch = DiskCh::new(c, h as u8);
+                let (table, raw) = track_buf.split_at(DMK_IDAM_TABLE_LEN);
+                let pointers = table
+                    .chunks_exact(2)
+                    .map(|p| u16::from_le_bytes([p[0], p[1]]))
+                    .take_while(|&p| p != 0)
+                    .collect::<Vec<_>>();
+
+                // A track normally holds IDs of a single density. Encode a mixed track in the
+                // density of the majority of its IDs.
+                let dd_ct = pointers.iter().filter(|&&p| p & DMK_IDAM_DOUBLE_DENSITY != 0).count();
+                let encoding = if pointers.is_empty() {
+                    if header.flags & DMK_FLAG_SINGLE_DENSITY != 0 {
+                        DiskDataEncoding::Fm
+                    } else {
+                        DiskDataEncoding::Mfm
+                    }
+                } else if dd_ct * 2 >= pointers.len() {
+                    DiskDataEncoding::Mfm
+                } else {
+                    DiskDataEncoding::Fm
+                };
+                if dd_ct != 0 && dd_ct != pointers.len() {
+                    disk_image.add_load_warning(
+                        Some(ch),
+                        format!("Track mixes single and double density IDs; encoding as {:?}", encoding),
+                    );
+                }
+
+                // Undo the doubling of single density bytes.
+                let doubled = matches!(encoding, DiskDataEncoding::Fm) && !header.single_byte_fm();
+                let data = if doubled {
+                    raw.iter().step_by(2).copied().collect::<Vec<_>>()
+                } else {
+                    raw.to_vec()
+                };
+
+                let mut idams = Vec::with_capacity(pointers.len());
+                for p in &pointers {
+                    let offset = (p & DMK_IDAM_OFFSET_MASK) as usize;
+                    match offset.checked_sub(DMK_IDAM_TABLE_LEN) {
+                        Some(offset) if offset < raw.len() => idams.push(if doubled { offset / 2 } else { offset }),
+                        _ => {
+                            disk_image.spec_violation(Some(ch), format!("IDAM pointer {:04X} out of range", p))?;
+                        }
+                    }
+                }
+
+                let marks = find_marks(&data, &idams, encoding);
+                let bits = encode_track(&data, &marks, encoding);
+
+                let mfm_len = if matches!(encoding, DiskDataEncoding::Fm) {
+                    data.len() * 2
+                } else {
+                    data.len()
+                };
+                let (data_rate, data_clock) = match (encoding, mfm_len > DMK_HD_TRACK_LEN) {
+                    (DiskDataEncoding::Fm, false) => (DiskDataRate::Rate125Kbps, 250_000),
+                    (DiskDataEncoding::Fm, true) => (DiskDataRate::Rate250Kbps, 500_000),
+                    (_, false) => (DiskDataRate::Rate250Kbps, 250_000),
+                    (_, true) => (DiskDataRate::Rate500Kbps, 500_000),
+                };
+
+                log::trace!(
+                    "load_image(): Adding DMK track {} {:?} IDs: {} Bitcells: {}",
+                    ch,
+                    encoding,
+                    idams.len(),
+                    bits.len()
+                );
+                disk_image.add_track_bitstream(
+                    encoding,
+                    data_rate,
+                    ch,
+                    data_clock,
+                    Some(bits.len()),
+                    &bits.to_bytes(),
+                    None,
+                )?;
+                first_track.get_or_insert((encoding, data_rate));
+            }
+            cylinders += 1;
+        }
+
+        let (data_encoding, data_rate) = first_track.ok_or(DiskImageError::ImageCorruptError)?;
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(cylinders, heads as u8),
+            data_rate,
+            data_encoding,
+            density: DiskDensity::from(data_rate),
+            default_sector_size: 256,
+            rpm: None,
+            write_protect: Some(header.write_protect == 0xFF),
+        };
+
+        Ok(disk_image)
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
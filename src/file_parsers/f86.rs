@@ -32,8 +32,7 @@
     86f format images are an internal bitstream-level format used by the 86Box emulator.
 
 */
-use crate::bitstream::TrackDataStream;
-use crate::diskimage::{DiskDescriptor, DiskImageFlags};
+use crate::diskimage::{DiskDescriptor, DiskImageFlags, ParseMode};
 use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
 use crate::trackdata::TrackData;
@@ -114,7 +113,7 @@ enum F86Endian {
 }
 
 fn f86_disk_time_shift(flags: u16) -> F86TimeShift {
-    match (flags & F86_DISK_RPM_SLOWDOWN >> 5, flags & F86_DISK_SPEEDUP_FLAG != 0) {
+    match ((flags & F86_DISK_RPM_SLOWDOWN) >> 5, flags & F86_DISK_SPEEDUP_FLAG != 0) {
         (0b00, _) => F86TimeShift::ZeroPercent,
         (0b01, false) => F86TimeShift::SlowOnePercent,
         (0b10, false) => F86TimeShift::SlowOneAndAHalfPercent,
@@ -126,16 +125,26 @@ fn f86_disk_time_shift(flags: u16) -> F86TimeShift {
     }
 }
 
-fn f86_track_data_rate(flags: u16) -> Option<DiskDataRate> {
+/// Return the data rate selected by the controller for a track, in bits per second. FM tracks
+/// transfer data at half this rate.
+fn f86_track_clock(flags: u16) -> Option<u32> {
     match flags & 0x07 {
-        0b000 => Some(DiskDataRate::Rate500Kbps),
-        0b001 => Some(DiskDataRate::Rate300Kbps),
-        0b010 => Some(DiskDataRate::Rate250Kbps),
-        0b011 => Some(DiskDataRate::Rate125Kbps),
+        0b000 => Some(500_000),
+        0b001 => Some(300_000),
+        0b010 => Some(250_000),
+        0b011 => Some(1_000_000),
         _ => None,
     }
 }
 
+fn f86_track_data_rate(flags: u16) -> Option<DiskDataRate> {
+    let clock = f86_track_clock(flags)?;
+    match f86_track_encoding(flags) {
+        Some(DiskDataEncoding::Fm) => Some(DiskDataRate::from(clock / 2)),
+        _ => Some(DiskDataRate::from(clock)),
+    }
+}
+
 fn f86_track_encoding(flags: u16) -> Option<DiskDataEncoding> {
     match (flags >> 3) & 0x03 {
         0b00 => Some(DiskDataEncoding::Fm),
@@ -153,6 +162,28 @@ fn f86_track_rpm(flags: u16) -> Option<DiskRpm> {
     }
 }
 
+/// Build the flags of a track header for a track of the specified encoding, data rate and RPM, or
+/// None if the data rate cannot be selected in an 86f image.
+fn f86_track_flags(encoding: DiskDataEncoding, data_rate: DiskDataRate, rpm: Option<DiskRpm>) -> Option<u16> {
+    let (encoding_flags, clock) = match encoding {
+        DiskDataEncoding::Fm => (0b00, u32::from(data_rate) * 2),
+        DiskDataEncoding::Mfm => (0b01, u32::from(data_rate)),
+        DiskDataEncoding::Gcr => (0b11, u32::from(data_rate)),
+    };
+    let rate_flags = match clock {
+        500_000 => 0b000,
+        300_000 => 0b001,
+        250_000 => 0b010,
+        1_000_000 => 0b011,
+        _ => return None,
+    };
+    let rpm_flags = match rpm {
+        Some(DiskRpm::Rpm360) => 0b001,
+        _ => 0b000,
+    };
+    Some(rate_flags | (encoding_flags << 3) | (rpm_flags << 5))
+}
+
 fn f86_weak_to_weak(bit_data: &mut [u8], weak_data: &[u8]) {
     for (byte, &weak_byte) in bit_data.iter_mut().zip(weak_data.iter()) {
         *byte |= weak_byte;
@@ -174,6 +205,11 @@ impl F86Format {
 
     pub fn capabilities() -> FormatCaps {
         bitstream_flags()
+            | FormatCaps::CAP_TRACK_ENCODING
+            | FormatCaps::CAP_WEAK_BITS
+            | FormatCaps::CAP_ENCODING_FM
+            | FormatCaps::CAP_ENCODING_MFM
+            | FormatCaps::CAP_ENCODING_GCR
     }
 
     pub fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
@@ -191,7 +227,10 @@ impl F86Format {
 
     pub fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
         if let Some(resolution) = image.resolution {
-            if !matches!(resolution, DiskDataResolution::BitStream) {
+            if !matches!(
+                resolution,
+                DiskDataResolution::BitStream | DiskDataResolution::FluxStream
+            ) {
                 return ParserWriteCompatibility::Incompatible;
             }
         } else {
@@ -202,8 +241,11 @@ impl F86Format {
         ParserWriteCompatibility::Ok
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         image
             .seek(std::io::SeekFrom::Start(0))
@@ -259,7 +301,7 @@ impl F86Format {
         {
             log::trace!("Extra bitcell count is an absolute count.");
             true
-        } else if extra_bitcell_mode {
+        } else if extra_bitcell_mode && !matches!(time_shift, F86TimeShift::ZeroPercent) {
             log::error!(
                 "Unsupported time shift: {:?} extra_bitcell_mode: {}",
                 time_shift,
@@ -375,11 +417,12 @@ impl F86Format {
                     false => 6, //size_of::<TrackHeader>(),
                 };
 
-            let mut track_data_length = if has_surface_desc {
+            let track_buffer_length = if has_surface_desc {
                 track_data_size / 2
             } else {
                 track_data_size
             };
+            let mut track_data_length = track_buffer_length;
 
             // If not using absolute bitcell count, track data is double what would be expected
             if !absolute_bitcell_count {
@@ -403,8 +446,28 @@ impl F86Format {
 
                     bitcell_ct = Some(absolute_count as usize);
                 }
+            } else if let Some(extra) = extra_bitcells {
+                // Without the speedup flag, the track header holds a signed number of bitcells to
+                // add to the nominal length of the track. A long track extends into the spare half
+                // of the track buffer.
+                let nominal = track_rpm.track_bitcells(track_data_rate) as i64;
+                let len = nominal + extra as i32 as i64;
+                let max_len = track_buffer_length as i64 * 8;
+                if len < 0 || len > max_len {
+                    disk_image.spec_violation(
+                        Some(DiskCh::from((cylinder_n, head_n))),
+                        format!(
+                            "Track length of {} bitcells does not fit track buffer of {} bitcells, truncated",
+                            len, max_len
+                        ),
+                    )?;
+                }
+                let len = len.clamp(0, max_len) as usize;
+                track_data_length = track_data_length.max(len.div_ceil(8));
+                bitcell_ct = Some(len);
             }
 
+            let track_data_offset = image.stream_position().map_err(|_| DiskImageError::IoError)?;
             let track_data_vec = {
                 let mut track_data = vec![0u8; track_data_length];
                 image.read_exact(&mut track_data).map_err(|_| DiskImageError::IoError)?;
@@ -420,11 +483,12 @@ impl F86Format {
                 track_encoding,
                 track_data_rate,
                 DiskCh::from((cylinder_n, head_n)),
-                track_data_rate.into(),
+                f86_track_clock(track_flags).unwrap_or(track_data_rate.into()),
                 bitcell_ct,
                 &track_data_vec,
                 None,
             )?;
+            disk_image.set_track_source(DiskCh::from((cylinder_n, head_n)), track_data_offset, track_data_vec)?;
 
             head_n += 1;
             if head_n == disk_sides {
@@ -433,10 +497,19 @@ impl F86Format {
             }
         }
 
+        // Take the image data rate and encoding from the first track, as the disk hole only
+        // specifies density.
+        let (disk_data_rate, disk_encoding) = disk_image
+            .track_iter()
+            .next()
+            .map_or((image_data_rate, DiskDataEncoding::Mfm), |track| {
+                (track.data_rate(), track.encoding())
+            });
+
         disk_image.descriptor = DiskDescriptor {
             geometry: DiskCh::from((cylinder_n, heads as u8)),
-            data_rate: Default::default(),
-            data_encoding: DiskDataEncoding::Mfm,
+            data_rate: disk_data_rate,
+            data_encoding: disk_encoding,
             density: image_density,
             default_sector_size: DEFAULT_SECTOR_SIZE,
             rpm: disk_rpm,
@@ -454,13 +527,19 @@ impl F86Format {
     ///
     /// When writing track data, the size must be rounded to the nearest word (2 bytes).
     pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
-        if matches!(image.resolution(), DiskDataResolution::BitStream) {
+        if matches!(
+            image.resolution(),
+            DiskDataResolution::BitStream | DiskDataResolution::FluxStream
+        ) {
             log::trace!("Saving 86f image...");
         } else {
             log::error!("Unsupported image resolution.");
             return Err(DiskImageError::UnsupportedFormat);
         }
 
+        // All tracks are written with the same data rate flags, so refuse mixed-density images.
+        image.check_medium(image.descriptor.data_rate, None)?;
+
         let mut disk_flags = 0;
 
         let mut has_surface_description = false;
@@ -516,8 +595,10 @@ impl F86Format {
         f86_header.write(output).map_err(|_| DiskImageError::IoError)?;
 
         log::trace!("Image geometry: {}", image.descriptor.geometry);
-        if image.descriptor.geometry.c() as usize > image.track_map[0].len()
-            || image.descriptor.geometry.c() as usize > image.track_map[1].len()
+        let heads = image.descriptor.geometry.h() as usize;
+        if image.track_map[..heads]
+            .iter()
+            .any(|head_map| image.descriptor.geometry.c() as usize > head_map.len())
         {
             log::error!(
                 "Image geometry does not match track maps: {}: {},{}",
@@ -535,8 +616,6 @@ impl F86Format {
             false
         };
 
-        let heads = image.descriptor.geometry.h() as usize;
-
         let track_entries = if double_tracks {
             image.descriptor.geometry.c() as usize * 2 * heads
         } else {
@@ -556,32 +635,7 @@ impl F86Format {
                 .map_err(|_| DiskImageError::IoError)?;
         }
 
-        // We shouldn't need to change track flags per track, so set them now.
-        let mut track_flags = 0;
-        log::trace!("Setting data rate: {:?}", image.descriptor.data_rate);
-        track_flags |= match image.descriptor.data_rate {
-            DiskDataRate::Rate500Kbps => 0b000,
-            DiskDataRate::Rate300Kbps => 0b001,
-            DiskDataRate::Rate250Kbps => 0b010,
-            DiskDataRate::Rate1000Kbps => 0b011,
-            _ => {
-                log::error!("Unsupported data rate: {:?}", image.descriptor.data_rate);
-                return Err(DiskImageError::UnsupportedFormat);
-            }
-        };
-
-        log::trace!("Setting data encoding: {:?}", image.descriptor.data_encoding);
-        track_flags |= match image.descriptor.data_encoding {
-            DiskDataEncoding::Fm => 0b00 << 3,
-            DiskDataEncoding::Mfm => 0b01 << 3,
-            DiskDataEncoding::Gcr => 0b11 << 3,
-        };
-
         log::trace!("Setting RPM: {:?}", image.descriptor.rpm);
-        track_flags |= image.descriptor.rpm.map_or(0, |rpm| match rpm {
-            DiskRpm::Rpm300 => 0b000 << 5,
-            DiskRpm::Rpm360 => 0b001 << 5,
-        });
 
         let mut c = 0;
         let mut h = 0;
@@ -600,15 +654,36 @@ impl F86Format {
             let ti = image.track_map[h][c as usize];
 
             if let TrackData::BitStream {
-                data: TrackDataStream::Mfm(mfm_codec),
+                encoding,
+                data_rate,
+                data: stream,
+                ..
+            }
+            | TrackData::FluxStream {
+                encoding,
+                data_rate,
+                data: stream,
                 ..
             } = &image.track_pool[ti]
             {
-                let absolute_bit_count = mfm_codec.len();
-                log::error!("Absolute bit count: {}", absolute_bit_count);
+                let track_flags = match f86_track_flags(*encoding, *data_rate, image.descriptor.rpm) {
+                    Some(flags) => flags,
+                    None => {
+                        log::error!("Unsupported data rate: {:?} for {:?} track", data_rate, encoding);
+                        return Err(DiskImageError::UnsupportedFormat);
+                    }
+                };
+
+                // Always write the track at its exact length, so long and short tracks survive.
+                image.track_pool[ti].warn_length_changed("f86::save_image()");
+                let absolute_bit_count = stream.len();
+                log::trace!("Absolute bit count: {}", absolute_bit_count);
 
-                let mut bit_data = mfm_codec.data();
-                let mut weak_data = mfm_codec.weak_data();
+                let mut bit_data = stream.bits().to_bytes();
+                let mut weak_data = match stream.get_weak_mask() {
+                    Some(weak_mask) => weak_mask.to_bytes(),
+                    None => vec![0; bit_data.len()],
+                };
 
                 if has_surface_description && (bit_data.len() != weak_data.len()) {
                     log::error!("Bitstream and weak data lengths do not match.");
@@ -631,7 +706,7 @@ impl F86Format {
                 }
 
                 if image.has_flag(DiskImageFlags::PROLOK) && c == 39 && h == 0 {
-                    log::trace!("PROLOK: Converting {} weak bits to holes.", mfm_codec.weak_data().len());
+                    log::trace!("PROLOK: Converting {} weak bits to holes.", weak_data.len());
                     f86_weak_to_holes(&mut bit_data, &mut weak_data);
                 } else {
                     f86_weak_to_weak(&mut bit_data, &mut weak_data);
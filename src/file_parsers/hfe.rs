@@ -31,14 +31,16 @@
     HFE format images are an internal bitstream-level format used by the HxC disk emulator.
 
 */
-use crate::diskimage::DiskDescriptor;
+use crate::diskimage::{DiskDescriptor, ParseMode};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::structure_parsers::system34::IBM_GAP3_DEFAULT;
 use crate::{
-    DiskCh, DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat,
-    DEFAULT_SECTOR_SIZE,
+    DiskCh, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskDensity, DiskImage, DiskImageError,
+    DiskImageFormat, DEFAULT_SECTOR_SIZE,
 };
-use binrw::{binrw, BinRead};
+use binrw::{binrw, BinRead, BinWrite};
+use std::io::Cursor;
 
 const fn reverse_bits(mut byte: u8) -> u8 {
     byte = (byte >> 4) | (byte << 4);
@@ -60,6 +62,8 @@ const fn generate_reverse_table() -> [u8; 256] {
 const REVERSE_TABLE: [u8; 256] = generate_reverse_table();
 
 pub const HFE_TRACK_OFFSET_BLOCK: u64 = 0x200;
+const HFE_BLOCK_LEN: usize = 512;
+const HFE_SIDE_BLOCK_LEN: usize = HFE_BLOCK_LEN / 2;
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
@@ -213,13 +217,39 @@ impl HfeFormat {
         detected
     }
 
-    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
-        // TODO: Determine what data representations would lead to data loss for PSI.
-        ParserWriteCompatibility::Ok
+    pub(crate) fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
+        if !matches!(image.descriptor.data_encoding, DiskDataEncoding::Mfm) {
+            return ParserWriteCompatibility::Incompatible;
+        }
+        // HFE has no way to store weak bits, or tracks of arbitrary length.
+        if image.has_weak_bits() || !Self::track_lengths_fit(image) {
+            ParserWriteCompatibility::DataLoss
+        } else {
+            ParserWriteCompatibility::Ok
+        }
+    }
+
+    /// Return true if every BitStream track of the image can be stored at its exact length. HFE
+    /// stores a single length per cylinder in whole 16-bit words, so both sides of a cylinder
+    /// must have the same length, which must be a multiple of 16 bitcells.
+    fn track_lengths_fit(image: &DiskImage) -> bool {
+        let bitstream_len = |ti: usize| {
+            let track = &image.track_pool[ti];
+            (track.resolution() != DiskDataResolution::ByteStream).then(|| track.bitcell_ct())
+        };
+        let aligned = |len: Option<usize>| len.is_none_or(|len| len % 16 == 0);
+        (0..image.track_map[0].len()).all(|c| {
+            let len0 = bitstream_len(image.track_map[0][c]);
+            let len1 = image.track_map[1].get(c).and_then(|&ti| bitstream_len(ti));
+            aligned(len0) && aligned(len1) && (len0.is_none() || len1.is_none() || len0 == len1)
+        })
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         let image_len = image
             .seek(std::io::SeekFrom::End(0))
@@ -282,11 +312,13 @@ impl HfeFormat {
             let data_block_ct = data_block_len / 512;
 
             if data_block_len % 512 != 0 {
-                log::warn!(
-                    "Cylinder {} data length {} is not a multiple of 512 bytes",
-                    ti,
-                    track.len
-                );
+                disk_image.spec_violation(
+                    None,
+                    format!(
+                        "Cylinder {} data length {} is not a multiple of 512 bytes",
+                        ti, track.len
+                    ),
+                )?;
             } else {
                 log::trace!(
                     "Cylinder {} data length {} contains {} 512 byte blocks.",
@@ -310,7 +342,14 @@ impl HfeFormat {
                     bytes_remaining / 2
                 };
 
+                // Each block holds 256 bytes for each head, even if the last block is partial.
+                let block_start = track_data_offset + block_ct * HFE_BLOCK_LEN as u64;
                 for head in 0..2 {
+                    image
+                        .seek(std::io::SeekFrom::Start(
+                            block_start + (head * HFE_SIDE_BLOCK_LEN) as u64,
+                        ))
+                        .map_err(|_| DiskImageError::IoError)?;
                     log::trace!(
                         "Reading track {} head {} block {} bytes_remaining: {}",
                         ti,
@@ -359,9 +398,9 @@ impl HfeFormat {
 
             disk_image.add_track_bitstream(
                 DiskDataEncoding::Mfm,
-                DiskDataRate::from(file_header.bit_rate as u32 * 100),
+                DiskDataRate::from(file_header.bit_rate as u32 * 1000),
                 DiskCh::from((ti as u16, 0)),
-                file_header.bit_rate as u32 * 100,
+                file_header.bit_rate as u32 * 1000,
                 None,
                 &track_data[0],
                 None,
@@ -376,9 +415,9 @@ impl HfeFormat {
             );
             disk_image.add_track_bitstream(
                 DiskDataEncoding::Mfm,
-                DiskDataRate::from(file_header.bit_rate as u32 * 100),
+                DiskDataRate::from(file_header.bit_rate as u32 * 1000),
                 DiskCh::from((ti as u16, 1)),
-                file_header.bit_rate as u32 * 100,
+                file_header.bit_rate as u32 * 1000,
                 None,
                 &track_data[1],
                 None,
@@ -387,7 +426,7 @@ impl HfeFormat {
 
         disk_image.descriptor = DiskDescriptor {
             geometry: DiskCh::from((file_header.number_of_tracks as u16, file_header.number_of_sides)),
-            data_rate: DiskDataRate::from(file_header.bit_rate as u32 * 100),
+            data_rate: DiskDataRate::from(file_header.bit_rate as u32 * 1000),
             density: DiskDensity::from(hfe_floppy_interface),
             data_encoding: DiskDataEncoding::Mfm,
             default_sector_size: DEFAULT_SECTOR_SIZE,
@@ -398,8 +437,160 @@ impl HfeFormat {
         Ok(disk_image)
     }
 
-    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
-        Err(DiskImageError::UnsupportedFormat)
+    /// Write a disk image in HFEv1 format.
+    ///
+    /// BitStream tracks are written at their own length, so long and short tracks survive.
+    /// ByteStream tracks have no length of their own, and are laid out with standard IBM gaps at
+    /// the length of the other side of their cylinder, or else the nominal track length for the
+    /// image's data rate and rotation speed, and encoded to MFM.
+    /// HFE always stores two sides, so the second side of a single-sided image is written as an
+    /// unformatted track.
+    ///
+    /// HFE stores a single length per cylinder, rounded up to a whole number of 16-bit words. A
+    /// side shorter than its cylinder is extended by wrapping around to its start and a warning
+    /// is logged; `can_write` reports images with such tracks as lossy.
+    pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        if !matches!(image.descriptor.data_encoding, DiskDataEncoding::Mfm) {
+            log::error!("save_image(): HFE export only supports MFM encoding.");
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        let track_ct = image.track_map[0].len();
+        let heads = if image.track_map[1].is_empty() { 1 } else { 2 };
+        if track_ct == 0 || track_ct > u8::MAX as usize {
+            log::error!("save_image(): Unsupported number of tracks: {}", track_ct);
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        let interface_mode = match image.descriptor.density {
+            DiskDensity::Standard | DiskDensity::Double => HfeFloppyInterface::IbmPcDd,
+            DiskDensity::High => HfeFloppyInterface::IbmPcHd,
+            DiskDensity::Extended => HfeFloppyInterface::IbmPcEd,
+        };
+        let rpm = image.descriptor.rpm.unwrap_or_default();
+        let nominal_bitcell_ct = rpm.track_bitcells(image.descriptor.data_rate);
+        let gap3 = image
+            .standard_format
+            .map(|format| format.get_gap3())
+            .unwrap_or(IBM_GAP3_DEFAULT);
+
+        // Encode both sides of each cylinder, with the bits of each byte reversed as HFE stores
+        // them LSB first. Both sides share a length, so the shorter side is extended by wrapping
+        // around to its start, as a drive would see it after passing the index.
+        let mut cylinders = Vec::with_capacity(track_ct);
+        for c in 0..track_ct {
+            // A ByteStream side is laid out at the length of a BitStream side of the same
+            // cylinder, if it has one, so that neither side needs to be padded.
+            let cylinder_bitcell_ct = (0..heads)
+                .map(|head| &image.track_pool[image.track_map[head][c]])
+                .find(|track| track.resolution() != DiskDataResolution::ByteStream)
+                .map_or(nominal_bitcell_ct, |track| track.bitcell_ct());
+            let mut sides: [Vec<u8>; 2] = Default::default();
+            for (head, side) in sides.iter_mut().enumerate().take(heads) {
+                let track = &image.track_pool[image.track_map[head][c]];
+                if !matches!(track.encoding(), DiskDataEncoding::Mfm) {
+                    log::error!("save_image(): Track {} is not MFM encoded.", track.ch());
+                    return Err(DiskImageError::UnsupportedFormat);
+                }
+                track.warn_length_changed("hfe::save_image()");
+                *side = track
+                    .to_mfm_bits(cylinder_bitcell_ct, gap3, &image.crc_params)?
+                    .to_bytes();
+            }
+            let side_len = sides[0].len().max(sides[1].len()).next_multiple_of(2);
+            for (head, side) in sides.iter_mut().enumerate() {
+                if side.is_empty() {
+                    side.resize(side_len, 0);
+                }
+                let track_len = side.len();
+                if track_len < side_len && head < heads {
+                    log::warn!(
+                        "save_image(): Cylinder {} head {} is {} bytes long, padded to {} bytes to match its cylinder.",
+                        c,
+                        head,
+                        track_len,
+                        side_len
+                    );
+                }
+                for i in track_len..side_len {
+                    side.push(side[i % track_len]);
+                }
+                for byte in side.iter_mut() {
+                    *byte = REVERSE_TABLE[*byte as usize];
+                }
+            }
+            if side_len * 2 > u16::MAX as usize {
+                log::error!(
+                    "save_image(): Cylinder {} is too long for HFE: {} bytes",
+                    c,
+                    side_len * 2
+                );
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+            cylinders.push(sides);
+        }
+
+        let file_header = HfeFileHeader {
+            signature: *b"HXCPICFE",
+            format_revision: 0,
+            number_of_tracks: track_ct as u8,
+            number_of_sides: heads as u8,
+            track_encoding: HfeFloppyEncoding::IsoIbmMfm as u8,
+            bit_rate: (u32::from(image.descriptor.data_rate) / 1000) as u16,
+            rpm: u32::from(rpm) as u16,
+            interface_mode: interface_mode as u8,
+            unused: 0,
+            rack_list_offset: 1,
+            write_allowed: if image.descriptor.write_protect.unwrap_or(false) {
+                0x00
+            } else {
+                0xFF
+            },
+            single_step: 0xFF,
+            track0s0_altencoding: 0xFF,
+            track0s0_encoding: 0xFF,
+            track0s1_altencoding: 0xFF,
+            track0s1_encoding: 0xFF,
+        };
+
+        // The header, track list and each track start on a 512-byte block boundary.
+        let mut header_block = Cursor::new(Vec::with_capacity(HFE_BLOCK_LEN));
+        file_header
+            .write(&mut header_block)
+            .map_err(|_| DiskImageError::IoError)?;
+        let mut file_data = header_block.into_inner();
+        file_data.resize(HFE_BLOCK_LEN, 0xFF);
+
+        let track_list_blocks = (track_ct * 4).div_ceil(HFE_BLOCK_LEN);
+        let mut block = 1 + track_list_blocks;
+        for sides in &cylinders {
+            let len = sides[0].len() * 2;
+            file_data.extend_from_slice(&(block as u16).to_le_bytes());
+            file_data.extend_from_slice(&(len as u16).to_le_bytes());
+            block += len.div_ceil(HFE_BLOCK_LEN);
+        }
+        file_data.resize((1 + track_list_blocks) * HFE_BLOCK_LEN, 0xFF);
+
+        // Track data is interleaved in blocks of 256 bytes from each side.
+        for sides in &cylinders {
+            for (side0, side1) in sides[0]
+                .chunks(HFE_SIDE_BLOCK_LEN)
+                .zip(sides[1].chunks(HFE_SIDE_BLOCK_LEN))
+            {
+                let block_start = file_data.len();
+                file_data.extend_from_slice(side0);
+                file_data.resize(block_start + HFE_SIDE_BLOCK_LEN, 0);
+                file_data.extend_from_slice(side1);
+                file_data.resize(block_start + HFE_BLOCK_LEN, 0);
+            }
+        }
+
+        output
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        output.write_all(&file_data).map_err(|_| DiskImageError::IoError)?;
+
+        Ok(())
     }
 }
 
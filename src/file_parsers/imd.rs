@@ -25,9 +25,10 @@
     --------------------------------------------------------------------------
 */
 use crate::chs::{DiskCh, DiskChs, DiskChsn};
-use crate::diskimage::{DiskDescriptor, SectorDescriptor};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor, TrackSectorIndex};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::trackdata::TrackData;
 use crate::util::{get_length, read_ascii};
 use crate::{
     DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, FoxHashSet,
@@ -35,6 +36,7 @@ use crate::{
 };
 use binrw::{binrw, BinRead, BinReaderExt};
 use regex::Regex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub const IMD_HEADER_REX: &str = r"(?s)IMD (?<v_major>\d)\.(?<v_minor>\d{2}): (?<day>\d{2})/(?<month>\d{2})/(?<year>\d{4}) (?<hh>\d{2}):(?<mm>\d{2}):(?<ss>\d{2})(?<comment>.*)?";
 
@@ -58,7 +60,7 @@ impl ImdTrack {
         self.h & 0x0F
     }
     pub fn is_valid(&self) -> bool {
-        self.mode < 6 && (self.h & !0xC0) < 2 && self.sector_size < 7
+        self.mode < 6 && (self.h & !0xC0) < 2 && (self.sector_size < 7 || self.has_sector_size_map())
     }
     pub fn has_head_map(&self) -> bool {
         self.h & 0x40 != 0
@@ -67,7 +69,7 @@ impl ImdTrack {
         self.h & 0x80 != 0
     }
     pub fn has_sector_size_map(&self) -> bool {
-        self.h == 0xFF
+        self.sector_size == 0xFF
     }
     pub fn sector_size(&self) -> Option<usize> {
         imd_sector_size_to_usize(self.sector_size)
@@ -86,6 +88,44 @@ fn imd_mode_to_rate(data_rate: u8) -> Option<(DiskDataRate, DiskDataEncoding)> {
     }
 }
 
+fn imd_rate_to_mode(data_rate: DiskDataRate, encoding: DiskDataEncoding) -> Option<u8> {
+    match (data_rate, encoding) {
+        (DiskDataRate::Rate500Kbps, DiskDataEncoding::Fm) => Some(0),
+        (DiskDataRate::Rate300Kbps, DiskDataEncoding::Fm) => Some(1),
+        (DiskDataRate::Rate250Kbps, DiskDataEncoding::Fm) => Some(2),
+        (DiskDataRate::Rate500Kbps, DiskDataEncoding::Mfm) => Some(3),
+        (DiskDataRate::Rate300Kbps, DiskDataEncoding::Mfm) => Some(4),
+        (DiskDataRate::Rate250Kbps, DiskDataEncoding::Mfm) => Some(5),
+        _ => None,
+    }
+}
+
+/// Return the current UTC time in the `DD/MM/YYYY HH:MM:SS` form of an IMD header.
+fn imd_timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since 1970-01-01 to a civil date, with years starting in March.
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+
+    format!(
+        "{:02}/{:02}/{:04} {:02}:{:02}:{:02}",
+        day,
+        month,
+        year,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
 fn imd_sector_size_to_usize(sector_size: u8) -> Option<usize> {
     match sector_size {
         0 => Some(128),
@@ -103,6 +143,7 @@ pub struct ImdSectorData {
     data: Vec<u8>,
     deleted: bool,
     error: bool,
+    unavailable: bool,
 }
 
 impl ImdFormat {
@@ -112,7 +153,16 @@ impl ImdFormat {
     }
 
     pub(crate) fn capabilities() -> FormatCaps {
-        FormatCaps::empty()
+        FormatCaps::CAP_VARIABLE_SPT
+            | FormatCaps::CAP_VARIABLE_SSPT
+            | FormatCaps::CAP_DATA_CRC
+            | FormatCaps::CAP_DATA_DELETED
+            | FormatCaps::CAP_SID_OVERRIDE
+            | FormatCaps::CAP_COMMENT
+            | FormatCaps::CAP_TRACK_ENCODING
+            | FormatCaps::CAP_TRACK_DATA_RATE
+            | FormatCaps::CAP_ENCODING_FM
+            | FormatCaps::CAP_ENCODING_MFM
     }
 
     pub(crate) fn extensions() -> Vec<&'static str> {
@@ -133,13 +183,19 @@ impl ImdFormat {
         detected
     }
 
-    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
-        // TODO: Determine what data representations would lead to data loss for IMD.
-        ParserWriteCompatibility::Ok
+    pub(crate) fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
+        if ImdFormat::capabilities().contains(image.required_caps()) {
+            ParserWriteCompatibility::Ok
+        } else {
+            ParserWriteCompatibility::DataLoss
+        }
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         // Assign the disk geometry or return error.
         let _raw_len = get_length(&mut image).map_err(|_e| DiskImageError::UnknownFormat)? as usize;
@@ -167,8 +223,7 @@ impl ImdFormat {
         let mut header_offset = image.stream_position().unwrap();
         let mut heads_seen: FoxHashSet<u8> = FoxHashSet::new();
 
-        let mut rate_opt = None;
-        let mut encoding_opt = None;
+        let mut mode_counts = [0usize; 6];
 
         let mut track_ct = 0;
 
@@ -176,8 +231,13 @@ impl ImdFormat {
             log::trace!("from_image: Track header: {:?} @ {:X}", &track_header, header_offset);
             log::trace!("from_image: Track header valid: {}", &track_header.is_valid());
             if !track_header.is_valid() {
-                log::error!("from_image: Invalid track header at offset {:X}", header_offset);
-                return Err(DiskImageError::FormatParseError);
+                if track_ct == 0 {
+                    log::error!("from_image: Invalid track header at offset {:X}", header_offset);
+                    return Err(DiskImageError::FormatParseError);
+                }
+                // Trailing garbage after at least one good track. Keep what we have.
+                disk_image.spec_violation(None, format!("Invalid track header at offset {:X}", header_offset))?;
+                break;
             }
 
             log::trace!(
@@ -191,15 +251,15 @@ impl ImdFormat {
             let mut cylinder_map = vec![track_header.c(); track_header.sector_ct as usize];
             let mut head_map = vec![track_header.h(); track_header.sector_ct as usize];
 
-            //let default_n = track_header.sector_size;
-            let default_sector_size = track_header.sector_size();
-            if default_sector_size.is_none() {
-                return Err(DiskImageError::FormatParseError);
-            }
+            // A track with a sector size map has no default sector size.
+            let default_sector_size = match track_header.sector_size() {
+                Some(size) => size,
+                None if track_header.has_sector_size_map() => 0,
+                None => return Err(DiskImageError::FormatParseError),
+            };
             // Sector size map is in words; so double the bytes.
-            let mut sector_size_map_u8: Vec<u8> = vec![0, track_header.sector_ct * 2];
-            let mut sector_size_map: Vec<u16> =
-                vec![default_sector_size.unwrap() as u16; track_header.sector_ct as usize];
+            let mut sector_size_map_u8: Vec<u8> = vec![0; track_header.sector_ct as usize * 2];
+            let mut sector_size_map: Vec<u16> = vec![default_sector_size as u16; track_header.sector_ct as usize];
 
             // Keep a set of heads seen.
             heads_seen.insert(track_header.h());
@@ -244,11 +304,10 @@ impl ImdFormat {
                 None => return Err(DiskImageError::FormatParseError),
             };
 
-            if rate_opt.is_none() {
-                rate_opt = Some(data_rate);
-            }
-            if encoding_opt.is_none() {
-                encoding_opt = Some(data_encoding);
+            // The mode byte is per-track, and images of 8" disks commonly have an FM track 0
+            // followed by MFM tracks. Use the mode of the majority of tracks for the descriptor.
+            if track_header.sector_ct > 0 {
+                mode_counts[track_header.mode as usize] += 1;
             }
 
             log::trace!("Adding track: C: {} H: {}", track_header.c, track_header.h);
@@ -270,13 +329,14 @@ impl ImdFormat {
                         let data = ImdFormat::read_data(data_marker, sector_size, &mut image)?;
 
                         log::trace!(
-                            "from_image: Sector {}: Data Marker: {:02X} Data ({}): {:02X?} Deleted: {} Error: {}",
+                            "from_image: Sector {}: Data Marker: {:02X} Data ({}): {:02X?} Deleted: {} Error: {} Unavailable: {}",
                             s + 1,
                             data_marker,
                             &data.data.len(),
-                            &data.data[0..16],
+                            &data.data[0..std::cmp::min(16, data.data.len())],
                             &data.deleted,
-                            &data.error
+                            &data.error,
+                            &data.unavailable
                         );
 
                         // Add this sector to track.
@@ -290,6 +350,10 @@ impl ImdFormat {
                             address_crc_error: false,
                             data_crc_error: data.error,
                             deleted_mark: data.deleted,
+                            no_dam: data.unavailable,
+                            position: None,
+                            read_time: None,
+                            timing: None,
                         };
 
                         disk_image.master_sector(
@@ -313,11 +377,22 @@ impl ImdFormat {
 
         let head_ct = heads_seen.len() as u8;
 
+        if track_ct == 0 {
+            log::error!("from_image: No tracks found");
+            return Err(DiskImageError::FormatParseError);
+        }
+        // Ties go to the lowest mode, ie, FM before MFM.
+        let (mode, _) = mode_counts.iter().enumerate().rev().max_by_key(|(_, ct)| **ct).unwrap();
+        let (data_rate, data_encoding) = imd_mode_to_rate(mode as u8).unwrap();
+
         disk_image.descriptor = DiskDescriptor {
             geometry: DiskCh::from((track_ct as u16 / head_ct as u16, head_ct)),
-            data_rate: rate_opt.unwrap(),
-            data_encoding: encoding_opt.unwrap(),
-            density: DiskDensity::from(rate_opt.unwrap()),
+            data_rate,
+            data_encoding,
+            density: match data_encoding {
+                DiskDataEncoding::Fm => DiskDensity::Standard,
+                _ => DiskDensity::from(data_rate),
+            },
             default_sector_size: DEFAULT_SECTOR_SIZE,
             rpm: None,
             write_protect: None,
@@ -326,86 +401,169 @@ impl ImdFormat {
         Ok(disk_image)
     }
 
+    /// Read the sector data record following `data_marker`.
+    ///
+    /// Data marker values are:
+    ///     0x00: Sector data unavailable - the sector has an ID field but no data field.
+    ///     0x01: Normal data
+    ///     0x02: Compressed data
+    ///     0x03: Normal data with 'deleted' address mark
+    ///     0x04: Compressed data with 'deleted' address mark
+    ///     0x05: Normal data with 'error' indicator
+    ///     0x06: Compressed data with 'error' indicator
+    ///     0x07: Normal data with 'deleted' address mark and 'error' indicator
+    ///     0x08: Compressed data with 'deleted' address mark and 'error' indicator
+    ///
+    /// Compressed data is a single byte, repeated sector_size times.
     fn read_data<RWS: ReadSeek>(
         data_marker: u8,
         sector_size: usize,
         image: &mut RWS,
     ) -> Result<ImdSectorData, DiskImageError> {
         match data_marker {
-            0x00 => {
-                // Sector data unavailable.
-                Ok(ImdSectorData {
-                    data: Vec::new(),
-                    deleted: false,
-                    error: false,
-                })
-            }
-            0x01 => {
-                // Normal data - sector_size bytes follow.
-                let mut data = vec![0; sector_size];
-                image.read_exact(&mut data).map_err(|_e| DiskImageError::IoError)?;
-                Ok(ImdSectorData {
-                    data,
-                    deleted: false,
-                    error: false,
-                })
-            }
-            0x02 => {
-                // Compressed data: A single byte follows, repeated sector_size times.
-                let data_byte = image.read_le().map_err(|_e| DiskImageError::IoError)?;
-                let data = vec![data_byte; sector_size];
-                Ok(ImdSectorData {
-                    data,
-                    deleted: false,
-                    error: false,
-                })
-            }
-            0x03 => {
-                // Normal data with 'deleted' address-mark.
-                let mut data = vec![0; sector_size];
-                image.read_exact(&mut data).map_err(|_e| DiskImageError::IoError)?;
-                Ok(ImdSectorData {
-                    data,
-                    deleted: true,
-                    error: false,
-                })
-            }
-            0x04 => {
-                // Compressed data with 'deleted' address-mark.
-                // A single byte follows, repeated sector_size times.
-                let data_byte = image.read_le().map_err(|_e| DiskImageError::IoError)?;
-                let data = vec![data_byte; sector_size];
+            0x00 => Ok(ImdSectorData {
+                data: Vec::new(),
+                deleted: false,
+                error: false,
+                unavailable: true,
+            }),
+            0x01..=0x08 => {
+                let compressed = data_marker & 0x01 == 0;
+                let data = if compressed {
+                    let data_byte = image.read_le().map_err(|_e| DiskImageError::IoError)?;
+                    vec![data_byte; sector_size]
+                } else {
+                    let mut data = vec![0; sector_size];
+                    image.read_exact(&mut data).map_err(|_e| DiskImageError::IoError)?;
+                    data
+                };
+                let flags = (data_marker - 1) >> 1;
                 Ok(ImdSectorData {
                     data,
-                    deleted: true,
-                    error: false,
+                    deleted: flags & 0x01 != 0,
+                    error: flags & 0x02 != 0,
+                    unavailable: false,
                 })
             }
-            0x05 => {
-                // Normal data with 'error' indicator.
-                let mut data = vec![0; sector_size];
-                image.read_exact(&mut data).map_err(|_e| DiskImageError::IoError)?;
-                Ok(ImdSectorData {
-                    data,
-                    deleted: false,
-                    error: true,
-                })
+            _ => Err(DiskImageError::FormatParseError),
+        }
+    }
+
+    /// Save the disk image as an IMD image. BitStream tracks are decoded to their sectors first.
+    /// Sectors whose data is a single repeated byte are written compressed, and the deleted-data
+    /// and data CRC error flags of each sector are kept.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if a track is not FM or MFM at a data rate IMD
+    ///   supports, or has a cylinder, sector count or sector size IMD cannot store.
+    pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        // The comment follows the timestamp on its own line.
+        let comment = image
+            .comment
+            .as_deref()
+            .map_or("", |comment| comment.trim_start_matches(['\r', '\n']));
+        let mut imd = format!("IMD 1.18: {}\r\n{}", imd_timestamp(), comment).into_bytes();
+        imd.push(0x1A);
+
+        let heads = if image.track_map[1].is_empty() { 1 } else { 2 };
+        for c in 0..image.track_map[0].len() {
+            for h in 0..heads {
+                let track = image.track_pool[image.track_map[h][c]].to_bytestream()?;
+                ImdFormat::write_track(&track, DiskCh::new(c as u16, h as u8), &mut imd)?;
             }
-            0x06 => {
-                // Compressed data with 'error' indicator.
-                let data_byte = image.read_le().map_err(|_e| DiskImageError::IoError)?;
-                let data = vec![data_byte; sector_size];
-                Ok(ImdSectorData {
-                    data,
-                    deleted: false,
-                    error: true,
-                })
+        }
+
+        output
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        output.write_all(&imd).map_err(|_| DiskImageError::IoError)?;
+
+        Ok(())
+    }
+
+    /// Write the track header, sector maps and sector data records of a ByteStream track.
+    fn write_track(track: &TrackData, ch: DiskCh, imd: &mut Vec<u8>) -> Result<(), DiskImageError> {
+        let TrackData::ByteStream { sectors, data, .. } = track else {
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        let Some(mode) = imd_rate_to_mode(track.data_rate(), track.encoding()) else {
+            log::error!(
+                "save_image(): Track {} has an encoding or data rate IMD can't store: {:?} {}",
+                ch,
+                track.encoding(),
+                track.data_rate()
+            );
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        let (Ok(cylinder), Ok(sector_ct)) = (u8::try_from(ch.c()), u8::try_from(sectors.len())) else {
+            log::error!("save_image(): Track {} has too many cylinders or sectors for IMD.", ch);
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        if sectors.iter().any(|si| si.cylinder_id > u8::MAX as u16 || si.n > 8) {
+            log::error!("save_image(): Track {} has a sector ID IMD can't store.", ch);
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        // Sizes other than 128 to 8192 bytes, or a mix of sizes, need a sector size map.
+        let n = sectors.first().map_or(2, |si| si.n);
+        let has_size_map = n > 6 || sectors.iter().any(|si| si.n != n);
+        let has_cylinder_map = sectors.iter().any(|si| si.cylinder_id != ch.c());
+        let has_head_map = sectors.iter().any(|si| si.head_id != ch.h());
+
+        let mut head = ch.h();
+        if has_cylinder_map {
+            head |= 0x80;
+        }
+        if has_head_map {
+            head |= 0x40;
+        }
+        imd.extend([mode, cylinder, head, sector_ct, if has_size_map { 0xFF } else { n }]);
+        imd.extend(sectors.iter().map(|si| si.sector_id));
+        if has_cylinder_map {
+            imd.extend(sectors.iter().map(|si| si.cylinder_id as u8));
+        }
+        if has_head_map {
+            imd.extend(sectors.iter().map(|si| si.head_id));
+        }
+        if has_size_map {
+            for si in sectors {
+                imd.extend((DiskChsn::n_to_bytes(si.n) as u16).to_le_bytes());
             }
-            _ => Err(DiskImageError::FormatParseError),
         }
+
+        for si in sectors {
+            ImdFormat::write_data(si, data, imd);
+        }
+        Ok(())
     }
 
-    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
-        Err(DiskImageError::UnsupportedFormat)
+    /// Write the data record of a sector, with a data marker as described by
+    /// [`ImdFormat::read_data`]. IMD has no flag for a bad address CRC, so such sectors are
+    /// marked with the data error indicator.
+    fn write_data(si: &TrackSectorIndex, data: &[u8], imd: &mut Vec<u8>) {
+        if si.no_dam {
+            imd.push(0x00);
+            return;
+        }
+
+        let start = std::cmp::min(si.t_idx, data.len());
+        let end = std::cmp::min(si.t_idx + si.len, data.len());
+        let mut sector = data[start..end].to_vec();
+        sector.resize(DiskChsn::n_to_bytes(si.n), 0);
+
+        let compressed = sector.iter().all(|&b| b == sector[0]);
+        let mut flags = 0;
+        if si.deleted_mark {
+            flags |= 0x01;
+        }
+        if si.data_crc_error || si.address_crc_error {
+            flags |= 0x02;
+        }
+        imd.push(1 + (flags << 1) + u8::from(compressed));
+        if compressed {
+            imd.push(sector[0]);
+        } else {
+            imd.extend(sector);
+        }
     }
 }
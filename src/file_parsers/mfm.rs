@@ -30,14 +30,16 @@
 
     MFM format images are bitstream images produced by the HxC disk emulator software.
 */
-use crate::diskimage::DiskDescriptor;
+use crate::diskimage::{DiskDescriptor, ParseMode};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::structure_parsers::detect_encoding;
 use crate::{
     DiskCh, DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat,
     DEFAULT_SECTOR_SIZE,
 };
 use binrw::{binrw, BinRead};
+use bit_vec::BitVec;
 
 pub struct MfmFormat;
 
@@ -112,8 +114,11 @@ impl MfmFormat {
         ParserWriteCompatibility::Ok
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         image
             .seek(std::io::SeekFrom::Start(0))
@@ -213,9 +218,11 @@ impl MfmFormat {
             let cylinder;
             let head;
             let track_data;
+            let track_offset;
             let data_rate;
             match header {
                 TrackHeader::Standard(s_header) => {
+                    track_offset = s_header.track_offset as u64;
                     track_data = MfmFormat::read_track_data(
                         &mut image,
                         s_header.track_offset as u64,
@@ -226,6 +233,7 @@ impl MfmFormat {
                     data_rate = file_header.bit_rate as u32 * 100;
                 }
                 TrackHeader::Advanced(a_header) => {
+                    track_offset = a_header.track_offset as u64;
                     track_data = MfmFormat::read_track_data(
                         &mut image,
                         a_header.track_offset as u64,
@@ -238,15 +246,11 @@ impl MfmFormat {
             }
 
             // TODO: Handle advanced track headers
-            disk_image.add_track_bitstream(
-                DiskDataEncoding::Mfm,
-                disk_data_rate,
-                DiskCh::from((cylinder as u16, head)),
-                data_rate,
-                None,
-                &track_data,
-                None,
-            )?;
+            let ch = DiskCh::from((cylinder as u16, head));
+            // Hybrid disks may mix encodings from track to track, so detect the encoding of each.
+            let encoding = detect_encoding(&BitVec::from_bytes(&track_data)).unwrap_or(DiskDataEncoding::Mfm);
+            disk_image.add_track_bitstream(encoding, disk_data_rate, ch, data_rate, None, &track_data, None)?;
+            disk_image.set_track_source(ch, track_offset, track_data)?;
         }
 
         disk_image.descriptor = DiskDescriptor {
@@ -24,11 +24,18 @@
 
     --------------------------------------------------------------------------
 */
+use crate::diskimage::ParseMode;
 use crate::io::{ReadSeek, ReadWriteSeek};
-use crate::{DiskImage, DiskImageError, DiskImageFormat};
+use crate::registry;
+use crate::{DiskDataResolution, DiskImage, DiskImageError, DiskImageFormat};
 use bitflags::bitflags;
 
+pub mod a2r;
+pub mod adf;
 pub mod compression;
+pub mod cpc;
+pub mod cqm;
+pub mod dmk;
 pub mod f86;
 pub mod hfe;
 pub mod imd;
@@ -36,8 +43,11 @@ pub mod mfm;
 pub mod pri;
 pub mod psi;
 pub mod raw;
+pub mod scp;
+pub mod stx;
 pub mod tc;
 pub mod td0;
+pub mod woz;
 
 bitflags! {
     /// Bit flags representing the capabilities of a specific image format. Used to determine if a
@@ -58,6 +68,7 @@ bitflags! {
         const CAP_ENCODING_FM       = 0b0000_0100_0000_0000; // Can store FM encoding
         const CAP_ENCODING_MFM      = 0b0000_1000_0000_0000; // Can store MFM encoding
         const CAP_ENCODING_GCR      = 0b0001_0000_0000_0000; // Can store GCR encoding
+        const CAP_SUB_TRACKS        = 0b0010_0000_0000_0000; // Can store half-tracks and quarter-tracks
     }
 }
 
@@ -78,48 +89,140 @@ pub enum ParserWriteCompatibility {
     UnsupportedFormat,
 }
 
-pub(crate) const IMAGE_FORMATS: [DiskImageFormat; 9] = [
+pub(crate) const IMAGE_FORMATS: [DiskImageFormat; 17] = [
     DiskImageFormat::ImageDisk,
     DiskImageFormat::TeleDisk,
+    DiskImageFormat::PastiImage,
+    DiskImageFormat::CopyQm,
     DiskImageFormat::PceSectorImage,
     DiskImageFormat::PceBitstreamImage,
+    DiskImageFormat::WozImage,
+    DiskImageFormat::DmkImage,
     DiskImageFormat::RawSectorImage,
+    // After raw images, so that the shared "dsk" extension keeps resolving to raw sector images.
+    DiskImageFormat::CpcDskImage,
     DiskImageFormat::MfmBitstreamImage,
     DiskImageFormat::HfeImage,
     DiskImageFormat::F86Image,
     DiskImageFormat::TransCopyImage,
+    DiskImageFormat::SuperCardPro,
+    DiskImageFormat::A2rImage,
+    DiskImageFormat::AmigaDiskFile,
 ];
 
 /// Returns a list of advertised file extensions supported by available image format parsers.
 /// This is a convenience function for use in file dialogs - internal image detection is not based
 /// on file extension, but by image file content and size.
 pub fn supported_extensions() -> Vec<&'static str> {
-    IMAGE_FORMATS.iter().flat_map(|f| f.extensions()).collect()
+    registry::all_formats().iter().flat_map(|f| f.extensions()).collect()
 }
 
 /// Returns a DiskImageFormat enum variant based on the file extension provided. If the extension
 /// is not recognized, None is returned.
 pub fn format_from_ext(ext: &str) -> Option<DiskImageFormat> {
-    for format in IMAGE_FORMATS.iter() {
-        if format.extensions().contains(&ext.to_lowercase().as_str()) {
-            return Some(*format);
-        }
-    }
-    None
+    let ext = ext.to_lowercase();
+    registry::all_formats()
+        .into_iter()
+        .find(|format| format.extensions().contains(&ext.as_str()))
 }
 
 /// Returns a list of image formats and their associated file extensions that support the specified
 /// capabilities.
 pub fn formats_from_caps(caps: FormatCaps) -> Vec<(DiskImageFormat, Vec<String>)> {
-    let format_vec = IMAGE_FORMATS
-        .iter()
+    let format_vec = registry::all_formats()
+        .into_iter()
         .filter(|f| f.capabilities().contains(caps))
-        .map(|f| (*f, f.extensions().iter().map(|s| s.to_string()).collect()))
+        .map(|f| (f, f.extensions().iter().map(|s| s.to_string()).collect()))
         .collect();
 
     format_vec
 }
 
+/// A description of a compiled-in image format and what fluxfox can do with it, as returned by
+/// [`formats`].
+#[derive(Clone, Debug)]
+pub struct FormatInfo {
+    /// The format's DiskImageFormat enum variant.
+    pub format: DiskImageFormat,
+    /// A human-readable name for the format.
+    pub name: String,
+    /// The file extensions associated with the format, in lowercase and without a leading dot.
+    pub extensions: Vec<&'static str>,
+    /// A short description of how the format is identified by its content.
+    pub magic: &'static str,
+    /// The capability flags of the format.
+    pub capabilities: FormatCaps,
+    /// True if images of this format can be loaded.
+    pub can_read: bool,
+    /// True if images can be saved in this format.
+    pub can_write: bool,
+    /// True if saving in this format preserves track bitstreams and not just sector data.
+    pub lossless: bool,
+}
+
+/// Returns a description of every compiled-in image format parser, followed by any formats
+/// registered with [`registry::register_format`].
+/// This is intended for GUI file dialogs and converters that need to build lists of loadable and
+/// saveable formats at runtime.
+pub fn formats() -> Vec<FormatInfo> {
+    registry::all_formats()
+        .into_iter()
+        .map(|f| {
+            let save_resolution = save_resolution(f);
+            FormatInfo {
+                format: f,
+                name: f.to_string(),
+                extensions: f.extensions(),
+                magic: magic(f),
+                capabilities: f.capabilities(),
+                can_read: true,
+                can_write: save_resolution.is_some(),
+                lossless: save_resolution == Some(DiskDataResolution::BitStream),
+            }
+        })
+        .collect()
+}
+
+/// Return the resolution at which `format` is saved by [`ImageParser::save_image`], or None if the
+/// format cannot be saved.
+fn save_resolution(format: DiskImageFormat) -> Option<DiskDataResolution> {
+    match format {
+        DiskImageFormat::RawSectorImage => Some(DiskDataResolution::ByteStream),
+        DiskImageFormat::ImageDisk => Some(DiskDataResolution::ByteStream),
+        DiskImageFormat::PceSectorImage => Some(DiskDataResolution::ByteStream),
+        DiskImageFormat::AmigaDiskFile => Some(DiskDataResolution::ByteStream),
+        DiskImageFormat::PceBitstreamImage => Some(DiskDataResolution::BitStream),
+        DiskImageFormat::HfeImage => Some(DiskDataResolution::BitStream),
+        DiskImageFormat::F86Image => Some(DiskDataResolution::BitStream),
+        _ => None,
+    }
+}
+
+/// Return a short description of the content `format` is detected by.
+fn magic(format: DiskImageFormat) -> &'static str {
+    match format {
+        DiskImageFormat::RawSectorImage => "none; detected by file size",
+        DiskImageFormat::ImageDisk => "ASCII header \"IMD v.vv: \"",
+        DiskImageFormat::PceSectorImage => "\"PSI \" chunk",
+        DiskImageFormat::PceBitstreamImage => "\"PRI \" chunk",
+        DiskImageFormat::MfmBitstreamImage => "\"HXCMFM\"",
+        DiskImageFormat::TeleDisk => "\"TD\" or \"td\"",
+        DiskImageFormat::KryofluxStream => "KryoFlux stream OOB blocks",
+        DiskImageFormat::HfeImage => "\"HXCPICFE\"",
+        DiskImageFormat::F86Image => "\"86BF\", version 2.12",
+        DiskImageFormat::TransCopyImage => "0x5A 0xA5",
+        DiskImageFormat::SuperCardPro => "\"SCP\"",
+        DiskImageFormat::A2rImage => "\"A2R2\" or \"A2R3\"",
+        DiskImageFormat::AmigaDiskFile => "none; detected by file size",
+        DiskImageFormat::WozImage => "\"WOZ1\" or \"WOZ2\", then 0xFF 0x0A 0x0D 0x0A",
+        DiskImageFormat::PastiImage => "\"RSY\\0\"",
+        DiskImageFormat::CopyQm => "\"CQ\" 0x14",
+        DiskImageFormat::DmkImage => "none; detected by header and file size",
+        DiskImageFormat::CpcDskImage => "\"EXTENDED\" or \"MV - CPC\"",
+        DiskImageFormat::Custom(id) => registry::custom_format(id).map(|f| f.magic).unwrap_or("unregistered"),
+    }
+}
+
 /// A trait to be implemented by disk image parsers. Called via enum dispatch.
 pub trait ImageParser {
     /// Return the capability flags for this format.
@@ -129,7 +232,14 @@ pub trait ImageParser {
     /// Return a list of file extensions associated with the parser.
     fn extensions(&self) -> Vec<&'static str>;
     /// Create a DiskImage from the specified image buffer, or DiskImageError if the format is not supported.
-    fn load_image<RWS: ReadSeek>(&self, image_buf: RWS) -> Result<DiskImage, DiskImageError>;
+    /// Violations of the format's specification are recovered from where possible.
+    fn load_image<RWS: ReadSeek>(&self, image_buf: RWS) -> Result<DiskImage, DiskImageError> {
+        self.load_image_with_mode(image_buf, ParseMode::Lenient)
+    }
+    /// Create a DiskImage from the specified image buffer, checking it against the format's
+    /// specification as strictly as `mode` requires.
+    fn load_image_with_mode<RWS: ReadSeek>(&self, image_buf: RWS, mode: ParseMode)
+        -> Result<DiskImage, DiskImageError>;
     /// Return true if the parser can write the specified disk image. Not all formats are writable
     /// at all, and not all DiskImages can be represented in the specified format.
     fn can_write(&self, image: &DiskImage) -> ParserWriteCompatibility;
@@ -148,6 +258,15 @@ impl ImageParser for DiskImageFormat {
             DiskImageFormat::HfeImage => hfe::HfeFormat::capabilities(),
             DiskImageFormat::F86Image => f86::F86Format::capabilities(),
             DiskImageFormat::TransCopyImage => tc::TCFormat::capabilities(),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::capabilities(),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::capabilities(),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::capabilities(),
+            DiskImageFormat::WozImage => woz::WozFormat::capabilities(),
+            DiskImageFormat::PastiImage => stx::StxFormat::capabilities(),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::capabilities(),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::capabilities(),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::capabilities(),
+            DiskImageFormat::Custom(id) => registry::custom_format(*id).map(|f| f.capabilities).unwrap_or_default(),
             _ => FormatCaps::empty(),
         }
     }
@@ -163,6 +282,21 @@ impl ImageParser for DiskImageFormat {
             DiskImageFormat::HfeImage => hfe::HfeFormat::detect(image_buf),
             DiskImageFormat::F86Image => f86::F86Format::detect(image_buf),
             DiskImageFormat::TransCopyImage => tc::TCFormat::detect(image_buf),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::detect(image_buf),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::detect(image_buf),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::detect(image_buf),
+            DiskImageFormat::WozImage => woz::WozFormat::detect(image_buf),
+            DiskImageFormat::PastiImage => stx::StxFormat::detect(image_buf),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::detect(image_buf),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::detect(image_buf),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::detect(image_buf),
+            DiskImageFormat::Custom(id) => {
+                let mut image_buf = image_buf;
+                if image_buf.seek(std::io::SeekFrom::Start(0)).is_err() {
+                    return false;
+                }
+                registry::custom_format(*id).is_some_and(|f| (f.detect)(&mut image_buf))
+            }
             _ => false,
         }
     }
@@ -178,21 +312,50 @@ impl ImageParser for DiskImageFormat {
             DiskImageFormat::HfeImage => hfe::HfeFormat::extensions(),
             DiskImageFormat::F86Image => f86::F86Format::extensions(),
             DiskImageFormat::TransCopyImage => tc::TCFormat::extensions(),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::extensions(),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::extensions(),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::extensions(),
+            DiskImageFormat::WozImage => woz::WozFormat::extensions(),
+            DiskImageFormat::PastiImage => stx::StxFormat::extensions(),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::extensions(),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::extensions(),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::extensions(),
+            DiskImageFormat::Custom(id) => registry::custom_format(*id).map(|f| f.extensions).unwrap_or_default(),
             _ => vec![],
         }
     }
 
-    fn load_image<RWS: ReadSeek>(&self, image_buf: RWS) -> Result<DiskImage, DiskImageError> {
+    fn load_image_with_mode<RWS: ReadSeek>(
+        &self,
+        image_buf: RWS,
+        mode: ParseMode,
+    ) -> Result<DiskImage, DiskImageError> {
         match self {
-            DiskImageFormat::RawSectorImage => raw::RawFormat::load_image(image_buf),
-            DiskImageFormat::ImageDisk => imd::ImdFormat::load_image(image_buf),
-            DiskImageFormat::TeleDisk => td0::Td0Format::load_image(image_buf),
-            DiskImageFormat::PceSectorImage => psi::PsiFormat::load_image(image_buf),
-            DiskImageFormat::PceBitstreamImage => pri::PriFormat::load_image(image_buf),
-            DiskImageFormat::MfmBitstreamImage => mfm::MfmFormat::load_image(image_buf),
-            DiskImageFormat::HfeImage => hfe::HfeFormat::load_image(image_buf),
-            DiskImageFormat::F86Image => f86::F86Format::load_image(image_buf),
-            DiskImageFormat::TransCopyImage => tc::TCFormat::load_image(image_buf),
+            DiskImageFormat::RawSectorImage => raw::RawFormat::load_image(image_buf, mode),
+            DiskImageFormat::ImageDisk => imd::ImdFormat::load_image(image_buf, mode),
+            DiskImageFormat::TeleDisk => td0::Td0Format::load_image(image_buf, mode),
+            DiskImageFormat::PceSectorImage => psi::PsiFormat::load_image(image_buf, mode),
+            DiskImageFormat::PceBitstreamImage => pri::PriFormat::load_image(image_buf, mode),
+            DiskImageFormat::MfmBitstreamImage => mfm::MfmFormat::load_image(image_buf, mode),
+            DiskImageFormat::HfeImage => hfe::HfeFormat::load_image(image_buf, mode),
+            DiskImageFormat::F86Image => f86::F86Format::load_image(image_buf, mode),
+            DiskImageFormat::TransCopyImage => tc::TCFormat::load_image(image_buf, mode),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::load_image(image_buf, mode),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::load_image(image_buf, mode),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::load_image(image_buf, mode),
+            DiskImageFormat::WozImage => woz::WozFormat::load_image(image_buf, mode),
+            DiskImageFormat::PastiImage => stx::StxFormat::load_image(image_buf, mode),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::load_image(image_buf, mode),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::load_image(image_buf, mode),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::load_image(image_buf, mode),
+            DiskImageFormat::Custom(id) => {
+                let mut image_buf = image_buf;
+                let format = registry::custom_format(*id).ok_or(DiskImageError::UnknownFormat)?;
+                image_buf
+                    .seek(std::io::SeekFrom::Start(0))
+                    .map_err(|_| DiskImageError::IoError)?;
+                (format.load)(&mut image_buf, mode)
+            }
             _ => Err(DiskImageError::UnknownFormat),
         }
     }
@@ -208,6 +371,14 @@ impl ImageParser for DiskImageFormat {
             DiskImageFormat::HfeImage => hfe::HfeFormat::can_write(image),
             DiskImageFormat::F86Image => f86::F86Format::can_write(image),
             DiskImageFormat::TransCopyImage => tc::TCFormat::can_write(image),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::can_write(image),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::can_write(image),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::can_write(image),
+            DiskImageFormat::WozImage => woz::WozFormat::can_write(image),
+            DiskImageFormat::PastiImage => stx::StxFormat::can_write(image),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::can_write(image),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::can_write(image),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::can_write(image),
             _ => ParserWriteCompatibility::UnsupportedFormat,
         }
     }
@@ -223,11 +394,39 @@ impl ImageParser for DiskImageFormat {
             DiskImageFormat::HfeImage => hfe::HfeFormat::save_image(image, image_buf),
             DiskImageFormat::F86Image => f86::F86Format::save_image(image, image_buf),
             DiskImageFormat::TransCopyImage => tc::TCFormat::save_image(image, image_buf),
+            DiskImageFormat::SuperCardPro => scp::ScpFormat::save_image(image, image_buf),
+            DiskImageFormat::A2rImage => a2r::A2rFormat::save_image(image, image_buf),
+            DiskImageFormat::AmigaDiskFile => adf::AdfFormat::save_image(image, image_buf),
+            DiskImageFormat::WozImage => woz::WozFormat::save_image(image, image_buf),
+            DiskImageFormat::PastiImage => stx::StxFormat::save_image(image, image_buf),
+            DiskImageFormat::CopyQm => cqm::CqmFormat::save_image(image, image_buf),
+            DiskImageFormat::DmkImage => dmk::DmkFormat::save_image(image, image_buf),
+            DiskImageFormat::CpcDskImage => cpc::CpcDskFormat::save_image(image, image_buf),
             _ => Err(DiskImageError::UnknownFormat),
         }
     }
 }
 
+/// A trait to be implemented by disk image exporters. Called via enum dispatch.
+///
+/// Unlike [`ImageParser::save_image`], which copies track data directly, an exporter resolves
+/// each sector through the [`DiskImage`] sector interface, so it works for any track resolution.
+/// Export fails if the image contains features the format cannot represent.
+pub trait ImageWriter {
+    /// Write `image` to `output` in this format, or return `DiskImageError::IncompatibleImage` if
+    /// the image cannot be represented exactly.
+    fn write_image<RWS: ReadWriteSeek>(&self, image: &mut DiskImage, output: &mut RWS) -> Result<(), DiskImageError>;
+}
+
+impl ImageWriter for DiskImageFormat {
+    fn write_image<RWS: ReadWriteSeek>(&self, image: &mut DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        match self {
+            DiskImageFormat::RawSectorImage => raw::RawFormat::write_image(image, output),
+            _ => Err(DiskImageError::UnsupportedFormat),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,9 +36,10 @@
 */
 
 use crate::chs::DiskCh;
-use crate::diskimage::DiskDescriptor;
+use crate::diskimage::{DiskDescriptor, ParseMode};
 use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
 use crate::io::{Cursor, ReadSeek, ReadWriteSeek, Write};
+use crate::structure_parsers::detect_encoding;
 
 use crate::trackdata::TrackData;
 use crate::{
@@ -47,6 +48,7 @@ use crate::{
 };
 use binrw::meta::WriteEndian;
 use binrw::{binrw, BinRead, BinWrite};
+use bit_vec::BitVec;
 
 pub struct PriFormat;
 pub const MAXIMUM_CHUNK_SIZE: usize = 0x100000; // Reasonable 1MB limit for chunk sizes.
@@ -133,6 +135,66 @@ pub struct PriChunk {
     pub chunk_type: PriChunkType,
     pub size: u32,
     pub data: Vec<u8>,
+    /// Whether the chunk CRC matched the chunk contents.
+    pub crc_valid: bool,
+}
+
+/// A track being assembled from a track header chunk and the chunks that follow it. The track is
+/// added to the image when the next track header or the end chunk is read, as weak mask and bit
+/// clock chunks may follow the track data.
+struct PriPendingTrack {
+    ch: DiskCh,
+    bit_length: usize,
+    default_clock: u32,
+    clock: u32,
+    data: Vec<u8>,
+    weak: Option<Vec<u8>>,
+}
+
+impl PriPendingTrack {
+    /// Set the bits of the weak mask starting at `bit_offset` from the bits set in `mask`.
+    fn merge_weak(&mut self, bit_offset: usize, mask: &[u8]) {
+        let weak = self.weak.get_or_insert_with(|| vec![0; self.bit_length.div_ceil(8)]);
+        for (i, byte) in mask.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) == 0 {
+                    continue;
+                }
+                let pos = bit_offset + i * 8 + bit;
+                if pos < self.bit_length {
+                    weak[pos / 8] |= 0x80 >> (pos % 8);
+                }
+            }
+        }
+    }
+
+    fn add_to(
+        mut self,
+        disk_image: &mut DiskImage,
+        disk_data_rate: &mut Option<DiskDataRate>,
+    ) -> Result<(), DiskImageError> {
+        // Set the global disk data rate once.
+        if disk_data_rate.is_none() {
+            *disk_data_rate = Some(DiskDataRate::from(self.clock));
+        }
+
+        if let Some(weak) = self.weak.as_mut() {
+            weak.resize(self.data.len(), 0);
+        }
+
+        // PRI does not record the encoding of a track, so detect it from the address marks.
+        let encoding = detect_encoding(&BitVec::from_bytes(&self.data)).unwrap_or(DiskDataEncoding::Mfm);
+
+        disk_image.add_track_bitstream(
+            encoding,
+            DiskDataRate::from(self.clock),
+            self.ch,
+            self.clock,
+            Some(self.bit_length),
+            &self.data,
+            self.weak.as_deref(),
+        )
+    }
 }
 
 pub(crate) fn pri_crc(buf: &[u8]) -> u32 {
@@ -180,7 +242,8 @@ impl PriFormat {
     }
 
     pub(crate) fn capabilities() -> FormatCaps {
-        bitstream_flags() | FormatCaps::CAP_COMMENT | FormatCaps::CAP_WEAK_BITS
+        // The encoding of each track is not stored, but is detected on load.
+        bitstream_flags() | FormatCaps::CAP_COMMENT | FormatCaps::CAP_WEAK_BITS | FormatCaps::CAP_TRACK_ENCODING
     }
 
     pub(crate) fn extensions() -> Vec<&'static str> {
@@ -203,7 +266,10 @@ impl PriFormat {
     /// Return the compatibility of the image with the parser.
     pub(crate) fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
         if let Some(resolution) = image.resolution {
-            if !matches!(resolution, DiskDataResolution::BitStream) {
+            if !matches!(
+                resolution,
+                DiskDataResolution::BitStream | DiskDataResolution::FluxStream
+            ) {
                 return ParserWriteCompatibility::Incompatible;
             }
         } else {
@@ -259,7 +325,11 @@ impl PriFormat {
         let chunk_crc = PriChunkCrc::read(&mut image).map_err(|_| DiskImageError::IoError)?;
 
         if chunk_crc.crc != crc_calc {
-            return Err(DiskImageError::CrcError);
+            log::warn!(
+                "Chunk CRC {:04X} does not match calculated CRC {:04X}",
+                chunk_crc.crc,
+                crc_calc
+            );
         }
 
         //log::trace!("CRC matched: {:04X} {:04X}", chunk_crc.crc, crc_calc);
@@ -268,6 +338,7 @@ impl PriFormat {
             chunk_type,
             size: chunk_header.size,
             data: buffer[8..].to_vec(),
+            crc_valid: chunk_crc.crc == crc_calc,
         };
         Ok(chunk)
     }
@@ -411,8 +482,11 @@ impl PriFormat {
         Ok(())
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         // Seek to start of image.
         image
@@ -430,23 +504,24 @@ impl PriFormat {
         log::trace!("Read PRI file header. Format version: {}", file_header.version);
 
         let mut comment_string = String::new();
-        let mut current_ch = DiskCh::default();
-        let current_crc_error = false;
 
         let mut heads_seen: FoxHashSet<u8> = FoxHashSet::new();
         let mut cylinders_seen: FoxHashSet<u16> = FoxHashSet::new();
 
-        let mut default_bit_clock = 0;
-        let mut current_bit_clock = 0;
-        let mut expected_data_size = 0;
-        let mut track_header = PriTrackHeader::default();
-
+        let mut pending: Option<PriPendingTrack> = None;
         let mut disk_data_rate = None;
 
         while chunk.chunk_type != PriChunkType::End {
+            if !chunk.crc_valid {
+                disk_image.spec_violation(None, format!("{:?} chunk has a bad CRC", chunk.chunk_type))?;
+            }
             match chunk.chunk_type {
                 PriChunkType::TrackHeader => {
-                    track_header = PriTrackHeader::read(&mut Cursor::new(&chunk.data))
+                    if let Some(track) = pending.take() {
+                        track.add_to(&mut disk_image, &mut disk_data_rate)?;
+                    }
+
+                    let track_header = PriTrackHeader::read(&mut Cursor::new(&chunk.data))
                         .map_err(|_| DiskImageError::FormatParseError)?;
 
                     let ch = DiskCh::from((track_header.cylinder as u16, track_header.head as u8));
@@ -457,57 +532,76 @@ impl PriFormat {
                         track_header.clock_rate
                     );
 
-                    expected_data_size =
-                        track_header.bit_length as usize / 8 + if track_header.bit_length % 8 != 0 { 1 } else { 0 };
-
-                    default_bit_clock = track_header.clock_rate;
                     cylinders_seen.insert(track_header.cylinder as u16);
                     heads_seen.insert(track_header.head as u8);
-                    current_ch = ch;
+                    pending = Some(PriPendingTrack {
+                        ch,
+                        bit_length: track_header.bit_length as usize,
+                        default_clock: track_header.clock_rate,
+                        clock: track_header.clock_rate,
+                        data: Vec::new(),
+                        weak: None,
+                    });
                 }
                 PriChunkType::AlternateBitClock => {
+                    let Some(track) = pending.as_mut() else {
+                        disk_image.spec_violation(
+                            None,
+                            "Alternate bit clock chunk without a preceding track header".to_string(),
+                        )?;
+                        chunk = PriFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
                     let alt_clock = PriAlternateClock::read(&mut Cursor::new(&chunk.data))
                         .map_err(|_| DiskImageError::FormatParseError)?;
 
-                    if alt_clock.new_clock == 0 {
-                        current_bit_clock = default_bit_clock;
+                    let new_bit_clock = if alt_clock.new_clock == 0 {
+                        track.default_clock
                     } else {
-                        let new_bit_clock =
-                            ((alt_clock.new_clock as f64 / u16::MAX as f64) * default_bit_clock as f64) as u32;
-
-                        current_bit_clock = new_bit_clock;
-                    }
+                        ((alt_clock.new_clock as f64 / u16::MAX as f64) * track.default_clock as f64) as u32
+                    };
                     log::trace!(
                         "Alternate bit clock. Bit offset: {} New clock: {}",
                         alt_clock.bit_offset,
-                        current_bit_clock
+                        new_bit_clock
                     );
+
+                    // A track has a single bit clock, so only a clock change from the start of the
+                    // track can be represented.
+                    if alt_clock.bit_offset == 0 {
+                        track.clock = new_bit_clock;
+                    } else {
+                        disk_image.add_load_warning(
+                            Some(track.ch),
+                            format!(
+                                "Ignoring bit clock change to {} at bit offset {}",
+                                new_bit_clock, alt_clock.bit_offset
+                            ),
+                        );
+                    }
                 }
                 PriChunkType::TrackData => {
+                    let Some(track) = pending.as_mut() else {
+                        disk_image
+                            .spec_violation(None, "Track data chunk without a preceding track header".to_string())?;
+                        chunk = PriFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
                     log::trace!(
-                        "Track data chunk: {} size: {} expected size: {} crc_error: {}",
-                        current_ch,
+                        "Track data chunk: {} size: {} expected size: {}",
+                        track.ch,
                         chunk.size,
-                        expected_data_size,
-                        current_crc_error
+                        track.bit_length.div_ceil(8)
                     );
-
-                    // Set the global disk data rate once.
-                    if disk_data_rate.is_none() {
-                        disk_data_rate = Some(DiskDataRate::from(current_bit_clock));
-                    }
-
-                    disk_image.add_track_bitstream(
-                        DiskDataEncoding::Mfm,
-                        DiskDataRate::from(current_bit_clock),
-                        current_ch,
-                        current_bit_clock,
-                        Some(track_header.bit_length as usize),
-                        &chunk.data,
-                        None,
-                    )?;
+                    track.data = chunk.data;
                 }
                 PriChunkType::WeakMask => {
+                    let Some(track) = pending.as_mut() else {
+                        disk_image
+                            .spec_violation(None, "Weak mask chunk without a preceding track header".to_string())?;
+                        chunk = PriFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
                     let weak_mask = PriWeakMask::read(&mut Cursor::new(&chunk.data))
                         .map_err(|_| DiskImageError::FormatParseError)?;
                     log::trace!(
@@ -515,6 +609,7 @@ impl PriFormat {
                         chunk.size,
                         weak_mask.bit_offset
                     );
+                    track.merge_weak(weak_mask.bit_offset as usize, &chunk.data[4..]);
                 }
                 PriChunkType::Text => {
                     // PSI docs:
@@ -535,15 +630,27 @@ impl PriFormat {
             chunk = PriFormat::read_chunk(&mut image)?;
         }
 
+        if let Some(track) = pending.take() {
+            track.add_to(&mut disk_image, &mut disk_data_rate)?;
+        }
+
         log::trace!("Comment: {}", comment_string);
 
         let head_ct = heads_seen.len() as u8;
         let cylinder_ct = cylinders_seen.len() as u16;
+        // Take the disk encoding from the first track, as we do the data rate.
+        let data_encoding = disk_image
+            .track_iter()
+            .next()
+            .map_or(DiskDataEncoding::Mfm, |track| track.encoding());
         disk_image.descriptor = DiskDescriptor {
             geometry: DiskCh::from((cylinder_ct, head_ct)),
-            data_rate: disk_data_rate.unwrap(),
-            data_encoding: DiskDataEncoding::Mfm,
-            density: DiskDensity::from(disk_data_rate.unwrap()),
+            data_rate: disk_data_rate.ok_or(DiskImageError::FormatParseError)?,
+            data_encoding,
+            density: match data_encoding {
+                DiskDataEncoding::Fm => DiskDensity::Standard,
+                _ => DiskDensity::from(disk_data_rate.unwrap()),
+            },
             default_sector_size: DEFAULT_SECTOR_SIZE,
             rpm: None,
             write_protect: None,
@@ -552,8 +659,17 @@ impl PriFormat {
         Ok(disk_image)
     }
 
+    /// Save the disk image as a PRI image. FluxStream tracks are written as the bitstream resolved
+    /// from their flux.
+    ///
+    /// Each track header records the track's data rate as its clock rate. A track whose data
+    /// clock differs from its data rate, such as a track recorded slightly fast or slow, is given
+    /// an alternate bit clock chunk from its start, and any weak bits are written as a weak mask.
     pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
-        if matches!(image.resolution(), DiskDataResolution::BitStream) {
+        if matches!(
+            image.resolution(),
+            DiskDataResolution::BitStream | DiskDataResolution::FluxStream
+        ) {
             log::trace!("Saving PRI image...");
         } else {
             log::error!("Unsupported image resolution.");
@@ -581,7 +697,15 @@ impl PriFormat {
                 cylinder,
                 head,
                 data,
-                sector_ids,
+                ..
+            }
+            | TrackData::FluxStream {
+                encoding,
+                data_rate,
+                data_clock,
+                cylinder,
+                head,
+                data,
                 ..
             } = track
             {
@@ -589,23 +713,37 @@ impl PriFormat {
                     "Track c:{} h:{} sectors: {} encoding: {:?} data_rate: {:?} bit length: {}",
                     cylinder,
                     head,
-                    sector_ids.len(),
+                    track.get_sector_ct(),
                     encoding,
                     data_rate,
                     data.len(),
                 );
 
-                // Write the track header.
+                track.warn_length_changed("pri::save_image()");
+
+                // Write the track header. The bit length is exact, so long and short tracks
+                // survive.
+                let clock_rate = u32::from(*data_rate);
                 let track_header = PriTrackHeader {
                     cylinder: *cylinder as u32,
                     head: *head as u32,
                     bit_length: data.len() as u32,
-                    clock_rate: *data_clock,
+                    clock_rate,
                 };
                 PriFormat::write_chunk(output, PriChunkType::TrackHeader, &track_header)?;
 
+                // The alternate clock is a fraction of the default clock, in 1/65535ths. Tracks
+                // created without a data clock run at their data rate.
+                if *data_clock != 0 && *data_clock != clock_rate && clock_rate != 0 {
+                    let alt_clock = PriAlternateClock {
+                        bit_offset: 0,
+                        new_clock: (*data_clock as f64 / clock_rate as f64 * u16::MAX as f64).round() as u32,
+                    };
+                    PriFormat::write_chunk(output, PriChunkType::AlternateBitClock, &alt_clock)?;
+                }
+
                 // Write the track data.
-                let track_data = data.data();
+                let track_data = data.bits().to_bytes();
                 PriFormat::write_chunk(output, PriChunkType::TrackData, &track_data)?;
 
                 // Write the weak mask, if any bits are set in the weak bit mask.
@@ -632,7 +770,7 @@ impl PriFormat {
 
                         // Write the weak mask data.
                         weak_buffer
-                            .write_all(&weak_data[slice_start..slice_end])
+                            .write_all(&weak_data[slice_start..=slice_end])
                             .map_err(|_| DiskImageError::IoError)?;
 
                         PriFormat::write_chunk_raw(output, PriChunkType::WeakMask, weak_buffer.get_ref())?;
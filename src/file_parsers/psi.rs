@@ -36,15 +36,16 @@
 */
 
 use crate::chs::{DiskCh, DiskChs, DiskChsn};
-use crate::diskimage::{DiskDescriptor, SectorDescriptor};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{Cursor, ReadSeek, ReadWriteSeek};
+use crate::trackdata::TrackData;
 
 use crate::{
     DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, FoxHashMap, FoxHashSet,
     DEFAULT_SECTOR_SIZE,
 };
-use binrw::{binrw, BinRead};
+use binrw::{binrw, BinRead, BinWrite};
 
 pub struct PsiFormat;
 pub const MAXIMUM_CHUNK_SIZE: usize = 0x100000; // Reasonable 1MB limit for chunk sizes.
@@ -91,6 +92,24 @@ pub struct PsiSectorHeader {
     pub compressed_data: u8,
 }
 
+#[derive(Debug)]
+#[binrw]
+#[brw(big)]
+pub struct PsiIbmSectorHeader {
+    pub cylinder: u8,
+    pub head: u8,
+    pub sector: u8,
+    pub n: u8,
+    pub flags: u8,
+    pub encoding: u8,
+}
+
+#[binrw]
+#[brw(big)]
+pub struct PsiU32 {
+    pub value: u32,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum PsiChunkType {
     FileHeader,
@@ -110,6 +129,60 @@ pub enum PsiChunkType {
 pub struct PsiChunk {
     pub chunk_type: PsiChunkType,
     pub data: Vec<u8>,
+    /// Whether the chunk CRC matched the chunk contents.
+    pub crc_valid: bool,
+}
+
+/// A sector being assembled from a sector header chunk and the chunks that follow it. A sector
+/// is added to its track when the next sector header or the end chunk is read.
+struct PsiPendingSector {
+    chs: DiskChs,
+    encoding: DiskDataEncoding,
+    data_rate: DiskDataRate,
+    alternate: bool,
+    sd: SectorDescriptor,
+}
+
+impl PsiPendingSector {
+    /// Mark any bits that differ between the sector data and an alternate read of it as weak.
+    fn merge_alternate(&mut self, alt_data: &[u8]) {
+        let diff: Vec<u8> = self.sd.data.iter().zip(alt_data).map(|(a, b)| a ^ b).collect();
+        self.merge_weak(&diff);
+    }
+
+    fn merge_weak(&mut self, mask: &[u8]) {
+        let len = std::cmp::max(self.sd.data.len(), mask.len());
+        let weak = self.sd.weak.get_or_insert_with(Vec::new);
+        if weak.len() < len {
+            weak.resize(len, 0);
+        }
+        for (w, m) in weak.iter_mut().zip(mask) {
+            *w |= m;
+        }
+    }
+
+    fn master(mut self, disk_image: &mut DiskImage, track_set: &mut FoxHashSet<DiskCh>) -> Result<(), DiskImageError> {
+        let ch = DiskCh::from(self.chs);
+        if !track_set.contains(&ch) {
+            // Tracks without sectors have no chunks, so add any skipped over as empty tracks.
+            if ch.h() < 2 {
+                while disk_image.track_map[ch.h() as usize].len() < ch.c() as usize {
+                    let empty_ch = DiskCh::new(disk_image.track_map[ch.h() as usize].len() as u16, ch.h());
+                    log::trace!("Adding empty track {}...", empty_ch);
+                    disk_image.add_track_bytestream(self.encoding, self.data_rate, empty_ch)?;
+                    track_set.insert(empty_ch);
+                }
+            }
+            log::trace!("Adding track {}...", ch);
+            disk_image.add_track_bytestream(self.encoding, self.data_rate, ch)?;
+            track_set.insert(ch);
+        }
+        // A weak mask may have been read before the sector data.
+        if let Some(weak) = self.sd.weak.as_mut() {
+            weak.resize(self.sd.data.len(), 0);
+        }
+        disk_image.master_sector(self.chs, &self.sd)
+    }
 }
 
 pub(crate) fn psi_crc(buf: &[u8]) -> u32 {
@@ -141,6 +214,27 @@ pub(crate) fn decode_psi_sector_format(sector_format: [u8; 2]) -> Option<(DiskDa
     }
 }
 
+/// Return the sector format of the file header for a disk of `density`, as decoded by
+/// [`decode_psi_sector_format`]. The encoding of each sector is given by its IBM sector header.
+pub(crate) fn encode_psi_sector_format(density: DiskDensity) -> [u8; 2] {
+    match density {
+        DiskDensity::Standard => [0x00, 0x00],
+        DiskDensity::Double => [0x01, 0x00],
+        DiskDensity::High => [0x02, 0x00],
+        DiskDensity::Extended => [0x02, 0x02],
+    }
+}
+
+/// Append a chunk with the specified `id` and `data` to `out`, followed by its CRC.
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    let start = out.len();
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+    let crc = psi_crc(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
 impl PsiFormat {
     #[allow(dead_code)]
     fn format() -> DiskImageFormat {
@@ -148,7 +242,17 @@ impl PsiFormat {
     }
 
     pub(crate) fn capabilities() -> FormatCaps {
-        FormatCaps::empty()
+        FormatCaps::CAP_VARIABLE_SPT
+            | FormatCaps::CAP_VARIABLE_SSPT
+            | FormatCaps::CAP_ADDRESS_CRC
+            | FormatCaps::CAP_DATA_CRC
+            | FormatCaps::CAP_DATA_DELETED
+            | FormatCaps::CAP_SID_OVERRIDE
+            | FormatCaps::CAP_COMMENT
+            | FormatCaps::CAP_TRACK_ENCODING
+            | FormatCaps::CAP_WEAK_BITS
+            | FormatCaps::CAP_ENCODING_FM
+            | FormatCaps::CAP_ENCODING_MFM
     }
 
     pub(crate) fn extensions() -> Vec<&'static str> {
@@ -168,9 +272,12 @@ impl PsiFormat {
         detected
     }
 
-    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
-        // TODO: Determine what data representations would lead to data loss for PSI.
-        ParserWriteCompatibility::Ok
+    pub(crate) fn can_write(image: &DiskImage) -> ParserWriteCompatibility {
+        if PsiFormat::capabilities().contains(image.required_caps()) {
+            ParserWriteCompatibility::Ok
+        } else {
+            ParserWriteCompatibility::DataLoss
+        }
     }
 
     pub(crate) fn read_chunk<RWS: ReadSeek>(mut image: RWS) -> Result<PsiChunk, DiskImageError> {
@@ -219,7 +326,11 @@ impl PsiFormat {
         let chunk_crc = PsiChunkCrc::read(&mut image).map_err(|_| DiskImageError::IoError)?;
 
         if chunk_crc.crc != crc_calc {
-            return Err(DiskImageError::CrcError);
+            log::warn!(
+                "Chunk CRC {:04X} does not match calculated CRC {:04X}",
+                chunk_crc.crc,
+                crc_calc
+            );
         }
 
         //log::trace!("CRC matched: {:04X} {:04X}", chunk_crc.crc, crc_calc);
@@ -227,12 +338,16 @@ impl PsiFormat {
         let chunk = PsiChunk {
             chunk_type,
             data: buffer[8..].to_vec(),
+            crc_valid: chunk_crc.crc == crc_calc,
         };
         Ok(chunk)
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         // Seek to start of image.
         image
@@ -252,8 +367,7 @@ impl PsiFormat {
         let (default_encoding, disk_density) =
             decode_psi_sector_format(file_header.sector_format).ok_or(DiskImageError::FormatParseError)?;
         let mut comment_string = String::new();
-        let mut current_chs = DiskChs::default();
-        let mut current_crc_error = false;
+        let mut pending: Option<PsiPendingSector> = None;
 
         let mut track_set: FoxHashSet<DiskCh> = FoxHashSet::new();
         let mut sector_counts: FoxHashMap<u8, u32> = FoxHashMap::new();
@@ -261,6 +375,9 @@ impl PsiFormat {
         let mut sectors_per_track = 0;
 
         while chunk.chunk_type != PsiChunkType::End {
+            if !chunk.crc_valid {
+                disk_image.spec_violation(None, format!("{:?} chunk has a bad CRC", chunk.chunk_type))?;
+            }
             match chunk.chunk_type {
                 PsiChunkType::FileHeader => {}
                 PsiChunkType::SectorHeader => {
@@ -268,14 +385,35 @@ impl PsiFormat {
                     let sector_header = PsiSectorHeader::read(&mut Cursor::new(&chunk.data))
                         .map_err(|_| DiskImageError::FormatParseError)?;
                     let chs = DiskChs::from((sector_header.cylinder, sector_header.head, sector_header.sector));
-                    let ch = DiskCh::from((sector_header.cylinder, sector_header.head));
+                    let compressed = sector_header.flags & SH_FLAG_COMPRESSED != 0;
+                    let crc_error = sector_header.flags & SH_FLAG_CRC_ERROR != 0;
+
+                    if sector_header.flags & SH_FLAG_ALTERNATE != 0 {
+                        // An alternate sector is another read of the previous sector. Any bits
+                        // that differ between the two reads are weak.
+                        log::trace!("Alternate sector data for {}", chs);
+                        let Some(pending) = pending.as_mut() else {
+                            disk_image
+                                .spec_violation(None, "Alternate sector data without a preceding sector".to_string())?;
+                            chunk = PsiFormat::read_chunk(&mut image)?;
+                            continue;
+                        };
+                        pending.alternate = true;
+                        if compressed {
+                            pending.merge_alternate(&vec![sector_header.compressed_data; sector_header.size as usize]);
+                        }
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
+                    }
+
+                    if let Some(sector) = pending.take() {
+                        sector.master(&mut disk_image, &mut track_set)?;
+                    }
 
                     heads_seen.insert(sector_header.head);
 
+                    let ch = DiskCh::from((sector_header.cylinder, sector_header.head));
                     if !track_set.contains(&ch) {
-                        log::trace!("Adding track...");
-                        disk_image.add_track_bytestream(default_encoding, DiskDataRate::from(disk_density), ch)?;
-                        track_set.insert(ch);
                         log::trace!("Observing sector count: {}", sectors_per_track);
                         sector_counts
                             .entry(sectors_per_track)
@@ -283,62 +421,128 @@ impl PsiFormat {
                             .or_insert(1);
                         sectors_per_track = 0;
                     }
+                    sectors_per_track += 1;
 
-                    if sector_header.flags & SH_FLAG_ALTERNATE != 0 {
-                        log::trace!("Alternate sector data.");
-                    }
-
-                    current_crc_error = sector_header.flags & SH_FLAG_CRC_ERROR != 0;
-
-                    // Write sector data immediately if compressed data is indicated (no sector data chunk follows)
-                    if sector_header.flags & SH_FLAG_COMPRESSED != 0 {
-                        log::trace!("Compressed sector data: {:02X}", sector_header.compressed_data);
-
-                        let chunk_expand = vec![sector_header.compressed_data; sector_header.size as usize];
+                    // Compressed sectors have no sector data chunk.
+                    let data = match compressed {
+                        true => {
+                            log::trace!("Compressed sector data: {:02X}", sector_header.compressed_data);
+                            vec![sector_header.compressed_data; sector_header.size as usize]
+                        }
+                        false => Vec::new(),
+                    };
 
-                        // Add this sector to track.
-                        let sd = SectorDescriptor {
+                    pending = Some(PsiPendingSector {
+                        chs,
+                        encoding: default_encoding,
+                        data_rate: DiskDataRate::from(disk_density),
+                        alternate: false,
+                        sd: SectorDescriptor {
                             id: chs.s(),
                             cylinder_id: None,
                             head_id: None,
-                            n: DiskChsn::bytes_to_n(chunk_expand.len()),
-                            data: chunk_expand,
+                            n: DiskChsn::bytes_to_n(sector_header.size as usize),
+                            data,
                             weak: None,
                             address_crc_error: false,
-                            data_crc_error: current_crc_error,
+                            data_crc_error: crc_error,
                             deleted_mark: false,
-                        };
+                            no_dam: false,
+                            position: None,
+                            read_time: None,
+                            timing: None,
+                        },
+                    });
 
-                        disk_image.master_sector(chs, &sd)?;
-                    }
-
-                    current_chs = chs;
                     log::trace!(
                         "Sector CHS: {} size: {} crc_error: {}",
                         chs,
                         sector_header.size,
-                        current_crc_error
+                        crc_error
                     );
                 }
                 PsiChunkType::SectorData => {
-                    log::trace!("Sector data chunk: {} crc_error: {}", current_chs, current_crc_error);
-
-                    // Add this sector to track.
-                    let sd = SectorDescriptor {
-                        id: current_chs.s(),
-                        cylinder_id: None,
-                        head_id: None,
-                        n: DiskChsn::bytes_to_n(chunk.data.len()),
-                        data: chunk.data,
-                        weak: None,
-                        address_crc_error: false,
-                        data_crc_error: current_crc_error,
-                        deleted_mark: false,
+                    let Some(pending) = pending.as_mut() else {
+                        disk_image
+                            .spec_violation(None, "Sector data chunk without a preceding sector header".to_string())?;
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
                     };
+                    log::trace!(
+                        "Sector data chunk: {} alternate: {} crc_error: {}",
+                        pending.chs,
+                        pending.alternate,
+                        pending.sd.data_crc_error
+                    );
+                    if pending.alternate {
+                        pending.merge_alternate(&chunk.data);
+                    } else {
+                        pending.sd.data = chunk.data;
+                    }
+                }
+                PsiChunkType::WeakMask => {
+                    let Some(pending) = pending.as_mut() else {
+                        disk_image
+                            .spec_violation(None, "Weak mask chunk without a preceding sector header".to_string())?;
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
+                    log::trace!("Weak mask chunk: {}", pending.chs);
+                    pending.merge_weak(&chunk.data);
+                }
+                PsiChunkType::IbmFmSectorHeader | PsiChunkType::IbmMfmSectorHeader => {
+                    let Some(pending) = pending.as_mut() else {
+                        disk_image.spec_violation(
+                            None,
+                            "IBM sector header chunk without a preceding sector header".to_string(),
+                        )?;
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
+                    let ibm_header = PsiIbmSectorHeader::read(&mut Cursor::new(&chunk.data))
+                        .map_err(|_| DiskImageError::FormatParseError)?;
+                    log::trace!("IBM sector header: {:?}", ibm_header);
 
-                    disk_image.master_sector(current_chs, &sd)?;
-
-                    sectors_per_track += 1;
+                    pending.encoding = match chunk.chunk_type {
+                        PsiChunkType::IbmFmSectorHeader => DiskDataEncoding::Fm,
+                        _ => DiskDataEncoding::Mfm,
+                    };
+                    // The IBM sector header carries the sector ID as recorded on the track, which
+                    // may not match the physical location given in the sector header.
+                    pending.sd.id = ibm_header.sector;
+                    pending.sd.cylinder_id = Some(ibm_header.cylinder as u16);
+                    pending.sd.head_id = Some(ibm_header.head);
+                    pending.sd.n = ibm_header.n;
+                    pending.sd.address_crc_error = ibm_header.flags & SH_IBM_FLAG_CRC_ERROR_ID != 0;
+                    pending.sd.data_crc_error |= ibm_header.flags & SH_IBM_FLAG_CRC_ERROR_DATA != 0;
+                    pending.sd.deleted_mark = ibm_header.flags & SH_IBM_DELETED_DATA != 0;
+                    pending.sd.no_dam = ibm_header.flags & SH_IBM_MISSING_DATA != 0;
+                }
+                PsiChunkType::SectorPositionOffset => {
+                    let Some(pending) = pending.as_mut() else {
+                        disk_image.spec_violation(
+                            None,
+                            "Sector position chunk without a preceding sector header".to_string(),
+                        )?;
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
+                    let offset =
+                        PsiU32::read(&mut Cursor::new(&chunk.data)).map_err(|_| DiskImageError::FormatParseError)?;
+                    pending.sd.position = Some(offset.value);
+                }
+                PsiChunkType::ClockRateAdjustment => {
+                    let Some(pending) = pending.as_mut() else {
+                        disk_image.spec_violation(
+                            None,
+                            "Sector read time chunk without a preceding sector header".to_string(),
+                        )?;
+                        chunk = PsiFormat::read_chunk(&mut image)?;
+                        continue;
+                    };
+                    let time =
+                        PsiU32::read(&mut Cursor::new(&chunk.data)).map_err(|_| DiskImageError::FormatParseError)?;
+                    pending.sd.read_time = Some(time.value);
                 }
                 PsiChunkType::Text => {
                     // PSI docs:
@@ -352,13 +556,21 @@ impl PsiFormat {
                     break;
                 }
                 _ => {
-                    log::warn!("Unhandled chunk type: {:?}", chunk.chunk_type);
+                    disk_image.add_load_warning(None, format!("Unhandled chunk type: {:?}", chunk.chunk_type));
                 }
             }
 
             chunk = PsiFormat::read_chunk(&mut image)?;
         }
 
+        if let Some(sector) = pending.take() {
+            sector.master(&mut disk_image, &mut track_set)?;
+        }
+
+        if !comment_string.is_empty() {
+            disk_image.comment = Some(comment_string);
+        }
+
         let head_ct = heads_seen.len() as u8;
         let track_ct = track_set.len() as u16;
         disk_image.descriptor = DiskDescriptor {
@@ -374,7 +586,160 @@ impl PsiFormat {
         Ok(disk_image)
     }
 
-    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
-        Err(DiskImageError::UnsupportedFormat)
+    /// Save the disk image as a PSI image. BitStream tracks are decoded to their sectors first.
+    ///
+    /// Each sector is written with an IBM FM or MFM sector header carrying its ID and flags, and
+    /// with its position and read time if known. Sectors whose data is a single repeated byte are
+    /// written compressed. The image comment is written as a TEXT chunk.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::UnsupportedFormat)` if a track is not FM or MFM, or a sector ID or
+    ///   size cannot be stored in a PSI sector header.
+    pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        let mut psi = Vec::new();
+
+        let sector_format = encode_psi_sector_format(image.descriptor.density);
+        write_chunk(&mut psi, b"PSI ", &[0x00, 0x00, sector_format[0], sector_format[1]]);
+
+        if let Some(comment) = image.comment.as_deref().filter(|c| !c.is_empty()) {
+            write_chunk(&mut psi, b"TEXT", comment.as_bytes());
+        }
+
+        let heads = if image.track_map[1].is_empty() { 1 } else { 2 };
+        for c in 0..image.track_map[0].len() {
+            for h in 0..heads {
+                let track = image.track_pool[image.track_map[h][c]].to_bytestream()?;
+                PsiFormat::write_track(&track, DiskCh::new(c as u16, h as u8), &mut psi)?;
+            }
+        }
+
+        write_chunk(&mut psi, b"END ", &[]);
+
+        output
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        output.write_all(&psi).map_err(|_| DiskImageError::IoError)?;
+
+        Ok(())
+    }
+
+    /// Write the chunks of each sector of a ByteStream track.
+    fn write_track(track: &TrackData, ch: DiskCh, psi: &mut Vec<u8>) -> Result<(), DiskImageError> {
+        let TrackData::ByteStream {
+            sectors,
+            data,
+            weak_mask,
+            ..
+        } = track
+        else {
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        let ibm_chunk_id = match track.encoding() {
+            DiskDataEncoding::Fm => b"IBMF",
+            DiskDataEncoding::Mfm => b"IMFM",
+            _ => {
+                log::error!("save_image(): Track {} is not FM or MFM encoded.", ch);
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+        };
+
+        for si in sectors {
+            let start = std::cmp::min(si.t_idx, data.len());
+            let end = std::cmp::min(si.t_idx + si.len, data.len());
+            let sector_data = &data[start..end];
+            let sector_weak = weak_mask.get(start..end).filter(|mask| mask.iter().any(|&b| b != 0));
+
+            // A sector without a data field has no data, but keeps the size of its ID.
+            let size = match si.no_dam {
+                true => si.chsn().n_size(),
+                false => sector_data.len(),
+            };
+            let (Ok(cylinder), Ok(size)) = (u8::try_from(si.cylinder_id), u16::try_from(size)) else {
+                log::error!(
+                    "save_image(): Sector {} can't be stored in a PSI sector header.",
+                    si.chsn()
+                );
+                return Err(DiskImageError::UnsupportedFormat);
+            };
+
+            let compressed = !sector_data.is_empty() && sector_data.iter().all(|&b| b == sector_data[0]);
+            let mut flags = 0;
+            if compressed {
+                flags |= SH_FLAG_COMPRESSED;
+            }
+            if si.data_crc_error {
+                flags |= SH_FLAG_CRC_ERROR;
+            }
+            let sector_header = PsiSectorHeader {
+                cylinder: ch.c(),
+                head: ch.h(),
+                sector: si.sector_id,
+                size,
+                flags,
+                compressed_data: if compressed { sector_data[0] } else { 0 },
+            };
+            PsiFormat::write_struct(psi, b"SECT", &sector_header)?;
+
+            let mut ibm_flags = 0;
+            if si.address_crc_error {
+                ibm_flags |= SH_IBM_FLAG_CRC_ERROR_ID;
+            }
+            if si.data_crc_error {
+                ibm_flags |= SH_IBM_FLAG_CRC_ERROR_DATA;
+            }
+            if si.deleted_mark {
+                ibm_flags |= SH_IBM_DELETED_DATA;
+            }
+            if si.no_dam {
+                ibm_flags |= SH_IBM_MISSING_DATA;
+            }
+            let ibm_header = PsiIbmSectorHeader {
+                cylinder,
+                head: si.head_id,
+                sector: si.sector_id,
+                n: si.n,
+                flags: ibm_flags,
+                encoding: 0,
+            };
+            PsiFormat::write_struct(psi, ibm_chunk_id, &ibm_header)?;
+
+            if !compressed && !sector_data.is_empty() {
+                write_chunk(psi, b"DATA", sector_data);
+            }
+            if let Some(mask) = sector_weak {
+                write_chunk(psi, b"WEAK", mask);
+            }
+            if let Some(position) = si.position {
+                PsiFormat::write_struct(psi, b"OFFS", &PsiU32 { value: position })?;
+            }
+            if let Some(read_time) = si.read_time {
+                PsiFormat::write_struct(psi, b"TIME", &PsiU32 { value: read_time })?;
+            }
+
+            // PCE returns the alternates of a sector on successive reads, so a weak sector is also
+            // given an alternate with its weak bits inverted.
+            if let Some(mask) = sector_weak {
+                let alternate: Vec<u8> = sector_data.iter().zip(mask).map(|(d, m)| d ^ m).collect();
+                let alternate_header = PsiSectorHeader {
+                    flags: SH_FLAG_ALTERNATE | (flags & SH_FLAG_CRC_ERROR),
+                    compressed_data: 0,
+                    ..sector_header
+                };
+                PsiFormat::write_struct(psi, b"SECT", &alternate_header)?;
+                write_chunk(psi, b"DATA", &alternate);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append a chunk holding the binrw structure `value` to `out`.
+    fn write_struct<T>(out: &mut Vec<u8>, id: &[u8; 4], value: &T) -> Result<(), DiskImageError>
+    where
+        T: for<'a> BinWrite<Args<'a> = ()>,
+    {
+        let mut buf = Cursor::new(Vec::new());
+        value.write_be(&mut buf).map_err(|_| DiskImageError::IoError)?;
+        write_chunk(out, id, buf.get_ref());
+        Ok(())
     }
 }
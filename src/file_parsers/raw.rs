@@ -25,9 +25,12 @@
     --------------------------------------------------------------------------
 */
 
-use crate::chs::{DiskChs, DiskChsn};
+use crate::chs::{DiskCh, DiskChs, DiskChsn};
+use crate::convert::{ConvertAction, ConvertIssue, ConvertIssueKind, ConvertPolicy, ConvertReport};
 use crate::detect::chs_from_raw_size;
-use crate::diskimage::{DiskConsistency, DiskDescriptor, DiskImage, SectorDescriptor};
+use crate::diskimage::{
+    DiskConsistency, DiskDescriptor, DiskImage, ParseMode, RawSideOrder, RawTrackOrder, RwSectorScope, SectorDescriptor,
+};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
 use crate::trackdata::TrackData;
@@ -36,6 +39,28 @@ use crate::{DiskDensity, DiskImageError, DiskImageFormat, StandardFormat, DEFAUL
 
 pub struct RawFormat;
 
+/// Return the index of the track `ch` within a raw sector image of geometry `geometry`, stored in
+/// the specified side and track order.
+fn raw_track_slot(ch: DiskCh, geometry: DiskCh, side_order: RawSideOrder, track_order: RawTrackOrder) -> usize {
+    let cylinders = geometry.c() as usize;
+    let heads = geometry.h() as usize;
+
+    let reversed = match track_order {
+        RawTrackOrder::OutToIn => false,
+        RawTrackOrder::InToOut => true,
+        RawTrackOrder::OutAndBack => ch.h() == 1,
+    };
+    let n = match reversed {
+        true => cylinders - 1 - ch.c() as usize,
+        false => ch.c() as usize,
+    };
+
+    match side_order {
+        RawSideOrder::Interleaved => n * heads + ch.h() as usize,
+        RawSideOrder::Sequential => ch.h() as usize * cylinders + n,
+    }
+}
+
 impl RawFormat {
     #[allow(dead_code)]
     fn format() -> DiskImageFormat {
@@ -59,28 +84,35 @@ impl RawFormat {
         ParserWriteCompatibility::UnsupportedFormat
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut raw: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(raw: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        RawFormat::load_image_ordered(raw, RawSideOrder::default(), RawTrackOrder::default(), mode)
+    }
+
+    /// Load a raw sector image whose tracks are stored in the specified side and track order.
+    pub(crate) fn load_image_ordered<RWS: ReadSeek>(
+        mut raw: RWS,
+        side_order: RawSideOrder,
+        track_order: RawTrackOrder,
+        mode: ParseMode,
+    ) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         // Assign the disk geometry or return error.
         let raw_len = get_length(&mut raw).map_err(|_e| DiskImageError::UnknownFormat)? as usize;
 
-        let floppy_format = StandardFormat::from(raw_len);
-        if floppy_format == StandardFormat::Invalid {
-            return Err(DiskImageError::UnknownFormat);
-        }
-
-        let disk_chs = floppy_format.get_chs();
+        // Images with a few cylinders more than their format are overdumps, and keep the extra
+        // tracks.
+        let (floppy_format, cylinders) = StandardFormat::from_raw_size(raw_len).ok_or(DiskImageError::UnknownFormat)?;
+        let format_chs = floppy_format.get_chs();
+        let disk_chs = DiskChs::new(cylinders, format_chs.h(), format_chs.s());
         log::trace!("load_image(): Disk CHS: {}", disk_chs);
         let data_rate = floppy_format.get_data_rate();
         let data_encoding = floppy_format.get_encoding();
         let rpm = floppy_format.get_rpm();
 
-        let mut cursor_chs = DiskChs::default();
-
-        raw.seek(std::io::SeekFrom::Start(0))
-            .map_err(|_e| DiskImageError::IoError)?;
-
         let track_size = disk_chs.s() as usize * DEFAULT_SECTOR_SIZE;
         let track_ct = raw_len / track_size;
         let track_ct_overflow = raw_len % track_size;
@@ -91,9 +123,14 @@ impl RawFormat {
 
         let mut sector_buffer = vec![0u8; DEFAULT_SECTOR_SIZE];
 
-        // Insert sectors in order encountered.
-        for _t in 0..track_ct {
-            disk_image.add_track_bytestream(data_encoding, data_rate, cursor_chs.into())?;
+        // Tracks must be added to the image in physical order, so seek to each track in turn.
+        for t in 0..track_ct {
+            let ch = DiskCh::new((t / disk_chs.h() as usize) as u16, (t % disk_chs.h() as usize) as u8);
+            let slot = raw_track_slot(ch, disk_chs.into(), side_order, track_order);
+            raw.seek(std::io::SeekFrom::Start((slot * track_size) as u64))
+                .map_err(|_e| DiskImageError::IoError)?;
+
+            disk_image.add_track_bytestream(data_encoding, data_rate, ch)?;
 
             for sector_id in 0..disk_chs.s() {
                 raw.read_exact(&mut sector_buffer)
@@ -110,11 +147,13 @@ impl RawFormat {
                     address_crc_error: false,
                     data_crc_error: false,
                     deleted_mark: false,
+                    no_dam: false,
+                    position: None,
+                    read_time: None,
+                    timing: None,
                 };
 
-                //log::trace!("Importing sector {} of length {}", cursor_chs, DEFAULT_SECTOR_SIZE);
-                disk_image.master_sector(cursor_chs, &sd)?;
-                cursor_chs.seek_forward(1, &disk_chs);
+                disk_image.master_sector(DiskChs::from((ch, sector_id + 1)), &sd)?;
             }
         }
 
@@ -130,6 +169,9 @@ impl RawFormat {
             consistent_track_length: Some(disk_chs.s()),
         };
 
+        // A raw image has no geometry of its own beyond that of the format matching its size.
+        disk_image.standard_format = Some(floppy_format);
+
         disk_image.descriptor = DiskDescriptor {
             geometry: disk_chs.into(),
             data_rate,
@@ -144,25 +186,23 @@ impl RawFormat {
     }
 
     pub fn save_image<RWS: ReadWriteSeek>(image: &DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
-        // Clamp track count to 40 or 80 for a standard disk image. We may read in more tracks
-        // depending on image format. For example, 86f format exports 86 tracks
-        let track_ct = match image.track_map[0].len() {
-            39..=50 => 40,
-            79..=90 => 80,
-            _ => {
-                return Err(DiskImageError::UnsupportedFormat);
-            }
-        };
+        // Clamp track count to that of the image's standard format. We may read in more tracks
+        // depending on image format. For example, 86f format exports 86 tracks. Overdumped
+        // tracks are only kept if they were formatted.
+        let track_ct = std::cmp::min(image.export_cylinder_ct(false), image.track_map[0].len());
+        let sector_size = image.nominal_sector_size();
+
+        let heads = if image.track_map[1].is_empty() { 1 } else { 2 };
 
         for track_n in 0..track_ct {
-            for head in 0..2 {
+            for head in 0..heads {
                 let ti = image.track_map[head][track_n];
                 let track = &image.track_pool[ti];
 
                 match &track {
                     TrackData::ByteStream { data, sectors, .. } => {
                         for sector in sectors {
-                            let sector_len = std::cmp::min(sector.len, DEFAULT_SECTOR_SIZE);
+                            let sector_len = std::cmp::min(sector.len, sector_size);
                             output
                                 .write_all(
                                     data[sector.t_idx..std::cmp::min(sector.t_idx + sector_len, data.len())].as_ref(),
@@ -179,4 +219,134 @@ impl RawFormat {
 
         Ok(())
     }
+
+    /// Export the disk image as a raw sector image in canonical order: cylinder, then head, then
+    /// sector ID. Any sector with weak bits, a bad CRC, a deleted mark or a nonstandard size, or
+    /// any missing sector, fails the export with `DiskImageError::IncompatibleImage`.
+    pub fn write_image<RWS: ReadWriteSeek>(image: &mut DiskImage, output: &mut RWS) -> Result<(), DiskImageError> {
+        image
+            .save_with_policy(DiskImageFormat::RawSectorImage, ConvertPolicy::strict(), output)
+            .map(|_| ())
+    }
+
+    /// Save the disk image as a raw sector image, applying `policy` to any sector that cannot be
+    /// represented exactly. Unlike `save_image()`, this supports BitStream images, and sectors are
+    /// written in logical order (by sector ID) rather than physical order.
+    ///
+    /// Every cylinder of `image` is written; trailing cylinders are resized beforehand by
+    /// [`DiskImage::save_with_policy`].
+    pub(crate) fn save_image_with_policy<RWS: ReadWriteSeek>(
+        image: &mut DiskImage,
+        policy: ConvertPolicy,
+        output: &mut RWS,
+    ) -> Result<ConvertReport, DiskImageError> {
+        let track_ct = image.track_map[0].len();
+        let heads = if image.track_map[1].is_empty() { 1 } else { 2 };
+
+        let spt = match (image.standard_format, image.consistency.consistent_track_length) {
+            (Some(format), _) => format.get_chs().s(),
+            (None, Some(spt)) => spt,
+            (None, None) => {
+                log::error!("save_image_with_policy(): Image has no consistent sector count per track.");
+                return Err(DiskImageError::IncompatibleImage);
+            }
+        };
+
+        let sector_size = image.nominal_sector_size();
+        let mut report = ConvertReport::default();
+
+        let total = track_ct * heads as usize;
+        for c in 0..track_ct {
+            for h in 0..heads {
+                image.progress.report("save", c * heads as usize + h as usize, total)?;
+                for s in 1..=spt {
+                    let chs = DiskChs::new(c as u16, h, s);
+                    let sector_buf = RawFormat::convert_sector(image, chs, sector_size, policy, &mut report)?;
+                    output.write_all(&sector_buf).map_err(|_e| DiskImageError::IoError)?;
+                }
+            }
+        }
+        image.progress.report("save", total, total)?;
+
+        Ok(report)
+    }
+
+    /// Read the sector at `chs` and return its data sized to `sector_size`, recording any issues
+    /// in `report`. Returns an error if the policy for an issue is [`ConvertAction::Fail`].
+    fn convert_sector(
+        image: &mut DiskImage,
+        chs: DiskChs,
+        sector_size: usize,
+        policy: ConvertPolicy,
+        report: &mut ConvertReport,
+    ) -> Result<Vec<u8>, DiskImageError> {
+        let default_n = DiskChsn::bytes_to_n(sector_size);
+
+        let rsr = match image.read_sector(chs, None, RwSectorScope::DataOnly, false) {
+            Ok(rsr) => rsr,
+            Err(DiskImageError::DataError) => {
+                let chsn = DiskChsn::from((chs, default_n));
+                RawFormat::apply_action(report, chsn, ConvertIssueKind::Missing, policy.missing)?;
+                return Ok(vec![0; sector_size]);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let chsn = image
+            .get_sector_id(chs)
+            .unwrap_or_else(|| DiskChsn::from((chs, default_n)));
+
+        let mut issues = Vec::new();
+        if image.sector_has_weak_bits(chs) {
+            issues.push((ConvertIssueKind::WeakBits, policy.weak_bits));
+        }
+        if rsr.address_crc_error {
+            issues.push((ConvertIssueKind::BadAddressCrc, policy.bad_crc));
+        }
+        if rsr.data_crc_error {
+            issues.push((ConvertIssueKind::BadDataCrc, policy.bad_crc));
+        }
+        if rsr.deleted_mark {
+            issues.push((ConvertIssueKind::Deleted, policy.deleted));
+        }
+        if chsn.n_size() != sector_size {
+            issues.push((ConvertIssueKind::NonstandardSize, policy.nonstandard_size));
+        }
+
+        let mut zero_fill = false;
+        for (kind, action) in issues {
+            RawFormat::apply_action(report, chsn, kind, action)?;
+            zero_fill |= action == ConvertAction::ZeroFill;
+        }
+
+        let mut sector_buf = vec![0; sector_size];
+        if !zero_fill {
+            let mut read_buf = rsr.read_buf;
+            if rsr.address_crc_error {
+                // Data is not returned for a sector with a bad address CRC unless requested.
+                read_buf = image.read_sector(chs, None, RwSectorScope::DataOnly, true)?.read_buf;
+            }
+            let len = std::cmp::min(read_buf.len(), sector_size);
+            sector_buf[..len].copy_from_slice(&read_buf[..len]);
+        }
+        Ok(sector_buf)
+    }
+
+    fn apply_action(
+        report: &mut ConvertReport,
+        chsn: DiskChsn,
+        kind: ConvertIssueKind,
+        action: ConvertAction,
+    ) -> Result<(), DiskImageError> {
+        if action == ConvertAction::Fail {
+            log::error!(
+                "Sector {} has {}, which cannot be represented in a raw image.",
+                chsn,
+                kind
+            );
+            return Err(DiskImageError::IncompatibleImage);
+        }
+        report.issues.push(ConvertIssue { chsn, kind, action });
+        Ok(())
+    }
 }
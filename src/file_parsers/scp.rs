@@ -29,11 +29,49 @@
     A parser for the SuperCardPro format.
 
     SCP format images encode raw flux information for each track of the disk.
+    Each track may hold several revolutions of flux captured from the index.
+
+    Flux intervals are stored as big-endian 16-bit counts of the capture clock,
+    which ticks every 25ns times (resolution + 1). An interval of 0 indicates
+    an overflow, adding 65536 ticks to the following interval.
+
+    fluxfox resolves the first revolution of each track into a bitstream with
+    a PLL, retaining the flux transitions in a FluxStream track.
 
 */
 
+use crate::diskimage::{DiskDescriptor, ParseMode};
+use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
+use crate::flux::FluxRevolution;
+use crate::io::{ReadSeek, ReadWriteSeek};
+use crate::{
+    DiskCh, DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, DiskRpm,
+    DEFAULT_SECTOR_SIZE,
+};
+use binrw::{binrw, BinRead};
+
 pub const MAX_TRACK_NUMBER: usize = 167;
 
+/// The period of the SCP capture clock at a resolution of 0, in seconds.
+pub const SCP_BASE_RESOLUTION: f64 = 25e-9;
+/// The offset of the track offset table in extended mode images.
+pub const SCP_EXTENDED_TABLE_OFFSET: u64 = 0x80;
+/// The offset of the first byte included in the image checksum.
+pub const SCP_CHECKSUM_START: u64 = 0x10;
+
+// File header flags.
+//pub const SCP_FLAG_INDEX: u8 = 0b0000_0001;
+//pub const SCP_FLAG_96TPI: u8 = 0b0000_0010;
+pub const SCP_FLAG_360RPM: u8 = 0b0000_0100;
+//pub const SCP_FLAG_NORMALIZED: u8 = 0b0000_1000;
+//pub const SCP_FLAG_READ_WRITE: u8 = 0b0001_0000;
+//pub const SCP_FLAG_FOOTER: u8 = 0b0010_0000;
+pub const SCP_FLAG_EXTENDED: u8 = 0b0100_0000;
+
+// Heads field values.
+//pub const SCP_HEADS_BOTH: u8 = 0;
+pub const SCP_HEADS_SIDE0: u8 = 1;
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
@@ -65,3 +103,287 @@ pub struct ScpTrackHeader {
     pub id: [u8; 3],
     pub track_number: u8,
 }
+
+/// An entry following the track header for each revolution captured.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct ScpTrackRevolution {
+    /// The duration of the revolution from index to index, in ticks.
+    pub index_time: u32,
+    /// The number of flux intervals in the revolution.
+    pub length: u32,
+    /// The offset of the revolution's flux data from the start of the track header.
+    pub offset: u32,
+}
+
+pub struct ScpFormat;
+
+impl ScpFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::SuperCardPro
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["scp"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        // SCP records no encoding, and flux is resolved as MFM.
+        bitstream_flags() | FormatCaps::CAP_TRACK_DATA_RATE | FormatCaps::CAP_ENCODING_MFM
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        match ScpFileHeader::read(&mut image) {
+            Ok(header) => header.id == *b"SCP",
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+
+        let header = ScpFileHeader::read(&mut image).map_err(|_| DiskImageError::UnknownFormat)?;
+        if header.id != *b"SCP" {
+            return Err(DiskImageError::UnknownFormat);
+        }
+
+        log::trace!(
+            "load_image(): SCP version: {}.{} disk type: {:02X} revolutions: {} tracks: {}-{} flags: {:08b}",
+            header.version >> 4,
+            header.version & 0x0F,
+            header.disk_type,
+            header.revolutions,
+            header.start_track,
+            header.end_track,
+            header.flags
+        );
+
+        if header.bit_cell_width != 0 && header.bit_cell_width != 16 {
+            log::error!("Unsupported bit cell width: {}", header.bit_cell_width);
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+        if header.heads > SCP_HEADS_SIDE0 {
+            log::error!("Images of side 1 only are not supported.");
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+        if header.revolutions == 0 {
+            log::error!("Image contains no revolutions.");
+            return Err(DiskImageError::FormatParseError);
+        }
+
+        if header.checksum != 0 {
+            let checksum = image_data
+                .iter()
+                .skip(SCP_CHECKSUM_START as usize)
+                .fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+            if checksum != header.checksum {
+                disk_image.spec_violation(
+                    None,
+                    format!(
+                        "Image checksum {:08X} does not match header checksum {:08X}",
+                        checksum, header.checksum
+                    ),
+                )?;
+            }
+        }
+
+        if header.flags & SCP_FLAG_EXTENDED != 0 {
+            image
+                .seek(std::io::SeekFrom::Start(SCP_EXTENDED_TABLE_OFFSET))
+                .map_err(|_| DiskImageError::IoError)?;
+        }
+        let offset_table = ScpTrackOffsetTable::read(&mut image).map_err(|_| DiskImageError::FormatParseError)?;
+
+        let tick = SCP_BASE_RESOLUTION * (header.resolution as f64 + 1.0);
+        let last_track = (header.end_track as usize).min(MAX_TRACK_NUMBER);
+        let mut heads_seen = [false; 2];
+        let mut rate_counts: Vec<(DiskDataRate, usize)> = Vec::new();
+        let mut track_data_rate = DiskDataRate::default();
+        let mut disk_rpm = None;
+
+        for track_n in header.start_track as usize..=last_track {
+            let track_offset = offset_table.track_offsets[track_n] as u64;
+            if track_offset == 0 {
+                continue;
+            }
+            let ch = DiskCh::new((track_n / 2) as u16, (track_n % 2) as u8);
+
+            image
+                .seek(std::io::SeekFrom::Start(track_offset))
+                .map_err(|_| DiskImageError::IoError)?;
+            let track_header = ScpTrackHeader::read(&mut image).map_err(|_| DiskImageError::FormatParseError)?;
+            if track_header.id != *b"TRK" || track_header.track_number as usize != track_n {
+                log::error!(
+                    "Invalid track header for track {} at offset {:X}",
+                    track_n,
+                    track_offset
+                );
+                return Err(DiskImageError::FormatParseError);
+            }
+
+            let mut revolutions = Vec::with_capacity(header.revolutions as usize);
+            for _ in 0..header.revolutions {
+                revolutions.push(ScpTrackRevolution::read(&mut image).map_err(|_| DiskImageError::FormatParseError)?);
+            }
+
+            let mut fluxes = Vec::with_capacity(revolutions.len());
+            for revolution in &revolutions {
+                let flux_start = (track_offset + revolution.offset as u64) as usize;
+                let flux_end = flux_start + revolution.length as usize * 2;
+                let Some(flux_data) = image_data.get(flux_start..flux_end) else {
+                    log::error!("Flux data for track {} extends beyond end of image.", ch);
+                    return Err(DiskImageError::FormatParseError);
+                };
+                fluxes.push(FluxRevolution::new(
+                    scp_read_flux(flux_data),
+                    tick,
+                    revolution.index_time as u64,
+                ));
+            }
+            let Some(flux) = fluxes.first() else {
+                log::error!("Track {} has no revolutions.", ch);
+                return Err(DiskImageError::FormatParseError);
+            };
+
+            log::trace!(
+                "load_image(): Track {}: {} revolutions, {} flux transitions in first revolution ({:.2}ms)",
+                ch,
+                fluxes.len(),
+                flux.transition_ct(),
+                flux.duration() * 1000.0
+            );
+
+            if disk_rpm.is_none() {
+                disk_rpm = flux.rpm();
+            }
+            heads_seen[ch.h() as usize] = true;
+
+            // Tracks missing from the offset table were not captured. Add them as unformatted
+            // tracks to keep the track map contiguous.
+            let gap_rate = flux.estimate_data_rate().unwrap_or(track_data_rate);
+            for c in disk_image.track_map[ch.h() as usize].len()..ch.c() as usize {
+                let gap_ch = DiskCh::new(c as u16, ch.h());
+                disk_image.add_load_warning(
+                    Some(gap_ch),
+                    "No track captured, added as an unformatted track".to_string(),
+                );
+                ScpFormat::add_unformatted_track(&mut disk_image, gap_ch, gap_rate, disk_rpm)?;
+            }
+
+            match flux.estimate_data_rate() {
+                Some(data_rate) => {
+                    track_data_rate = data_rate;
+                    match rate_counts.iter_mut().find(|(rate, _)| *rate == data_rate) {
+                        Some((_, count)) => *count += 1,
+                        None => rate_counts.push((data_rate, 1)),
+                    }
+                    disk_image.add_track_fluxstream(DiskDataEncoding::Mfm, data_rate, ch, fluxes)?;
+                }
+                None => {
+                    // A track with no flux transitions is unformatted. Add it as a track of
+                    // nominal length with no transitions, which will read as weak bits.
+                    disk_image.add_load_warning(
+                        Some(ch),
+                        "No flux transitions, added as an unformatted track".to_string(),
+                    );
+                    ScpFormat::add_unformatted_track(&mut disk_image, ch, track_data_rate, disk_rpm)?;
+                }
+            }
+        }
+
+        let head_ct = heads_seen.iter().filter(|&&h| h).count() as u8;
+        if head_ct == 0 {
+            log::error!("Image contains no tracks.");
+            return Err(DiskImageError::FormatParseError);
+        }
+
+        let disk_data_rate = rate_counts
+            .iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(rate, _)| *rate)
+            .unwrap_or_default();
+        let disk_rpm = if header.flags & SCP_FLAG_360RPM != 0 {
+            DiskRpm::Rpm360
+        } else {
+            disk_rpm.unwrap_or_default()
+        };
+
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::from((disk_image.track_map[0].len() as u16, head_ct)),
+            data_rate: disk_data_rate,
+            data_encoding: DiskDataEncoding::Mfm,
+            density: DiskDensity::from(disk_data_rate),
+            default_sector_size: DEFAULT_SECTOR_SIZE,
+            rpm: Some(disk_rpm),
+            write_protect: None,
+        };
+
+        Ok(disk_image)
+    }
+
+    /// SCP export is not supported. Flux can't be synthesized from the bitstream tracks of an
+    /// image, so long and short tracks can only be preserved by exporting to 86F or PRI.
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+
+    /// Add a track of nominal length with no flux transitions at `ch`, which will read as weak
+    /// bits.
+    fn add_unformatted_track(
+        disk_image: &mut DiskImage,
+        ch: DiskCh,
+        data_rate: DiskDataRate,
+        rpm: Option<DiskRpm>,
+    ) -> Result<(), DiskImageError> {
+        let bitcell_ct = rpm.unwrap_or_default().track_bitcells(data_rate);
+        disk_image.add_track_bitstream(
+            DiskDataEncoding::Mfm,
+            data_rate,
+            ch,
+            data_rate.into(),
+            Some(bitcell_ct),
+            &vec![0; bitcell_ct.div_ceil(8)],
+            None,
+        )
+    }
+}
+
+/// Decode SCP flux data into a vector of flux intervals in ticks. An interval of 0 adds 65536
+/// ticks to the next interval.
+fn scp_read_flux(data: &[u8]) -> Vec<u32> {
+    let mut intervals = Vec::with_capacity(data.len() / 2);
+    let mut overflow = 0u32;
+    for word in data.chunks_exact(2) {
+        let value = u16::from_be_bytes([word[0], word[1]]) as u32;
+        if value == 0 {
+            overflow = overflow.saturating_add(0x10000);
+        } else {
+            intervals.push(overflow.saturating_add(value));
+            overflow = 0;
+        }
+    }
+    intervals
+}
@@ -0,0 +1,447 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/stx.rs
+
+    A parser for the Pasti STX disk image format.
+
+    STX images are produced by the Pasti imaging tool for the Atari ST, and
+    preserve the copy protection of most ST titles. The file is a 16-byte
+    header followed by one record per track. A track record either holds plain
+    512-byte sectors, or a list of sector descriptors recording the ID, flags,
+    position and read time of each sector, followed by a fuzzy byte mask, the
+    sector data, and an optional image of the raw track.
+
+    Fuzzy bytes are loaded into the weak bit mask of their sector. Revision 2
+    images may also record the variable bit timing of a sector, which is kept
+    as per-sector timing metadata. Tracks are loaded as MFM ByteStream tracks;
+    the raw track image is not used.
+*/
+
+use crate::chs::{DiskCh, DiskChs};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor};
+use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
+use crate::io::{Cursor, ReadSeek, ReadWriteSeek};
+use crate::{
+    DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, DiskRpm, FoxHashSet,
+    DEFAULT_SECTOR_SIZE,
+};
+use binrw::{binrw, BinRead};
+
+pub const STX_HEADER_LEN: usize = 16;
+pub const STX_TRACK_HEADER_LEN: usize = 16;
+pub const STX_SECTOR_DESCRIPTOR_LEN: usize = 16;
+pub const STX_VERSION: u16 = 3;
+/// The size of the block of sector data each timing value applies to.
+pub const STX_TIMING_BLOCK_SIZE: usize = 16;
+
+pub const STX_TRACK_FLAG_SECTORS: u16 = 0x0001;
+pub const STX_TRACK_FLAG_IMAGE: u16 = 0x0040;
+pub const STX_TRACK_FLAG_SYNC: u16 = 0x0080;
+
+pub const STX_FDC_VARIABLE_TIMING: u8 = 0x01;
+pub const STX_FDC_CRC_ERROR: u8 = 0x08;
+pub const STX_FDC_RECORD_NOT_FOUND: u8 = 0x10;
+pub const STX_FDC_DELETED: u8 = 0x20;
+pub const STX_FDC_FUZZY: u8 = 0x80;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct StxFileHeader {
+    pub id: [u8; 4],
+    pub version: u16,
+    pub tool: u16,
+    pub reserved_1: u16,
+    pub track_count: u8,
+    pub revision: u8,
+    pub reserved_2: u32,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct StxTrackHeader {
+    /// The size of the track record, including this header.
+    pub record_size: u32,
+    /// The size of the fuzzy byte mask following the sector descriptors.
+    pub fuzzy_count: u32,
+    pub sector_count: u16,
+    pub flags: u16,
+    /// The length of the track in bytes.
+    pub track_length: u16,
+    /// The cylinder number, with the side in bit 7.
+    pub track_number: u8,
+    pub track_type: u8,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct StxSectorDescriptor {
+    /// The offset of the sector data from the start of the track data.
+    pub data_offset: u32,
+    /// The offset of the sector ID from the index, in bits.
+    pub bit_position: u16,
+    /// The time taken to read the sector in microseconds, or 0 for standard timing.
+    pub read_time: u16,
+    pub id_track: u8,
+    pub id_side: u8,
+    pub id_number: u8,
+    pub id_size: u8,
+    #[brw(big)]
+    pub id_crc: u16,
+    pub fdc_flags: u8,
+    pub reserved: u8,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct StxTimingHeader {
+    pub flags: u16,
+    /// The size of the timing record, including this header.
+    pub size: u16,
+}
+
+pub struct StxFormat;
+
+impl StxFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::PastiImage
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["stx"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        FormatCaps::CAP_VARIABLE_SPT
+            | FormatCaps::CAP_VARIABLE_SSPT
+            | FormatCaps::CAP_DATA_CRC
+            | FormatCaps::CAP_DATA_DELETED
+            | FormatCaps::CAP_SID_OVERRIDE
+            | FormatCaps::CAP_WEAK_BITS
+            | FormatCaps::CAP_ENCODING_MFM
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        match StxFileHeader::read(&mut image) {
+            Ok(header) => header.id == *b"RSY\0",
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+
+        let mut cursor = Cursor::new(&image_data);
+        let header = StxFileHeader::read(&mut cursor).map_err(|_| DiskImageError::UnknownFormat)?;
+        if header.id != *b"RSY\0" {
+            return Err(DiskImageError::UnknownFormat);
+        }
+        if header.version != STX_VERSION {
+            log::error!("Unsupported STX version: {}", header.version);
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+        log::trace!(
+            "Read STX file header. Tool: {:04X} Tracks: {} Revision: {}",
+            header.tool,
+            header.track_count,
+            header.revision
+        );
+
+        let mut heads_seen: FoxHashSet<u8> = FoxHashSet::new();
+        let mut cylinder_ct = 0;
+        let mut record_offset = STX_HEADER_LEN;
+
+        for _ in 0..header.track_count {
+            cursor.set_position(record_offset as u64);
+            let track_header = StxTrackHeader::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+            let record_size = track_header.record_size as usize;
+            if record_size < STX_TRACK_HEADER_LEN || record_offset + record_size > image_data.len() {
+                log::error!("STX track record at offset {} exceeds the image.", record_offset);
+                return Err(DiskImageError::FormatParseError);
+            }
+            let record = &image_data[record_offset..record_offset + record_size];
+            record_offset += record_size;
+
+            let ch = DiskCh::new(
+                (track_header.track_number & 0x7F) as u16,
+                track_header.track_number >> 7,
+            );
+            log::trace!(
+                "Track {} Sectors: {} Flags: {:04X} Fuzzy bytes: {} Length: {}",
+                ch,
+                track_header.sector_count,
+                track_header.flags,
+                track_header.fuzzy_count,
+                track_header.track_length
+            );
+
+            heads_seen.insert(ch.h());
+            cylinder_ct = std::cmp::max(cylinder_ct, ch.c() + 1);
+            disk_image.add_track_bytestream(DiskDataEncoding::Mfm, DiskDataRate::Rate250Kbps, ch)?;
+
+            let sectors = match track_header.flags & STX_TRACK_FLAG_SECTORS {
+                0 => StxFormat::read_plain_sectors(&mut disk_image, ch, &track_header, record)?,
+                _ => StxFormat::read_sectors(&mut disk_image, ch, &track_header, record, header.revision)?,
+            };
+            for (chs, sd) in sectors {
+                disk_image.master_sector(chs, &sd)?;
+            }
+        }
+
+        let head_ct = std::cmp::max(heads_seen.len(), 1) as u8;
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new(cylinder_ct, head_ct),
+            data_rate: DiskDataRate::Rate250Kbps,
+            data_encoding: DiskDataEncoding::Mfm,
+            density: DiskDensity::Double,
+            default_sector_size: DEFAULT_SECTOR_SIZE,
+            rpm: Some(DiskRpm::Rpm300),
+            write_protect: None,
+        };
+
+        Ok(disk_image)
+    }
+
+    /// Read a track record without sector descriptors, which holds standard 512-byte sectors
+    /// numbered from 1.
+    fn read_plain_sectors(
+        disk_image: &mut DiskImage,
+        ch: DiskCh,
+        track_header: &StxTrackHeader,
+        record: &[u8],
+    ) -> Result<Vec<(DiskChs, SectorDescriptor)>, DiskImageError> {
+        let mut sectors = Vec::new();
+        for (s, data) in record[STX_TRACK_HEADER_LEN..]
+            .chunks_exact(DEFAULT_SECTOR_SIZE)
+            .take(track_header.sector_count as usize)
+            .enumerate()
+        {
+            let chs = DiskChs::from((ch, s as u8 + 1));
+            sectors.push((
+                chs,
+                SectorDescriptor {
+                    id: chs.s(),
+                    n: 2,
+                    data: data.to_vec(),
+                    ..Default::default()
+                },
+            ));
+        }
+        if sectors.len() < track_header.sector_count as usize {
+            disk_image.spec_violation(
+                Some(ch),
+                format!(
+                    "Track record holds {} of {} sectors",
+                    sectors.len(),
+                    track_header.sector_count
+                ),
+            )?;
+        }
+        Ok(sectors)
+    }
+
+    /// Read a track record with sector descriptors, applying the fuzzy byte mask and any timing
+    /// record to the sectors that reference them.
+    fn read_sectors(
+        disk_image: &mut DiskImage,
+        ch: DiskCh,
+        track_header: &StxTrackHeader,
+        record: &[u8],
+        revision: u8,
+    ) -> Result<Vec<(DiskChs, SectorDescriptor)>, DiskImageError> {
+        let mut cursor = Cursor::new(record);
+        cursor.set_position(STX_TRACK_HEADER_LEN as u64);
+        let mut descriptors = Vec::with_capacity(track_header.sector_count as usize);
+        for _ in 0..track_header.sector_count {
+            descriptors.push(StxSectorDescriptor::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?);
+        }
+
+        let fuzzy_start = STX_TRACK_HEADER_LEN + descriptors.len() * STX_SECTOR_DESCRIPTOR_LEN;
+        let data_start = fuzzy_start + track_header.fuzzy_count as usize;
+        if data_start > record.len() {
+            log::error!("STX track {} fuzzy mask exceeds the track record.", ch);
+            return Err(DiskImageError::FormatParseError);
+        }
+        let mut fuzzy_mask = &record[fuzzy_start..data_start];
+        let track_data = &record[data_start..];
+
+        // The timing record follows the furthest of the sector data and the track image.
+        let mut data_end = 0;
+        if track_header.flags & STX_TRACK_FLAG_IMAGE != 0 {
+            let size_offset = match track_header.flags & STX_TRACK_FLAG_SYNC {
+                0 => 0,
+                _ => 2,
+            };
+            if let Some(size) = track_data.get(size_offset..size_offset + 2) {
+                data_end = size_offset + 2 + u16::from_le_bytes([size[0], size[1]]) as usize;
+            }
+        }
+        for desc in &descriptors {
+            if desc.fdc_flags & STX_FDC_RECORD_NOT_FOUND == 0 {
+                data_end = std::cmp::max(data_end, desc.data_offset as usize + (128 << (desc.id_size & 0x03)));
+            }
+        }
+        let mut timing_values = StxFormat::read_timing(&descriptors, track_data, data_end, revision);
+
+        let mut sectors = Vec::with_capacity(descriptors.len());
+        for desc in &descriptors {
+            let size = 128usize << (desc.id_size & 0x03);
+            let no_dam = desc.fdc_flags & STX_FDC_RECORD_NOT_FOUND != 0;
+            log::trace!(
+                "Sector ID: {} {} {} {} Flags: {:02X} Position: {} Read time: {}",
+                desc.id_track,
+                desc.id_side,
+                desc.id_number,
+                desc.id_size,
+                desc.fdc_flags,
+                desc.bit_position,
+                desc.read_time
+            );
+
+            let data = match no_dam {
+                true => Vec::new(),
+                false => {
+                    let start = desc.data_offset as usize;
+                    match track_data.get(start..start + size) {
+                        Some(data) => data.to_vec(),
+                        None => {
+                            disk_image.spec_violation(
+                                Some(ch),
+                                format!("Sector {} data exceeds the track record", desc.id_number),
+                            )?;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            // A clear bit in the fuzzy mask marks a bit that reads differently on each pass.
+            let weak = match desc.fdc_flags & STX_FDC_FUZZY != 0 && !no_dam {
+                true => {
+                    if fuzzy_mask.len() < size {
+                        disk_image.spec_violation(
+                            Some(ch),
+                            format!("Fuzzy mask of sector {} exceeds the fuzzy record", desc.id_number),
+                        )?;
+                    }
+                    let (mask, rest) = fuzzy_mask.split_at(std::cmp::min(size, fuzzy_mask.len()));
+                    fuzzy_mask = rest;
+                    Some(mask.iter().map(|&b| !b).collect())
+                }
+                false => None,
+            };
+
+            let timing = match desc.fdc_flags & STX_FDC_VARIABLE_TIMING != 0 && !no_dam {
+                true => {
+                    let blocks = size / STX_TIMING_BLOCK_SIZE;
+                    let timing: Vec<u16> = timing_values.by_ref().take(blocks).collect();
+                    (timing.len() == blocks).then_some(timing)
+                }
+                false => None,
+            };
+
+            // The read time is recorded in microseconds; a bit clock at 250Kbps is 4us.
+            let read_time = match desc.read_time {
+                0 => None,
+                time => Some(time as u32 / 4),
+            };
+
+            sectors.push((
+                DiskChs::from((ch, desc.id_number)),
+                SectorDescriptor {
+                    id: desc.id_number,
+                    cylinder_id: Some(desc.id_track as u16),
+                    head_id: Some(desc.id_side),
+                    n: desc.id_size,
+                    data,
+                    weak,
+                    address_crc_error: false,
+                    data_crc_error: desc.fdc_flags & STX_FDC_CRC_ERROR != 0 && !no_dam,
+                    deleted_mark: desc.fdc_flags & STX_FDC_DELETED != 0,
+                    no_dam,
+                    position: Some(desc.bit_position as u32),
+                    read_time,
+                    timing,
+                },
+            ));
+        }
+        Ok(sectors)
+    }
+
+    /// Return an iterator over the timing values recorded for the variable timing sectors of a
+    /// track. Only revision 2 images record timing; the timing record begins at `data_end`
+    /// within the track data, and its values are stored big-endian.
+    fn read_timing<'a>(
+        descriptors: &[StxSectorDescriptor],
+        track_data: &'a [u8],
+        data_end: usize,
+        revision: u8,
+    ) -> impl Iterator<Item = u16> + 'a {
+        let mut values: &[u8] = &[];
+        let variable = descriptors.iter().any(|d| d.fdc_flags & STX_FDC_VARIABLE_TIMING != 0);
+        if variable && revision == 2 {
+            let mut cursor = Cursor::new(track_data);
+            cursor.set_position(data_end as u64);
+            if let Ok(timing_header) = StxTimingHeader::read(&mut cursor) {
+                log::trace!(
+                    "Timing record flags: {:04X} size: {}",
+                    timing_header.flags,
+                    timing_header.size
+                );
+                let end = std::cmp::min(data_end + timing_header.size as usize, track_data.len());
+                values = track_data.get(data_end + 4..end).unwrap_or(&[]);
+            }
+        }
+        values.chunks_exact(2).map(|v| u16::from_be_bytes([v[0], v[1]]))
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
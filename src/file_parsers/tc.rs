@@ -50,7 +50,7 @@
 use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
 use crate::io::{ReadSeek, ReadWriteSeek};
 
-use crate::diskimage::DiskDescriptor;
+use crate::diskimage::{DiskDescriptor, ParseMode};
 use crate::{
     DiskCh, DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskRpm, DEFAULT_SECTOR_SIZE,
 };
@@ -169,8 +169,11 @@ impl TCFormat {
         ParserWriteCompatibility::UnsupportedFormat
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
 
         let disk_image_size = image.seek(std::io::SeekFrom::End(0)).unwrap();
 
@@ -358,6 +361,7 @@ impl TCFormat {
                 &track_data_vec,
                 None,
             )?;
+            disk_image.set_track_source(DiskCh::from((cylinder_n, head_n)), track_offset, track_data_vec)?;
 
             head_n += 1;
             if head_n == disk_info.num_sides {
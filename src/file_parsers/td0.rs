@@ -36,7 +36,7 @@
     LZHUF compression.
 
 */
-use crate::diskimage::{DiskDescriptor, SectorDescriptor};
+use crate::diskimage::{DiskDescriptor, ParseMode, SectorDescriptor};
 use crate::file_parsers::compression::lzhuf::{expand, TD0_READ_OPTIONS};
 use crate::file_parsers::{FormatCaps, ParserWriteCompatibility};
 use crate::io::{Cursor, Read, ReadBytesExt, ReadSeek, ReadWriteSeek, Seek};
@@ -52,6 +52,10 @@ pub const SECTOR_SKIPPED: u8 = 0b0001_0000;
 pub const SECTOR_NO_DATA: u8 = 0b0010_0000;
 pub const SECTOR_NO_ID: u8 = 0b0100_0000;
 
+/// Set in the data rate of the file header if the whole disk is single density (FM), and in the
+/// head number of a track header if that track is.
+pub const FM_FLAG: u8 = 0b1000_0000;
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
@@ -68,6 +72,7 @@ pub struct TelediskHeader {
     pub crc: u16,
 }
 
+pub const TELEDISK_HEADER_SIZE: usize = 12;
 pub const COMMENT_HEADER_SIZE: usize = 10;
 /// Teledisk comment block header
 /// 'length' bytes of comment data line records follow the header, as nul-terminated strings.
@@ -125,15 +130,12 @@ pub struct RepeatedDataEntry {
 
 pub struct Td0Format {}
 
-fn td0_data_rate(rate: u8) -> DiskDataRate {
+fn td0_data_rate(rate: u8) -> Option<DiskDataRate> {
     match rate & 0x03 {
-        0 => DiskDataRate::Rate250Kbps,
-        1 => DiskDataRate::Rate300Kbps,
-        2 => DiskDataRate::Rate500Kbps,
-        _ => {
-            log::warn!("TD0 Data Rate out of range: {} Assuming 300Kbps", rate);
-            DiskDataRate::Rate300Kbps
-        }
+        0 => Some(DiskDataRate::Rate250Kbps),
+        1 => Some(DiskDataRate::Rate300Kbps),
+        2 => Some(DiskDataRate::Rate500Kbps),
+        _ => None,
     }
 }
 
@@ -200,8 +202,11 @@ impl Td0Format {
         ParserWriteCompatibility::Ok
     }
 
-    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::default();
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
         let mut image_data = Vec::new();
 
         image
@@ -211,7 +216,7 @@ impl Td0Format {
             .read_to_end(&mut image_data)
             .map_err(|_| DiskImageError::IoError)?;
 
-        if image_data.len() < 12 {
+        if image_data.len() < TELEDISK_HEADER_SIZE {
             log::trace!("Image is too small to be a Teledisk image.");
             return Err(DiskImageError::UnknownFormat);
         }
@@ -234,7 +239,19 @@ impl Td0Format {
         let minor_version = file_header.version % 10;
         let has_comment_block = file_header.stepping & 0x80 != 0;
 
-        let disk_data_rate = td0_data_rate(file_header.data_rate);
+        let disk_data_rate = match td0_data_rate(file_header.data_rate) {
+            Some(rate) => rate,
+            None => {
+                disk_image.spec_violation(
+                    None,
+                    format!("TD0 Data Rate out of range: {} Assuming 300Kbps", file_header.data_rate),
+                )?;
+                DiskDataRate::Rate300Kbps
+            }
+        };
+        let disk_fm = file_header.data_rate & FM_FLAG != 0;
+        let mut fm_tracks = 0;
+        let mut mfm_tracks = 0;
 
         log::trace!(
             "Detected Teledisk Image, version {}.{}, compressed: {} comment_block: {}",
@@ -246,14 +263,21 @@ impl Td0Format {
 
         log::trace!("Header CRC: {:04X} Calculated CRC: {:04X}", file_header.crc, header_crc,);
         if file_header.crc != header_crc {
-            return Err(DiskImageError::ImageCorruptError);
+            disk_image.spec_violation(
+                None,
+                format!(
+                    "Header CRC {:04X} does not match calculated CRC {:04X}",
+                    file_header.crc, header_crc
+                ),
+            )?;
         }
 
-        // Decompress the image data if necessary.
+        // Decompress the image data if necessary. The file header itself is never compressed.
         let mut compressed_data = Cursor::new(image_data.to_vec());
-        let mut image_data_ref = &mut compressed_data;
+        let mut uncompressed_data = Cursor::new(image_data[TELEDISK_HEADER_SIZE..].to_vec());
+        let mut image_data_ref = &mut uncompressed_data;
         let mut decompression_buffer = Cursor::new(Vec::with_capacity(image_data.len() * 2));
-        let mut decompression_length = 0;
+        let mut decompression_length = (image_data.len() - TELEDISK_HEADER_SIZE) as u64;
         if compressed {
             (_, decompression_length) = expand(&mut compressed_data, &mut decompression_buffer, &TD0_READ_OPTIONS)
                 .map_err(|_| DiskImageError::ImageCorruptError)?;
@@ -281,7 +305,13 @@ impl Td0Format {
             )?;
 
             if comment_header.crc != calculated_crc {
-                return Err(DiskImageError::ImageCorruptError);
+                disk_image.spec_violation(
+                    None,
+                    format!(
+                        "Comment block CRC {:04X} does not match calculated CRC {:04X}",
+                        comment_header.crc, calculated_crc
+                    ),
+                )?;
             }
 
             log::trace!(
@@ -331,15 +361,31 @@ impl Td0Format {
                 break;
             }
 
+            // Images of 8" disks commonly have an FM track 0 followed by MFM tracks.
+            let head = track_header.head & !FM_FLAG;
             if track_header.crc != calculated_track_header_crc as u8 {
-                return Err(DiskImageError::ImageCorruptError);
+                disk_image.spec_violation(
+                    Some(DiskCh::new(track_header.cylinder as u16, head)),
+                    format!(
+                        "Track header CRC {:02X} does not match calculated CRC {:02X}",
+                        track_header.crc, calculated_track_header_crc as u8
+                    ),
+                )?;
             }
 
-            log::trace!("Adding track: c:{} h:{}...", track_header.cylinder, track_header.head);
+            let encoding = if disk_fm || track_header.head & FM_FLAG != 0 {
+                fm_tracks += 1;
+                DiskDataEncoding::Fm
+            } else {
+                mfm_tracks += 1;
+                DiskDataEncoding::Mfm
+            };
+
+            log::trace!("Adding {} track: c:{} h:{}...", encoding, track_header.cylinder, head);
             disk_image.add_track_bytestream(
-                DiskDataEncoding::Mfm,
+                encoding,
                 disk_data_rate,
-                DiskCh::from((track_header.cylinder as u16, track_header.head)),
+                DiskCh::from((track_header.cylinder as u16, head)),
             )?;
             cylinder_set.insert(track_header.cylinder as u16);
 
@@ -408,8 +454,17 @@ impl Td0Format {
                         data_crc as u8
                     );
 
-                    if sector_header.crc != data_crc as u8 {
-                        return Err(DiskImageError::ImageCorruptError);
+                    // A sector whose data does not match its CRC is kept, but marked as having a
+                    // data CRC error.
+                    let data_crc_valid = sector_header.crc == data_crc as u8;
+                    if !data_crc_valid {
+                        disk_image.spec_violation(
+                            Some(DiskCh::new(track_header.cylinder as u16, head)),
+                            format!(
+                                "Sector {} data block CRC {:02X} does not match calculated CRC {:02X}",
+                                sector_header.sector_id, sector_header.crc, data_crc as u8
+                            ),
+                        )?;
                     }
 
                     // Add this sector to track.
@@ -421,16 +476,16 @@ impl Td0Format {
                         data: sector_data_vec,
                         weak: None,
                         address_crc_error: false,
-                        data_crc_error: sector_header.flags & SECTOR_CRC_ERROR != 0,
+                        data_crc_error: sector_header.flags & SECTOR_CRC_ERROR != 0 || !data_crc_valid,
                         deleted_mark: sector_header.flags & SECTOR_DELETED != 0,
+                        no_dam: false,
+                        position: None,
+                        read_time: None,
+                        timing: None,
                     };
 
                     disk_image.master_sector(
-                        DiskChs::from((
-                            sector_header.cylinder as u16,
-                            sector_header.head,
-                            sector_header.sector_id,
-                        )),
+                        DiskChs::from((track_header.cylinder as u16, head, sector_header.sector_id)),
                         &sd,
                     )?;
                 }
@@ -440,11 +495,18 @@ impl Td0Format {
             track_header_offset = image_data_ref.stream_position().map_err(|_| DiskImageError::IoError)?;
         }
 
+        // Use the encoding of the majority of tracks for the descriptor. Ties go to FM.
+        let (data_encoding, density) = if fm_tracks >= mfm_tracks {
+            (DiskDataEncoding::Fm, DiskDensity::Standard)
+        } else {
+            (DiskDataEncoding::Mfm, DiskDensity::from(disk_data_rate))
+        };
+
         disk_image.descriptor = DiskDescriptor {
             geometry: DiskCh::from((cylinder_set.len() as u16, file_header.heads)),
             data_rate: disk_data_rate,
-            data_encoding: DiskDataEncoding::Mfm,
-            density: DiskDensity::from(disk_data_rate),
+            data_encoding,
+            density,
             default_sector_size: 512,
             rpm: None,
             write_protect: None,
@@ -542,3 +604,73 @@ impl Td0Format {
         Err(DiskImageError::UnsupportedFormat)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diskimage::RwSectorScope;
+    use crate::file_parsers::compression::lzhuf::{compress, TD0_WRITE_OPTIONS};
+
+    fn sector_data(s: u8) -> Vec<u8> {
+        (0..512).map(|i| (i as u8).wrapping_mul(s)).collect()
+    }
+
+    /// Build an uncompressed TD0 image with a comment block and a single track of two sectors.
+    fn build_td0() -> Vec<u8> {
+        let mut td0 = b"TD".to_vec();
+        // Version 2.1, 250Kbps, comment block present, one head.
+        td0.extend_from_slice(&[0, 0, 21, 0, 1, 0x80, 0, 1]);
+        let crc = td0_crc(&td0, 0);
+        td0.extend_from_slice(&crc.to_le_bytes());
+
+        let comment = b"fluxfox\0test\0";
+        let mut comment_block = (comment.len() as u16).to_le_bytes().to_vec();
+        comment_block.extend_from_slice(&[124, 0, 1, 12, 0, 0]);
+        comment_block.extend_from_slice(comment);
+        td0.extend_from_slice(&td0_crc(&comment_block, 0).to_le_bytes());
+        td0.extend_from_slice(&comment_block);
+
+        td0.extend_from_slice(&[2, 0, 0, td0_crc(&[2, 0, 0], 0) as u8]);
+        for s in 1..=2 {
+            let data = sector_data(s);
+            td0.extend_from_slice(&[0, 0, s, 2, 0, td0_crc(&data, 0) as u8]);
+            td0.extend_from_slice(&513u16.to_le_bytes());
+            td0.push(0);
+            td0.extend_from_slice(&data);
+        }
+        td0.extend_from_slice(&[0xFF, 0, 0, 0]);
+        td0
+    }
+
+    /// Compress a TD0 image with advanced compression, as TeleDisk does.
+    fn compress_td0(td0: &[u8]) -> Vec<u8> {
+        let mut compressed = Cursor::new(Vec::new());
+        compress(&mut Cursor::new(td0), &mut compressed, &TD0_WRITE_OPTIONS).unwrap();
+        let mut compressed = compressed.into_inner();
+        compressed[..TELEDISK_HEADER_SIZE].copy_from_slice(&td0[..TELEDISK_HEADER_SIZE]);
+        compressed[..2].copy_from_slice(b"td");
+        let crc = td0_crc(&compressed[..10], 0);
+        compressed[10..12].copy_from_slice(&crc.to_le_bytes());
+        compressed
+    }
+
+    #[test]
+    fn test_td0_advanced_compression() {
+        let td0 = build_td0();
+        let compressed = compress_td0(&td0);
+        assert!(compressed.len() < td0.len());
+
+        for image_data in [td0, compressed] {
+            assert!(Td0Format::detect(Cursor::new(&image_data)));
+            let mut image = Td0Format::load_image(Cursor::new(&image_data), ParseMode::Strict).unwrap();
+            assert!(image.load_warnings().is_empty());
+            for s in 1..=2 {
+                let rsr = image
+                    .read_sector(DiskChs::new(0, 0, s), None, RwSectorScope::DataOnly, false)
+                    .unwrap();
+                assert!(!rsr.data_crc_error);
+                assert_eq!(&rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len], sector_data(s));
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/parsers/woz.rs
+
+    A parser for the WOZ v2 format.
+
+    WOZ images store the GCR bitstream of each track of an Apple II 5.25" or
+    Apple 3.5" disk, as captured by the Applesauce. The file is a series of
+    chunks following a 12-byte header. The TMAP chunk maps each head position to
+    an entry in the TRKS chunk; for 5.25" disks, positions are in quarter-track
+    steps, and adjacent quarter-tracks usually map to the same captured track.
+    Track bitstreams are stored in 512-byte blocks, with an exact bit count.
+
+    Whole tracks are loaded as GCR BitStream tracks. Half-tracks and
+    quarter-tracks that were captured separately from their neighboring whole
+    tracks are loaded as sub-tracks.
+*/
+
+use crate::bitstream::gcr::GCR_SECTOR_SIZE;
+use crate::chs::{DiskCh, QuarterTrack};
+use crate::diskimage::{DiskDescriptor, ParseMode};
+use crate::file_parsers::{bitstream_flags, FormatCaps, ParserWriteCompatibility};
+use crate::io::{Cursor, ReadSeek, ReadWriteSeek};
+use crate::util::crc32;
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskImage, DiskImageError, DiskImageFormat, DiskRpm};
+use binrw::{binrw, BinRead};
+
+pub const WOZ_HEADER_LEN: usize = 12;
+pub const WOZ_BLOCK_SIZE: usize = 512;
+/// The number of entries in the TMAP and TRKS chunks.
+pub const WOZ_TRACK_ENTRIES: usize = 160;
+/// A TMAP entry with no captured track.
+pub const WOZ_NO_TRACK: u8 = 0xFF;
+
+pub const WOZ_DISK_TYPE_525: u8 = 1;
+pub const WOZ_DISK_TYPE_35: u8 = 2;
+/// The default bit timing of 5.25" and 3.5" disks, in 125ns units, for INFO chunks that predate
+/// the optimal bit timing field.
+pub const WOZ_DEFAULT_TIMING_525: u8 = 32;
+pub const WOZ_DEFAULT_TIMING_35: u8 = 16;
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct WozFileHeader {
+    pub id: [u8; 4],
+    pub high_bit: u8,
+    pub line_ends: [u8; 3],
+    pub crc: u32,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct WozChunkHeader {
+    pub id: [u8; 4],
+    pub size: u32,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct WozInfoChunk {
+    pub version: u8,
+    pub disk_type: u8,
+    pub write_protected: u8,
+    pub synchronized: u8,
+    pub cleaned: u8,
+    pub creator: [u8; 32],
+    // The following fields are only valid for INFO version 2 and above.
+    pub disk_sides: u8,
+    pub boot_sector_format: u8,
+    pub optimal_bit_timing: u8,
+    pub compatible_hardware: u16,
+    pub required_ram: u16,
+    pub largest_track: u16,
+}
+
+/// An entry in the TRKS chunk, locating the bitstream of a captured track.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct WozTrackEntry {
+    /// The first 512-byte block of the bitstream, counted from the start of the file.
+    pub starting_block: u16,
+    pub block_count: u16,
+    pub bit_count: u32,
+}
+
+pub struct WozFormat;
+
+impl WozFormat {
+    #[allow(dead_code)]
+    fn format() -> DiskImageFormat {
+        DiskImageFormat::WozImage
+    }
+
+    pub(crate) fn extensions() -> Vec<&'static str> {
+        vec!["woz"]
+    }
+
+    pub(crate) fn capabilities() -> FormatCaps {
+        bitstream_flags() | FormatCaps::CAP_ENCODING_GCR | FormatCaps::CAP_SUB_TRACKS
+    }
+
+    pub(crate) fn detect<RWS: ReadSeek>(mut image: RWS) -> bool {
+        if image.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return false;
+        }
+        match WozFileHeader::read(&mut image) {
+            Ok(header) => (header.id == *b"WOZ1" || header.id == *b"WOZ2") && header.high_bit == 0xFF,
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn can_write(_image: &DiskImage) -> ParserWriteCompatibility {
+        ParserWriteCompatibility::UnsupportedFormat
+    }
+
+    pub(crate) fn load_image<RWS: ReadSeek>(mut image: RWS, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+        let mut disk_image = DiskImage {
+            parse_mode: mode,
+            ..Default::default()
+        };
+
+        let mut image_data = Vec::new();
+        image
+            .seek(std::io::SeekFrom::Start(0))
+            .map_err(|_| DiskImageError::IoError)?;
+        image
+            .read_to_end(&mut image_data)
+            .map_err(|_| DiskImageError::IoError)?;
+
+        let mut cursor = Cursor::new(&image_data);
+        let header = WozFileHeader::read(&mut cursor).map_err(|_| DiskImageError::UnknownFormat)?;
+        match &header.id {
+            b"WOZ2" => {}
+            b"WOZ1" => {
+                log::error!("WOZ version 1 images are not supported.");
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+            _ => return Err(DiskImageError::UnknownFormat),
+        }
+
+        if header.crc != 0 {
+            let crc = crc32(&image_data[WOZ_HEADER_LEN..]);
+            if crc != header.crc {
+                disk_image.spec_violation(
+                    None,
+                    format!("Image CRC {:08X} does not match header CRC {:08X}", crc, header.crc),
+                )?;
+            }
+        }
+
+        let mut info = None;
+        let mut tmap = None;
+        let mut trks = None;
+        let mut chunk_offset = WOZ_HEADER_LEN;
+        while chunk_offset + 8 <= image_data.len() {
+            cursor.set_position(chunk_offset as u64);
+            let chunk = WozChunkHeader::read(&mut cursor).map_err(|_| DiskImageError::FormatParseError)?;
+            let data_start = chunk_offset + 8;
+            let Some(chunk_data) = image_data.get(data_start..data_start + chunk.size as usize) else {
+                disk_image.spec_violation(
+                    None,
+                    format!(
+                        "Chunk {} extends beyond end of image",
+                        String::from_utf8_lossy(&chunk.id)
+                    ),
+                )?;
+                break;
+            };
+
+            log::trace!(
+                "load_image(): Chunk {} at offset {:X}, size {}",
+                String::from_utf8_lossy(&chunk.id),
+                chunk_offset,
+                chunk.size
+            );
+            match &chunk.id {
+                b"INFO" => {
+                    info = Some(
+                        WozInfoChunk::read(&mut Cursor::new(chunk_data))
+                            .map_err(|_| DiskImageError::FormatParseError)?,
+                    );
+                }
+                b"TMAP" if chunk_data.len() >= WOZ_TRACK_ENTRIES => {
+                    tmap = Some(chunk_data[..WOZ_TRACK_ENTRIES].to_vec());
+                }
+                b"TRKS" => {
+                    let mut trk_cursor = Cursor::new(chunk_data);
+                    let mut entries = Vec::with_capacity(WOZ_TRACK_ENTRIES);
+                    for _ in 0..WOZ_TRACK_ENTRIES {
+                        entries
+                            .push(WozTrackEntry::read(&mut trk_cursor).map_err(|_| DiskImageError::FormatParseError)?);
+                    }
+                    trks = Some(entries);
+                }
+                _ => {}
+            }
+            chunk_offset = data_start + chunk.size as usize;
+        }
+
+        let (Some(info), Some(tmap), Some(trks)) = (info, tmap, trks) else {
+            log::error!("Image is missing a required INFO, TMAP or TRKS chunk.");
+            return Err(DiskImageError::FormatParseError);
+        };
+
+        let (default_timing, head_ct, sector_size, disk_rpm) = match info.disk_type {
+            WOZ_DISK_TYPE_525 => (WOZ_DEFAULT_TIMING_525, 1, GCR_SECTOR_SIZE, Some(DiskRpm::Rpm300)),
+            // Apple 3.5" drives vary their speed by zone, which has no DiskRpm.
+            WOZ_DISK_TYPE_35 => (WOZ_DEFAULT_TIMING_35, info.disk_sides.clamp(1, 2), 512, None),
+            _ => {
+                log::error!("Unsupported disk type: {}", info.disk_type);
+                return Err(DiskImageError::UnsupportedFormat);
+            }
+        };
+        let bit_timing = match info.optimal_bit_timing {
+            0 => default_timing,
+            timing if info.version >= 2 => timing,
+            _ => default_timing,
+        };
+        // The bit timing is the bitcell time in units of 125ns. As for MFM, the data rate is half
+        // the bitcell clock, so the 4us and 2us GCR bitcells of Apple drives load as 125Kbps and
+        // 250Kbps, as they do from A2R images.
+        let bitcell_clock = 1_000_000_000 / (bit_timing as u32 * 125);
+        let data_clock = bitcell_clock / 2;
+        let data_rate = DiskDataRate::from(data_clock);
+
+        log::trace!(
+            "load_image(): WOZ INFO version: {} disk type: {} creator: {} bit timing: {}",
+            info.version,
+            info.disk_type,
+            String::from_utf8_lossy(&info.creator).trim_end(),
+            bit_timing
+        );
+
+        let cylinder_ct = match info.disk_type {
+            WOZ_DISK_TYPE_525 => WOZ_TRACK_ENTRIES / 4,
+            _ => WOZ_TRACK_ENTRIES / 2,
+        };
+        let last_cylinder = (0..cylinder_ct)
+            .rev()
+            .find(|&c| (0..head_ct as usize).any(|h| woz_whole_entry(&tmap, info.disk_type, c, h) != WOZ_NO_TRACK));
+        let Some(last_cylinder) = last_cylinder else {
+            log::error!("Image contains no tracks.");
+            return Err(DiskImageError::FormatParseError);
+        };
+
+        let nominal_bitcells = (bitcell_clock * 60 / u32::from(disk_rpm.unwrap_or_default())) as usize;
+        for c in 0..=last_cylinder {
+            for h in 0..head_ct as usize {
+                let ch = DiskCh::new(c as u16, h as u8);
+                match woz_track_bits(&image_data, &trks, woz_whole_entry(&tmap, info.disk_type, c, h))? {
+                    Some((bytes, bit_ct)) => {
+                        log::trace!("load_image(): Adding GCR track {} Bitcells: {}", ch, bit_ct);
+                        disk_image.add_track_bitstream(
+                            DiskDataEncoding::Gcr,
+                            data_rate,
+                            ch,
+                            data_clock,
+                            Some(bit_ct),
+                            bytes,
+                            None,
+                        )?;
+                    }
+                    None => {
+                        // Tracks between captured tracks were unformatted. Add them as empty
+                        // tracks of nominal length to keep the track map contiguous.
+                        disk_image
+                            .add_load_warning(Some(ch), "No track captured, added as an unformatted track".to_string());
+                        disk_image.add_track_bitstream(
+                            DiskDataEncoding::Gcr,
+                            data_rate,
+                            ch,
+                            data_clock,
+                            Some(nominal_bitcells),
+                            &vec![0; nominal_bitcells.div_ceil(8)],
+                            None,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if info.disk_type == WOZ_DISK_TYPE_525 {
+            // Only add sub-tracks that were captured apart from both neighboring whole tracks.
+            for q in (0..WOZ_TRACK_ENTRIES).filter(|q| q % 4 != 0) {
+                let entry = tmap[q];
+                let below = woz_whole_entry(&tmap, info.disk_type, q / 4, 0);
+                let above = woz_whole_entry(&tmap, info.disk_type, q / 4 + 1, 0);
+                if entry == WOZ_NO_TRACK || entry == below || entry == above {
+                    continue;
+                }
+                if let Some((bytes, bit_ct)) = woz_track_bits(&image_data, &trks, entry)? {
+                    let position = QuarterTrack(q as u16);
+                    log::trace!("load_image(): Adding GCR sub-track {} Bitcells: {}", position, bit_ct);
+                    disk_image.add_sub_track_bitstream(
+                        DiskDataEncoding::Gcr,
+                        data_rate,
+                        0,
+                        position,
+                        data_clock,
+                        Some(bit_ct),
+                        bytes,
+                        None,
+                    )?;
+                }
+            }
+        }
+
+        disk_image.descriptor = DiskDescriptor {
+            geometry: DiskCh::new((last_cylinder + 1) as u16, head_ct),
+            data_rate,
+            data_encoding: DiskDataEncoding::Gcr,
+            density: DiskDensity::from(data_rate),
+            default_sector_size: sector_size,
+            rpm: disk_rpm,
+            write_protect: Some(info.write_protected != 0),
+        };
+
+        Ok(disk_image)
+    }
+
+    pub fn save_image<RWS: ReadWriteSeek>(_image: &DiskImage, _output: &mut RWS) -> Result<(), DiskImageError> {
+        Err(DiskImageError::UnsupportedFormat)
+    }
+}
+
+/// Return the TMAP entry for whole cylinder `c` on head `h`. 5.25" disks are mapped in
+/// quarter-track steps on one side; 3.5" disks are mapped by cylinder and side.
+fn woz_whole_entry(tmap: &[u8], disk_type: u8, c: usize, h: usize) -> u8 {
+    let index = match disk_type {
+        WOZ_DISK_TYPE_525 => c * 4,
+        _ => c * 2 + h,
+    };
+    tmap.get(index).copied().unwrap_or(WOZ_NO_TRACK)
+}
+
+/// Resolve the TMAP entry `entry` into the bitstream bytes and bit count of its track, or None
+/// if no track was captured.
+fn woz_track_bits<'a>(
+    image_data: &'a [u8],
+    trks: &[WozTrackEntry],
+    entry: u8,
+) -> Result<Option<(&'a [u8], usize)>, DiskImageError> {
+    if entry == WOZ_NO_TRACK {
+        return Ok(None);
+    }
+    let Some(trk) = trks.get(entry as usize) else {
+        log::error!("TMAP entry {} is out of range.", entry);
+        return Err(DiskImageError::FormatParseError);
+    };
+    let start = trk.starting_block as usize * WOZ_BLOCK_SIZE;
+    let len = (trk.bit_count as usize).div_ceil(8);
+    if len > trk.block_count as usize * WOZ_BLOCK_SIZE {
+        log::error!("Track {} bit count exceeds its block count.", entry);
+        return Err(DiskImageError::FormatParseError);
+    }
+    match image_data.get(start..start + len) {
+        Some(bytes) => Ok(Some((bytes, trk.bit_count as usize))),
+        None => {
+            log::error!("Track {} extends beyond end of image.", entry);
+            Err(DiskImageError::FormatParseError)
+        }
+    }
+}
@@ -0,0 +1,127 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/flux.rs
+
+    Types for raw flux transition data captured from a track, as stored by
+    flux-level image formats such as SuperCard Pro.
+
+*/
+use crate::{DiskDataRate, DiskRpm};
+
+/// The fraction of a flux revolution's intervals used to locate the shortest interval peak when
+/// estimating the data rate.
+const SHORT_PEAK_QUANTILE: f64 = 0.1;
+/// Intervals up to this multiple of the quantile value are considered part of the shortest peak.
+const SHORT_PEAK_SPAN: f64 = 1.25;
+/// The maximum deviation of a revolution's duration from a nominal rotation rate for it to be
+/// considered a match.
+const RPM_TOLERANCE: f64 = 0.05;
+
+/// A [`FluxRevolution`] holds the flux transitions captured over a single revolution of a track,
+/// starting at the index pulse.
+#[derive(Clone, Debug, Default)]
+pub struct FluxRevolution {
+    /// The intervals between successive flux transitions, in ticks of the capture clock.
+    pub intervals: Vec<u32>,
+    /// The period of the capture clock, in seconds.
+    pub tick: f64,
+    /// The duration of the revolution from index to index, in ticks of the capture clock.
+    pub index_ticks: u64,
+}
+
+impl FluxRevolution {
+    pub fn new(intervals: Vec<u32>, tick: f64, index_ticks: u64) -> Self {
+        Self {
+            intervals,
+            tick,
+            index_ticks,
+        }
+    }
+
+    /// Return the number of flux transitions in the revolution.
+    pub fn transition_ct(&self) -> usize {
+        self.intervals.len()
+    }
+
+    /// Return the duration of the revolution from index to index, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.index_ticks as f64 * self.tick
+    }
+
+    /// Return an iterator over the intervals between flux transitions, in seconds.
+    pub fn iter_seconds(&self) -> impl Iterator<Item = f64> + '_ {
+        self.intervals.iter().map(|&i| i as f64 * self.tick)
+    }
+
+    /// Return the standard rotation rate closest to the duration of the revolution, or `None` if
+    /// the duration is not within 5% of either standard rate.
+    pub fn rpm(&self) -> Option<DiskRpm> {
+        let duration = self.duration();
+        [DiskRpm::Rpm300, DiskRpm::Rpm360].into_iter().find(|rpm| {
+            let nominal = 60.0 / u32::from(*rpm) as f64;
+            ((duration - nominal) / nominal).abs() <= RPM_TOLERANCE
+        })
+    }
+
+    /// Estimate the data rate of MFM data recorded on the track from the shortest flux interval
+    /// peak, which is two bitcells long. Returns `None` if the revolution has no transitions.
+    pub fn estimate_data_rate(&self) -> Option<DiskDataRate> {
+        let mut sorted: Vec<u32> = self.intervals.iter().copied().filter(|&i| i > 0).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_unstable();
+
+        let quantile = sorted[(sorted.len() as f64 * SHORT_PEAK_QUANTILE) as usize] as f64;
+        let peak: Vec<f64> = sorted
+            .iter()
+            .map(|&i| i as f64)
+            .take_while(|&i| i <= quantile * SHORT_PEAK_SPAN)
+            .collect();
+        let cell_time = peak.iter().sum::<f64>() / peak.len() as f64 * self.tick / 2.0;
+
+        [
+            DiskDataRate::Rate125Kbps,
+            DiskDataRate::Rate250Kbps,
+            DiskDataRate::Rate300Kbps,
+            DiskDataRate::Rate500Kbps,
+            DiskDataRate::Rate1000Kbps,
+        ]
+        .into_iter()
+        .min_by(|a, b| {
+            let da = (cell_time / cell_time_of(*a)).ln().abs();
+            let db = (cell_time / cell_time_of(*b)).ln().abs();
+            da.total_cmp(&db)
+        })
+    }
+}
+
+/// Return the duration of an MFM bitcell at the specified data rate, in seconds. Each data bit
+/// is encoded as two bitcells.
+pub fn cell_time_of(data_rate: DiskDataRate) -> f64 {
+    1.0 / (u32::from(data_rate) as f64 * 2.0)
+}
@@ -0,0 +1,132 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/handle.rs
+
+    Small, stable handle types referring to the tracks and sectors of a disk
+    image, for use by FFI and GUI layers in place of internal indices.
+*/
+
+use crate::chs::{DiskCh, DiskChsn, QuarterTrack};
+use std::fmt::Display;
+
+/// An opaque handle to a track of a [`DiskImage`](crate::DiskImage), either a whole cylinder or
+/// a sub-track.
+///
+/// A [`TrackId`] refers to a track by its physical location rather than its position in the
+/// image's internal storage, so it remains valid when tracks are added, edited or re-ordered. It
+/// fits in a `u32` for passing across an FFI boundary; see [`TrackId::to_raw`].
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TrackId(u32);
+
+impl TrackId {
+    /// Return the handle of the track at `position` on `head`.
+    pub fn new(head: u8, position: QuarterTrack) -> Self {
+        Self((head as u32) << 16 | position.0 as u32)
+    }
+    /// Return the head of the track.
+    pub fn head(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+    /// Return the position of the track in quarter-track steps.
+    pub fn position(&self) -> QuarterTrack {
+        QuarterTrack(self.0 as u16)
+    }
+    /// Return the head and cylinder of the track. For sub-tracks, this is the whole cylinder
+    /// below the track.
+    pub fn ch(&self) -> DiskCh {
+        DiskCh::new(self.position().c(), self.head())
+    }
+    /// Return true if the track is recorded between whole cylinders.
+    pub fn is_sub_track(&self) -> bool {
+        !self.position().is_whole()
+    }
+    /// Return the handle as a `u32`.
+    pub fn to_raw(self) -> u32 {
+        self.0
+    }
+    /// Return the handle previously returned by [`TrackId::to_raw`].
+    pub fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<DiskCh> for TrackId {
+    /// Return the handle of the whole cylinder `ch`.
+    fn from(ch: DiskCh) -> Self {
+        Self::new(ch.h(), QuarterTrack::from(ch.c()))
+    }
+}
+
+impl Display for TrackId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[h:{} t:{}]", self.head(), self.position())
+    }
+}
+
+/// An opaque handle to a sector of a [`DiskImage`](crate::DiskImage).
+///
+/// A [`SectorId`] refers to a sector by its track and the ID recorded in its header, counting
+/// sectors with duplicate IDs on the same track in physical order. It remains valid when the
+/// track is edited, unless the sector's ID is changed or another sector with the same ID is
+/// inserted before it.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct SectorId {
+    track: TrackId,
+    chsn: DiskChsn,
+    occurrence: u8,
+}
+
+impl SectorId {
+    pub(crate) fn new(track: TrackId, chsn: DiskChsn, occurrence: u8) -> Self {
+        Self {
+            track,
+            chsn,
+            occurrence,
+        }
+    }
+    /// Return the handle of the track holding the sector.
+    pub fn track(&self) -> TrackId {
+        self.track
+    }
+    /// Return the ID recorded in the sector's header.
+    pub fn chsn(&self) -> DiskChsn {
+        self.chsn
+    }
+    /// Return the number of sectors with the same ID that precede this one on the track.
+    pub fn occurrence(&self) -> u8 {
+        self.occurrence
+    }
+}
+
+impl Display for SectorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.occurrence {
+            0 => write!(f, "{} {}", self.track, self.chsn),
+            n => write!(f, "{} {} #{}", self.track, self.chsn, n + 1),
+        }
+    }
+}
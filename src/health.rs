@@ -0,0 +1,170 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/health.rs
+
+    A summary of the condition of a disk image as a single score, for triaging
+    large collections of images.
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+/// The fraction of a damaged sector's weight given to a sector with weak bits. Weak bits are
+/// often deliberate, as a form of copy protection, so they count for less than outright damage.
+pub const WEAK_SECTOR_WEIGHT: f64 = 0.5;
+
+/// A problem counted by a [`HealthReport`]. Each sector is counted once, under the first of
+/// `WeakBits`, `BadAddressCrc`, `NoData` and `BadDataCrc` that applies to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HealthIssue {
+    WeakBits,
+    BadAddressCrc,
+    NoData,
+    BadDataCrc,
+    /// A sector ID expected by the disk's format that was not found on its track.
+    MissingSector,
+    /// A track expected by the disk's format that is not present in the image, as when an image
+    /// file is truncated. Counts as a full track of missing sectors.
+    MissingTrack,
+}
+
+impl Display for HealthIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthIssue::WeakBits => write!(f, "weak bits"),
+            HealthIssue::BadAddressCrc => write!(f, "bad address CRC"),
+            HealthIssue::NoData => write!(f, "no data"),
+            HealthIssue::BadDataCrc => write!(f, "bad data CRC"),
+            HealthIssue::MissingSector => write!(f, "missing sectors"),
+            HealthIssue::MissingTrack => write!(f, "missing tracks"),
+        }
+    }
+}
+
+/// A letter grade for a [`HealthReport`] score. Only an image with no problems at all is graded
+/// `A`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthGrade {
+    A,
+    B,
+    C,
+    D,
+    F,
+}
+
+impl HealthGrade {
+    /// Return the grade for a score from 0 to 100.
+    pub fn from_score(score: u8) -> Self {
+        match score {
+            100.. => HealthGrade::A,
+            95..=99 => HealthGrade::B,
+            80..=94 => HealthGrade::C,
+            50..=79 => HealthGrade::D,
+            _ => HealthGrade::F,
+        }
+    }
+}
+
+impl Display for HealthGrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A [`HealthReport`] summarizes the condition of a disk image, as returned by
+/// [`crate::DiskImage::health`].
+///
+/// Each problem sector, and each sector of a missing track, costs a share of the score in
+/// proportion to the number of sectors the disk is expected to hold. The score is rounded down,
+/// so that any problem at all gives a score below 100.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The number of sectors the disk is expected to hold, from its format if known.
+    pub expected_sectors: usize,
+    /// The number of sectors expected on each track, or 0 if not known.
+    pub sectors_per_track: usize,
+    /// The number of sectors found.
+    pub sectors: usize,
+    pub weak_sectors: usize,
+    pub address_crc_errors: usize,
+    pub no_data: usize,
+    pub data_crc_errors: usize,
+    pub missing_sectors: usize,
+    pub missing_tracks: usize,
+}
+
+impl HealthReport {
+    /// Return the count of each kind of problem, and the points it costs the score.
+    pub fn breakdown(&self) -> Vec<(HealthIssue, usize, f64)> {
+        let per_sector = 100.0 / self.expected_sectors.max(self.sectors).max(1) as f64;
+        [
+            (HealthIssue::WeakBits, self.weak_sectors, WEAK_SECTOR_WEIGHT),
+            (HealthIssue::BadAddressCrc, self.address_crc_errors, 1.0),
+            (HealthIssue::NoData, self.no_data, 1.0),
+            (HealthIssue::BadDataCrc, self.data_crc_errors, 1.0),
+            (HealthIssue::MissingSector, self.missing_sectors, 1.0),
+            (
+                HealthIssue::MissingTrack,
+                self.missing_tracks,
+                self.sectors_per_track.max(1) as f64,
+            ),
+        ]
+        .into_iter()
+        .map(|(issue, count, weight)| (issue, count, count as f64 * weight * per_sector))
+        .collect()
+    }
+
+    /// Return the health score, from 0 (unreadable) to 100 (no problems found).
+    pub fn score(&self) -> u8 {
+        let lost: f64 = self.breakdown().iter().map(|(_, _, points)| points).sum();
+        if lost == 0.0 {
+            return 100;
+        }
+        // Guard against rounding a tiny loss back up to a perfect score.
+        (100.0 - lost).floor().clamp(0.0, 99.0) as u8
+    }
+
+    pub fn grade(&self) -> HealthGrade {
+        HealthGrade::from_score(self.score())
+    }
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Health: {} ({}), {} of {} expected sectors found",
+            self.score(),
+            self.grade(),
+            self.sectors,
+            self.expected_sectors
+        )?;
+        for (issue, count, points) in self.breakdown().into_iter().filter(|(_, count, _)| *count > 0) {
+            writeln!(f, "  {:<16} {:>6}  -{:.2}", issue.to_string() + ":", count, points)?;
+        }
+        Ok(())
+    }
+}
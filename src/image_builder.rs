@@ -91,21 +91,17 @@ impl ImageBuilder {
             return Err(DiskImageError::ParameterError);
         }
 
-        if self.standard_format.is_some() {
-            match self.resolution {
-                Some(DiskDataResolution::BitStream) => self.build_bitstream(),
-                None | Some(DiskDataResolution::ByteStream) => self.build_bytestream(),
-                _ => Err(DiskImageError::UnsupportedFormat),
-            }
-        } else {
-            Err(DiskImageError::UnsupportedFormat)
-        }
-    }
+        let format = match self.standard_format {
+            Some(format) => format,
+            None => return Err(DiskImageError::UnsupportedFormat),
+        };
+        let resolution = match self.resolution {
+            Some(resolution @ (DiskDataResolution::BitStream | DiskDataResolution::ByteStream)) => resolution,
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
 
-    fn build_bitstream(self) -> Result<DiskImage, DiskImageError> {
-        let format = self.standard_format.unwrap();
         let mut disk_image = DiskImage::create(format);
-        disk_image.set_resolution(DiskDataResolution::BitStream);
+        disk_image.set_resolution(resolution);
 
         let chsn = format.get_chsn();
         let encoding = format.get_encoding();
@@ -128,14 +124,4 @@ impl ImageBuilder {
 
         Ok(disk_image)
     }
-
-    fn build_bytestream(self) -> Result<DiskImage, DiskImageError> {
-        let mut disk_image = DiskImage::create(self.standard_format.unwrap());
-        disk_image.set_resolution(DiskDataResolution::ByteStream);
-
-        // Clear dirty flag
-        disk_image.clear_flag(DiskImageFlags::DIRTY);
-
-        Ok(disk_image)
-    }
 }
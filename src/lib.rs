@@ -43,21 +43,48 @@
 //! a disk image file, or by creating a new disk image from scratch.
 //!
 //! It is recommended to use the [`image_builder::ImageBuilder`] interface to load or create a disk image.
+pub mod analysis;
+pub mod batch;
 pub mod bitstream;
 mod boot_sector;
+pub mod canonical;
 mod chs;
+pub mod consensus;
 mod containers;
+pub mod convert;
 mod detect;
+pub mod disk_set;
 pub mod diskimage;
+pub mod drive;
+pub mod drive_bay;
+pub mod duplicator;
+pub mod fdc;
 mod file_parsers;
+pub mod flux;
+pub mod handle;
+pub mod health;
 pub mod image_builder;
 mod io;
+pub mod linear;
+pub mod media;
+pub mod packed;
+pub mod platform;
+pub mod progress;
 mod random;
+pub mod registry;
 mod sector;
+pub mod shared;
 pub mod standard_format;
 pub mod structure_parsers;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod timeline;
+pub mod track_builder;
 mod trackdata;
 pub mod util;
+pub mod verify;
+pub mod virus;
+pub mod waveform;
 
 #[cfg(feature = "viz")]
 pub mod visualization;
@@ -101,10 +128,20 @@ pub enum DiskImageError {
     ParameterError,
     #[error("Write-protect status prevents writing to the disk image")]
     WriteProtectError,
+    #[error(
+        "The disk image contains tracks incompatible with the target medium: {}",
+        .0.iter().map(|ch| ch.to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    IncompatibleTracks(Vec<DiskCh>),
+    #[error("The operation was cancelled")]
+    Cancelled,
+    #[error("The disk image violates its format specification: {0}")]
+    SpecViolation(String),
 }
 
 /// The resolution of the data in the disk image.
-/// Currently only ByteStream and BitStream are implemented.
+/// FluxStream tracks are resolved to a bitstream when loaded, so images of FluxStream source formats
+/// are otherwise handled as BitStream images.
 #[repr(usize)]
 #[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub enum DiskDataResolution {
@@ -148,6 +185,8 @@ pub enum DiskPhysicalDimensions {
     Dimension5_25,
     #[doc = "A 3.5\" Diskette"]
     Dimension3_5,
+    #[doc = "A 3\" Diskette, as used by Amstrad machines"]
+    Dimension3,
 }
 
 /// The density of the disk image. Only 8" diskettes were available in standard density.
@@ -233,7 +272,7 @@ impl From<usize> for EncodingPhase {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum DiskDataRate {
     RateNonstandard(u32),
     Rate125Kbps,
@@ -307,6 +346,23 @@ pub enum DiskRpm {
     Rpm360,
 }
 
+impl DiskRpm {
+    /// Return the nominal number of bitcells on an MFM track recorded at `data_rate` at this
+    /// rotation rate. Each data bit is encoded as two bitcells.
+    pub fn track_bitcells(&self, data_rate: DiskDataRate) -> usize {
+        (u32::from(data_rate) as u64 * 2 * 60 / u32::from(*self) as u64) as usize
+    }
+}
+
+impl From<DiskRpm> for u32 {
+    fn from(rpm: DiskRpm) -> Self {
+        match rpm {
+            DiskRpm::Rpm300 => 300,
+            DiskRpm::Rpm360 => 360,
+        }
+    }
+}
+
 impl Display for DiskRpm {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -316,7 +372,11 @@ impl Display for DiskRpm {
     }
 }
 
-pub use crate::chs::{DiskCh, DiskChs, DiskChsn};
+pub use crate::chs::{DiskCh, DiskChs, DiskChsn, QuarterTrack};
 pub use crate::diskimage::{DiskImage, DiskImageFormat};
-pub use crate::file_parsers::{format_from_ext, supported_extensions, ImageParser, ParserWriteCompatibility};
+pub use crate::file_parsers::{
+    format_from_ext, formats, supported_extensions, FormatCaps, FormatInfo, ImageParser, ImageWriter,
+    ParserWriteCompatibility,
+};
+pub use crate::handle::{SectorId, TrackId};
 pub use crate::standard_format::StandardFormat;
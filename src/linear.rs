@@ -0,0 +1,162 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/linear.rs
+
+    A view of the logical sectors of a disk image as a single seekable byte
+    stream, for use with filesystem libraries that operate on a block device.
+*/
+
+use crate::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use crate::{DiskImage, DiskImageError};
+
+/// A [`LinearView`] exposes the sectors of a [`DiskImage`] as one contiguous stream of bytes, in
+/// the logical block order of [`DiskImage::read_lba`]. It implements [`Read`], [`Write`] and
+/// [`Seek`], so a filesystem library expecting a disk image file can be pointed at a
+/// [`DiskImage`] directly, without exporting it first.
+///
+/// Reads of a sector with a bad CRC or without a data field fail with an error of kind
+/// [`ErrorKind::InvalidData`], rather than returning the damaged data. Writes are applied to the
+/// image sector by sector as they are made, with the CRC of each sector written regenerated on
+/// BitStream tracks. A write covering only part of a sector reads the rest of the sector first,
+/// so it fails if the sector is damaged; a write of the whole sector does not.
+pub struct LinearView<'a> {
+    image: &'a mut DiskImage,
+    sector_size: usize,
+    sector_ct: usize,
+    pos: u64,
+    /// The most recently accessed sector and its data, so that small reads and writes within a
+    /// sector do not decode it again.
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl<'a> LinearView<'a> {
+    pub(crate) fn new(image: &'a mut DiskImage) -> std::result::Result<Self, DiskImageError> {
+        let geometry = image.nominal_geometry().ok_or(DiskImageError::IncompatibleImage)?;
+        let sector_size = image.nominal_sector_size();
+        Ok(Self {
+            image,
+            sector_size,
+            sector_ct: geometry.get_sector_count() as usize,
+            pos: 0,
+            cache: None,
+        })
+    }
+
+    /// Return the length of the view in bytes.
+    pub fn len(&self) -> u64 {
+        (self.sector_ct * self.sector_size) as u64
+    }
+
+    /// Return true if the view contains no sectors.
+    pub fn is_empty(&self) -> bool {
+        self.sector_ct == 0
+    }
+
+    /// Return the size of each sector of the view in bytes.
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Return the data of the sector at `lba`, reading it from the image if it is not cached.
+    fn sector(&mut self, lba: usize) -> Result<&mut Vec<u8>> {
+        if !matches!(self.cache, Some((cached, _)) if cached == lba) {
+            let data = self
+                .image
+                .read_lba(lba)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            if data.len() != self.sector_size {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("sector {} is {} bytes, expected {}", lba, data.len(), self.sector_size),
+                ));
+            }
+            self.cache = Some((lba, data));
+        }
+        // The cache was filled above if it did not hold the sector.
+        Ok(&mut self.cache.as_mut().unwrap().1)
+    }
+}
+
+impl Read for LinearView<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() || self.pos >= self.len() {
+            return Ok(0);
+        }
+        let lba = (self.pos / self.sector_size as u64) as usize;
+        let offset = (self.pos % self.sector_size as u64) as usize;
+        let sector = self.sector(lba)?;
+        let len = buf.len().min(sector.len() - offset);
+        buf[..len].copy_from_slice(&sector[offset..offset + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for LinearView<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.len() {
+            return Err(Error::new(ErrorKind::WriteZero, "write past end of disk"));
+        }
+        let lba = (self.pos / self.sector_size as u64) as usize;
+        let offset = (self.pos % self.sector_size as u64) as usize;
+        let len = buf.len().min(self.sector_size - offset);
+
+        // A whole sector is written without reading it first, so a damaged sector may be
+        // overwritten.
+        if len == self.sector_size {
+            self.cache = Some((lba, buf[..len].to_vec()));
+        } else {
+            self.sector(lba)?[offset..offset + len].copy_from_slice(&buf[..len]);
+        }
+        let data = &self.cache.as_ref().unwrap().1;
+        if let Err(e) = self.image.write_lba(lba, data) {
+            self.cache = None;
+            return Err(Error::new(ErrorKind::InvalidData, e));
+        }
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for LinearView<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,197 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/media.rs
+
+    Describes the physical characteristics of diskette media and the drives
+    that read them.
+
+    Unlike StandardFormat, which describes a logical sector layout, a
+    MediaProfile describes what a track on the physical medium can hold. It is
+    used to validate images before export, and to calculate track lengths when
+    formatting or synthesizing tracks.
+
+    Profiles are provided for:
+
+        5.25" DD  48 tpi, 300RPM, 250Kbps
+        5.25" HD  96 tpi, 360RPM, 500Kbps
+        3.5"  DD 135 tpi, 300RPM, 250Kbps
+        3.5"  HD 135 tpi, 300RPM, 500Kbps
+        3.5"  ED 135 tpi, 300RPM, 1Mbps
+        8"    SD  48 tpi, 360RPM, 250Kbps FM
+        8"    DD  48 tpi, 360RPM, 500Kbps
+        3"    DD 100 tpi, 300RPM, 250Kbps (Amstrad CF2)
+*/
+
+use crate::{DiskDataEncoding, DiskDataRate, DiskDensity, DiskPhysicalDimensions, DiskRpm};
+use std::fmt::{self, Display, Formatter};
+
+/// The density sensing hole present in a diskette's jacket, if any. Only 3.5" media have one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DensityHole {
+    None,
+    HighDensity,
+    ExtendedDensity,
+}
+
+/// A [`MediaProfile`] describes a type of physical diskette and the drive used to read it.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum MediaProfile {
+    Dd5_25,
+    Hd5_25,
+    Dd3_5,
+    Hd3_5,
+    Ed3_5,
+    Sd8,
+    Dd8,
+    Amstrad3,
+}
+
+impl Display for MediaProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaProfile::Dd5_25 => write!(f, "5.25\" DD"),
+            MediaProfile::Hd5_25 => write!(f, "5.25\" HD"),
+            MediaProfile::Dd3_5 => write!(f, "3.5\" DD"),
+            MediaProfile::Hd3_5 => write!(f, "3.5\" HD"),
+            MediaProfile::Ed3_5 => write!(f, "3.5\" ED"),
+            MediaProfile::Sd8 => write!(f, "8\" SD"),
+            MediaProfile::Dd8 => write!(f, "8\" DD"),
+            MediaProfile::Amstrad3 => write!(f, "3\" Amstrad"),
+        }
+    }
+}
+
+impl MediaProfile {
+    /// Return a list of all known media profiles.
+    pub fn list() -> Vec<MediaProfile> {
+        vec![
+            MediaProfile::Dd5_25,
+            MediaProfile::Hd5_25,
+            MediaProfile::Dd3_5,
+            MediaProfile::Hd3_5,
+            MediaProfile::Ed3_5,
+            MediaProfile::Sd8,
+            MediaProfile::Dd8,
+            MediaProfile::Amstrad3,
+        ]
+    }
+
+    pub fn dimensions(&self) -> DiskPhysicalDimensions {
+        match self {
+            MediaProfile::Dd5_25 | MediaProfile::Hd5_25 => DiskPhysicalDimensions::Dimension5_25,
+            MediaProfile::Dd3_5 | MediaProfile::Hd3_5 | MediaProfile::Ed3_5 => DiskPhysicalDimensions::Dimension3_5,
+            MediaProfile::Sd8 | MediaProfile::Dd8 => DiskPhysicalDimensions::Dimension8,
+            MediaProfile::Amstrad3 => DiskPhysicalDimensions::Dimension3,
+        }
+    }
+
+    /// Return the track density of the drive, in tracks per inch.
+    pub fn tpi(&self) -> u16 {
+        match self {
+            MediaProfile::Dd5_25 => 48,
+            MediaProfile::Hd5_25 => 96,
+            MediaProfile::Dd3_5 | MediaProfile::Hd3_5 | MediaProfile::Ed3_5 => 135,
+            MediaProfile::Sd8 | MediaProfile::Dd8 => 48,
+            MediaProfile::Amstrad3 => 100,
+        }
+    }
+
+    /// Return the number of cylinders the drive can normally seek to.
+    pub fn cylinders(&self) -> u16 {
+        match self {
+            MediaProfile::Dd5_25 => 40,
+            MediaProfile::Sd8 | MediaProfile::Dd8 => 77,
+            MediaProfile::Amstrad3 => 40,
+            _ => 80,
+        }
+    }
+
+    /// Return the number of sides the drive can read without flipping the disk.
+    pub fn sides(&self) -> u8 {
+        match self {
+            MediaProfile::Amstrad3 => 1,
+            _ => 2,
+        }
+    }
+
+    pub fn rpm(&self) -> DiskRpm {
+        match self {
+            MediaProfile::Hd5_25 | MediaProfile::Sd8 | MediaProfile::Dd8 => DiskRpm::Rpm360,
+            _ => DiskRpm::Rpm300,
+        }
+    }
+
+    pub fn data_rate(&self) -> DiskDataRate {
+        match self {
+            MediaProfile::Dd5_25 | MediaProfile::Dd3_5 | MediaProfile::Sd8 | MediaProfile::Amstrad3 => {
+                DiskDataRate::Rate250Kbps
+            }
+            MediaProfile::Hd5_25 | MediaProfile::Hd3_5 | MediaProfile::Dd8 => DiskDataRate::Rate500Kbps,
+            MediaProfile::Ed3_5 => DiskDataRate::Rate1000Kbps,
+        }
+    }
+
+    pub fn encoding(&self) -> DiskDataEncoding {
+        match self {
+            MediaProfile::Sd8 => DiskDataEncoding::Fm,
+            _ => DiskDataEncoding::Mfm,
+        }
+    }
+
+    pub fn density(&self) -> DiskDensity {
+        match self {
+            MediaProfile::Sd8 => DiskDensity::Standard,
+            MediaProfile::Hd5_25 | MediaProfile::Hd3_5 => DiskDensity::High,
+            MediaProfile::Ed3_5 => DiskDensity::Extended,
+            _ => DiskDensity::Double,
+        }
+    }
+
+    pub fn density_hole(&self) -> DensityHole {
+        match self {
+            MediaProfile::Hd3_5 => DensityHole::HighDensity,
+            MediaProfile::Ed3_5 => DensityHole::ExtendedDensity,
+            _ => DensityHole::None,
+        }
+    }
+
+    /// Return true if the medium has a high density sensing hole.
+    pub fn hd_hole(&self) -> bool {
+        self.density_hole() == DensityHole::HighDensity
+    }
+
+    /// Return the number of bitcells that fit on one revolution of a track at the nominal data
+    /// rate and rotation rate of this medium.
+    pub fn bitcells(&self) -> usize {
+        self.rpm().track_bitcells(self.data_rate())
+    }
+
+    /// Return the number of decoded bytes that fit on one revolution of a track.
+    pub fn track_bytes(&self) -> usize {
+        self.bitcells() / 16
+    }
+}
@@ -0,0 +1,291 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/packed.rs
+
+    Detection of compressed executables and archives within the sectors of a
+    disk image, for classifying the contents of a collection without needing to
+    understand the filesystem on each disk.
+*/
+
+use crate::util::crc32;
+use std::fmt::{self, Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// The largest basic header an ARJ archive may contain.
+pub const ARJ_MAX_HEADER_SIZE: usize = 2600;
+
+/// A kind of compressed payload recognized by [`scan`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PackedKind {
+    /// A DOS executable compressed with PKWARE's PKLITE.
+    Pklite,
+    /// A DOS executable compressed with Fabrice Bellard's LZEXE.
+    Lzexe,
+    /// A ZIP archive, including the archive appended to a self-extracting executable.
+    Zip,
+    /// An ARJ archive, including the archive appended to a self-extracting executable.
+    Arj,
+}
+
+impl Display for PackedKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PackedKind::Pklite => write!(f, "PKLITE executable"),
+            PackedKind::Lzexe => write!(f, "LZEXE executable"),
+            PackedKind::Zip => write!(f, "ZIP archive"),
+            PackedKind::Arj => write!(f, "ARJ archive"),
+        }
+    }
+}
+
+/// A compressed executable or archive found by [`scan`], as returned by
+/// [`crate::DiskImage::scan_packed`].
+///
+/// Offsets are into the sectors of the disk in logical block order. A file is assumed to occupy
+/// consecutive sectors, as it will on most disks written in one go; the extent of a file that is
+/// fragmented on disk will be wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedRegion {
+    pub kind: PackedKind,
+    /// The byte offset of the start of the region.
+    pub offset: usize,
+    /// The length of the region in bytes, as given by its headers.
+    pub len: usize,
+    /// The logical block addresses of the sectors the region occupies.
+    pub lbas: RangeInclusive<usize>,
+    /// The name of the archive, or of its first file, if recorded in its headers.
+    pub name: Option<String>,
+    /// True if the region is cut off by the end of the disk, or its end could not be found.
+    pub truncated: bool,
+}
+
+impl Display for PackedRegion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {:#X}, {} bytes in sectors {}-{}",
+            self.kind,
+            self.offset,
+            self.len,
+            self.lbas.start(),
+            self.lbas.end()
+        )?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        if self.truncated {
+            write!(f, " [truncated]")?;
+        }
+        Ok(())
+    }
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> Option<usize> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> Option<usize> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// Return the null-terminated string at `offset`, if it is non-empty.
+fn c_string_at(buf: &[u8], offset: usize) -> Option<String> {
+    let bytes = buf.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    (end > 0).then(|| String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+/// Scan `volume`, the data of every sector of a disk in logical block order, for compressed
+/// executables and archives. Executables are only recognized at the start of a sector, where a
+/// file will begin on disk. Archives are recognized anywhere, so that the archive appended to a
+/// self-extracting executable is found as well.
+///
+/// The returned regions are in order of offset and do not overlap.
+pub fn scan(volume: &[u8], sector_size: usize) -> Vec<PackedRegion> {
+    let sector_size = sector_size.max(1);
+    let mut regions = Vec::new();
+    let mut pos = 0;
+
+    while pos < volume.len() {
+        let found = match pos % sector_size {
+            0 => detect_exe(volume, pos),
+            _ => None,
+        }
+        .or_else(|| detect_zip(volume, pos))
+        .or_else(|| detect_arj(volume, pos));
+
+        match found {
+            Some((kind, len, name, truncated)) => {
+                let end = pos + len.max(1) - 1;
+                log::debug!("scan(): Found {} at offset {:#X}, {} bytes", kind, pos, len);
+                regions.push(PackedRegion {
+                    kind,
+                    offset: pos,
+                    len,
+                    lbas: (pos / sector_size)..=(end / sector_size),
+                    name,
+                    truncated,
+                });
+                pos = end + 1;
+            }
+            None => pos += 1,
+        }
+    }
+    regions
+}
+
+type Detection = (PackedKind, usize, Option<String>, bool);
+
+/// Clamp the length `len` of a region at `pos` to the end of `volume`, returning the length and
+/// whether it was truncated.
+fn clamp_len(volume: &[u8], pos: usize, len: usize) -> (usize, bool) {
+    let available = volume.len() - pos;
+    (len.min(available), len > available)
+}
+
+/// Detect a PKLITE or LZEXE compressed DOS executable, sized by its MZ header.
+fn detect_exe(volume: &[u8], pos: usize) -> Option<Detection> {
+    let header = volume.get(pos..pos + 0x60)?;
+    if &header[0..2] != b"MZ" && &header[0..2] != b"ZM" {
+        return None;
+    }
+
+    let kind = if matches!(&header[0x1C..0x20], b"LZ09" | b"LZ91") {
+        PackedKind::Lzexe
+    } else if header[0x1C..].windows(6).any(|w| w.eq_ignore_ascii_case(b"PKLITE")) {
+        PackedKind::Pklite
+    } else {
+        return None;
+    };
+
+    // The image size is given in 512-byte pages, the last of which may be partial.
+    let last_page = u16_at(header, 2)?;
+    let pages = u16_at(header, 4)?;
+    if pages == 0 || last_page >= 512 {
+        return None;
+    }
+    let size = pages * 512 - if last_page > 0 { 512 - last_page } else { 0 };
+
+    let (len, truncated) = clamp_len(volume, pos, size);
+    Some((kind, len, None, truncated))
+}
+
+/// Detect a ZIP archive starting with a local file header, extending to the end of its central
+/// directory.
+fn detect_zip(volume: &[u8], pos: usize) -> Option<Detection> {
+    if volume.get(pos..pos + 4)? != b"PK\x03\x04" {
+        return None;
+    }
+    // Reject implausible version and compression method fields.
+    let version = u16_at(volume, pos + 4)?;
+    let method = u16_at(volume, pos + 8)?;
+    let name_len = u16_at(volume, pos + 26)?;
+    if version > 63 || method > 99 || name_len == 0 {
+        return None;
+    }
+    let name = volume
+        .get(pos + 30..pos + 30 + name_len)
+        .map(|name| String::from_utf8_lossy(name).to_string());
+
+    // The archive ends with the end of central directory record and its comment.
+    let eocd = volume[pos..]
+        .windows(4)
+        .position(|w| w == b"PK\x05\x06")
+        .map(|i| pos + i);
+    let (len, truncated) = match eocd.and_then(|eocd| Some((eocd, u16_at(volume, eocd + 20)?))) {
+        Some((eocd, comment_len)) => clamp_len(volume, pos, eocd + 22 + comment_len - pos),
+        None => (volume.len() - pos, true),
+    };
+    Some((PackedKind::Zip, len, name, truncated))
+}
+
+/// Read the ARJ header at `pos`, verifying its CRC.
+///
+/// # Returns
+/// - `Some((Some(header), next))` for a valid header, where `header` is the basic header and
+///   `next` is the offset following the header and its extended headers.
+/// - `Some((None, next))` for the end of archive marker.
+/// - `None` if there is no valid header at `pos`.
+fn arj_header(volume: &[u8], pos: usize) -> Option<(Option<&[u8]>, usize)> {
+    if volume.get(pos..pos + 2)? != [0x60, 0xEA] {
+        return None;
+    }
+    let size = u16_at(volume, pos + 2)?;
+    if size == 0 {
+        return Some((None, pos + 4));
+    }
+    if size > ARJ_MAX_HEADER_SIZE {
+        return None;
+    }
+    let header = volume.get(pos + 4..pos + 4 + size)?;
+    if crc32(header) as usize != u32_at(volume, pos + 4 + size)? {
+        return None;
+    }
+
+    // Skip any extended headers, each followed by its own CRC.
+    let mut next = pos + 4 + size + 4;
+    loop {
+        let ext_size = u16_at(volume, next)?;
+        next += 2;
+        if ext_size == 0 {
+            break;
+        }
+        next += ext_size + 4;
+    }
+    Some((Some(header), next))
+}
+
+/// Detect an ARJ archive starting with its main header, extending to its end of archive marker.
+fn detect_arj(volume: &[u8], pos: usize) -> Option<Detection> {
+    let (Some(main_header), mut next) = arj_header(volume, pos)? else {
+        return None;
+    };
+    // The main header has a file type of 2. The archive name follows the fixed header fields.
+    let first_size = *main_header.first()? as usize;
+    if main_header.get(6) != Some(&2) || first_size > main_header.len() {
+        return None;
+    }
+    let name = c_string_at(main_header, first_size);
+
+    // Each file header is followed by the file's compressed data.
+    loop {
+        match arj_header(volume, next) {
+            Some((Some(header), data)) => next = data + u32_at(header, 12)?,
+            Some((None, end)) => {
+                let (len, truncated) = clamp_len(volume, pos, end - pos);
+                return Some((PackedKind::Arj, len, name, truncated));
+            }
+            None if next >= volume.len() => return Some((PackedKind::Arj, volume.len() - pos, name, true)),
+            None => {
+                // A damaged header; report what we could follow.
+                return Some((PackedKind::Arj, next - pos, name, true));
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/platform.rs
+
+    Detection of the platform a disk was written for, from its boot sector,
+    filesystem structures and track encoding.
+*/
+
+use crate::bitstream::TrackDataStream;
+use crate::boot_sector::BootSector;
+use crate::diskimage::RwSectorScope;
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskChs, DiskDataEncoding, DiskImage};
+use std::fmt::{self, Display, Formatter};
+use std::io::Cursor;
+
+/// Two Amiga sync words preceded by MFM encoded zeros. IBM sync fields contain the same pattern,
+/// so it only indicates an Amiga track when no IBM sector IDs are found.
+const AMIGA_SYNC: u64 = 0xAAAA_AAAA_4489_4489;
+/// The checksum of the big-endian words of an executable Atari ST boot sector.
+const ATARI_BOOT_CHECKSUM: u16 = 0x1234;
+/// The number of cylinders searched for a CP/M directory. CP/M reserves up to three system
+/// tracks before the directory.
+const CPM_DIRECTORY_CYLINDERS: u16 = 4;
+
+/// The platform, or family of operating systems, a disk was written for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DiskPlatform {
+    Dos,
+    Amiga,
+    AtariSt,
+    Cpm,
+    Pc98,
+    Unknown,
+}
+
+impl DiskPlatform {
+    /// Return true if the platform lays out its tracks in the IBM System 34 format, and so can be
+    /// read with [`System34Parser`](crate::structure_parsers::system34::System34Parser).
+    pub fn uses_system34(&self) -> bool {
+        !matches!(self, DiskPlatform::Amiga | DiskPlatform::Unknown)
+    }
+
+    /// Return true if the platform normally uses a FAT filesystem, described by a BIOS parameter
+    /// block in the boot sector.
+    pub fn uses_fat(&self) -> bool {
+        matches!(self, DiskPlatform::Dos | DiskPlatform::AtariSt | DiskPlatform::Pc98)
+    }
+}
+
+impl Display for DiskPlatform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskPlatform::Dos => write!(f, "DOS"),
+            DiskPlatform::Amiga => write!(f, "Amiga"),
+            DiskPlatform::AtariSt => write!(f, "Atari ST"),
+            DiskPlatform::Cpm => write!(f, "CP/M"),
+            DiskPlatform::Pc98 => write!(f, "PC-98"),
+            DiskPlatform::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// A single observation in favor of a platform. Stronger evidence carries a greater weight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlatformEvidence {
+    pub platform: DiskPlatform,
+    pub weight: u32,
+    pub description: String,
+}
+
+/// The evidence gathered by [`DiskImage::detect_platform`].
+#[derive(Clone, Debug, Default)]
+pub struct PlatformReport {
+    pub evidence: Vec<PlatformEvidence>,
+}
+
+impl PlatformReport {
+    fn add(&mut self, platform: DiskPlatform, weight: u32, description: impl Into<String>) {
+        self.evidence.push(PlatformEvidence {
+            platform,
+            weight,
+            description: description.into(),
+        });
+    }
+
+    /// Return the total weight of the evidence for each platform with any evidence, from the
+    /// highest to the lowest.
+    pub fn scores(&self) -> Vec<(DiskPlatform, u32)> {
+        let mut scores: Vec<(DiskPlatform, u32)> = Vec::new();
+        for e in &self.evidence {
+            match scores.iter_mut().find(|(p, _)| *p == e.platform) {
+                Some((_, score)) => *score += e.weight,
+                None => scores.push((e.platform, e.weight)),
+            }
+        }
+        scores.sort_by(|(p1, s1), (p2, s2)| s2.cmp(s1).then(p1.cmp(p2)));
+        scores
+    }
+
+    /// Return the platform with the most evidence, or [`DiskPlatform::Unknown`] if there is none.
+    pub fn platform(&self) -> DiskPlatform {
+        self.scores().first().map_or(DiskPlatform::Unknown, |(p, _)| *p)
+    }
+}
+
+impl Display for PlatformReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Platform: {}", self.platform())?;
+        for e in &self.evidence {
+            writeln!(f, "  {:<8} +{} {}", e.platform, e.weight, e.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gather evidence of the platform `image` was written for.
+pub(crate) fn detect(image: &mut DiskImage) -> PlatformReport {
+    let mut report = PlatformReport::default();
+    let ch = DiskCh::new(0, 0);
+
+    if let Some(track) = image.get_track_ch(ch) {
+        examine_track(track, image.geometry(), &mut report);
+    }
+    if let Some(buf) = read_first_sector(image, ch) {
+        examine_boot_sector(&buf, &mut report);
+    }
+    for c in 0..CPM_DIRECTORY_CYLINDERS.min(image.geometry().c()) {
+        if let Some(buf) = read_first_sector(image, DiskCh::new(c, 0)) {
+            if is_cpm_directory(&buf) {
+                report.add(
+                    DiskPlatform::Cpm,
+                    3,
+                    format!("CP/M directory entries on cylinder {}", c),
+                );
+                break;
+            }
+        }
+    }
+    report
+}
+
+/// Examine the encoding and sector layout of the first track.
+fn examine_track(track: &TrackData, geometry: DiskCh, report: &mut PlatformReport) {
+    let sectors = track.get_sector_list();
+
+    if let TrackData::BitStream {
+        data: TrackDataStream::Mfm(codec),
+        ..
+    }
+    | TrackData::FluxStream {
+        data: TrackDataStream::Mfm(codec),
+        ..
+    } = track
+    {
+        if track.is_amiga() {
+            report.add(DiskPlatform::Amiga, 3, "Amiga trackdisk sectors");
+        } else if sectors.is_empty() && codec.find_marker(AMIGA_SYNC, 0, None).is_some() {
+            report.add(DiskPlatform::Amiga, 3, "Amiga sync words with no IBM sector IDs");
+        }
+    }
+
+    if matches!(track.encoding(), DiskDataEncoding::Fm) {
+        report.add(DiskPlatform::Cpm, 1, "FM encoded first track");
+    }
+
+    let n = sectors.first().map(|s| s.chsn.n());
+    match n {
+        Some(0) if sectors.len() == 26 => {
+            report.add(
+                DiskPlatform::Cpm,
+                2,
+                "26 128-byte sectors, the 8\" CP/M interchange format",
+            );
+        }
+        Some(3) if geometry.c() == 77 => {
+            report.add(DiskPlatform::Pc98, 3, "1024-byte sectors on 77 cylinders");
+        }
+        Some(3) => {
+            report.add(DiskPlatform::Pc98, 1, "1024-byte sectors");
+        }
+        _ => {}
+    }
+}
+
+/// Examine the first sector of the disk for boot code and parameter blocks.
+fn examine_boot_sector(buf: &[u8], report: &mut PlatformReport) {
+    if buf.len() < 512 {
+        return;
+    }
+
+    if buf.starts_with(b"DOS") && buf[3] < 8 {
+        report.add(DiskPlatform::Amiga, 3, "AmigaDOS boot block");
+    }
+
+    let x86_jump = buf[0] == 0xEB || buf[0] == 0xE9;
+    if x86_jump && buf[510..512] == [0x55, 0xAA] {
+        report.add(DiskPlatform::Dos, 3, "x86 jump and 0x55AA boot signature");
+    } else if x86_jump {
+        report.add(DiskPlatform::Dos, 1, "x86 jump instruction");
+    }
+
+    if BootSector::new(&mut Cursor::new(&buf[..512])).is_ok_and(|bs| bs.has_valid_bpb()) {
+        report.add(DiskPlatform::Dos, 1, "valid BIOS parameter block");
+    }
+
+    let oem = &buf[3..11];
+    if oem.starts_with(b"IPL1") || oem.starts_with(b"NEC") {
+        report.add(DiskPlatform::Pc98, 3, "PC-98 boot loader signature");
+    }
+
+    // 0x60 is the opcode of the 68000 BRA.S instruction.
+    if buf[0] == 0x60 {
+        report.add(DiskPlatform::AtariSt, 2, "68000 branch instruction");
+    }
+    let checksum = buf[..512]
+        .chunks_exact(2)
+        .fold(0u16, |sum, w| sum.wrapping_add(u16::from_be_bytes([w[0], w[1]])));
+    if checksum == ATARI_BOOT_CHECKSUM {
+        report.add(DiskPlatform::AtariSt, 3, "executable Atari ST boot sector checksum");
+    }
+}
+
+/// Return true if `buf` holds CP/M directory entries: every entry is either erased, with a user
+/// number of 0xE5, or has a user number from 0 to 15 and a printable file name, and at least one
+/// entry is in use.
+fn is_cpm_directory(buf: &[u8]) -> bool {
+    let mut used = 0;
+    for entry in buf.chunks_exact(32) {
+        if entry[0] == 0xE5 {
+            continue;
+        }
+        // The high bits of the name and extension hold file attributes.
+        let name = entry[1..12].iter().map(|b| b & 0x7F);
+        if entry[0] > 15 || !name.clone().all(|b| (0x20..0x7F).contains(&b)) || name.clone().all(|b| b == b' ') {
+            return false;
+        }
+        used += 1;
+    }
+    used > 0
+}
+
+/// Read the data of the lowest numbered sector on the track `ch`.
+fn read_first_sector(image: &mut DiskImage, ch: DiskCh) -> Option<Vec<u8>> {
+    let s = image
+        .get_track_ch(ch)?
+        .get_sector_list()
+        .iter()
+        .map(|s| s.chsn.s())
+        .min()?;
+    let rsr = image
+        .read_sector(DiskChs::new(ch.c(), ch.h(), s), None, RwSectorScope::DataOnly, false)
+        .ok()?;
+    rsr.read_buf
+        .get(rsr.data_idx..rsr.data_idx + rsr.data_len)
+        .map(|d| d.to_vec())
+}
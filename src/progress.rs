@@ -0,0 +1,132 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+
+    --------------------------------------------------------------------------
+
+    src/progress.rs
+
+    Progress reporting and cancellation for long-running operations.
+*/
+
+use crate::DiskImageError;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A [`CancellationToken`] requests that a long-running operation stop early. Clones of a token
+/// share the same state, so a token may be cancelled from another thread while an operation is in
+/// progress.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Request cancellation of any operation observing this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The status of a long-running operation, passed to a progress callback.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProgressStatus {
+    /// A short name for the operation, such as "resample" or "format".
+    pub operation: &'static str,
+    /// The number of units of work completed, typically tracks.
+    pub completed: usize,
+    /// The total number of units of work.
+    pub total: usize,
+}
+
+type ProgressCallback = Arc<dyn Fn(ProgressStatus) + Send + Sync>;
+
+/// A [`Progress`] combines a progress callback and a [`CancellationToken`]. It is set on a
+/// [`crate::DiskImage`] with [`crate::DiskImage::set_progress`], and is observed by all of the
+/// image's long-running operations.
+///
+/// Operations check for cancellation each time they report progress, and return
+/// [`DiskImageError::Cancelled`] if it has been requested.
+#[derive(Clone, Default)]
+pub struct Progress {
+    callback: Option<ProgressCallback>,
+    token: CancellationToken,
+}
+
+impl Debug for Progress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("callback", &self.callback.is_some())
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the function to be called as an operation makes progress.
+    pub fn with_callback(mut self, callback: impl Fn(ProgressStatus) + Send + Sync + 'static) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set the [`CancellationToken`] used to cancel operations.
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Report that `completed` of `total` units of `operation` are done.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::Cancelled)` if cancellation has been requested.
+    pub(crate) fn report(&self, operation: &'static str, completed: usize, total: usize) -> Result<(), DiskImageError> {
+        if self.token.is_cancelled() {
+            log::debug!("{}: Cancelled after {} of {}", operation, completed, total);
+            return Err(DiskImageError::Cancelled);
+        }
+        if let Some(callback) = &self.callback {
+            callback(ProgressStatus {
+                operation,
+                completed,
+                total,
+            });
+        }
+        Ok(())
+    }
+}
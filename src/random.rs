@@ -26,8 +26,10 @@
 
     src/random.rs
 
-    Provide a simple random bit generator.
+    Provide a simple random bit generator, and a seedable random source for
+    everywhere fluxfox returns random data.
 */
+use std::sync::atomic::{AtomicU64, Ordering};
 
 const RANDOM_BITS_SIZE: usize = 2048;
 
@@ -58,3 +60,67 @@ pub fn random_bit(index: usize) -> bool {
 pub fn random_bit_ref(index: usize) -> &'static bool {
     &PSEUDO_RANDOM_BITS[index & (RANDOM_BITS_SIZE - 1)]
 }
+
+/// A seedable source of random data, used wherever fluxfox returns random data such as when
+/// reading weak bits. A [`RandomSource`] created by [`Default`] is seeded from entropy; one created
+/// with [`RandomSource::new`] produces the same sequence for the same seed, so runs can be made
+/// reproducible.
+///
+/// The state is held atomically so that it can be advanced by read methods taking `&self`.
+/// Cloning a [`RandomSource`] copies its current state.
+#[derive(Debug)]
+pub(crate) struct RandomSource {
+    state: AtomicU64,
+}
+
+impl RandomSource {
+    const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    /// Create a new [`RandomSource`] with the specified seed.
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Derive a new [`RandomSource`] for the stream `index` of the specified seed, so that
+    /// independent consumers seeded from one seed do not produce the same sequence.
+    pub(crate) fn derive(seed: u64, index: usize) -> Self {
+        Self::new(RandomSource::mix(seed ^ RandomSource::mix(index as u64)))
+    }
+
+    /// Return the next 64 random bits, using the SplitMix64 generator.
+    pub(crate) fn next_u64(&self) -> u64 {
+        let state = self
+            .state
+            .fetch_add(Self::GOLDEN_GAMMA, Ordering::Relaxed)
+            .wrapping_add(Self::GOLDEN_GAMMA);
+        RandomSource::mix(state)
+    }
+
+    pub(crate) fn next_u32(&self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    pub(crate) fn next_bool(&self) -> bool {
+        self.next_u64() >> 63 != 0
+    }
+
+    fn mix(mut z: u64) -> u64 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for RandomSource {
+    fn default() -> Self {
+        Self::new(rand::random())
+    }
+}
+
+impl Clone for RandomSource {
+    fn clone(&self) -> Self {
+        Self::new(self.state.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,134 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/registry.rs
+
+    A process-wide registry of image formats supplied by other crates, and of
+    the order in which formats are tried when detecting the format of an
+    image.
+
+    Registered formats are loaded through plain function pointers, so that a
+    proprietary or niche format can be supported without forking fluxfox. They
+    are read-only; fluxfox cannot save images in a registered format.
+*/
+
+use crate::diskimage::ParseMode;
+use crate::file_parsers::{FormatCaps, IMAGE_FORMATS};
+use crate::{DiskDataResolution, DiskImage, DiskImageError, DiskImageFormat};
+use std::sync::RwLock;
+
+pub use crate::io::ReadSeek;
+
+/// A disk image format implemented outside fluxfox, registered with [`register_format`].
+#[derive(Clone)]
+pub struct CustomFormat {
+    /// A human-readable name for the format.
+    pub name: String,
+    /// The file extensions associated with the format, in lowercase and without a leading dot.
+    pub extensions: Vec<&'static str>,
+    /// A short description of how the format is identified by its content.
+    pub magic: &'static str,
+    /// The resolution of the tracks created by the loader.
+    pub resolution: DiskDataResolution,
+    /// The capability flags of the format.
+    pub capabilities: FormatCaps,
+    /// Return true if the image is of this format. The image is rewound to its start beforehand.
+    pub detect: fn(&mut dyn ReadSeek) -> bool,
+    /// Create a DiskImage from the image, checking it as strictly as the [`ParseMode`] requires.
+    /// The image is rewound to its start beforehand.
+    pub load: fn(&mut dyn ReadSeek, ParseMode) -> Result<DiskImage, DiskImageError>,
+}
+
+struct Registry {
+    formats: Vec<CustomFormat>,
+    order: Option<Vec<DiskImageFormat>>,
+}
+
+static REGISTRY: RwLock<Registry> = RwLock::new(Registry {
+    formats: Vec::new(),
+    order: None,
+});
+
+/// Register a custom image format, returning the [`DiskImageFormat`] that identifies it.
+/// Unless a detection order has been set with [`set_detection_order`], registered formats are
+/// tried in the order they were registered, before any built-in format.
+pub fn register_format(format: CustomFormat) -> DiskImageFormat {
+    let mut registry = REGISTRY.write().unwrap();
+    registry.formats.push(format);
+    DiskImageFormat::Custom(registry.formats.len() as u16 - 1)
+}
+
+/// Return the description of the registered format `id`, if any.
+pub fn custom_format(id: u16) -> Option<CustomFormat> {
+    REGISTRY.read().unwrap().formats.get(id as usize).cloned()
+}
+
+/// Return every available format: the built-in formats followed by registered formats.
+pub fn all_formats() -> Vec<DiskImageFormat> {
+    let registry = REGISTRY.read().unwrap();
+    IMAGE_FORMATS
+        .iter()
+        .copied()
+        .chain((0..registry.formats.len()).map(|id| DiskImageFormat::Custom(id as u16)))
+        .collect()
+}
+
+/// Set the order in which formats are tried when detecting the format of an image. Formats not
+/// in `order` are not detected, though they may still be loaded explicitly. A format hint, such
+/// as the file extension of an image loaded by path, is still checked first.
+///
+/// # Returns
+/// - `Err(DiskImageError::UnknownFormat)` if `order` contains a custom format that has not been
+///   registered.
+pub fn set_detection_order(order: &[DiskImageFormat]) -> Result<(), DiskImageError> {
+    let mut registry = REGISTRY.write().unwrap();
+    let registered = registry.formats.len();
+    if order
+        .iter()
+        .any(|f| matches!(f, DiskImageFormat::Custom(id) if *id as usize >= registered))
+    {
+        return Err(DiskImageError::UnknownFormat);
+    }
+    registry.order = Some(order.to_vec());
+    Ok(())
+}
+
+/// Restore the default detection order, replacing any order set with [`set_detection_order`].
+pub fn reset_detection_order() {
+    REGISTRY.write().unwrap().order = None;
+}
+
+/// Return the order in which formats are tried when detecting the format of an image.
+pub fn detection_order() -> Vec<DiskImageFormat> {
+    let registry = REGISTRY.read().unwrap();
+    match &registry.order {
+        Some(order) => order.clone(),
+        None => (0..registry.formats.len())
+            .map(|id| DiskImageFormat::Custom(id as u16))
+            .chain(IMAGE_FORMATS.iter().copied())
+            .collect(),
+    }
+}
@@ -0,0 +1,174 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/shared.rs
+
+    Shared access to a disk image from multiple threads, with a lock per track
+    so that operations on different tracks do not block each other.
+*/
+
+use crate::diskimage::{DiskImageFlags, ReadSectorResult, RwSectorScope, TrackMapEntry, WriteSectorResult};
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskChs, DiskImage, DiskImageError};
+use std::sync::RwLock;
+
+/// A [`SharedDiskImage`] allows a [`DiskImage`] to be read and written from several threads at
+/// once, such as an emulator thread writing sectors while a UI thread renders the sector map.
+///
+/// Each track is guarded by its own lock, so an operation on one track never waits on an
+/// operation on another. Image-level state, such as the track map and consistency information,
+/// is guarded by a separate lock that is only held briefly and never while a track is locked.
+///
+/// The set of tracks is fixed while the image is shared. Operations that add, remove or re-order
+/// tracks require taking the image back with [`SharedDiskImage::into_inner`].
+pub struct SharedDiskImage {
+    image: RwLock<DiskImage>,
+    tracks: Vec<RwLock<TrackData>>,
+}
+
+impl From<DiskImage> for SharedDiskImage {
+    fn from(image: DiskImage) -> Self {
+        Self::new(image)
+    }
+}
+
+impl SharedDiskImage {
+    pub fn new(mut image: DiskImage) -> Self {
+        let tracks = std::mem::take(&mut image.track_pool)
+            .into_iter()
+            .map(RwLock::new)
+            .collect();
+        Self {
+            image: RwLock::new(image),
+            tracks,
+        }
+    }
+
+    /// Return the image, with all changes made while it was shared.
+    pub fn into_inner(self) -> DiskImage {
+        let mut image = self.image.into_inner().unwrap();
+        image.track_pool = self.tracks.into_iter().map(|t| t.into_inner().unwrap()).collect();
+        image
+    }
+
+    /// Return the index of the track identified by `ch`.
+    fn track_index(image: &DiskImage, ch: DiskCh) -> Result<usize, DiskImageError> {
+        image
+            .track_map
+            .get(ch.h() as usize)
+            .and_then(|heads| heads.get(ch.c() as usize))
+            .copied()
+            .ok_or(DiskImageError::SeekError)
+    }
+
+    /// Read the sector data from the sector identified by `chs`, as [`DiskImage::read_sector`]
+    /// does. The track is locked exclusively for the duration of the read, as a read may update
+    /// the state of the track.
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track is not present in the image.
+    pub fn read_sector(
+        &self,
+        chs: DiskChs,
+        n: Option<u8>,
+        scope: RwSectorScope,
+        debug: bool,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        let (ti, policy) = {
+            let image = self.image.read().unwrap();
+            (Self::track_index(&image, DiskCh::from(chs))?, image.match_policy)
+        };
+        self.tracks[ti]
+            .write()
+            .unwrap()
+            .read_sector(chs, n, scope, policy, debug)
+    }
+
+    /// Write `data` to the sector identified by `chs`, as [`DiskImage::write_sector`] does.
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track is not present in the image.
+    /// - `Err(DiskImageError::IoError)` if the image's backup policy could not be applied.
+    pub fn write_sector(
+        &self,
+        chs: DiskChs,
+        n: Option<u8>,
+        data: &[u8],
+        scope: RwSectorScope,
+        deleted: bool,
+        debug: bool,
+    ) -> Result<WriteSectorResult, DiskImageError> {
+        let (ti, policy) = {
+            let mut image = self.image.write().unwrap();
+            let ti = Self::track_index(&image, DiskCh::from(chs))?;
+            image.begin_write()?;
+            (ti, image.match_policy)
+        };
+
+        let (result, sectors, resolution) = {
+            let mut track = self.tracks[ti].write().unwrap();
+            let result = track.write_sector(chs, n, data, scope, policy, deleted, debug, None)?;
+            (result, track.get_sector_list(), track.resolution())
+        };
+
+        let mut image = self.image.write().unwrap();
+        image.set_flag(DiskImageFlags::DIRTY);
+        image.record_sector_consistency(chs, &sectors, resolution);
+        Ok(result)
+    }
+
+    /// Return the map entry of the track identified by `ch`, as an entry of
+    /// [`DiskImage::get_sector_map`], or `None` if the track is not present. The track is only
+    /// locked for reading, so several threads may build maps of the same track at once.
+    pub fn track_map_entry(&self, ch: DiskCh) -> Option<TrackMapEntry> {
+        let ti = Self::track_index(&self.image.read().unwrap(), ch).ok()?;
+        let track = self.tracks[ti].read().unwrap();
+        Some(TrackMapEntry::from(&*track))
+    }
+
+    /// Call `f` with the track identified by `ch`, holding the track's lock for reading.
+    /// Returns `None` if the track is not present.
+    pub fn with_track<R>(&self, ch: DiskCh, f: impl FnOnce(&TrackData) -> R) -> Option<R> {
+        let ti = Self::track_index(&self.image.read().unwrap(), ch).ok()?;
+        let track = self.tracks[ti].read().unwrap();
+        Some(f(&track))
+    }
+
+    /// Call `f` with the track identified by `ch` for modification, holding the track's lock
+    /// exclusively. The image is marked as modified.
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track is not present in the image.
+    /// - `Err(DiskImageError::IoError)` if the image's backup policy could not be applied.
+    pub fn with_track_mut<R>(&self, ch: DiskCh, f: impl FnOnce(&mut TrackData) -> R) -> Result<R, DiskImageError> {
+        let ti = {
+            let mut image = self.image.write().unwrap();
+            let ti = Self::track_index(&image, ch)?;
+            image.begin_write()?;
+            image.set_flag(DiskImageFlags::DIRTY);
+            ti
+        };
+        let mut track = self.tracks[ti].write().unwrap();
+        Ok(f(&mut track))
+    }
+}
@@ -44,8 +44,13 @@
         2.88M ED Double-Sided 3.5"
 */
 use crate::diskimage::DiskDescriptor;
+use crate::media::MediaProfile;
 use crate::{DiskCh, DiskChs, DiskChsn, DiskDataEncoding, DiskDataRate, DiskDensity, DiskRpm, DEFAULT_SECTOR_SIZE};
 
+/// The most cylinders beyond the standard count of a format that may be present in an image of a
+/// disk in that format. Many dumps capture a few tracks past the last formatted one.
+pub const MAX_OVERDUMP_CYLINDERS: u16 = 6;
+
 /// An enumeration describing the type of disk image.
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub enum StandardFormat {
@@ -92,18 +97,33 @@ impl StandardFormat {
 
     pub fn get_data_rate(&self) -> DiskDataRate {
         match self {
-            StandardFormat::PcFloppy160 => DiskDataRate::Rate500Kbps,
-            StandardFormat::PcFloppy180 => DiskDataRate::Rate500Kbps,
-            StandardFormat::PcFloppy320 => DiskDataRate::Rate500Kbps,
-            StandardFormat::PcFloppy360 => DiskDataRate::Rate500Kbps,
-            StandardFormat::PcFloppy720 => DiskDataRate::Rate500Kbps,
+            StandardFormat::PcFloppy160 => DiskDataRate::Rate250Kbps,
+            StandardFormat::PcFloppy180 => DiskDataRate::Rate250Kbps,
+            StandardFormat::PcFloppy320 => DiskDataRate::Rate250Kbps,
+            StandardFormat::PcFloppy360 => DiskDataRate::Rate250Kbps,
+            StandardFormat::PcFloppy720 => DiskDataRate::Rate250Kbps,
             StandardFormat::PcFloppy1200 => DiskDataRate::Rate500Kbps,
             StandardFormat::PcFloppy1440 => DiskDataRate::Rate500Kbps,
-            StandardFormat::PcFloppy2880 => DiskDataRate::Rate500Kbps,
+            StandardFormat::PcFloppy2880 => DiskDataRate::Rate1000Kbps,
             _ => DiskDataRate::Rate500Kbps,
         }
     }
 
+    /// Return the [`MediaProfile`] of the physical media this format is normally written to.
+    pub fn get_media_profile(&self) -> MediaProfile {
+        match self {
+            StandardFormat::PcFloppy160 => MediaProfile::Dd5_25,
+            StandardFormat::PcFloppy180 => MediaProfile::Dd5_25,
+            StandardFormat::PcFloppy320 => MediaProfile::Dd5_25,
+            StandardFormat::PcFloppy360 => MediaProfile::Dd5_25,
+            StandardFormat::PcFloppy720 => MediaProfile::Dd3_5,
+            StandardFormat::PcFloppy1200 => MediaProfile::Hd5_25,
+            StandardFormat::PcFloppy1440 => MediaProfile::Hd3_5,
+            StandardFormat::PcFloppy2880 => MediaProfile::Ed3_5,
+            _ => MediaProfile::Dd5_25,
+        }
+    }
+
     pub fn get_density(&self) -> DiskDensity {
         DiskDensity::from(self.get_data_rate())
     }
@@ -122,17 +142,12 @@ impl StandardFormat {
         }
     }
 
+    /// Return the number of bitcells in one revolution of a track of this format, as determined
+    /// by its data rate and rotation speed.
     pub fn get_bitcell_ct(&self) -> usize {
         match self {
-            StandardFormat::PcFloppy160 => 100_000,
-            StandardFormat::PcFloppy180 => 100_000,
-            StandardFormat::PcFloppy320 => 100_000,
-            StandardFormat::PcFloppy360 => 100_000,
-            StandardFormat::PcFloppy720 => 100_000,
-            StandardFormat::PcFloppy1200 => 166_666,
-            StandardFormat::PcFloppy1440 => 200_000,
-            StandardFormat::PcFloppy2880 => 400_000,
-            _ => 100_000,
+            StandardFormat::Invalid => 100_000,
+            _ => self.get_rpm().track_bitcells(self.get_data_rate()),
         }
     }
 
@@ -163,6 +178,33 @@ impl StandardFormat {
         }
     }
 
+    /// Return the standard format of a raw sector image of `size` bytes, and the number of
+    /// cylinders in the image. Besides the exact sizes of the standard formats, images with up to
+    /// [`MAX_OVERDUMP_CYLINDERS`] extra cylinders are recognized, such as an 84-cylinder dump of a
+    /// 1.44MB disk. If several formats fit, the one with the fewest extra cylinders is returned, so
+    /// a 360K image is not taken for an overdumped 320K image.
+    pub fn from_raw_size(size: usize) -> Option<(StandardFormat, u16)> {
+        [
+            StandardFormat::PcFloppy160,
+            StandardFormat::PcFloppy180,
+            StandardFormat::PcFloppy320,
+            StandardFormat::PcFloppy360,
+            StandardFormat::PcFloppy720,
+            StandardFormat::PcFloppy1200,
+            StandardFormat::PcFloppy1440,
+            StandardFormat::PcFloppy2880,
+        ]
+        .into_iter()
+        .filter_map(|format| {
+            let chs = format.get_chs();
+            let cylinder_size = chs.h() as usize * chs.s() as usize * DEFAULT_SECTOR_SIZE;
+            let c = u16::try_from(size / cylinder_size).ok()?;
+            (size.is_multiple_of(cylinder_size) && (chs.c()..=chs.c() + MAX_OVERDUMP_CYLINDERS).contains(&c))
+                .then_some((format, c))
+        })
+        .min_by_key(|(format, c)| c - format.get_chs().c())
+    }
+
     pub fn size(&self) -> usize {
         match self {
             StandardFormat::PcFloppy160 => 163_840,
@@ -0,0 +1,316 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/structure_parser/amiga.rs
+
+    A parser for the Amiga trackdisk sector layout.
+
+    An Amiga track is written in one pass as a sequence of sectors without
+    IBM-style address marks. Each sector begins with two 0x4489 sync words,
+    followed by a header, a label area, header and data checksums, and 512
+    bytes of data. Each field is stored as its odd bits followed by its even
+    bits, so that a whole field can be decoded with a single blitter pass.
+*/
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType};
+use crate::bitstream::TrackDataStream;
+use crate::chs::DiskChsn;
+use crate::structure_parsers::system34::System34Element;
+use crate::structure_parsers::{
+    DiskStructureElement, DiskStructureGenericElement, DiskStructureMetadata, DiskStructureMetadataItem,
+};
+use bit_vec::BitVec;
+use std::fmt::{Display, Formatter};
+
+/// The two 0x4489 sync words that begin every sector. The preceding 0x00 bytes are not matched,
+/// as their first clock bit depends on the last bit of the previous sector.
+pub const AMIGA_SYNC: u64 = 0x4489_4489;
+pub const AMIGA_SYNC_MASK: u64 = 0xFFFF_FFFF;
+/// The length of the sync field, including the two 0x00 bytes before the sync words, in bitcells.
+pub const AMIGA_SYNC_LEN: usize = 64;
+/// The length of a sector in bitcells, from the start of its sync field to the end of its data.
+pub const AMIGA_SECTOR_LEN: usize = AMIGA_SYNC_LEN + (HEADER_LONGS + DATA_LONGS) * 64;
+pub const AMIGA_SECTOR_SIZE: usize = 512;
+/// The format byte that begins every sector header, for the standard AmigaDOS format.
+pub const AMIGA_FORMAT_BYTE: u8 = 0xFF;
+
+/// Only the data bits of an MFM encoded long are included in a checksum.
+const DATA_BITS: u32 = 0x5555_5555;
+/// The number of decoded longs in a sector header: the info long, four label longs and the
+/// header and data checksums.
+const HEADER_LONGS: usize = 7;
+const LABEL_LONGS: usize = 4;
+const DATA_LONGS: usize = AMIGA_SECTOR_SIZE / 4;
+
+#[derive(Copy, Clone, Debug)]
+pub enum AmigaElement {
+    Marker,
+    SectorHeader(DiskChsn, bool),
+    Data { address_crc: bool, data_crc: bool },
+}
+
+impl Display for AmigaElement {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            AmigaElement::Marker => write!(f, "Amiga Sync Marker"),
+            AmigaElement::SectorHeader(chsn, checksum) => {
+                write!(f, "Amiga Sector Header {}", chsn)?;
+                if !checksum {
+                    write!(f, " (bad checksum)")?;
+                }
+                Ok(())
+            }
+            AmigaElement::Data { address_crc, data_crc } => {
+                write!(f, "Amiga Data")?;
+                if !address_crc {
+                    write!(f, " (bad header checksum)")?;
+                }
+                if !data_crc {
+                    write!(f, " (bad data checksum)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<AmigaElement> for DiskStructureGenericElement {
+    fn from(elem: AmigaElement) -> Self {
+        match elem {
+            AmigaElement::Marker => DiskStructureGenericElement::Marker,
+            AmigaElement::SectorHeader(_, true) => DiskStructureGenericElement::SectorHeader,
+            AmigaElement::SectorHeader(_, false) => DiskStructureGenericElement::SectorBadHeader,
+            AmigaElement::Data {
+                address_crc: true,
+                data_crc: true,
+            } => DiskStructureGenericElement::SectorData,
+            AmigaElement::Data { .. } => DiskStructureGenericElement::SectorBadData,
+        }
+    }
+}
+
+impl AmigaElement {
+    pub fn is_sector(&self) -> bool {
+        matches!(self, AmigaElement::Data { .. })
+    }
+}
+
+pub struct AmigaParser;
+
+impl AmigaParser {
+    /// Scan an MFM track for Amiga sectors, returning a sync marker, sector header and data
+    /// element for each. The sector ID of each sector is taken from its header, with the track
+    /// number split into cylinder and head. Amiga sectors are numbered from 0, and are always 512
+    /// bytes (n = 2). A sector cut off by the end of the track is ignored.
+    pub fn scan_track_metadata(track: &TrackDataStream) -> Vec<DiskStructureMetadataItem> {
+        let codec = match track {
+            TrackDataStream::Mfm(codec) => codec,
+            _ => return Vec::new(),
+        };
+        let bits = codec.bits();
+
+        let mut items = Vec::new();
+        let mut bit_idx = 0;
+        while let Some((marker_idx, _)) = codec.find_next_marker(AMIGA_SYNC, AMIGA_SYNC_MASK, bit_idx) {
+            let header_idx = marker_idx + AMIGA_SYNC_LEN;
+            if marker_idx + AMIGA_SECTOR_LEN > bits.len() {
+                log::warn!(
+                    "scan_track_metadata(): Amiga sector at bit {} is cut off by the end of the track.",
+                    marker_idx
+                );
+                break;
+            }
+
+            items.push(DiskStructureMetadataItem {
+                elem_type: DiskStructureElement::Amiga(AmigaElement::Marker),
+                start: marker_idx,
+                end: header_idx,
+                chsn: None,
+                _crc: None,
+            });
+
+            // The info and label fields are covered by the header checksum.
+            let info = AmigaParser::decode_long(bits, header_idx, 1);
+            let header_sum = (0..2 + LABEL_LONGS * 2).fold(0, |sum, i| sum ^ raw_long(bits, header_idx + i * 32));
+            let header_ok = header_sum == AmigaParser::decode_long(bits, header_idx + 5 * 64, 1);
+            let [format, track_no, sector, _sectors_to_gap] = info.to_be_bytes();
+            if format != AMIGA_FORMAT_BYTE {
+                log::warn!(
+                    "scan_track_metadata(): Amiga sector at bit {} has unknown format byte {:02X}",
+                    marker_idx,
+                    format
+                );
+            }
+            let chsn = DiskChsn::new((track_no / 2) as u16, track_no % 2, sector, 2);
+
+            let data_idx = header_idx + HEADER_LONGS * 64;
+            let data_sum = (0..DATA_LONGS * 2).fold(0, |sum, i| sum ^ raw_long(bits, data_idx + i * 32));
+            let data_ok = data_sum == AmigaParser::decode_long(bits, header_idx + 6 * 64, 1);
+
+            items.push(DiskStructureMetadataItem {
+                elem_type: DiskStructureElement::Amiga(AmigaElement::SectorHeader(chsn, header_ok)),
+                start: header_idx,
+                end: data_idx,
+                chsn: Some(chsn),
+                _crc: None,
+            });
+            items.push(DiskStructureMetadataItem {
+                elem_type: DiskStructureElement::Amiga(AmigaElement::Data {
+                    address_crc: header_ok,
+                    data_crc: data_ok,
+                }),
+                start: data_idx,
+                end: data_idx + DATA_LONGS * 64,
+                chsn: Some(chsn),
+                _crc: None,
+            });
+            bit_idx = data_idx + DATA_LONGS * 64;
+        }
+        items
+    }
+
+    /// Replace `metadata` with the Amiga sector layout of `track` if `metadata` holds no IBM sector
+    /// IDs and Amiga sectors are found, rebuilding the clock map of the track from the Amiga
+    /// syncs. IBM sync fields contain the same sync words, so IBM sectors take precedence.
+    pub(crate) fn rescan_if_amiga(track: &mut TrackDataStream, metadata: &mut DiskStructureMetadata) {
+        let has_ibm_ids = metadata.items.iter().any(|i| {
+            matches!(
+                i.elem_type,
+                DiskStructureElement::System34(System34Element::SectorHeader(..))
+            )
+        });
+        if has_ibm_ids {
+            return;
+        }
+        let items = AmigaParser::scan_track_metadata(track);
+        if items.is_empty() {
+            return;
+        }
+        if let Some(clock_map) = track.clock_map_mut() {
+            AmigaParser::create_clock_map(&items, clock_map);
+        }
+        *metadata = DiskStructureMetadata::new(items);
+    }
+
+    /// Set the clock phase of `clock_map` from the sync marker of each Amiga sector found by
+    /// [`AmigaParser::scan_track_metadata`].
+    pub fn create_clock_map(items: &[DiskStructureMetadataItem], clock_map: &mut BitVec) {
+        let mut starts = items
+            .iter()
+            .filter(|i| matches!(i.elem_type, DiskStructureElement::Amiga(AmigaElement::Marker)))
+            .map(|i| i.start)
+            .collect::<Vec<_>>();
+        starts.push(clock_map.len());
+
+        for pair in starts.windows(2) {
+            for bi in (pair[0]..pair[1].saturating_sub(1)).step_by(2) {
+                clock_map.set(bi, true);
+                clock_map.set(bi + 1, false);
+            }
+        }
+    }
+
+    /// Decode the 512 bytes of sector data starting at the bitcell index `data_idx`, as returned
+    /// in the start of an [`AmigaElement::Data`] element.
+    pub fn decode_sector_data(bits: &BitVec, data_idx: usize) -> Vec<u8> {
+        (0..DATA_LONGS)
+            .flat_map(|i| AmigaParser::decode_long(bits, data_idx + i * 32, DATA_LONGS).to_be_bytes())
+            .collect()
+    }
+
+    /// Decode the long whose odd bits are stored at the bitcell index `idx`, and whose even bits
+    /// are stored `stride` longs later.
+    fn decode_long(bits: &BitVec, idx: usize, stride: usize) -> u32 {
+        let odd = raw_long(bits, idx);
+        let even = raw_long(bits, idx + stride * 32);
+        (odd << 1) | even
+    }
+
+    /// Encode a track of Amiga sectors from `data`, which must be a whole number of 512-byte
+    /// sectors. The sectors are written in order from the index, and the track is filled out to
+    /// `bitcell_ct` bitcells with a gap of 0x00 bytes.
+    pub fn encode_track(c: u16, h: u8, data: &[u8], bitcell_ct: usize) -> BitVec {
+        let sector_ct = data.len() / AMIGA_SECTOR_SIZE;
+        let track_no = (c * 2 + h as u16) as u8;
+        let mut bits = BitVec::with_capacity(bitcell_ct);
+
+        for (s, sector) in data.chunks_exact(AMIGA_SECTOR_SIZE).enumerate() {
+            push_encoded(&mut bits, &[0x00, 0x00], MfmEncodingType::Data);
+            push_encoded(&mut bits, &[0xA1, 0xA1], MfmEncodingType::AddressMark);
+
+            let info = u32::from_be_bytes([AMIGA_FORMAT_BYTE, track_no, s as u8, (sector_ct - s) as u8]);
+            let label = [0u32; LABEL_LONGS];
+            let data_longs = sector
+                .chunks_exact(4)
+                .map(|l| u32::from_be_bytes(l.try_into().unwrap()))
+                .collect::<Vec<_>>();
+
+            let mut header_raw = split_longs(&[info]);
+            header_raw.extend(split_longs(&label));
+            let data_raw = split_longs(&data_longs);
+            let header_sum = header_raw.iter().fold(0, |sum, l| sum ^ l);
+            let data_sum = data_raw.iter().fold(0, |sum, l| sum ^ l);
+
+            for raw in header_raw
+                .iter()
+                .chain(split_longs(&[header_sum]).iter())
+                .chain(split_longs(&[data_sum]).iter())
+                .chain(data_raw.iter())
+            {
+                push_encoded(&mut bits, &squeeze(*raw).to_be_bytes(), MfmEncodingType::Data);
+            }
+        }
+
+        while bits.len() < bitcell_ct {
+            push_encoded(&mut bits, &[0x00], MfmEncodingType::Data);
+        }
+        bits.truncate(bitcell_ct);
+        bits
+    }
+}
+
+/// Read the 32 bitcells starting at `idx`, keeping only the data bits.
+fn raw_long(bits: &BitVec, idx: usize) -> u32 {
+    (idx..idx + 32).fold(0, |acc, bi| (acc << 1) | bits[bi] as u32) & DATA_BITS
+}
+
+/// Split `longs` into a block of their odd bits followed by a block of their even bits, each
+/// shifted into the data bit positions of an MFM encoded long.
+fn split_longs(longs: &[u32]) -> Vec<u32> {
+    let odd = longs.iter().map(|l| (l >> 1) & DATA_BITS);
+    let even = longs.iter().map(|l| l & DATA_BITS);
+    odd.chain(even).collect()
+}
+
+/// Pack the data bit positions of `raw` into a 16-bit value, to be MFM encoded.
+fn squeeze(raw: u32) -> u16 {
+    (0..16).fold(0, |acc, i| (acc << 1) | ((raw >> (30 - i * 2)) & 1) as u16)
+}
+
+/// MFM encode `data` onto the end of `bits`, continuing from its last data bit.
+fn push_encoded(bits: &mut BitVec, data: &[u8], encoding_type: MfmEncodingType) {
+    let prev_bit = !bits.is_empty() && bits[bits.len() - 1];
+    bits.extend(MfmCodec::encode_mfm(data, prev_bit, encoding_type).iter());
+}
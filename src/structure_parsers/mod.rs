@@ -33,18 +33,25 @@
     responsible for encoding data to be written back into a compatible layout.
 
     A DiskStructureParser trait is defined here that can be implemented by
-    different parser types. For the time being, only the IBM System 32 (standard
-    PC floppy) type will be implemented.
+    different parser types. The IBM System 34 (standard PC floppy) type is the
+    primary implementation; the Amiga trackdisk layout is also understood.
 */
 
+pub mod amiga;
 pub mod system34;
 
+use crate::bitstream::fm::FmCodec;
+use crate::bitstream::gcr::{GcrCodec, GCR_ADDRESS_PROLOGUE, GCR_NIBBLE_LEN};
+use crate::bitstream::mfm::MfmCodec;
 use crate::bitstream::TrackDataStream;
 use crate::chs::DiskChsn;
-use crate::structure_parsers::system34::{System34Element, System34Marker};
+use crate::structure_parsers::amiga::AmigaElement;
+use crate::structure_parsers::system34::{System34Element, System34Marker, System34Parser};
+use crate::DiskDataEncoding;
 use bit_vec::BitVec;
+use std::fmt::{Display, Formatter};
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct DiskStructureMetadata {
     pub items: Vec<DiskStructureMetadataItem>,
 }
@@ -99,8 +106,12 @@ impl DiskStructureMetadata {
         }
 
         for item in &self.items {
-            if let DiskStructureElement::System34(System34Element::SectorHeader(chsn, true)) = item.elem_type {
-                sector_ids.push(chsn);
+            match item.elem_type {
+                DiskStructureElement::System34(System34Element::SectorHeader(chsn, true))
+                | DiskStructureElement::Amiga(AmigaElement::SectorHeader(chsn, true)) => {
+                    sector_ids.push(chsn);
+                }
+                _ => {}
             }
         }
 
@@ -126,6 +137,16 @@ pub struct DiskStructureMetadataItem {
     pub(crate) _crc: Option<DiskStructureCrc>,
 }
 
+impl Display for DiskStructureMetadataItem {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{} start: {} end: {}", self.elem_type, self.start, self.end)?;
+        if let Some(chsn) = self.chsn {
+            write!(f, " id: {}", chsn)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct DiskStructureCrc {
     stored: u16,
@@ -159,13 +180,25 @@ pub enum DiskStructureGenericElement {
 #[derive(Copy, Clone, Debug)]
 pub enum DiskStructureElement {
     System34(System34Element),
+    Amiga(AmigaElement),
     Placeholder,
 }
 
+impl Display for DiskStructureElement {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            DiskStructureElement::System34(elem) => write!(f, "{}", elem),
+            DiskStructureElement::Amiga(elem) => write!(f, "{}", elem),
+            DiskStructureElement::Placeholder => write!(f, "Placeholder"),
+        }
+    }
+}
+
 impl From<DiskStructureElement> for DiskStructureGenericElement {
     fn from(elem: DiskStructureElement) -> Self {
         match elem {
             DiskStructureElement::System34(sys34elem) => sys34elem.into(),
+            DiskStructureElement::Amiga(amiga_elem) => amiga_elem.into(),
             _ => DiskStructureGenericElement::NoElement,
         }
     }
@@ -175,6 +208,7 @@ impl DiskStructureElement {
     pub fn is_sector(&self) -> bool {
         match self {
             DiskStructureElement::System34(elem) => elem.is_sector(),
+            DiskStructureElement::Amiga(elem) => elem.is_sector(),
             _ => false,
         }
     }
@@ -206,3 +240,49 @@ pub trait DiskStructureParser {
 
     fn crc16(track: &mut TrackDataStream, start: usize, end: usize) -> u16;
 }
+
+/// Try each codec that fluxfox can parse on a raw track bitstream, and score how well the track
+/// decodes with each as the number of ID address marks found: System34 IDAMs for MFM and FM, and
+/// address field prologues for Apple GCR.
+pub fn encoding_scores(bits: &BitVec) -> [(DiskDataEncoding, usize); 3] {
+    let count_idams = |mut stream: TrackDataStream| {
+        System34Parser::scan_track_markers(&mut stream)
+            .iter()
+            .filter(|item| matches!(item.elem_type, DiskStructureMarker::System34(System34Marker::Idam)))
+            .count()
+    };
+    let mfm = count_idams(TrackDataStream::Mfm(MfmCodec::new(bits.clone(), None, None)));
+    let fm = count_idams(TrackDataStream::Fm(FmCodec::new(bits.clone(), None, None)));
+
+    let gcr_codec = GcrCodec::new(bits.clone(), None, None);
+    let mut gcr = 0;
+    let mut cursor = 0;
+    while let Some(index) = gcr_codec.find_nibbles(&GCR_ADDRESS_PROLOGUE, cursor) {
+        gcr += 1;
+        cursor = index + GCR_ADDRESS_PROLOGUE.len() * GCR_NIBBLE_LEN;
+    }
+
+    [
+        (DiskDataEncoding::Mfm, mfm),
+        (DiskDataEncoding::Fm, fm),
+        (DiskDataEncoding::Gcr, gcr),
+    ]
+}
+
+/// Detect the encoding of a raw track bitstream, for file formats that do not record the encoding
+/// of each track. Each track is detected on its own, so hybrid disks that carry both MFM and GCR
+/// tracks load with the correct codec for every track.
+///
+/// # Returns
+/// - `Some(DiskDataEncoding)` for the best scoring encoding of [`encoding_scores`], preferring
+///   MFM, then FM, on a tie.
+/// - `None` if no ID address marks were found in any encoding.
+pub fn detect_encoding(bits: &BitVec) -> Option<DiskDataEncoding> {
+    // max_by_key() returns the last of equal scores, so search in reverse to prefer MFM.
+    encoding_scores(bits)
+        .into_iter()
+        .rev()
+        .filter(|(_, score)| *score > 0)
+        .max_by_key(|(_, score)| *score)
+        .map(|(encoding, _)| encoding)
+}
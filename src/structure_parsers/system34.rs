@@ -30,15 +30,16 @@
     This was the standard disk format used on IBM PCs and compatibles.
 
 */
-use crate::bitstream::mfm::{MfmCodec, MFM_BYTE_LEN, MFM_MARKER_LEN};
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType, MFM_BYTE_LEN, MFM_MARKER_LEN};
 use crate::bitstream::TrackDataStream;
 use crate::chs::DiskChsn;
+use crate::diskimage::ParseMode;
 use crate::io::{Read, Seek, SeekFrom};
 use crate::structure_parsers::{
     DiskStructureElement, DiskStructureGenericElement, DiskStructureMarker, DiskStructureMarkerItem,
     DiskStructureMetadataItem, DiskStructureParser,
 };
-use crate::util::crc_ccitt;
+use crate::util::crc16;
 use crate::{mfm_offset, DiskImageError};
 use bit_vec::BitVec;
 use std::fmt::{Display, Formatter};
@@ -62,10 +63,19 @@ pub const PERPENDICULAR_GAP2: usize = 41;
 pub const IAM_MARKER: u64 = 0x5224522452245552;
 pub const IDAM_MARKER: u64 = 0x4489448944895554;
 pub const DAM_MARKER: u64 = 0x4489448944895545;
-pub const DDAM_MARKER: u64 = 0x4489448944895548;
+pub const DDAM_MARKER: u64 = 0x448944894489554A;
 pub const ANY_MARKER: u64 = 0x4489448944890000;
 pub const MARKER_MASK: u64 = 0xFFFFFFFFFFFF0000;
 
+// Pre-encoded FM markers. FM address marks are preceded by 0x00 sync bytes, and distinguished
+// from data by missing clock bits in the mark byte itself.
+pub const FM_IAM_MARKER: u64 = 0xAAAAAAAAAAAAF77A;
+pub const FM_IDAM_MARKER: u64 = 0xAAAAAAAAAAAAF57E;
+pub const FM_DAM_MARKER: u64 = 0xAAAAAAAAAAAAF56F;
+pub const FM_DDAM_MARKER: u64 = 0xAAAAAAAAAAAAF56A;
+pub const FM_ANY_MARKER: u64 = 0xAAAAAAAAAAAAF500;
+pub const FM_MARKER_MASK: u64 = 0xFFFFFFFFFFFFFF00;
+
 pub const IAM_MARKER_BYTES: [u8; 4] = [0xC2, 0xC2, 0xC2, 0xFC];
 pub const IDAM_MARKER_BYTES: [u8; 4] = [0xA1, 0xA1, 0xA1, 0xFE];
 pub const DAM_MARKER_BYTES: [u8; 4] = [0xA1, 0xA1, 0xA1, 0xFB];
@@ -90,6 +100,51 @@ impl From<&[u8]> for System34FormatBuffer {
     }
 }
 
+/// The bytes of an address mark and the field following it that are covered by a CRC.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum System34CrcSpan {
+    /// The three sync marks, the address mark byte and the field, as checked by the NEC uPD765
+    /// and compatible controllers.
+    #[default]
+    Full,
+    /// The address mark byte and the field, excluding the sync marks.
+    FromMark,
+    /// The field only, excluding the whole address mark.
+    FieldOnly,
+}
+
+/// The parameters of the CRC protecting System34 ID and data fields. Controllers other than the
+/// NEC uPD765 family may use a different initial value or cover a different span of bytes;
+/// configuring these allows their disks to be read without spurious CRC errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct System34CrcParams {
+    pub poly: u16,
+    pub init: u16,
+    pub span: System34CrcSpan,
+}
+
+impl Default for System34CrcParams {
+    fn default() -> Self {
+        System34CrcParams {
+            poly: 0x1021,
+            init: 0xFFFF,
+            span: System34CrcSpan::Full,
+        }
+    }
+}
+
+impl System34CrcParams {
+    /// Calculate the CRC of a field preceded by the 4-byte address mark `mark`.
+    pub fn crc(&self, mark: &[u8], field: &[u8]) -> u16 {
+        let mark = match self.span {
+            System34CrcSpan::Full => mark,
+            System34CrcSpan::FromMark => &mark[mark.len().saturating_sub(1)..],
+            System34CrcSpan::FieldOnly => &[],
+        };
+        crc16(field, self.poly, crc16(mark, self.poly, self.init))
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum System34Standard {
     Ibm,
@@ -98,6 +153,29 @@ pub enum System34Standard {
 }
 
 impl System34Standard {
+    pub fn gap1(&self) -> usize {
+        match self {
+            System34Standard::Ibm => IBM_GAP1,
+            System34Standard::Perpendicular => PERPENDICULAR_GAP1,
+            System34Standard::Iso => ISO_GAP1,
+        }
+    }
+
+    /// Return the length in bytes of the track preamble written before the first sector.
+    /// The IBM and Perpendicular standards write GAP4A, sync and an IAM before GAP1.
+    pub fn preamble_len(&self) -> usize {
+        match self {
+            System34Standard::Ibm | System34Standard::Perpendicular => IBM_GAP4A + SYNC_LEN + 4 + self.gap1(),
+            System34Standard::Iso => self.gap1(),
+        }
+    }
+
+    /// Return the length in bytes of a formatted sector of size `chsn`, excluding GAP3: sync,
+    /// IDAM, CHSN and CRC, GAP2, sync, DAM, data and CRC.
+    pub fn sector_len(&self, chsn: &DiskChsn) -> usize {
+        SYNC_LEN + 4 + 4 + 2 + self.gap2() + SYNC_LEN + 4 + chsn.n_size() + 2
+    }
+
     pub fn gap2(&self) -> usize {
         match self {
             System34Standard::Ibm => IBM_GAP2,
@@ -107,7 +185,7 @@ impl System34Standard {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum System34Marker {
     Iam,
     Idam,
@@ -115,6 +193,17 @@ pub enum System34Marker {
     Ddam,
 }
 
+impl Display for System34Marker {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            System34Marker::Iam => write!(f, "IAM"),
+            System34Marker::Idam => write!(f, "IDAM"),
+            System34Marker::Dam => write!(f, "DAM"),
+            System34Marker::Ddam => write!(f, "DDAM"),
+        }
+    }
+}
+
 impl From<System34Marker> for u64 {
     fn from(marker: System34Marker) -> u64 {
         match marker {
@@ -126,6 +215,29 @@ impl From<System34Marker> for u64 {
     }
 }
 
+impl System34Marker {
+    /// Return the encoded FM marker, including the leading sync bytes, in a u64 suitable for
+    /// comparison to a shift register used to search a BitVec.
+    pub fn fm_marker(&self) -> u64 {
+        match self {
+            System34Marker::Iam => FM_IAM_MARKER,
+            System34Marker::Idam => FM_IDAM_MARKER,
+            System34Marker::Dam => FM_DAM_MARKER,
+            System34Marker::Ddam => FM_DDAM_MARKER,
+        }
+    }
+
+    /// Return the decoded byte sequence of the marker, including the leading sync bytes.
+    pub fn bytes(&self) -> [u8; 4] {
+        match self {
+            System34Marker::Iam => IAM_MARKER_BYTES,
+            System34Marker::Idam => IDAM_MARKER_BYTES,
+            System34Marker::Dam => DAM_MARKER_BYTES,
+            System34Marker::Ddam => DDAM_MARKER_BYTES,
+        }
+    }
+}
+
 impl TryInto<System34Marker> for u16 {
     type Error = ();
 
@@ -134,6 +246,9 @@ impl TryInto<System34Marker> for u16 {
             0x5554 => Ok(System34Marker::Idam),
             0x5545 => Ok(System34Marker::Dam),
             0x554A => Ok(System34Marker::Ddam),
+            0xF57E => Ok(System34Marker::Idam),
+            0xF56F => Ok(System34Marker::Dam),
+            0xF56A => Ok(System34Marker::Ddam),
             _ => {
                 log::error!("Invalid System34 marker: {:04X}", self);
                 Err(())
@@ -159,6 +274,44 @@ pub enum System34Element {
     },
 }
 
+impl Display for System34Element {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            System34Element::Gap1 => write!(f, "GAP1"),
+            System34Element::Gap2 => write!(f, "GAP2"),
+            System34Element::Gap3 => write!(f, "GAP3"),
+            System34Element::Gap4a => write!(f, "GAP4a"),
+            System34Element::Gap4b => write!(f, "GAP4b"),
+            System34Element::Sync => write!(f, "SYNC"),
+            System34Element::Marker(marker, _) => write!(f, "{} Marker", marker),
+            System34Element::SectorHeader(chsn, crc) => {
+                write!(f, "Sector Header {}", chsn)?;
+                if !crc {
+                    write!(f, " (bad CRC)")?;
+                }
+                Ok(())
+            }
+            System34Element::Data {
+                address_crc,
+                data_crc,
+                deleted,
+            } => {
+                match deleted {
+                    true => write!(f, "Deleted Data")?,
+                    false => write!(f, "Data")?,
+                }
+                if !address_crc {
+                    write!(f, " (bad address CRC)")?;
+                }
+                if !data_crc {
+                    write!(f, " (bad data CRC)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 impl From<System34Element> for DiskStructureGenericElement {
     fn from(elem: System34Element) -> Self {
         match elem {
@@ -244,6 +397,11 @@ impl Display for SectorId {
 pub struct System34FormatResult {
     pub track_bytes: Vec<u8>,
     pub markers: Vec<(System34Marker, usize)>,
+    /// The GAP3 length used, which may be smaller than requested if the sectors would not
+    /// otherwise fit on the track.
+    pub gap3: usize,
+    /// The length of GAP4B in bytes, including any partial byte at the end of the track.
+    pub gap4b: usize,
 }
 
 pub struct System34Parser;
@@ -259,19 +417,48 @@ impl System34Parser {
         marker & Self::MFM_MARKER_CLOCK_MASK | Self::MFM_MARKER_CLOCK
     }
 
+    /// Lay out a track of `bitcell_ct` bitcells with the sectors in `format_buffer`, returning the
+    /// decoded track bytes and the offsets of the address marks to be written.
+    ///
+    /// GAP4B is sized to exactly fill the rest of the track, so the track may end on a partial
+    /// byte when `bitcell_ct` is not a multiple of 16. If the sectors will not fit with the
+    /// requested `gap3`, GAP3 is reduced to the largest length that fits.
+    /// ID and data field CRCs are calculated with `crc`.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if the sectors will not fit on the track.
     pub fn format_track_as_bytes(
         standard: System34Standard,
         bitcell_ct: usize,
         format_buffer: Vec<DiskChsn>,
         fill_byte: u8,
         gap3: usize,
+        crc: &System34CrcParams,
     ) -> Result<System34FormatResult, DiskImageError> {
-        let track_byte_ct = (bitcell_ct + MFM_BYTE_LEN - 1) / MFM_BYTE_LEN;
-        log::error!(
+        let track_byte_ct = bitcell_ct.div_ceil(MFM_BYTE_LEN);
+        log::trace!(
             "format_track_as_bytes(): Formatting track with {} bitcells, {} bytes",
             bitcell_ct,
             track_byte_ct
         );
+
+        let fit_gap3 = System34Parser::fit_gap3(standard, bitcell_ct, &format_buffer, gap3).ok_or_else(|| {
+            log::error!(
+                "format_track_as_bytes(): {} sectors will not fit on a track of {} bitcells.",
+                format_buffer.len(),
+                bitcell_ct
+            );
+            DiskImageError::ParameterError
+        })?;
+        if fit_gap3 < gap3 {
+            log::warn!(
+                "format_track_as_bytes(): GAP3 of {} would pass the index. Reducing GAP3 to {}.",
+                gap3,
+                fit_gap3
+            );
+        }
+        let gap3 = fit_gap3;
+
         let mut track_bytes: Vec<u8> = Vec::with_capacity(track_byte_ct);
         let mut markers = Vec::new();
 
@@ -280,6 +467,8 @@ impl System34Parser {
             track_bytes.extend_from_slice(&[GAP_BYTE; IBM_GAP4A]); // GAP0
             track_bytes.extend_from_slice(&[SYNC_BYTE; SYNC_LEN]); // Sync
             markers.push((System34Marker::Iam, track_bytes.len()));
+            track_bytes.extend_from_slice(&IAM_MARKER_BYTES);
+            track_bytes.extend_from_slice(&vec![GAP_BYTE; standard.gap1()]);
         } else {
             // Just write Gap1 for ISO standard, there is no IAM marker.
             track_bytes.extend_from_slice(&[GAP_BYTE; ISO_GAP1]);
@@ -298,9 +487,11 @@ impl System34Parser {
             track_bytes.push(sector.n());
 
             // Write CRC word.
-            //log::error!("Calculating crc over : {:X?}", &track_bytes[idam_crc_offset..]);
-            let crc16 = crc_ccitt(&track_bytes[idam_crc_offset..], None);
-            track_bytes.extend_from_slice(&crc16.to_be_bytes());
+            let id_crc = crc.crc(
+                &track_bytes[idam_crc_offset..idam_crc_offset + 4],
+                &track_bytes[idam_crc_offset + 4..],
+            );
+            track_bytes.extend_from_slice(&id_crc.to_be_bytes());
 
             // Write GAP2.
             track_bytes.extend_from_slice(&vec![GAP_BYTE; standard.gap2()]);
@@ -317,227 +508,99 @@ impl System34Parser {
             track_bytes.extend_from_slice(&vec![fill_byte; sector.n_size()]);
 
             // Write CRC word.
-            let crc16 = crc_ccitt(&track_bytes[dam_crc_offset..], None);
-            track_bytes.extend_from_slice(&crc16.to_be_bytes());
+            let data_crc = crc.crc(
+                &track_bytes[dam_crc_offset..dam_crc_offset + 4],
+                &track_bytes[dam_crc_offset + 4..],
+            );
+            track_bytes.extend_from_slice(&data_crc.to_be_bytes());
 
             // Write GAP3.
             track_bytes.extend_from_slice(&vec![GAP_BYTE; gap3]);
         }
 
-        // Fill rest of track with GAP4B.
-        if track_bytes.len() < track_byte_ct {
-            track_bytes.extend_from_slice(&vec![GAP_BYTE; track_byte_ct - track_bytes.len()]);
-        }
-
-        if track_bytes.len() > track_byte_ct {
-            log::warn!(
-                "format_track_as_bytes(): Format operation passed index. Truncating track to {} bytes",
-                track_byte_ct
-            );
-            track_bytes.truncate(track_byte_ct);
-        }
+        // Fill the rest of the track with GAP4B. fit_gap3() guarantees the sectors fit in the
+        // whole bytes of the track, so GAP4B is never negative.
+        let gap4b = track_byte_ct - track_bytes.len();
+        track_bytes.extend_from_slice(&vec![GAP_BYTE; gap4b]);
 
         log::trace!(
-            "format_track_as_bytes(): Wrote {} markers to track of size {} bitcells: {}",
+            "format_track_as_bytes(): Wrote {} markers to track of size {} bytes, GAP3: {} GAP4B: {}",
             markers.len(),
             track_bytes.len(),
-            track_bytes.len() * 8
+            gap3,
+            gap4b
         );
 
-        Ok(System34FormatResult { track_bytes, markers })
+        Ok(System34FormatResult {
+            track_bytes,
+            markers,
+            gap3,
+            gap4b,
+        })
     }
 
-    pub(crate) fn set_track_markers(
-        mfm_codec: &mut MfmCodec,
-        markers: Vec<(System34Marker, usize)>,
-    ) -> Result<(), DiskImageError> {
-        for (marker, offset) in markers {
-            let marker_u64 = u64::from(marker);
-
-            let marker_bit_index = offset * MFM_BYTE_LEN;
-
-            let marker_bytes = marker_u64.to_be_bytes();
-
-            log::error!("Setting marker {:X?} at bit index: {}", marker_bytes, marker_bit_index);
-            mfm_codec
-                .write_raw_buf(&marker_bytes, marker_bit_index)
-                .map_err(|_| DiskImageError::IoError)?;
-        }
-
-        Ok(())
-    }
-}
-
-impl DiskStructureParser for System34Parser {
-    /// Find the provided pattern of bytes within the specified bitstream, starting at `offset` bits
-    /// into the track.
-    /// The bit offset of the pattern is returned if found, otherwise None.
-    /// The pattern length is limited to 8 characters.
-    fn find_data_pattern(track: &TrackDataStream, pattern: &[u8], offset: usize) -> Option<usize> {
-        let mut buffer = [0u8; 8];
-        let len = pattern.len().min(8);
-        buffer[(8 - len)..8].copy_from_slice(&pattern[..len]);
-        let pat = u64::from_be_bytes(buffer);
-
-        let pat_mask = u64::MAX >> (8 * (8 - len));
-        let mut shift_reg = 0;
-
-        //log::trace!("Constructed pattern: {:016X?}, mask: {:016X}", pat, pat_mask);
-
-        let mut bit_ct = 0;
-        for bi in offset..track.len() {
-            shift_reg = shift_reg << 1 | track[bi] as u64;
-            if (bit_ct >= (len * 8)) && (shift_reg & pat_mask) == pat {
-                //log::trace!("find_data_pattern: shift_reg: {:064b} pat: {:064b}", shift_reg, pat);
-                //log::trace!("find_data_pattern: Found pattern at bit offset: {}", bi);
-                return Some(bi - len * 8 + 1);
-            }
-            bit_ct += 1;
-        }
-        None
-    }
-
-    /// Find the next address marker in the track bitstream. The type of marker and its position in
-    /// the bitstream is returned, or None.
-    fn find_next_marker(track: &TrackDataStream, offset: usize) -> Option<(DiskStructureMarker, usize)> {
-        if let TrackDataStream::Mfm(mfm_stream) = track {
-            if let Some((index, marker_u16)) = mfm_stream.find_next_marker(ANY_MARKER, MARKER_MASK, offset) {
-                if let Ok(marker) = marker_u16.try_into() {
-                    return Some((DiskStructureMarker::System34(marker), index));
-                }
-            }
-        }
-        None
-    }
-
-    fn find_marker(
-        track: &TrackDataStream,
-        marker: DiskStructureMarker,
-        offset: usize,
-        limit: Option<usize>,
+    /// Return the largest GAP3 length, no greater than `max_gap3`, that allows the sectors in
+    /// `format_buffer` to fit on a track of `bitcell_ct` bitcells when formatted by
+    /// [`System34Parser::format_track_as_bytes`]. Returns `None` if the sectors will not fit.
+    pub fn fit_gap3(
+        standard: System34Standard,
+        bitcell_ct: usize,
+        format_buffer: &[DiskChsn],
+        max_gap3: usize,
     ) -> Option<usize> {
-        if let DiskStructureMarker::System34(marker) = marker {
-            let marker_u64 = u64::from(marker);
+        let track_byte_ct = bitcell_ct / MFM_BYTE_LEN;
+        let sectors_len: usize = format_buffer.iter().map(|chsn| standard.sector_len(chsn)).sum();
 
-            if let TrackDataStream::Mfm(mfm_stream) = track {
-                //log::trace!("find_marker(): Searching for marker at offset: {}", offset);
-                return mfm_stream.find_marker(marker_u64, offset, limit);
-            }
+        let used_len = standard.preamble_len() + sectors_len;
+        if used_len > track_byte_ct {
+            return None;
         }
-        None
-    }
-
-    fn find_element(track: &TrackDataStream, element: DiskStructureElement, offset: usize) -> Option<usize> {
-        if let DiskStructureElement::System34(element) = element {
-            use System34Element::*;
-
-            let (marker, _pattern) = match element {
-                Gap1 | Gap2 | Gap3 | Gap4a | Gap4b => (System34Parser::encode_marker(&[0x4E; 4]), &[0x4E; 4]),
-                Sync => (MfmCodec::encode_marker(&[0x00; 4]), &[0x00; 4]),
-                _ => return None,
-            };
-
-            //let marker = System34Parser::encode_marker(pattern);
-            log::trace!(
-                "find_element(): Encoded element: {:?} as {:016X}/{:064b}",
-                element,
-                marker,
-                marker
-            );
-
-            if let TrackDataStream::Mfm(mfm_stream) = track {
-                log::trace!("find_element(): Searching for element at offset: {}", offset);
-                //let mfm_offset = System34Parser::find_data_pattern(track, pattern, offset >> 1);
-                let raw_offset = mfm_stream.find_marker(marker, offset, None);
-                /*                if let Some(mfm_offset) = mfm_offset {
-                    log::trace!(
-                        "find_element(): Found element in decoded stream: {:?} at offset: {}",
-                        element,
-                        mfm_offset << 1
-                    );
-
-                    log::trace!(
-                        "find_element(): marker_bits (encoded) {}",
-                        mfm_stream.debug_marker(mfm_offset << 1)
-                    );
-                    log::trace!(
-                        "find_element(): marker_bits (decoded) {}",
-                        mfm_stream.debug_decode(mfm_offset)
-                    );
-                }*/
-
-                if let Some(offset) = raw_offset {
-                    log::trace!(
-                        "find_element(): Found element in raw stream: {:?} at offset: {}, sync: {} debug: {}",
-                        element,
-                        offset,
-                        offset & 1,
-                        mfm_stream.debug_marker(offset)
-                    );
-                    return Some(offset);
-                }
-            }
-
-            //log::trace!("Searching for pattern: {:02X?} at offset {}", pattern, offset);
-            //return System34Parser::find_pattern(track, pattern, offset);
-        };
-
-        None
-    }
-
-    /// Scan a track bitstream for address markers, including the IAM, IDAM and DAM markers. Return
-    /// their positions. The marker positions will be used to create the clock phase map for the
-    /// track, which must be performed before we can read the data off the disk which is done in
-    /// a second pass.
-    fn scan_track_markers(track: &mut TrackDataStream) -> Vec<DiskStructureMarkerItem> {
-        let mut bit_cursor: usize = 0;
-        let mut markers = Vec::new();
-
-        // Look for the IAM marker first - but it may not be present (ISO standard encoding does
-        // not require it).
-
-        if let Some(marker_offset) = System34Parser::find_marker(
-            track,
-            DiskStructureMarker::System34(System34Marker::Iam),
-            bit_cursor,
-            Some(5_000),
-        ) {
-            log::trace!(
-                "scan_track_markers(): Found IAM marker at bit offset: {}",
-                marker_offset
-            );
-            markers.push(DiskStructureMarkerItem {
-                elem_type: DiskStructureMarker::System34(System34Marker::Iam),
-                start: marker_offset,
-            });
-            bit_cursor = marker_offset + 4 * MFM_BYTE_LEN;
+        if format_buffer.is_empty() {
+            return Some(max_gap3);
         }
-
-        while let Some((marker, marker_offset)) = System34Parser::find_next_marker(track, bit_cursor) {
-            /*
-            log::trace!(
-                "scan_track_markers(): Found marker of type {:?} at bit offset: {}",
-                marker,
-                marker_offset
-            );*/
-
-            markers.push(DiskStructureMarkerItem {
-                elem_type: marker,
-                start: marker_offset,
-            });
-            bit_cursor = marker_offset + 4 * MFM_BYTE_LEN;
-        }
-        markers
+        Some(std::cmp::min(
+            (track_byte_ct - used_len) / format_buffer.len(),
+            max_gap3,
+        ))
     }
 
     /// Scan a track bitstream using the pre-scanned marker positions to extract marker data such
     /// as Sector ID values and CRCs. This is done in a second pass after the markers have been
     /// found by scan_track_markers() and a clock phase map created for the track - required for the
     /// proper functioning of the Read and Seek traits on MfmCodec.
-    fn scan_track_metadata(
+    ///
+    /// ID and data field CRCs are checked with `crc_params`.
+    pub fn scan_track_metadata_crc(
         track: &mut TrackDataStream,
         markers: Vec<DiskStructureMarkerItem>,
+        crc_params: &System34CrcParams,
     ) -> Vec<DiskStructureMetadataItem> {
+        // A lenient scan never fails.
+        System34Parser::scan_track_metadata_mode(track, markers, crc_params, ParseMode::Lenient).unwrap_or_default()
+    }
+
+    /// Scan a track bitstream as [`System34Parser::scan_track_metadata_crc`] does, checking that
+    /// each ID and data field fits on the track as strictly as `mode` requires.
+    ///
+    /// In [`ParseMode::Lenient`] mode, an ID field cut off by the end of the track is ignored, and
+    /// a data field cut off by the end of the track is treated as having a bad CRC.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SpecViolation)` in [`ParseMode::Strict`] mode if an ID or data field
+    ///   is cut off by the end of the track.
+    pub fn scan_track_metadata_mode(
+        track: &mut TrackDataStream,
+        markers: Vec<DiskStructureMarkerItem>,
+        crc_params: &System34CrcParams,
+        mode: ParseMode,
+    ) -> Result<Vec<DiskStructureMetadataItem>, DiskImageError> {
+        let truncated = |message: String| match mode {
+            ParseMode::Strict => Err(DiskImageError::SpecViolation(message)),
+            ParseMode::Lenient => {
+                log::warn!("scan_track_metadata_mode(): {}", message);
+                Ok(())
+            }
+        };
         let mut elements = Vec::new();
         let mut last_marker_opt: Option<System34Marker> = None;
         let mut last_sector_id = SectorId::default();
@@ -550,6 +613,14 @@ impl DiskStructureParser for System34Parser {
             if let DiskStructureMarker::System34(sys34_marker) = marker.elem_type {
                 match (last_marker_opt, sys34_marker) {
                     (_, System34Marker::Idam) => {
+                        if marker.start + mfm_offset!(10) > track.len() {
+                            truncated(format!(
+                                "Sector ID field at bitcell {} extends past the end of the track",
+                                marker.start
+                            ))?;
+                            last_marker_opt = None;
+                            continue;
+                        }
                         let mut sector_header = [0; 8];
 
                         // TODO: Don't unwrap in a library unless provably safe.
@@ -568,7 +639,10 @@ impl DiskStructureParser for System34Parser {
                         let crc_byte1 = track.read_decoded_byte(marker.start + mfm_offset!(9)).unwrap_or(0xAA);
 
                         let crc = u16::from_be_bytes([crc_byte0, crc_byte1]);
-                        let calculated_crc = crc_ccitt(&sector_header[0..8], None);
+                        let calculated_crc = crc_params.crc(
+                            System34Parser::crc_mark(track, &sector_header[0..4]),
+                            &sector_header[4..8],
+                        );
 
                         let sector_id = SectorId {
                             c: sector_header[4],
@@ -614,13 +688,21 @@ impl DiskStructureParser for System34Parser {
 
                         //log::trace!("dam header verify: {:02X?}", dam_header);
 
-                        let crc_byte0 = track.read_decoded_byte(data_end).unwrap_or(0xAA);
-                        let crc_byte1 = track.read_decoded_byte(data_end + mfm_offset!(1)).unwrap_or(0xAA);
-                        let crc = u16::from_be_bytes([crc_byte0, crc_byte1]);
-                        let calculated_crc = System34Parser::crc16(track, element_offset, data_end);
-                        log::trace!("Data CRC16: {:04X} Calculated: {:04X}", crc, calculated_crc);
-
-                        let crc_correct = crc == calculated_crc;
+                        let crc_correct = if data_end + mfm_offset!(2) > track.len() {
+                            truncated(format!(
+                                "{}Data field at bitcell {} extends past the end of the track",
+                                log_prefix, element_offset
+                            ))?;
+                            false
+                        } else {
+                            let crc_byte0 = track.read_decoded_byte(data_end).unwrap_or(0xAA);
+                            let crc_byte1 = track.read_decoded_byte(data_end + mfm_offset!(1)).unwrap_or(0xAA);
+                            let crc = u16::from_be_bytes([crc_byte0, crc_byte1]);
+                            let calculated_crc =
+                                System34Parser::crc16_params(track, element_offset, data_end, crc_params);
+                            log::trace!("Data CRC16: {:04X} Calculated: {:04X}", crc, calculated_crc);
+                            crc == calculated_crc
+                        };
                         if !crc_correct {
                             log::warn!("Data CRC error detected at offset: {}", element_offset);
                         }
@@ -714,7 +796,243 @@ impl DiskStructureParser for System34Parser {
 
         // Sort elements by start offset.
         elements.sort_by(|a, b| a.start.cmp(&b.start));
-        elements
+        Ok(elements)
+    }
+
+    /// Calculate the CRC of the address mark and field spanning the bitcells `bit_index..end`
+    /// with `crc_params`.
+    pub fn crc16_params(
+        track: &mut TrackDataStream,
+        bit_index: usize,
+        end: usize,
+        crc_params: &System34CrcParams,
+    ) -> u16 {
+        let bytes_requested = ((end - bit_index) >> 1) / 8;
+
+        log::trace!(
+            "Performing CRC on {} bytes from bit index {}",
+            bytes_requested,
+            bit_index
+        );
+        let mut data = vec![0; bytes_requested];
+        match track {
+            TrackDataStream::Mfm(mfm_stream) => {
+                mfm_stream.seek(SeekFrom::Start((bit_index >> 1) as u64)).unwrap();
+                mfm_stream.read_exact(&mut data).unwrap();
+            }
+            TrackDataStream::Fm(fm_stream) => {
+                fm_stream.seek(SeekFrom::Start((bit_index >> 1) as u64)).unwrap();
+                fm_stream.read_exact(&mut data).unwrap();
+            }
+            _ => return 0,
+        }
+        let mark_len = std::cmp::min(4, data.len());
+        crc_params.crc(System34Parser::crc_mark(track, &data[..mark_len]), &data[mark_len..])
+    }
+
+    /// Return the bytes of the decoded address mark `mark` that are covered by the CRC of the
+    /// field that follows it. FM address marks are preceded by plain 0x00 sync bytes rather than
+    /// sync marks, and these are not covered, so only the address mark byte is returned for FM.
+    pub(crate) fn crc_mark<'a>(track: &TrackDataStream, mark: &'a [u8]) -> &'a [u8] {
+        match track {
+            TrackDataStream::Fm(_) => &mark[mark.len().saturating_sub(1)..],
+            _ => mark,
+        }
+    }
+
+    pub(crate) fn set_track_markers(
+        mfm_codec: &mut MfmCodec,
+        markers: Vec<(System34Marker, usize)>,
+    ) -> Result<(), DiskImageError> {
+        for (marker, offset) in markers {
+            let marker_bit_index = offset * MFM_BYTE_LEN;
+
+            log::trace!("Setting marker {:?} at bit index: {}", marker, marker_bit_index);
+            mfm_codec
+                .write_encoded_buf(&marker.bytes(), marker_bit_index, MfmEncodingType::AddressMark)
+                .map_err(|_| DiskImageError::IoError)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DiskStructureParser for System34Parser {
+    /// Find the provided pattern of bytes within the specified bitstream, starting at `offset` bits
+    /// into the track.
+    /// The bit offset of the pattern is returned if found, otherwise None.
+    /// The pattern length is limited to 8 characters.
+    fn find_data_pattern(track: &TrackDataStream, pattern: &[u8], offset: usize) -> Option<usize> {
+        let mut buffer = [0u8; 8];
+        let len = pattern.len().min(8);
+        buffer[(8 - len)..8].copy_from_slice(&pattern[..len]);
+        let pat = u64::from_be_bytes(buffer);
+
+        let pat_mask = u64::MAX >> (8 * (8 - len));
+        let mut shift_reg = 0;
+
+        //log::trace!("Constructed pattern: {:016X?}, mask: {:016X}", pat, pat_mask);
+
+        let mut bit_ct = 0;
+        for bi in offset..track.len() {
+            shift_reg = shift_reg << 1 | track[bi] as u64;
+            if (bit_ct >= (len * 8)) && (shift_reg & pat_mask) == pat {
+                //log::trace!("find_data_pattern: shift_reg: {:064b} pat: {:064b}", shift_reg, pat);
+                //log::trace!("find_data_pattern: Found pattern at bit offset: {}", bi);
+                return Some(bi - len * 8 + 1);
+            }
+            bit_ct += 1;
+        }
+        None
+    }
+
+    /// Find the next address marker in the track bitstream. The type of marker and its position in
+    /// the bitstream is returned, or None.
+    fn find_next_marker(track: &TrackDataStream, offset: usize) -> Option<(DiskStructureMarker, usize)> {
+        let next_marker = match track {
+            TrackDataStream::Mfm(mfm_stream) => mfm_stream.find_next_marker(ANY_MARKER, MARKER_MASK, offset),
+            TrackDataStream::Fm(fm_stream) => fm_stream.find_next_marker(FM_ANY_MARKER, FM_MARKER_MASK, offset),
+            _ => None,
+        };
+        if let Some((index, marker_u16)) = next_marker {
+            if let Ok(marker) = marker_u16.try_into() {
+                return Some((DiskStructureMarker::System34(marker), index));
+            }
+        }
+        None
+    }
+
+    fn find_marker(
+        track: &TrackDataStream,
+        marker: DiskStructureMarker,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Option<usize> {
+        if let DiskStructureMarker::System34(marker) = marker {
+            match track {
+                TrackDataStream::Mfm(mfm_stream) => {
+                    //log::trace!("find_marker(): Searching for marker at offset: {}", offset);
+                    return mfm_stream.find_marker(u64::from(marker), offset, limit);
+                }
+                TrackDataStream::Fm(fm_stream) => return fm_stream.find_marker(marker.fm_marker(), offset, limit),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn find_element(track: &TrackDataStream, element: DiskStructureElement, offset: usize) -> Option<usize> {
+        if let DiskStructureElement::System34(element) = element {
+            use System34Element::*;
+
+            let (marker, _pattern) = match element {
+                Gap1 | Gap2 | Gap3 | Gap4a | Gap4b => (System34Parser::encode_marker(&[0x4E; 4]), &[0x4E; 4]),
+                Sync => (MfmCodec::encode_marker(&[0x00; 4]), &[0x00; 4]),
+                _ => return None,
+            };
+
+            //let marker = System34Parser::encode_marker(pattern);
+            log::trace!(
+                "find_element(): Encoded element: {:?} as {:016X}/{:064b}",
+                element,
+                marker,
+                marker
+            );
+
+            if let TrackDataStream::Mfm(mfm_stream) = track {
+                log::trace!("find_element(): Searching for element at offset: {}", offset);
+                //let mfm_offset = System34Parser::find_data_pattern(track, pattern, offset >> 1);
+                let raw_offset = mfm_stream.find_marker(marker, offset, None);
+                /*                if let Some(mfm_offset) = mfm_offset {
+                    log::trace!(
+                        "find_element(): Found element in decoded stream: {:?} at offset: {}",
+                        element,
+                        mfm_offset << 1
+                    );
+
+                    log::trace!(
+                        "find_element(): marker_bits (encoded) {}",
+                        mfm_stream.debug_marker(mfm_offset << 1)
+                    );
+                    log::trace!(
+                        "find_element(): marker_bits (decoded) {}",
+                        mfm_stream.debug_decode(mfm_offset)
+                    );
+                }*/
+
+                if let Some(offset) = raw_offset {
+                    log::trace!(
+                        "find_element(): Found element in raw stream: {:?} at offset: {}, sync: {} debug: {}",
+                        element,
+                        offset,
+                        offset & 1,
+                        mfm_stream.debug_marker(offset)
+                    );
+                    return Some(offset);
+                }
+            }
+
+            //log::trace!("Searching for pattern: {:02X?} at offset {}", pattern, offset);
+            //return System34Parser::find_pattern(track, pattern, offset);
+        };
+
+        None
+    }
+
+    /// Scan a track bitstream for address markers, including the IAM, IDAM and DAM markers. Return
+    /// their positions. The marker positions will be used to create the clock phase map for the
+    /// track, which must be performed before we can read the data off the disk which is done in
+    /// a second pass.
+    fn scan_track_markers(track: &mut TrackDataStream) -> Vec<DiskStructureMarkerItem> {
+        let mut bit_cursor: usize = 0;
+        let mut markers = Vec::new();
+
+        // Look for the IAM marker first - but it may not be present (ISO standard encoding does
+        // not require it).
+
+        if let Some(marker_offset) = System34Parser::find_marker(
+            track,
+            DiskStructureMarker::System34(System34Marker::Iam),
+            bit_cursor,
+            Some(5_000),
+        ) {
+            log::trace!(
+                "scan_track_markers(): Found IAM marker at bit offset: {}",
+                marker_offset
+            );
+            markers.push(DiskStructureMarkerItem {
+                elem_type: DiskStructureMarker::System34(System34Marker::Iam),
+                start: marker_offset,
+            });
+            bit_cursor = marker_offset + 4 * MFM_BYTE_LEN;
+        }
+
+        while let Some((marker, marker_offset)) = System34Parser::find_next_marker(track, bit_cursor) {
+            /*
+            log::trace!(
+                "scan_track_markers(): Found marker of type {:?} at bit offset: {}",
+                marker,
+                marker_offset
+            );*/
+
+            markers.push(DiskStructureMarkerItem {
+                elem_type: marker,
+                start: marker_offset,
+            });
+            bit_cursor = marker_offset + 4 * MFM_BYTE_LEN;
+        }
+        markers
+    }
+
+    /// Scan a track bitstream using the pre-scanned marker positions to extract marker data such
+    /// as Sector ID values and CRCs. This is done in a second pass after the markers have been
+    /// found by scan_track_markers() and a clock phase map created for the track - required for the
+    /// proper functioning of the Read and Seek traits on MfmCodec.
+    fn scan_track_metadata(
+        track: &mut TrackDataStream,
+        markers: Vec<DiskStructureMarkerItem>,
+    ) -> Vec<DiskStructureMetadataItem> {
+        System34Parser::scan_track_metadata_crc(track, markers, &System34CrcParams::default())
     }
 
     /// Use the list of track markers to create a clock phase map for the track. This a requirement
@@ -759,20 +1077,6 @@ impl DiskStructureParser for System34Parser {
     }
 
     fn crc16(track: &mut TrackDataStream, bit_index: usize, end: usize) -> u16 {
-        let bytes_requested = ((end - bit_index) >> 1) / 8;
-
-        log::trace!(
-            "Performing CRC on {} bytes from bit index {}",
-            bytes_requested,
-            bit_index
-        );
-        if let TrackDataStream::Mfm(mfm_stream) = track {
-            let mut data = vec![0; bytes_requested];
-            mfm_stream.seek(SeekFrom::Start((bit_index >> 1) as u64)).unwrap();
-            mfm_stream.read_exact(&mut data).unwrap();
-            crc_ccitt(&data, None)
-        } else {
-            0
-        }
+        System34Parser::crc16_params(track, bit_index, end, &System34CrcParams::default())
     }
 }
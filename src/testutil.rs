@@ -0,0 +1,230 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/testutil.rs
+
+    Generators for reference disk images, for use in tests.
+
+    Each TestImage is built programmatically from a formatted StandardFormat
+    image, then modified to contain a specific quirk on a known track so that
+    tests can exercise it without depending on checked-in image files.
+*/
+
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType, MFM_BYTE_LEN};
+use crate::bitstream::TrackDataStream;
+use crate::diskimage::{MatchPolicy, RwSectorScope};
+use crate::image_builder::ImageBuilder;
+use crate::structure_parsers::system34::{System34Standard, DDAM_MARKER_BYTES};
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskChs, DiskChsn, DiskDataResolution, DiskImage, DiskImageError, StandardFormat};
+use bit_vec::BitVec;
+
+/// The cylinder on which quirks are placed in generated test images. Head 0 is always used.
+pub const TEST_QUIRK_CYLINDER: u16 = 1;
+
+/// The sector sizes, as values of N, used for the track in a [`TestImage::MixedSectorSizes`] image.
+/// Sector IDs are numbered from 1.
+pub const TEST_MIXED_SECTOR_SIZES: [u8; 4] = [0, 1, 2, 3];
+
+/// The sector IDs, in physical order, of the track in a [`TestImage::DuplicateIds`] image.
+/// Sector 5 appears twice and sector 6 is missing.
+pub const TEST_DUPLICATE_IDS: [u8; 9] = [1, 2, 3, 4, 5, 5, 7, 8, 9];
+
+/// The sector ID of the sector modified in images containing a single quirked sector.
+pub const TEST_QUIRK_SECTOR: u8 = 4;
+
+/// A [`TestImage`] specifies a reference disk image that can be generated programmatically.
+///
+/// Quirk images are based on a formatted 360K BitStream image, with the quirk placed on
+/// cylinder [`TEST_QUIRK_CYLINDER`], head 0. All other tracks are standard.
+#[derive(Copy, Clone, Debug)]
+pub enum TestImage {
+    /// A formatted BitStream image of the specified standard format.
+    Standard(StandardFormat),
+    /// A track with sectors of different sizes, given by [`TEST_MIXED_SECTOR_SIZES`].
+    MixedSectorSizes,
+    /// A track where sector [`TEST_QUIRK_SECTOR`] has a sector header with a bad CRC.
+    BadAddressCrc,
+    /// A track where sector [`TEST_QUIRK_SECTOR`] has a data block with a bad CRC.
+    BadDataCrc,
+    /// A track where sector [`TEST_QUIRK_SECTOR`] has a deleted data address mark.
+    DeletedData,
+    /// A track where the first 16 bytes of sector [`TEST_QUIRK_SECTOR`] consist of weak bits.
+    WeakBits,
+    /// A track with the duplicate and missing sector IDs given by [`TEST_DUPLICATE_IDS`].
+    DuplicateIds,
+}
+
+impl TestImage {
+    /// Generate the [`DiskImage`] specified by this [`TestImage`].
+    pub fn generate(&self) -> Result<DiskImage, DiskImageError> {
+        if let TestImage::Standard(format) = self {
+            return ImageBuilder::new()
+                .with_resolution(DiskDataResolution::BitStream)
+                .with_standard_format(*format)
+                .with_formatted()
+                .build();
+        }
+
+        let mut image = ImageBuilder::new()
+            .with_resolution(DiskDataResolution::BitStream)
+            .with_standard_format(StandardFormat::PcFloppy360)
+            .with_formatted()
+            .build()?;
+
+        let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+        let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+
+        match self {
+            TestImage::MixedSectorSizes => {
+                let format_buffer = TEST_MIXED_SECTOR_SIZES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| DiskChsn::new(ch.c(), ch.h(), i as u8 + 1, *n))
+                    .collect();
+                image.format_track(ch, System34Standard::Iso, format_buffer, 0xF6, 0x50)?;
+            }
+            TestImage::BadAddressCrc => {
+                // Overwrite the CRC following the sector ID with zeros.
+                let header_idx = sector_header_index(&image, chs)?;
+                image.write_encoded_data(ch, &[0, 0], header_idx + 8 * MFM_BYTE_LEN, MfmEncodingType::Data)?;
+            }
+            TestImage::BadDataCrc => {
+                // Write a short data block. The CRC is written after it, not where the header
+                // size indicates.
+                image.write_sector_debug(chs, None, &[0xAA; 256], 0x00, false)?;
+            }
+            TestImage::DeletedData => {
+                let data_idx = sector_data_index(&image, chs)?;
+                image.write_encoded_data(ch, &DDAM_MARKER_BYTES, data_idx, MfmEncodingType::AddressMark)?;
+                // Rewrite the data so that the data CRC covers the new address mark.
+                let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, true)?;
+                image.write_sector(chs, None, &rsr.read_buf, RwSectorScope::DataOnly, true, false)?;
+            }
+            TestImage::WeakBits => {
+                let data_idx = sector_data_index(&image, chs)?;
+                set_weak_region(&mut image, ch, data_idx + 4 * MFM_BYTE_LEN, 16 * MFM_BYTE_LEN)?;
+            }
+            TestImage::DuplicateIds => {
+                let format_buffer = TEST_DUPLICATE_IDS
+                    .iter()
+                    .map(|s| DiskChsn::new(ch.c(), ch.h(), *s, 2))
+                    .collect();
+                image.format_track(ch, System34Standard::Iso, format_buffer, 0xF6, 0x50)?;
+            }
+            TestImage::Standard(_) => unreachable!(),
+        }
+
+        Ok(image)
+    }
+}
+
+/// Return a copy of the undecoded bitstream of the MFM track at `ch`, without a clock map or
+/// metadata, as it would be presented to a structure parser when loading an image.
+pub fn track_stream(image: &DiskImage, ch: DiskCh) -> Result<TrackDataStream, DiskImageError> {
+    match get_track(image, ch)? {
+        TrackData::BitStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        } => Ok(TrackDataStream::Mfm(MfmCodec::new(
+            BitVec::from_bytes(&mfm_codec.data()),
+            Some(mfm_codec.len()),
+            None,
+        ))),
+        _ => Err(DiskImageError::UnsupportedFormat),
+    }
+}
+
+fn get_track(image: &DiskImage, ch: DiskCh) -> Result<&TrackData, DiskImageError> {
+    if ch.h() > 1 || ch.c() as usize >= image.track_map[ch.h() as usize].len() {
+        return Err(DiskImageError::SeekError);
+    }
+    Ok(&image.track_pool[image.track_map[ch.h() as usize][ch.c() as usize]])
+}
+
+/// Return the bitcell index of the IDAM of the sector header for the sector at `chs`.
+fn sector_header_index(image: &DiskImage, chs: DiskChs) -> Result<usize, DiskImageError> {
+    let ch = DiskCh::from(chs);
+    let mut bit_index = 0;
+    while let Some((chsn, idx)) = get_track(image, ch)?.get_next_id_at(bit_index) {
+        if idx < bit_index {
+            break;
+        }
+        if chsn.s() == chs.s() {
+            return Ok(idx);
+        }
+        bit_index = idx + 1;
+    }
+    Err(DiskImageError::DataError)
+}
+
+/// Return the bitcell index of the data address mark for the sector at `chs`.
+fn sector_data_index(image: &DiskImage, chs: DiskChs) -> Result<usize, DiskImageError> {
+    get_track(image, DiskCh::from(chs))?
+        .get_sector_bit_index(chs, None, MatchPolicy::Chsn)
+        .map(|(idx, ..)| idx)
+        .ok_or(DiskImageError::DataError)
+}
+
+/// Mark `len` bitcells of the track at `ch`, starting at `bit_index`, as weak. The bitcells are
+/// cleared, as no flux transitions would be recorded in a weak region, and set in the weak mask.
+fn set_weak_region(image: &mut DiskImage, ch: DiskCh, bit_index: usize, len: usize) -> Result<(), DiskImageError> {
+    if ch.h() > 1 || ch.c() as usize >= image.track_map[ch.h() as usize].len() {
+        return Err(DiskImageError::SeekError);
+    }
+    let ti = image.track_map[ch.h() as usize][ch.c() as usize];
+
+    match &mut image.track_pool[ti] {
+        TrackData::BitStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        } => {
+            mfm_codec
+                .write_raw_buf(&vec![0; len / 8], bit_index)
+                .map_err(|_| DiskImageError::IoError)?;
+
+            let mut weak_mask = mfm_codec.get_weak_mask().clone();
+            for i in bit_index..bit_index + len {
+                weak_mask.set(i, true);
+            }
+            mfm_codec
+                .set_weak_mask(weak_mask)
+                .map_err(|_| DiskImageError::IoError)?;
+        }
+        _ => return Err(DiskImageError::UnsupportedFormat),
+    }
+
+    // The sector's data CRC is no longer valid.
+    image.track_pool[ti].rescan()
+}
@@ -0,0 +1,257 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/timeline.rs
+
+    Renders the structure of a track as a timeline of its fields, as text, SVG
+    or a GraphViz graph, for documenting and debugging unusual tracks.
+*/
+
+use crate::bitstream::mfm::MFM_BYTE_LEN;
+use crate::structure_parsers::system34::System34Marker;
+use crate::{DiskCh, DiskChsn};
+use std::fmt::Write;
+
+/// A [`TimelineElement`] identifies the kind of field a [`TimelineSpan`] covers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimelineElement {
+    /// Gap bytes, or any other region not part of a recognized field.
+    Gap,
+    /// A run of 0x00 sync bytes preceding an address mark.
+    Sync,
+    Marker(System34Marker),
+    /// The sector ID (CHSN) following an IDAM.
+    SectorId(DiskChsn),
+    /// The data following a DAM or DDAM, up to its CRC.
+    Data(DiskChsn),
+    /// The CRC following a sector ID or data field, and whether it is valid.
+    Crc(bool),
+}
+
+impl TimelineElement {
+    /// Return a short label for the element.
+    pub fn label(&self) -> String {
+        match self {
+            TimelineElement::Gap => "GAP".to_string(),
+            TimelineElement::Sync => "SYNC".to_string(),
+            TimelineElement::Marker(marker) => marker.to_string(),
+            TimelineElement::SectorId(chsn) => format!("ID {}", chsn),
+            TimelineElement::Data(chsn) => format!("DATA s:{}", chsn.s()),
+            TimelineElement::Crc(true) => "CRC ok".to_string(),
+            TimelineElement::Crc(false) => "CRC bad".to_string(),
+        }
+    }
+
+    /// The character drawn for the element on a text timeline.
+    fn symbol(&self) -> char {
+        match self {
+            TimelineElement::Gap => '.',
+            TimelineElement::Sync => 's',
+            TimelineElement::Marker(_) => 'M',
+            TimelineElement::SectorId(_) => 'I',
+            TimelineElement::Data(_) => 'D',
+            TimelineElement::Crc(true) => 'c',
+            TimelineElement::Crc(false) => '!',
+        }
+    }
+
+    /// The fill color for the element on an SVG timeline or GraphViz graph.
+    fn color(&self) -> &'static str {
+        match self {
+            TimelineElement::Gap => "#d0d0d0",
+            TimelineElement::Sync => "#a0c0e0",
+            TimelineElement::Marker(System34Marker::Iam) => "#4040ff",
+            TimelineElement::Marker(System34Marker::Ddam) => "#ff8000",
+            TimelineElement::Marker(_) => "#ffd000",
+            TimelineElement::SectorId(_) => "#80c080",
+            TimelineElement::Data(_) => "#40a040",
+            TimelineElement::Crc(true) => "#206020",
+            TimelineElement::Crc(false) => "#ff2020",
+        }
+    }
+}
+
+/// A [`TimelineSpan`] is a field of a track, spanning the bitcells `start..end`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimelineSpan {
+    pub element: TimelineElement,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TimelineSpan {
+    /// Return the length of the span in bytes.
+    pub fn byte_len(&self) -> usize {
+        (self.end - self.start) / MFM_BYTE_LEN
+    }
+}
+
+/// A [`TrackTimeline`] lists the fields of a track in order from the index, as returned by
+/// [`crate::DiskImage::track_timeline`]. Spans may overlap where a data field as sized by its
+/// sector header runs into the fields that follow it.
+#[derive(Clone, Debug)]
+pub struct TrackTimeline {
+    pub ch: DiskCh,
+    pub bitcell_ct: usize,
+    pub spans: Vec<TimelineSpan>,
+}
+
+impl TrackTimeline {
+    /// Render the timeline as text: a bar `width` characters wide showing the track layout to
+    /// scale, followed by a table listing each field with its bitcell offset and length in bytes.
+    pub fn render_text(&self, width: usize) -> String {
+        let mut out = String::new();
+        _ = writeln!(out, "Track {} ({} bitcells)", self.ch, self.bitcell_ct);
+
+        // Fields shorter than a column still claim it, so that markers and CRCs stay visible.
+        let width = width.max(1);
+        let mut bar = vec!['.'; width];
+        for span in &self.spans {
+            let first = span.start * width / self.bitcell_ct.max(1);
+            let last = (span.end.saturating_sub(1) * width / self.bitcell_ct.max(1)).max(first);
+            for c in bar.iter_mut().take(last.min(width - 1) + 1).skip(first) {
+                if *c == '.' || span.element != TimelineElement::Gap {
+                    *c = span.element.symbol();
+                }
+            }
+        }
+        _ = writeln!(out, "|{}|", bar.into_iter().collect::<String>());
+        _ = writeln!(out, "{}", TIMELINE_LEGEND);
+
+        _ = writeln!(out, "{:>9} {:>9} {:>6}  element", "start", "end", "bytes");
+        for span in &self.spans {
+            _ = writeln!(
+                out,
+                "{:>9} {:>9} {:>6}  {}",
+                span.start,
+                span.end,
+                span.byte_len(),
+                span.element.label()
+            );
+        }
+        out
+    }
+
+    /// Render the timeline as an SVG image `width` pixels wide. Each field is drawn to scale as a
+    /// colored bar, with a tooltip giving its offset and length, and sector IDs are labeled.
+    pub fn render_svg(&self, width: u32) -> String {
+        const BAR_Y: u32 = 20;
+        const BAR_HEIGHT: u32 = 40;
+        let height = BAR_Y + BAR_HEIGHT + 20;
+        let scale = width as f64 / self.bitcell_ct.max(1) as f64;
+
+        let mut out = String::new();
+        _ = writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="monospace" font-size="10">"#
+        );
+        _ = writeln!(
+            out,
+            r#"<text x="0" y="12">Track {} ({} bitcells)</text>"#,
+            self.ch, self.bitcell_ct
+        );
+        for span in &self.spans {
+            let x = span.start as f64 * scale;
+            let w = ((span.end - span.start) as f64 * scale).max(0.5);
+            _ = writeln!(
+                out,
+                r#"<rect x="{:.2}" y="{}" width="{:.2}" height="{}" fill="{}"><title>{}: {}..{} ({} bytes)</title></rect>"#,
+                x,
+                BAR_Y,
+                w,
+                BAR_HEIGHT,
+                span.element.color(),
+                xml_escape(&span.element.label()),
+                span.start,
+                span.end,
+                span.byte_len()
+            );
+            if let TimelineElement::SectorId(chsn) = span.element {
+                _ = writeln!(
+                    out,
+                    r#"<text x="{:.2}" y="{}">{}</text>"#,
+                    x,
+                    BAR_Y + BAR_HEIGHT + 12,
+                    chsn.s()
+                );
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Render the timeline as a GraphViz graph in the DOT language, as a left-to-right chain of
+    /// fields. Gaps and syncs are omitted for legibility; their lengths are implied by the
+    /// offsets of the fields on either side.
+    pub fn render_dot(&self) -> String {
+        let mut out = String::new();
+        _ = writeln!(out, "digraph track {{");
+        _ = writeln!(out, "    label=\"Track {} ({} bitcells)\";", self.ch, self.bitcell_ct);
+        _ = writeln!(out, "    rankdir=LR;");
+        _ = writeln!(out, "    node [shape=record, style=filled, fontname=monospace];");
+
+        let mut prev = None;
+        for (i, span) in self
+            .spans
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| !matches!(span.element, TimelineElement::Gap | TimelineElement::Sync))
+        {
+            _ = writeln!(
+                out,
+                "    n{} [label=\"{}|{}\", fillcolor=\"{}\"];",
+                i,
+                dot_escape(&span.element.label()),
+                span.start,
+                span.element.color()
+            );
+            if let Some(prev) = prev {
+                _ = writeln!(out, "    n{} -> n{};", prev, i);
+            }
+            prev = Some(i);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// The legend for the symbols used by [`TrackTimeline::render_text`].
+pub const TIMELINE_LEGEND: &str = ". gap  s sync  M address mark  I sector ID  D data  c CRC  ! bad CRC";
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn dot_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '"' | '{' | '}' | '|' | '<' | '>' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,249 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+
+    --------------------------------------------------------------------------
+
+    src/track_builder.rs
+
+    Implements the Builder pattern for composite bitstream tracks.
+
+    Allows a track to be assembled from segments taken from different sources,
+    such as sectors from one image, gaps from another, and a protection region
+    specified as raw bitcells.
+*/
+
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType};
+use crate::diskimage::MatchPolicy;
+use crate::structure_parsers::system34::GAP_BYTE;
+use crate::{DiskCh, DiskChs, DiskImage, DiskImageError};
+use bit_vec::BitVec;
+use std::ops::Range;
+
+enum TrackSegment {
+    Bits {
+        bits: BitVec,
+        weak: BitVec,
+    },
+    Encoded {
+        data: Vec<u8>,
+        encoding_type: MfmEncodingType,
+    },
+}
+
+impl TrackSegment {
+    fn bitcell_len(&self) -> usize {
+        match self {
+            TrackSegment::Bits { bits, .. } => bits.len(),
+            TrackSegment::Encoded { data, .. } => data.len() * 16,
+        }
+    }
+}
+
+/// Implements the Builder pattern for composite MFM bitstream tracks.
+///
+/// Segments are appended in order from the index. Raw bitcells are copied verbatim, while byte
+/// segments are MFM encoded with clock bits continuing from the preceding segment. When the track
+/// is spliced into an image with [`DiskImage::splice_track`], any remaining bitcell budget is
+/// filled with gap bytes and the track metadata is regenerated.
+pub struct TrackBuilder {
+    segments: Vec<TrackSegment>,
+    bitcell_ct: Option<usize>,
+    fill_byte: u8,
+}
+
+impl Default for TrackBuilder {
+    fn default() -> Self {
+        TrackBuilder {
+            segments: Vec::new(),
+            bitcell_ct: None,
+            fill_byte: GAP_BYTE,
+        }
+    }
+}
+
+impl TrackBuilder {
+    pub fn new() -> TrackBuilder {
+        Default::default()
+    }
+
+    /// Set the length of the track to be built in bitcells. If this is not set, the track keeps
+    /// the length of the track it replaces.
+    pub fn with_bitcell_ct(mut self, bitcell_ct: usize) -> TrackBuilder {
+        self.bitcell_ct = Some(bitcell_ct);
+        self
+    }
+
+    /// Set the byte used to fill the track after the last segment. The default is the IBM gap
+    /// byte, 0x4E.
+    pub fn with_fill_byte(mut self, fill_byte: u8) -> TrackBuilder {
+        self.fill_byte = fill_byte;
+        self
+    }
+
+    /// Append `bit_ct` raw bitcells from `data`, most significant bit first. An optional weak bit
+    /// mask of the same length as `data` may be supplied.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if `data` holds fewer than `bit_ct` bits, or the
+    ///   weak mask is not the same length as `data`.
+    pub fn with_bits(
+        mut self,
+        data: &[u8],
+        bit_ct: usize,
+        weak: Option<&[u8]>,
+    ) -> Result<TrackBuilder, DiskImageError> {
+        if bit_ct > data.len() * 8 || weak.is_some_and(|w| w.len() != data.len()) {
+            return Err(DiskImageError::ParameterError);
+        }
+
+        let mut bits = BitVec::from_bytes(data);
+        bits.truncate(bit_ct);
+        let mut weak = weak.map_or_else(|| BitVec::from_elem(bit_ct, false), BitVec::from_bytes);
+        weak.truncate(bit_ct);
+
+        self.segments.push(TrackSegment::Bits { bits, weak });
+        Ok(self)
+    }
+
+    /// Append the raw bitcells in `range` of the track `ch` of `image`, along with its weak bit
+    /// mask.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track does not exist.
+    /// - `Err(DiskImageError::ParameterError)` if the track is not an MFM BitStream track or
+    ///   `range` is out of bounds.
+    pub fn with_track_bits(
+        mut self,
+        image: &DiskImage,
+        ch: DiskCh,
+        range: Range<usize>,
+    ) -> Result<TrackBuilder, DiskImageError> {
+        let track = image.get_track_ch(ch).ok_or(DiskImageError::SeekError)?;
+        let (bits, weak) = track.copy_bits(range).ok_or(DiskImageError::ParameterError)?;
+
+        self.segments.push(TrackSegment::Bits { bits, weak });
+        Ok(self)
+    }
+
+    /// Append the sector matching `chs` and `n` from `image`, from the sync field preceding its
+    /// IDAM to the end of its data CRC. The sector is copied as raw bitcells, so any CRC errors
+    /// or weak bits are preserved.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::SeekError)` if the track or sector does not exist.
+    /// - `Err(DiskImageError::ParameterError)` if the track is not an MFM BitStream track.
+    pub fn with_sector(
+        mut self,
+        image: &DiskImage,
+        chs: DiskChs,
+        n: Option<u8>,
+        policy: MatchPolicy,
+    ) -> Result<TrackBuilder, DiskImageError> {
+        let track = image.get_track_ch(chs.into()).ok_or(DiskImageError::SeekError)?;
+        let range = track
+            .sector_bit_range(chs, n, policy)
+            .ok_or(DiskImageError::SeekError)?;
+        let (bits, weak) = track.copy_bits(range).ok_or(DiskImageError::ParameterError)?;
+
+        self.segments.push(TrackSegment::Bits { bits, weak });
+        Ok(self)
+    }
+
+    /// Append `data` as MFM encoded bytes. Encoding with [`MfmEncodingType::AddressMark`] will
+    /// produce 0xA1 and 0xC2 sync bytes with missing clock bits.
+    pub fn with_encoded(mut self, data: &[u8], encoding_type: MfmEncodingType) -> TrackBuilder {
+        self.segments.push(TrackSegment::Encoded {
+            data: data.to_vec(),
+            encoding_type,
+        });
+        self
+    }
+
+    /// Append `len` MFM encoded copies of `byte`, such as a gap or sync field.
+    pub fn with_gap(self, byte: u8, len: usize) -> TrackBuilder {
+        self.with_encoded(&vec![byte; len], MfmEncodingType::Data)
+    }
+
+    /// Return the number of bitcells used by the segments appended so far.
+    pub fn bitcell_len(&self) -> usize {
+        self.segments.iter().map(|s| s.bitcell_len()).sum()
+    }
+
+    /// Assemble the segments into a bitstream and weak bit mask of exactly `bitcell_ct` bitcells,
+    /// filling the remainder of the track with the fill byte.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if the segments do not fit in `bitcell_ct` bitcells.
+    pub(crate) fn build(&self, bitcell_ct: usize) -> Result<(BitVec, BitVec), DiskImageError> {
+        let bitcell_ct = self.bitcell_ct.unwrap_or(bitcell_ct);
+        let used = self.bitcell_len();
+        if used > bitcell_ct {
+            log::error!(
+                "TrackBuilder::build(): Segments require {} bitcells, exceeding track length of {} bitcells.",
+                used,
+                bitcell_ct
+            );
+            return Err(DiskImageError::ParameterError);
+        }
+
+        let mut bits = BitVec::with_capacity(bitcell_ct);
+        let mut weak = BitVec::with_capacity(bitcell_ct);
+
+        for segment in &self.segments {
+            match segment {
+                TrackSegment::Bits {
+                    bits: segment_bits,
+                    weak: segment_weak,
+                } => {
+                    bits.extend(segment_bits.iter());
+                    weak.extend(segment_weak.iter());
+                }
+                TrackSegment::Encoded { data, encoding_type } => {
+                    append_mfm(&mut bits, &mut weak, data, *encoding_type);
+                }
+            }
+        }
+
+        let fill_len = (bitcell_ct - bits.len()).div_ceil(16);
+        append_mfm(
+            &mut bits,
+            &mut weak,
+            &vec![self.fill_byte; fill_len],
+            MfmEncodingType::Data,
+        );
+        bits.truncate(bitcell_ct);
+        weak.truncate(bitcell_ct);
+
+        Ok((bits, weak))
+    }
+}
+
+/// MFM encode `data` onto the end of `bits`, continuing the clock from the last bitcell.
+fn append_mfm(bits: &mut BitVec, weak: &mut BitVec, data: &[u8], encoding_type: MfmEncodingType) {
+    let prev_bit = !bits.is_empty() && bits[bits.len() - 1];
+    let encoded = MfmCodec::encode_mfm(data, prev_bit, encoding_type);
+    weak.grow(encoded.len(), false);
+    bits.extend(encoded.iter());
+}
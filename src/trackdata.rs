@@ -30,22 +30,31 @@
     and associated methods.
 
 */
-use crate::bitstream::mfm::{MfmCodec, MfmEncodingType, MFM_BYTE_LEN};
+use crate::bitstream::mfm::{MfmCodec, MfmEncodingType, MFM_A1_SYNC, MFM_BYTE_LEN};
 use crate::bitstream::TrackDataStream;
 use crate::chs::DiskChsn;
 use crate::diskimage::{
-    ReadSectorResult, ReadTrackResult, RwSectorScope, SectorMapEntry, TrackSectorIndex, WriteSectorResult,
+    CrcField, CrcFix, IndexMark, MatchPolicy, MemoryUsage, ReadAddressResult, ReadSectorResult, ReadTrackResult,
+    RwSectorScope, SectorGaps, SectorMapEntry, SectorReadTime, TrackGaps, TrackSectorIndex, TrackSource,
+    WriteSectorResult, WriteTrackResult,
 };
+use crate::flux::{cell_time_of, FluxRevolution};
+use crate::random::RandomSource;
+use crate::structure_parsers::amiga::{AmigaElement, AmigaParser};
 use crate::structure_parsers::system34::{
-    System34Element, System34Marker, System34Parser, System34Standard, DAM_MARKER_BYTES, DDAM_MARKER_BYTES,
+    System34CrcParams, System34Element, System34Marker, System34Parser, System34Standard, DAM_MARKER_BYTES,
+    DDAM_MARKER_BYTES, IBM_GAP3_DEFAULT, IDAM_MARKER_BYTES, SYNC_LEN,
 };
 use crate::structure_parsers::{
     DiskStructureElement, DiskStructureMetadata, DiskStructureMetadataItem, DiskStructureParser,
 };
+use crate::timeline::{TimelineElement, TimelineSpan, TrackTimeline};
 use crate::util::crc_ccitt;
-use crate::{DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskImageError};
+use crate::{DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskImageError, DiskRpm};
+use bit_vec::BitVec;
 use sha1_smol::Digest;
 use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
 
 pub struct TrackDataIndexResult {
     element_start: usize,
@@ -56,11 +65,15 @@ pub struct TrackDataIndexResult {
     deleted: bool,
 }
 
-/// A TrackData enum is one of two variants indicating the representational level of the disk image.
+/// A TrackData enum is one of three variants indicating the representational level of the disk image.
 /// A BitStream variant contains an encoded bitstream of the disk data along with metadata describing
 /// the structure of the data.
+/// A FluxStream variant is a BitStream that retains the revolutions of flux transitions it was
+/// resolved from.
 /// A ByteStream variant contains byte-level data organized by sector. A weak bit mask may be
 /// present to indicate sectors with weak bits.
+#[derive(Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum TrackData {
     BitStream {
         encoding: DiskDataEncoding,
@@ -70,7 +83,38 @@ pub enum TrackData {
         head: u8,
         data: TrackDataStream,
         metadata: DiskStructureMetadata,
-        sector_ids: Vec<DiskChsn>,
+        /// The CRC parameters used to check and write the track's ID and data fields.
+        crc: System34CrcParams,
+        source: Option<TrackSource>,
+        /// The length of the track in bitcells as loaded from the source image. Long and short
+        /// tracks are a common copy protection feature, so exporters preserve this length and
+        /// warn if the track has since been written at a different one. `None` for tracks created
+        /// in memory.
+        source_bitcell_ct: Option<usize>,
+        /// The bitcell index where the last write to the track ended and the new recording meets
+        /// the old one. `None` if the track has not been written bit by bit.
+        splice: Option<usize>,
+    },
+    /// A FluxStream track holds each revolution of flux transitions a track was captured as, along
+    /// with the bitstream resolved from one of them by a [`crate::bitstream::pll::Pll`]. All other
+    /// fields are as for a BitStream track, and FluxStream tracks are read and written through the
+    /// resolved bitstream.
+    FluxStream {
+        encoding: DiskDataEncoding,
+        data_rate: DiskDataRate,
+        data_clock: u32,
+        cylinder: u16,
+        head: u8,
+        data: TrackDataStream,
+        metadata: DiskStructureMetadata,
+        crc: System34CrcParams,
+        source: Option<TrackSource>,
+        source_bitcell_ct: Option<usize>,
+        splice: Option<usize>,
+        /// The captured revolutions of flux transitions, starting at the index pulse.
+        revolutions: Vec<FluxRevolution>,
+        /// The index into `revolutions` of the revolution the bitstream was resolved from.
+        resolved: usize,
     },
     ByteStream {
         encoding: DiskDataEncoding,
@@ -80,20 +124,504 @@ pub enum TrackData {
         sectors: Vec<TrackSectorIndex>,
         data: Vec<u8>,
         weak_mask: Vec<u8>,
+        source: Option<TrackSource>,
     },
 }
 
 impl TrackData {
     pub fn ch(&self) -> DiskCh {
         match self {
-            TrackData::BitStream { cylinder, head, .. } => DiskCh::new(*cylinder, *head),
+            TrackData::BitStream { cylinder, head, .. } | TrackData::FluxStream { cylinder, head, .. } => {
+                DiskCh::new(*cylinder, *head)
+            }
             TrackData::ByteStream { cylinder, head, .. } => DiskCh::new(*cylinder, *head),
         }
     }
 
+    pub fn resolution(&self) -> DiskDataResolution {
+        match self {
+            TrackData::BitStream { .. } => DiskDataResolution::BitStream,
+            TrackData::FluxStream { .. } => DiskDataResolution::FluxStream,
+            TrackData::ByteStream { .. } => DiskDataResolution::ByteStream,
+        }
+    }
+
+    /// Convert a BitStream track into a FluxStream track that retains `revolutions`, the flux
+    /// transitions captured from the track, of which the bitstream was resolved from the one at
+    /// index `resolved`. The revolutions of a FluxStream track are replaced. ByteStream tracks are
+    /// returned unchanged.
+    pub(crate) fn with_flux(self, revolutions: Vec<FluxRevolution>, resolved: usize) -> TrackData {
+        match self {
+            TrackData::BitStream {
+                encoding,
+                data_rate,
+                data_clock,
+                cylinder,
+                head,
+                data,
+                metadata,
+                crc,
+                source,
+                source_bitcell_ct,
+                splice,
+            }
+            | TrackData::FluxStream {
+                encoding,
+                data_rate,
+                data_clock,
+                cylinder,
+                head,
+                data,
+                metadata,
+                crc,
+                source,
+                source_bitcell_ct,
+                splice,
+                ..
+            } => TrackData::FluxStream {
+                encoding,
+                data_rate,
+                data_clock,
+                cylinder,
+                head,
+                data,
+                metadata,
+                crc,
+                source,
+                source_bitcell_ct,
+                splice,
+                revolutions,
+                resolved,
+            },
+            track => track,
+        }
+    }
+
+    /// Convert a FluxStream track into a BitStream track, discarding its flux revolutions. Other
+    /// tracks are unchanged.
+    fn drop_flux(&mut self) {
+        if !matches!(self, TrackData::FluxStream { .. }) {
+            return;
+        }
+        let placeholder = TrackData::ByteStream {
+            encoding: Default::default(),
+            data_rate: Default::default(),
+            cylinder: 0,
+            head: 0,
+            sectors: Vec::new(),
+            data: Vec::new(),
+            weak_mask: Vec::new(),
+            source: None,
+        };
+        if let TrackData::FluxStream {
+            encoding,
+            data_rate,
+            data_clock,
+            cylinder,
+            head,
+            data,
+            metadata,
+            crc,
+            source,
+            source_bitcell_ct,
+            splice,
+            ..
+        } = std::mem::replace(self, placeholder)
+        {
+            *self = TrackData::BitStream {
+                encoding,
+                data_rate,
+                data_clock,
+                cylinder,
+                head,
+                data,
+                metadata,
+                crc,
+                source,
+                source_bitcell_ct,
+                splice,
+            };
+        }
+    }
+
+    /// Decode a BitStream or FluxStream track into an equivalent ByteStream track holding only its
+    /// sectors, in track order. The address CRC, data CRC and deleted status of each sector are
+    /// kept, as is the weak bit mask of its data. ByteStream tracks are returned as a copy.
+    ///
+    /// Sectors with a duplicate ID all receive the data of the first sector with that ID.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::IncompatibleImage)` if the track is not an MFM or FM System34 track.
+    pub(crate) fn to_bytestream(&self) -> Result<TrackData, DiskImageError> {
+        if matches!(self, TrackData::ByteStream { .. }) {
+            return Ok(self.clone());
+        }
+        if !matches!(self.encoding(), DiskDataEncoding::Mfm | DiskDataEncoding::Fm) || self.is_amiga() {
+            return Err(DiskImageError::IncompatibleImage);
+        }
+
+        // Reading moves the stream cursor, so read from a copy.
+        let mut track = self.clone();
+        let mut sectors = Vec::new();
+        let mut data = Vec::new();
+        let mut weak_mask = Vec::new();
+
+        for entry in self.get_sector_list() {
+            let chsn = entry.chsn;
+            // A sector with a bad address CRC is only read in debug mode, at its recorded size.
+            let (n, debug) = match entry.address_crc_valid {
+                true => (None, false),
+                false => (Some(chsn.n()), true),
+            };
+            let result = track.read_sector(
+                DiskChs::from(chsn),
+                n,
+                RwSectorScope::DataOnly,
+                MatchPolicy::Chsn,
+                debug,
+            )?;
+
+            let sector_data = &result.read_buf[result.data_idx..result.data_idx + result.data_len];
+            sectors.push(TrackSectorIndex {
+                sector_id: chsn.s(),
+                cylinder_id: chsn.c(),
+                head_id: chsn.h(),
+                t_idx: data.len(),
+                n: chsn.n(),
+                len: sector_data.len(),
+                address_crc_error: !entry.address_crc_valid,
+                data_crc_error: result.data_crc_error,
+                deleted_mark: result.deleted_mark,
+                no_dam: entry.no_dam,
+                position: entry.position,
+                read_time: entry.read_time,
+                timing: None,
+            });
+            data.extend_from_slice(sector_data);
+            match result.weak_mask {
+                Some(mask) => {
+                    weak_mask.extend(mask.iter().copied().chain(std::iter::repeat(0)).take(sector_data.len()))
+                }
+                None => weak_mask.resize(data.len(), 0),
+            }
+        }
+
+        let ch = self.ch();
+        Ok(TrackData::ByteStream {
+            encoding: self.encoding(),
+            data_rate: self.data_rate(),
+            cylinder: ch.c(),
+            head: ch.h(),
+            sectors,
+            data,
+            weak_mask,
+            source: self.source_bytes().cloned(),
+        })
+    }
+
+    /// Mark as weak each bitcell of a sector data field that differs from the same field of
+    /// `other`, another revolution of the same track. Fields are matched by sector ID, and
+    /// compared from their address marks so that drift between the revolutions elsewhere on the
+    /// track does not matter. Returns the number of bitcells newly marked weak.
+    pub(crate) fn mark_weak_from(&mut self, other: &TrackData) -> usize {
+        let data_fields = |track: &TrackData| -> Vec<(Option<DiskChsn>, usize, usize)> {
+            track.metadata().map_or(Vec::new(), |metadata| {
+                metadata
+                    .items
+                    .iter()
+                    .filter(|i| {
+                        matches!(
+                            i.elem_type,
+                            DiskStructureElement::System34(System34Element::Data { .. })
+                        )
+                    })
+                    .map(|i| (i.chsn, i.start, i.end))
+                    .collect()
+            })
+        };
+        let ours = data_fields(self);
+        let theirs = data_fields(other);
+
+        let (TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. }) = self else {
+            return 0;
+        };
+        let (TrackData::BitStream { data: other_data, .. } | TrackData::FluxStream { data: other_data, .. }) = other
+        else {
+            return 0;
+        };
+        let Some(mut weak_mask) = data.get_weak_mask().cloned() else {
+            return 0;
+        };
+        let (bits, other_bits) = (data.bits(), other_data.bits());
+
+        let mut weak_ct = 0;
+        for (i, &(chsn, start, end)) in ours.iter().enumerate() {
+            // Match repeated IDs in order of appearance.
+            let nth = ours[..i].iter().filter(|f| f.0 == chsn).count();
+            let Some(&(_, other_start, other_end)) = theirs.iter().filter(|f| f.0 == chsn).nth(nth) else {
+                continue;
+            };
+            for k in 0..(end - start).min(other_end - other_start) {
+                if bits.get(start + k) != other_bits.get(other_start + k) && weak_mask.get(start + k) == Some(false) {
+                    weak_mask.set(start + k, true);
+                    weak_ct += 1;
+                }
+            }
+        }
+
+        if weak_ct > 0 {
+            _ = data.set_weak_mask(weak_mask);
+        }
+        weak_ct
+    }
+
+    /// Set the source of the random data returned when reading weak bits of the track. ByteStream
+    /// tracks return their weak bits as stored, so are unaffected.
+    pub(crate) fn set_rng(&mut self, rng: RandomSource) {
+        match self {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => data.set_rng(rng),
+            TrackData::ByteStream { .. } => {}
+        }
+    }
+
+    /// Return the encoded bitcell at `pos`, counted from the index, as a drive reading the track
+    /// would see it. Positions past the end of the track wrap around to its start, and weak
+    /// bitcells return random data on each read. Returns `None` for ByteStream tracks.
+    pub fn bit_at(&self, pos: usize) -> Option<bool> {
+        match self {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => data.bit_at(pos),
+            TrackData::ByteStream { .. } => None,
+        }
+    }
+
+    /// Return an iterator over the encoded bitcells of the track starting at `pos`, as returned
+    /// by [`TrackData::bit_at`]. The iterator wraps around the end of the track and never ends,
+    /// like a spinning disk, so emulators can clock bits from it directly. ByteStream and empty
+    /// tracks return an empty iterator.
+    pub fn bits_iter(&self, pos: usize) -> impl Iterator<Item = bool> + '_ {
+        let data = match self {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } if !data.is_empty() => Some(data),
+            _ => None,
+        };
+        data.into_iter().flat_map(move |data| {
+            (0..data.len())
+                .cycle()
+                .skip(pos % data.len())
+                .map(move |i| data.bit_at(i).unwrap_or(false))
+        })
+    }
+
+    /// Return the bitcell index where the last bit-level write to the track ended, as recorded by
+    /// [`crate::diskimage::TrackBitWriter`]. Returns `None` if the track has not been written bit
+    /// by bit, and for ByteStream tracks.
+    pub fn splice(&self) -> Option<usize> {
+        match self {
+            TrackData::BitStream { splice, .. } | TrackData::FluxStream { splice, .. } => *splice,
+            TrackData::ByteStream { .. } => None,
+        }
+    }
+
+    /// Return the revolution of flux transitions a FluxStream track was resolved from, or `None`
+    /// for other tracks.
+    pub fn flux(&self) -> Option<&FluxRevolution> {
+        match self {
+            TrackData::FluxStream {
+                revolutions, resolved, ..
+            } => revolutions.get(*resolved),
+            _ => None,
+        }
+    }
+
+    /// Return every captured revolution of flux transitions of a FluxStream track. Other tracks
+    /// return an empty slice.
+    pub fn revolutions(&self) -> &[FluxRevolution] {
+        match self {
+            TrackData::FluxStream { revolutions, .. } => revolutions,
+            _ => &[],
+        }
+    }
+
+    /// Return the flux transitions of the track over one revolution. FluxStream tracks return
+    /// their captured flux. For BitStream tracks a transition is placed at each set bitcell, with
+    /// bitcells timed by the track's data rate.
+    pub(crate) fn flux_revolution(&self) -> Result<FluxRevolution, DiskImageError> {
+        match self {
+            TrackData::FluxStream { .. } => self.flux().cloned().ok_or(DiskImageError::DataError),
+            TrackData::BitStream { data, data_rate, .. } => {
+                // Indexing a stream returns decoded bits, so walk the raw bitcells.
+                let mut intervals = Vec::new();
+                let mut last = 0;
+                for i in data.bits().iter().enumerate().filter_map(|(i, bit)| bit.then_some(i)) {
+                    intervals.push((i + 1 - last) as u32);
+                    last = i + 1;
+                }
+                Ok(FluxRevolution::new(
+                    intervals,
+                    cell_time_of(*data_rate),
+                    data.len() as u64,
+                ))
+            }
+            TrackData::ByteStream { .. } => Err(DiskImageError::UnsupportedFormat),
+        }
+    }
+
+    /// Return the bytes of memory allocated for the track's data and metadata.
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let source = |source: &Option<TrackSource>| source.as_ref().map_or(0, |s| s.data.capacity());
+        match self {
+            TrackData::BitStream {
+                data,
+                metadata,
+                source: track_source,
+                ..
+            }
+            | TrackData::FluxStream {
+                data,
+                metadata,
+                source: track_source,
+                ..
+            } => {
+                let (bitstream, clock_map, weak_mask) = data.memory_usage();
+                MemoryUsage {
+                    bitstream,
+                    clock_map,
+                    weak_mask,
+                    metadata: metadata.items.capacity() * size_of::<DiskStructureMetadataItem>(),
+                    source: source(track_source),
+                    flux: self
+                        .revolutions()
+                        .iter()
+                        .map(|f| f.intervals.capacity() * size_of::<u32>())
+                        .sum(),
+                    ..Default::default()
+                }
+            }
+            TrackData::ByteStream {
+                sectors,
+                data,
+                weak_mask,
+                source: track_source,
+                ..
+            } => MemoryUsage {
+                bytestream: data.capacity(),
+                weak_mask: weak_mask.capacity(),
+                metadata: sectors.capacity() * size_of::<TrackSectorIndex>(),
+                source: source(track_source),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Set the physical cylinder of the track, when the image's track map is reinterpreted.
+    pub(crate) fn set_cylinder(&mut self, c: u16) {
+        match self {
+            TrackData::BitStream { cylinder, .. }
+            | TrackData::FluxStream { cylinder, .. }
+            | TrackData::ByteStream { cylinder, .. } => *cylinder = c,
+        }
+    }
+
+    pub fn encoding(&self) -> DiskDataEncoding {
+        match self {
+            TrackData::BitStream { encoding, .. } | TrackData::FluxStream { encoding, .. } => *encoding,
+            TrackData::ByteStream { encoding, .. } => *encoding,
+        }
+    }
+
+    pub fn data_rate(&self) -> DiskDataRate {
+        match self {
+            TrackData::BitStream { data_rate, .. } | TrackData::FluxStream { data_rate, .. } => *data_rate,
+            TrackData::ByteStream { data_rate, .. } => *data_rate,
+        }
+    }
+
+    /// Return the rotation rate of the drive the track was captured in, judged from the index
+    /// period of its flux. `None` for tracks without flux, or with a nonstandard index period.
+    pub fn capture_rpm(&self) -> Option<DiskRpm> {
+        self.flux().and_then(|flux| flux.rpm())
+    }
+
+    /// Return the data rate the track would be read at in a 300 RPM drive. This differs from
+    /// [`TrackData::data_rate`] for a track captured at 300Kbps in a 360 RPM drive, which is a
+    /// 250Kbps double density track, typically a 360K disk written in a 1.2M drive.
+    pub fn nominal_data_rate(&self) -> DiskDataRate {
+        match (self.data_rate(), self.capture_rpm()) {
+            (DiskDataRate::Rate300Kbps, Some(DiskRpm::Rpm360)) => DiskDataRate::Rate250Kbps,
+            (data_rate, _) => data_rate,
+        }
+    }
+
+    /// Return the length of the track in bitcells. ByteStream tracks do not store bitcells, so
+    /// the length is estimated from the length of the track data.
+    pub fn bitcell_ct(&self) -> usize {
+        match self {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => data.len(),
+            TrackData::ByteStream { data, .. } => data.len() * MFM_BYTE_LEN,
+        }
+    }
+
+    /// Return the length of the track in bitcells as loaded from the source image, if the track
+    /// was loaded from a BitStream image.
+    pub fn source_bitcell_ct(&self) -> Option<usize> {
+        match self {
+            TrackData::BitStream { source_bitcell_ct, .. } | TrackData::FluxStream { source_bitcell_ct, .. } => {
+                *source_bitcell_ct
+            }
+            TrackData::ByteStream { .. } => None,
+        }
+    }
+
+    /// Log a warning if the track has been rewritten at a different length than it was loaded
+    /// at. Exporters write tracks at their current length, so this is the only notice that an
+    /// original long or short track will not be preserved.
+    pub(crate) fn warn_length_changed(&self, exporter: &str) {
+        if let Some(source_ct) = self.source_bitcell_ct() {
+            if source_ct != self.bitcell_ct() {
+                log::warn!(
+                    "{}: Track {} was loaded with {} bitcells, but is now {} bitcells long.",
+                    exporter,
+                    self.ch(),
+                    source_ct,
+                    self.bitcell_ct()
+                );
+            }
+        }
+    }
+
+    /// Return the length of the track in decoded bytes.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => data.len() / MFM_BYTE_LEN,
+            TrackData::ByteStream { data, .. } => data.len(),
+        }
+    }
+
+    /// Return the undecoded track payload and its offset in the source image file, if it was
+    /// retained by the loader.
+    pub fn source_bytes(&self) -> Option<&TrackSource> {
+        match self {
+            TrackData::BitStream { source, .. } | TrackData::FluxStream { source, .. } => source.as_ref(),
+            TrackData::ByteStream { source, .. } => source.as_ref(),
+        }
+    }
+
+    pub(crate) fn set_source(&mut self, new_source: TrackSource) {
+        match self {
+            TrackData::BitStream { source, .. } | TrackData::FluxStream { source, .. } => *source = Some(new_source),
+            TrackData::ByteStream { source, .. } => *source = Some(new_source),
+        }
+    }
+
+    pub(crate) fn clear_source(&mut self) {
+        match self {
+            TrackData::BitStream { source, .. } | TrackData::FluxStream { source, .. } => *source = None,
+            TrackData::ByteStream { source, .. } => *source = None,
+        }
+    }
+
     pub(crate) fn metadata(&self) -> Option<&DiskStructureMetadata> {
         match self {
-            TrackData::BitStream { metadata, .. } => Some(metadata),
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => Some(metadata),
             TrackData::ByteStream { .. } => None,
         }
     }
@@ -101,7 +629,7 @@ impl TrackData {
     pub(crate) fn get_sector_ct(&self) -> usize {
         match self {
             TrackData::ByteStream { sectors, .. } => sectors.len(),
-            TrackData::BitStream { metadata, .. } => {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
                 let mut sector_ct = 0;
                 for item in &metadata.items {
                     if item.elem_type.is_sector() {
@@ -122,10 +650,10 @@ impl TrackData {
                     }
                 }
             }
-            TrackData::BitStream { metadata, .. } => {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
                 for item in &metadata.items {
-                    if let DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _)) =
-                        item.elem_type
+                    if let DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _))
+                    | DiskStructureElement::Amiga(AmigaElement::SectorHeader(..)) = item.elem_type
                     {
                         if let Some(chsn) = item.chsn {
                             if chsn.s() == id {
@@ -148,25 +676,35 @@ impl TrackData {
                     address_crc_valid: !s.address_crc_error,
                     data_crc_valid: !s.data_crc_error,
                     deleted_mark: s.deleted_mark,
+                    no_dam: s.no_dam,
+                    position: s.position,
+                    read_time: s.read_time,
                 })
                 .collect(),
-            TrackData::BitStream { metadata, .. } => {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
                 let mut sector_list = Vec::new();
                 for item in &metadata.items {
-                    if let DiskStructureElement::System34(System34Element::Data {
-                        address_crc,
-                        data_crc,
-                        deleted,
-                    }) = item.elem_type
-                    {
-                        if let Some(chsn) = item.chsn {
-                            sector_list.push(SectorMapEntry {
-                                chsn,
-                                address_crc_valid: address_crc,
-                                data_crc_valid: data_crc,
-                                deleted_mark: deleted,
-                            });
+                    let (address_crc, data_crc, deleted) = match item.elem_type {
+                        DiskStructureElement::System34(System34Element::Data {
+                            address_crc,
+                            data_crc,
+                            deleted,
+                        }) => (address_crc, data_crc, deleted),
+                        DiskStructureElement::Amiga(AmigaElement::Data { address_crc, data_crc }) => {
+                            (address_crc, data_crc, false)
                         }
+                        _ => continue,
+                    };
+                    if let Some(chsn) = item.chsn {
+                        sector_list.push(SectorMapEntry {
+                            chsn,
+                            address_crc_valid: address_crc,
+                            data_crc_valid: data_crc,
+                            deleted_mark: deleted,
+                            no_dam: false,
+                            position: None,
+                            read_time: None,
+                        });
                     }
                 }
                 sector_list
@@ -176,12 +714,11 @@ impl TrackData {
 
     pub(crate) fn read_exact_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), DiskImageError> {
         match self {
-            TrackData::BitStream { data, .. } => match data {
-                TrackDataStream::Mfm(mfm_decoder) => {
-                    mfm_decoder
-                        .seek(SeekFrom::Start((offset >> 1) as u64))
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => match data {
+                TrackDataStream::Mfm(_) | TrackDataStream::Fm(_) => {
+                    data.seek(SeekFrom::Start((offset >> 1) as u64))
                         .map_err(|_| DiskImageError::SeekError)?;
-                    mfm_decoder.read_exact(buf).map_err(|_| DiskImageError::IoError)?;
+                    data.read_exact(buf).ok_or(DiskImageError::IoError)?;
                 }
                 _ => {
                     return Err(DiskImageError::UnsupportedFormat);
@@ -208,7 +745,7 @@ impl TrackData {
     /// This function does not panic.
     pub(crate) fn get_first_sector_at_bit_index(&self, bit_index: usize) -> Option<TrackDataIndexResult> {
         match self {
-            TrackData::BitStream { metadata, .. } => {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
                 let mut last_idam_matched = false;
                 let mut idam_chsn: Option<DiskChsn> = None;
                 for mdi in &metadata.items {
@@ -265,6 +802,7 @@ impl TrackData {
     /// # Arguments
     /// - `seek_chs` - The CHS address of the sector to find.
     /// - `n` - The sector size to match. If `None`, the sector size is not checked.
+    /// - `policy` - The [`MatchPolicy`] determining which fields of the sector ID are compared.
     ///
     /// # Returns
     /// - `Some(TrackDataIndexResult)` if the first sector is found, containing the start index,
@@ -277,9 +815,11 @@ impl TrackData {
         &self,
         seek_chs: DiskChs,
         n: Option<u8>,
+        policy: MatchPolicy,
     ) -> Option<(usize, DiskChsn, bool, bool, bool)> {
+        let resolution = self.resolution();
         match self {
-            TrackData::BitStream { metadata, .. } => {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
                 let mut last_idam_matched = false;
                 let mut idam_chsn: Option<DiskChsn> = None;
                 for mdi in &metadata.items {
@@ -290,8 +830,7 @@ impl TrackData {
                             ..
                         } => {
                             if let Some(metadata_chsn) = chsn {
-                                if DiskChs::from(*metadata_chsn) == seek_chs && (n.is_none() || metadata_chsn.n() == n?)
-                                {
+                                if policy.matches(*metadata_chsn, seek_chs, n, resolution) {
                                     last_idam_matched = true;
                                 }
                             }
@@ -316,6 +855,21 @@ impl TrackData {
                                 return Some((mdi.start, idam_chsn.unwrap(), *address_crc, *data_crc, *deleted));
                             }
                         }
+                        DiskStructureMetadataItem {
+                            elem_type: DiskStructureElement::Amiga(AmigaElement::SectorHeader(chsn, _)),
+                            ..
+                        } => {
+                            last_idam_matched = policy.matches(*chsn, seek_chs, n, resolution);
+                            idam_chsn = Some(*chsn);
+                        }
+                        DiskStructureMetadataItem {
+                            elem_type: DiskStructureElement::Amiga(AmigaElement::Data { address_crc, data_crc }),
+                            ..
+                        } => {
+                            if last_idam_matched {
+                                return Some((mdi.start, idam_chsn.unwrap(), *address_crc, *data_crc, false));
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -326,6 +880,86 @@ impl TrackData {
         None
     }
 
+    /// Calculate the rotational wait and transfer time of a read of the sector matching `chs`,
+    /// starting from the bitcell index `from_position`. The wait ends at the sector's IDAM and
+    /// the transfer ends after its data CRC. If several sectors match, the one that reaches the
+    /// head first is chosen.
+    ///
+    /// ByteStream tracks have no physical layout, so positions are estimated from each sector's
+    /// offset within the track data, ignoring gaps and address marks.
+    pub(crate) fn sector_read_time(
+        &self,
+        chs: DiskChs,
+        n: Option<u8>,
+        policy: MatchPolicy,
+        from_position: usize,
+    ) -> Option<SectorReadTime> {
+        let resolution = self.resolution();
+        // Collect the start of the IDAM and end of the data CRC of each matching sector.
+        let mut candidates = Vec::new();
+        match self {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => {
+                let mut matched_idam: Option<usize> = None;
+                for mdi in &metadata.items {
+                    match mdi.elem_type {
+                        DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _)) => {
+                            matched_idam = match mdi.chsn {
+                                Some(chsn) if policy.matches(chsn, chs, n, resolution) => Some(mdi.start),
+                                _ => None,
+                            };
+                        }
+                        DiskStructureElement::System34(System34Element::Data { .. }) => {
+                            if let Some(idam_start) = matched_idam.take() {
+                                candidates.push((idam_start, mdi.end + 2 * MFM_BYTE_LEN));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            TrackData::ByteStream { sectors, .. } => {
+                for si in sectors
+                    .iter()
+                    .filter(|si| policy.matches(si.chsn(), chs, n, resolution))
+                {
+                    candidates.push((si.t_idx * MFM_BYTE_LEN, (si.t_idx + si.len + 2) * MFM_BYTE_LEN));
+                }
+            }
+        }
+
+        let track_len = self.bitcell_ct();
+        if track_len == 0 {
+            return None;
+        }
+        let from_position = from_position % track_len;
+
+        candidates
+            .iter()
+            .map(|&(start, end)| {
+                let wait_bitcells = (start + track_len - from_position) % track_len;
+                SectorReadTime {
+                    wait_bitcells,
+                    transfer_bitcells: end - start,
+                    end_position: end % track_len,
+                    data_rate: self.data_rate(),
+                }
+            })
+            .min_by_key(|t| t.wait_bitcells)
+    }
+
+    /// Return the variable bit timing recorded for the first sector matching `chs`, if any. Only
+    /// ByteStream tracks loaded from images that record sector timing carry it.
+    pub(crate) fn sector_timing(&self, chs: DiskChs, n: Option<u8>, policy: MatchPolicy) -> Option<&[u16]> {
+        let resolution = self.resolution();
+        match self {
+            TrackData::ByteStream { sectors, .. } => sectors
+                .iter()
+                .find(|si| policy.matches(si.chsn(), chs, n, resolution))
+                .and_then(|si| si.timing.as_deref()),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => None,
+        }
+    }
+
     /// Read the sector data from the sector identified by 'chs'. The data is returned within a
     /// ReadSectorResult struct which also sets some convenience metadata flags where are needed
     /// when handling ByteStream images.
@@ -337,27 +971,123 @@ impl TrackData {
         chs: DiskChs,
         n: Option<u8>,
         scope: RwSectorScope,
+        policy: MatchPolicy,
+        debug: bool,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        let mut read_vec = Vec::new();
+        let mut rsr = self.read_sector_into(chs, n, scope, policy, debug, &mut read_vec)?;
+        rsr.read_buf = read_vec;
+        Ok(rsr)
+    }
+
+    /// Return true if the track was parsed as an Amiga trackdisk track, rather than as IBM System
+    /// 34 sectors.
+    pub(crate) fn is_amiga(&self) -> bool {
+        self.metadata().is_some_and(|metadata| {
+            metadata
+                .items
+                .iter()
+                .any(|i| matches!(i.elem_type, DiskStructureElement::Amiga(_)))
+        })
+    }
+
+    /// Decode the Amiga sector found at `bit_index` into `buf`. Amiga sectors have no address
+    /// marks, so the scope of the read is always the sector data alone.
+    fn read_amiga_sector_into(
+        &self,
+        chs: DiskChs,
+        bit_index: Option<(usize, DiskChsn, bool, bool, bool)>,
+        debug: bool,
+        buf: &mut Vec<u8>,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        let (TrackData::BitStream {
+            data: TrackDataStream::Mfm(codec),
+            ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(codec),
+            ..
+        }) = self
+        else {
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        let Some((data_idx, chsn, address_crc_valid, data_crc_valid, _)) = bit_index else {
+            log::warn!("Amiga sector {} not found reading sector!", chs);
+            return Err(DiskImageError::DataError);
+        };
+
+        let mut rsr = ReadSectorResult {
+            data_idx: 0,
+            data_len: 0,
+            read_buf: Vec::new(),
+            deleted_mark: false,
+            control_mark: false,
+            no_dam: false,
+            weak_mask: None,
+            not_found: false,
+            address_crc_error: !address_crc_valid,
+            data_crc_error: !data_crc_valid,
+            wrong_cylinder: chsn.c() != chs.c(),
+            wrong_head: chsn.h() != chs.h(),
+            overread_len: 0,
+        };
+        // As with IBM sectors, data is not returned for a bad header unless debugging.
+        if rsr.address_crc_error && !debug {
+            rsr.data_crc_error = false;
+            return Ok(rsr);
+        }
+
+        buf.extend(AmigaParser::decode_sector_data(codec.bits(), data_idx));
+        rsr.data_len = buf.len();
+        Ok(rsr)
+    }
+
+    /// Read the sector data from the sector identified by 'chs' into `buf`, as
+    /// [`TrackData::read_sector`] does. `buf` is cleared and resized to the length of the read,
+    /// reusing its allocation, and the `read_buf` of the returned ReadSectorResult is left empty.
+    pub(crate) fn read_sector_into(
+        &mut self,
+        chs: DiskChs,
+        n: Option<u8>,
+        scope: RwSectorScope,
+        policy: MatchPolicy,
         debug: bool,
+        buf: &mut Vec<u8>,
     ) -> Result<ReadSectorResult, DiskImageError> {
+        let resolution = self.resolution();
         let data_idx;
         let mut data_len;
 
-        let mut read_vec = Vec::new();
+        buf.clear();
 
         let mut data_crc_error = false;
         let mut address_crc_error = false;
         let mut deleted_mark = false;
+        let mut no_dam = false;
         let mut wrong_cylinder = false;
+        let mut wrong_head = false;
 
         // Read index first to avoid borrowing issues in next match.
         let bit_index = match self {
-            TrackData::BitStream { .. } => self.get_sector_bit_index(chs, n),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => self.get_sector_bit_index(chs, n, policy),
             TrackData::ByteStream { .. } => None,
         };
 
+        if self.is_amiga() {
+            return self.read_amiga_sector_into(chs, bit_index, debug, buf);
+        }
+
         match self {
             TrackData::BitStream {
-                data: TrackDataStream::Mfm(mfm_decoder),
+                data: stream @ (TrackDataStream::Mfm(_) | TrackDataStream::Fm(_)),
+                metadata,
+                crc,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: stream @ (TrackDataStream::Mfm(_) | TrackDataStream::Fm(_)),
+                metadata,
+                crc,
                 ..
             } => {
                 let (sector_offset, chsn, address_crc_valid, data_crc_valid, deleted) = match bit_index {
@@ -367,6 +1097,8 @@ impl TrackData {
                         return Err(DiskImageError::DataError);
                     }
                 };
+                wrong_cylinder = chsn.c() != chs.c();
+                wrong_head = chsn.h() != chs.h();
                 address_crc_error = !address_crc_valid;
                 // If there's a bad address mark, we not proceed to read the data, unless we're requesting
                 // it anyway for debugging purposes.
@@ -376,11 +1108,15 @@ impl TrackData {
                         data_len: 0,
                         read_buf: Vec::new(),
                         deleted_mark: false,
+                        control_mark: false,
+                        no_dam: false,
+                        weak_mask: None,
                         not_found: false,
                         address_crc_error: true,
                         data_crc_error: false,
                         wrong_cylinder,
-                        wrong_head: false,
+                        wrong_head,
+                        overread_len: 0,
                     });
                 }
 
@@ -417,24 +1153,36 @@ impl TrackData {
                     }
                 } else {
                     data_len = chsn.n_size();
+                    // A protection scheme may record a sector header with a bogus N. Rather than
+                    // read through the following sectors, size the read by what was recorded.
+                    if let Some((fit_len, fit_crc_valid)) =
+                        TrackData::fit_data_len(stream, metadata, crc, sector_offset, data_len)
+                    {
+                        log::debug!(
+                            "read_sector(): Sector {} with N of {} overruns the next sector, reading {} bytes",
+                            chsn,
+                            chsn.n(),
+                            fit_len
+                        );
+                        data_len = fit_len;
+                        data_crc_error = !fit_crc_valid;
+                    }
                 }
                 data_idx = scope_data_off;
 
-                read_vec = vec![0u8; data_len + scope_data_adj];
+                buf.resize(data_len + scope_data_adj, 0);
 
                 log::trace!(
                     "read_sector(): Found sector_id: {} at offset: {} read length: {}",
                     chs.s(),
                     sector_offset,
-                    read_vec.len()
+                    buf.len()
                 );
 
-                mfm_decoder
+                stream
                     .seek(SeekFrom::Start(((sector_offset >> 1) + scope_read_off) as u64))
                     .map_err(|_| DiskImageError::SeekError)?;
-                mfm_decoder
-                    .read_exact(&mut read_vec)
-                    .map_err(|_| DiskImageError::IoError)?;
+                stream.read_exact(buf).ok_or(DiskImageError::IoError)?;
             }
             TrackData::ByteStream { sectors, data, .. } => {
                 // No address mark for ByteStream data, so data starts immediately.
@@ -447,8 +1195,9 @@ impl TrackData {
                     RwSectorScope::DataOnly => {}
                 };
 
+                let mut sector_found = false;
                 for si in sectors {
-                    if si.sector_id == chs.s() {
+                    if policy.matches(si.chsn(), chs, n, resolution) {
                         log::trace!(
                             "read_sector(): Found sector_id: {} at t_idx: {}",
                             si.sector_id,
@@ -456,20 +1205,21 @@ impl TrackData {
                         );
 
                         data_len = std::cmp::min(si.t_idx + si.len, data.len()) - si.t_idx;
-                        read_vec.extend(data[si.t_idx..si.t_idx + data_len].to_vec());
+                        buf.extend_from_slice(&data[si.t_idx..si.t_idx + data_len]);
+
+                        data_crc_error = si.data_crc_error;
+                        deleted_mark = si.deleted_mark;
+                        no_dam = si.no_dam;
+                        wrong_cylinder = si.cylinder_id != chs.c();
+                        wrong_head = si.head_id != chs.h();
+                        sector_found = true;
+                        break;
+                    }
+                }
 
-                        if si.data_crc_error {
-                            data_crc_error = true;
-                        }
-
-                        if si.cylinder_id != chs.c() {
-                            wrong_cylinder = true;
-                        }
-
-                        if si.deleted_mark {
-                            deleted_mark = true;
-                        }
-                    }
+                if !sector_found {
+                    log::warn!("read_sector(): Sector ID not found reading sector!");
+                    return Err(DiskImageError::DataError);
                 }
             }
             _ => {
@@ -477,42 +1227,223 @@ impl TrackData {
             }
         }
 
+        let weak_mask = self.sector_weak_mask(chs, n, policy);
+
         Ok(ReadSectorResult {
             data_idx,
             data_len,
-            read_buf: read_vec,
+            read_buf: Vec::new(),
             deleted_mark,
+            // A Read Data operation encountering a deleted data mark sets Control Mark.
+            control_mark: deleted_mark,
+            no_dam,
+            weak_mask,
             not_found: false,
             address_crc_error,
             data_crc_error,
             wrong_cylinder,
-            wrong_head: false,
+            wrong_head,
+            overread_len: 0,
         })
     }
 
+    /// Read `read_len` bytes from the start of the data field of the sector identified by `chs`,
+    /// continuing past the data CRC into the gap if `read_len` exceeds the recorded data length.
+    pub(crate) fn read_sector_overread(
+        &mut self,
+        chs: DiskChs,
+        read_len: usize,
+        policy: MatchPolicy,
+    ) -> Result<ReadSectorResult, DiskImageError> {
+        // Match the sector by ID alone. The size code in the header is what's being overridden.
+        let policy = match policy.resolve(self.resolution()) {
+            MatchPolicy::Chsn => MatchPolicy::Chs,
+            MatchPolicy::IgnoreHead => MatchPolicy::SectorOnly,
+            policy => policy,
+        };
+
+        if !matches!(
+            self,
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(_),
+                ..
+            } | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(_),
+                ..
+            }
+        ) {
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        let mut rsr = self.read_sector(chs, None, RwSectorScope::DataOnly, policy, false)?;
+        if rsr.address_crc_error || read_len <= rsr.data_len {
+            rsr.read_buf.truncate(read_len);
+            if let Some(mask) = rsr.weak_mask.as_mut() {
+                mask.truncate(read_len);
+            }
+            return Ok(rsr);
+        }
+
+        let (sector_offset, ..) = self
+            .get_sector_bit_index(chs, None, policy)
+            .ok_or(DiskImageError::DataError)?;
+        let (TrackData::BitStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            ..
+        }) = self
+        else {
+            unreachable!();
+        };
+
+        // Skip the 4-byte data address mark, and stop at the end of the track.
+        let data_start = sector_offset + 4 * MFM_BYTE_LEN;
+        let available = mfm_codec.len().saturating_sub(data_start) / MFM_BYTE_LEN;
+        if read_len > available {
+            log::warn!(
+                "read_sector_overread(): Read of sector {} truncated to {} bytes at end of track",
+                chs,
+                available
+            );
+        }
+        let read_len = std::cmp::max(std::cmp::min(read_len, available), rsr.data_len);
+
+        let mut read_vec = vec![0u8; read_len];
+        mfm_codec
+            .seek(SeekFrom::Start((data_start >> 1) as u64))
+            .map_err(|_| DiskImageError::SeekError)?;
+        mfm_codec
+            .read_exact(&mut read_vec)
+            .map_err(|_| DiskImageError::IoError)?;
+
+        // A data bit is weak if either its clock or data bitcell is weak.
+        let weak_mask = mfm_codec.get_weak_mask();
+        let mut mask = vec![0u8; read_len];
+        for (i, byte) in mask.iter_mut().enumerate() {
+            for bit in 0..8 {
+                let cell = data_start + (i * 8 + bit) * 2;
+                if weak_mask.get(cell).unwrap_or(false) || weak_mask.get(cell + 1).unwrap_or(false) {
+                    *byte |= 0x80 >> bit;
+                }
+            }
+        }
+
+        rsr.overread_len = read_len - rsr.data_len;
+        rsr.read_buf = read_vec;
+        rsr.weak_mask = mask.iter().any(|b| *b != 0).then_some(mask);
+        Ok(rsr)
+    }
+
+    /// Check whether a data field of `data_len` bytes starting at the data address mark at
+    /// `dam_offset` would run past the next ID address mark or the end of the track. If so, return
+    /// the largest standard sector size that fits, preferring one with a valid CRC, and whether
+    /// its CRC is valid. Return `None` if the data field fits.
+    fn fit_data_len(
+        stream: &mut TrackDataStream,
+        metadata: &DiskStructureMetadata,
+        crc: &System34CrcParams,
+        dam_offset: usize,
+        data_len: usize,
+    ) -> Option<(usize, bool)> {
+        let limit = metadata
+            .items
+            .iter()
+            .filter(|item| {
+                matches!(
+                    item.elem_type,
+                    DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _))
+                ) && item.start > dam_offset
+            })
+            .map(|item| item.start)
+            .min()
+            .unwrap_or(stream.len());
+
+        // The bytes available for the address mark, data and CRC.
+        let available = limit.saturating_sub(dam_offset) / MFM_BYTE_LEN;
+        if 4 + data_len + 2 <= available {
+            return None;
+        }
+
+        let mut best = None;
+        for n in (0..=7u8).rev() {
+            let size = DiskChsn::n_to_bytes(n);
+            if size >= data_len || 4 + size + 2 > available {
+                continue;
+            }
+            let mut buf = vec![0u8; 4 + size + 2];
+            if stream.seek(SeekFrom::Start((dam_offset >> 1) as u64)).is_err() || stream.read_exact(&mut buf).is_none()
+            {
+                continue;
+            }
+            let recorded_crc = u16::from_be_bytes([buf[4 + size], buf[4 + size + 1]]);
+            if crc.crc(System34Parser::crc_mark(stream, &buf[0..4]), &buf[4..4 + size]) == recorded_crc {
+                return Some((size, true));
+            }
+            best.get_or_insert((size, false));
+        }
+        best
+    }
+
+    /// Write sector data to the sector identified by 'chs'.
+    ///
+    /// If `pad_byte` is provided, the write is performed in debug mode with relaxed size checks:
+    /// the size of the data block written is determined by `n` if provided, otherwise by the
+    /// length of `write_data`, regardless of the value of N in the sector header. A write buffer
+    /// shorter than the requested size is padded with `pad_byte`. This allows reproducing
+    /// mastering quirks where a sector's data overflows into the following gap and sector, or
+    /// underfills its header size. A valid CRC is written after the data block either way.
+    ///
+    /// The sector is marked deleted if `write_deleted` is set, and marked normal otherwise,
+    /// replacing its data address mark as needed.
     pub(crate) fn write_sector(
         &mut self,
         chs: DiskChs,
         n: Option<u8>,
         write_data: &[u8],
         _scope: RwSectorScope,
+        policy: MatchPolicy,
         write_deleted: bool,
         debug: bool,
+        pad_byte: Option<u8>,
     ) -> Result<WriteSectorResult, DiskImageError> {
+        let resolution = self.resolution();
         let data_len;
         let address_crc_error;
         let mut wrong_cylinder = false;
         let mut wrong_head = false;
+        let mut needs_rescan = false;
+
+        // In a sized debug write, 'n' specifies the size of the write, not the sector to match.
+        let match_n = match pad_byte {
+            Some(_) => None,
+            None => n,
+        };
+
+        if self.is_amiga() {
+            log::error!("write_sector(): Writing sectors of Amiga tracks is not supported.");
+            return Err(DiskImageError::UnsupportedFormat);
+        }
 
         // Read index first to avoid borrowing issues in next match.
         let bit_index = match self {
-            TrackData::BitStream { .. } => self.get_sector_bit_index(chs, n),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => {
+                self.get_sector_bit_index(chs, match_n, policy)
+            }
             TrackData::ByteStream { .. } => None,
         };
 
         match self {
             TrackData::BitStream {
                 data: TrackDataStream::Mfm(mfm_codec),
+                crc: crc_params,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                crc: crc_params,
                 ..
             } => {
                 let (sector_offset, chsn, address_crc_valid, data_crc_valid, deleted) = match bit_index {
@@ -536,17 +1467,23 @@ impl TrackData {
                     });
                 }
 
-                let mark_bytes = match deleted {
+                // Like a Write Data or Write Deleted Data command, rewrite the data address mark
+                // to match the type of data written.
+                let mark_bytes = match write_deleted {
                     true => DDAM_MARKER_BYTES,
                     false => DAM_MARKER_BYTES,
                 };
 
                 if write_deleted != deleted {
-                    log::warn!(
-                        "write_sector(): Deleted mark mismatch, expected: {} got: {}. Changing sector data type not implemented",
-                        write_deleted,
-                        deleted
+                    log::debug!(
+                        "write_sector(): Changing data address mark of sector {} to {}",
+                        chsn,
+                        if write_deleted { "DDAM" } else { "DAM" }
                     );
+                    mfm_codec
+                        .write_encoded_buf(&mark_bytes, sector_offset, MfmEncodingType::AddressMark)
+                        .map_err(|_| DiskImageError::IoError)?;
+                    needs_rescan = true;
                 }
 
                 // Normally we write the contents of the sector determined by N in the sector header.
@@ -555,7 +1492,25 @@ impl TrackData {
                 // header value.
                 // If 'debug' is false, 'n' must be matched or the write operation will fail as
                 // sector id not found.
-                if let Some(n_value) = n {
+                if let Some(pad_byte) = pad_byte {
+                    // Sized debug write. The data block may be larger or smaller than the header
+                    // specifies.
+                    data_len = match n {
+                        Some(n_value) => DiskChsn::n_to_bytes(n_value),
+                        None => write_data.len(),
+                    };
+                    if data_len != chsn.n_size() {
+                        log::debug!(
+                            "write_sector(): Writing {} bytes to sector of size {}, padding with {:02X}",
+                            data_len,
+                            chsn.n_size(),
+                            pad_byte
+                        );
+                    }
+                    // The CRC may have moved, and the write may have overwritten following sectors,
+                    // so the track metadata must be rebuilt.
+                    needs_rescan = true;
+                } else if let Some(n_value) = n {
                     if debug {
                         // Try to use provided n, but limit to the size of the write buffer.
                         data_len = std::cmp::min(write_data.len(), DiskChsn::n_to_bytes(n_value));
@@ -591,6 +1546,15 @@ impl TrackData {
                     data_len = chsn.n_size();
                 }
 
+                // A sector that previously had a bad CRC will now have a good one.
+                if !data_crc_valid {
+                    needs_rescan = true;
+                }
+
+                // Truncate or pad the write buffer to the size of the data block.
+                let mut write_vec = write_data[0..std::cmp::min(data_len, write_data.len())].to_vec();
+                write_vec.resize(data_len, pad_byte.unwrap_or(0));
+
                 mfm_codec
                     .seek(SeekFrom::Start(((sector_offset >> 1) + 32) as u64))
                     .map_err(|_| DiskImageError::SeekError)?;
@@ -603,37 +1567,48 @@ impl TrackData {
                 );
 
                 mfm_codec
-                    .write_buf(&write_data[0..data_len], sector_offset + 4 * MFM_BYTE_LEN)
+                    .write_buf(&write_vec, sector_offset + 4 * MFM_BYTE_LEN)
                     .map_err(|_| DiskImageError::IoError)?;
 
                 // Calculate the CRC of the data address mark + data.
-                let mut crc = crc_ccitt(&mark_bytes, None);
-                crc = crc_ccitt(&write_data[0..data_len], Some(crc));
+                let crc = crc_params.crc(&mark_bytes, &write_vec);
 
                 // Write the CRC after the data.
                 mfm_codec
                     .write_buf(&crc.to_be_bytes(), sector_offset + (4 + data_len) * MFM_BYTE_LEN)
                     .map_err(|_| DiskImageError::IoError)?;
-
-                return Ok(WriteSectorResult {
-                    not_found: false,
-                    address_crc_error: false,
-                    wrong_cylinder,
-                    wrong_head,
-                });
             }
             TrackData::ByteStream { sectors, data, .. } => {
                 for si in sectors {
-                    let mut sector_match;
+                    if policy.matches(si.chsn(), chs, match_n, resolution) {
+                        if si.cylinder_id != chs.c() {
+                            wrong_cylinder = true;
+                        }
 
-                    sector_match = si.sector_id == chs.s();
+                        if si.head_id != chs.h() {
+                            wrong_head = true;
+                        }
 
-                    // Validate n too if provided.
-                    if let Some(n) = n {
-                        sector_match = sector_match && si.n == n;
-                    }
+                        if let Some(pad_byte) = pad_byte {
+                            // Sized debug write. There is no CRC or gap to overflow into in a
+                            // ByteStream track, so the write may spill into the following sector's
+                            // data up to the end of the track.
+                            let write_len = match n {
+                                Some(n_value) => DiskChsn::n_to_bytes(n_value),
+                                None => write_data.len(),
+                            };
+                            let mut write_vec = write_data[0..std::cmp::min(write_len, write_data.len())].to_vec();
+                            write_vec.resize(write_len, pad_byte);
+
+                            let end = std::cmp::min(si.t_idx + write_len, data.len());
+                            data[si.t_idx..end].copy_from_slice(&write_vec[0..end - si.t_idx]);
+
+                            // Reading the sector by its header size would not find a valid CRC.
+                            si.data_crc_error = write_len != si.len;
+                            si.deleted_mark = write_deleted;
+                            break;
+                        }
 
-                    if sector_match {
                         // Validate provided data size.
                         let write_data_len = write_data.len();
                         if DiskChsn::n_to_bytes(si.n) != write_data_len {
@@ -646,15 +1621,8 @@ impl TrackData {
                             return Err(DiskImageError::ParameterError);
                         }
 
-                        if si.cylinder_id != chs.c() {
-                            wrong_cylinder = true;
-                        }
-
-                        if si.head_id != chs.h() {
-                            wrong_head = true;
-                        }
-
                         data[si.t_idx..si.t_idx + write_data_len].copy_from_slice(write_data);
+                        si.deleted_mark = write_deleted;
                         break;
                     }
                 }
@@ -664,6 +1632,10 @@ impl TrackData {
             }
         }
 
+        if needs_rescan {
+            self.rescan()?;
+        }
+
         Ok(WriteSectorResult {
             not_found: false,
             address_crc_error: false,
@@ -675,7 +1647,7 @@ impl TrackData {
     pub fn get_hash(&self) -> Digest {
         let mut hasher = sha1_smol::Sha1::new();
         match self {
-            TrackData::BitStream { data, .. } => {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => {
                 hasher.update(&data.data());
                 hasher.digest()
             }
@@ -694,7 +1666,7 @@ impl TrackData {
     /// This function is intended for use in implementing the Read Track FDC command.
     pub fn read_all_sectors(&mut self, ch: DiskCh, n: u8, eot: u8) -> Result<ReadTrackResult, DiskImageError> {
         match self {
-            TrackData::BitStream { .. } => self.read_all_sectors_bitstream(ch, n, eot),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => self.read_all_sectors_bitstream(ch, n, eot),
             TrackData::ByteStream { .. } => self.read_all_sectors_bytestream(ch, n, eot),
         }
     }
@@ -849,57 +1821,68 @@ impl TrackData {
 
     pub(crate) fn read_track(&mut self, ch: DiskCh) -> Result<ReadTrackResult, DiskImageError> {
         match self {
-            TrackData::BitStream { .. } => self.read_track_bitstream(ch),
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => self.read_track_bitstream(ch),
             TrackData::ByteStream { .. } => self.read_track_bytestream(ch),
         }
     }
 
+    /// Return the ID of the sector following the first sector with a sector ID matching `chs`,
+    /// in the order the sectors would pass under the head. If the matching sector is the last on
+    /// the track, the first sector is returned.
+    ///
+    /// If a track contains duplicate sector IDs, only the first occurrence is considered. Use
+    /// [`TrackData::get_next_id_at`] to walk through all sector IDs on a track by position.
     pub(crate) fn get_next_id(&self, chs: DiskChs) -> Option<DiskChsn> {
-        match self {
-            TrackData::BitStream { sector_ids, .. } => {
-                if sector_ids.is_empty() {
-                    log::warn!("get_next_id(): No sector_id vector for track!");
-                }
-                let first_sector = *sector_ids.first()?;
-                let mut sector_matched = false;
-                for sid in sector_ids {
-                    if sector_matched {
-                        return Some(*sid);
-                    }
-                    if sid.s() == chs.s() {
-                        // Have matching sector id
-                        sector_matched = true;
-                    }
-                }
-                // If we reached here, we matched the last sector in the list, so return the first
-                // sector as we wrap around the track.
-                if sector_matched {
-                    Some(first_sector)
-                } else {
-                    log::warn!("get_next_id(): Sector not found: {:?}", chs);
-                    None
-                }
+        let sector_ids = self.get_sector_id_positions();
+        if sector_ids.is_empty() {
+            log::warn!("get_next_id(): No sector IDs found for track!");
+            return None;
+        }
+
+        match sector_ids.iter().find(|(chsn, _)| chsn.s() == chs.s()) {
+            Some((_, bit_index)) => self.get_next_id_at(*bit_index + 1).map(|(chsn, _)| chsn),
+            None => {
+                log::warn!("get_next_id(): Sector not found: {:?}", chs);
+                None
             }
-            TrackData::ByteStream { sectors, .. } => {
-                let first_sector = sectors.first()?;
-                let mut sector_matched = false;
-                for si in sectors.iter() {
-                    if sector_matched {
-                        return Some(DiskChsn::new(chs.c(), chs.h(), si.sector_id, si.n));
-                    }
-                    if si.sector_id == chs.s() {
-                        // Have matching sector id
-                        sector_matched = true;
+        }
+    }
+
+    /// Return the ID of the first sector with a valid sector header starting at or after the
+    /// bitcell index `bit_index`, along with the bitcell index of its header. If no sector header
+    /// is found before the end of the track, the search wraps around to the start of the track.
+    ///
+    /// ByteStream tracks have no physical layout, so the index returned is an estimate derived from
+    /// the sector's offset within the track data.
+    pub(crate) fn get_next_id_at(&self, bit_index: usize) -> Option<(DiskChsn, usize)> {
+        let sector_ids = self.get_sector_id_positions();
+
+        sector_ids
+            .iter()
+            .find(|(_, idx)| *idx >= bit_index)
+            .or(sector_ids.first())
+            .copied()
+    }
+
+    /// Return a list of the sector IDs with valid sector headers on the track along with the
+    /// bitcell index of each header, in order of position.
+    fn get_sector_id_positions(&self) -> Vec<(DiskChsn, usize)> {
+        match self {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => metadata
+                .items
+                .iter()
+                .filter_map(|item| match item.elem_type {
+                    DiskStructureElement::System34(System34Element::SectorHeader(chsn, true)) => {
+                        Some((chsn, item.start))
                     }
-                }
-                // If we reached here, we matched the last sector in the list, so return the first
-                // sector as we wrap around the track.
-                if sector_matched {
-                    Some(DiskChsn::new(chs.c(), chs.h(), first_sector.sector_id, first_sector.n))
-                } else {
-                    None
-                }
-            }
+                    _ => None,
+                })
+                .collect(),
+            TrackData::ByteStream { sectors, .. } => sectors
+                .iter()
+                .filter(|si| !si.address_crc_error)
+                .map(|si| (si.chsn(), si.t_idx * MFM_BYTE_LEN))
+                .collect(),
         }
     }
 
@@ -907,6 +1890,10 @@ impl TrackData {
         if let TrackData::BitStream {
             data: TrackDataStream::Mfm(mfm_decoder),
             ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(mfm_decoder),
+            ..
         } = self
         {
             let data_size = mfm_decoder.len() / 16 + if mfm_decoder.len() % 16 > 0 { 1 } else { 0 };
@@ -936,9 +1923,188 @@ impl TrackData {
         Err(DiskImageError::UnsupportedFormat)
     }
 
+    /// Read the first ID field, valid or not, starting at or after the bitcell index `bit_index`,
+    /// wrapping around the track if necessary. This implements the Read Address command of the
+    /// WD177x/WD179x and the Read ID command of the µPD765; the raw CRC bytes are returned so
+    /// that the WD177x behavior can be reproduced.
+    ///
+    /// ByteStream tracks store no CRC, so one is calculated. A sector with an address CRC error
+    /// receives an inverted CRC.
+    pub(crate) fn read_address(&mut self, bit_index: usize) -> Result<ReadAddressResult, DiskImageError> {
+        let headers: Vec<(DiskChsn, bool, usize)> = match self {
+            TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. } => metadata
+                .items
+                .iter()
+                .filter_map(|item| match item.elem_type {
+                    DiskStructureElement::System34(System34Element::SectorHeader(chsn, valid)) => {
+                        Some((chsn, valid, item.start))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            TrackData::ByteStream { sectors, .. } => sectors
+                .iter()
+                .map(|si| (si.chsn(), !si.address_crc_error, si.t_idx * MFM_BYTE_LEN))
+                .collect(),
+        };
+
+        let (chsn, valid, header_index) = headers
+            .iter()
+            .find(|(_, _, idx)| *idx >= bit_index)
+            .or(headers.first())
+            .copied()
+            .ok_or(DiskImageError::DataError)?;
+
+        let mut id_field = [0u8; 6];
+        match self {
+            TrackData::BitStream { .. } | TrackData::FluxStream { .. } => {
+                self.read_exact_at(header_index + 4 * MFM_BYTE_LEN, &mut id_field)?;
+            }
+            TrackData::ByteStream { .. } => {
+                let id_bytes = [chsn.c() as u8, chsn.h(), chsn.s(), chsn.n()];
+                let mut crc = crc_ccitt(&IDAM_MARKER_BYTES, None);
+                crc = crc_ccitt(&id_bytes, Some(crc));
+                if !valid {
+                    crc = !crc;
+                }
+                id_field[0..4].copy_from_slice(&id_bytes);
+                id_field[4..6].copy_from_slice(&crc.to_be_bytes());
+            }
+        }
+
+        Ok(ReadAddressResult {
+            chsn,
+            id_field,
+            address_crc_error: !valid,
+            bit_index: header_index,
+        })
+    }
+
+    /// Read the entire track from the index as a WD177x/WD179x Read Track command would. Unlike
+    /// [`TrackData::read_track`], the byte framing is only resynchronized when an 0xA1 sync mark
+    /// is encountered, so gaps and write splices decode as the same garbage a real controller
+    /// returns. For GCR-encoded BitStream tracks, the disk nibbles are returned as read by the disk
+    /// controller's latch. Only supported for MFM and GCR-encoded BitStream tracks.
+    pub(crate) fn read_track_raw(&self) -> Result<Vec<u8>, DiskImageError> {
+        if let TrackData::BitStream {
+            data: TrackDataStream::Gcr(gcr_codec),
+            ..
+        } = self
+        {
+            return Ok(gcr_codec.read_nibbles());
+        }
+
+        let stream = match self {
+            TrackData::BitStream {
+                data: stream @ TrackDataStream::Mfm(_),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: stream @ TrackDataStream::Mfm(_),
+                ..
+            } => stream,
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        let mut track_bytes = Vec::with_capacity(stream.len() / MFM_BYTE_LEN + 1);
+        let mut shift_reg: u16 = 0;
+        let mut bit_ct = 0;
+
+        // Indexing a codec returns decoded data bits, so work from the raw bitcells.
+        let raw_bytes = stream.data();
+        for i in 0..stream.len() {
+            let bit = (raw_bytes[i >> 3] >> (7 - (i & 0x07))) & 0x01;
+            shift_reg = (shift_reg << 1) | bit as u16;
+            bit_ct += 1;
+
+            if shift_reg == MFM_A1_SYNC {
+                track_bytes.push(0xA1);
+                bit_ct = 0;
+            } else if bit_ct == MFM_BYTE_LEN {
+                // Data bits are in the odd bitcells of each 16 bitcell window.
+                let byte = (0..8).fold(0u8, |acc, b| (acc << 1) | ((shift_reg >> (14 - b * 2)) & 0x01) as u8);
+                track_bytes.push(byte);
+                bit_ct = 0;
+            }
+        }
+
+        Ok(track_bytes)
+    }
+
+    /// Replace the track with the contents of `buf`, interpreted as the data stream of a
+    /// WD177x/WD179x Write Track command in MFM mode:
+    ///
+    /// * 0xF5 writes an 0xA1 sync mark with a missing clock bit and presets the CRC.
+    /// * 0xF6 writes an 0xC2 index sync mark with a missing clock bit.
+    /// * 0xF7 writes the two CRC bytes.
+    /// * All other values are written as data and included in the CRC.
+    ///
+    /// Writing starts at the index and stops at the next index, keeping the track's length.
+    /// Bytes past the end of the track are discarded. If `buf` is too short to fill the track,
+    /// the rest of the track is written with zeros and `lost_data` is set in the result.
+    /// Only supported for MFM-encoded BitStream tracks.
+    pub(crate) fn write_track(&mut self, buf: &[u8]) -> Result<WriteTrackResult, DiskImageError> {
+        let data = match self {
+            TrackData::BitStream {
+                data: data @ TrackDataStream::Mfm(_),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: data @ TrackDataStream::Mfm(_),
+                ..
+            } => data,
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        let bitcell_ct = data.len();
+        let mut new_bit_vec = BitVec::with_capacity(bitcell_ct + MFM_BYTE_LEN * 2);
+        let mut crc = 0xFFFF;
+        let mut bytes_written = 0;
+
+        let encode = |bits: &mut BitVec, bytes: &[u8], encoding_type: MfmEncodingType| {
+            let prev_bit = bits.get(bits.len().wrapping_sub(1)).unwrap_or(false);
+            bits.extend(MfmCodec::encode_mfm(bytes, prev_bit, encoding_type));
+        };
+
+        for &byte in buf {
+            if new_bit_vec.len() >= bitcell_ct {
+                break;
+            }
+            match byte {
+                0xF5 => {
+                    // The CRC is preset as if two sync marks preceded this one, so that a run of
+                    // three 0xF5 bytes produces the CRC of a standard address mark.
+                    crc = crc_ccitt(&[0xA1, 0xA1, 0xA1], None);
+                    encode(&mut new_bit_vec, &[0xA1], MfmEncodingType::AddressMark);
+                }
+                0xF6 => encode(&mut new_bit_vec, &[0xC2], MfmEncodingType::AddressMark),
+                0xF7 => encode(&mut new_bit_vec, &crc.to_be_bytes(), MfmEncodingType::Data),
+                _ => {
+                    crc = crc_ccitt(&[byte], Some(crc));
+                    encode(&mut new_bit_vec, &[byte], MfmEncodingType::Data);
+                }
+            }
+            bytes_written += 1;
+        }
+
+        let lost_data = new_bit_vec.len() < bitcell_ct;
+        while new_bit_vec.len() < bitcell_ct {
+            encode(&mut new_bit_vec, &[0x00], MfmEncodingType::Data);
+        }
+        new_bit_vec.truncate(bitcell_ct);
+
+        data.replace(new_bit_vec);
+        self.rescan()?;
+
+        Ok(WriteTrackResult {
+            bytes_written,
+            lost_data,
+        })
+    }
+
     pub(crate) fn has_weak_bits(&self) -> bool {
         match self {
-            TrackData::BitStream { data, .. } => {
+            TrackData::BitStream { data, .. } | TrackData::FluxStream { data, .. } => {
                 if let TrackDataStream::Mfm(mfm_decoder) = data {
                     mfm_decoder.has_weak_bits()
                 } else {
@@ -949,6 +2115,175 @@ impl TrackData {
         }
     }
 
+    /// Return true if any bit of the data of the sector identified by `chs` is marked as weak.
+    pub(crate) fn sector_has_weak_bits(&self, chs: DiskChs, n: Option<u8>, policy: MatchPolicy) -> bool {
+        let resolution = self.resolution();
+        match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } => match self.get_sector_bit_index(chs, n, policy) {
+                Some((sector_offset, chsn, ..)) => {
+                    // Skip the 4-byte data address mark.
+                    let data_start = sector_offset + 4 * MFM_BYTE_LEN;
+                    let data_end = data_start + chsn.n_size() * MFM_BYTE_LEN;
+                    let weak_mask = mfm_codec.get_weak_mask();
+                    (data_start..data_end).any(|i| weak_mask.get(i).unwrap_or(false))
+                }
+                None => false,
+            },
+            TrackData::ByteStream { sectors, weak_mask, .. } => sectors
+                .iter()
+                .find(|si| policy.matches(si.chsn(), chs, n, resolution))
+                .map(|si| {
+                    let end = std::cmp::min(si.t_idx + si.len, weak_mask.len());
+                    weak_mask.get(si.t_idx..end).is_some_and(|w| w.iter().any(|&x| x != 0))
+                })
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Return the weak bit mask of the data of the sector identified by `chs`, or None if the
+    /// sector has no weak bits. Each bit of the mask corresponds to a decoded data bit.
+    pub(crate) fn sector_weak_mask(&self, chs: DiskChs, n: Option<u8>, policy: MatchPolicy) -> Option<Vec<u8>> {
+        let resolution = self.resolution();
+        if !self.sector_has_weak_bits(chs, n, policy) {
+            return None;
+        }
+        match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } => {
+                let (sector_offset, chsn, ..) = self.get_sector_bit_index(chs, n, policy)?;
+                // Skip the 4-byte data address mark.
+                let data_start = sector_offset + 4 * MFM_BYTE_LEN;
+                let weak_mask = mfm_codec.get_weak_mask();
+                let mut mask = vec![0u8; chsn.n_size()];
+                for (i, byte) in mask.iter_mut().enumerate() {
+                    for bit in 0..8 {
+                        // A data bit is weak if either its clock or data bitcell is weak.
+                        let cell = data_start + (i * 8 + bit) * 2;
+                        if weak_mask.get(cell).unwrap_or(false) || weak_mask.get(cell + 1).unwrap_or(false) {
+                            *byte |= 0x80 >> bit;
+                        }
+                    }
+                }
+                Some(mask)
+            }
+            TrackData::ByteStream { sectors, weak_mask, .. } => sectors
+                .iter()
+                .find(|si| policy.matches(si.chsn(), chs, n, resolution))
+                .and_then(|si| {
+                    let end = std::cmp::min(si.t_idx + si.len, weak_mask.len());
+                    weak_mask.get(si.t_idx..end).map(|w| w.to_vec())
+                }),
+            _ => None,
+        }
+    }
+
+    /// Return the MFM bitcells of the track.
+    ///
+    /// A BitStream track returns its bitcells as-is. A ByteStream track has no gaps or address
+    /// marks, so it is laid out on a track of `bitcell_ct` bitcells as by the FDC Format Track
+    /// command with the requested `gap3`, then each sector's data is written in. ID and data field
+    /// CRCs are calculated with `crc`, and deliberately corrupted for sectors flagged with a CRC
+    /// error so that the errors survive re-encoding.
+    pub(crate) fn to_mfm_bits(
+        &self,
+        bitcell_ct: usize,
+        gap3: usize,
+        crc: &System34CrcParams,
+    ) -> Result<BitVec, DiskImageError> {
+        match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } => Ok(mfm_codec.bits().clone()),
+            TrackData::ByteStream {
+                encoding: DiskDataEncoding::Mfm,
+                sectors,
+                data,
+                ..
+            } => {
+                let format_buffer = sectors.iter().map(|si| si.chsn()).collect();
+                let format_result = System34Parser::format_track_as_bytes(
+                    System34Standard::Ibm,
+                    bitcell_ct,
+                    format_buffer,
+                    0,
+                    gap3,
+                    crc,
+                )?;
+                let mut track_bytes = format_result.track_bytes;
+                let mut markers = format_result.markers;
+
+                let idam_offsets = markers
+                    .iter()
+                    .filter(|(marker, _)| matches!(marker, System34Marker::Idam))
+                    .map(|(_, offset)| *offset)
+                    .collect::<Vec<_>>();
+                let mut dam_markers = markers
+                    .iter_mut()
+                    .filter(|(marker, _)| matches!(marker, System34Marker::Dam))
+                    .collect::<Vec<_>>();
+
+                for ((si, idam_offset), dam_marker) in sectors.iter().zip(idam_offsets).zip(dam_markers.iter_mut()) {
+                    if si.address_crc_error {
+                        let crc_offset = idam_offset + IDAM_MARKER_BYTES.len() + 4;
+                        track_bytes[crc_offset] ^= 0xFF;
+                    }
+
+                    let dam_offset = dam_marker.1;
+                    if si.deleted_mark {
+                        dam_marker.0 = System34Marker::Ddam;
+                        track_bytes[dam_offset..dam_offset + DDAM_MARKER_BYTES.len()]
+                            .copy_from_slice(&DDAM_MARKER_BYTES);
+                    }
+
+                    let data_offset = dam_offset + DAM_MARKER_BYTES.len();
+                    let size = si.chsn().n_size();
+                    let end = std::cmp::min(si.t_idx + std::cmp::min(si.len, size), data.len());
+                    if si.t_idx < end {
+                        track_bytes[data_offset..data_offset + end - si.t_idx].copy_from_slice(&data[si.t_idx..end]);
+                    }
+
+                    let mut data_crc = crc.crc(
+                        &track_bytes[dam_offset..data_offset],
+                        &track_bytes[data_offset..data_offset + size],
+                    );
+                    if si.data_crc_error {
+                        data_crc = !data_crc;
+                    }
+                    track_bytes[data_offset + size..data_offset + size + 2].copy_from_slice(&data_crc.to_be_bytes());
+                }
+
+                let mut bits = MfmCodec::encode_mfm(&track_bytes, false, MfmEncodingType::Data);
+                bits.truncate(bitcell_ct);
+                let mut mfm_codec = MfmCodec::new(bits, None, None);
+                System34Parser::set_track_markers(&mut mfm_codec, markers)?;
+                Ok(mfm_codec.bits().clone())
+            }
+            _ => Err(DiskImageError::UnsupportedFormat),
+        }
+    }
+
+    /// Format the track with a sector for each ID in `format_buffer`, in order, filled with
+    /// `fill_byte`. As with the FDC Format Track command, IDs are written exactly as given: they
+    /// may repeat, skip numbers, vary in size, or name a different cylinder and head.
     pub(crate) fn format(
         &mut self,
         standard: System34Standard,
@@ -957,23 +2292,26 @@ impl TrackData {
         gap3: usize,
     ) -> Result<(), DiskImageError> {
         match self {
-            TrackData::BitStream {
-                data,
-                metadata,
-                sector_ids,
-                ..
-            } => {
+            TrackData::BitStream { data, crc, .. } | TrackData::FluxStream { data, crc, .. } => {
                 let bitcell_ct = data.len();
-                log::error!("Formatting track with {} bitcells", bitcell_ct);
-                let new_bit_vec;
+                log::trace!("format(): Formatting track with {} bitcells", bitcell_ct);
+                let mut new_bit_vec;
 
                 if let TrackDataStream::Mfm(mfm_codec) = data {
-                    let format_result =
-                        System34Parser::format_track_as_bytes(standard, bitcell_ct, format_buffer, fill_byte, gap3)?;
+                    let format_result = System34Parser::format_track_as_bytes(
+                        standard,
+                        bitcell_ct,
+                        format_buffer,
+                        fill_byte,
+                        gap3,
+                        crc,
+                    )?;
 
                     new_bit_vec = MfmCodec::encode_mfm(&format_result.track_bytes, false, MfmEncodingType::Data);
-                    log::error!(
-                        "New bitstream size: {} from {} bytes",
+                    // The formatted track is rounded up to a whole byte. Keep the original length.
+                    new_bit_vec.truncate(bitcell_ct);
+                    log::trace!(
+                        "format(): New bitstream size: {} from {} bytes",
                         new_bit_vec.len(),
                         format_result.track_bytes.len()
                     );
@@ -984,33 +2322,691 @@ impl TrackData {
                     return Err(DiskImageError::UnsupportedFormat);
                 }
 
-                // Scan the new track data for markers and create a clock map.
+                self.rescan()
+            }
+            TrackData::ByteStream {
+                cylinder,
+                head,
+                sectors,
+                data,
+                weak_mask,
+                ..
+            } => {
+                // A ByteStream track has no gaps or address marks, so only the sector IDs and
+                // their data are recorded. The IDs are taken as given, as the FDC would write them.
+                log::trace!(
+                    "format(): Formatting ByteStream track {} with {} sectors",
+                    DiskCh::new(*cylinder, *head),
+                    format_buffer.len()
+                );
+                sectors.clear();
+                data.clear();
+                weak_mask.clear();
+                for chsn in format_buffer {
+                    sectors.push(TrackSectorIndex {
+                        sector_id: chsn.s(),
+                        cylinder_id: chsn.c(),
+                        head_id: chsn.h(),
+                        t_idx: data.len(),
+                        n: chsn.n(),
+                        len: chsn.n_size(),
+                        address_crc_error: false,
+                        data_crc_error: false,
+                        deleted_mark: false,
+                        no_dam: false,
+                        position: None,
+                        read_time: None,
+                        timing: None,
+                    });
+                    data.resize(data.len() + chsn.n_size(), fill_byte);
+                    weak_mask.resize(weak_mask.len() + chsn.n_size(), 0);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Re-encode the track for the specified data rate and rotation rate.
+    ///
+    /// The length of the track in bitcells is recalculated from `new_rate` and `new_rpm`, and the
+    /// track is reformatted with the same sector IDs, data and deleted marks, in the same order.
+    /// A track with an index address mark keeps the IBM layout, and any other track is given the
+    /// ISO layout. GAP3 keeps its original length if the sectors fit, and is shortened otherwise.
+    /// ByteStream tracks have no bitcells, so only their data rate is updated. A FluxStream track
+    /// becomes a BitStream track, as its flux revolutions no longer match the new bitstream.
+    ///
+    /// As the bitstream is regenerated, weak bits, CRC errors and any data outside of sectors are
+    /// not preserved. Sectors with duplicate IDs will all receive the data of the first.
+    ///
+    /// # Returns
+    /// - `Err(DiskImageError::ParameterError)` if the sectors will not fit on the resampled track.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not MFM encoded, or holds
+    ///   structures other than System34 sectors, such as Amiga sectors.
+    pub fn resample(&mut self, new_rate: DiskDataRate, new_rpm: DiskRpm) -> Result<(), DiskImageError> {
+        if self.metadata().is_some_and(|metadata| {
+            metadata
+                .items
+                .iter()
+                .any(|i| !matches!(i.elem_type, DiskStructureElement::System34(_)))
+        }) {
+            log::error!("resample(): Track {} is not a System34 track.", self.ch());
+            return Err(DiskImageError::UnsupportedFormat);
+        }
+
+        let (format_buffer, gap3, standard) = match self {
+            TrackData::ByteStream { data_rate, .. } => {
+                *data_rate = new_rate;
+                return Ok(());
+            }
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(_),
+                metadata,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(_),
+                metadata,
+                ..
+            } => {
+                let mut format_buffer = Vec::new();
+                let mut gap3 = None;
+                let mut last_data_end = None;
+                let mut standard = System34Standard::Iso;
+
+                for item in &metadata.items {
+                    match item.elem_type {
+                        DiskStructureElement::System34(System34Element::Marker(System34Marker::Iam, _)) => {
+                            standard = System34Standard::Ibm;
+                        }
+                        DiskStructureElement::System34(System34Element::SectorHeader(..)) => {
+                            // Measure GAP3 from the end of the previous sector's data CRC to the
+                            // sync preceding this sector's IDAM, keeping the smallest gap seen.
+                            if let Some(data_end) = last_data_end {
+                                let gap =
+                                    (item.start.saturating_sub(data_end) / MFM_BYTE_LEN).saturating_sub(2 + SYNC_LEN);
+                                gap3 = Some(gap3.map_or(gap, |g: usize| g.min(gap)));
+                            }
+                        }
+                        DiskStructureElement::System34(System34Element::Data {
+                            address_crc,
+                            data_crc,
+                            deleted,
+                        }) => {
+                            if let Some(chsn) = item.chsn {
+                                if !address_crc || !data_crc {
+                                    log::warn!("resample(): CRC error in sector {} will not be preserved.", chsn);
+                                }
+                                format_buffer.push((chsn, deleted, item.start));
+                            }
+                            last_data_end = Some(item.end);
+                        }
+                        _ => {}
+                    }
+                }
+                (format_buffer, gap3.unwrap_or(IBM_GAP3_DEFAULT), standard)
+            }
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        if self.has_weak_bits() {
+            log::warn!("resample(): Weak bits on track {} will not be preserved.", self.ch());
+        }
+
+        // Read the data of each sector before the bitstream is replaced.
+        let mut sector_data = Vec::with_capacity(format_buffer.len());
+        for (chsn, _, start) in &format_buffer {
+            let mut buf = vec![0u8; chsn.n_size()];
+            // Skip the 4-byte data address mark.
+            self.read_exact_at(start + 4 * MFM_BYTE_LEN, &mut buf)?;
+            sector_data.push(buf);
+        }
+
+        let new_bitcell_ct = new_rpm.track_bitcells(new_rate);
+        let chsn_vec = format_buffer.iter().map(|(chsn, ..)| *chsn).collect::<Vec<_>>();
+        let new_gap3 = System34Parser::fit_gap3(standard, new_bitcell_ct, &chsn_vec, gap3).ok_or_else(|| {
+            log::error!(
+                "resample(): {} sectors will not fit on a track of {} bitcells.",
+                chsn_vec.len(),
+                new_bitcell_ct
+            );
+            DiskImageError::ParameterError
+        })?;
+
+        log::debug!(
+            "resample(): Resampling track {} to {} bitcells at {} {}, {:?} GAP3: {}",
+            self.ch(),
+            new_bitcell_ct,
+            new_rate,
+            new_rpm,
+            standard,
+            new_gap3
+        );
+
+        self.drop_flux();
+        if let TrackData::BitStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            data_rate,
+            data_clock,
+            ..
+        } = self
+        {
+            let rng = mfm_codec.rng().clone();
+            *mfm_codec = MfmCodec::new(BitVec::from_elem(new_bitcell_ct, false), None, None);
+            mfm_codec.set_rng(rng);
+            *data_rate = new_rate;
+            *data_clock = u32::from(new_rate);
+        }
+
+        self.format(standard, chsn_vec, 0x00, new_gap3)?;
+
+        for ((chsn, deleted, _), data) in format_buffer.iter().zip(sector_data) {
+            let chs = DiskChs::from(*chsn);
+            if *deleted {
+                // The formatter writes normal data address marks, so replace the DAM with a DDAM.
+                if let Some((dam_idx, ..)) = self.get_sector_bit_index(chs, Some(chsn.n()), MatchPolicy::Chsn) {
+                    self.write_encoded_buf(&DDAM_MARKER_BYTES, dam_idx, MfmEncodingType::AddressMark)?;
+                }
+            }
+            self.write_sector(
+                chs,
+                Some(chsn.n()),
+                &data,
+                RwSectorScope::DataOnly,
+                MatchPolicy::Chsn,
+                *deleted,
+                false,
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode `buf` and write it to the track bitstream at the raw bitcell index `offset`, then
+    /// re-scan the track so that any markers or sectors created by the write are recognized.
+    /// Only supported for MFM-encoded BitStream tracks.
+    pub(crate) fn write_encoded_buf(
+        &mut self,
+        buf: &[u8],
+        offset: usize,
+        encoding_type: MfmEncodingType,
+    ) -> Result<usize, DiskImageError> {
+        let bits_written = match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } => mfm_codec
+                .write_encoded_buf(buf, offset, encoding_type)
+                .map_err(|_| DiskImageError::ParameterError)?,
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        self.rescan()?;
+        Ok(bits_written)
+    }
+
+    /// Overwrite the bitcells of the track from index `start` with `bits`, wrapping around the
+    /// index as a write gate held open past it would. Written bitcells are no longer weak. The
+    /// track is then re-scanned, and the index where the write ended is recorded as its splice.
+    ///
+    /// # Returns
+    /// - The bitcell index of the splice.
+    /// - `Err(DiskImageError::UnsupportedFormat)` if the track is not a BitStream or FluxStream track.
+    /// - `Err(DiskImageError::ParameterError)` if the track is empty.
+    pub(crate) fn splice_bits(&mut self, start: usize, bits: &BitVec) -> Result<usize, DiskImageError> {
+        let (TrackData::BitStream { data, splice, .. } | TrackData::FluxStream { data, splice, .. }) = self else {
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+        let len = data.len();
+        if len == 0 {
+            return Err(DiskImageError::ParameterError);
+        }
+        if bits.is_empty() {
+            return Ok(start % len);
+        }
+
+        let mut new_bits = data.bits().clone();
+        let mut weak_mask = data.get_weak_mask().cloned();
+        for (i, bit) in bits.iter().enumerate() {
+            let index = (start + i) % len;
+            new_bits.set(index, bit);
+            if let Some(weak_mask) = weak_mask.as_mut() {
+                weak_mask.set(index, false);
+            }
+        }
+        data.replace(new_bits);
+        if let Some(weak_mask) = weak_mask {
+            _ = data.set_weak_mask(weak_mask);
+        }
+
+        let end = (start + bits.len()) % len;
+        *splice = Some(end);
+        self.rescan()?;
+        Ok(end)
+    }
+
+    /// Copy the raw bitcells and weak bit mask in `range` out of an MFM BitStream track.
+    /// Returns `None` if the track is not an MFM BitStream track or `range` is out of bounds.
+    pub(crate) fn copy_bits(&self, range: Range<usize>) -> Option<(BitVec, BitVec)> {
+        match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } if range.start <= range.end && range.end <= mfm_codec.len() => {
+                let bits = range.clone().map(|i| mfm_codec.bits()[i]).collect();
+                let weak = range.map(|i| mfm_codec.get_weak_mask()[i]).collect();
+                Some((bits, weak))
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the range of bitcells occupied by the sector matching `chs`, from the start of the
+    /// sync field preceding its IDAM to the end of its data CRC.
+    pub(crate) fn sector_bit_range(&self, chs: DiskChs, n: Option<u8>, policy: MatchPolicy) -> Option<Range<usize>> {
+        let resolution = self.resolution();
+        let (TrackData::BitStream { metadata, .. } | TrackData::FluxStream { metadata, .. }) = self else {
+            return None;
+        };
+
+        let mut idam_start = None;
+        for mdi in &metadata.items {
+            match mdi.elem_type {
+                DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _)) => {
+                    idam_start = mdi
+                        .chsn
+                        .filter(|id| policy.matches(*id, chs, n, resolution))
+                        .map(|_| mdi.start);
+                }
+                DiskStructureElement::System34(System34Element::Data { .. }) => {
+                    if let Some(start) = idam_start {
+                        return Some(start.saturating_sub(SYNC_LEN * MFM_BYTE_LEN)..mdi.end + 2 * MFM_BYTE_LEN);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Measure the lengths of the gaps and sync fields between the sectors of an MFM BitStream
+    /// track. Sync fields are measured by counting 0x00 bytes back from each address mark, and
+    /// gaps span the remaining bytes between fields.
+    pub(crate) fn measure_gaps(&self) -> Result<TrackGaps, DiskImageError> {
+        let (mfm_codec, metadata) = match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                ..
+            } => (mfm_codec, metadata),
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        let bits = mfm_codec.bits();
+        let sync_len = |mark: usize, limit: usize| TrackData::sync_len(bits, mark, limit);
+
+        // Collect each IDAM, with the start and end of the data field that follows it, if any.
+        let mut fields = Vec::new();
+        for mdi in &metadata.items {
+            match mdi.elem_type {
+                DiskStructureElement::System34(System34Element::Marker(System34Marker::Idam, _)) => {
+                    if let Some(chsn) = mdi.chsn {
+                        fields.push((chsn, mdi.start, None));
+                    }
+                }
+                DiskStructureElement::System34(System34Element::Data { .. }) => {
+                    if let Some((_, _, data_field @ None)) = fields.last_mut() {
+                        *data_field = Some((mdi.start, mdi.end + 2 * MFM_BYTE_LEN));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut sectors = Vec::with_capacity(fields.len());
+        let mut field_end = 0;
+        let mut gap1 = 0;
+        for (chsn, idam_start, data_field) in &fields {
+            let id_sync = sync_len(*idam_start, field_end);
+            let sync_start = idam_start - id_sync * MFM_BYTE_LEN;
+            match sectors.last_mut() {
+                None => gap1 = sync_start / MFM_BYTE_LEN,
+                Some(SectorGaps {
+                    gap2: Some(_), gap3, ..
+                }) => *gap3 = Some(sync_start.saturating_sub(field_end) / MFM_BYTE_LEN),
+                Some(_) => {}
+            }
+
+            // The ID field is the IDAM, CHSN and CRC.
+            let id_end = idam_start + 10 * MFM_BYTE_LEN;
+            let (gap2, data_sync) = match data_field {
+                Some((dam_start, data_end)) => {
+                    let data_sync = sync_len(*dam_start, id_end);
+                    field_end = *data_end;
+                    (
+                        Some((dam_start - data_sync * MFM_BYTE_LEN).saturating_sub(id_end) / MFM_BYTE_LEN),
+                        Some(data_sync),
+                    )
+                }
+                None => {
+                    field_end = id_end;
+                    (None, None)
+                }
+            };
+
+            sectors.push(SectorGaps {
+                chsn: *chsn,
+                id_sync,
+                gap2,
+                data_sync,
+                gap3: None,
+            });
+        }
+
+        Ok(TrackGaps {
+            ch: self.ch(),
+            gap1,
+            gap4b: bits.len().saturating_sub(field_end) / MFM_BYTE_LEN,
+            sectors,
+        })
+    }
+
+    /// Count the sync bytes preceding the address mark at `mark`, without passing `limit`.
+    /// The clock map is not reliable before the first marker on the track, so sync bytes are
+    /// matched by their encoding of alternating clock bits.
+    fn sync_len(bits: &BitVec, mark: usize, limit: usize) -> usize {
+        let mut len = 0;
+        while mark >= limit + (len + 1) * MFM_BYTE_LEN {
+            let start = mark - (len + 1) * MFM_BYTE_LEN;
+            if !(0..MFM_BYTE_LEN).all(|i| bits[start + i] == (i % 2 == 0)) {
+                break;
+            }
+            len += 1;
+        }
+        len
+    }
+
+    /// Locate the index address mark of an MFM BitStream track, and measure the gaps around it.
+    pub(crate) fn index_mark(&self) -> Result<IndexMark, DiskImageError> {
+        let gaps = self.measure_gaps()?;
+        let (mfm_codec, metadata) = match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                ..
+            } => (mfm_codec, metadata),
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        let mut index_mark = IndexMark {
+            ch: self.ch(),
+            bit_index: None,
+            gap4a: None,
+            sync: None,
+            gap1: None,
+            gap4b: gaps.gap4b,
+        };
+
+        let marker_start = |marker: System34Marker| {
+            metadata
+                .items
+                .iter()
+                .find(|mdi| {
+                    matches!(mdi.elem_type, DiskStructureElement::System34(System34Element::Marker(m, _)) if m == marker)
+                })
+                .map(|mdi| mdi.start)
+        };
+        let Some(iam_start) = marker_start(System34Marker::Iam) else {
+            return Ok(index_mark);
+        };
+
+        let sync = TrackData::sync_len(mfm_codec.bits(), iam_start, 0);
+        let iam_end = iam_start + 4 * MFM_BYTE_LEN;
+        index_mark.bit_index = Some(iam_start);
+        index_mark.sync = Some(sync);
+        index_mark.gap4a = Some((iam_start - sync * MFM_BYTE_LEN) / MFM_BYTE_LEN);
+        index_mark.gap1 = marker_start(System34Marker::Idam)
+            .zip(gaps.sectors.first())
+            .map(|(idam_start, sector)| {
+                (idam_start - sector.id_sync * MFM_BYTE_LEN).saturating_sub(iam_end) / MFM_BYTE_LEN
+            });
+        Ok(index_mark)
+    }
+
+    /// Lay out the fields of an MFM BitStream track in order, from its metadata. Sync fields are
+    /// measured as in [`TrackData::measure_gaps`], and anything between fields is a gap.
+    pub(crate) fn timeline(&self) -> Result<TrackTimeline, DiskImageError> {
+        let (mfm_codec, metadata, crc) = match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                crc,
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                metadata,
+                crc,
+                ..
+            } => (mfm_codec, metadata, crc),
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        };
+
+        let bits = mfm_codec.bits();
+        let mut spans = Vec::new();
+        let mut push = |element, start, end| spans.push(TimelineSpan { element, start, end });
+        let mut cursor = 0;
+
+        for mdi in &metadata.items {
+            let DiskStructureElement::System34(System34Element::Marker(marker, _)) = mdi.elem_type else {
+                continue;
+            };
+            let sync = TrackData::sync_len(bits, mdi.start, cursor);
+            let sync_start = mdi.start - sync * MFM_BYTE_LEN;
+            if sync_start > cursor {
+                push(TimelineElement::Gap, cursor, sync_start);
+            }
+            if sync > 0 {
+                push(TimelineElement::Sync, sync_start, mdi.start);
+            }
+            let marker_end = mdi.start + 4 * MFM_BYTE_LEN;
+            push(TimelineElement::Marker(marker), mdi.start, marker_end);
+            cursor = marker_end;
+
+            match marker {
+                System34Marker::Idam => {
+                    let chsn = mdi.chsn.unwrap_or_default();
+                    let id_end = marker_end + 4 * MFM_BYTE_LEN;
+                    let mut id_field = [0u8; 10];
+                    for (i, byte) in id_field.iter_mut().enumerate() {
+                        *byte = mfm_codec.read_decoded_byte(mdi.start + i * MFM_BYTE_LEN).unwrap_or(0);
+                    }
+                    let crc_valid =
+                        crc.crc(&id_field[0..4], &id_field[4..8]) == u16::from_be_bytes([id_field[8], id_field[9]]);
+                    push(TimelineElement::SectorId(chsn), marker_end, id_end);
+                    push(TimelineElement::Crc(crc_valid), id_end, id_end + 2 * MFM_BYTE_LEN);
+                    cursor = id_end + 2 * MFM_BYTE_LEN;
+                }
+                System34Marker::Dam | System34Marker::Ddam => {
+                    let data = metadata.items.iter().find_map(|item| match item.elem_type {
+                        DiskStructureElement::System34(System34Element::Data { data_crc, .. })
+                            if item.start == mdi.start =>
+                        {
+                            Some((item.end, data_crc, item.chsn.unwrap_or_default()))
+                        }
+                        _ => None,
+                    });
+                    // A data mark with no preceding ID has no data field.
+                    if let Some((data_end, data_crc, chsn)) = data {
+                        push(TimelineElement::Data(chsn), marker_end, data_end);
+                        push(TimelineElement::Crc(data_crc), data_end, data_end + 2 * MFM_BYTE_LEN);
+                        cursor = data_end + 2 * MFM_BYTE_LEN;
+                    }
+                }
+                System34Marker::Iam => {}
+            }
+        }
+        if cursor < bits.len() {
+            push(TimelineElement::Gap, cursor, bits.len());
+        }
+
+        Ok(TrackTimeline {
+            ch: self.ch(),
+            bitcell_ct: bits.len(),
+            spans,
+        })
+    }
+
+    /// Replace the bitstream of an MFM BitStream track with `bits`, keeping its encoding and data
+    /// rate, and regenerate the track metadata.
+    pub(crate) fn replace_bits(&mut self, bits: BitVec, weak: BitVec) -> Result<(), DiskImageError> {
+        match self {
+            TrackData::BitStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            }
+            | TrackData::FluxStream {
+                data: TrackDataStream::Mfm(mfm_codec),
+                ..
+            } => {
+                let rng = mfm_codec.rng().clone();
+                *mfm_codec = MfmCodec::new(bits, None, Some(weak));
+                mfm_codec.set_rng(rng);
+            }
+            _ => return Err(DiskImageError::UnsupportedFormat),
+        }
+        self.rescan()
+    }
+
+    /// Recompute the address and data CRCs of the sectors for which `select` returns true, and
+    /// rewrite those that do not match. The track is re-scanned first, so that sectors are found
+    /// at their current positions after the bitstream was edited.
+    pub(crate) fn fix_sector_crcs(&mut self, select: &dyn Fn(DiskChsn) -> bool) -> Result<Vec<CrcFix>, DiskImageError> {
+        self.rescan()?;
+
+        let (TrackData::BitStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            metadata,
+            crc: crc_params,
+            ..
+        }
+        | TrackData::FluxStream {
+            data: TrackDataStream::Mfm(mfm_codec),
+            metadata,
+            crc: crc_params,
+            ..
+        }) = self
+        else {
+            return Err(DiskImageError::UnsupportedFormat);
+        };
+
+        let read = |start: usize, len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|i| mfm_codec.read_decoded_byte(start + i * MFM_BYTE_LEN).unwrap_or(0))
+                .collect()
+        };
+
+        // Each fix, with the bit index of the CRC to rewrite.
+        let mut fixes = Vec::new();
+        for mdi in &metadata.items {
+            let DiskStructureElement::System34(element) = mdi.elem_type else {
+                continue;
+            };
+            let (field, chsn, len) = match element {
+                System34Element::Marker(System34Marker::Idam, _) => {
+                    let id = read(mdi.start + 4 * MFM_BYTE_LEN, 4);
+                    (CrcField::Address, DiskChsn::new(id[0] as u16, id[1], id[2], id[3]), 4)
+                }
+                System34Element::Data { .. } => {
+                    let len = (mdi.end - mdi.start) / MFM_BYTE_LEN - 4;
+                    (CrcField::Data, mdi.chsn.unwrap_or_default(), len)
+                }
+                _ => continue,
+            };
+            if !select(chsn) {
+                continue;
+            }
+
+            let bytes = read(mdi.start, 4 + len + 2);
+            let calculated = crc_params.crc(&bytes[0..4], &bytes[4..4 + len]);
+            let recorded = u16::from_be_bytes([bytes[4 + len], bytes[5 + len]]);
+            if calculated != recorded {
+                let crc_index = mdi.start + (4 + len) * MFM_BYTE_LEN;
+                fixes.push((
+                    crc_index,
+                    CrcFix {
+                        chsn,
+                        field,
+                        recorded,
+                        calculated,
+                    },
+                ));
+            }
+        }
+
+        for (crc_index, fix) in &fixes {
+            mfm_codec
+                .write_buf(&fix.calculated.to_be_bytes(), *crc_index)
+                .map_err(|_| DiskImageError::IoError)?;
+        }
+        if !fixes.is_empty() {
+            self.rescan()?;
+        }
+
+        Ok(fixes.into_iter().map(|(_, fix)| fix).collect())
+    }
+
+    /// Scan the track bitstream for markers, rebuild the clock map, and regenerate the track
+    /// metadata. This must be called whenever the bitstream is modified.
+    pub(crate) fn rescan(&mut self) -> Result<(), DiskImageError> {
+        match self {
+            TrackData::BitStream {
+                data, metadata, crc, ..
+            }
+            | TrackData::FluxStream {
+                data, metadata, crc, ..
+            } => {
                 let markers = System34Parser::scan_track_markers(data);
                 if markers.is_empty() {
-                    log::error!("TrackData::format(): No markers found in track data.");
+                    log::warn!("TrackData::rescan(): No markers found in track data.");
                 } else {
-                    log::error!("TrackData::format(): Found {} markers in track data.", markers.len());
+                    log::trace!("TrackData::rescan(): Found {} markers in track data.", markers.len());
+                }
+                if let Some(clock_map) = data.clock_map_mut() {
+                    System34Parser::create_clock_map(&markers, clock_map);
                 }
-                System34Parser::create_clock_map(&markers, data.clock_map_mut().unwrap());
-
-                let new_metadata = DiskStructureMetadata::new(System34Parser::scan_track_metadata(data, markers));
 
-                log::error!(
-                    "TrackData::format(): Found {} metadata items in track data.",
+                let mut new_metadata =
+                    DiskStructureMetadata::new(System34Parser::scan_track_metadata_crc(data, markers, crc));
+                AmigaParser::rescan_if_amiga(data, &mut new_metadata);
+                log::trace!(
+                    "TrackData::rescan(): Found {} metadata items in track data.",
                     new_metadata.items.len()
                 );
 
-                for item in &new_metadata.items {
-                    log::error!("TrackData::format(): Metadata item: {:?}", item);
-                }
-
-                let new_sector_ids = new_metadata.get_sector_ids();
-                if new_sector_ids.is_empty() {
-                    log::error!("TrackData::format(): No sectors ids found in track metadata.");
+                if new_metadata.get_sector_ids().is_empty() {
+                    log::warn!("TrackData::rescan(): No sectors ids found in track metadata.");
                 }
 
                 *metadata = new_metadata;
-                *sector_ids = new_sector_ids;
 
                 Ok(())
             }
@@ -70,14 +70,19 @@ pub(crate) fn read_ascii<T: Read>(source: &mut T, max_len: Option<usize>) -> (Op
 
 /// Helper function to calculate a CRC-CCITT 16-bit checksum over a byte slice.
 pub fn crc_ccitt(data: &[u8], start: Option<u16>) -> u16 {
-    const POLY: u16 = 0x1021; // Polynomial x^16 + x^12 + x^5 + 1
-    let mut crc: u16 = start.unwrap_or(0xFFFF);
+    crc16(data, 0x1021, start.unwrap_or(0xFFFF)) // Polynomial x^16 + x^12 + x^5 + 1
+}
+
+/// Calculate an MSB-first 16-bit CRC over a byte slice with the polynomial `poly`, continuing
+/// from the CRC value `start`.
+pub fn crc16(data: &[u8], poly: u16, start: u16) -> u16 {
+    let mut crc = start;
 
     for &byte in data {
         crc ^= (byte as u16) << 8;
         for _ in 0..8 {
             if (crc & 0x8000) != 0 {
-                crc = (crc << 1) ^ POLY;
+                crc = (crc << 1) ^ poly;
             } else {
                 crc <<= 1;
             }
@@ -86,6 +91,24 @@ pub fn crc_ccitt(data: &[u8], start: Option<u16>) -> u16 {
     crc
 }
 
+/// Calculate the standard (IEEE 802.3) reflected 32-bit CRC over a byte slice, as used by ZIP
+/// and ARJ archives.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            if (crc & 1) != 0 {
+                crc = (crc >> 1) ^ 0xEDB8_8320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
 pub fn crc_ccitt_byte(byte: u8, crc: u16) -> u16 {
     const POLY: u16 = 0x1021; // Polynomial x^16 + x^12 + x^5 + 1
     let mut crc = crc;
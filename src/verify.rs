@@ -0,0 +1,200 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+
+    src/verify.rs
+
+    Compares two independent captures of the same disk, sector by sector, to
+    establish whether a dump can be considered verified.
+*/
+
+use crate::diskimage::{MatchPolicy, RwSectorScope};
+use crate::trackdata::TrackData;
+use crate::{DiskCh, DiskChs, DiskChsn, DiskImage, DiskImageError};
+use std::collections::HashSet;
+
+/// How the two captures compared on a single sector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SectorAgreement {
+    /// Both captures read the sector with a good CRC and identical data.
+    Match,
+    /// Both captures read the sector with a good CRC, but the data differs.
+    Conflict,
+    /// Only the capture at index `good` read the sector with a good CRC.
+    OneGood { good: usize },
+    /// Neither capture read the sector with a good CRC. Identical data in both suggests a sector
+    /// that was deliberately written bad, rather than a read error.
+    BothBad { identical: bool },
+    /// The sector was only found in the capture at index `found`.
+    Missing { found: usize },
+}
+
+impl SectorAgreement {
+    /// Return true if the captures agree on the sector, including on a consistently bad sector.
+    pub fn agrees(&self) -> bool {
+        matches!(
+            self,
+            SectorAgreement::Match | SectorAgreement::BothBad { identical: true }
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectorVerification {
+    pub chsn: DiskChsn,
+    pub agreement: SectorAgreement,
+}
+
+/// The comparison of one physical track of the two captures.
+#[derive(Clone, Debug)]
+pub struct TrackVerification {
+    pub ch: DiskCh,
+    /// Whether the track is present in each capture.
+    pub present: [bool; 2],
+    pub sectors: Vec<SectorVerification>,
+}
+
+impl TrackVerification {
+    /// Return true if the track is present in both captures and they agree on every sector.
+    pub fn is_verified(&self) -> bool {
+        self.present == [true, true] && self.sectors.iter().all(|s| s.agreement.agrees())
+    }
+}
+
+/// A report of the agreement between two captures of a disk, built by [`DiskImage::verify_dump`].
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    pub tracks: Vec<TrackVerification>,
+}
+
+impl VerifyReport {
+    /// Return true if both captures hold the same tracks and agree on every sector. A dump should
+    /// only be marked verified if this is true.
+    pub fn is_verified(&self) -> bool {
+        !self.tracks.is_empty() && self.tracks.iter().all(|t| t.is_verified())
+    }
+
+    /// Return the total number of sectors compared.
+    pub fn sector_ct(&self) -> usize {
+        self.tracks.iter().map(|t| t.sectors.len()).sum()
+    }
+
+    /// Return the number of sectors the captures agree on.
+    pub fn agreed_ct(&self) -> usize {
+        self.tracks
+            .iter()
+            .flat_map(|t| &t.sectors)
+            .filter(|s| s.agreement.agrees())
+            .count()
+    }
+
+    /// Return the fraction of sectors the captures agree on, from 0.0 to 1.0. An empty report
+    /// has a confidence of 0.0.
+    pub fn confidence(&self) -> f64 {
+        match self.sector_ct() {
+            0 => 0.0,
+            ct => self.agreed_ct() as f64 / ct as f64,
+        }
+    }
+
+    /// Return an iterator over the sectors the captures do not agree on, with their tracks.
+    pub fn disagreements(&self) -> impl Iterator<Item = (DiskCh, &SectorVerification)> {
+        self.tracks
+            .iter()
+            .flat_map(|t| t.sectors.iter().map(move |s| (t.ch, s)))
+            .filter(|(_, s)| !s.agreement.agrees())
+    }
+}
+
+/// Compare the captures `first` and `second`. See [`DiskImage::verify_dump`].
+pub(crate) fn compare(first: &DiskImage, second: &DiskImage) -> Result<VerifyReport, DiskImageError> {
+    let mut report = VerifyReport::default();
+    for h in 0..2 {
+        let cylinder_ct = first.track_map[h].len().max(second.track_map[h].len());
+        for c in 0..cylinder_ct {
+            let ch = DiskCh::new(c as u16, h as u8);
+            // Sector reads seek the track, so read from copies of each track.
+            let mut tracks = [first.get_track_ch(ch).cloned(), second.get_track_ch(ch).cloned()];
+            report.tracks.push(compare_track(ch, &mut tracks));
+        }
+    }
+    Ok(report)
+}
+
+/// Compare every sector found on either of `tracks`, in the order they were found.
+fn compare_track(ch: DiskCh, tracks: &mut [Option<TrackData>; 2]) -> TrackVerification {
+    let present = [tracks[0].is_some(), tracks[1].is_some()];
+
+    let mut seen = HashSet::new();
+    let ids: Vec<DiskChsn> = tracks
+        .iter()
+        .flatten()
+        .flat_map(|track| track.get_sector_list())
+        .map(|sector| sector.chsn)
+        // Only the first of several sectors with the same ID can be read.
+        .filter(|chsn| seen.insert(*chsn))
+        .collect();
+
+    let mut sectors = Vec::with_capacity(ids.len());
+    for chsn in ids {
+        let copies = [
+            tracks[0].as_mut().and_then(|track| read_copy(track, chsn)),
+            tracks[1].as_mut().and_then(|track| read_copy(track, chsn)),
+        ];
+        let agreement = match copies {
+            [Some((data0, true)), Some((data1, true))] if data0 == data1 => SectorAgreement::Match,
+            [Some((_, true)), Some((_, true))] => SectorAgreement::Conflict,
+            [Some((_, true)), Some(_)] => SectorAgreement::OneGood { good: 0 },
+            [Some(_), Some((_, true))] => SectorAgreement::OneGood { good: 1 },
+            [Some((data0, _)), Some((data1, _))] => SectorAgreement::BothBad {
+                identical: data0 == data1,
+            },
+            [Some(_), None] => SectorAgreement::Missing { found: 0 },
+            [None, Some(_)] => SectorAgreement::Missing { found: 1 },
+            [None, None] => continue,
+        };
+        sectors.push(SectorVerification { chsn, agreement });
+    }
+
+    TrackVerification { ch, present, sectors }
+}
+
+/// Read the data of sector `chsn` from `track`, returning the data and whether it was read with
+/// good CRCs. A sector with a bad address CRC or no data field reads as bad and empty.
+fn read_copy(track: &mut TrackData, chsn: DiskChsn) -> Option<(Vec<u8>, bool)> {
+    let rsr = track
+        .read_sector(
+            DiskChs::from(chsn),
+            Some(chsn.n()),
+            RwSectorScope::DataOnly,
+            MatchPolicy::Chsn,
+            false,
+        )
+        .ok()?;
+    if rsr.address_crc_error || rsr.no_dam {
+        return Some((Vec::new(), false));
+    }
+    let data = rsr.read_buf.get(rsr.data_idx..rsr.data_idx + rsr.data_len)?.to_vec();
+    Some((data, !rsr.data_crc_error))
+}
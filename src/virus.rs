@@ -0,0 +1,111 @@
+/*
+    FluxFox
+    https://github.com/dbalsom/fluxfox
+
+    Copyright 2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    src/virus.rs
+
+    A small signature database and scanner for classic boot sector viruses,
+    which are frequently found on disks imaged from old collections.
+*/
+
+use crate::DiskChs;
+use std::fmt::{self, Display, Formatter};
+
+/// A boot sector virus, recognized when every one of its `patterns` occurs within a sector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VirusSignature {
+    pub name: &'static str,
+    pub patterns: &'static [&'static [u8]],
+}
+
+/// The signatures checked by [`scan_sector`]. Most are text the virus carries in its body, which
+/// also matches its common variants. Michelangelo carries no text, so it is matched by its check
+/// of the real time clock for its trigger date of March 6th.
+pub const SIGNATURES: &[VirusSignature] = &[
+    VirusSignature {
+        name: "Stoned",
+        patterns: &[b"Your PC is now Stoned!"],
+    },
+    VirusSignature {
+        name: "Stoned (Marijuana)",
+        patterns: &[b"LEGALISE MARIJUANA!"],
+    },
+    VirusSignature {
+        name: "Michelangelo",
+        // MOV AH,04h; INT 1Ah (read the RTC date) and CMP DX,0306h.
+        patterns: &[&[0xB4, 0x04, 0xCD, 0x1A], &[0x81, 0xFA, 0x06, 0x03]],
+    },
+    VirusSignature {
+        name: "Form",
+        patterns: &[b"The FORM-Virus sends greetings"],
+    },
+    VirusSignature {
+        name: "Brain",
+        patterns: &[b"Welcome to the Dungeon"],
+    },
+];
+
+/// A virus signature found in a sector, as returned by [`crate::DiskImage::scan_viruses`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VirusMatch {
+    pub signature: &'static VirusSignature,
+    /// The sector the signature was found in.
+    pub chs: DiskChs,
+    /// The byte offset within the sector of the first pattern of the signature.
+    pub offset: usize,
+}
+
+impl Display for VirusMatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} virus in sector {} at offset {:#X}",
+            self.signature.name, self.chs, self.offset
+        )
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Scan the data of the sector `chs` for the signatures in [`SIGNATURES`].
+pub fn scan_sector(chs: DiskChs, data: &[u8]) -> Vec<VirusMatch> {
+    SIGNATURES
+        .iter()
+        .filter_map(|signature| {
+            let offsets = signature
+                .patterns
+                .iter()
+                .map(|p| find(data, p))
+                .collect::<Option<Vec<_>>>()?;
+            Some(VirusMatch {
+                signature,
+                chs,
+                offset: offsets[0],
+            })
+        })
+        .collect()
+}
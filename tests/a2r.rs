@@ -0,0 +1,182 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{track_stream, TestImage};
+use fluxfox::{DiskCh, DiskChs, DiskDataRate, DiskImage, DiskImageFormat, ImageParser, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Capture clock ticks per bitcell of a 250Kbps MFM track at the A2R capture resolution of 125ns.
+const TICKS_PER_CELL: u32 = 16;
+const DRIVE_525_DS_40: u8 = 4;
+
+/// Convert the bitstream of the track at `ch` into the flux intervals of one ideal revolution,
+/// returning the intervals and the duration of the revolution in ticks.
+fn track_intervals(image: &DiskImage, ch: DiskCh) -> (Vec<u32>, u32) {
+    let stream = track_stream(image, ch).unwrap();
+    let bytes = stream.data();
+    let mut intervals = Vec::new();
+    let mut last = 0;
+    for i in 0..stream.len() {
+        if (bytes[i >> 3] >> (7 - (i & 0x07))) & 0x01 != 0 {
+            intervals.push((i + 1 - last) as u32 * TICKS_PER_CELL);
+            last = i + 1;
+        }
+    }
+    (intervals, stream.len() as u32 * TICKS_PER_CELL)
+}
+
+/// Return the intervals of a revolution captured following another, whose first interval
+/// includes the time from the last transition of the previous revolution to the index.
+fn following(intervals: &[u32], index_ticks: u32) -> Vec<u32> {
+    let mut revolution = intervals.to_vec();
+    revolution[0] += index_ticks - intervals.iter().sum::<u32>();
+    revolution
+}
+
+/// Encode flux intervals as A2R timing data, where a byte of 255 adds 255 ticks to the next byte.
+fn timing_data(intervals: &[u32]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for &interval in intervals {
+        let mut remaining = interval;
+        while remaining >= 255 {
+            data.push(255);
+            remaining -= 255;
+        }
+        data.push(remaining as u8);
+    }
+    data
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend((data.len() as u32).to_le_bytes());
+    chunk.extend(data);
+    chunk
+}
+
+fn info_chunk(version: u8, drive_type: u8) -> Vec<u8> {
+    let mut info = vec![1u8];
+    let mut creator = *b"Applesauce v2.0                 ";
+    creator[12] = b'0' + version;
+    info.extend(creator);
+    info.extend([drive_type, 1, 0]);
+    if version >= 3 {
+        info.push(0);
+    }
+    chunk(b"INFO", &info)
+}
+
+fn a2r_image(version: u8, chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut a2r = vec![b'A', b'2', b'R', b'0' + version, 0xFF, 0x0A, 0x0D, 0x0A];
+    for chunk in chunks {
+        a2r.extend(chunk);
+    }
+    a2r
+}
+
+fn check_image(image: &mut DiskImage, heads: u8) {
+    assert_eq!(image.source_format(), Some(DiskImageFormat::A2rImage));
+    assert_eq!(image.image_format().geometry.c(), 1);
+    assert_eq!(image.image_format().geometry.h(), heads);
+    assert_eq!(image.image_format().write_protect, Some(true));
+    assert!(matches!(image.image_format().data_rate, DiskDataRate::Rate250Kbps));
+
+    for h in 0..heads {
+        let track = image.get_track_ch(DiskCh::new(0, h)).unwrap();
+        assert_eq!(track.revolutions().len(), 2);
+        for s in 1..=9 {
+            let chs = DiskChs::new(0, h, s);
+            let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+            assert!(!rsr.address_crc_error, "sector {}", chs);
+            assert!(!rsr.data_crc_error, "sector {}", chs);
+            assert!(rsr.weak_mask.is_none(), "sector {} is weak", chs);
+        }
+    }
+}
+
+#[test]
+fn test_a2r_v2_load() {
+    init();
+
+    let source = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    // An extended timing capture of each side of cylinder 0, of two revolutions and part of a
+    // third.
+    let mut strm = Vec::new();
+    for h in 0..2u8 {
+        let (intervals, loop_point) = track_intervals(&source, DiskCh::new(0, h));
+        let next = following(&intervals, loop_point);
+        let timing = timing_data(&[&intervals[..], &next[..], &next[..100]].concat());
+        strm.extend([h, 3]);
+        strm.extend((timing.len() as u32).to_le_bytes());
+        strm.extend(loop_point.to_le_bytes());
+        strm.extend(timing);
+    }
+    strm.push(0xFF);
+    let meta = "title\tTest Disk\npublisher\tfluxfox\nlanguage\tEnglish\n";
+
+    let a2r = a2r_image(
+        2,
+        &[
+            info_chunk(2, DRIVE_525_DS_40),
+            chunk(b"STRM", &strm),
+            chunk(b"META", meta.as_bytes()),
+        ],
+    );
+    let mut in_buffer = Cursor::new(a2r);
+    assert!(DiskImageFormat::A2rImage.detect(&mut in_buffer));
+    let mut image = DiskImage::load(&mut in_buffer).unwrap();
+    check_image(&mut image, 2);
+
+    let metadata = image.source_metadata();
+    assert_eq!(metadata.get("creator").map(String::as_str), Some("Applesauce v2.0"));
+    assert_eq!(
+        metadata.get("drive_type").map(String::as_str),
+        Some("5.25\" DS 40-track")
+    );
+    assert_eq!(metadata.get("write_protected").map(String::as_str), Some("true"));
+    assert_eq!(metadata.get("title").map(String::as_str), Some("Test Disk"));
+    assert_eq!(metadata.get("publisher").map(String::as_str), Some("fluxfox"));
+    assert_eq!(metadata.get("language").map(String::as_str), Some("English"));
+}
+
+#[test]
+fn test_a2r_v3_load() {
+    init();
+
+    let source = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let (intervals, index_ticks) = track_intervals(&source, DiskCh::new(0, 0));
+    let next = following(&intervals, index_ticks);
+
+    // A capture beginning with the end of a revolution before the first index signal, followed by
+    // two whole revolutions.
+    let lead_in = &intervals[intervals.len() - 100..];
+    let first_index = lead_in.iter().sum::<u32>() + next[0] - intervals[0];
+    let timing = timing_data(&[lead_in, &next[..], &next[..]].concat());
+
+    let mut rwcp = vec![1u8];
+    rwcp.extend(125_000u32.to_le_bytes());
+    rwcp.extend([0u8; 11]);
+    rwcp.push(b'C');
+    rwcp.push(1);
+    rwcp.extend(0u16.to_le_bytes());
+    rwcp.push(3);
+    for i in 0..3 {
+        rwcp.extend((first_index + i * index_ticks).to_le_bytes());
+    }
+    rwcp.extend((timing.len() as u32).to_le_bytes());
+    rwcp.extend(timing);
+    rwcp.push(b'X');
+
+    let a2r = a2r_image(3, &[info_chunk(3, DRIVE_525_DS_40), chunk(b"RWCP", &rwcp)]);
+    let mut image = DiskImage::load(&mut Cursor::new(a2r)).unwrap();
+    check_image(&mut image, 1);
+
+    let metadata = image.source_metadata();
+    assert_eq!(metadata.get("creator").map(String::as_str), Some("Applesauce v3.0"));
+    assert_eq!(metadata.get("synchronized").map(String::as_str), Some("false"));
+    assert!(!metadata.contains_key("hard_sectors"));
+    assert!(!metadata.contains_key("title"));
+}
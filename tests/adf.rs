@@ -0,0 +1,114 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::platform::DiskPlatform;
+use fluxfox::structure_parsers::amiga::AmigaParser;
+use fluxfox::{DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, ImageParser};
+use std::io::Cursor;
+
+const SECTORS: usize = 11;
+const ADF_SIZE: usize = 80 * 2 * SECTORS * 512;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Build an AmigaDOS disk with a boot block, a root block named `name` at the middle of the disk,
+/// and every other block filled with a pattern unique to the block.
+fn build_adf(name: &str) -> Vec<u8> {
+    let mut adf = Vec::with_capacity(ADF_SIZE);
+    for block in 0..ADF_SIZE / 512 {
+        adf.extend((0..512).map(|i| (i as u8) ^ (block as u8).wrapping_mul(13) ^ (block >> 8) as u8));
+    }
+
+    adf[0..4].copy_from_slice(b"DOS\0");
+
+    let root = &mut adf[880 * 512..881 * 512];
+    root.fill(0);
+    root[3] = 2;
+    root[511] = 1;
+    root[432] = name.len() as u8;
+    root[433..433 + name.len()].copy_from_slice(name.as_bytes());
+    adf
+}
+
+fn load_adf(adf: &[u8]) -> DiskImage {
+    let mut cursor = Cursor::new(adf.to_vec());
+    DiskImage::load(&mut cursor).unwrap()
+}
+
+#[test]
+fn test_adf_load() {
+    init();
+    let adf = build_adf("Workbench");
+    let mut image = load_adf(&adf);
+    assert_eq!(image.source_format(), Some(DiskImageFormat::AmigaDiskFile));
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map.len(), 2);
+    assert_eq!(sector_map[0].len(), 80);
+    let track = &sector_map[1][40];
+    let ids = track.sectors.iter().map(|s| s.chsn.s()).collect::<Vec<_>>();
+    assert_eq!(ids, (0..SECTORS as u8).collect::<Vec<_>>());
+    assert!(track
+        .sectors
+        .iter()
+        .all(|s| s.chsn.c() == 40 && s.chsn.h() == 1 && s.address_crc_valid && s.data_crc_valid));
+
+    let chs = DiskChs::new(40, 1, 7);
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let block = (40 * 2 + 1) * SECTORS + 7;
+    assert_eq!(rsr.read_buf, adf[block * 512..(block + 1) * 512]);
+    assert_eq!(image.read_lba(block).unwrap(), adf[block * 512..(block + 1) * 512]);
+
+    assert_eq!(image.detect_platform().platform(), DiskPlatform::Amiga);
+    assert_eq!(image.volume_name(), Some("Workbench"));
+}
+
+#[test]
+fn test_adf_round_trip() {
+    init();
+    let adf = build_adf("Extras");
+    let image = load_adf(&adf);
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::AmigaDiskFile
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    assert_eq!(out_buffer.into_inner(), adf);
+}
+
+#[test]
+fn test_amiga_bad_checksum() {
+    init();
+    let adf = build_adf("Extras");
+    let mut bits = AmigaParser::encode_track(0, 0, &adf[..SECTORS * 512], 100_000);
+    // Flip a data bit of the second sector. Each sector is 1088 bytes of bitcells, and its data
+    // starts 64 bytes in.
+    let bit = (1088 + 64 + 100) * 8 + 1;
+    bits.set(bit, !bits[bit]);
+
+    let mut image = DiskImage::default();
+    image
+        .add_track_bitstream(
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            DiskCh::new(0, 0),
+            250_000,
+            Some(bits.len()),
+            &bits.to_bytes(),
+            None,
+        )
+        .unwrap();
+
+    let rsr = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.data_crc_error);
+    assert!(!rsr.address_crc_error);
+    assert_ne!(rsr.read_buf, adf[512..1024]);
+
+    let rsr = image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, adf[1024..1536]);
+}
@@ -0,0 +1,89 @@
+use fluxfox::analysis::timing_map;
+use fluxfox::flux::FluxRevolution;
+use fluxfox::{DiskCh, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Capture clock ticks per bitcell of a 250Kbps MFM track at a 25ns capture resolution.
+const TICKS_PER_CELL: u32 = 80;
+const TICK: f64 = 25e-9;
+
+/// Build a revolution of evenly spaced transitions two bitcells apart. If `jitter` is set, the
+/// transitions in the sixth tenth of the revolution alternate early and late by an eighth of
+/// their interval.
+fn build_flux(jitter: bool) -> FluxRevolution {
+    let count = 25_000;
+    let intervals: Vec<u32> = (0..count)
+        .map(|i| match (jitter, i * 10 / count) {
+            (true, 5) if i % 2 == 0 => TICKS_PER_CELL * 2 + 20,
+            (true, 5) => TICKS_PER_CELL * 2 - 20,
+            _ => TICKS_PER_CELL * 2,
+        })
+        .collect();
+    let index_ticks = intervals.iter().map(|&i| i as u64).sum();
+    FluxRevolution::new(intervals, TICK, index_ticks)
+}
+
+fn build_image() -> DiskImage {
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    for (c, jitter) in [false, true].into_iter().enumerate() {
+        image
+            .add_track_fluxstream(
+                DiskDataEncoding::Mfm,
+                DiskDataRate::Rate250Kbps,
+                DiskCh::new(c as u16, 0),
+                vec![build_flux(jitter)],
+            )
+            .unwrap();
+    }
+    image
+}
+
+#[test]
+fn test_timing_map() {
+    init();
+
+    let image = build_image();
+    let map = timing_map(&image, 0, 10);
+    assert_eq!(map.tracks.len(), 2);
+
+    let clean = map.tracks[0].as_ref().unwrap();
+    assert!(clean.iter().all(|&d| d < 0.01));
+
+    let jittered = map.tracks[1].as_ref().unwrap();
+    assert!((jittered[5] - 0.125).abs() < 0.01, "{:?}", jittered);
+    for (bin, deviation) in jittered.iter().enumerate().filter(|(bin, _)| *bin != 5) {
+        assert!(*deviation < 0.01, "bin {}: {}", bin, deviation);
+    }
+    assert!((map.max_deviation() - 0.125).abs() < 0.01);
+
+    // The other head has no tracks.
+    assert!(timing_map(&image, 1, 10).tracks.is_empty());
+}
+
+#[cfg(feature = "viz")]
+#[test]
+fn test_render_timing_map() {
+    use fluxfox::analysis::render_timing_map;
+    use fluxfox::DiskImageError;
+
+    init();
+
+    let image = build_image();
+    let heatmap = render_timing_map(&image, 0, (10, 4), None).unwrap();
+    assert_eq!(heatmap.dimensions(), (10, 4));
+
+    // The clean track is blue throughout, and the jittered track red where it was jittered.
+    assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 255, 255]);
+    assert_eq!(heatmap.get_pixel(5, 1).0, [0, 0, 255, 255]);
+    assert_eq!(heatmap.get_pixel(5, 2).0, [255, 0, 0, 255]);
+    assert_eq!(heatmap.get_pixel(4, 3).0, [0, 0, 255, 255]);
+
+    assert!(matches!(
+        render_timing_map(&image, 1, (10, 4), None),
+        Err(DiskImageError::UnsupportedFormat)
+    ));
+}
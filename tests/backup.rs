@@ -0,0 +1,74 @@
+use fluxfox::diskimage::{BackupPolicy, DiskImageFlags, LoadOptions, RwSectorScope};
+use fluxfox::{DiskChs, DiskImage, DiskImageError};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const IMAGE_PATH: &str = "tests/images/Transylvania.img";
+
+fn load(policy: BackupPolicy) -> DiskImage {
+    let image_buf = std::fs::read(IMAGE_PATH).unwrap();
+    let options = LoadOptions {
+        backup_policy: policy,
+        ..Default::default()
+    };
+    DiskImage::load_with_options(&mut Cursor::new(image_buf), options).unwrap()
+}
+
+fn write_boot_sector(image: &mut DiskImage) -> Result<(), DiskImageError> {
+    image
+        .write_sector(
+            DiskChs::new(0, 0, 1),
+            None,
+            &[0xAA; 512],
+            RwSectorScope::DataOnly,
+            false,
+            false,
+        )
+        .map(|_| ())
+}
+
+#[test]
+fn test_backup_in_memory() {
+    init();
+
+    let original = std::fs::read(IMAGE_PATH).unwrap();
+    let mut image = load(BackupPolicy::InMemory);
+    assert_eq!(image.backup(), Some(original.as_slice()));
+
+    write_boot_sector(&mut image).unwrap();
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+    assert_eq!(image.backup(), Some(original.as_slice()));
+
+    // No backup is kept by default.
+    let image = load(BackupPolicy::None);
+    assert!(image.backup().is_none());
+}
+
+#[test]
+fn test_backup_file() {
+    init();
+
+    let dir = std::env::temp_dir().join(format!("fluxfox_backup_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let backup_path = dir.join("Transylvania.img.bak");
+
+    let original = std::fs::read(IMAGE_PATH).unwrap();
+    let mut image = load(BackupPolicy::File(backup_path.clone()));
+    assert!(!backup_path.exists());
+
+    // The backup is written on the first modification only.
+    write_boot_sector(&mut image).unwrap();
+    assert_eq!(std::fs::read(&backup_path).unwrap(), original);
+    write_boot_sector(&mut image).unwrap();
+
+    // An existing backup is never overwritten, and the modification is refused.
+    let mut image = load(BackupPolicy::File(backup_path.clone()));
+    assert!(write_boot_sector(&mut image).is_err());
+    assert!(!image.has_flag(DiskImageFlags::DIRTY));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,118 @@
+use fluxfox::batch::{convert_batch, BatchOptions, BatchStage, BatchWarning, ConvertJob};
+use fluxfox::convert::{ConvertIssueKind, ConvertPolicy};
+use fluxfox::progress::{CancellationToken, Progress};
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskImageError, DiskImageFormat, ImageParser, StandardFormat};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluxfox_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_pri(image: TestImage, path: &Path) {
+    let image = image.generate().unwrap();
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage.save_image(&image, &mut out).unwrap();
+    std::fs::write(path, out.into_inner()).unwrap();
+}
+
+#[test]
+fn test_convert_batch() {
+    init();
+
+    let dir = temp_dir("batch");
+    write_pri(TestImage::Standard(StandardFormat::PcFloppy360), &dir.join("good.pri"));
+    write_pri(TestImage::BadDataCrc, &dir.join("bad.pri"));
+    std::fs::write(dir.join("existing.img"), b"keep").unwrap();
+
+    let jobs = vec![
+        ConvertJob::new(dir.join("good.pri"), DiskImageFormat::RawSectorImage),
+        ConvertJob::new(dir.join("bad.pri"), DiskImageFormat::RawSectorImage),
+        ConvertJob::new(dir.join("missing.pri"), DiskImageFormat::RawSectorImage),
+        ConvertJob::new(dir.join("good.pri"), DiskImageFormat::RawSectorImage).with_output(dir.join("existing.img")),
+        ConvertJob::new(dir.join("good.pri"), DiskImageFormat::PceBitstreamImage).with_output(dir.join("copy.pri")),
+    ];
+    let options = BatchOptions {
+        threads: 3,
+        ..Default::default()
+    };
+    let manifest = convert_batch(&jobs, &options);
+
+    // Entries are in job order, regardless of which thread ran them.
+    assert_eq!(manifest.entries.len(), 5);
+    for (entry, job) in manifest.entries.iter().zip(&jobs) {
+        assert_eq!(entry.job.input, job.input);
+    }
+    assert_eq!(manifest.clean().count(), 2);
+    assert_eq!(manifest.warned().count(), 1);
+    assert_eq!(manifest.failed().count(), 2);
+    assert!(!manifest.is_clean());
+
+    let good = &manifest.entries[0];
+    assert_eq!(good.output.as_deref(), Some(dir.join("good.img").as_path()));
+    assert_eq!(std::fs::metadata(dir.join("good.img")).unwrap().len(), 368_640);
+    assert!(std::fs::metadata(dir.join("copy.pri")).is_ok());
+
+    let bad = &manifest.entries[1];
+    assert!(bad.succeeded());
+    assert!(bad
+        .warnings
+        .iter()
+        .any(|w| matches!(w, BatchWarning::Issue(issue) if issue.kind == ConvertIssueKind::BadDataCrc)));
+
+    let missing = manifest.entries[2].result.as_ref().unwrap_err();
+    assert_eq!(missing.stage, BatchStage::Load);
+
+    // Existing files are not replaced unless requested.
+    let existing = manifest.entries[3].result.as_ref().unwrap_err();
+    assert_eq!(existing.stage, BatchStage::Write);
+    assert_eq!(std::fs::read(dir.join("existing.img")).unwrap(), b"keep");
+
+    let summary = manifest.to_string();
+    assert!(summary.ends_with("2 converted, 1 with warnings, 2 failed"));
+    assert!(summary.contains("FAIL"));
+
+    // A strict policy turns the warning into a failure.
+    let options = BatchOptions {
+        policy: ConvertPolicy::strict(),
+        overwrite: true,
+        ..Default::default()
+    };
+    let manifest = convert_batch(&jobs[1..2], &options);
+    let failure = manifest.entries[0].result.as_ref().unwrap_err();
+    assert_eq!(failure.stage, BatchStage::Save);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_convert_batch_cancel() {
+    init();
+
+    let dir = temp_dir("batch_cancel");
+    write_pri(TestImage::Standard(StandardFormat::PcFloppy360), &dir.join("good.pri"));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = BatchOptions {
+        progress: Progress::new().with_token(token),
+        ..Default::default()
+    };
+    let jobs = vec![ConvertJob::new(dir.join("good.pri"), DiskImageFormat::RawSectorImage); 2];
+    let manifest = convert_batch(&jobs, &options);
+    assert_eq!(manifest.failed().count(), 2);
+    assert!(manifest
+        .failed()
+        .all(|e| matches!(e.result, Err(ref f) if matches!(f.error, DiskImageError::Cancelled))));
+    assert!(!dir.join("good.img").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
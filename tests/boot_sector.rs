@@ -0,0 +1,103 @@
+use fluxfox::diskimage::DiskImageFlags;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// The first sector of the root directory of a 360K disk.
+const ROOT_DIR_LBA: usize = 5;
+
+#[test]
+fn test_volume_serial() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let original = image.read_lba(0).unwrap();
+    assert!(image.volume_serial().is_none());
+
+    // The default boot sector has no extended BPB, but leaves space for one.
+    image.clear_flag(DiskImageFlags::DIRTY);
+    assert_eq!(image.set_volume_serial(Some(0x1234_ABCD)).unwrap(), 0x1234_ABCD);
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+    assert_eq!(image.volume_serial(), Some(0x1234_ABCD));
+
+    let sector = image.read_lba(0).unwrap();
+    assert_eq!(sector[0x26], 0x29);
+    assert_eq!(&sector[0x27..0x2B], &[0xCD, 0xAB, 0x34, 0x12]);
+    assert_eq!(&sector[0x2B..0x3E], b"NO NAME    FAT12   ");
+    assert_eq!(&sector[0x0B..0x24], &original[0x0B..0x24]);
+    assert_eq!(&sector[0x3E..], &original[0x3E..]);
+
+    let serial = image.set_volume_serial(None).unwrap();
+    assert_eq!(image.volume_serial(), Some(serial));
+}
+
+#[test]
+fn test_volume_label() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    image.set_volume_label("my disk").unwrap();
+
+    let root = image.read_lba(ROOT_DIR_LBA).unwrap();
+    assert_eq!(&root[0..12], b"MY DISK    \x08");
+    assert_eq!(&image.read_lba(0).unwrap()[0x2B..0x36], b"MY DISK    ");
+
+    // The label entry is replaced rather than added to.
+    image.set_volume_label("FLUXFOX").unwrap();
+    let root = image.read_lba(ROOT_DIR_LBA).unwrap();
+    assert_eq!(&root[0..12], b"FLUXFOX    \x08");
+    assert_eq!(root[32], 0);
+
+    image.set_volume_label("").unwrap();
+    assert_eq!(image.read_lba(ROOT_DIR_LBA).unwrap()[0], 0xE5);
+    assert_eq!(&image.read_lba(0).unwrap()[0x2B..0x36], b"NO NAME    ");
+
+    for label in ["A.B", "TWELVE CHARS", "tab\t"] {
+        assert!(matches!(
+            image.set_volume_label(label),
+            Err(DiskImageError::ParameterError)
+        ));
+    }
+}
+
+#[test]
+fn test_install_boot_sector() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    image.set_volume_serial(Some(0xCAFE_F00D)).unwrap();
+    let original = image.read_lba(0).unwrap();
+
+    // Boot code with its own, wrong, BPB and an extended BPB.
+    let mut code = [0xCC; 512];
+    code[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    code[3..0x0B].copy_from_slice(b"MSDOS5.0");
+    code[0x0B..0x26].fill(0);
+    code[0x26] = 0x29;
+    code[0x27..0x3E].fill(0x20);
+    code[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    image.install_boot_sector(&code).unwrap();
+    let sector = image.read_lba(0).unwrap();
+    assert_eq!(&sector[0..0x0B], &code[0..0x0B]);
+    assert_eq!(&sector[0x0B..0x3E], &original[0x0B..0x3E]);
+    assert_eq!(&sector[0x3E..], &code[0x3E..]);
+    assert_eq!(image.volume_serial(), Some(0xCAFE_F00D));
+
+    // Older boot code starting right after the DOS 3.0 BPB is not overwritten.
+    code[0..2].copy_from_slice(&[0xEB, 0x1E]);
+    code[0x26] = 0xCC;
+    image.install_boot_sector(&code).unwrap();
+    let sector = image.read_lba(0).unwrap();
+    assert_eq!(&sector[0x0B..0x20], &original[0x0B..0x20]);
+    assert_eq!(&sector[0x20..], &code[0x20..]);
+    assert!(image.volume_serial().is_none());
+
+    assert!(matches!(
+        image.install_boot_sector(&code[..256]),
+        Err(DiskImageError::ParameterError)
+    ));
+}
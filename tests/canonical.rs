@@ -0,0 +1,76 @@
+use fluxfox::canonical::WeakBitPolicy;
+use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{DiskCh, DiskChs, DiskImage};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Re-master the track at `ch` with nonstandard gaps and length, as another tool might.
+fn relayout(image: &mut DiskImage, ch: DiskCh) {
+    let mut builder = TrackBuilder::new().with_bitcell_ct(101_000).with_gap(0x4E, 40);
+    for s in 1..=9 {
+        builder = builder
+            .with_sector(image, DiskChs::new(ch.c(), ch.h(), s), Some(2), MatchPolicy::Chsn)
+            .unwrap()
+            .with_gap(0x4E, 60);
+    }
+    image.splice_track(ch, &builder).unwrap();
+}
+
+#[test]
+fn test_canonicalize() {
+    init();
+
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let original = TestImage::WeakBits.generate().unwrap();
+    let mut relaid = TestImage::WeakBits.generate().unwrap();
+    relayout(&mut relaid, ch);
+    assert_ne!(original.get_hash(), relaid.get_hash());
+
+    for policy in [WeakBitPolicy::Mark, WeakBitPolicy::Clear] {
+        let mut canonical = original.canonicalize(policy).unwrap();
+        let other = relaid.canonicalize(policy).unwrap();
+        assert_eq!(canonical.get_hash(), other.get_hash());
+        assert_eq!(canonical.get_hash(), canonical.canonicalize(policy).unwrap().get_hash());
+        assert_eq!(other.track_timeline(ch).unwrap().bitcell_ct, 100_000);
+
+        // The weak bits read as zeros, and the rest of the sector is intact.
+        let rsr = canonical
+            .read_sector(chs, None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        let weak = rsr.weak_mask.clone();
+        let data = rsr.read_buf;
+        assert!(!rsr.address_crc_error);
+        let mut original = TestImage::WeakBits.generate().unwrap();
+        let expected = original.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        assert_eq!(&data[16..], &expected.read_buf[16..]);
+        match policy {
+            WeakBitPolicy::Mark => {
+                let weak = weak.unwrap();
+                assert!(weak[0..16].iter().all(|b| *b == 0xFF));
+                assert!(weak[16..].iter().all(|b| *b == 0));
+                assert!(canonical.has_weak_bits());
+            }
+            WeakBitPolicy::Clear => {
+                assert!(weak.is_none());
+                assert_eq!(&data[0..16], &[0; 16]);
+                assert!(!canonical.has_weak_bits());
+            }
+        }
+
+        // Sectors without weak bits read the same as the original.
+        let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, 1);
+        let expected = original
+            .read_sector(chs, None, RwSectorScope::DataBlock, false)
+            .unwrap();
+        let rsr = canonical
+            .read_sector(chs, None, RwSectorScope::DataBlock, false)
+            .unwrap();
+        assert_eq!(rsr.read_buf, expected.read_buf);
+        assert!(!rsr.data_crc_error);
+    }
+}
@@ -0,0 +1,54 @@
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskDataRate, DiskImageError, DiskImageFormat, DiskRpm, ImageParser, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_check_medium() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert!(image
+        .check_medium(DiskDataRate::Rate250Kbps, Some(DiskRpm::Rpm300))
+        .is_ok());
+
+    // Rates are compared numerically, so a nonstandard rate near the target, such as one measured
+    // from flux, is compatible.
+    image
+        .get_track_mut(1)
+        .unwrap()
+        .resample(DiskDataRate::RateNonstandard(248_000), DiskRpm::Rpm300)
+        .unwrap();
+    assert!(image
+        .check_medium(DiskDataRate::Rate250Kbps, Some(DiskRpm::Rpm300))
+        .is_ok());
+
+    // The tracks of a 300RPM image are too long for a 360RPM medium.
+    match image.check_medium(DiskDataRate::Rate250Kbps, Some(DiskRpm::Rpm360)) {
+        Err(DiskImageError::IncompatibleTracks(tracks)) => assert_eq!(tracks.len(), 80),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // Make the image mixed-density by re-encoding the first track at 500Kbps.
+    image
+        .get_track_mut(0)
+        .unwrap()
+        .resample(DiskDataRate::Rate500Kbps, DiskRpm::Rpm300)
+        .unwrap();
+
+    match image.check_medium(DiskDataRate::Rate250Kbps, Some(DiskRpm::Rpm300)) {
+        Err(DiskImageError::IncompatibleTracks(tracks)) => assert_eq!(tracks, vec![DiskCh::new(0, 0)]),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // 86F stores a single data rate, so the image can't be exported.
+    let mut out_buffer = Cursor::new(Vec::new());
+    let err = DiskImageFormat::F86Image
+        .save_image(&image, &mut out_buffer)
+        .unwrap_err();
+    assert!(matches!(err, DiskImageError::IncompatibleTracks(_)));
+    assert!(err.to_string().contains("[c:0 h:0]"));
+}
@@ -0,0 +1,96 @@
+use fluxfox::bitstream::mfm::{MfmEncodingType, MFM_BYTE_LEN};
+use fluxfox::consensus::ConsensusSource;
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskCh, DiskChs, DiskImage, DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn standard() -> DiskImage {
+    TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap()
+}
+
+/// Overwrite byte `offset` of the data of sector 1 on track 0 without updating the data CRC.
+fn corrupt_sector_1(image: &mut DiskImage, offset: usize) {
+    let ch = DiskCh::new(0, 0);
+    let (_, idam) = image.get_next_id_at(ch, 0).unwrap();
+    // The DAM follows the ID field, GAP2 and a sync field.
+    let data_start = idam + (10 + 22 + 12 + 4) * MFM_BYTE_LEN;
+    image
+        .write_encoded_data(ch, &[0x55], data_start + offset * MFM_BYTE_LEN, MfmEncodingType::Data)
+        .unwrap();
+}
+
+#[test]
+fn test_consensus_good_copy() {
+    init();
+
+    let images = [TestImage::BadDataCrc.generate().unwrap(), standard(), standard()];
+    let (mut image, report) = DiskImage::consensus(&images).unwrap();
+
+    assert_eq!(report.sectors.len(), 720);
+    assert!(report.is_complete());
+    assert_eq!(report.from_image(0), 719);
+    assert_eq!(report.from_image(1), 1);
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let won = report.sectors.iter().find(|s| DiskChs::from(s.chsn) == chs).unwrap();
+    assert_eq!(won.source, ConsensusSource::Image { index: 1, votes: 2 });
+    assert_eq!(report.sectors[0].source, ConsensusSource::Image { index: 0, votes: 3 });
+
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(image.health().score(), 100);
+}
+
+#[test]
+fn test_consensus_bit_vote() {
+    init();
+
+    let mut images = [standard(), standard(), standard()];
+    for (image, offset) in images.iter_mut().zip([10, 100, 200]) {
+        corrupt_sector_1(image, offset);
+        let rsr = image
+            .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert!(rsr.data_crc_error);
+    }
+
+    // Each dump is damaged in a different place, so a vote on each bit recovers the sector.
+    let (mut image, report) = DiskImage::consensus(&images).unwrap();
+    assert_eq!(report.sectors[0].source, ConsensusSource::Voted { copies: 3 });
+    assert!(report.is_complete());
+    let rsr = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(
+        rsr.read_buf,
+        standard()
+            .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+            .unwrap()
+            .read_buf
+    );
+}
+
+#[test]
+fn test_consensus_unresolved() {
+    init();
+
+    // Both dumps have the same damage, so there is nothing to recover it from.
+    let images = [
+        TestImage::BadDataCrc.generate().unwrap(),
+        TestImage::BadDataCrc.generate().unwrap(),
+    ];
+    let (image, report) = DiskImage::consensus(&images).unwrap();
+    assert!(!report.is_complete());
+    let unresolved: Vec<_> = report.unresolved().collect();
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(unresolved[0].chsn.s(), TEST_QUIRK_SECTOR);
+    assert_eq!(unresolved[0].source, ConsensusSource::Unresolved { copies: 2 });
+    assert_eq!(image.health().data_crc_errors, 1);
+
+    assert!(matches!(DiskImage::consensus(&[]), Err(DiskImageError::ParameterError)));
+}
@@ -0,0 +1,206 @@
+use fluxfox::convert::{ConvertAction, ConvertIssueKind, ConvertPolicy};
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskImage, DiskImageError, DiskImageFormat, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Return the offset of the quirk sector in a raw 360K image.
+fn quirk_offset() -> usize {
+    ((TEST_QUIRK_CYLINDER as usize * 2) * 9 + (TEST_QUIRK_SECTOR as usize - 1)) * 512
+}
+
+/// Build a single-sided IMD image with no standard format: 35 cylinders of 16 256-byte MFM
+/// sectors. Returns the image and its sector data in raw image order.
+fn build_imd_35() -> (Vec<u8>, Vec<u8>) {
+    let mut imd = b"IMD 1.18: 16/10/2024 12:00:00\r\n".to_vec();
+    imd.push(0x1A);
+    let mut raw = Vec::new();
+    for c in 0..35u8 {
+        // Mode 5 is MFM at 250kbps, and size code 1 is 256 bytes.
+        imd.extend([5, c, 0, 16, 1]);
+        imd.extend(1..=16u8);
+        for s in 1..=16u8 {
+            let data = [c ^ s.wrapping_mul(7); 256];
+            imd.push(0x01);
+            imd.extend(data);
+            raw.extend(data);
+        }
+    }
+    (imd, raw)
+}
+
+#[test]
+fn test_convert_standard() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut out_buffer = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(
+            DiskImageFormat::RawSectorImage,
+            ConvertPolicy::strict(),
+            &mut out_buffer,
+        )
+        .unwrap();
+
+    assert!(report.is_empty());
+    assert_eq!(out_buffer.into_inner().len(), StandardFormat::PcFloppy360.size());
+}
+
+#[test]
+fn test_convert_policy() {
+    init();
+
+    let cases = [
+        (TestImage::BadDataCrc, ConvertIssueKind::BadDataCrc),
+        (TestImage::BadAddressCrc, ConvertIssueKind::BadAddressCrc),
+        (TestImage::DeletedData, ConvertIssueKind::Deleted),
+        (TestImage::WeakBits, ConvertIssueKind::WeakBits),
+    ];
+
+    for (test_image, kind) in cases {
+        let mut image = test_image.generate().unwrap();
+
+        // A strict policy refuses to drop the feature.
+        let mut out_buffer = Cursor::new(Vec::new());
+        assert!(image
+            .save_with_policy(
+                DiskImageFormat::RawSectorImage,
+                ConvertPolicy::strict(),
+                &mut out_buffer
+            )
+            .is_err());
+
+        // The default policy writes what it can and reports the feature.
+        let mut out_buffer = Cursor::new(Vec::new());
+        let report = image
+            .save_with_policy(
+                DiskImageFormat::RawSectorImage,
+                ConvertPolicy::default(),
+                &mut out_buffer,
+            )
+            .unwrap();
+        let issues = report.issues_of(kind).collect::<Vec<_>>();
+        assert_eq!(issues.len(), 1, "{:?}: {:?}", test_image, report);
+        assert_eq!(issues[0].chsn.c(), TEST_QUIRK_CYLINDER);
+        assert_eq!(issues[0].chsn.s(), TEST_QUIRK_SECTOR);
+        assert_eq!(issues[0].action, ConvertAction::BestEffort);
+        assert_eq!(out_buffer.get_ref().len(), StandardFormat::PcFloppy360.size());
+    }
+}
+
+#[test]
+fn test_convert_zero_fill() {
+    init();
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    image
+        .write_sector(chs, None, &[0xAA; 512], RwSectorScope::DataOnly, true, false)
+        .unwrap();
+
+    let policy = ConvertPolicy {
+        deleted: ConvertAction::ZeroFill,
+        ..ConvertPolicy::strict()
+    };
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(DiskImageFormat::RawSectorImage, policy, &mut out_buffer)
+        .unwrap();
+    assert_eq!(report.issues.len(), 1);
+
+    // The deleted sector is zeroed.
+    let out = out_buffer.into_inner();
+    let offset = quirk_offset();
+    assert!(out[offset..offset + 512].iter().all(|&b| b == 0));
+
+    // With the default policy, the deleted sector's data is written.
+    let mut out_buffer = Cursor::new(Vec::new());
+    image
+        .save_with_policy(
+            DiskImageFormat::RawSectorImage,
+            ConvertPolicy::default(),
+            &mut out_buffer,
+        )
+        .unwrap();
+    let out = out_buffer.into_inner();
+    assert!(out[offset..offset + 512].iter().all(|&b| b == 0xAA));
+}
+
+#[test]
+fn test_convert_mixed_sizes() {
+    init();
+
+    let mut image = TestImage::MixedSectorSizes.generate().unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(
+            DiskImageFormat::RawSectorImage,
+            ConvertPolicy::default(),
+            &mut out_buffer,
+        )
+        .unwrap();
+
+    // Three of the four sectors are not 512 bytes, and the remaining five sectors are missing.
+    assert_eq!(report.issues_of(ConvertIssueKind::NonstandardSize).count(), 3);
+    assert_eq!(report.issues_of(ConvertIssueKind::Missing).count(), 5);
+}
+
+#[test]
+fn test_convert_nonstandard_geometry() {
+    init();
+
+    // The sector size and cylinder count of the raw image are taken from the image itself.
+    let (imd, raw) = build_imd_35();
+    let mut image = DiskImage::load(&mut Cursor::new(imd)).unwrap();
+    let mut out_buffer = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(
+            DiskImageFormat::RawSectorImage,
+            ConvertPolicy::strict(),
+            &mut out_buffer,
+        )
+        .unwrap();
+
+    assert!(report.is_empty(), "{:?}", report);
+    assert!(out_buffer.into_inner() == raw);
+}
+
+#[test]
+fn test_convert_policy_other_format() {
+    init();
+
+    // IMD cannot store weak bits, and only raw images apply the policy per sector.
+    let mut image = TestImage::WeakBits.generate().unwrap();
+    let save = |image: &mut DiskImage, policy| {
+        image.save_with_policy(DiskImageFormat::ImageDisk, policy, &mut Cursor::new(Vec::new()))
+    };
+
+    assert!(matches!(
+        save(&mut image, ConvertPolicy::strict()),
+        Err(DiskImageError::IncompatibleImage)
+    ));
+
+    let policy = ConvertPolicy {
+        weak_bits: ConvertAction::ZeroFill,
+        ..ConvertPolicy::default()
+    };
+    assert!(matches!(
+        save(&mut image, policy),
+        Err(DiskImageError::UnsupportedFormat)
+    ));
+
+    assert!(save(&mut image, ConvertPolicy::default()).is_ok());
+
+    // A format that stores the image without loss honors any policy.
+    let mut out_buffer = Cursor::new(Vec::new());
+    assert!(image
+        .save_with_policy(DiskImageFormat::F86Image, ConvertPolicy::strict(), &mut out_buffer)
+        .is_ok());
+}
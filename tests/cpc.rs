@@ -0,0 +1,232 @@
+use fluxfox::diskimage::{LoadOptions, ParseMode, RwSectorScope};
+use fluxfox::{
+    DiskChs, DiskDataEncoding, DiskImage, DiskImageError, DiskImageFormat, ImageParser, ParserWriteCompatibility,
+};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn sector_data(c: u8, s: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8) ^ c.wrapping_mul(17) ^ s).collect()
+}
+
+/// A sector of a DSK track: its ID, the ST1 and ST2 status bytes, and the data stored.
+struct DskSector {
+    c: u8,
+    r: u8,
+    n: u8,
+    st1: u8,
+    st2: u8,
+    data: Vec<u8>,
+}
+
+impl DskSector {
+    fn new(c: u8, r: u8, data: Vec<u8>) -> Self {
+        DskSector {
+            c,
+            r,
+            n: 2,
+            st1: 0,
+            st2: 0,
+            data,
+        }
+    }
+}
+
+/// Build a DSK track record: the track information block, followed by the sector data padded to
+/// a multiple of 256 bytes.
+fn dsk_track(c: u8, recording_mode: u8, sectors: &[DskSector]) -> Vec<u8> {
+    let mut track = vec![0u8; 256];
+    track[0..12].copy_from_slice(b"Track-Info\r\n");
+    track[0x10] = c;
+    track[0x12] = 1;
+    track[0x13] = recording_mode;
+    track[0x14] = 2;
+    track[0x15] = sectors.len() as u8;
+    track[0x16] = 0x4E;
+    track[0x17] = 0xE5;
+    for (i, sector) in sectors.iter().enumerate() {
+        let info = &mut track[0x18 + i * 8..0x20 + i * 8];
+        info[..6].copy_from_slice(&[sector.c, 0, sector.r, sector.n, sector.st1, sector.st2]);
+        info[6..].copy_from_slice(&(sector.data.len() as u16).to_le_bytes());
+    }
+    for sector in sectors {
+        track.extend(&sector.data);
+    }
+    track.resize(track.len().div_ceil(256) * 256, 0);
+    track
+}
+
+/// Build a single-sided DSK image from track records. Extended images record the size of each
+/// track, standard images the size of the first.
+fn dsk_image(extended: bool, tracks: &[Vec<u8>]) -> Vec<u8> {
+    let mut dsk = vec![0u8; 256];
+    let id: &[u8] = if extended {
+        b"EXTENDED CPC DSK File\r\nDisk-Info\r\n"
+    } else {
+        b"MV - CPCEMU Disk-File\r\nDisk-Info\r\n"
+    };
+    dsk[0..34].copy_from_slice(id);
+    dsk[0x22..0x2A].copy_from_slice(b"fluxfox\0");
+    dsk[0x30] = tracks.len() as u8;
+    dsk[0x31] = 1;
+    if extended {
+        for (i, track) in tracks.iter().enumerate() {
+            dsk[0x34 + i] = (track.len() / 256) as u8;
+        }
+    } else {
+        dsk[0x32..0x34].copy_from_slice(&(tracks[0].len() as u16).to_le_bytes());
+    }
+    for track in tracks {
+        dsk.extend(track);
+    }
+    dsk
+}
+
+#[test]
+fn test_edsk_load() {
+    init();
+
+    let track0 = (0xC1..=0xC9)
+        .map(|r| DskSector::new(0, r, sector_data(0, r, 512)))
+        .collect::<Vec<_>>();
+
+    // A weak sector stored as three reads, which differ in bytes 100 to 107.
+    let mut weak_copies = Vec::new();
+    for copy in 0..3u8 {
+        let mut data = sector_data(1, 0x42, 512);
+        data[100..108].iter_mut().for_each(|b| *b ^= copy);
+        weak_copies.extend(data);
+    }
+    let track1 = vec![
+        DskSector::new(1, 0x41, sector_data(1, 0x41, 512)),
+        DskSector::new(1, 0x42, weak_copies),
+        DskSector {
+            st1: 0x20,
+            st2: 0x20,
+            ..DskSector::new(1, 0x43, sector_data(1, 0x43, 512))
+        },
+        DskSector {
+            st2: 0x40,
+            ..DskSector::new(1, 0x44, sector_data(1, 0x44, 512))
+        },
+        // A sector stored shorter than its ID declares.
+        DskSector::new(1, 0x45, sector_data(1, 0x45, 256)),
+        DskSector {
+            st1: 0x20,
+            ..DskSector::new(1, 0x46, Vec::new())
+        },
+    ];
+
+    let dsk = dsk_image(true, &[dsk_track(0, 2, &track0), dsk_track(1, 1, &track1)]);
+    let mut in_buffer = Cursor::new(dsk);
+    assert!(DiskImageFormat::CpcDskImage.detect(&mut in_buffer));
+    let mut image = DiskImage::load(&mut in_buffer).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::CpcDskImage));
+    assert_eq!(image.image_format().geometry.c(), 2);
+    assert_eq!(image.image_format().geometry.h(), 1);
+    assert!(matches!(
+        DiskImageFormat::CpcDskImage.can_write(&image),
+        ParserWriteCompatibility::UnsupportedFormat
+    ));
+
+    let sector_map = image.get_sector_map();
+    assert!(matches!(sector_map[0][0].encoding, DiskDataEncoding::Mfm));
+    assert!(matches!(sector_map[0][1].encoding, DiskDataEncoding::Fm));
+    assert_eq!(sector_map[0][0].sectors.len(), 9);
+    assert_eq!(sector_map[0][1].sectors.len(), 6);
+    let flags = sector_map[0][1]
+        .sectors
+        .iter()
+        .map(|s| (s.address_crc_valid, s.data_crc_valid, s.deleted_mark))
+        .collect::<Vec<_>>();
+    assert_eq!(flags[2], (true, false, false));
+    assert_eq!(flags[3], (true, true, true));
+    assert_eq!(flags[5], (false, true, false));
+
+    for r in 0xC1..=0xC9 {
+        let rsr = image
+            .read_sector(DiskChs::new(0, 0, r), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert_eq!(rsr.read_buf, sector_data(0, r, 512));
+        assert!(rsr.weak_mask.is_none());
+    }
+
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 0x42), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf, sector_data(1, 0x42, 512));
+    let mut expected_mask = vec![0u8; 512];
+    expected_mask[100..108].fill(0x03);
+    assert_eq!(rsr.weak_mask, Some(expected_mask));
+
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 0x43), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, sector_data(1, 0x43, 512));
+
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 0x44), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.deleted_mark);
+
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 0x45), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.data_len, 256);
+    assert_eq!(rsr.read_buf, sector_data(1, 0x45, 256));
+}
+
+#[test]
+fn test_standard_dsk_load() {
+    init();
+
+    let tracks = (0..3u8)
+        .map(|c| {
+            let sectors = (1..=9)
+                .map(|r| DskSector::new(c, r, sector_data(c, r, 512)))
+                .collect::<Vec<_>>();
+            dsk_track(c, 0, &sectors)
+        })
+        .collect::<Vec<_>>();
+
+    let mut image = DiskImage::load(&mut Cursor::new(dsk_image(false, &tracks))).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::CpcDskImage));
+    assert_eq!(image.image_format().geometry.c(), 3);
+
+    for c in 0..3u8 {
+        for r in 1..=9 {
+            let rsr = image
+                .read_sector(DiskChs::new(c as u16, 0, r), None, RwSectorScope::DataOnly, false)
+                .unwrap();
+            assert_eq!(rsr.read_buf, sector_data(c, r, 512), "sector {}:{}", c, r);
+        }
+    }
+}
+
+#[test]
+fn test_edsk_invalid_size_code() {
+    init();
+
+    // A sector whose size code would overflow the sector size.
+    let track = vec![DskSector {
+        n: 63,
+        ..DskSector::new(0, 0xC1, sector_data(0, 0xC1, 512))
+    }];
+    let dsk = dsk_image(true, &[dsk_track(0, 2, &track)]);
+
+    let result = DiskImage::load_with_warnings(&mut Cursor::new(dsk.clone()), LoadOptions::default()).unwrap();
+    assert!(result.warnings.iter().any(|w| w.message.contains("invalid size code")));
+
+    let strict = LoadOptions {
+        parse_mode: ParseMode::Strict,
+        ..Default::default()
+    };
+    assert!(matches!(
+        DiskImage::load_with_options(&mut Cursor::new(dsk), strict),
+        Err(DiskImageError::SpecViolation(_))
+    ));
+}
@@ -0,0 +1,163 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::TestImage;
+use fluxfox::{
+    DiskChs, DiskImage, DiskImageError, DiskImageFormat, ImageParser, ImageWriter, ParserWriteCompatibility,
+    StandardFormat,
+};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Compress `data` with CopyQM's RLE scheme, using a run for any byte repeated 4 or more times.
+fn cqm_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..].iter().take(0x7FFF).take_while(|&&b| b == data[i]).count();
+        if run >= 4 || i - literal_start == 0x7FFF {
+            if i > literal_start {
+                out.extend(((i - literal_start) as i16).to_le_bytes());
+                out.extend(&data[literal_start..i]);
+            }
+            if run >= 4 {
+                out.extend((-(run as i16)).to_le_bytes());
+                out.push(data[i]);
+                i += run;
+            }
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if i > literal_start {
+        out.extend(((i - literal_start) as i16).to_le_bytes());
+        out.extend(&data[literal_start..i]);
+    }
+    out
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cqm_image(
+    mode: u8,
+    sector_size: u16,
+    total_sectors: u16,
+    spt: u16,
+    heads: u16,
+    cylinders: u8,
+    sector_base: u8,
+    comment: &str,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut header = vec![0u8; 133];
+    header[0..3].copy_from_slice(&[b'C', b'Q', 0x14]);
+    header[0x03..0x05].copy_from_slice(&sector_size.to_le_bytes());
+    header[0x0B..0x0D].copy_from_slice(&total_sectors.to_le_bytes());
+    header[0x10..0x12].copy_from_slice(&spt.to_le_bytes());
+    header[0x12..0x14].copy_from_slice(&heads.to_le_bytes());
+    header[0x58] = mode;
+    header[0x5A] = cylinders;
+    header[0x5B] = cylinders;
+    header[0x6F..0x71].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+    header[0x71] = sector_base;
+    let sum = header.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    header[0x84] = sum.wrapping_neg();
+
+    let mut cqm = header;
+    cqm.extend(comment.as_bytes());
+    cqm.extend(cqm_compress(data));
+    cqm
+}
+
+#[test]
+fn test_cqm_dos_mode() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut raw = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage
+        .write_image(&mut image, &mut raw)
+        .unwrap();
+    let raw = raw.into_inner();
+
+    let cqm = cqm_image(0, 512, 720, 9, 2, 40, 0, "Driver Disk v1.0\0", &raw);
+    assert!(cqm.len() < raw.len());
+
+    let mut in_buffer = Cursor::new(cqm);
+    assert!(DiskImageFormat::CopyQm.detect(&mut in_buffer));
+    let mut image = DiskImage::load(&mut in_buffer).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::CopyQm));
+    assert_eq!(image.image_format().geometry.c(), 40);
+    assert_eq!(image.get_comment(), Some("Driver Disk v1.0"));
+    assert!(matches!(
+        DiskImageFormat::CopyQm.can_write(&image),
+        ParserWriteCompatibility::UnsupportedFormat
+    ));
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage
+        .write_image(&mut image, &mut out_buffer)
+        .unwrap();
+    assert_eq!(out_buffer.into_inner(), raw);
+}
+
+#[test]
+fn test_cqm_blind_mode() {
+    init();
+
+    // Two single-sided cylinders of ten 256-byte sectors numbered from 0x41, with only the first
+    // cylinder stored.
+    let mut data = Vec::new();
+    for s in 0..10u8 {
+        data.extend([s; 128]);
+        data.extend((0..128u8).map(|b| b ^ s));
+    }
+    let mut cqm = cqm_image(1, 256, 0, 10, 1, 2, 0x40, "", &data);
+    cqm[0x5A] = 1;
+    cqm[0x84] = cqm[0x84].wrapping_add(1);
+
+    let mut image = DiskImage::load(&mut Cursor::new(cqm)).unwrap();
+    assert_eq!(image.image_format().geometry.c(), 2);
+    assert_eq!(image.image_format().default_sector_size, 256);
+    assert_eq!(image.get_comment(), None);
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 0x43), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, data[512..768]);
+
+    let result = image
+        .read_sector(DiskChs::new(1, 0, 0x4A), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![0; 256]);
+}
+
+#[test]
+fn test_cqm_bad_header() {
+    init();
+
+    // A file holding only the signature.
+    let cqm = cqm_image(0, 512, 720, 9, 2, 40, 0, "", &[]);
+    assert!(DiskImageFormat::CopyQm.detect(Cursor::new(&cqm[..3])));
+    assert!(DiskImageFormat::CopyQm.load_image(Cursor::new(&cqm[..3])).is_err());
+    assert!(DiskImageFormat::CopyQm.load_image(Cursor::new(&cqm[..100])).is_err());
+
+    // A DOS mode header whose BPB claims far more sectors than any floppy holds.
+    let mut cqm = cqm_image(0, 512, 0, 9, 2, 40, 0, "", &[0xF6; 512]);
+    cqm[0x18..0x1C].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+    cqm[0x84] = cqm[0x84].wrapping_sub(0xFC);
+    assert!(matches!(
+        DiskImageFormat::CopyQm.load_image(Cursor::new(cqm)),
+        Err(DiskImageError::FormatParseError)
+    ));
+
+    for (sector_size, spt, heads) in [(512, 0, 2), (512, 9, 0), (512, 9, 3), (8192, 255, 2)] {
+        let cqm = cqm_image(1, sector_size, 0, spt, heads, 40, 0, "", &[0xF6; 512]);
+        assert!(matches!(
+            DiskImageFormat::CopyQm.load_image(Cursor::new(cqm)),
+            Err(DiskImageError::FormatParseError)
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+use fluxfox::bitstream::mfm::{MfmEncodingType, MFM_BYTE_LEN};
+use fluxfox::diskimage::{CrcField, RwSectorScope};
+use fluxfox::testutil::TestImage;
+use fluxfox::timeline::TimelineElement;
+use fluxfox::{DiskCh, DiskChs, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Overwrite the first bytes of the data field of sector `s`, leaving its CRC alone.
+fn poke_data(image: &mut DiskImage, ch: DiskCh, s: u8, bytes: &[u8]) {
+    let timeline = image.track_timeline(ch).unwrap();
+    let span = timeline
+        .spans
+        .iter()
+        .find(|span| matches!(span.element, TimelineElement::Data(chsn) if chsn.s() == s))
+        .unwrap();
+    image
+        .write_encoded_data(ch, bytes, span.start, MfmEncodingType::Data)
+        .unwrap();
+}
+
+/// Overwrite the cylinder byte in the header of sector `s`, leaving its CRC alone.
+fn poke_header_c(image: &mut DiskImage, ch: DiskCh, s: u8, c: u8) {
+    let mut bit_index = 0;
+    let header_idx = loop {
+        let (chsn, idx) = image.get_next_id_at(ch, bit_index).unwrap();
+        if chsn.s() == s {
+            break idx;
+        }
+        bit_index = idx + 1;
+    };
+    image
+        .write_encoded_data(ch, &[c], header_idx + 4 * MFM_BYTE_LEN, MfmEncodingType::Data)
+        .unwrap();
+}
+
+fn read(image: &mut DiskImage, chs: DiskChs) -> (bool, bool, Vec<u8>) {
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    (rsr.address_crc_error, rsr.data_crc_error, rsr.read_buf)
+}
+
+#[test]
+fn test_fix_sector_crcs() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+
+    poke_data(&mut image, ch, 3, &[0xDE, 0xAD]);
+    poke_data(&mut image, ch, 4, &[0xBE, 0xEF]);
+    poke_header_c(&mut image, ch, 5, 7);
+    assert!(read(&mut image, DiskChs::new(0, 0, 3)).1);
+
+    // Sector 4 is left bad on purpose.
+    let fixes = image.fix_sector_crcs(ch, None, &[4]).unwrap();
+    let fixed: Vec<_> = fixes.iter().map(|f| (f.chsn.c(), f.chsn.s(), f.field)).collect();
+    assert_eq!(fixed, vec![(0, 3, CrcField::Data), (7, 5, CrcField::Address)]);
+    assert!(fixes.iter().all(|f| f.recorded != f.calculated));
+
+    let (address_err, data_err, data) = read(&mut image, DiskChs::new(0, 0, 3));
+    assert!(!address_err && !data_err);
+    assert_eq!(&data[0..2], &[0xDE, 0xAD]);
+    assert!(read(&mut image, DiskChs::new(0, 0, 4)).1);
+    let rsr = image
+        .read_sector(DiskChs::new(7, 0, 5), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.address_crc_error && !rsr.data_crc_error);
+
+    // Nothing left to fix but the sector we kept.
+    assert!(image.fix_sector_crcs(ch, None, &[4]).unwrap().is_empty());
+    assert_eq!(image.fix_sector_crcs(ch, None, &[]).unwrap().len(), 1);
+    assert!(!read(&mut image, DiskChs::new(0, 0, 4)).1);
+}
+
+#[test]
+fn test_fix_sector_crcs_selected() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(1, 1);
+
+    poke_data(&mut image, ch, 1, &[0x11]);
+    poke_data(&mut image, ch, 2, &[0x22]);
+
+    let fixes = image.fix_sector_crcs(ch, Some(&[2]), &[]).unwrap();
+    assert_eq!(fixes.len(), 1);
+    assert_eq!(fixes[0].chsn.s(), 2);
+    assert!(read(&mut image, DiskChs::new(1, 1, 1)).1);
+    assert!(!read(&mut image, DiskChs::new(1, 1, 2)).1);
+
+    assert!(image.fix_sector_crcs(DiskCh::new(90, 0), None, &[]).is_err());
+}
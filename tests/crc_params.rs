@@ -0,0 +1,93 @@
+use fluxfox::diskimage::{LoadOptions, RwSectorScope};
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::structure_parsers::system34::{System34CrcParams, System34CrcSpan, System34Standard};
+use fluxfox::{DiskCh, DiskChs, DiskChsn, DiskDataResolution, DiskImage, DiskImageFormat, ImageParser, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const ODD_CRC: System34CrcParams = System34CrcParams {
+    poly: 0x1021,
+    init: 0x0000,
+    span: System34CrcSpan::FromMark,
+};
+
+fn format_buffer(ch: DiskCh) -> Vec<DiskChsn> {
+    (1..=9).map(|s| DiskChsn::new(ch.c(), ch.h(), s, 2)).collect()
+}
+
+fn crc_errors(image: &DiskImage, ch: DiskCh) -> usize {
+    image.get_sector_map()[ch.h() as usize][ch.c() as usize]
+        .sectors
+        .iter()
+        .filter(|s| !s.address_crc_valid || !s.data_crc_valid)
+        .count()
+}
+
+#[test]
+fn test_crc_params() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+    let ch = DiskCh::new(3, 0);
+    assert_eq!(crc_errors(&image, ch), 0);
+
+    // Switching parameters re-checks existing tracks, which were written with the standard CRC.
+    image.set_crc_params(ODD_CRC).unwrap();
+    assert_eq!(image.crc_params(), ODD_CRC);
+    assert_eq!(crc_errors(&image, ch), 9);
+
+    // Tracks formatted and written with the odd parameters read back cleanly.
+    image
+        .format_track(ch, System34Standard::Iso, format_buffer(ch), 0xF6, 0x50)
+        .unwrap();
+    assert_eq!(crc_errors(&image, ch), 0);
+    let chs = DiskChs::new(3, 0, 4);
+    image
+        .write_sector(chs, None, &[0x42; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(!result.address_crc_error && !result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0x42; 512]);
+
+    image.set_crc_params(System34CrcParams::default()).unwrap();
+    assert_eq!(crc_errors(&image, ch), 9);
+    assert_eq!(crc_errors(&image, DiskCh::new(2, 0)), 0);
+}
+
+#[test]
+fn test_crc_params_load_option() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .build()
+        .unwrap();
+    image.set_crc_params(ODD_CRC).unwrap();
+    let ch = DiskCh::new(0, 0);
+    image
+        .format_track(ch, System34Standard::Iso, format_buffer(ch), 0xF6, 0x50)
+        .unwrap();
+
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage.save_image(&image, &mut out).unwrap();
+    let buf = out.into_inner();
+
+    let loaded = DiskImage::load(&mut Cursor::new(buf.clone())).unwrap();
+    assert_eq!(crc_errors(&loaded, ch), 9);
+
+    let options = LoadOptions {
+        crc_params: ODD_CRC,
+        ..Default::default()
+    };
+    let loaded = DiskImage::load_with_options(&mut Cursor::new(buf), options).unwrap();
+    assert_eq!(crc_errors(&loaded, ch), 0);
+}
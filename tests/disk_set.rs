@@ -0,0 +1,100 @@
+use fluxfox::disk_set::{DiskSetIssue, DiskSetManifest};
+use fluxfox::diskimage::LoadOptions;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskImageError, DiskImageFormat, ImageWriter, StandardFormat};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluxfox_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_raw(path: &Path, format: StandardFormat) {
+    let mut image = TestImage::Standard(format).generate().unwrap();
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage
+        .write_image(&mut image, &mut out)
+        .unwrap();
+    std::fs::write(path, out.into_inner()).unwrap();
+}
+
+/// A two-disk set of 360K images, with its manifest saved as `set.json`.
+fn two_disk_set(dir: &Path) -> PathBuf {
+    write_raw(&dir.join("disk1.img"), StandardFormat::PcFloppy360);
+    write_raw(&dir.join("disk2.img"), StandardFormat::PcFloppy360);
+
+    let mut manifest = DiskSetManifest::new("Test Title");
+    manifest.set_base_dir(dir);
+    assert_eq!(manifest.add_disk("disk1.img", "Program Disk"), 1);
+    assert_eq!(manifest.add_disk("disk2.img", "Data Disk"), 2);
+    manifest.record_hashes().unwrap();
+
+    let manifest_path = dir.join("set.json");
+    manifest.save(&manifest_path).unwrap();
+    manifest_path
+}
+
+#[test]
+fn test_disk_set_round_trip() {
+    init();
+    let dir = temp_dir("disk_set");
+    let manifest_path = two_disk_set(&dir);
+
+    let manifest = DiskSetManifest::load(&manifest_path).unwrap();
+    assert_eq!(manifest.title, "Test Title");
+    assert_eq!(manifest.disk_ct(), 2);
+    assert_eq!(manifest.disk(2).unwrap().label, "Data Disk");
+    assert!(manifest.disk(0).is_none());
+    assert!(manifest.disk(3).is_none());
+    assert_eq!(manifest.disk_path(1), Some(dir.join("disk1.img")));
+    assert!(manifest.disk(1).unwrap().sha1.is_some());
+
+    let report = manifest.validate();
+    assert!(report.is_valid(), "{}", report);
+
+    let image = manifest.load_disk(2, LoadOptions::default()).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::RawSectorImage));
+    assert!(matches!(
+        manifest.load_disk(3, LoadOptions::default()),
+        Err(DiskImageError::ParameterError)
+    ));
+    assert!(matches!(
+        DiskSetManifest::from_json("{\"title\": 5}"),
+        Err(DiskImageError::FormatParseError)
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_disk_set_validate() {
+    init();
+    let dir = temp_dir("disk_set_validate");
+    let manifest_path = two_disk_set(&dir);
+
+    let mut manifest = DiskSetManifest::load(&manifest_path).unwrap();
+    std::fs::remove_file(dir.join("disk2.img")).unwrap();
+    write_raw(&dir.join("disk1.img"), StandardFormat::PcFloppy360);
+    let mut modified = std::fs::read(dir.join("disk1.img")).unwrap();
+    modified[0x200] ^= 0xFF;
+    std::fs::write(dir.join("disk1.img"), modified).unwrap();
+    write_raw(&dir.join("disk3.img"), StandardFormat::PcFloppy720);
+    manifest.add_disk("disk3.img", "Extras Disk");
+    manifest.add_disk("disk1.img", "Program Disk (copy)");
+
+    let report = manifest.validate();
+    assert!(!report.is_valid());
+    assert_eq!(report.missing(), vec![2]);
+    assert!(report.issues.contains(&(1, DiskSetIssue::ChecksumMismatch)));
+    assert!(report.issues.contains(&(3, DiskSetIssue::Inconsistent)));
+    assert!(report.issues.contains(&(4, DiskSetIssue::Duplicate { of: 1 })));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
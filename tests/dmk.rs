@@ -0,0 +1,174 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::{DiskChs, DiskDataEncoding, DiskImage, DiskImageFormat, ImageParser, ParserWriteCompatibility};
+use std::io::Cursor;
+
+const TRACK_LEN: usize = 0x1900;
+const MFM_SECTORS: u8 = 10;
+const FM_SECTORS: u8 = 5;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn crc_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn sector_data(c: u8, s: u8) -> Vec<u8> {
+    (0..256).map(|i| (i as u8) ^ c.wrapping_mul(31) ^ s).collect()
+}
+
+/// Append an ID or data field, preceded by its sync bytes, and followed by its CRC.
+fn push_field(track: &mut Vec<u8>, sync: &[u8], field: &[u8]) {
+    track.extend(sync);
+    let mut crc_data = sync.iter().copied().filter(|&b| b == 0xA1).collect::<Vec<_>>();
+    crc_data.extend(field);
+    track.extend(field);
+    track.extend(crc_ccitt(&crc_data).to_be_bytes());
+}
+
+/// Build the IDAM table and bytes of a DMK track. Double density tracks hold sectors numbered from
+/// 1, single density tracks hold sectors numbered from 0 with every byte stored twice.
+fn dmk_track(c: u8, double_density: bool) -> Vec<u8> {
+    let mut track = Vec::new();
+    let mut idams = Vec::new();
+
+    if double_density {
+        track.extend([0x4E; 80]);
+        track.extend([0x00; 12]);
+        track.extend([0xC2, 0xC2, 0xC2, 0xFC]);
+        track.extend([0x4E; 50]);
+        for s in 1..=MFM_SECTORS {
+            track.extend([0x00; 12]);
+            idams.push(track.len() + 3);
+            push_field(&mut track, &[0xA1; 3], &[0xFE, c, 0, s, 1]);
+            track.extend([0x4E; 22]);
+            track.extend([0x00; 12]);
+            let mut field = vec![0xFB];
+            field.extend(sector_data(c, s));
+            push_field(&mut track, &[0xA1; 3], &field);
+            track.extend([0x4E; 24]);
+        }
+        track.resize(TRACK_LEN - 128, 0x4E);
+    } else {
+        track.extend([0xFF; 40]);
+        track.extend([0x00; 6]);
+        track.push(0xFC);
+        track.extend([0xFF; 26]);
+        for s in 0..FM_SECTORS {
+            track.extend([0x00; 6]);
+            idams.push(track.len());
+            push_field(&mut track, &[], &[0xFE, c, 0, s, 1]);
+            track.extend([0xFF; 11]);
+            track.extend([0x00; 6]);
+            let mut field = vec![0xFB];
+            field.extend(sector_data(c, s));
+            push_field(&mut track, &[], &field);
+            track.extend([0xFF; 27]);
+        }
+        track.resize((TRACK_LEN - 128) / 2, 0xFF);
+        track = track.iter().flat_map(|&b| [b, b]).collect();
+        idams.iter_mut().for_each(|idam| *idam *= 2);
+    }
+
+    let mut table = vec![0u8; 128];
+    for (i, idam) in idams.iter().enumerate() {
+        let mut pointer = (128 + idam) as u16;
+        if double_density {
+            pointer |= 0x8000;
+        }
+        table[i * 2..i * 2 + 2].copy_from_slice(&pointer.to_le_bytes());
+    }
+    table.extend(track);
+    table
+}
+
+/// A single-sided DMK image of two tracks, the first double density and the second single density.
+fn dmk_image() -> Vec<u8> {
+    let mut dmk = vec![0xFF, 2];
+    dmk.extend((TRACK_LEN as u16).to_le_bytes());
+    dmk.push(0x10);
+    dmk.extend([0; 11]);
+    dmk.extend(dmk_track(0, true));
+    dmk.extend(dmk_track(1, false));
+    dmk
+}
+
+#[test]
+fn test_dmk_load() {
+    init();
+
+    let mut in_buffer = Cursor::new(dmk_image());
+    assert!(DiskImageFormat::DmkImage.detect(&mut in_buffer));
+    let mut image = DiskImage::load(&mut in_buffer).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::DmkImage));
+    assert_eq!(image.image_format().geometry.c(), 2);
+    assert_eq!(image.image_format().geometry.h(), 1);
+    assert_eq!(image.image_format().write_protect, Some(true));
+    assert!(matches!(
+        DiskImageFormat::DmkImage.can_write(&image),
+        ParserWriteCompatibility::UnsupportedFormat
+    ));
+
+    let sector_map = image.get_sector_map();
+    assert!(matches!(sector_map[0][0].encoding, DiskDataEncoding::Mfm));
+    assert!(matches!(sector_map[0][1].encoding, DiskDataEncoding::Fm));
+    assert_eq!(sector_map[0][0].sectors.len(), MFM_SECTORS as usize);
+    assert_eq!(sector_map[0][1].sectors.len(), FM_SECTORS as usize);
+
+    for (c, sectors) in [(0u8, 1..=MFM_SECTORS), (1u8, 0..=FM_SECTORS - 1)] {
+        for s in sectors {
+            let chs = DiskChs::new(c as u16, 0, s);
+            let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+            assert_eq!(
+                rsr.read_buf[..rsr.data_len],
+                sector_data(c, s),
+                "sector {} does not match",
+                chs
+            );
+            assert!(!rsr.address_crc_error, "sector {}", chs);
+            assert!(!rsr.data_crc_error, "sector {}", chs);
+        }
+    }
+}
+
+#[test]
+fn test_dmk_detect() {
+    init();
+
+    let mut dmk = dmk_image();
+    // A file one byte short of the size given by its header is not a DMK image.
+    dmk.pop();
+    assert!(!DiskImageFormat::DmkImage.detect(Cursor::new(&dmk)));
+
+    // Nor is one whose IDAM table points outside the track.
+    let mut dmk = dmk_image();
+    dmk[16..18].copy_from_slice(&0x9900u16.to_le_bytes());
+    assert!(!DiskImageFormat::DmkImage.detect(Cursor::new(&dmk)));
+}
+
+#[test]
+fn test_dmk_truncated() {
+    init();
+
+    // An image cut short in its last track holds only the tracks read in full.
+    let mut dmk = dmk_image();
+    dmk.truncate(dmk.len() - 100);
+    let image = DiskImageFormat::DmkImage.load_image(Cursor::new(dmk)).unwrap();
+    assert_eq!(image.load_warnings().len(), 1);
+    assert!(image.load_warnings()[0]
+        .message
+        .contains("Image ends before the last track"));
+    assert_eq!(image.image_format().geometry.c(), 1);
+}
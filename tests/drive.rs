@@ -0,0 +1,60 @@
+use fluxfox::drive::{DriveState, DriveTiming};
+use fluxfox::fdc::{Upd765Status, Wd177xStatus, ST0_NOT_READY, WD_MOTOR_ON};
+use fluxfox::media::MediaProfile;
+use std::time::Duration;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn test_drive_state() {
+    let timing = DriveTiming::for_profile(MediaProfile::Dd5_25);
+    let mut drive = DriveState::new(MediaProfile::Dd5_25);
+
+    // A stopped drive is never ready.
+    assert!(!drive.is_ready(ms(0)));
+    assert_eq!(drive.ready_at(), None);
+    assert_eq!(drive.bit_position(ms(0), 100_000), None);
+
+    drive.set_motor(true, ms(100));
+    assert!(!drive.is_ready(ms(100)));
+    assert_eq!(drive.ready_at(), Some(ms(100) + timing.spin_up));
+    assert!(drive.is_ready(ms(100) + timing.spin_up));
+
+    // Switching the motor on again doesn't restart the spin-up.
+    drive.set_motor(true, ms(300));
+    assert_eq!(drive.ready_at(), Some(ms(100) + timing.spin_up));
+
+    // Seek 10 cylinders once spun up.
+    let now = ms(1000);
+    let done = drive.seek(10, now);
+    assert_eq!(done, now + timing.step_rate * 10 + timing.head_settle);
+    assert_eq!(drive.cylinder(), 10);
+    assert!(!drive.is_ready(now));
+    assert_eq!(drive.operation_delay(now), Some(done - now));
+
+    // A seek to the current cylinder is immediate.
+    assert_eq!(drive.seek(10, done), done);
+
+    // 300RPM is 200ms per revolution.
+    assert_eq!(drive.bit_position(ms(100), 100_000), Some(0));
+    assert_eq!(drive.bit_position(ms(150), 100_000), Some(25_000));
+    assert_eq!(drive.bit_position(ms(350), 100_000), Some(25_000));
+
+    drive.set_motor(false, ms(2000));
+    assert!(!drive.is_ready(ms(2000)));
+}
+
+#[test]
+fn test_drive_status() {
+    let mut drive = DriveState::new(MediaProfile::Hd3_5);
+
+    let status = Upd765Status::not_ready(0, 1);
+    assert!(status.is_error());
+    assert_eq!(status.st0 & ST0_NOT_READY, ST0_NOT_READY);
+
+    assert_eq!(Wd177xStatus::default().with_motor(&drive).bits(), 0);
+    drive.set_motor(true, ms(0));
+    assert_eq!(Wd177xStatus::default().with_motor(&drive).bits(), WD_MOTOR_ON);
+}
@@ -0,0 +1,85 @@
+use fluxfox::diskimage::{DiskImageFlags, RwSectorScope};
+use fluxfox::drive_bay::{DriveBay, DriveEvent};
+use fluxfox::media::MediaProfile;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskChs, DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_drive_bay() {
+    init();
+
+    let mut bay = DriveBay::new(&[MediaProfile::Hd5_25, MediaProfile::Dd5_25]);
+    assert_eq!(bay.len(), 2);
+    assert!(bay.drive(0).unwrap().is_write_protected());
+    assert!(bay
+        .insert(2, TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap())
+        .is_err());
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert!(bay.insert(1, image).unwrap().is_none());
+    assert!(bay.drive(1).unwrap().has_disk());
+    assert!(!bay.drive(1).unwrap().is_write_protected());
+    assert_eq!(bay.take_events(), vec![DriveEvent::Inserted(1)]);
+    assert!(bay.take_events().is_empty());
+
+    // The disk change line stays set until the drive is stepped with a disk present.
+    assert!(bay.drive(1).unwrap().disk_changed());
+    bay.clear_disk_change(1);
+    assert!(!bay.drive(1).unwrap().disk_changed());
+    bay.clear_disk_change(0);
+    assert!(bay.drive(0).unwrap().disk_changed());
+
+    // Modify the disk and flush it.
+    assert!(bay.dirty_drives().is_empty());
+    bay.image_mut(1)
+        .unwrap()
+        .write_sector(
+            DiskChs::new(0, 0, 1),
+            None,
+            &[0xAA; 512],
+            RwSectorScope::DataOnly,
+            false,
+            false,
+        )
+        .unwrap();
+    bay.image_mut(1).unwrap().set_flag(DiskImageFlags::DIRTY);
+    assert_eq!(bay.dirty_drives(), vec![1]);
+
+    // A failed save leaves the image dirty.
+    assert!(bay.flush(|_, _| Err(DiskImageError::IoError)).is_err());
+    assert_eq!(bay.dirty_drives(), vec![1]);
+
+    let mut saved_drives = Vec::new();
+    let saved = bay
+        .flush(|drive, _| {
+            saved_drives.push(drive);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(saved, 1);
+    assert_eq!(saved_drives, vec![1]);
+    assert!(bay.dirty_drives().is_empty());
+
+    // Write protect follows the image's READONLY flag.
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    image.set_flag(DiskImageFlags::READONLY);
+    bay.image_mut(1).unwrap().set_flag(DiskImageFlags::DIRTY);
+    let ejected = bay.insert(1, image).unwrap();
+    assert!(ejected.is_some());
+    assert!(bay.drive(1).unwrap().is_write_protected());
+    assert_eq!(
+        bay.take_events(),
+        vec![
+            DriveEvent::Ejected { drive: 1, dirty: true },
+            DriveEvent::Inserted(1),
+            DriveEvent::WriteProtect(1, true)
+        ]
+    );
+
+    assert!(bay.eject(1).unwrap().is_some());
+    assert!(bay.eject(1).unwrap().is_none());
+}
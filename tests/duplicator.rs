@@ -0,0 +1,94 @@
+use fluxfox::bitstream::mfm::MfmEncodingType;
+use fluxfox::diskimage::MatchPolicy;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{DiskCh, DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+/// Rewrite every track of `image` with nine sectors, preceded by an IAM after `gap4a` bytes if
+/// `gap4a` is `Some`, then `gap1` bytes of gap.
+fn relayout(image: &mut DiskImage, gap4a: Option<usize>, gap1: usize) {
+    for h in 0..2 {
+        for c in 0..40 {
+            let mut builder = TrackBuilder::new();
+            if let Some(gap4a) = gap4a {
+                builder = builder
+                    .with_gap(0x4E, gap4a)
+                    .with_gap(0x00, 12)
+                    .with_encoded(&[0xC2, 0xC2, 0xC2, 0xFC], MfmEncodingType::AddressMark);
+            }
+            builder = builder.with_gap(0x4E, gap1);
+            for s in 1..=9 {
+                if s > 1 {
+                    builder = builder.with_gap(0x4E, 80);
+                }
+                builder = builder
+                    .with_sector(image, DiskChs::new(c, h, s), None, MatchPolicy::Chsn)
+                    .unwrap();
+            }
+            image.splice_track(DiskCh::new(c, h), &builder).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_duplicator_iso() {
+    init();
+
+    let image = build_image();
+    let report = image.duplicator_report();
+    let fingerprint = &report.fingerprint;
+    assert_eq!(fingerprint.tracks.len(), 80);
+    assert!(!fingerprint.iam());
+    assert_eq!(fingerprint.gap4a(), None);
+    assert_eq!(fingerprint.gap_byte(), Some(0x4E));
+    assert!(fingerprint.index_aligned());
+    assert_eq!(report.best().unwrap().name, "ISO format (controller)");
+}
+
+#[test]
+fn test_duplicator_signatures() {
+    init();
+
+    let mut image = build_image();
+    relayout(&mut image, Some(80), 50);
+    let report = image.duplicator_report();
+    assert!(report.fingerprint.iam());
+    assert_eq!(report.fingerprint.gap4a(), Some(80));
+    assert_eq!(report.fingerprint.gap1(), Some(50));
+    assert_eq!(report.best().unwrap().name, "IBM System 34 format (controller)");
+
+    let mut image = build_image();
+    relayout(&mut image, None, 16);
+    let report = image.duplicator_report();
+    assert_eq!(report.best().unwrap().name, "Formaster");
+    assert!(report.to_string().contains("Signature: Formaster"));
+
+    let mut image = build_image();
+    relayout(&mut image, Some(20), 50);
+    assert_eq!(image.duplicator_report().best().unwrap().name, "Trace");
+
+    // Tracks written at varying offsets from the index match no signature.
+    let mut image = build_image();
+    relayout(&mut image, None, 16);
+    let builder = TrackBuilder::new()
+        .with_gap(0x4E, 100)
+        .with_sector(&image, DiskChs::new(0, 0, 1), None, MatchPolicy::Chsn)
+        .unwrap();
+    image.splice_track(DiskCh::new(0, 0), &builder).unwrap();
+    let report = image.duplicator_report();
+    assert!(!report.fingerprint.index_aligned());
+    assert!(report.best().is_none());
+}
@@ -0,0 +1,117 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::platform::DiskPlatform;
+use fluxfox::{DiskCh, DiskChs, DiskDataEncoding, DiskDensity, DiskImage, DiskImageFormat, ImageParser};
+use std::io::Cursor;
+
+const CYLINDERS: u8 = 77;
+const SECTORS: u8 = 26;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// The contents of each sector of a single sided, single density 8" CP/M disk: the directory
+/// at the start of cylinder 2, after the two system tracks, and a fill pattern elsewhere.
+fn sector_data(c: u8, s: u8) -> Vec<u8> {
+    let mut data = vec![0xE5; 128];
+    if (c, s) == (2, 1) {
+        data[..32].fill(0);
+        data[1..12].copy_from_slice(b"PIP     COM");
+    } else if c != 2 {
+        data.fill(c ^ s.wrapping_mul(7));
+    }
+    data
+}
+
+/// Build an IMD image of the classic 8" CP/M format: 77 cylinders of 26 128-byte FM sectors.
+fn build_imd() -> Vec<u8> {
+    let mut imd = b"IMD 1.18: 16/10/2024 12:00:00\r\nCP/M 2.2 8\" SSSD".to_vec();
+    imd.push(0x1A);
+    for c in 0..CYLINDERS {
+        // Mode 0 is FM at 500kbps.
+        imd.extend([0, c, 0, SECTORS, 0]);
+        imd.extend(1..=SECTORS);
+        for s in 1..=SECTORS {
+            imd.push(0x01);
+            imd.extend(sector_data(c, s));
+        }
+    }
+    imd
+}
+
+fn td0_crc(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = (crc << 1) ^ if crc & 0x8000 != 0 { 0xA097 } else { 0 };
+        }
+    }
+    crc
+}
+
+/// Build an uncompressed TeleDisk image of the same disk, marked single density in the header.
+fn build_td0() -> Vec<u8> {
+    // Data rate 2 (500kbps) with the FM flag, drive type 4 (8"), one head.
+    let mut td0 = vec![b'T', b'D', 0, 0, 21, 0x82, 4, 0, 0, 1];
+    td0.extend(td0_crc(&td0).to_le_bytes());
+    for c in 0..CYLINDERS {
+        let header = [SECTORS, c, 0];
+        td0.extend(header);
+        td0.push(td0_crc(&header) as u8);
+        for s in 1..=SECTORS {
+            let data = sector_data(c, s);
+            td0.extend([c, 0, s, 0, 0, td0_crc(&data) as u8]);
+            td0.extend(129u16.to_le_bytes());
+            td0.push(0);
+            td0.extend(data);
+        }
+    }
+    td0.push(0xFF);
+    td0
+}
+
+fn check_image(mut image: DiskImage) {
+    let format = image.image_format();
+    assert_eq!(format.geometry, DiskCh::new(77, 1));
+    assert!(matches!(format.data_encoding, DiskDataEncoding::Fm));
+    assert!(matches!(format.density, DiskDensity::Standard));
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map.len(), 1);
+    assert_eq!(sector_map[0].len(), 77);
+    for track in &sector_map[0] {
+        assert!(matches!(track.encoding, DiskDataEncoding::Fm));
+        assert_eq!(track.sectors.len(), 26);
+        assert!(track.sectors.iter().all(|s| s.chsn.n() == 0));
+    }
+
+    let result = image
+        .read_sector(DiskChs::new(76, 0, 26), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, sector_data(76, 26));
+
+    let health = image.health();
+    assert_eq!(health.expected_sectors, 77 * 26);
+    assert_eq!(health.score(), 100);
+    assert_eq!(image.detect_platform().platform(), DiskPlatform::Cpm);
+
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage.save_image(&image, &mut out).unwrap();
+    let expected: Vec<u8> = (0..CYLINDERS)
+        .flat_map(|c| (1..=SECTORS).flat_map(move |s| sector_data(c, s)))
+        .collect();
+    assert_eq!(out.into_inner(), expected);
+}
+
+#[test]
+fn test_eight_inch_imd() {
+    init();
+    check_image(DiskImage::load(&mut Cursor::new(build_imd())).unwrap());
+}
+
+#[test]
+fn test_eight_inch_td0() {
+    init();
+    check_image(DiskImage::load(&mut Cursor::new(build_td0())).unwrap());
+}
@@ -0,0 +1,84 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskCh, DiskChs, DiskImage, DiskImageError, DiskImageFormat, ImageParser};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Save `image` in `format` and load it back.
+fn round_trip(image: &DiskImage, format: DiskImageFormat) -> DiskImage {
+    let mut out_buffer = Cursor::new(Vec::new());
+    format.save_image(image, &mut out_buffer).unwrap();
+    DiskImage::load(&mut Cursor::new(out_buffer.into_inner())).unwrap()
+}
+
+#[test]
+fn test_extract_tracks_bytestream() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut Cursor::new(buf)).unwrap();
+
+    let subset = image.extract_tracks(38..=39, None).unwrap();
+    assert_eq!(subset.image_format().geometry, DiskCh::new(40, 2));
+
+    let mut hfe_image = round_trip(&subset, DiskImageFormat::HfeImage);
+    for chs in [DiskChs::new(38, 0, 1), DiskChs::new(39, 1, 9)] {
+        let expected = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        let rsr = hfe_image
+            .read_sector(chs, None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert_eq!(rsr.read_buf, expected.read_buf, "sector {} does not match", chs);
+    }
+
+    // Cylinders before the selection are unformatted.
+    let sector_map = hfe_image.get_sector_map();
+    assert!(sector_map[0][0].sectors.is_empty());
+    assert!(sector_map[1][37].sectors.is_empty());
+    assert_eq!(sector_map[1][38].sectors.len(), 9);
+}
+
+#[test]
+fn test_extract_tracks_single_head() {
+    init();
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let subset = image
+        .extract_tracks(TEST_QUIRK_CYLINDER..=TEST_QUIRK_CYLINDER, Some(0))
+        .unwrap();
+    assert_eq!(subset.image_format().geometry, DiskCh::new(TEST_QUIRK_CYLINDER + 1, 1));
+
+    let mut pri_image = round_trip(&subset, DiskImageFormat::PceBitstreamImage);
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let expected = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let rsr = pri_image
+        .read_sector(chs, None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, expected.read_buf);
+}
+
+#[test]
+fn test_extract_tracks_out_of_range() {
+    init();
+
+    let image = TestImage::Standard(fluxfox::StandardFormat::PcFloppy360)
+        .generate()
+        .unwrap();
+    assert!(matches!(
+        image.extract_tracks(39..=40, None),
+        Err(DiskImageError::SeekError)
+    ));
+    assert!(matches!(
+        image.extract_tracks(0..=0, Some(2)),
+        Err(DiskImageError::SeekError)
+    ));
+    #[allow(clippy::reversed_empty_ranges)]
+    let empty = 5..=4;
+    assert!(matches!(
+        image.extract_tracks(empty, None),
+        Err(DiskImageError::SeekError)
+    ));
+}
@@ -0,0 +1,147 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::fdc::*;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::util::crc_ccitt;
+use fluxfox::{DiskCh, DiskChs, DiskChsn, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_fdc_status() {
+    init();
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 1, TEST_QUIRK_SECTOR);
+
+    // A good sector reports normal termination with the head and unit in ST0.
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let status = Upd765Status::from_read_sector(&result, 1, 2);
+    assert_eq!(
+        status,
+        Upd765Status {
+            st0: 0x06,
+            st1: 0,
+            st2: 0
+        }
+    );
+    assert!(!status.is_error());
+    assert_eq!(Wd177xStatus::from_read_sector(&result).bits(), 0);
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let status = Upd765Status::from_read_sector(&result, 0, 0);
+    assert_eq!(status.st0, ST0_ABNORMAL_TERMINATION);
+    assert_eq!(status.st1, ST1_DATA_ERROR);
+    assert_eq!(status.st2, ST2_DATA_ERROR_IN_DATA);
+    assert_eq!(Wd177xStatus::from_read_sector(&result).bits(), WD_CRC_ERROR);
+
+    let mut image = TestImage::BadAddressCrc.generate().unwrap();
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let status = Upd765Status::from_read_sector(&result, 0, 0);
+    assert_eq!(status.st1, ST1_DATA_ERROR);
+    assert_eq!(status.st2, 0);
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let status = Upd765Status::from_read_sector(&result, 0, 0);
+    assert!(status.is_error());
+    assert_eq!(status.st2, ST2_CONTROL_MARK);
+    assert_eq!(Wd177xStatus::from_read_sector(&result).bits(), WD_RECORD_TYPE);
+    assert!(!Wd177xStatus::from_read_sector(&result).is_error());
+
+    let status = Upd765Status::not_found(0, 0, false);
+    assert_eq!(status.st1, ST1_NO_DATA);
+    assert!(Wd177xStatus::not_found().is_error());
+}
+
+/// Build a WD177x Write Track data stream for a 9 sector, 512 byte per sector track.
+fn wd_format_buffer(c: u8, h: u8, fill: u8) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend([0x4E; 80]);
+    buf.extend([0x00; 12]);
+    buf.extend([0xF6, 0xF6, 0xF6, 0xFC]);
+    buf.extend([0x4E; 50]);
+    for s in 1..=9 {
+        buf.extend([0x00; 12]);
+        buf.extend([0xF5, 0xF5, 0xF5, 0xFE, c, h, s, 0x02, 0xF7]);
+        buf.extend([0x4E; 22]);
+        buf.extend([0x00; 12]);
+        buf.extend([0xF5, 0xF5, 0xF5, 0xFB]);
+        buf.extend([fill; 512]);
+        buf.push(0xF7);
+        buf.extend([0x4E; 84]);
+    }
+    buf
+}
+
+#[test]
+fn test_wd_read_address() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+
+    // Walk every ID field on the track in rotational order.
+    let mut bit_index = 0;
+    for s in 1..=9 {
+        let result = image.read_address(ch, bit_index).unwrap();
+        assert_eq!(result.chsn, DiskChsn::new(0, 0, s, 2));
+        assert!(!result.address_crc_error);
+        assert_eq!(result.id_field[0..4], [0, 0, s, 2]);
+
+        let crc = crc_ccitt(&[0xA1, 0xA1, 0xA1, 0xFE, 0, 0, s, 2], None);
+        assert_eq!(result.id_field[4..6], crc.to_be_bytes());
+        assert_eq!(Wd177xStatus::from_read_address(&result).bits(), 0);
+        bit_index = result.bit_index + 1;
+    }
+
+    // Read Address reports ID fields with bad CRCs.
+    let mut image = TestImage::BadAddressCrc.generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let mut bit_index = 0;
+    let result = loop {
+        let result = image.read_address(ch, bit_index).unwrap();
+        if result.chsn.s() == TEST_QUIRK_SECTOR {
+            break result;
+        }
+        bit_index = result.bit_index + 1;
+    };
+    assert!(result.address_crc_error);
+    assert_eq!(Wd177xStatus::from_read_address(&result).bits(), WD_CRC_ERROR);
+}
+
+#[test]
+fn test_wd_write_track() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(2, 1);
+
+    let mut buf = wd_format_buffer(2, 1, 0xE5);
+    buf.extend([0x4E; 400]);
+    let result = image.write_track(ch, &buf).unwrap();
+    assert!(!result.lost_data);
+    assert_eq!(Wd177xStatus::from_write_track(&result, false).bits(), 0);
+
+    for s in 1..=9 {
+        let result = image
+            .read_sector(DiskChs::new(2, 1, s), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert!(!result.address_crc_error && !result.data_crc_error);
+        assert!(result.read_buf.iter().all(|&b| b == 0xE5));
+    }
+
+    // Read Track returns the ID fields, synchronized on the A1 marks.
+    let track = image.read_track_raw(ch).unwrap();
+    let id_field = [0xA1, 0xA1, 0xA1, 0xFE, 2, 1, 5, 2];
+    assert!(track.windows(id_field.len()).any(|w| w == id_field));
+
+    // A buffer that runs out before the index loses data.
+    let result = image.write_track(ch, &wd_format_buffer(2, 1, 0xE5)[..1000]).unwrap();
+    assert!(result.lost_data);
+    assert_eq!(Wd177xStatus::from_write_track(&result, false).bits(), WD_LOST_DATA);
+}
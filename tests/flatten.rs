@@ -0,0 +1,71 @@
+use fluxfox::diskimage::{DiskImageFlags, RwSectorScope};
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskDataResolution, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_flatten_standard() {
+    init();
+
+    let original = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    image.clear_flag(DiskImageFlags::DIRTY);
+    image.flatten().unwrap();
+
+    assert!(image.resolution() == DiskDataResolution::ByteStream);
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+    assert!(image.verify_dump(&original).unwrap().is_verified());
+
+    let boot_sector = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(&boot_sector.read_buf[510..512], &[0x55, 0xAA]);
+}
+
+#[test]
+fn test_flatten_keeps_flags() {
+    init();
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    for test_image in [TestImage::BadAddressCrc, TestImage::BadDataCrc, TestImage::DeletedData] {
+        let original = test_image.generate().unwrap();
+        let mut image = test_image.generate().unwrap();
+        image.flatten().unwrap();
+
+        let entry = |image: &fluxfox::DiskImage| {
+            image.get_sector_map()[0][TEST_QUIRK_CYLINDER as usize]
+                .sectors
+                .iter()
+                .find(|s| DiskChs::from(s.chsn) == chs)
+                .cloned()
+                .unwrap()
+        };
+        let (before, after) = (entry(&original), entry(&image));
+        assert_eq!(after.address_crc_valid, before.address_crc_valid, "{:?}", test_image);
+        assert_eq!(after.data_crc_valid, before.data_crc_valid, "{:?}", test_image);
+        assert_eq!(after.deleted_mark, before.deleted_mark, "{:?}", test_image);
+    }
+}
+
+#[test]
+fn test_flatten_weak_bits() {
+    init();
+
+    let mut image = TestImage::WeakBits.generate().unwrap();
+    image.flatten().unwrap();
+
+    let result = image
+        .read_sector(
+            DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR),
+            None,
+            RwSectorScope::DataOnly,
+            false,
+        )
+        .unwrap();
+    let weak_mask = result.weak_mask.expect("weak bits not kept");
+    assert!(weak_mask[..16].iter().all(|&b| b == 0xFF));
+    assert!(weak_mask[16..].iter().all(|&b| b == 0));
+}
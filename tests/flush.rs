@@ -0,0 +1,79 @@
+use fluxfox::diskimage::DiskImageFlags;
+use fluxfox::drive_bay::DriveBay;
+use fluxfox::media::MediaProfile;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskDataRate, DiskImage, DiskRpm, StandardFormat};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluxfox_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_flush_to() {
+    init();
+
+    let dir = temp_dir("flush");
+    let path = dir.join("disk.img");
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    // A clean image is not written.
+    image.clear_flag(DiskImageFlags::DIRTY);
+    assert!(!image.flush_to(&path).unwrap());
+    assert!(!path.exists());
+
+    image.set_flag(DiskImageFlags::DIRTY);
+    assert!(image.flush_to(&path).unwrap());
+    assert!(!image.has_flag(DiskImageFlags::DIRTY));
+
+    let image_buf = std::fs::read(&path).unwrap();
+    assert_eq!(image_buf.len(), StandardFormat::PcFloppy360.size());
+    DiskImage::load(&mut Cursor::new(image_buf)).unwrap();
+
+    // A failed save leaves the original file intact. 86F can't store a mixed-density image.
+    let f86_path = dir.join("disk.86f");
+    std::fs::write(&f86_path, b"original").unwrap();
+    image
+        .get_track_mut(0)
+        .unwrap()
+        .resample(DiskDataRate::Rate500Kbps, DiskRpm::Rpm300)
+        .unwrap();
+    assert!(image.flush_to(&f86_path).is_err());
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+    assert_eq!(std::fs::read(&f86_path).unwrap(), b"original");
+
+    // No temporary files are left behind.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_eject_to() {
+    init();
+
+    let dir = temp_dir("eject");
+    let path = dir.join("disk.img");
+
+    let mut bay = DriveBay::new(&[MediaProfile::Dd5_25]);
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    image.set_flag(DiskImageFlags::DIRTY);
+    bay.insert(0, image).unwrap();
+
+    let ejected = bay.eject_to(0, &path).unwrap().unwrap();
+    assert!(!ejected.has_flag(DiskImageFlags::DIRTY));
+    assert!(!bay.drive(0).unwrap().has_disk());
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().len() as usize,
+        StandardFormat::PcFloppy360.size()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,137 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::flux::FluxRevolution;
+use fluxfox::testutil::{track_stream, TestImage};
+use fluxfox::{
+    DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskImage, DiskRpm, StandardFormat,
+};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Capture clock ticks per bitcell of a 250Kbps MFM track at a 25ns capture resolution.
+const TICKS_PER_CELL: u32 = 80;
+const TICK: f64 = 25e-9;
+
+/// Convert the bitstream of the track at `ch` into a revolution of ideal flux transitions.
+fn track_flux(image: &DiskImage, ch: DiskCh) -> FluxRevolution {
+    track_flux_with_tick(image, ch, TICK)
+}
+
+/// Convert the bitstream of the track at `ch` into a revolution of ideal flux transitions, with
+/// each bitcell lasting `TICKS_PER_CELL` ticks of `tick` seconds.
+fn track_flux_with_tick(image: &DiskImage, ch: DiskCh, tick: f64) -> FluxRevolution {
+    let stream = track_stream(image, ch).unwrap();
+    let bytes = stream.data();
+    let mut intervals = Vec::new();
+    let mut last = 0;
+    for i in 0..stream.len() {
+        if (bytes[i >> 3] >> (7 - (i & 0x07))) & 0x01 != 0 {
+            intervals.push((i + 1 - last) as u32 * TICKS_PER_CELL);
+            last = i + 1;
+        }
+    }
+    FluxRevolution::new(intervals, tick, stream.len() as u64 * TICKS_PER_CELL as u64)
+}
+
+#[test]
+fn test_flux_revolutions_weak_bits() {
+    init();
+
+    let ch = DiskCh::new(0, 0);
+    let weak_chs = DiskChs::new(0, 0, 4);
+    let original = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut modified = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    modified
+        .write_sector(weak_chs, None, &[0xA5; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    let revolutions = vec![
+        track_flux(&original, ch),
+        track_flux(&original, ch),
+        track_flux(&modified, ch),
+    ];
+    image
+        .add_track_fluxstream(DiskDataEncoding::Mfm, DiskDataRate::Rate250Kbps, ch, revolutions)
+        .unwrap();
+
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(track.revolutions().len(), 3);
+    assert!(track.flux().is_some());
+
+    // The sector that differs between revolutions reads as weak; the others do not.
+    let weak = image
+        .read_sector(weak_chs, None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(weak.weak_mask.is_some_and(|mask| mask.iter().any(|&b| b != 0)));
+    for s in [1, 3, 5, 9] {
+        let rsr = image
+            .read_sector(DiskChs::new(0, 0, s), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert!(!rsr.data_crc_error);
+        assert!(rsr.weak_mask.is_none(), "sector {} is weak", s);
+    }
+}
+
+#[test]
+fn test_flux_revolutions_empty() {
+    init();
+
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    assert!(image
+        .add_track_fluxstream(
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            DiskCh::new(0, 0),
+            Vec::new()
+        )
+        .is_err());
+}
+
+#[test]
+fn test_flux_revolutions_360rpm_data_rate() {
+    init();
+
+    let ch = DiskCh::new(0, 0);
+    let source = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    // A 360K track captured in a 300 RPM drive reads at its nominal rate.
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    image
+        .add_track_fluxstream(
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            ch,
+            vec![track_flux(&source, ch)],
+        )
+        .unwrap();
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(track.capture_rpm(), Some(DiskRpm::Rpm300));
+    assert_eq!(track.nominal_data_rate(), DiskDataRate::Rate250Kbps);
+
+    // The same track captured in a 360 RPM drive passes under the head 20% faster, at 300Kbps.
+    let tick = 1.0 / (600_000.0 * TICKS_PER_CELL as f64);
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    image
+        .add_track_fluxstream(
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate300Kbps,
+            ch,
+            vec![track_flux_with_tick(&source, ch, tick)],
+        )
+        .unwrap();
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(track.capture_rpm(), Some(DiskRpm::Rpm360));
+    assert_eq!(track.data_rate(), DiskDataRate::Rate300Kbps);
+    assert_eq!(track.nominal_data_rate(), DiskDataRate::Rate250Kbps);
+
+    let entry = &image.get_sector_map()[0][0];
+    assert_eq!(entry.capture_rpm, Some(DiskRpm::Rpm360));
+    assert_eq!(entry.nominal_data_rate, DiskDataRate::Rate250Kbps);
+    assert_eq!(entry.sectors.len(), 9);
+}
@@ -0,0 +1,176 @@
+use fluxfox::bitstream::fm::{FmCodec, FmEncodingType};
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::{DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, ImageParser};
+use std::io::Cursor;
+
+const SECTORS: u8 = 8;
+const TRACK_BYTES: usize = 3125;
+const DELETED_SECTOR: u8 = 3;
+const BAD_CRC_SECTOR: u8 = 6;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn crc_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn sector_data(c: u8, s: u8) -> Vec<u8> {
+    (0..256).map(|i| (i as u8) ^ c.wrapping_mul(31) ^ s).collect()
+}
+
+/// Encode a single density track of 256-byte sectors, with a deleted sector and a sector with a
+/// bad data CRC.
+fn build_fm_track(c: u8) -> Vec<u8> {
+    let mut bits = FmCodec::encode_fm(&[0xFF; 40], FmEncodingType::Data);
+    let mut push = |bytes: &[u8], encoding_type: FmEncodingType| {
+        bits.extend(FmCodec::encode_fm(bytes, encoding_type).iter());
+    };
+
+    push(&[0x00; 6], FmEncodingType::Data);
+    push(&[0xFC], FmEncodingType::AddressMark);
+    push(&[0xFF; 26], FmEncodingType::Data);
+
+    for s in 1..=SECTORS {
+        let id = [0xFE, c, 0, s, 1];
+        push(&[0x00; 6], FmEncodingType::Data);
+        push(&id[..1], FmEncodingType::AddressMark);
+        push(&id[1..], FmEncodingType::Data);
+        push(&crc_ccitt(&id).to_be_bytes(), FmEncodingType::Data);
+        push(&[0xFF; 11], FmEncodingType::Data);
+
+        let mark = if s == DELETED_SECTOR { 0xF8 } else { 0xFB };
+        let mut field = vec![mark];
+        field.extend(sector_data(c, s));
+        let mut crc = crc_ccitt(&field);
+        if s == BAD_CRC_SECTOR {
+            crc ^= 0x5555;
+        }
+        push(&[0x00; 6], FmEncodingType::Data);
+        push(&field[..1], FmEncodingType::AddressMark);
+        push(&field[1..], FmEncodingType::Data);
+        push(&crc.to_be_bytes(), FmEncodingType::Data);
+        push(&[0xFF; 27], FmEncodingType::Data);
+    }
+
+    // Fill out the rest of the track with gap bytes. Each byte encodes to two bytes of bitcells.
+    push(&[0xFF; 600], FmEncodingType::Data);
+    let mut track = bits.to_bytes();
+    track.truncate(TRACK_BYTES * 2);
+    track
+}
+
+fn build_fm_image() -> DiskImage {
+    let mut image = DiskImage::default();
+    for c in 0..2 {
+        let track = build_fm_track(c);
+        image
+            .add_track_bitstream(
+                DiskDataEncoding::Fm,
+                DiskDataRate::Rate125Kbps,
+                DiskCh::new(c as u16, 0),
+                250_000,
+                Some(track.len() * 8),
+                &track,
+                None,
+            )
+            .unwrap();
+    }
+    image
+}
+
+fn verify_sectors(image: &mut DiskImage) {
+    for c in 0..2 {
+        for s in 1..=SECTORS {
+            let chs = DiskChs::new(c as u16, 0, s);
+            let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+            assert_eq!(
+                rsr.read_buf[..rsr.data_len],
+                sector_data(c, s),
+                "sector {} does not match",
+                chs
+            );
+            assert!(!rsr.address_crc_error, "sector {}", chs);
+            assert_eq!(rsr.data_crc_error, s == BAD_CRC_SECTOR, "sector {}", chs);
+            assert_eq!(rsr.deleted_mark, s == DELETED_SECTOR, "sector {}", chs);
+        }
+    }
+}
+
+#[test]
+fn test_fm_read_sectors() {
+    init();
+    let mut image = build_fm_image();
+    verify_sectors(&mut image);
+}
+
+#[test]
+fn test_fm_pri_round_trip() {
+    init();
+    let image = build_fm_image();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    out_buffer.set_position(0);
+
+    // PRI does not record the track encoding, so FM must be detected from the bitstream.
+    let mut reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    assert!(matches!(reloaded.image_format().data_encoding, DiskDataEncoding::Fm));
+
+    let sector_map = reloaded.get_sector_map();
+    let ids = sector_map[0][1].sectors.iter().map(|s| s.chsn.s()).collect::<Vec<_>>();
+    assert_eq!(ids, (1..=SECTORS).collect::<Vec<_>>());
+    assert!(matches!(sector_map[0][1].encoding, DiskDataEncoding::Fm));
+
+    verify_sectors(&mut reloaded);
+}
+
+#[test]
+fn test_fm_86f_round_trip() {
+    init();
+    let mut image = build_fm_image();
+    let mut descriptor = image.image_format();
+    descriptor.geometry = DiskCh::new(2, 1);
+    descriptor.data_encoding = DiskDataEncoding::Fm;
+    descriptor.data_rate = DiskDataRate::Rate125Kbps;
+    image.set_image_format(descriptor);
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::F86Image.save_image(&image, &mut out_buffer).unwrap();
+    out_buffer.set_position(0);
+
+    // 86F selects the controller data rate per track, which FM tracks run at half of.
+    let mut reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    assert!(matches!(reloaded.image_format().data_encoding, DiskDataEncoding::Fm));
+
+    let sector_map = reloaded.get_sector_map();
+    assert!(matches!(sector_map[0][0].encoding, DiskDataEncoding::Fm));
+    assert!(matches!(sector_map[0][0].data_rate, DiskDataRate::Rate125Kbps));
+    let ids = sector_map[0][0].sectors.iter().map(|s| s.chsn.s()).collect::<Vec<_>>();
+    assert_eq!(ids, (1..=SECTORS).collect::<Vec<_>>());
+
+    for s in 1..=SECTORS {
+        let chs = DiskChs::new(0, 0, s);
+        let rsr = reloaded.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        assert_eq!(
+            rsr.read_buf[..rsr.data_len],
+            sector_data(0, s),
+            "sector {} does not match",
+            chs
+        );
+    }
+}
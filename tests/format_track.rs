@@ -0,0 +1,261 @@
+use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::structure_parsers::system34::{System34Marker, System34Parser, System34Standard, IAM_MARKER_BYTES};
+use fluxfox::timeline::TimelineElement;
+use fluxfox::{
+    DiskCh, DiskChs, DiskChsn, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskImage, DiskImageError,
+    StandardFormat,
+};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn format_buffer(ct: u8) -> Vec<DiskChsn> {
+    (1..=ct).map(|s| DiskChsn::new(0, 0, s, 2)).collect()
+}
+
+#[test]
+fn test_format_gap4b_fills_track() {
+    init();
+
+    // A track length that is not a whole number of MFM bytes ends on a partial GAP4B byte.
+    let result = System34Parser::format_track_as_bytes(
+        System34Standard::Iso,
+        100_003,
+        format_buffer(9),
+        0xF6,
+        0x50,
+        &Default::default(),
+    )
+    .unwrap();
+    assert_eq!(result.track_bytes.len(), 6251);
+    assert_eq!(result.gap3, 0x50);
+    let sector_len = System34Standard::Iso.sector_len(&DiskChsn::new(0, 0, 1, 2));
+    assert_eq!(result.gap4b, 6251 - (32 + 9 * (sector_len + 0x50)));
+
+    // The IBM standard writes an IAM and GAP1 before the first sector.
+    let result = System34Parser::format_track_as_bytes(
+        System34Standard::Ibm,
+        100_000,
+        format_buffer(9),
+        0xF6,
+        0x50,
+        &Default::default(),
+    )
+    .unwrap();
+    assert!(matches!(result.markers[0], (System34Marker::Iam, 92)));
+    assert_eq!(result.track_bytes[92..96], IAM_MARKER_BYTES);
+    assert!(matches!(result.markers[1], (System34Marker::Idam, 158)));
+    assert_eq!(result.track_bytes.len(), 6250);
+}
+
+#[test]
+fn test_format_short_track() {
+    init();
+
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::BitStream);
+    image
+        .add_empty_track(
+            DiskCh::new(0, 0),
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            85_000,
+        )
+        .unwrap();
+
+    // Nine sectors fit on this track only with a reduced GAP3. The last sector must not be
+    // truncated at the index.
+    image
+        .format_track(DiskCh::new(0, 0), System34Standard::Iso, format_buffer(9), 0xF6, 0x50)
+        .unwrap();
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0][0].bitcells, 85_000);
+    assert_eq!(sector_map[0][0].sectors.len(), 9);
+    for s in 1..=9 {
+        let result = image
+            .read_sector(DiskChs::new(0, 0, s), Some(2), RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert!(!result.data_crc_error);
+        assert_eq!(result.read_buf, vec![0xF6; 512]);
+    }
+
+    // Ten sectors will not fit at all.
+    assert!(image
+        .format_track(DiskCh::new(0, 0), System34Standard::Iso, format_buffer(10), 0xF6, 0x50)
+        .is_err());
+}
+
+#[test]
+fn test_format_custom_ids() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+    image.set_match_policy(MatchPolicy::SectorOnly);
+
+    // A duplicate ID, gaps in numbering, mixed sizes, and IDs with the wrong cylinder and head,
+    // as a protection scheme might lay out with the FDC Format Track command.
+    let ch = DiskCh::new(2, 1);
+    let ids = vec![
+        DiskChsn::new(2, 1, 1, 2),
+        DiskChsn::new(2, 1, 1, 2),
+        DiskChsn::new(2, 1, 3, 1),
+        DiskChsn::new(40, 0, 7, 2),
+        DiskChsn::new(2, 1, 0xF7, 0),
+        DiskChsn::new(0xFF, 0xFF, 9, 3),
+    ];
+    image
+        .format_track(ch, System34Standard::Iso, ids.clone(), 0xE5, 0x20)
+        .unwrap();
+
+    let sector_map = image.get_sector_map();
+    let track = &sector_map[1][2];
+    assert_eq!(track.to_string(), "1 1 3 7 247 9");
+    let chsn_list = track.sectors.iter().map(|s| s.chsn).collect::<Vec<_>>();
+    assert_eq!(chsn_list, ids);
+
+    for id in &ids {
+        let result = image
+            .read_sector(
+                DiskChs::new(ch.c(), ch.h(), id.s()),
+                Some(id.n()),
+                RwSectorScope::DataOnly,
+                false,
+            )
+            .unwrap();
+        assert!(!result.data_crc_error);
+        assert_eq!(result.read_buf, vec![0xE5; id.n_size()]);
+    }
+
+    // Both copies of the duplicate sector are present on the track, in order.
+    let first = image.read_address(ch, 0).unwrap();
+    let second = image.read_address(ch, first.bit_index + 1).unwrap();
+    assert_eq!((first.chsn, second.chsn), (ids[0], ids[1]));
+    assert!(second.bit_index > first.bit_index);
+
+    // As on a real FDC, the full ID must match, so sector 7 can't be found at its physical
+    // cylinder and head.
+    image.set_match_policy(MatchPolicy::Chsn);
+    assert!(image
+        .read_sector(DiskChs::new(2, 1, 7), Some(2), RwSectorScope::DataOnly, false)
+        .is_err());
+    assert!(image
+        .read_sector(DiskChs::new(2, 1, 3), Some(1), RwSectorScope::DataOnly, false)
+        .is_ok());
+}
+
+#[test]
+fn test_format_custom_ids_bytestream() {
+    init();
+
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.set_resolution(DiskDataResolution::ByteStream);
+    for c in 0..2 {
+        image
+            .add_empty_track(
+                DiskCh::new(c, 0),
+                DiskDataEncoding::Mfm,
+                DiskDataRate::Rate250Kbps,
+                100_000,
+            )
+            .unwrap();
+    }
+    image.set_match_policy(MatchPolicy::SectorOnly);
+
+    let ch = DiskCh::new(1, 0);
+    let ids = vec![
+        DiskChsn::new(1, 0, 2, 2),
+        DiskChsn::new(1, 0, 2, 2),
+        DiskChsn::new(1, 0, 5, 1),
+        DiskChsn::new(0x50, 1, 1, 2),
+    ];
+    image
+        .format_track(ch, System34Standard::Iso, ids.clone(), 0xF6, 0x50)
+        .unwrap();
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0][1].to_string(), "2 2 5 1");
+    let chsn_list = sector_map[0][1].sectors.iter().map(|s| s.chsn).collect::<Vec<_>>();
+    assert_eq!(chsn_list, ids);
+
+    for id in &ids {
+        let result = image
+            .read_sector(
+                DiskChs::new(ch.c(), ch.h(), id.s()),
+                Some(id.n()),
+                RwSectorScope::DataOnly,
+                false,
+            )
+            .unwrap();
+        assert_eq!(result.read_buf, vec![0xF6; id.n_size()]);
+    }
+}
+
+#[test]
+fn test_format_track_extend() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+
+    // Format the cylinder past the end of the disk, as a drive stepped beyond its last track.
+    let ch = DiskCh::new(40, 0);
+    let ids = (1..=9).map(|s| DiskChsn::new(40, 0, s, 2)).collect();
+    image.format_track(ch, System34Standard::Ibm, ids, 0xE5, 0x50).unwrap();
+    assert_eq!(image.get_track_ct(0), 41);
+    assert_eq!(image.geometry(), DiskCh::new(41, 2));
+
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(
+        track.bitcell_ct(),
+        image.get_track_ch(DiskCh::new(39, 0)).unwrap().bitcell_ct()
+    );
+    let result = image
+        .read_sector(DiskChs::new(40, 0, 9), Some(2), RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![0xE5; 512]);
+
+    // The IBM standard writes an index address mark.
+    let timeline = image.track_timeline(ch).unwrap();
+    assert!(timeline
+        .spans
+        .iter()
+        .any(|span| matches!(span.element, TimelineElement::Marker(System34Marker::Iam))));
+
+    // Tracks can't be added beyond the next cylinder.
+    assert!(matches!(
+        image.format_track(DiskCh::new(42, 0), System34Standard::Iso, format_buffer(9), 0xF6, 0x50),
+        Err(DiskImageError::SeekError)
+    ));
+}
+
+#[test]
+fn test_format_disk() {
+    init();
+
+    // An image with no resolution set is formatted as a ByteStream image.
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    image.format(StandardFormat::PcFloppy720, None, None).unwrap();
+
+    assert!(matches!(image.resolution(), DiskDataResolution::ByteStream));
+    assert_eq!(image.geometry(), DiskCh::new(80, 2));
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0].len(), 80);
+    assert_eq!(sector_map[1].len(), 80);
+    assert!(sector_map.iter().flatten().all(|track| track.sectors.len() == 9));
+
+    let boot_sector = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(&boot_sector.read_buf[510..512], &[0x55, 0xAA]);
+}
@@ -0,0 +1,34 @@
+use fluxfox::{formats, DiskImageFormat, FormatCaps};
+
+#[test]
+fn test_formats_list() {
+    let formats = formats();
+    assert!(!formats.is_empty());
+
+    for info in &formats {
+        assert!(!info.extensions.is_empty(), "{} has no extensions", info.name);
+        assert!(!info.magic.is_empty(), "{} has no magic description", info.name);
+        assert!(info.can_read);
+        assert!(!info.lossless || info.can_write);
+    }
+}
+
+#[test]
+fn test_formats_capabilities() {
+    let formats = formats();
+    let find = |format| formats.iter().find(|f| f.format == format).unwrap();
+
+    let raw = find(DiskImageFormat::RawSectorImage);
+    assert!(raw.extensions.contains(&"img"));
+    assert!(raw.can_write);
+    assert!(!raw.lossless);
+
+    let f86 = find(DiskImageFormat::F86Image);
+    assert!(f86.can_write);
+    assert!(f86.lossless);
+    assert!(f86.capabilities.contains(FormatCaps::CAP_DATA_DELETED));
+
+    let scp = find(DiskImageFormat::SuperCardPro);
+    assert!(!scp.can_write);
+    assert!(!scp.lossless);
+}
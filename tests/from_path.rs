@@ -0,0 +1,71 @@
+use fluxfox::diskimage::LoadOptions;
+use fluxfox::{DiskImage, DiskImageError, DiskImageFormat};
+use std::path::PathBuf;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluxfox_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_format_from_path() {
+    init();
+
+    assert_eq!(
+        DiskImageFormat::from_path("tests/images/Transylvania.img").unwrap(),
+        DiskImageFormat::RawSectorImage
+    );
+    assert_eq!(
+        DiskImageFormat::from_path("tests/images/Transylvania.imd").unwrap(),
+        DiskImageFormat::ImageDisk
+    );
+    assert!(matches!(
+        DiskImageFormat::from_path("tests/images/missing.img"),
+        Err(DiskImageError::IoError)
+    ));
+}
+
+#[test]
+fn test_format_from_path_wrong_extension() {
+    init();
+    let dir = temp_dir("from_path");
+
+    // Content takes precedence over a misleading extension.
+    let path = dir.join("Transylvania.adf");
+    std::fs::copy("tests/images/Transylvania.img", &path).unwrap();
+    assert_eq!(
+        DiskImageFormat::from_path(&path).unwrap(),
+        DiskImageFormat::RawSectorImage
+    );
+
+    let image = DiskImage::load_from_path(&path, LoadOptions::default()).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::RawSectorImage));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_format_from_path_stream_set() {
+    init();
+    let dir = temp_dir("from_path_set");
+
+    for name in ["track00.0.raw", "track00.1.raw", "track01.0.raw"] {
+        std::fs::write(dir.join(name), [0u8; 16]).unwrap();
+    }
+    assert_eq!(
+        DiskImageFormat::from_path(&dir).unwrap(),
+        DiskImageFormat::KryofluxStream
+    );
+    assert!(matches!(
+        DiskImage::load_from_path(&dir, LoadOptions::default()),
+        Err(DiskImageError::UnsupportedFormat)
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
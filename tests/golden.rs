@@ -0,0 +1,192 @@
+/*
+    Golden-file tests for image parsers.
+
+    Each JSON file in tests/golden describes a sample image in tests/images and the
+    results expected from loading it: the detected format, geometry, a compact
+    sector map for every track, and a hash of all sector data.
+
+    A golden file with a "round_trip" key names the extension of a writable format. The
+    sample image is saved in that format and reloaded before it is compared, so writers are
+    covered by the same samples. ByteStream samples are first copied onto a formatted
+    BitStream image for formats that only store bitstreams. ADF is not covered, as there
+    is no Amiga sample image.
+
+    To regenerate the golden files after an intentional change in parser behavior,
+    run the tests with FLUXFOX_BLESS=1 set, and review the resulting diff.
+*/
+
+mod common;
+
+use common::compute_slice_hash;
+use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::{
+    format_from_ext, DiskChs, DiskDataResolution, DiskImage, ImageParser, ParserWriteCompatibility, StandardFormat,
+};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Collect the properties of a loaded image to compare against a golden file.
+fn image_summary(image_name: &str, image: &mut DiskImage) -> Value {
+    let sector_map = image.get_sector_map();
+
+    let mut tracks = Vec::new();
+    let mut sector_data = Vec::new();
+
+    // Read sectors by ID alone, so that sectors with unusual IDs are included.
+    image.set_match_policy(MatchPolicy::SectorOnly);
+
+    for track in sector_map.iter().flatten() {
+        tracks.push(format!("{} {}", track.ch, track));
+
+        for sector in &track.sectors {
+            let chs = DiskChs::new(track.ch.c(), track.ch.h(), sector.chsn.s());
+            match image.read_sector(chs, Some(sector.chsn.n()), RwSectorScope::DataOnly, false) {
+                Ok(rsr) => sector_data.extend_from_slice(&rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len]),
+                Err(e) => sector_data.extend_from_slice(format!("{}", e).as_bytes()),
+            }
+        }
+    }
+
+    json!({
+        "image": image_name,
+        "source_format": image.source_format().map(|f| f.to_string()),
+        "geometry": image.geometry().to_string(),
+        "encoding": image.data_encoding().to_string(),
+        "data_rate": image.data_rate().to_string(),
+        "sector_data_sha1": compute_slice_hash(&sector_data),
+        "tracks": tracks,
+    })
+}
+
+/// Copy every sector of `image` onto a formatted BitStream image of the same standard format.
+fn to_bitstream(image: &mut DiskImage) -> DiskImage {
+    let sector_map = image.get_sector_map();
+    let size = sector_map
+        .iter()
+        .flatten()
+        .flat_map(|track| track.sectors.iter())
+        .map(|sector| sector.chsn.n_size())
+        .sum::<usize>();
+    let (format, _) = StandardFormat::from_raw_size(size).expect("Sample image is not a standard format");
+
+    let mut bitstream = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(format)
+        .with_formatted()
+        .build()
+        .unwrap();
+
+    for track in sector_map.iter().flatten() {
+        for sector in &track.sectors {
+            let chs = DiskChs::new(track.ch.c(), track.ch.h(), sector.chsn.s());
+            let rsr = image
+                .read_sector(chs, Some(sector.chsn.n()), RwSectorScope::DataOnly, false)
+                .unwrap();
+            bitstream
+                .write_sector(
+                    chs,
+                    Some(sector.chsn.n()),
+                    &rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len],
+                    RwSectorScope::DataOnly,
+                    false,
+                    false,
+                )
+                .unwrap();
+        }
+    }
+    bitstream
+}
+
+/// Save `image` in the format with extension `ext` and load it back.
+fn round_trip(mut image: DiskImage, ext: &str) -> Result<DiskImage, String> {
+    let format = format_from_ext(ext).ok_or(format!("unknown round trip format '{}'", ext))?;
+    if matches!(format.can_write(&image), ParserWriteCompatibility::Incompatible)
+        && image.resolution() == DiskDataResolution::ByteStream
+    {
+        image = to_bitstream(&mut image);
+    }
+
+    let mut out_buffer = std::io::Cursor::new(Vec::new());
+    format
+        .save_image(&image, &mut out_buffer)
+        .map_err(|e| format!("failed to save as {}: {}", format, e))?;
+    out_buffer.set_position(0);
+    DiskImage::load(&mut out_buffer).map_err(|e| format!("failed to reload as {}: {}", format, e))
+}
+
+fn golden_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(Path::new("tests").join("golden"))
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_golden_images() {
+    init();
+
+    let bless = std::env::var("FLUXFOX_BLESS").is_ok();
+    let mut failures = Vec::new();
+
+    let files = golden_files();
+    assert!(!files.is_empty(), "No golden files found.");
+
+    for golden_path in files {
+        let golden: Value = serde_json::from_str(&std::fs::read_to_string(&golden_path).unwrap()).unwrap();
+        let image_name = golden["image"].as_str().expect("Golden file missing image name");
+        let image_path = Path::new("tests").join("images").join(image_name);
+
+        let mut reader = std::io::Cursor::new(std::fs::read(&image_path).unwrap());
+        let mut image = match DiskImage::load(&mut reader) {
+            Ok(image) => image,
+            Err(e) => {
+                failures.push(format!("{}: failed to load image: {}", image_name, e));
+                continue;
+            }
+        };
+
+        let round_trip_ext = golden.get("round_trip").and_then(|ext| ext.as_str());
+        if let Some(ext) = round_trip_ext {
+            image = match round_trip(image, ext) {
+                Ok(image) => image,
+                Err(e) => {
+                    failures.push(format!("{}: {}", golden_path.display(), e));
+                    continue;
+                }
+            };
+        }
+
+        let mut actual = image_summary(image_name, &mut image);
+        if let Some(ext) = round_trip_ext {
+            actual["round_trip"] = json!(ext);
+        }
+
+        if bless {
+            std::fs::write(&golden_path, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+            println!("Blessed {}", golden_path.display());
+            continue;
+        }
+
+        for (key, expected) in golden.as_object().unwrap() {
+            if actual[key] != *expected {
+                failures.push(format!(
+                    "{}: mismatch in '{}'\n  expected: {}\n    actual: {}",
+                    golden_path.display(),
+                    key,
+                    expected,
+                    actual[key]
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "Golden file mismatches:\n{}", failures.join("\n"));
+}
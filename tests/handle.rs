@@ -0,0 +1,97 @@
+use fluxfox::diskimage::{DiskImageFlags, RwSectorScope};
+use fluxfox::structure_parsers::system34::System34Standard;
+use fluxfox::testutil::{TestImage, TEST_DUPLICATE_IDS, TEST_QUIRK_CYLINDER};
+use fluxfox::{DiskCh, DiskChs, DiskChsn, DiskImageError, QuarterTrack, TrackId};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_track_ids() {
+    init();
+
+    let image = TestImage::DuplicateIds.generate().unwrap();
+    let ids = image.track_ids();
+    assert_eq!(ids.len(), 80);
+    assert_eq!(ids[0], TrackId::from(DiskCh::new(0, 0)));
+    assert_eq!(ids[1], TrackId::from(DiskCh::new(0, 1)));
+    assert_eq!(ids[79].ch(), DiskCh::new(39, 1));
+
+    for id in ids {
+        assert_eq!(TrackId::from_raw(id.to_raw()), id);
+        assert!(!id.is_sub_track());
+        assert_eq!(image.get_track_by_id(id).unwrap().ch(), id.ch());
+    }
+
+    let half = TrackId::new(1, QuarterTrack::new(3, 2));
+    assert!(half.is_sub_track());
+    assert_eq!(half.ch(), DiskCh::new(3, 1));
+    assert_eq!(half.to_string(), "[h:1 t:3.5]");
+    assert!(image.get_track_by_id(half).is_none());
+    assert!(image.get_track_by_id(TrackId::from(DiskCh::new(40, 0))).is_none());
+    assert!(image.sector_ids(half).is_empty());
+}
+
+#[test]
+fn test_sector_ids() {
+    init();
+
+    let mut image = TestImage::DuplicateIds.generate().unwrap();
+    let track = TrackId::from(DiskCh::new(TEST_QUIRK_CYLINDER, 0));
+    let ids = image.sector_ids(track);
+    let sectors: Vec<_> = ids.iter().map(|id| (id.chsn().s(), id.occurrence())).collect();
+    assert_eq!(
+        sectors,
+        vec![(1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (5, 1), (7, 0), (8, 0), (9, 0)]
+    );
+    assert_eq!(ids.len(), TEST_DUPLICATE_IDS.len());
+    assert_eq!(ids[5].to_string(), "[h:0 t:1] [c:1 h:0 s:5 n: 2] #2");
+
+    for id in &ids {
+        let sector = image.get_sector_by_id(*id).unwrap();
+        assert_eq!(sector.chsn, id.chsn());
+        assert_eq!(id.track(), track);
+    }
+
+    // Handles stay valid when the track is edited.
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, 7);
+    image
+        .write_sector(chs, Some(2), &[0xE5; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+    assert_eq!(image.sector_ids(track), ids);
+    assert!(image.get_sector_by_id(ids[6]).unwrap().data_crc_valid);
+
+    // A handle to a sector that is no longer present is not found.
+    image
+        .format_track(
+            track.ch(),
+            System34Standard::Iso,
+            vec![DiskChsn::new(TEST_QUIRK_CYLINDER, 0, 1, 2)],
+            0xF6,
+            0x50,
+        )
+        .unwrap();
+    assert!(image.get_sector_by_id(ids[0]).is_some());
+    assert!(image.get_sector_by_id(ids[1]).is_none());
+}
+
+#[test]
+fn test_track_by_id_mut() {
+    init();
+
+    let mut image = TestImage::DuplicateIds.generate().unwrap();
+    image.clear_flag(DiskImageFlags::DIRTY);
+
+    let half = TrackId::new(1, QuarterTrack::new(3, 2));
+    assert!(matches!(
+        image.get_track_by_id_mut(half),
+        Err(DiskImageError::SeekError)
+    ));
+    assert!(!image.has_flag(DiskImageFlags::DIRTY));
+
+    // Borrowing a track for modification marks the image as modified.
+    let id = TrackId::from(DiskCh::new(1, 0));
+    assert_eq!(image.get_track_by_id_mut(id).unwrap().ch(), DiskCh::new(1, 0));
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+}
@@ -0,0 +1,70 @@
+use fluxfox::health::{HealthGrade, HealthIssue};
+use fluxfox::testutil::{TestImage, TEST_DUPLICATE_IDS};
+use fluxfox::{DiskCh, DiskDataEncoding, DiskDataRate, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_health_clean() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let report = image.health();
+    assert_eq!(report.expected_sectors, 720);
+    assert_eq!(report.sectors, 720);
+    assert_eq!(report.score(), 100);
+    assert_eq!(report.grade(), HealthGrade::A);
+    assert!(report
+        .breakdown()
+        .iter()
+        .all(|(_, count, points)| *count == 0 && *points == 0.0));
+    assert_eq!(report.to_string().lines().count(), 1);
+}
+
+#[test]
+fn test_health_issues() {
+    init();
+
+    // A single bad sector costs less than a point, but still prevents a perfect score.
+    let report = TestImage::BadDataCrc.generate().unwrap().health();
+    assert_eq!(report.data_crc_errors, 1);
+    assert_eq!(report.score(), 99);
+    assert_eq!(report.grade(), HealthGrade::B);
+
+    let report = TestImage::BadAddressCrc.generate().unwrap().health();
+    assert_eq!((report.address_crc_errors, report.data_crc_errors), (1, 0));
+
+    // Weak bits take precedence over the bad CRC they cause, and count for half a sector.
+    let report = TestImage::WeakBits.generate().unwrap().health();
+    assert_eq!((report.weak_sectors, report.data_crc_errors), (1, 0));
+    let (issue, count, points) = report.breakdown()[0];
+    assert_eq!((issue, count), (HealthIssue::WeakBits, 1));
+    assert!((points - 50.0 / 720.0).abs() < 1e-9);
+
+    let report = TestImage::DuplicateIds.generate().unwrap().health();
+    let missing = (1..=9).filter(|id| !TEST_DUPLICATE_IDS.contains(id)).count();
+    assert_eq!(report.missing_sectors, missing);
+    assert!(report.to_string().contains("missing sectors"));
+    assert!(report.score() < 100);
+}
+
+#[test]
+fn test_health_truncated() {
+    init();
+
+    // An image holding only the first cylinder of a 360K disk, with no sectors on it.
+    let mut image = DiskImage::create(StandardFormat::PcFloppy360);
+    for h in 0..2 {
+        image
+            .add_track_bytestream(DiskDataEncoding::Mfm, DiskDataRate::Rate250Kbps, DiskCh::new(0, h))
+            .unwrap();
+    }
+    let report = image.health();
+    assert_eq!(report.missing_tracks, 78);
+    assert_eq!(report.missing_sectors, 18);
+    assert_eq!(report.sectors, 0);
+    assert_eq!(report.score(), 0);
+    assert_eq!(report.grade(), HealthGrade::F);
+}
@@ -0,0 +1,74 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskImage, DiskImageFormat, ImageParser};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Save `image` as HFE and load it back.
+fn round_trip(image: &DiskImage) -> DiskImage {
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::HfeImage.save_image(image, &mut out_buffer).unwrap();
+    assert_eq!(out_buffer.get_ref().len() % 512, 0);
+
+    let hfe_image = DiskImage::load(&mut Cursor::new(out_buffer.into_inner())).unwrap();
+    assert_eq!(hfe_image.source_format(), Some(DiskImageFormat::HfeImage));
+    hfe_image
+}
+
+#[test]
+fn test_hfe_from_bytestream() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut Cursor::new(buf)).unwrap();
+
+    // Give a sector a bad data CRC, which must survive re-encoding.
+    let bad_chs = DiskChs::new(2, 1, 5);
+    image
+        .write_sector_debug(bad_chs, Some(1), &[0x55; 256], 0, false)
+        .unwrap();
+
+    let mut hfe_image = round_trip(&image);
+    assert_eq!(hfe_image.load_warnings().len(), 1);
+    assert_eq!(hfe_image.image_format().geometry, image.image_format().geometry);
+    assert_eq!(hfe_image.image_format().data_rate, image.image_format().data_rate);
+
+    let sector_map = image.get_sector_map();
+    let hfe_sector_map = hfe_image.get_sector_map();
+    for (head_map, hfe_head_map) in sector_map.iter().zip(hfe_sector_map.iter()) {
+        for (track, hfe_track) in head_map.iter().zip(hfe_head_map.iter()) {
+            let ids = track.sectors.iter().map(|s| s.chsn).collect::<Vec<_>>();
+            let hfe_ids = hfe_track.sectors.iter().map(|s| s.chsn).collect::<Vec<_>>();
+            assert_eq!(ids, hfe_ids, "track {} sectors differ", track.ch);
+            for sector in &track.sectors {
+                let chs = DiskChs::from((track.ch, sector.chsn.s()));
+                let expected = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+                let rsr = hfe_image
+                    .read_sector(chs, None, RwSectorScope::DataOnly, false)
+                    .unwrap();
+                assert_eq!(rsr.data_crc_error, chs == bad_chs, "sector {}", chs);
+                assert_eq!(rsr.read_buf, expected.read_buf, "sector {} does not match", chs);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hfe_from_bitstream() {
+    init();
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let mut hfe_image = round_trip(&image);
+    assert_eq!(hfe_image.load_warnings().len(), 1);
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let expected = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let rsr = hfe_image
+        .read_sector(chs, None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, expected.read_buf);
+}
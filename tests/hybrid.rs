@@ -0,0 +1,126 @@
+use bit_vec::BitVec;
+use fluxfox::bitstream::fm::{FmCodec, FmEncodingType};
+use fluxfox::bitstream::gcr::{GcrCodec, GCR_ADDRESS_PROLOGUE, GCR_EPILOGUE};
+use fluxfox::structure_parsers::{detect_encoding, encoding_scores};
+use fluxfox::testutil::{track_stream, TestImage};
+use fluxfox::{
+    DiskCh, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, FormatCaps, ImageParser,
+    ParserWriteCompatibility, StandardFormat,
+};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const SECTORS: u8 = 16;
+
+fn push_byte(bits: &mut BitVec, byte: u8) {
+    bits.extend((0..8).rev().map(|i| byte & (1 << i) != 0));
+}
+
+/// A GCR track holding the address fields of 16 sectors, padded with sync bits.
+fn gcr_track_bits() -> BitVec {
+    let mut bits = BitVec::new();
+    for sector in 0..SECTORS {
+        for _ in 0..16 {
+            push_byte(&mut bits, 0xFF);
+            bits.extend([false, false]);
+        }
+        for nibble in GCR_ADDRESS_PROLOGUE {
+            push_byte(&mut bits, nibble);
+        }
+        for value in [254, 1, sector, 254 ^ 1 ^ sector] {
+            for nibble in GcrCodec::encode_44(value) {
+                push_byte(&mut bits, nibble);
+            }
+        }
+        for nibble in GCR_EPILOGUE {
+            push_byte(&mut bits, nibble);
+        }
+    }
+    while bits.len() < 51_200 {
+        bits.push(true);
+    }
+    bits
+}
+
+/// An FM track holding the ID fields of 16 sectors.
+fn fm_track_bits() -> BitVec {
+    let mut bits = FmCodec::encode_fm(&[0xFF; 40], FmEncodingType::Data);
+    for sector in 1..=SECTORS {
+        bits.extend(FmCodec::encode_fm(&[0x00; 6], FmEncodingType::Data).iter());
+        bits.extend(FmCodec::encode_fm(&[0xFE], FmEncodingType::AddressMark).iter());
+        bits.extend(FmCodec::encode_fm(&[2, 0, sector, 0, 0, 0], FmEncodingType::Data).iter());
+        bits.extend(FmCodec::encode_fm(&[0xFF; 11], FmEncodingType::Data).iter());
+    }
+    bits
+}
+
+#[test]
+fn test_detect_encoding() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mfm_bits = track_stream(&image, DiskCh::new(0, 0)).unwrap().bits().clone();
+    assert!(matches!(detect_encoding(&mfm_bits), Some(DiskDataEncoding::Mfm)));
+    assert!(matches!(detect_encoding(&fm_track_bits()), Some(DiskDataEncoding::Fm)));
+    assert!(matches!(
+        detect_encoding(&gcr_track_bits()),
+        Some(DiskDataEncoding::Gcr)
+    ));
+    assert!(detect_encoding(&BitVec::from_elem(50_000, false)).is_none());
+
+    let scores = encoding_scores(&gcr_track_bits());
+    let gcr_score = scores
+        .iter()
+        .find(|(encoding, _)| matches!(encoding, DiskDataEncoding::Gcr))
+        .map(|(_, score)| *score);
+    assert_eq!(gcr_score, Some(SECTORS as usize));
+}
+
+#[test]
+fn test_hybrid_pri_round_trip() {
+    init();
+
+    let source = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mfm_bits = track_stream(&source, DiskCh::new(0, 0)).unwrap().bits().clone();
+    let gcr_bits = gcr_track_bits();
+
+    let mut image = DiskImage::default();
+    for (c, encoding, bits) in [
+        (0, DiskDataEncoding::Mfm, &mfm_bits),
+        (1, DiskDataEncoding::Gcr, &gcr_bits),
+    ] {
+        image
+            .add_track_bitstream(
+                encoding,
+                DiskDataRate::Rate250Kbps,
+                DiskCh::new(c, 0),
+                250_000,
+                Some(bits.len()),
+                &bits.to_bytes(),
+                None,
+            )
+            .unwrap();
+    }
+    assert!(image.has_mixed_encodings());
+    assert!(image.required_caps().contains(FormatCaps::CAP_TRACK_ENCODING));
+    assert!(matches!(
+        DiskImageFormat::PceBitstreamImage.can_write(&image),
+        ParserWriteCompatibility::Ok
+    ));
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    out_buffer.set_position(0);
+
+    // Each track is reloaded with its own codec.
+    let reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    let encoding = |c: u16| reloaded.get_track_ch(DiskCh::new(c, 0)).unwrap().encoding();
+    assert!(matches!(encoding(0), DiskDataEncoding::Mfm));
+    assert!(matches!(encoding(1), DiskDataEncoding::Gcr));
+    assert_eq!(reloaded.get_sector_map()[0][0].sectors.len(), 9);
+}
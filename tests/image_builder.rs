@@ -1,5 +1,6 @@
+use fluxfox::diskimage::RwSectorScope;
 use fluxfox::image_builder::ImageBuilder;
-use fluxfox::{DiskDataResolution, DiskImageFormat, ImageParser, StandardFormat};
+use fluxfox::{DiskChs, DiskDataResolution, DiskImage, DiskImageFormat, ImageParser, StandardFormat};
 use std::io::Cursor;
 
 mod common;
@@ -32,3 +33,42 @@ fn test_image_builder() {
 
     std::fs::write(".\\tests\\images\\test_formatted.86f", out_buffer.get_ref()).unwrap();
 }
+
+#[test]
+fn test_create_formatted() {
+    init();
+
+    for resolution in [DiskDataResolution::ByteStream, DiskDataResolution::BitStream] {
+        let mut image = DiskImage::create_formatted(StandardFormat::PcFloppy360, resolution).unwrap();
+
+        assert!(image.resolution() == resolution);
+        let sector_map = image.get_sector_map();
+        assert_eq!(sector_map[0].len(), 40);
+        assert_eq!(sector_map[1].len(), 40);
+        assert!(sector_map.iter().flatten().all(|track| track.sectors.len() == 9));
+
+        let boot_sector = image
+            .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert_eq!(&boot_sector.read_buf[510..512], &[0x55, 0xAA]);
+
+        let sector = image
+            .read_sector(DiskChs::new(39, 1, 9), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert!(!sector.data_crc_error);
+        assert!(sector.read_buf.iter().all(|&b| b == 0));
+    }
+}
+
+#[test]
+fn test_build_bytestream_unformatted() {
+    init();
+
+    let image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::ByteStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .build()
+        .unwrap();
+
+    assert_eq!(image.get_sector_map()[0].len(), 40);
+}
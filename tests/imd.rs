@@ -37,3 +37,185 @@ fn test_imd() {
     assert_eq!(in_hash, out_hash);
     println!("Hashes match!");
 }
+
+/// Build an IMD image exercising the cylinder, head and sector size maps, every class of sector
+/// data record, and a mix of FM and MFM tracks.
+fn build_imd() -> Vec<u8> {
+    let mut imd = b"IMD 1.18: 16/10/2024 12:00:00\r\nfluxfox test".to_vec();
+    imd.push(0x1A);
+
+    // Track 0: FM, 128 byte sectors, with cylinder and head maps.
+    imd.extend([2, 0, 0xC0, 3, 0]);
+    imd.extend([1, 2, 3]);
+    imd.extend([0x10, 0x10, 0x10]);
+    imd.extend([1, 1, 1]);
+    // Data unavailable.
+    imd.push(0x00);
+    // Compressed.
+    imd.extend([0x02, 0xE5]);
+    // Normal, deleted with error.
+    imd.push(0x07);
+    imd.extend([0x55; 128]);
+
+    // Track 1: MFM, with a sector size map.
+    imd.extend([5, 1, 0, 2, 0xFF]);
+    imd.extend([1, 2]);
+    imd.extend(256u16.to_le_bytes());
+    imd.extend(512u16.to_le_bytes());
+    // Compressed, deleted with error.
+    imd.extend([0x08, 0xAA]);
+    imd.push(0x01);
+    imd.extend([0x11; 512]);
+
+    // Track 2: MFM, compressed and deleted.
+    imd.extend([5, 2, 0, 1, 2]);
+    imd.push(1);
+    imd.extend([0x04, 0x22]);
+
+    imd
+}
+
+#[test]
+fn test_imd_sector_attributes() {
+    use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+    use fluxfox::fdc::{Upd765Status, ST2_MISSING_DATA_MARK};
+    use fluxfox::{DiskChs, DiskDataEncoding};
+    use std::io::Cursor;
+
+    let mut image = DiskImage::load(&mut Cursor::new(build_imd())).unwrap();
+
+    // The descriptor takes the mode of the majority of tracks.
+    assert!(matches!(image.image_format().data_encoding, DiskDataEncoding::Mfm));
+
+    let sector_map = image.get_sector_map();
+    let track0 = &sector_map[0][0];
+    assert!(matches!(track0.encoding, DiskDataEncoding::Fm));
+    assert_eq!(track0.to_string(), "1m 2 3d!");
+    for sector in &track0.sectors {
+        assert_eq!((sector.chsn.c(), sector.chsn.h()), (0x10, 1));
+    }
+
+    let track1 = &sector_map[0][1];
+    assert!(matches!(track1.encoding, DiskDataEncoding::Mfm));
+    assert_eq!(track1.sectors[0].chsn.n(), 1);
+    assert_eq!(track1.sectors[1].chsn.n(), 2);
+    assert_eq!(track1.to_string(), "1d! 2");
+
+    // Track 0's sector IDs don't match its physical cylinder and head.
+    image.set_match_policy(MatchPolicy::SectorOnly);
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.no_dam);
+    assert!(result.read_buf.is_empty());
+    assert_ne!(
+        Upd765Status::from_read_sector(&result, 0, 0).st2 & ST2_MISSING_DATA_MARK,
+        0
+    );
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!result.no_dam);
+    assert_eq!(result.read_buf, vec![0xE5; 128]);
+
+    let result = image
+        .read_sector(DiskChs::new(1, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.deleted_mark && result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0xAA; 256]);
+
+    let result = image
+        .read_sector(DiskChs::new(2, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.deleted_mark && !result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0x22; 512]);
+}
+
+#[test]
+fn test_imd_mismatched_id() {
+    use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+    use fluxfox::DiskChs;
+    use std::io::Cursor;
+
+    let mut image = DiskImage::load(&mut Cursor::new(build_imd())).unwrap();
+
+    // Track 0's sector IDs claim cylinder 0x10, head 1. The default policy matches the sector ID
+    // only on ByteStream tracks, and flags the mismatched fields.
+    assert_eq!(image.match_policy(), MatchPolicy::Auto);
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.wrong_cylinder);
+    assert!(result.wrong_head);
+    assert_eq!(result.read_buf, vec![0xE5; 128]);
+
+    image.set_match_policy(MatchPolicy::Chsn);
+    assert!(image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .is_err());
+}
+
+#[test]
+fn test_imd_export_roundtrip() {
+    use fluxfox::diskimage::RwSectorScope;
+    use fluxfox::{DiskChs, DiskDataEncoding};
+    use std::io::Cursor;
+
+    let mut image = DiskImage::load(&mut Cursor::new(build_imd())).unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::ImageDisk.save_image(&image, &mut out_buffer).unwrap();
+    let imd = out_buffer.into_inner();
+    assert!(imd.starts_with(b"IMD 1.18: "));
+
+    // The uniform sector of track 0 is compressed on export.
+    assert!(imd.len() < build_imd().len());
+
+    let mut reloaded = DiskImage::load(&mut Cursor::new(imd)).unwrap();
+    assert_eq!(reloaded.source_format(), Some(DiskImageFormat::ImageDisk));
+    assert_eq!(reloaded.get_comment(), image.get_comment());
+
+    let (sector_map, reloaded_map) = (image.get_sector_map(), reloaded.get_sector_map());
+    for (track, reloaded_track) in sector_map[0].iter().zip(&reloaded_map[0]) {
+        assert_eq!(track.to_string(), reloaded_track.to_string());
+        for (sector, reloaded_sector) in track.sectors.iter().zip(&reloaded_track.sectors) {
+            assert_eq!(sector.chsn, reloaded_sector.chsn);
+        }
+    }
+    assert!(matches!(reloaded_map[0][0].encoding, DiskDataEncoding::Fm));
+    assert!(matches!(reloaded_map[0][1].encoding, DiskDataEncoding::Mfm));
+
+    for c in 1..3 {
+        let chs = DiskChs::new(c, 0, 1);
+        let original = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        let result = reloaded.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        assert_eq!(result.read_buf, original.read_buf);
+        assert_eq!(result.deleted_mark, original.deleted_mark);
+        assert_eq!(result.data_crc_error, original.data_crc_error);
+    }
+}
+
+#[test]
+fn test_imd_export_bitstream() {
+    use fluxfox::diskimage::RwSectorScope;
+    use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+    use fluxfox::DiskChs;
+    use std::io::Cursor;
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::ImageDisk.save_image(&image, &mut out_buffer).unwrap();
+    let mut reloaded = DiskImage::load(&mut Cursor::new(out_buffer.into_inner())).unwrap();
+    assert_eq!(reloaded.image_format().geometry, image.image_format().geometry);
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let original = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let result = reloaded.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(result.deleted_mark);
+    assert_eq!(
+        result.read_buf,
+        original.read_buf[original.data_idx..original.data_idx + original.data_len]
+    );
+}
@@ -39,3 +39,100 @@ fn test_img() {
     assert_eq!(in_hash, out_hash);
     println!("Hashes match!");
 }
+
+/// Maps the index of a track in a raw image to the cylinder and head it holds.
+type TrackAt = fn(usize) -> (u8, u8);
+
+/// Build a raw 360K image with tracks stored in the order given by `track_at`, where each
+/// sector begins with its own cylinder, head and sector ID.
+fn build_ordered_img(track_at: TrackAt) -> Vec<u8> {
+    let mut img = Vec::new();
+    for slot in 0..80 {
+        let (c, h) = track_at(slot);
+        for s in 1..=9 {
+            let mut sector = vec![0u8; 512];
+            sector[0..3].copy_from_slice(&[c, h, s]);
+            img.extend(sector);
+        }
+    }
+    img
+}
+
+#[test]
+fn test_img_track_order() {
+    use fluxfox::diskimage::{LoadOptions, RawSideOrder, RawTrackOrder, RwSectorScope};
+    use fluxfox::DiskChs;
+    use std::io::Cursor;
+
+    let cases: [(RawSideOrder, RawTrackOrder, TrackAt); 4] = [
+        (RawSideOrder::Interleaved, RawTrackOrder::OutToIn, |t| {
+            ((t / 2) as u8, (t % 2) as u8)
+        }),
+        (RawSideOrder::Sequential, RawTrackOrder::OutToIn, |t| {
+            ((t % 40) as u8, (t / 40) as u8)
+        }),
+        (RawSideOrder::Interleaved, RawTrackOrder::InToOut, |t| {
+            (39 - (t / 2) as u8, (t % 2) as u8)
+        }),
+        (RawSideOrder::Sequential, RawTrackOrder::OutAndBack, |t| match t / 40 {
+            0 => (t as u8, 0),
+            _ => (79 - t as u8, 1),
+        }),
+    ];
+
+    for (side_order, track_order, track_at) in cases {
+        let options = LoadOptions {
+            raw_side_order: side_order,
+            raw_track_order: track_order,
+            ..Default::default()
+        };
+        let mut image = DiskImage::load_with_options(&mut Cursor::new(build_ordered_img(track_at)), options).unwrap();
+
+        for (c, h, s) in [(0, 0, 1), (0, 1, 9), (17, 1, 4), (39, 0, 2), (39, 1, 9)] {
+            let result = image
+                .read_sector(DiskChs::new(c, h, s), None, RwSectorScope::DataOnly, false)
+                .unwrap();
+            assert_eq!(
+                result.read_buf[0..3],
+                [c as u8, h, s],
+                "{:?} {:?}",
+                side_order,
+                track_order
+            );
+        }
+    }
+}
+
+#[test]
+fn test_img_write_image() {
+    use fluxfox::diskimage::RwSectorScope;
+    use fluxfox::testutil::TestImage;
+    use fluxfox::{DiskChs, DiskImageError, ImageWriter, StandardFormat};
+    use std::io::Cursor;
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage
+        .write_image(&mut image, &mut out_buffer)
+        .unwrap();
+
+    let out_inner = out_buffer.into_inner();
+    assert_eq!(out_inner.len(), StandardFormat::PcFloppy360.size());
+    for (c, h, s) in [(0, 0, 1), (0, 1, 9), (17, 1, 4), (39, 0, 2), (39, 1, 9)] {
+        let offset = ((c as usize * 2 + h as usize) * 9 + (s as usize - 1)) * 512;
+        let result = image
+            .read_sector(DiskChs::new(c, h, s), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert_eq!(&out_inner[offset..offset + 512], &result.read_buf[..]);
+    }
+
+    // Features a raw image cannot represent fail the export.
+    for test_image in [TestImage::BadDataCrc, TestImage::WeakBits] {
+        let mut image = test_image.generate().unwrap();
+        let mut out_buffer = Cursor::new(Vec::new());
+        assert!(matches!(
+            DiskImageFormat::RawSectorImage.write_image(&mut image, &mut out_buffer),
+            Err(DiskImageError::IncompatibleImage)
+        ));
+    }
+}
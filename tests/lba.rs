@@ -0,0 +1,82 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskImage, DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_lba_raw_order() {
+    init();
+
+    // A raw sector image stores its sectors in LBA order.
+    let file = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut std::io::Cursor::new(file.clone())).unwrap();
+    let geometry = image.nominal_geometry().unwrap();
+    let sector_ct = geometry.get_sector_count() as usize;
+    assert_eq!(sector_ct * 512, file.len());
+
+    for lba in (0..sector_ct).step_by(7) {
+        assert_eq!(image.read_lba(lba).unwrap(), &file[lba * 512..(lba + 1) * 512]);
+    }
+    assert!(matches!(image.read_lba(sector_ct), Err(DiskImageError::SeekError)));
+}
+
+#[test]
+fn test_write_lba() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert_eq!(image.nominal_geometry(), Some(DiskChs::new(40, 2, 9)));
+
+    for lba in [0, 8, 9, 17, 18, 719] {
+        image.write_lba(lba, &[lba as u8; 512]).unwrap();
+    }
+    for lba in [0, 8, 9, 17, 18, 719] {
+        assert_eq!(image.read_lba(lba).unwrap(), vec![lba as u8; 512]);
+    }
+
+    // The sector after the last on head 0 is the first on head 1.
+    let rsr = image
+        .read_sector(DiskChs::new(0, 1, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf, vec![9; 512]);
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf, vec![18; 512]);
+
+    assert!(matches!(
+        image.write_lba(720, &[0; 512]),
+        Err(DiskImageError::SeekError)
+    ));
+    assert!(matches!(
+        image.write_lba(1, &[0; 256]),
+        Err(DiskImageError::ParameterError)
+    ));
+}
+
+#[test]
+fn test_lba_errors() {
+    init();
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let geometry = image.nominal_geometry().unwrap();
+    let lba = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR).to_lba(&geometry);
+    assert!(matches!(image.read_lba(lba), Err(DiskImageError::CrcError)));
+    assert!(image.read_lba(lba + 1).is_ok());
+
+    // Sector 6 is missing from the quirk track.
+    let mut image = TestImage::DuplicateIds.generate().unwrap();
+    let lba = DiskChs::new(TEST_QUIRK_CYLINDER, 0, 6).to_lba(&geometry);
+    assert!(matches!(image.read_lba(lba), Err(DiskImageError::DataError)));
+    assert!(matches!(
+        image.write_lba(lba, &[0; 512]),
+        Err(DiskImageError::DataError)
+    ));
+
+    let mut image = DiskImage::default();
+    assert!(image.nominal_geometry().is_none());
+    assert!(matches!(image.read_lba(0), Err(DiskImageError::IncompatibleImage)));
+}
@@ -0,0 +1,87 @@
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskImage, StandardFormat};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_linear_read() {
+    init();
+
+    let file = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut std::io::Cursor::new(file.clone())).unwrap();
+    let mut view = image.linear_view().unwrap();
+    assert_eq!(view.len(), file.len() as u64);
+    assert_eq!(view.sector_size(), 512);
+
+    let mut buf = Vec::new();
+    view.read_to_end(&mut buf).unwrap();
+    assert!(buf == file);
+
+    // Reads spanning a sector boundary.
+    let mut buf = [0u8; 100];
+    assert_eq!(view.seek(SeekFrom::Start(1000)).unwrap(), 1000);
+    view.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, file[1000..1100]);
+
+    assert_eq!(view.seek(SeekFrom::End(-10)).unwrap(), file.len() as u64 - 10);
+    assert_eq!(view.read(&mut buf).unwrap(), 10);
+    assert_eq!(view.read(&mut buf).unwrap(), 0);
+    assert_eq!(view.seek(SeekFrom::Current(-20)).unwrap(), file.len() as u64 - 20);
+    assert!(view.seek(SeekFrom::Current(-(file.len() as i64))).is_err());
+}
+
+#[test]
+fn test_linear_write() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut view = image.linear_view().unwrap();
+    assert_eq!(view.len(), 368_640);
+
+    let start = 20 * 512 + 500;
+    let mut before = [0u8; 1104];
+    view.seek(SeekFrom::Start(start - 2)).unwrap();
+    view.read_exact(&mut before).unwrap();
+
+    view.seek(SeekFrom::Start(start)).unwrap();
+    view.write_all(&[0x55; 1100]).unwrap();
+    view.seek(SeekFrom::Start(start - 2)).unwrap();
+    let mut buf = [0u8; 1104];
+    view.read_exact(&mut buf).unwrap();
+    assert_eq!(buf[..2], before[..2]);
+    assert!(buf[2..1102].iter().all(|&b| b == 0x55));
+    assert_eq!(buf[1102..], before[1102..]);
+
+    view.seek(SeekFrom::End(0)).unwrap();
+    assert_eq!(view.write(&[0]).unwrap_err().kind(), ErrorKind::WriteZero);
+
+    assert_eq!(image.read_lba(21).unwrap(), vec![0x55; 512]);
+    assert_eq!(image.read_lba(22).unwrap(), vec![0x55; 512]);
+    assert_eq!(image.read_lba(23).unwrap()[..64], [0x55; 64]);
+}
+
+#[test]
+fn test_linear_bad_sector() {
+    init();
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let mut view = image.linear_view().unwrap();
+    let lba = (TEST_QUIRK_CYLINDER as u64 * 2) * 9 + TEST_QUIRK_SECTOR as u64 - 1;
+    let mut buf = [0u8; 512];
+
+    view.seek(SeekFrom::Start((lba - 1) * 512)).unwrap();
+    view.read_exact(&mut buf).unwrap();
+    assert_eq!(view.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // A partial write needs the rest of the damaged sector, but overwriting all of it repairs it.
+    view.seek(SeekFrom::Start(lba * 512 + 16)).unwrap();
+    assert_eq!(view.write(&[0x11; 16]).unwrap_err().kind(), ErrorKind::InvalidData);
+    view.seek(SeekFrom::Start(lba * 512)).unwrap();
+    view.write_all(&[0x11; 512]).unwrap();
+    view.seek(SeekFrom::Start(lba * 512)).unwrap();
+    view.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [0x11; 512]);
+}
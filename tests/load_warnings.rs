@@ -0,0 +1,51 @@
+use fluxfox::diskimage::LoadOptions;
+use fluxfox::{DiskCh, DiskImage};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Set the error flag on the first sector of the first track of an IMD image.
+fn flag_first_sector_error(imd: &mut [u8]) {
+    let track = imd.iter().position(|&b| b == 0x1A).unwrap() + 1;
+    let head = imd[track + 2];
+    let sector_ct = imd[track + 3] as usize;
+    let mut marker = track + 5 + sector_ct;
+    if head & 0x80 != 0 {
+        marker += sector_ct;
+    }
+    if head & 0x40 != 0 {
+        marker += sector_ct;
+    }
+    assert!(matches!(imd[marker], 0x01 | 0x02));
+    imd[marker] += 4;
+}
+
+#[test]
+fn test_load_warnings_clean() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    let result = DiskImage::load_with_warnings(&mut Cursor::new(buf), LoadOptions::default()).unwrap();
+    assert!(result.warnings.is_empty());
+    assert!(result.image.load_warnings().is_empty());
+}
+
+#[test]
+fn test_load_warnings_bad_crc() {
+    init();
+
+    let mut buf = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    flag_first_sector_error(&mut buf);
+
+    let result = DiskImage::load_with_warnings(&mut Cursor::new(buf.clone()), LoadOptions::default()).unwrap();
+    assert_eq!(result.warnings.len(), 1);
+    let warning = &result.warnings[0];
+    assert_eq!(warning.ch, Some(DiskCh::new(0, 0)));
+    assert_eq!(warning.to_string(), "Track [c:0 h:0]: 1 sector(s) had bad data CRCs");
+
+    // The warnings stay available on an image loaded without asking for them.
+    let image = DiskImage::load(&mut Cursor::new(buf)).unwrap();
+    assert_eq!(image.load_warnings(), &result.warnings[..]);
+}
@@ -0,0 +1,67 @@
+use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::structure_parsers::system34::System34Standard;
+use fluxfox::{DiskCh, DiskChs, DiskChsn, DiskDataResolution, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_match_policy() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+
+    // Reformat cylinder 1, head 0 with sector IDs that claim to be on head 1.
+    let ch = DiskCh::new(1, 0);
+    let format_buffer = (1..=9).map(|s| DiskChsn::new(1, 1, s, 2)).collect();
+    image
+        .format_track(ch, System34Standard::Iso, format_buffer, 0xF6, 0x50)
+        .unwrap();
+
+    // The default policy compares all ID fields of a BitStream track, so the sector should not
+    // be found.
+    assert_eq!(image.match_policy(), MatchPolicy::Auto);
+    assert!(image
+        .read_sector(DiskChs::new(1, 0, 1), None, RwSectorScope::DataOnly, false)
+        .is_err());
+
+    // Ignoring the head ID, the sector should be found and flagged as having the wrong head.
+    image.set_match_policy(MatchPolicy::IgnoreHead);
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.wrong_head);
+    assert!(!rsr.wrong_cylinder);
+    assert_eq!(rsr.read_buf.len(), 512);
+
+    // A mismatched size is still rejected.
+    assert!(image
+        .read_sector(DiskChs::new(1, 0, 1), Some(3), RwSectorScope::DataOnly, false)
+        .is_err());
+
+    // Comparing the sector ID only will also find the sector.
+    image.set_match_policy(MatchPolicy::SectorOnly);
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.wrong_head);
+}
+
+#[test]
+fn test_match_policy_auto() {
+    let id = DiskChsn::new(5, 1, 3, 2);
+    let chs = DiskChs::new(1, 0, 3);
+
+    // Auto is resolved against the track resolution by the comparison itself.
+    assert!(MatchPolicy::Auto.matches(id, chs, None, DiskDataResolution::ByteStream));
+    assert!(!MatchPolicy::Auto.matches(id, chs, None, DiskDataResolution::BitStream));
+    assert!(MatchPolicy::Auto.matches(id, DiskChs::from(id), Some(2), DiskDataResolution::BitStream));
+    assert!(!MatchPolicy::Auto.matches(id, DiskChs::from(id), Some(3), DiskDataResolution::FluxStream));
+}
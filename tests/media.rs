@@ -0,0 +1,56 @@
+use fluxfox::media::{DensityHole, MediaProfile};
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_media_profiles() {
+    init();
+
+    // Bitcell counts should agree with the standard formats written to each medium.
+    for format in [
+        StandardFormat::PcFloppy360,
+        StandardFormat::PcFloppy720,
+        StandardFormat::PcFloppy1200,
+        StandardFormat::PcFloppy1440,
+        StandardFormat::PcFloppy2880,
+    ] {
+        let profile = format.get_media_profile();
+        assert_eq!(profile.bitcells(), format.get_bitcell_ct(), "{}", profile);
+        assert_eq!(profile.rpm(), format.get_rpm(), "{}", profile);
+        assert_eq!(profile.data_rate(), format.get_data_rate(), "{}", profile);
+    }
+
+    assert!(MediaProfile::Hd3_5.hd_hole());
+    assert!(!MediaProfile::Hd5_25.hd_hole());
+    assert_eq!(MediaProfile::Ed3_5.density_hole(), DensityHole::ExtendedDensity);
+    assert_eq!(MediaProfile::Amstrad3.sides(), 1);
+    assert_eq!(MediaProfile::list().len(), 8);
+}
+
+#[test]
+fn test_check_media_profile() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert!(image.check_media_profile(MediaProfile::Dd5_25).is_ok());
+    assert!(image.check_media_profile(MediaProfile::Dd3_5).is_ok());
+
+    // Wrong data rate for every track.
+    match image.check_media_profile(MediaProfile::Hd3_5) {
+        Err(DiskImageError::IncompatibleTracks(tracks)) => assert_eq!(tracks.len(), 80),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // The Amstrad drive is single-sided, so every track on head 1 is reported.
+    match image.check_media_profile(MediaProfile::Amstrad3) {
+        Err(DiskImageError::IncompatibleTracks(tracks)) => {
+            assert_eq!(tracks.len(), 40);
+            assert!(tracks.iter().all(|ch| ch.h() == 1));
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,55 @@
+use fluxfox::diskimage::{BackupPolicy, LoadOptions, MemoryUsage};
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskImage, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_memory_usage_bitstream() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let report = image.memory_usage();
+    assert_eq!(report.tracks.len(), 80);
+    assert_eq!(report.original_bytes, 0);
+
+    let (ch, usage) = report.tracks[0];
+    assert_eq!(ch, DiskCh::new(0, 0));
+    // A 100,000 bitcell MFM track holds a bitstream, clock map and weak mask of one bit per cell.
+    assert!(usage.bitstream >= 100_000 / 8);
+    assert!(usage.clock_map >= 100_000 / 8);
+    assert!(usage.weak_mask >= 100_000 / 8);
+    assert!(usage.metadata > 0);
+    assert_eq!(usage.bytestream, 0);
+
+    let total = report.tracks_total();
+    assert_eq!(
+        total.bitstream,
+        report.tracks.iter().map(|(_, u)| u.bitstream).sum::<usize>()
+    );
+    assert_eq!(report.total(), total.total());
+    assert!(total.to_string().starts_with(&format!("{} bytes", total.total())));
+}
+
+#[test]
+fn test_memory_usage_bytestream() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let options = LoadOptions {
+        backup_policy: BackupPolicy::InMemory,
+        ..Default::default()
+    };
+    let image = DiskImage::load_with_options(&mut Cursor::new(buf.clone()), options).unwrap();
+    let report = image.memory_usage();
+
+    let total = report.tracks_total();
+    assert!(total.bytestream >= buf.len());
+    assert_eq!((total.bitstream, total.clock_map), (0, 0));
+    assert_eq!(report.original_bytes, buf.len());
+    assert_eq!(report.total(), total.total() + buf.len());
+    assert_ne!(total, MemoryUsage::default());
+}
@@ -0,0 +1,38 @@
+use fluxfox::testutil::{TestImage, TEST_DUPLICATE_IDS, TEST_QUIRK_CYLINDER};
+use fluxfox::{DiskCh, DiskChs};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_next_id_duplicates() {
+    init();
+
+    let image = TestImage::DuplicateIds.generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let ids = TEST_DUPLICATE_IDS;
+
+    // Walk the track by position. Every header should be visited, in order.
+    let mut bit_index = 0;
+    let mut positions = Vec::new();
+    for id in ids {
+        let (chsn, idx) = image.get_next_id_at(ch, bit_index).unwrap();
+        assert_eq!(chsn.s(), id);
+        positions.push(idx);
+        bit_index = idx + 1;
+    }
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+    // Continuing past the last header wraps around to the first.
+    let (chsn, idx) = image.get_next_id_at(ch, bit_index).unwrap();
+    assert_eq!(chsn.s(), 1);
+    assert_eq!(idx, positions[0]);
+
+    // Lookup by ID uses the first occurrence.
+    let c = TEST_QUIRK_CYLINDER;
+    assert_eq!(image.get_next_id(DiskChs::new(c, 0, 4)).unwrap().s(), 5);
+    assert_eq!(image.get_next_id(DiskChs::new(c, 0, 5)).unwrap().s(), 5);
+    assert_eq!(image.get_next_id(DiskChs::new(c, 0, 9)).unwrap().s(), 1);
+    assert!(image.get_next_id(DiskChs::new(c, 0, 6)).is_none());
+}
@@ -0,0 +1,106 @@
+use fluxfox::convert::ConvertPolicy;
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskChs, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Build a raw 720K image with `cylinders` cylinders, each sector filled with its LBA.
+fn build_raw_720(cylinders: usize) -> Vec<u8> {
+    (0..cylinders * 2 * 9).flat_map(|lba| [lba as u8; 512]).collect()
+}
+
+#[test]
+fn test_from_raw_size() {
+    init();
+
+    assert_eq!(
+        StandardFormat::from_raw_size(737_280),
+        Some((StandardFormat::PcFloppy720, 80))
+    );
+    assert_eq!(
+        StandardFormat::from_raw_size(84 * 2 * 18 * 512),
+        Some((StandardFormat::PcFloppy1440, 84))
+    );
+    assert_eq!(
+        StandardFormat::from_raw_size(42 * 2 * 9 * 512),
+        Some((StandardFormat::PcFloppy360, 42))
+    );
+    assert_eq!(
+        StandardFormat::from_raw_size(86 * 2 * 15 * 512),
+        Some((StandardFormat::PcFloppy1200, 86))
+    );
+    // Exact sizes are preferred over overdumps of formats with fewer sectors per track.
+    assert_eq!(
+        StandardFormat::from_raw_size(368_640),
+        Some((StandardFormat::PcFloppy360, 40))
+    );
+    assert_eq!(
+        StandardFormat::from_raw_size(184_320),
+        Some((StandardFormat::PcFloppy180, 40))
+    );
+    assert_eq!(StandardFormat::from_raw_size(87 * 2 * 9 * 512), None);
+    assert_eq!(StandardFormat::from_raw_size(737_281), None);
+}
+
+#[test]
+fn test_overdump_raw_load() {
+    init();
+
+    let raw = build_raw_720(84);
+    let mut image = DiskImage::load(&mut Cursor::new(raw.clone())).unwrap();
+    assert_eq!(image.image_format().geometry, DiskCh::new(84, 2));
+    assert_eq!(image.nominal_cylinders(), Some(80));
+
+    let rsr = image
+        .read_sector(DiskChs::new(83, 1, 9), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf[rsr.data_idx], (84 * 2 * 9 - 1) as u8);
+
+    // Formatted overdumped cylinders are kept unless trimmed.
+    let mut out = Cursor::new(Vec::new());
+    image
+        .save_with_policy(DiskImageFormat::RawSectorImage, ConvertPolicy::strict(), &mut out)
+        .unwrap();
+    assert!(out.into_inner() == raw);
+
+    let policy = ConvertPolicy {
+        trim_overdump: true,
+        ..ConvertPolicy::strict()
+    };
+    let mut out = Cursor::new(Vec::new());
+    image
+        .save_with_policy(DiskImageFormat::RawSectorImage, policy, &mut out)
+        .unwrap();
+    assert!(out.into_inner() == raw[..737_280]);
+}
+
+#[test]
+fn test_overdump_unformatted_dropped() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy720).generate().unwrap();
+    for c in 80..84 {
+        for h in 0..2 {
+            image
+                .add_empty_track(
+                    DiskCh::new(c, h),
+                    DiskDataEncoding::Mfm,
+                    DiskDataRate::Rate250Kbps,
+                    100_000,
+                )
+                .unwrap();
+        }
+    }
+    assert_eq!(image.get_track_ct(0), 84);
+    assert_eq!(image.nominal_cylinders(), Some(80));
+
+    let mut out = Cursor::new(Vec::new());
+    image
+        .save_with_policy(DiskImageFormat::RawSectorImage, ConvertPolicy::strict(), &mut out)
+        .unwrap();
+    assert_eq!(out.into_inner().len(), 737_280);
+}
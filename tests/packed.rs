@@ -0,0 +1,128 @@
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::packed::PackedKind;
+use fluxfox::util::crc32;
+use fluxfox::{DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+/// Write `data` to consecutive sectors starting at `lba`, as a file would be laid out on disk.
+fn write_file(image: &mut DiskImage, lba: usize, data: &[u8]) {
+    for (i, chunk) in data.chunks(512).enumerate() {
+        let mut sector = chunk.to_vec();
+        sector.resize(512, 0);
+        image.write_lba(lba + i, &sector).unwrap();
+    }
+}
+
+/// Build a DOS executable of `size` bytes with `signature` at offset 0x1C.
+fn build_exe(size: usize, signature: &[u8]) -> Vec<u8> {
+    let mut exe = vec![0x90; size];
+    exe[0..2].copy_from_slice(b"MZ");
+    exe[2..4].copy_from_slice(&((size % 512) as u16).to_le_bytes());
+    exe[4..6].copy_from_slice(&(size.div_ceil(512) as u16).to_le_bytes());
+    exe[0x1C..0x1C + signature.len()].copy_from_slice(signature);
+    exe
+}
+
+/// Build a stored ZIP archive of a single file.
+fn build_zip(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut zip = b"PK\x03\x04".to_vec();
+    zip.extend([20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    zip.extend(crc32(data).to_le_bytes());
+    zip.extend((data.len() as u32).to_le_bytes());
+    zip.extend((data.len() as u32).to_le_bytes());
+    zip.extend((name.len() as u16).to_le_bytes());
+    zip.extend([0, 0]);
+    zip.extend(name.as_bytes());
+    zip.extend(data);
+
+    // A central directory is not needed to be found, only its end record.
+    zip.extend(b"PK\x05\x06");
+    zip.extend([0; 16]);
+    zip.extend([3, 0]);
+    zip.extend(b"hi!");
+    zip
+}
+
+fn arj_header(out: &mut Vec<u8>, file_type: u8, name: &str, compressed_len: usize) {
+    let mut header = vec![30, 11, 1, 0, 0, 0, file_type, 0];
+    header.extend([0; 4]);
+    header.extend((compressed_len as u32).to_le_bytes());
+    header.extend([0; 14]);
+    header.extend(name.as_bytes());
+    header.extend([0, 0]);
+
+    out.extend([0x60, 0xEA]);
+    out.extend((header.len() as u16).to_le_bytes());
+    out.extend(&header);
+    out.extend(crc32(&header).to_le_bytes());
+    out.extend([0, 0]);
+}
+
+/// Build an ARJ archive of two stored files.
+fn build_arj(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut arj = Vec::new();
+    arj_header(&mut arj, 2, name, 0);
+    for file in ["ONE.TXT", "TWO.TXT"] {
+        arj_header(&mut arj, 0, file, data.len());
+        arj.extend(data);
+    }
+    arj.extend([0x60, 0xEA, 0, 0]);
+    arj
+}
+
+#[test]
+fn test_packed_scan() {
+    init();
+    let mut image = build_image();
+    assert!(image.scan_packed().unwrap().is_empty());
+
+    let lzexe = build_exe(1500, b"LZ91");
+    write_file(&mut image, 10, &lzexe);
+    write_file(
+        &mut image,
+        20,
+        &build_exe(3000, b"\x00\x00PKLITE Copr. 1990-92 PKWARE Inc."),
+    );
+
+    let zip = build_zip("README.TXT", &[0x41; 1200]);
+    write_file(&mut image, 30, &zip);
+
+    // A self-extracting archive: an unpacked stub followed by an ARJ archive.
+    let mut sfx = build_exe(700, b"RJSX");
+    let arj = build_arj("DATA.ARJ", &[0x42; 600]);
+    sfx.extend(&arj);
+    write_file(&mut image, 40, &sfx);
+
+    // A ZIP archive cut off by the end of the disk.
+    write_file(&mut image, 719, &zip[..512]);
+
+    let regions = image.scan_packed().unwrap();
+    let summary = regions
+        .iter()
+        .map(|r| (r.kind, r.offset, r.len, r.lbas.clone(), r.truncated))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (PackedKind::Lzexe, 10 * 512, 1500, 10..=12, false),
+            (PackedKind::Pklite, 20 * 512, 3000, 20..=25, false),
+            (PackedKind::Zip, 30 * 512, zip.len(), 30..=32, false),
+            (PackedKind::Arj, 40 * 512 + 700, arj.len(), 41..=44, false),
+            (PackedKind::Zip, 719 * 512, 512, 719..=719, true),
+        ]
+    );
+    assert_eq!(regions[2].name.as_deref(), Some("README.TXT"));
+    assert_eq!(regions[3].name.as_deref(), Some("DATA.ARJ"));
+}
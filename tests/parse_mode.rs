@@ -0,0 +1,46 @@
+use fluxfox::diskimage::{LoadOptions, ParseMode};
+use fluxfox::{DiskImage, DiskImageError};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn strict() -> LoadOptions {
+    LoadOptions {
+        parse_mode: ParseMode::Strict,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_parse_mode_clean() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    let result = DiskImage::load_with_warnings(&mut Cursor::new(buf), strict()).unwrap();
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn test_parse_mode_trailing_garbage() {
+    init();
+
+    // An invalid track header after the last track.
+    let mut buf = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    buf.extend_from_slice(&[0xFF; 5]);
+
+    let result = DiskImage::load_with_warnings(&mut Cursor::new(buf.clone()), LoadOptions::default()).unwrap();
+    assert_eq!(result.warnings.len(), 1);
+    assert!(result.warnings[0].message.contains("Invalid track header"));
+
+    let clean = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    let clean = DiskImage::load(&mut Cursor::new(clean)).unwrap();
+    assert_eq!(result.image.image_format().geometry, clean.image_format().geometry);
+
+    match DiskImage::load_with_warnings(&mut Cursor::new(buf), strict()) {
+        Err(DiskImageError::SpecViolation(msg)) => assert!(msg.contains("Invalid track header")),
+        Err(e) => panic!("unexpected error: {:?}", e),
+        Ok(_) => panic!("strict load should fail"),
+    }
+}
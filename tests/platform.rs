@@ -0,0 +1,104 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::platform::DiskPlatform;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{DiskCh, DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+fn write(image: &mut DiskImage, chs: DiskChs, buf: &[u8]) {
+    image
+        .write_sector(chs, None, buf, RwSectorScope::DataOnly, false, false)
+        .unwrap();
+}
+
+#[test]
+fn test_platform_dos() {
+    init();
+
+    let mut image = build_image();
+    let report = image.detect_platform();
+    assert_eq!(report.platform(), DiskPlatform::Dos);
+    assert!(report.platform().uses_system34() && report.platform().uses_fat());
+    assert_eq!(report.scores(), vec![(DiskPlatform::Dos, 4)]);
+    assert!(report.to_string().starts_with("Platform: DOS\n"));
+}
+
+#[test]
+fn test_platform_atari_st() {
+    init();
+
+    let mut image = build_image();
+    // An executable boot sector: a BRA.S, with the last word adjusting the checksum to 0x1234.
+    let mut buf = [0u8; 512];
+    buf[0] = 0x60;
+    buf[1] = 0x1C;
+    let sum = buf[..510]
+        .chunks_exact(2)
+        .fold(0u16, |sum, w| sum.wrapping_add(u16::from_be_bytes([w[0], w[1]])));
+    buf[510..].copy_from_slice(&0x1234u16.wrapping_sub(sum).to_be_bytes());
+    write(&mut image, DiskChs::new(0, 0, 1), &buf);
+
+    let report = image.detect_platform();
+    assert_eq!(report.platform(), DiskPlatform::AtariSt);
+    assert_eq!(report.scores()[0], (DiskPlatform::AtariSt, 5));
+}
+
+#[test]
+fn test_platform_cpm() {
+    init();
+
+    let mut image = build_image();
+    write(&mut image, DiskChs::new(0, 0, 1), &[0xE5; 512]);
+    let mut dir = [0xE5u8; 512];
+    dir[..32].fill(0);
+    dir[1..12].copy_from_slice(b"PIP     COM");
+    dir[32..64].fill(0);
+    dir[32] = 1;
+    // The high bit of the first extension byte marks the file read-only.
+    dir[33..44].copy_from_slice(b"STAT    COM");
+    dir[41] |= 0x80;
+    write(&mut image, DiskChs::new(2, 0, 1), &dir);
+
+    let report = image.detect_platform();
+    assert_eq!(report.platform(), DiskPlatform::Cpm);
+    assert_eq!(report.evidence[0].description, "CP/M directory entries on cylinder 2");
+}
+
+#[test]
+fn test_platform_amiga() {
+    init();
+
+    let mut image = build_image();
+    let builder = TrackBuilder::new()
+        .with_gap(0x00, 4)
+        .with_bits(&[0x44, 0x89, 0x44, 0x89], 32, None)
+        .unwrap()
+        .with_gap(0xFF, 32);
+    image.splice_track(DiskCh::new(0, 0), &builder).unwrap();
+
+    let report = image.detect_platform();
+    assert_eq!(report.platform(), DiskPlatform::Amiga);
+    assert!(!report.platform().uses_system34());
+
+    // A blank disk has no evidence.
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .build()
+        .unwrap();
+    let report = image.detect_platform();
+    assert!(report.evidence.is_empty());
+    assert_eq!(report.platform(), DiskPlatform::Unknown);
+}
@@ -0,0 +1,90 @@
+use fluxfox::bitstream::pll::Pll;
+use fluxfox::flux::FluxRevolution;
+use fluxfox::DiskDataRate;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Capture clock ticks per bitcell of a 250Kbps MFM track at a 25ns capture resolution.
+const TICKS_PER_CELL: u32 = 80;
+const TICK: f64 = 25e-9;
+
+/// Build a revolution of intervals of 2, 3 and 4 bitcells, with `f` applied to each interval
+/// given its index.
+fn build_flux(f: impl Fn(usize, u32) -> u32) -> FluxRevolution {
+    let intervals: Vec<u32> = [2, 3, 4]
+        .iter()
+        .cycle()
+        .take(3000)
+        .enumerate()
+        .map(|(i, &cells)| f(i, cells * TICKS_PER_CELL))
+        .collect();
+    let index_ticks = intervals.iter().map(|&i| i as u64).sum();
+    FluxRevolution::new(intervals, TICK, index_ticks)
+}
+
+#[test]
+fn test_pll_clean() {
+    init();
+
+    let pll = Pll::from_data_rate(DiskDataRate::Rate250Kbps);
+    let (bits, stats) = pll.decode_with_stats(&build_flux(|_, i| i));
+    assert_eq!(bits.len(), 9000);
+    assert_eq!(bits.iter().filter(|&b| b).count(), 3000);
+    assert_eq!(stats.transitions, 3000);
+    assert_eq!(stats.bitcells, 9000);
+    assert_eq!(stats.out_of_band, 0);
+    assert_eq!(stats.lock_losses, 0);
+    assert!(stats.max_phase_error < 0.01);
+    assert!((stats.mean_clock - pll.cell_time()).abs() < pll.cell_time() * 0.01);
+}
+
+#[test]
+fn test_pll_out_of_band() {
+    init();
+
+    // Stretch every tenth interval by 0.4 of a bitcell, outside the default jitter tolerance.
+    let flux = build_flux(|n, i| if n % 10 == 5 { i + TICKS_PER_CELL * 2 / 5 } else { i });
+    let pll = Pll::from_data_rate(DiskDataRate::Rate250Kbps);
+    let (bits, stats) = pll.decode_with_stats(&flux);
+    assert_eq!(bits.len(), 9000);
+    assert_eq!(stats.out_of_band, 300);
+    assert_eq!(stats.lock_losses, 0);
+
+    // A wider data window accepts the same transitions.
+    let (_, stats) = pll.with_jitter_tolerance(0.45).decode_with_stats(&flux);
+    assert_eq!(stats.out_of_band, 0);
+}
+
+#[test]
+fn test_pll_lock_loss() {
+    init();
+
+    // A burst of intervals between cell boundaries, as from a damaged region of the track.
+    let flux = build_flux(|n, i| {
+        if (1500..1516).contains(&n) {
+            TICKS_PER_CELL * 7 / 5
+        } else {
+            i
+        }
+    });
+    let pll = Pll::from_data_rate(DiskDataRate::Rate250Kbps);
+    let (_, stats) = pll.decode_with_stats(&flux);
+    assert!(stats.lock_losses >= 1);
+    assert!(stats.out_of_band >= 16);
+}
+
+#[test]
+fn test_pll_parameters() {
+    init();
+
+    let pll = Pll::new(1e-6)
+        .with_adjust_rate(0.1)
+        .with_clock_tolerance(0.2)
+        .with_jitter_tolerance(0.3);
+    assert_eq!(pll.cell_time(), 1e-6);
+    assert_eq!(pll.adjust_rate(), 0.1);
+    assert_eq!(pll.clock_tolerance(), 0.2);
+    assert_eq!(pll.jitter_tolerance(), 0.3);
+}
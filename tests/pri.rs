@@ -57,7 +57,7 @@ fn test_pri_sector_reads() {
     // Collect indices to avoid borrowing issues
     let ti_vec: Vec<usize> = pri_image.track_idx_iter().collect();
     for ti in ti_vec {
-        if let Some(td) = pri_image.get_track_mut(ti) {
+        if let Ok(td) = pri_image.get_track_mut(ti) {
             let ch = td.ch();
             println!("Reading track {}...", ch);
             let rtr = match td.read_all_sectors(ch, 2, 0) {
@@ -105,3 +105,139 @@ fn test_pri_sector_reads() {
         }
     }
 }
+
+fn pri_crc(buf: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in buf {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            if crc & 0x80000000 != 0 {
+                crc = (crc << 1) ^ 0x1edc6f41;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+fn pri_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    let mut chunk = id.to_vec();
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(data);
+    let crc = pri_crc(&chunk);
+    out.extend(chunk);
+    out.extend(crc.to_be_bytes());
+}
+
+#[test]
+fn test_pri_weak_mask_and_clock() {
+    init();
+    use fluxfox::DiskDataRate;
+    use std::io::Cursor;
+
+    const BITCELLS: u32 = 100_000;
+
+    let mut pri = Vec::new();
+    pri_chunk(&mut pri, b"PRI ", &[0; 4]);
+
+    let mut trak = Vec::new();
+    for value in [0, 0, BITCELLS, 500_000] {
+        trak.extend(u32::to_be_bytes(value));
+    }
+    pri_chunk(&mut pri, b"TRAK", &trak);
+    pri_chunk(&mut pri, b"DATA", &[0xAA; BITCELLS as usize / 8]);
+
+    // Weak bits at an unaligned bit offset.
+    let mut weak = 1004u32.to_be_bytes().to_vec();
+    weak.extend([0xFF, 0xF0]);
+    pri_chunk(&mut pri, b"WEAK", &weak);
+
+    // Clock chunks may follow the track data. Double the clock from the start of the track.
+    let mut bclk = 0u32.to_be_bytes().to_vec();
+    bclk.extend((u16::MAX as u32 * 2).to_be_bytes());
+    pri_chunk(&mut pri, b"BCLK", &bclk);
+    pri_chunk(&mut pri, b"END ", &[]);
+
+    let image = DiskImage::load(&mut Cursor::new(pri)).unwrap();
+    assert!(image.has_weak_bits());
+
+    let sector_map = image.get_sector_map();
+    assert!(matches!(sector_map[0][0].data_rate, DiskDataRate::Rate1000Kbps));
+
+    // Save and reload, and the weak bits survive.
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    out_buffer.set_position(0);
+    let reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    assert!(reloaded.has_weak_bits());
+}
+
+#[test]
+fn test_pri_weak_sector_round_trip() {
+    init();
+    use fluxfox::diskimage::RwSectorScope;
+    use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+    use fluxfox::DiskChs;
+    use std::io::Cursor;
+
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let mut image = TestImage::WeakBits.generate().unwrap();
+    let weak_mask = image
+        .read_sector(chs, None, RwSectorScope::DataOnly, false)
+        .unwrap()
+        .weak_mask
+        .unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    out_buffer.set_position(0);
+
+    let mut reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    let result = reloaded.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert_eq!(result.weak_mask, Some(weak_mask));
+}
+
+#[test]
+fn test_pri_export_empty_track_clock() {
+    init();
+    use fluxfox::testutil::TestImage;
+    use fluxfox::{DiskCh, DiskDataEncoding, DiskDataRate, StandardFormat};
+    use std::io::Cursor;
+
+    // Empty tracks carry no bit clock of their own, and are written at the track's data rate.
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let bitcells = image.get_track_ch(DiskCh::new(0, 0)).unwrap().bitcell_ct();
+    image
+        .add_empty_track(
+            DiskCh::new(40, 0),
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            bitcells,
+        )
+        .unwrap();
+    image
+        .add_empty_track(
+            DiskCh::new(40, 1),
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            bitcells,
+        )
+        .unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceBitstreamImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    out_buffer.set_position(0);
+
+    let reloaded = DiskImage::load(&mut out_buffer).unwrap();
+    assert_eq!(reloaded.get_track_ct(0), 41);
+    let track = reloaded.get_track_ch(DiskCh::new(40, 0)).unwrap();
+    assert!(matches!(track.data_rate(), DiskDataRate::Rate250Kbps));
+    assert_eq!(track.bitcell_ct(), bitcells);
+}
@@ -0,0 +1,81 @@
+use fluxfox::convert::ConvertPolicy;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::progress::{CancellationToken, Progress, ProgressStatus};
+use fluxfox::{DiskDataRate, DiskDataResolution, DiskImage, DiskImageError, DiskImageFormat, DiskRpm, StandardFormat};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_progress_callback() {
+    init();
+
+    let mut image = build_image();
+    let reports = Arc::new(Mutex::new(Vec::<ProgressStatus>::new()));
+    let reports_cb = reports.clone();
+    image.set_progress(Progress::new().with_callback(move |status| reports_cb.lock().unwrap().push(status)));
+
+    image.resample(DiskDataRate::Rate250Kbps, DiskRpm::Rpm360).unwrap();
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 81);
+        assert!(reports.iter().all(|r| r.operation == "resample" && r.total == 80));
+        assert_eq!(reports.last().unwrap().completed, 80);
+    }
+
+    reports.lock().unwrap().clear();
+    let mut out = Cursor::new(Vec::new());
+    image
+        .save_with_policy(DiskImageFormat::RawSectorImage, ConvertPolicy::default(), &mut out)
+        .unwrap();
+    let reports = reports.lock().unwrap();
+    assert!(reports.iter().all(|r| r.operation == "save"));
+    assert_eq!(
+        reports.iter().map(|r| r.completed).collect::<Vec<_>>(),
+        (0..=80).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_progress_cancel() {
+    init();
+
+    let mut image = build_image();
+    let token = CancellationToken::new();
+    let token_cb = token.clone();
+    // Cancel partway through, as a user interface might from another thread.
+    image.set_progress(Progress::new().with_token(token.clone()).with_callback(move |status| {
+        if status.completed == 10 {
+            let token = token_cb.clone();
+            std::thread::spawn(move || token.cancel()).join().unwrap();
+        }
+    }));
+
+    let result = image.format(StandardFormat::PcFloppy360, None, None);
+    assert!(matches!(result, Err(DiskImageError::Cancelled)));
+    assert!(token.is_cancelled());
+
+    // The progress survives the reset performed by format(), and stays cancelled.
+    assert!(image.progress().token().is_cancelled());
+    assert!(matches!(
+        image.resample(DiskDataRate::Rate250Kbps, DiskRpm::Rpm360),
+        Err(DiskImageError::Cancelled)
+    ));
+
+    // A fresh token allows operations to run again.
+    image.set_progress(Progress::new());
+    image.format(StandardFormat::PcFloppy360, None, None).unwrap();
+    assert_eq!(image.get_sector_map()[1][39].sectors.len(), 9);
+}
@@ -42,3 +42,156 @@ fn test_psi() {
     assert_eq!(in_hash, out_hash);
     println!("Hashes match!");
 }
+
+fn psi_crc(buf: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in buf {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            if crc & 0x80000000 != 0 {
+                crc = (crc << 1) ^ 0x1edc6f41;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+fn psi_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    let mut chunk = id.to_vec();
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(data);
+    let crc = psi_crc(&chunk);
+    out.extend(chunk);
+    out.extend(crc.to_be_bytes());
+}
+
+fn psi_sect(out: &mut Vec<u8>, s: u8, size: u16, flags: u8, fill: u8) {
+    let mut sect = vec![0, 0, 0, s];
+    sect.extend(size.to_be_bytes());
+    sect.extend([flags, fill]);
+    psi_chunk(out, b"SECT", &sect);
+}
+
+/// Build a single track PSI image with alternate sector data, a weak mask, IBM MFM sector headers,
+/// and sector position and read time chunks.
+fn build_psi() -> Vec<u8> {
+    let mut psi = Vec::new();
+    psi_chunk(&mut psi, b"PSI ", &[0x00, 0x00, 0x01, 0x00]);
+
+    // Sector 1, with an alternate read differing in the low nibble of byte 10.
+    psi_sect(&mut psi, 1, 512, 0, 0);
+    psi_chunk(&mut psi, b"IMFM", &[0, 0, 1, 2, 0, 0]);
+    psi_chunk(&mut psi, b"DATA", &[0x00; 512]);
+    psi_chunk(&mut psi, b"OFFS", &1000u32.to_be_bytes());
+    psi_chunk(&mut psi, b"TIME", &4096u32.to_be_bytes());
+    psi_sect(&mut psi, 1, 512, 0x02, 0);
+    let mut alt = vec![0x00; 512];
+    alt[10] = 0x0F;
+    psi_chunk(&mut psi, b"DATA", &alt);
+
+    // Sector 2, compressed, with a weak mask and a sector ID that doesn't match its location.
+    psi_sect(&mut psi, 2, 256, 0x01, 0xE5);
+    psi_chunk(&mut psi, b"IMFM", &[5, 1, 2, 1, 0x06, 0]);
+    let mut weak = vec![0x00; 256];
+    weak[0] = 0x80;
+    psi_chunk(&mut psi, b"WEAK", &weak);
+
+    // Sector 3, with no data address mark.
+    psi_sect(&mut psi, 3, 512, 0, 0);
+    psi_chunk(&mut psi, b"IMFM", &[0, 0, 3, 2, 0x08, 0]);
+
+    psi_chunk(&mut psi, b"END ", &[]);
+    psi
+}
+
+#[test]
+fn test_psi_sector_chunks() {
+    use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+    use fluxfox::{DiskChs, DiskDataEncoding};
+    use std::io::Cursor;
+
+    let mut image = DiskImage::load(&mut Cursor::new(build_psi())).unwrap();
+    image.set_match_policy(MatchPolicy::SectorOnly);
+
+    let sector_map = image.get_sector_map();
+    let track = &sector_map[0][0];
+    assert!(matches!(track.encoding, DiskDataEncoding::Mfm));
+    assert_eq!(track.to_string(), "1 2d! 3m");
+    assert_eq!(track.sectors[0].position, Some(1000));
+    assert_eq!(track.sectors[0].read_time, Some(4096));
+    assert_eq!(track.sectors[1].position, None);
+    assert_eq!((track.sectors[1].chsn.c(), track.sectors[1].chsn.h()), (5, 1));
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![0x00; 512]);
+    let weak_mask = result.weak_mask.unwrap();
+    assert_eq!(weak_mask.len(), 512);
+    assert_eq!(weak_mask[10], 0x0F);
+    assert_eq!(weak_mask.iter().filter(|&&b| b != 0).count(), 1);
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.deleted_mark && result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0xE5; 256]);
+    assert_eq!(result.weak_mask.unwrap()[0], 0x80);
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 3), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(result.no_dam);
+    assert!(result.weak_mask.is_none());
+}
+
+#[test]
+fn test_psi_export_roundtrip() {
+    use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+    use fluxfox::DiskChs;
+    use std::io::Cursor;
+
+    let mut image = DiskImage::load(&mut Cursor::new(build_psi())).unwrap();
+    image.set_comment("fluxfox test".to_string());
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceSectorImage
+        .save_image(&image, &mut out_buffer)
+        .unwrap();
+    let psi = out_buffer.into_inner();
+
+    let mut reloaded = DiskImage::load(&mut Cursor::new(psi.clone())).unwrap();
+    assert_eq!(reloaded.source_format(), Some(DiskImageFormat::PceSectorImage));
+    assert_eq!(reloaded.load_warnings(), image.load_warnings());
+    assert_eq!(reloaded.get_comment(), Some("fluxfox test"));
+
+    let (track, reloaded_track) = (&image.get_sector_map()[0][0], &reloaded.get_sector_map()[0][0]);
+    assert_eq!(track.to_string(), reloaded_track.to_string());
+    for (sector, reloaded_sector) in track.sectors.iter().zip(&reloaded_track.sectors) {
+        assert_eq!(sector.chsn, reloaded_sector.chsn);
+        assert_eq!(sector.position, reloaded_sector.position);
+        assert_eq!(sector.read_time, reloaded_sector.read_time);
+    }
+
+    image.set_match_policy(MatchPolicy::SectorOnly);
+    reloaded.set_match_policy(MatchPolicy::SectorOnly);
+    for s in 1..=3 {
+        let chs = DiskChs::new(0, 0, s);
+        let original = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        let result = reloaded.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+        assert_eq!(result.read_buf, original.read_buf);
+        assert_eq!(result.weak_mask, original.weak_mask);
+        assert_eq!(result.data_crc_error, original.data_crc_error);
+        assert_eq!(result.deleted_mark, original.deleted_mark);
+        assert_eq!(result.no_dam, original.no_dam);
+    }
+
+    // Saving the reloaded image reproduces the same file.
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::PceSectorImage
+        .save_image(&reloaded, &mut out_buffer)
+        .unwrap();
+    assert!(out_buffer.into_inner() == psi);
+}
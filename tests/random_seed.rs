@@ -0,0 +1,70 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskImage};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Read the weak sector of `image` several times, returning the data of each read.
+fn read_weak_sector(image: &mut DiskImage) -> Vec<Vec<u8>> {
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    (0..4)
+        .map(|_| {
+            image
+                .read_sector(chs, None, RwSectorScope::DataOnly, false)
+                .unwrap()
+                .read_buf
+        })
+        .collect()
+}
+
+#[test]
+fn test_random_seed_reproducible() {
+    init();
+
+    let mut first = TestImage::WeakBits.generate().unwrap();
+    let mut second = TestImage::WeakBits.generate().unwrap();
+    first.set_random_seed(0x1234);
+    second.set_random_seed(0x1234);
+    assert_eq!(first.random_seed(), Some(0x1234));
+
+    let first_reads = read_weak_sector(&mut first);
+    assert_eq!(first_reads, read_weak_sector(&mut second));
+
+    // Weak bits still read differently from one read to the next.
+    assert!(first_reads.windows(2).any(|w| w[0][..16] != w[1][..16]));
+    // Only the weak bytes vary.
+    assert!(first_reads.windows(2).all(|w| w[0][16..] == w[1][16..]));
+}
+
+#[test]
+fn test_random_seed_differs() {
+    init();
+
+    let mut first = TestImage::WeakBits.generate().unwrap();
+    let mut second = TestImage::WeakBits.generate().unwrap();
+    first.set_random_seed(1);
+    second.set_random_seed(2);
+
+    assert_ne!(read_weak_sector(&mut first), read_weak_sector(&mut second));
+}
+
+#[test]
+fn test_random_seed_volume_serial() {
+    init();
+
+    let mut first = TestImage::Standard(fluxfox::StandardFormat::PcFloppy360)
+        .generate()
+        .unwrap();
+    let mut second = TestImage::Standard(fluxfox::StandardFormat::PcFloppy360)
+        .generate()
+        .unwrap();
+    first.set_random_seed(7);
+    second.set_random_seed(7);
+
+    assert_eq!(
+        first.set_volume_serial(None).unwrap(),
+        second.set_volume_serial(None).unwrap()
+    );
+}
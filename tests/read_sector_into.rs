@@ -0,0 +1,65 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskChs, DiskDataResolution, DiskImage};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Read every sector of the first cylinders with `read_sector_into` and check the results
+/// against `read_sector`, reading into a single buffer.
+fn compare_reads(image: &mut DiskImage, scope: RwSectorScope) {
+    let mut buf = Vec::with_capacity(1024);
+    let buf_ptr = buf.as_ptr();
+    for c in 0..2 {
+        for s in 1..=9 {
+            let chs = DiskChs::new(c, 0, s);
+            let expected = image.read_sector(chs, None, scope, false).unwrap();
+            let rsr = image.read_sector_into(chs, None, scope, false, &mut buf).unwrap();
+            assert!(rsr.read_buf.is_empty());
+            assert_eq!(buf, expected.read_buf);
+            assert_eq!(
+                (rsr.data_idx, rsr.data_len, rsr.data_crc_error, rsr.deleted_mark),
+                (
+                    expected.data_idx,
+                    expected.data_len,
+                    expected.data_crc_error,
+                    expected.deleted_mark
+                )
+            );
+        }
+    }
+    // The buffer was never reallocated.
+    assert_eq!(buf.as_ptr(), buf_ptr);
+}
+
+#[test]
+fn test_read_sector_into() {
+    init();
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    compare_reads(&mut image, RwSectorScope::DataOnly);
+    compare_reads(&mut image, RwSectorScope::DataBlock);
+
+    let mut buf = vec![0xFF; 4];
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let rsr = image
+        .read_sector_into(chs, None, RwSectorScope::DataOnly, false, &mut buf)
+        .unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(buf.len(), 512);
+
+    assert!(image
+        .read_sector_into(DiskChs::new(0, 0, 20), None, RwSectorScope::DataOnly, false, &mut buf)
+        .is_err());
+}
+
+#[test]
+fn test_read_sector_into_bytestream() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut std::io::Cursor::new(buf)).unwrap();
+    assert!(matches!(image.resolution(), DiskDataResolution::ByteStream));
+    compare_reads(&mut image, RwSectorScope::DataOnly);
+}
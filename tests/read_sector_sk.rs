@@ -0,0 +1,46 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::DiskChs;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_read_sector_sk() {
+    init();
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let normal_chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR + 1);
+
+    // Read Data without SK: the deleted sector is read, with Control Mark set.
+    let rsr = image
+        .read_sector_sk(chs, None, RwSectorScope::DataOnly, false, false, false)
+        .unwrap();
+    assert!(rsr.deleted_mark);
+    assert!(rsr.control_mark);
+    assert_eq!(rsr.read_buf.len(), 512);
+
+    // Read Data with SK: the deleted sector is skipped.
+    let rsr = image
+        .read_sector_sk(chs, None, RwSectorScope::DataOnly, false, true, false)
+        .unwrap();
+    assert!(rsr.control_mark);
+    assert!(rsr.read_buf.is_empty());
+
+    // Read Deleted Data: the deleted sector is read without Control Mark.
+    let rsr = image
+        .read_sector_sk(chs, None, RwSectorScope::DataOnly, true, true, false)
+        .unwrap();
+    assert!(!rsr.control_mark);
+    assert_eq!(rsr.read_buf.len(), 512);
+
+    // Read Deleted Data with SK on a normal sector: the sector is skipped.
+    let rsr = image
+        .read_sector_sk(normal_chs, None, RwSectorScope::DataOnly, true, true, false)
+        .unwrap();
+    assert!(!rsr.deleted_mark);
+    assert!(rsr.control_mark);
+    assert!(rsr.read_buf.is_empty());
+}
@@ -0,0 +1,93 @@
+use fluxfox::diskimage::ParseMode;
+use fluxfox::registry::{self, CustomFormat, ReadSeek};
+use fluxfox::testutil::TestImage;
+use fluxfox::{
+    format_from_ext, formats, DiskDataResolution, DiskImage, DiskImageError, DiskImageFormat, FormatCaps, ImageParser,
+    ImageWriter, StandardFormat,
+};
+use std::io::Cursor;
+use std::sync::Mutex;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+// The registry is global to the process, so tests that change it must not run concurrently.
+static REGISTRY_LOCK: Mutex<()> = Mutex::new(());
+
+const FOX_MAGIC: &[u8; 8] = b"FOXWRAP\0";
+
+/// A wrapper format: a magic signature followed by a raw sector image.
+fn fox_detect(image: &mut dyn ReadSeek) -> bool {
+    let mut magic = [0u8; 8];
+    image.read_exact(&mut magic).is_ok() && magic == *FOX_MAGIC
+}
+
+fn fox_load(image: &mut dyn ReadSeek, mode: ParseMode) -> Result<DiskImage, DiskImageError> {
+    let mut buf = Vec::new();
+    image.read_to_end(&mut buf).map_err(|_| DiskImageError::IoError)?;
+    let raw = buf.get(FOX_MAGIC.len()..).ok_or(DiskImageError::FormatParseError)?;
+    DiskImageFormat::RawSectorImage.load_image_with_mode(Cursor::new(raw.to_vec()), mode)
+}
+
+fn fox_format() -> CustomFormat {
+    CustomFormat {
+        name: "Fox Wrapper Image".to_string(),
+        extensions: vec!["fox"],
+        magic: "\"FOXWRAP\\0\"",
+        resolution: DiskDataResolution::ByteStream,
+        capabilities: FormatCaps::empty(),
+        detect: fox_detect,
+        load: fox_load,
+    }
+}
+
+fn raw_image() -> Vec<u8> {
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::RawSectorImage
+        .write_image(&mut image, &mut out)
+        .unwrap();
+    out.into_inner()
+}
+
+#[test]
+fn test_register_format() {
+    init();
+    let _lock = REGISTRY_LOCK.lock().unwrap();
+
+    let format = registry::register_format(fox_format());
+    assert_eq!(format.to_string(), "Fox Wrapper Image");
+    assert_eq!(registry::detection_order()[0], format);
+    assert_eq!(format_from_ext("FOX"), Some(format));
+    assert!(formats().iter().any(|f| f.format == format && !f.can_write));
+
+    let mut fox = FOX_MAGIC.to_vec();
+    fox.extend(raw_image());
+    let image = DiskImage::load(&mut Cursor::new(fox)).unwrap();
+    assert_eq!(image.source_format(), Some(format));
+    assert_eq!(image.image_format().geometry.c(), 40);
+
+    // Images of other formats are still detected as before.
+    let image = DiskImage::load(&mut Cursor::new(raw_image())).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::RawSectorImage));
+}
+
+#[test]
+fn test_detection_order() {
+    init();
+    let _lock = REGISTRY_LOCK.lock().unwrap();
+
+    assert!(matches!(
+        registry::set_detection_order(&[DiskImageFormat::Custom(u16::MAX)]),
+        Err(DiskImageError::UnknownFormat)
+    ));
+
+    registry::set_detection_order(&[DiskImageFormat::ImageDisk, DiskImageFormat::PceSectorImage]).unwrap();
+    assert_eq!(registry::detection_order().len(), 2);
+    let result = DiskImage::load(&mut Cursor::new(raw_image()));
+    registry::reset_detection_order();
+    assert!(matches!(result, Err(DiskImageError::UnknownFormat)));
+
+    assert!(DiskImage::load(&mut Cursor::new(raw_image())).is_ok());
+}
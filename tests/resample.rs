@@ -0,0 +1,139 @@
+use fluxfox::diskimage::{DiskImageFlags, RwSectorScope};
+use fluxfox::progress::{CancellationToken, Progress};
+use fluxfox::structure_parsers::system34::System34Standard;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskCh, DiskChs, DiskChsn, DiskDataRate, DiskImage, DiskImageError, DiskRpm, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_resample() {
+    init();
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let normal_chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR + 1);
+    image
+        .write_sector(normal_chs, None, &[0xAA; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+
+    // A 250Kbps track recorded at 360RPM is shorter than one recorded at 300RPM.
+    image.resample(DiskDataRate::Rate250Kbps, DiskRpm::Rpm360).unwrap();
+    let track_map = image.get_sector_map();
+    assert_eq!(track_map[0][TEST_QUIRK_CYLINDER as usize].bitcells, 83_333);
+    assert_eq!(track_map[0][TEST_QUIRK_CYLINDER as usize].sectors.len(), 9);
+
+    // Sector data and deleted marks survive resampling.
+    let rsr = image
+        .read_sector(normal_chs, None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, vec![0xAA; 512]);
+
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(rsr.deleted_mark);
+    assert!(!rsr.data_crc_error);
+
+    for s in 1..=9 {
+        assert!(image
+            .read_sector(DiskChs::new(0, 1, s), None, RwSectorScope::DataOnly, false)
+            .is_ok());
+    }
+
+    // And back again.
+    image.resample(DiskDataRate::Rate250Kbps, DiskRpm::Rpm300).unwrap();
+    assert_eq!(
+        image.get_sector_map()[0][TEST_QUIRK_CYLINDER as usize].bitcells,
+        100_000
+    );
+    let rsr = image
+        .read_sector(normal_chs, None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf, vec![0xAA; 512]);
+}
+
+#[test]
+fn test_resample_too_short() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    // Nine 512-byte sectors will not fit on a 125Kbps track. The track is left unchanged.
+    let track = image.get_track_mut(0).unwrap();
+    assert!(matches!(
+        track.resample(DiskDataRate::Rate125Kbps, DiskRpm::Rpm300),
+        Err(DiskImageError::ParameterError)
+    ));
+    assert_eq!(track.bitcell_ct(), 100_000);
+}
+
+#[test]
+fn test_resample_keeps_iam() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    // Reformat one track in the IBM layout, which begins with an index address mark.
+    let ch = DiskCh::new(1, 0);
+    let format_buffer = (1..=9).map(|s| DiskChsn::new(1, 0, s, 2)).collect();
+    image
+        .format_track(ch, System34Standard::Ibm, format_buffer, 0xF6, 0x50)
+        .unwrap();
+    assert!(image.index_mark(ch).unwrap().bit_index.is_some());
+    assert!(image.index_mark(DiskCh::new(0, 0)).unwrap().bit_index.is_none());
+
+    image.resample(DiskDataRate::Rate300Kbps, DiskRpm::Rpm360).unwrap();
+    assert!(image.index_mark(ch).unwrap().bit_index.is_some());
+    assert!(image.index_mark(DiskCh::new(0, 0)).unwrap().bit_index.is_none());
+
+    let rsr = image
+        .read_sector(DiskChs::new(1, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf, vec![0xF6; 512]);
+}
+
+#[test]
+fn test_resample_amiga() {
+    init();
+
+    let mut image = DiskImage::load(&mut Cursor::new(vec![0u8; 80 * 2 * 11 * 512])).unwrap();
+    assert_eq!(image.get_sector_map()[0][0].sectors.len(), 11);
+
+    // Amiga tracks can't be reformatted as System34 tracks, so the image is left unchanged.
+    assert!(matches!(
+        image.resample(DiskDataRate::Rate250Kbps, DiskRpm::Rpm300),
+        Err(DiskImageError::UnsupportedFormat)
+    ));
+    assert_eq!(image.get_sector_map()[0][0].sectors.len(), 11);
+    assert!(!image.has_flag(DiskImageFlags::DIRTY));
+}
+
+#[test]
+fn test_resample_cancel() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let bitcells = image.get_track_ch(DiskCh::new(0, 0)).unwrap().bitcell_ct();
+    let token = CancellationToken::new();
+    let token_cb = token.clone();
+    image.set_progress(Progress::new().with_token(token).with_callback(move |status| {
+        if status.completed == 10 {
+            token_cb.cancel();
+        }
+    }));
+
+    // Tracks are only replaced once every track is resampled, so a cancelled resample changes
+    // nothing.
+    assert!(matches!(
+        image.resample(DiskDataRate::Rate300Kbps, DiskRpm::Rpm360),
+        Err(DiskImageError::Cancelled)
+    ));
+    assert_eq!(image.get_track_ch(DiskCh::new(0, 0)).unwrap().bitcell_ct(), bitcells);
+    assert!(image
+        .track_iter()
+        .all(|track| track.data_rate() == DiskDataRate::Rate250Kbps));
+    assert!(!image.has_flag(DiskImageFlags::DIRTY));
+}
@@ -0,0 +1,149 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{track_stream, TestImage};
+use fluxfox::{DiskCh, DiskChs, DiskImage, DiskImageFormat, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Capture clock ticks per bitcell of a 250Kbps MFM track at the SCP base resolution of 25ns.
+const TICKS_PER_CELL: f64 = 80.0;
+
+/// Convert the bitstream of the track at `ch` into flux intervals, applying a slow drift in
+/// rotation speed and pseudo-random jitter to each transition.
+fn track_flux(image: &DiskImage, ch: DiskCh, seed: u32) -> (Vec<u16>, u32) {
+    let stream = track_stream(image, ch).unwrap();
+    let bytes = stream.data();
+    let bit_ct = stream.len();
+
+    let mut rng = seed;
+    let mut intervals = Vec::new();
+    let mut last = 0.0;
+    let mut time = 0.0;
+    for i in 0..bit_ct {
+        let drift = 1.0 + 0.03 * (i as f64 / bit_ct as f64 * std::f64::consts::TAU).sin();
+        time += TICKS_PER_CELL * drift;
+        if (bytes[i >> 3] >> (7 - (i & 0x07))) & 0x01 != 0 {
+            rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
+            let jitter = ((rng >> 16) % 17) as f64 - 8.0;
+            let t = time + jitter;
+            intervals.push((t - last).round() as u16);
+            last = t;
+        }
+    }
+    (intervals, time as u32)
+}
+
+/// Build an SCP image of all tracks of `image`, with a single revolution per track.
+fn build_scp(image: &DiskImage) -> Vec<u8> {
+    let geometry = image.image_format().geometry;
+    let track_ct = geometry.c() as usize * 2;
+
+    let mut scp = vec![0u8; 0x10 + 168 * 4];
+    scp[0..3].copy_from_slice(b"SCP");
+    scp[3] = 0x22;
+    scp[5] = 1;
+    scp[7] = (track_ct - 1) as u8;
+    scp[8] = 0x01;
+
+    for track_n in 0..track_ct {
+        let ch = DiskCh::new((track_n / 2) as u16, (track_n % 2) as u8);
+        let (intervals, index_time) = track_flux(image, ch, track_n as u32);
+
+        let offset = scp.len() as u32;
+        scp[0x10 + track_n * 4..0x14 + track_n * 4].copy_from_slice(&offset.to_le_bytes());
+        scp.extend_from_slice(b"TRK");
+        scp.push(track_n as u8);
+        scp.extend_from_slice(&index_time.to_le_bytes());
+        scp.extend_from_slice(&(intervals.len() as u32).to_le_bytes());
+        scp.extend_from_slice(&16u32.to_le_bytes());
+        for interval in intervals {
+            scp.extend_from_slice(&interval.to_be_bytes());
+        }
+    }
+
+    let checksum = scp[0x10..].iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+    scp[0x0C..0x10].copy_from_slice(&checksum.to_le_bytes());
+    scp
+}
+
+#[test]
+fn test_scp_load() {
+    init();
+
+    let mut original = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let scp = build_scp(&original);
+
+    let mut image = DiskImage::load(&mut Cursor::new(scp)).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::SuperCardPro));
+    assert_eq!(image.image_format().geometry, original.image_format().geometry);
+    assert!(image.load_warnings().is_empty());
+
+    let track = image.get_track_ch(DiskCh::new(0, 0)).unwrap();
+    assert!(track.flux().is_some_and(|flux| flux.transition_ct() > 0));
+
+    for c in 0..40 {
+        for h in 0..2 {
+            for s in 1..=9 {
+                let chs = DiskChs::new(c, h, s);
+                let expected = original.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+                let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+                assert!(
+                    !rsr.address_crc_error && !rsr.data_crc_error,
+                    "sector {} has a bad CRC",
+                    chs
+                );
+                assert_eq!(rsr.read_buf, expected.read_buf, "sector {} does not match", chs);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_scp_bad_checksum() {
+    init();
+
+    let original = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut scp = build_scp(&original);
+    scp[0x0C] ^= 0xFF;
+
+    let image = DiskImage::load(&mut Cursor::new(scp)).unwrap();
+    let warnings = image.load_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].message.contains("checksum"));
+}
+
+#[test]
+fn test_scp_missing_tracks() {
+    init();
+
+    let mut original = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let mut scp = build_scp(&original);
+    // Begin the capture at cylinder 1, and drop cylinder 5 of head 0.
+    scp[6] = 2;
+    scp[0x10 + 10 * 4..0x14 + 10 * 4].fill(0);
+    scp[0x0C..0x10].fill(0);
+
+    let mut image = DiskImage::load(&mut Cursor::new(scp)).unwrap();
+    assert_eq!(image.image_format().geometry, original.image_format().geometry);
+    let warned = image.load_warnings().iter().map(|w| w.ch).collect::<Vec<_>>();
+    assert_eq!(
+        warned,
+        vec![
+            Some(DiskCh::new(0, 0)),
+            Some(DiskCh::new(0, 1)),
+            Some(DiskCh::new(5, 0))
+        ]
+    );
+
+    let sector_map = image.get_sector_map();
+    assert!(sector_map[0][0].sectors.is_empty());
+    assert!(sector_map[0][5].sectors.is_empty());
+    assert_eq!(sector_map[0][6].sectors.len(), 9);
+
+    let chs = DiskChs::new(6, 0, 1);
+    let expected = original.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert_eq!(rsr.read_buf, expected.read_buf);
+}
@@ -0,0 +1,79 @@
+use fluxfox::diskimage::SECTOR_MAP_LEGEND;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER};
+use fluxfox::{DiskDataEncoding, DiskDataRate, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_sector_map_track_info() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy1200).generate().unwrap();
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map.len(), 2);
+
+    let track = &sector_map[1][5];
+    assert_eq!((track.ch.c(), track.ch.h()), (5, 1));
+    assert!(matches!(track.encoding, DiskDataEncoding::Mfm));
+    assert!(matches!(track.data_rate, DiskDataRate::Rate500Kbps));
+    assert_eq!(track.len_bytes, track.bitcells / 16);
+    assert_eq!(track.sectors.len(), 15);
+
+    let mut out = Vec::new();
+    image.dump_sector_map(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("\tTrack 0 MFM 500Kbps"));
+}
+
+#[test]
+fn test_sector_map_compact() {
+    init();
+
+    let image = TestImage::BadDataCrc.generate().unwrap();
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0][0].to_string(), "1 2 3 4 5 6 7 8 9");
+    assert_eq!(
+        sector_map[0][TEST_QUIRK_CYLINDER as usize].to_string(),
+        "1 2 3 4! 5 6 7 8 9"
+    );
+
+    let image = TestImage::DeletedData.generate().unwrap();
+    let sector_map = image.get_sector_map();
+    assert_eq!(
+        sector_map[0][TEST_QUIRK_CYLINDER as usize].to_string(),
+        "1 2 3 4d 5 6 7 8 9"
+    );
+}
+
+#[test]
+fn test_render_sector_map_text() {
+    init();
+
+    let image = TestImage::BadDataCrc.generate().unwrap();
+    let text = image.render_sector_map_text(80);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "     123456789");
+    assert_eq!(lines[1], " 0:0 +++++++++");
+    assert_eq!(lines[1 + TEST_QUIRK_CYLINDER as usize], " 1:0 +++X+++++");
+    assert_eq!(lines.last(), Some(&SECTOR_MAP_LEGEND));
+
+    for (test_image, row) in [
+        (TestImage::DeletedData, " 1:0 +++D+++++"),
+        (TestImage::WeakBits, " 1:0 +++W+++++"),
+        (TestImage::DuplicateIds, " 1:0 +++++-+++"),
+    ] {
+        let text = test_image.generate().unwrap().render_sector_map_text(80);
+        assert!(text.lines().any(|line| line == row), "{}", text);
+    }
+
+    // A narrow grid is split into blocks of columns.
+    let text = image.render_sector_map_text(10);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "     12345");
+    assert!(lines.contains(&"     6789"));
+    assert!(lines.contains(&" 1:0 +++X+"));
+}
@@ -0,0 +1,89 @@
+use fluxfox::bitstream::mfm::{MfmEncodingType, MFM_BYTE_LEN};
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskCh, DiskChs, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Rewrite the size code in the header of sector `s`, with a valid header CRC.
+fn set_header_n(image: &mut DiskImage, ch: DiskCh, s: u8, n: u8) {
+    let mut bit_index = 0;
+    let header_idx = loop {
+        let (chsn, idx) = image.get_next_id_at(ch, bit_index).unwrap();
+        if chsn.s() == s {
+            break idx;
+        }
+        bit_index = idx + 1;
+    };
+    let id = [ch.c() as u8, ch.h(), s, n];
+    let crc = image.crc_params().crc(&[0xA1, 0xA1, 0xA1, 0xFE], &id);
+    let mut field = vec![n];
+    field.extend_from_slice(&crc.to_be_bytes());
+    image
+        .write_encoded_data(ch, &field, header_idx + 7 * MFM_BYTE_LEN, MfmEncodingType::Data)
+        .unwrap();
+}
+
+#[test]
+fn test_read_sector_oversized_header() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    image
+        .write_sector(chs, Some(2), &[0x5A; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+
+    // A header claiming 8192 bytes, for a 512 byte data field followed by the next sector.
+    set_header_n(&mut image, ch, TEST_QUIRK_SECTOR, 6);
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(!rsr.address_crc_error);
+    assert!(!rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, vec![0x5A; 512]);
+
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataBlock, false).unwrap();
+    assert_eq!(rsr.read_buf.len(), 4 + 512 + 2);
+
+    // A header with a size that fits is trusted, even if the data CRC doesn't match it.
+    set_header_n(&mut image, ch, TEST_QUIRK_SECTOR, 1);
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(rsr.read_buf, vec![0x5A; 256]);
+}
+
+#[test]
+fn test_read_sector_overread() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let chs = DiskChs::new(0, 0, 1);
+    image
+        .write_sector(chs, Some(2), &[0x5A; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+
+    // Read with an N of 3: the 512 data bytes, their CRC, then the gap.
+    let rsr = image.read_sector_overread(chs, 3).unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(rsr.data_len, 512);
+    assert_eq!(rsr.overread_len, 512);
+    assert_eq!(rsr.read_buf.len(), 1024);
+    assert_eq!(&rsr.read_buf[..512], &[0x5A; 512]);
+    let crc = image.crc_params().crc(&[0xA1, 0xA1, 0xA1, 0xFB], &[0x5A; 512]);
+    assert_eq!(&rsr.read_buf[512..514], &crc.to_be_bytes());
+    assert!(rsr.read_buf[514..534].iter().all(|b| *b == 0x4E));
+    assert!(rsr.weak_mask.is_none());
+
+    // A smaller N returns the start of the data, with nothing read beyond it.
+    let rsr = image.read_sector_overread(chs, 1).unwrap();
+    assert_eq!(rsr.read_buf, vec![0x5A; 256]);
+    assert_eq!(rsr.overread_len, 0);
+
+    // The read stops at the end of the track.
+    let last = DiskChs::new(0, 0, 9);
+    let rsr = image.read_sector_overread(last, 6).unwrap();
+    assert!(rsr.read_buf.len() < 8192);
+    assert_eq!(rsr.overread_len, rsr.read_buf.len() - 512);
+}
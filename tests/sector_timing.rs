@@ -0,0 +1,41 @@
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskChs, StandardFormat};
+use std::time::Duration;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_sector_read_time() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+    let track_len = image.get_sector_map()[0][0].bitcells;
+    let idam = image.read_address(ch, 0).unwrap().bit_index;
+
+    // From the index, the wait is the distance to the first IDAM.
+    let timing = image.sector_read_time(DiskChs::new(0, 0, 1), 0).unwrap();
+    assert_eq!(timing.wait_bitcells, idam);
+
+    // The transfer covers the ID field, GAP2, and the 512 byte data field and CRC.
+    assert!(timing.transfer_bitcells > (4 + 512 + 2) * 16);
+    assert!(timing.transfer_bitcells < (4 + 4 + 2 + 22 + 12 + 4 + 512 + 2 + 16) * 16);
+    assert_eq!(timing.end_position, idam + timing.transfer_bitcells);
+
+    // At 250Kbps each bitcell is 2µs.
+    assert_eq!(timing.wait_time(), Duration::from_micros(2 * idam as u64));
+    assert_eq!(timing.total_time(), timing.wait_time() + timing.transfer_time());
+
+    // Starting just past the IDAM, the head must wait for almost a full revolution.
+    let timing = image.sector_read_time(DiskChs::new(0, 0, 1), idam + 1).unwrap();
+    assert_eq!(timing.wait_bitcells, track_len - 1);
+    assert!(timing.wait_time() < Duration::from_millis(200));
+
+    // A later sector arrives later.
+    let timing2 = image.sector_read_time(DiskChs::new(0, 0, 2), 0).unwrap();
+    assert!(timing2.wait_bitcells > idam + timing.transfer_bitcells);
+
+    assert!(image.sector_read_time(DiskChs::new(0, 0, 10), 0).is_err());
+}
@@ -0,0 +1,128 @@
+use fluxfox::diskimage::{DiskImageFlags, RwSectorScope};
+use fluxfox::shared::SharedDiskImage;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskChs, StandardFormat};
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn shared_image() -> SharedDiskImage {
+    SharedDiskImage::new(TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap())
+}
+
+#[test]
+fn test_shared_read_write() {
+    init();
+
+    let shared = shared_image();
+    let chs = DiskChs::new(5, 0, 3);
+    shared
+        .write_sector(chs, None, &[0x5A; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+    let rsr = shared.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert_eq!(&rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len], &[0x5A; 512]);
+
+    let entry = shared.track_map_entry(DiskCh::new(5, 0)).unwrap();
+    assert_eq!(entry.sectors.len(), 9);
+    assert!(shared.track_map_entry(DiskCh::new(40, 0)).is_none());
+    assert!(shared
+        .read_sector(DiskChs::new(40, 0, 1), None, RwSectorScope::DataOnly, false)
+        .is_err());
+
+    let mut image = shared.into_inner();
+    assert!(image.has_flag(DiskImageFlags::DIRTY));
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert_eq!(&rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len], &[0x5A; 512]);
+}
+
+#[test]
+fn test_shared_track_lock_independent() {
+    init();
+
+    let shared = shared_image();
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let shared = &shared;
+    std::thread::scope(|scope| {
+        // Hold track 5 for writing until the other track has been read.
+        scope.spawn(move || {
+            shared
+                .with_track_mut(DiskCh::new(5, 0), |_| {
+                    locked_tx.send(()).unwrap();
+                    done_rx.recv_timeout(Duration::from_secs(10)).is_ok()
+                })
+                .unwrap()
+        });
+        locked_rx.recv().unwrap();
+
+        let (read_tx, read_rx) = mpsc::channel();
+        scope.spawn(move || {
+            let entry = shared.track_map_entry(DiskCh::new(30, 0)).unwrap();
+            let rsr = shared
+                .read_sector(DiskChs::new(30, 0, 1), None, RwSectorScope::DataOnly, false)
+                .unwrap();
+            read_tx.send((entry.sectors.len(), rsr.data_crc_error)).unwrap();
+        });
+
+        // Track 30 must be readable while track 5 is held.
+        let result = read_rx.recv_timeout(Duration::from_secs(5));
+        done_tx.send(()).unwrap();
+        assert_eq!(result, Ok((9, false)));
+    });
+}
+
+#[test]
+fn test_shared_stress() {
+    init();
+
+    const WRITERS: u16 = 4;
+    const ROUNDS: u8 = 50;
+
+    let shared = shared_image();
+    std::thread::scope(|scope| {
+        for c in 0..WRITERS {
+            let shared = &shared;
+            scope.spawn(move || {
+                for round in 0..ROUNDS {
+                    for s in 1..=9 {
+                        let chs = DiskChs::new(c, 0, s);
+                        shared
+                            .write_sector(chs, None, &[round ^ s; 512], RwSectorScope::DataOnly, false, false)
+                            .unwrap();
+                    }
+                }
+            });
+        }
+        for c in WRITERS..WRITERS * 2 {
+            let shared = &shared;
+            scope.spawn(move || {
+                for _ in 0..ROUNDS {
+                    let entry = shared.track_map_entry(DiskCh::new(c, 1)).unwrap();
+                    assert!(entry.sectors.iter().all(|s| s.data_crc_valid));
+                    let rsr = shared
+                        .read_sector(DiskChs::new(c, 1, 1), None, RwSectorScope::DataOnly, false)
+                        .unwrap();
+                    assert!(!rsr.data_crc_error);
+                }
+            });
+        }
+    });
+
+    let mut image = shared.into_inner();
+    for c in 0..WRITERS {
+        for s in 1..=9 {
+            let rsr = image
+                .read_sector(DiskChs::new(c, 0, s), None, RwSectorScope::DataOnly, false)
+                .unwrap();
+            assert!(!rsr.data_crc_error);
+            assert_eq!(
+                &rsr.read_buf[rsr.data_idx..rsr.data_idx + rsr.data_len],
+                &[(ROUNDS - 1) ^ s; 512]
+            );
+        }
+    }
+}
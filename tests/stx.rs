@@ -0,0 +1,128 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::{DiskChs, DiskImage, DiskImageFormat, ImageParser, ParserWriteCompatibility};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const FUZZY_BYTES: usize = 16;
+
+fn track_header(record_size: usize, fuzzy_count: usize, sector_count: u16, flags: u16, track_number: u8) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend((record_size as u32).to_le_bytes());
+    header.extend((fuzzy_count as u32).to_le_bytes());
+    header.extend(sector_count.to_le_bytes());
+    header.extend(flags.to_le_bytes());
+    header.extend(6250u16.to_le_bytes());
+    header.push(track_number);
+    header.push(0);
+    header
+}
+
+fn sector_descriptor(data_offset: u32, bit_position: u16, read_time: u16, id: u8, fdc_flags: u8) -> Vec<u8> {
+    let mut desc = Vec::new();
+    desc.extend(data_offset.to_le_bytes());
+    desc.extend(bit_position.to_le_bytes());
+    desc.extend(read_time.to_le_bytes());
+    desc.extend([0, 0, id, 2]);
+    desc.extend(0u16.to_be_bytes());
+    desc.push(fdc_flags);
+    desc.push(0);
+    desc
+}
+
+/// A revision 2 STX image with two tracks. Side 0 has a regular sector, a sector with fuzzy bytes
+/// and variable timing, and an ID with no data. Side 1 is a plain track of 9 sectors.
+fn stx_image() -> Vec<u8> {
+    let mut stx = Vec::new();
+    stx.extend(b"RSY\0");
+    stx.extend(3u16.to_le_bytes());
+    stx.extend(0x01u16.to_le_bytes());
+    stx.extend(0u16.to_le_bytes());
+    stx.push(2);
+    stx.push(2);
+    stx.extend(0u32.to_le_bytes());
+
+    let mut body = Vec::new();
+    body.extend(sector_descriptor(0, 1000, 0, 1, 0));
+    body.extend(sector_descriptor(512, 6000, 17000, 2, 0x80 | 0x01 | 0x08));
+    body.extend(sector_descriptor(0, 11000, 0, 3, 0x10));
+    // Clear bits in the fuzzy mask mark the fuzzy bits.
+    body.extend([0x00; FUZZY_BYTES]);
+    body.extend([0xFF; 512 - FUZZY_BYTES]);
+    body.extend([0x11; 512]);
+    body.extend([0x22; 512]);
+    body.extend(5u16.to_le_bytes());
+    body.extend((4u16 + 64).to_le_bytes());
+    for i in 0..32u16 {
+        body.extend((127 + i).to_be_bytes());
+    }
+    stx.extend(track_header(16 + body.len(), 512, 3, 0x01, 0x00));
+    stx.extend(body);
+
+    let mut body = Vec::new();
+    for s in 1..=9u8 {
+        body.extend([s; 512]);
+    }
+    stx.extend(track_header(16 + body.len(), 0, 9, 0x00, 0x80));
+    stx.extend(body);
+    stx
+}
+
+#[test]
+fn test_stx_load() {
+    init();
+
+    let mut in_buffer = Cursor::new(stx_image());
+    assert!(DiskImageFormat::PastiImage.detect(&mut in_buffer));
+    let mut image = DiskImage::load(&mut in_buffer).unwrap();
+    assert_eq!(image.image_format().geometry.h(), 2);
+    assert!(matches!(
+        DiskImageFormat::PastiImage.can_write(&image),
+        ParserWriteCompatibility::UnsupportedFormat
+    ));
+
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0][0].sectors.len(), 3);
+    assert_eq!(sector_map[1][0].sectors.len(), 9);
+    let fuzzy = &sector_map[0][0].sectors[1];
+    assert!(!fuzzy.data_crc_valid);
+    assert_eq!(fuzzy.position, Some(6000));
+    assert_eq!(fuzzy.read_time, Some(17000 / 4));
+    assert!(sector_map[0][0].sectors[2].no_dam);
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 1), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![0x11; 512]);
+    assert!(result.weak_mask.is_none());
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 2), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![0x22; 512]);
+    let weak_mask = result.weak_mask.unwrap();
+    assert!(weak_mask[..FUZZY_BYTES].iter().all(|&b| b == 0xFF));
+    assert!(weak_mask[FUZZY_BYTES..].iter().all(|&b| b == 0));
+
+    let result = image
+        .read_sector(DiskChs::new(0, 1, 9), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(result.read_buf, vec![9; 512]);
+}
+
+#[test]
+fn test_stx_sector_timing() {
+    init();
+
+    let image = DiskImage::load(&mut Cursor::new(stx_image())).unwrap();
+    assert_eq!(image.sector_timing(DiskChs::new(0, 0, 1)).unwrap(), None);
+
+    let timing = image.sector_timing(DiskChs::new(0, 0, 2)).unwrap().unwrap();
+    assert_eq!(timing.len(), 512 / 16);
+    assert_eq!(timing[0], 127);
+    assert_eq!(timing[31], 158);
+
+    assert!(image.sector_timing(DiskChs::new(0, 0, 7)).is_err());
+}
@@ -0,0 +1,105 @@
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::{
+    DiskCh, DiskDataEncoding, DiskDataRate, DiskDataResolution, DiskImageFormat, ImageParser, ParserWriteCompatibility,
+    QuarterTrack, StandardFormat,
+};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_remap_double_stepped() {
+    init();
+
+    // An 80 track image, as captured from a 40 track disk in an 80 track drive.
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy720)
+        .with_formatted()
+        .build()
+        .unwrap();
+    assert!(!image.has_sub_tracks());
+
+    image.remap_double_stepped().unwrap();
+    assert!(image.has_sub_tracks());
+    assert_eq!(image.geometry(), DiskCh::new(40, 2));
+
+    let positions = image.sub_track_positions(1);
+    assert_eq!(positions.len(), 40);
+    assert_eq!(positions[0], QuarterTrack::new(0, 2));
+    assert_eq!(positions[39].to_string(), "39.5");
+
+    // Captured track 2 is now cylinder 1, and captured track 3 is half-track 1.5.
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0].len(), 40);
+    assert_eq!(sector_map[0][1].ch, DiskCh::new(1, 0));
+    let sub_track_map = image.get_sub_track_map();
+    assert_eq!(sub_track_map[0][1].0, QuarterTrack::from_half(3));
+    assert_eq!(sub_track_map[0][1].1.ch, DiskCh::new(1, 0));
+    assert!(image.get_track_at(0, QuarterTrack::from_half(3)).is_some());
+
+    let mut out = Vec::new();
+    image.dump_sector_map(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("\tTrack 1 MFM"));
+    assert!(out.contains("\tTrack 1.5 MFM"));
+
+    // Formats without sub-track support would drop the half-tracks.
+    assert!(matches!(
+        DiskImageFormat::PceBitstreamImage.can_write(&image),
+        ParserWriteCompatibility::DataLoss
+    ));
+
+    // An image can only be remapped once.
+    assert!(image.remap_double_stepped().is_err());
+}
+
+#[test]
+fn test_add_sub_track() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+
+    let data = vec![0xAA; 100_000 / 8];
+    let position = QuarterTrack::new(17, 1);
+    image
+        .add_sub_track_bitstream(
+            DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            0,
+            position,
+            500_000,
+            None,
+            &data,
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(image.sub_track_positions(0), vec![position]);
+    assert!(image.sub_track_positions(1).is_empty());
+    assert_eq!(image.get_track_at(0, position).unwrap().ch(), DiskCh::new(17, 0));
+    // The whole track geometry is unchanged.
+    assert_eq!(image.geometry(), DiskCh::new(40, 2));
+
+    // Whole cylinders and occupied positions are rejected.
+    for position in [QuarterTrack::from(18), position] {
+        assert!(image
+            .add_sub_track_bitstream(
+                DiskDataEncoding::Mfm,
+                DiskDataRate::Rate250Kbps,
+                0,
+                position,
+                500_000,
+                None,
+                &data,
+                None,
+            )
+            .is_err());
+    }
+}
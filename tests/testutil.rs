@@ -0,0 +1,108 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::*;
+use fluxfox::{DiskCh, DiskChs, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn quirk_chs() -> DiskChs {
+    DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR)
+}
+
+#[test]
+fn test_standard_images() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy720).generate().unwrap();
+    let rsr = image
+        .read_sector(DiskChs::new(79, 1, 9), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert_eq!(rsr.read_buf.len(), 512);
+}
+
+#[test]
+fn test_mixed_sector_sizes() {
+    init();
+
+    let mut image = TestImage::MixedSectorSizes.generate().unwrap();
+    for (i, n) in TEST_MIXED_SECTOR_SIZES.iter().enumerate() {
+        let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, i as u8 + 1);
+        let rsr = image
+            .read_sector(chs, Some(*n), RwSectorScope::DataOnly, false)
+            .unwrap();
+        assert_eq!(rsr.read_buf.len(), 128 << n);
+        assert!(!rsr.data_crc_error);
+    }
+}
+
+#[test]
+fn test_bad_crc_images() {
+    init();
+
+    let mut image = TestImage::BadAddressCrc.generate().unwrap();
+    let sector_map = image.get_sector_map();
+    let sectors = &sector_map[0][TEST_QUIRK_CYLINDER as usize].sectors;
+    assert!(sectors
+        .iter()
+        .any(|s| s.chsn.s() == TEST_QUIRK_SECTOR && !s.address_crc_valid));
+    let rsr = image
+        .read_sector(quirk_chs(), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.address_crc_error);
+
+    let mut image = TestImage::BadDataCrc.generate().unwrap();
+    let rsr = image
+        .read_sector(quirk_chs(), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.address_crc_error);
+    assert!(rsr.data_crc_error);
+}
+
+#[test]
+fn test_deleted_data() {
+    init();
+
+    let mut image = TestImage::DeletedData.generate().unwrap();
+    let rsr = image
+        .read_sector(quirk_chs(), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(rsr.deleted_mark);
+    assert!(!rsr.data_crc_error);
+}
+
+#[test]
+fn test_weak_bits() {
+    init();
+
+    let mut image = TestImage::WeakBits.generate().unwrap();
+    assert!(image.has_weak_bits());
+
+    // Weak bits should read back differently at least some of the time.
+    let first = image
+        .read_sector(quirk_chs(), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(first.data_crc_error);
+    let differs = (0..8).any(|_| {
+        let rsr = image
+            .read_sector(quirk_chs(), None, RwSectorScope::DataOnly, false)
+            .unwrap();
+        rsr.read_buf[0..16] != first.read_buf[0..16]
+    });
+    assert!(differs);
+}
+
+#[test]
+fn test_duplicate_ids() {
+    init();
+
+    let image = TestImage::DuplicateIds.generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let sector_map = image.get_sector_map();
+    let ids: Vec<u8> = sector_map[0][ch.c() as usize]
+        .sectors
+        .iter()
+        .map(|s| s.chsn.s())
+        .collect();
+    assert_eq!(ids, TEST_DUPLICATE_IDS);
+}
@@ -0,0 +1,85 @@
+use fluxfox::structure_parsers::system34::System34Marker;
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::timeline::{TimelineElement, TIMELINE_LEGEND};
+use fluxfox::{DiskCh, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_track_timeline() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let timeline = image.track_timeline(DiskCh::new(0, 0)).unwrap();
+    assert_eq!(timeline.bitcell_ct, 100_000);
+
+    // The spans tile the track from the index, in order.
+    assert_eq!(timeline.spans.first().unwrap().start, 0);
+    assert_eq!(timeline.spans.last().unwrap().end, 100_000);
+    assert!(timeline.spans.windows(2).all(|w| w[0].end == w[1].start));
+
+    // GAP1, then each sector: sync, IDAM, ID, CRC, GAP2, sync, DAM, data, CRC, GAP3.
+    let elements: Vec<_> = timeline.spans.iter().map(|s| s.element).collect();
+    assert_eq!(elements[0], TimelineElement::Gap);
+    assert_eq!(timeline.spans[0].byte_len(), 32);
+    assert_eq!(elements[1], TimelineElement::Sync);
+    assert_eq!(elements[2], TimelineElement::Marker(System34Marker::Idam));
+    assert!(matches!(elements[3], TimelineElement::SectorId(chsn) if chsn.s() == 1 && chsn.n() == 2));
+    assert_eq!(elements[4], TimelineElement::Crc(true));
+    assert_eq!((elements[5], timeline.spans[5].byte_len()), (TimelineElement::Gap, 22));
+    assert_eq!(elements[7], TimelineElement::Marker(System34Marker::Dam));
+    assert_eq!(
+        (elements[8].label(), timeline.spans[8].byte_len()),
+        ("DATA s:1".to_string(), 512)
+    );
+    assert_eq!(elements[9], TimelineElement::Crc(true));
+    assert_eq!(
+        elements
+            .iter()
+            .filter(|e| matches!(e, TimelineElement::Data(_)))
+            .count(),
+        9
+    );
+
+    let text = timeline.render_text(80);
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines[0], "Track [c:0 h:0] (100000 bitcells)");
+    assert_eq!(lines[1].len(), 82);
+    assert!(lines[1].starts_with('|') && lines[1].contains("DDD"));
+    assert_eq!(lines[2], TIMELINE_LEGEND);
+    assert_eq!(lines.len(), 4 + timeline.spans.len());
+
+    let svg = timeline.render_svg(1000);
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert_eq!(svg.matches("<rect").count(), timeline.spans.len());
+
+    // Gaps and syncs are left out of the graph.
+    let dot = timeline.render_dot();
+    assert!(dot.starts_with("digraph track {"));
+    assert_eq!(dot.matches("->").count(), 9 * 6 - 1);
+
+    assert!(image.track_timeline(DiskCh::new(40, 0)).is_err());
+}
+
+#[test]
+fn test_track_timeline_crc() {
+    init();
+
+    let image = TestImage::BadAddressCrc.generate().unwrap();
+    let timeline = image.track_timeline(DiskCh::new(TEST_QUIRK_CYLINDER, 0)).unwrap();
+    let bad: Vec<_> = timeline
+        .spans
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.element == TimelineElement::Crc(false))
+        .collect();
+    assert_eq!(bad.len(), 1);
+    assert!(
+        matches!(timeline.spans[bad[0].0 - 1].element, TimelineElement::SectorId(chsn) if chsn.s() == TEST_QUIRK_SECTOR)
+    );
+    assert!(timeline.render_text(80).contains("CRC bad"));
+    assert!(timeline.render_dot().contains("#ff2020"));
+}
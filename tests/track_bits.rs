@@ -0,0 +1,76 @@
+use fluxfox::testutil::{track_stream, TestImage, TEST_QUIRK_CYLINDER};
+use fluxfox::{DiskCh, DiskDataRate, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_track_bit_at() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+    let track = image.get_track_ch(ch).unwrap();
+    let bits = track_stream(&image, ch).unwrap().bits().clone();
+    let len = bits.len();
+
+    assert!((0..len).all(|i| track.bit_at(i) == bits.get(i)));
+    // Positions past the end of the track wrap around to the index.
+    assert_eq!(track.bit_at(len), bits.get(0));
+    assert_eq!(track.bit_at(len * 3 + 17), bits.get(17));
+}
+
+#[test]
+fn test_track_bits_iter_wraps() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+    let track = image.get_track_ch(ch).unwrap();
+    let bits = track_stream(&image, ch).unwrap().bits().clone();
+    let len = bits.len();
+
+    let expected: Vec<bool> = bits
+        .iter()
+        .skip(len - 8)
+        .chain(bits.iter())
+        .chain(bits.iter().take(8))
+        .collect();
+    let streamed: Vec<bool> = track.bits_iter(len - 8).take(len + 16).collect();
+    assert_eq!(streamed, expected);
+}
+
+#[test]
+fn test_track_bits_iter_weak() {
+    init();
+
+    let image = TestImage::WeakBits.generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let track = image.get_track_ch(ch).unwrap();
+    let len = track.bitcell_ct();
+
+    // Two revolutions differ only where weak bits return random data.
+    let rev: Vec<bool> = track.bits_iter(0).take(len * 2).collect();
+    let (first, second) = rev.split_at(len);
+    let differing = first.iter().zip(second).filter(|(a, b)| a != b).count();
+    assert!(differing > 0);
+    assert!(differing <= 16 * 16);
+}
+
+#[test]
+fn test_track_bits_bytestream() {
+    init();
+
+    let mut image = fluxfox::DiskImage::create(StandardFormat::PcFloppy360);
+    image
+        .add_track_bytestream(
+            fluxfox::DiskDataEncoding::Mfm,
+            DiskDataRate::Rate250Kbps,
+            DiskCh::new(0, 0),
+        )
+        .unwrap();
+    let track = image.get_track_ch(DiskCh::new(0, 0)).unwrap();
+    assert_eq!(track.bit_at(0), None);
+    assert_eq!(track.bits_iter(0).next(), None);
+}
@@ -0,0 +1,116 @@
+use fluxfox::bitstream::mfm::MfmEncodingType;
+use fluxfox::diskimage::{MatchPolicy, RwSectorScope};
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{DiskCh, DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_splice_track() {
+    init();
+
+    let mut source = build_image();
+    source
+        .write_sector(
+            DiskChs::new(0, 0, 1),
+            Some(2),
+            &[0x5A; 512],
+            RwSectorScope::DataOnly,
+            false,
+            false,
+        )
+        .unwrap();
+    let mut image = build_image();
+    image
+        .write_sector(
+            DiskChs::new(0, 0, 2),
+            Some(2),
+            &[0xC3; 512],
+            RwSectorScope::DataOnly,
+            false,
+            false,
+        )
+        .unwrap();
+
+    let ch = DiskCh::new(0, 0);
+    let bitcell_ct = image.get_track_ch(ch).unwrap().bitcell_ct();
+
+    // Sector 1 from the source image, sector 2 from the image being remastered, separated by
+    // gaps, followed by a raw region of bitcells with no valid clock.
+    let builder = TrackBuilder::new()
+        .with_gap(0x4E, 80)
+        .with_sector(&source, DiskChs::new(0, 0, 1), Some(2), MatchPolicy::Chsn)
+        .unwrap()
+        .with_gap(0x4E, 50)
+        .with_sector(&image, DiskChs::new(0, 0, 2), Some(2), MatchPolicy::Chsn)
+        .unwrap()
+        .with_gap(0x4E, 50)
+        .with_bits(&[0xFF; 16], 120, Some(&[0xFF; 16]))
+        .unwrap();
+
+    // Each copied sector spans its sync field, IDAM, ID, gap 2, DAM, data and CRC.
+    let sector_bits = (12 + 4 + 4 + 2 + 22 + 12 + 4 + 512 + 2) * 16;
+    assert_eq!(builder.bitcell_len(), (80 + 50 + 50) * 16 + 2 * sector_bits + 120);
+
+    image.splice_track(ch, &builder).unwrap();
+
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(track.bitcell_ct(), bitcell_ct);
+    let sector_map = image.get_sector_map();
+    assert_eq!(sector_map[0][0].to_string(), "1 2");
+
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 1), Some(2), RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0x5A; 512]);
+    let result = image
+        .read_sector(DiskChs::new(0, 0, 2), Some(2), RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!result.data_crc_error);
+    assert_eq!(result.read_buf, vec![0xC3; 512]);
+
+    // The rest of the disk is untouched.
+    assert_eq!(image.get_sector_map()[0][1].sectors.len(), 9);
+}
+
+#[test]
+fn test_splice_track_budget() {
+    init();
+
+    let mut image = build_image();
+    let ch = DiskCh::new(1, 1);
+
+    // A hand-encoded sector with a bad ID CRC and a truncated data field, on a short track.
+    let builder = TrackBuilder::new()
+        .with_bitcell_ct(20_000)
+        .with_gap(0x4E, 80)
+        .with_gap(0x00, 12)
+        .with_encoded(&[0xA1, 0xA1, 0xA1, 0xFE], MfmEncodingType::AddressMark)
+        .with_encoded(&[0x01, 0x01, 0x07, 0x02, 0x00, 0x00], MfmEncodingType::Data)
+        .with_gap(0x4E, 22)
+        .with_gap(0x00, 12)
+        .with_encoded(&[0xA1, 0xA1, 0xA1, 0xFB], MfmEncodingType::AddressMark)
+        .with_gap(0xE5, 16);
+    image.splice_track(ch, &builder).unwrap();
+    assert_eq!(image.get_track_ch(ch).unwrap().bitcell_ct(), 20_000);
+    let result = image.read_address(ch, 0).unwrap();
+    assert_eq!(result.chsn.s(), 7);
+    assert!(result.address_crc_error);
+
+    let builder = TrackBuilder::new().with_bitcell_ct(1000).with_gap(0x4E, 100);
+    assert!(image.splice_track(ch, &builder).is_err());
+    assert!(image.splice_track(DiskCh::new(40, 0), &TrackBuilder::new()).is_err());
+}
@@ -0,0 +1,109 @@
+use fluxfox::bitstream::mfm::MfmEncodingType;
+use fluxfox::diskimage::MatchPolicy;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{DiskCh, DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_track_gaps_standard() {
+    init();
+
+    let image = build_image();
+    let gaps = image.track_gaps(DiskCh::new(5, 1)).unwrap();
+
+    assert_eq!(gaps.ch, DiskCh::new(5, 1));
+    assert_eq!(gaps.gap1, 32);
+    assert_eq!(gaps.sectors.len(), 9);
+    for (i, sector) in gaps.sectors.iter().enumerate() {
+        assert_eq!(sector.chsn.s(), i as u8 + 1);
+        assert_eq!(sector.id_sync, 12);
+        assert_eq!(sector.gap2, Some(22));
+        assert_eq!(sector.data_sync, Some(12));
+        assert_eq!(sector.gap3, if i < 8 { Some(0x50) } else { None });
+    }
+    assert_eq!(gaps.common_gap3(), Some(0x50));
+    // Everything after the last data CRC belongs to GAP4B.
+    assert_eq!(gaps.gap4b, 6250 - 32 - 9 * 574 - 8 * 0x50);
+}
+
+#[test]
+fn test_track_gaps_custom() {
+    init();
+
+    let mut image = build_image();
+    let ch = DiskCh::new(0, 0);
+    let builder = TrackBuilder::new()
+        .with_gap(0x4E, 60)
+        .with_sector(&image, DiskChs::new(0, 0, 1), None, MatchPolicy::Chsn)
+        .unwrap()
+        .with_gap(0x4E, 40)
+        .with_sector(&image, DiskChs::new(0, 0, 2), None, MatchPolicy::Chsn)
+        .unwrap()
+        .with_gap(0x4E, 17)
+        .with_gap(0x00, 6)
+        .with_encoded(&[0xA1, 0xA1, 0xA1, 0xFE], MfmEncodingType::AddressMark)
+        .with_encoded(&[0x00, 0x00, 0x03, 0x02, 0x00, 0x00], MfmEncodingType::Data);
+    image.splice_track(ch, &builder).unwrap();
+
+    let gaps = image.track_gaps(ch).unwrap();
+    assert_eq!(gaps.gap1, 60);
+    assert_eq!(gaps.sectors.len(), 3);
+    assert_eq!(gaps.sectors[0].gap3, Some(40));
+    assert_eq!(gaps.sectors[1].gap3, Some(17));
+    assert_eq!(gaps.common_gap3(), Some(17));
+
+    // The last sector has an ID field but no data field.
+    let last = &gaps.sectors[2];
+    assert_eq!(last.chsn.s(), 3);
+    assert_eq!(last.id_sync, 6);
+    assert_eq!((last.gap2, last.data_sync, last.gap3), (None, None, None));
+    assert_eq!(gaps.gap4b, 6250 - (60 + 2 * 574 + 40 + 17 + 6 + 10));
+
+    assert!(image.track_gaps(DiskCh::new(40, 0)).is_err());
+}
+
+#[test]
+fn test_index_mark() {
+    init();
+
+    // ISO formats such as the PC 360K format have no IAM.
+    let mut image = build_image();
+    let iam = image.index_mark(DiskCh::new(0, 0)).unwrap();
+    assert!(!iam.is_present());
+    assert_eq!((iam.bit_index, iam.gap4a, iam.sync, iam.gap1), (None, None, None, None));
+    assert_eq!(iam.gap4b, image.track_gaps(DiskCh::new(0, 0)).unwrap().gap4b);
+
+    // An IBM layout: GAP4A, sync, IAM, GAP1, then the sectors.
+    let ch = DiskCh::new(0, 0);
+    let builder = TrackBuilder::new()
+        .with_gap(0x4E, 80)
+        .with_gap(0x00, 12)
+        .with_encoded(&[0xC2, 0xC2, 0xC2, 0xFC], MfmEncodingType::AddressMark)
+        .with_gap(0x4E, 50)
+        .with_sector(&image, DiskChs::new(0, 0, 1), None, MatchPolicy::Chsn)
+        .unwrap();
+    image.splice_track(ch, &builder).unwrap();
+
+    let iam = image.index_mark(ch).unwrap();
+    assert!(iam.is_present());
+    assert_eq!(iam.bit_index, Some((80 + 12) * 16));
+    assert_eq!(iam.gap4a, Some(80));
+    assert_eq!(iam.sync, Some(12));
+    assert_eq!(iam.gap1, Some(50));
+    assert_eq!(iam.gap4b, 6250 - (80 + 12 + 4 + 50 + 574));
+
+    assert!(image.index_mark(DiskCh::new(40, 0)).is_err());
+}
@@ -0,0 +1,50 @@
+use fluxfox::structure_parsers::system34::System34Standard;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskChsn, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn format(image: &mut DiskImage, ch: DiskCh, order: &[u8]) {
+    let format_buffer = order.iter().map(|s| DiskChsn::new(ch.c(), ch.h(), *s, 2)).collect();
+    image
+        .format_track(ch, System34Standard::Iso, format_buffer, 0xF6, 0x50)
+        .unwrap();
+}
+
+#[test]
+fn test_track_layouts() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    // Head 1 skewed by two sectors, then cylinder 1 at 2:1 interleave, skewed by one sector.
+    format(&mut image, DiskCh::new(0, 1), &[8, 9, 1, 2, 3, 4, 5, 6, 7]);
+    format(&mut image, DiskCh::new(1, 0), &[5, 1, 6, 2, 7, 3, 8, 4, 9]);
+
+    let layouts = image.track_layouts();
+    assert_eq!(layouts.len(), 80);
+    let layout = |c: u16, h: u8| layouts.iter().find(|l| l.ch == DiskCh::new(c, h)).unwrap();
+
+    let track = layout(0, 0);
+    assert_eq!(track.sector_order, (1..=9).collect::<Vec<u8>>());
+    assert_eq!(track.interleave, Some(1));
+    assert_eq!((track.head_skew, track.cylinder_skew), (None, None));
+    assert_eq!(track.gaps.as_ref().unwrap().common_gap3(), Some(0x50));
+
+    let track = layout(0, 1);
+    assert_eq!(track.interleave, Some(1));
+    assert_eq!(track.head_skew, Some(2));
+
+    let track = layout(1, 0);
+    assert_eq!(track.interleave, Some(2));
+    assert_eq!(track.cylinder_skew, Some(1));
+    assert_eq!(
+        track.to_string(),
+        "[c:1 h:0] interleave: 2 head skew: - cylinder skew: 1 gap3: 80 order: 5 1 6 2 7 3 8 4 9"
+    );
+
+    // Cylinder 2 is back in order, one position behind cylinder 1.
+    assert_eq!(layout(2, 0).cylinder_skew, Some(8));
+    assert_eq!(layout(1, 1).cylinder_skew, Some(7));
+}
@@ -0,0 +1,124 @@
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::testutil::track_stream;
+use fluxfox::track_builder::TrackBuilder;
+use fluxfox::{
+    DiskCh, DiskDataResolution, DiskImage, DiskImageFormat, ImageParser, ParserWriteCompatibility, StandardFormat,
+};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+    image
+        .splice_track(DiskCh::new(5, 0), &TrackBuilder::new().with_bitcell_ct(101_003))
+        .unwrap();
+    image
+        .splice_track(DiskCh::new(6, 1), &TrackBuilder::new().with_bitcell_ct(98_501))
+        .unwrap();
+    image
+}
+
+#[test]
+fn test_track_length_roundtrip() {
+    init();
+
+    for format in [DiskImageFormat::F86Image, DiskImageFormat::PceBitstreamImage] {
+        let image = build_image();
+        let mut out = Cursor::new(Vec::new());
+        format.save_image(&image, &mut out).unwrap();
+        let reloaded = DiskImage::load(&mut Cursor::new(out.into_inner())).unwrap();
+
+        let len = |ch: DiskCh| reloaded.get_track_ch(ch).unwrap().bitcell_ct();
+        assert_eq!(len(DiskCh::new(5, 0)), 101_003, "{:?}", format);
+        assert_eq!(len(DiskCh::new(6, 1)), 98_501, "{:?}", format);
+        assert_eq!(len(DiskCh::new(0, 0)), 100_000, "{:?}", format);
+        let source_len = |ch: DiskCh| reloaded.get_track_ch(ch).unwrap().source_bitcell_ct();
+        assert_eq!(source_len(DiskCh::new(5, 0)), Some(101_003), "{:?}", format);
+    }
+}
+
+/// Build an 86F image in extra bitcell mode, where the bitcell count of each track header is a
+/// signed adjustment to the nominal length of the track, and the track data fills a buffer of
+/// twice the nominal length.
+fn build_f86_extra_bitcells(image: &DiskImage) -> Vec<u8> {
+    // Extra bitcell mode, two sides, double density.
+    let mut f86 = b"86BF".to_vec();
+    f86.extend([0x0C, 0x02]);
+    f86.extend(0x0088u16.to_le_bytes());
+
+    let table_pos = f86.len();
+    f86.resize(table_pos + 512 * 4, 0);
+    for c in 0..40 {
+        for h in 0..2 {
+            let entry = c as usize * 2 + h as usize;
+            let offset = f86.len() as u32;
+            f86[table_pos + entry * 4..table_pos + entry * 4 + 4].copy_from_slice(&offset.to_le_bytes());
+
+            let track = image.get_track_ch(DiskCh::new(c, h)).unwrap();
+            let extra = (track.bitcell_ct() as i32 - 100_000) as u32;
+            // 250Kbps MFM at 300RPM.
+            f86.extend(0x000Au16.to_le_bytes());
+            f86.extend(extra.to_le_bytes());
+            f86.extend(0u32.to_le_bytes());
+
+            let mut data = track_stream(image, DiskCh::new(c, h)).unwrap().data();
+            data.resize(25_000, 0);
+            f86.extend(data);
+        }
+    }
+    f86
+}
+
+#[test]
+fn test_track_length_f86_extra_bitcells() {
+    init();
+
+    let image = build_image();
+    let reloaded = DiskImage::load(&mut Cursor::new(build_f86_extra_bitcells(&image))).unwrap();
+    let track = |ch: DiskCh| reloaded.get_track_ch(ch).unwrap();
+    assert_eq!(track(DiskCh::new(5, 0)).bitcell_ct(), 101_003);
+    assert_eq!(track(DiskCh::new(5, 0)).source_bitcell_ct(), Some(101_003));
+    assert_eq!(track(DiskCh::new(6, 1)).bitcell_ct(), 98_501);
+    assert_eq!(track(DiskCh::new(0, 0)).bitcell_ct(), 100_000);
+}
+
+#[test]
+fn test_track_length_hfe() {
+    init();
+
+    // HFE stores one length per cylinder in 16-bit words, so only the long cylinder survives.
+    let mut image = build_image();
+    assert!(matches!(
+        DiskImageFormat::HfeImage.can_write(&image),
+        ParserWriteCompatibility::DataLoss
+    ));
+    image
+        .splice_track(DiskCh::new(5, 0), &TrackBuilder::new().with_bitcell_ct(101_008))
+        .unwrap();
+    image
+        .splice_track(DiskCh::new(5, 1), &TrackBuilder::new().with_bitcell_ct(101_008))
+        .unwrap();
+    image
+        .splice_track(DiskCh::new(6, 1), &TrackBuilder::new().with_bitcell_ct(100_000))
+        .unwrap();
+    assert!(matches!(
+        DiskImageFormat::HfeImage.can_write(&image),
+        ParserWriteCompatibility::Ok
+    ));
+
+    let mut out = Cursor::new(Vec::new());
+    DiskImageFormat::HfeImage.save_image(&image, &mut out).unwrap();
+    let reloaded = DiskImage::load(&mut Cursor::new(out.into_inner())).unwrap();
+    let len = |ch: DiskCh| reloaded.get_track_ch(ch).unwrap().bitcell_ct();
+    assert_eq!(len(DiskCh::new(5, 0)), 101_008);
+    assert_eq!(len(DiskCh::new(5, 1)), 101_008);
+    assert_eq!(len(DiskCh::new(0, 0)), 100_000);
+}
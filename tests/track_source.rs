@@ -0,0 +1,46 @@
+use fluxfox::diskimage::LoadOptions;
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskImage, DiskImageFormat, ImageParser, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_track_source() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+
+    let mut out_buffer = Cursor::new(Vec::new());
+    DiskImageFormat::F86Image.save_image(&image, &mut out_buffer).unwrap();
+    let image_buf = out_buffer.into_inner();
+
+    // Track payloads are discarded unless requested.
+    let f86_image = DiskImage::load(&mut Cursor::new(&image_buf)).unwrap();
+    assert!(f86_image.track_source(DiskCh::new(0, 0)).is_none());
+
+    let options = LoadOptions {
+        retain_track_sources: true,
+        ..Default::default()
+    };
+    let f86_image = DiskImage::load_with_options(&mut Cursor::new(&image_buf), options).unwrap();
+
+    // The retained payload for each track should be the exact bytes at its offset in the file.
+    for ch in [DiskCh::new(0, 0), DiskCh::new(0, 1), DiskCh::new(39, 1)] {
+        let source = f86_image.track_source(ch).unwrap();
+        let start = source.offset as usize;
+        assert!(!source.data.is_empty());
+        assert_eq!(&image_buf[start..start + source.data.len()], source.data.as_slice());
+    }
+
+    // Sector-based loaders do not retain track payloads.
+    let imd_buf = std::fs::read("tests/images/Transylvania.imd").unwrap();
+    let options = LoadOptions {
+        retain_track_sources: true,
+        ..Default::default()
+    };
+    let imd_image = DiskImage::load_with_options(&mut Cursor::new(imd_buf), options).unwrap();
+    assert!(imd_image.track_source(DiskCh::new(0, 0)).is_none());
+}
@@ -0,0 +1,114 @@
+use fluxfox::convert::{ConvertAction, ConvertIssueKind, ConvertPolicy, TrailingTracks};
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskCh, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// A formatted 360K image followed by two unformatted cylinders.
+fn image_with_empty_tail() -> DiskImage {
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let bitcells = image.get_track_ch(DiskCh::new(0, 0)).unwrap().bitcell_ct();
+    for c in 40..42 {
+        for h in 0..2 {
+            image
+                .add_empty_track(
+                    DiskCh::new(c, h),
+                    DiskDataEncoding::Mfm,
+                    DiskDataRate::Rate250Kbps,
+                    bitcells,
+                )
+                .unwrap();
+        }
+    }
+    image
+}
+
+fn save(image: &mut DiskImage, format: DiskImageFormat, policy: ConvertPolicy) -> Vec<u8> {
+    let mut out = Cursor::new(Vec::new());
+    image.save_with_policy(format, policy, &mut out).unwrap();
+    out.into_inner()
+}
+
+fn hfe_cylinders(hfe: Vec<u8>) -> usize {
+    DiskImage::load(&mut Cursor::new(hfe)).unwrap().get_track_ct(0)
+}
+
+#[test]
+fn test_trailing_tracks_auto() {
+    init();
+    let mut image = image_with_empty_tail();
+
+    // Raw images are trimmed to their exact size, while track formats keep every track.
+    let raw = save(&mut image, DiskImageFormat::RawSectorImage, ConvertPolicy::strict());
+    assert_eq!(raw.len(), 368_640);
+
+    let hfe = save(&mut image, DiskImageFormat::HfeImage, ConvertPolicy::default());
+    assert_eq!(hfe_cylinders(hfe), 42);
+
+    // The source image is left untouched.
+    assert_eq!(image.get_track_ct(0), 42);
+}
+
+#[test]
+fn test_trailing_tracks_keep() {
+    init();
+    let mut image = image_with_empty_tail();
+
+    let policy = ConvertPolicy {
+        trailing_tracks: TrailingTracks::Keep,
+        ..ConvertPolicy::strict()
+    };
+    let mut out = Cursor::new(Vec::new());
+    assert!(image
+        .save_with_policy(DiskImageFormat::RawSectorImage, policy, &mut out)
+        .is_err());
+
+    let policy = ConvertPolicy {
+        missing: ConvertAction::ZeroFill,
+        ..policy
+    };
+    let mut out = Cursor::new(Vec::new());
+    let report = image
+        .save_with_policy(DiskImageFormat::RawSectorImage, policy, &mut out)
+        .unwrap();
+    assert_eq!(out.into_inner().len(), 42 * 2 * 9 * 512);
+    assert_eq!(report.issues_of(ConvertIssueKind::Missing).count(), 2 * 2 * 9);
+}
+
+#[test]
+fn test_trailing_tracks_trim() {
+    init();
+    let mut image = image_with_empty_tail();
+
+    let policy = ConvertPolicy {
+        trailing_tracks: TrailingTracks::Trim,
+        ..ConvertPolicy::default()
+    };
+    let hfe = save(&mut image, DiskImageFormat::HfeImage, policy);
+    assert_eq!(hfe_cylinders(hfe), 40);
+}
+
+#[test]
+fn test_trailing_tracks_synthesize() {
+    init();
+    // An 80-track disk dumped with its last cylinder missing.
+    let full = TestImage::Standard(StandardFormat::PcFloppy720).generate().unwrap();
+    let mut image = full.extract_tracks(0..=78, None).unwrap();
+    assert_eq!(image.nominal_cylinders(), Some(80));
+
+    let policy = ConvertPolicy {
+        trailing_tracks: TrailingTracks::Synthesize,
+        ..ConvertPolicy::default()
+    };
+    let hfe = save(&mut image, DiskImageFormat::HfeImage, policy);
+    assert_eq!(hfe_cylinders(hfe), 80);
+
+    let report = image
+        .save_with_policy(DiskImageFormat::RawSectorImage, policy, &mut Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(report.issues_of(ConvertIssueKind::Missing).count(), 2 * 9);
+    assert_eq!(image.get_track_ct(0), 79);
+}
@@ -0,0 +1,88 @@
+use fluxfox::testutil::{TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::verify::SectorAgreement;
+use fluxfox::{DiskCh, DiskChs, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn standard(format: StandardFormat) -> DiskImage {
+    TestImage::Standard(format).generate().unwrap()
+}
+
+#[test]
+fn test_verify_identical() {
+    init();
+
+    let first = standard(StandardFormat::PcFloppy360);
+    let second = standard(StandardFormat::PcFloppy360);
+    let report = first.verify_dump(&second).unwrap();
+
+    assert_eq!(report.tracks.len(), 80);
+    assert_eq!(report.sector_ct(), 720);
+    assert!(report.is_verified());
+    assert_eq!(report.confidence(), 1.0);
+    assert_eq!(report.disagreements().count(), 0);
+}
+
+#[test]
+fn test_verify_one_good() {
+    init();
+
+    let first = TestImage::BadDataCrc.generate().unwrap();
+    let second = standard(StandardFormat::PcFloppy360);
+    let report = first.verify_dump(&second).unwrap();
+
+    assert!(!report.is_verified());
+    assert_eq!(report.agreed_ct(), 719);
+
+    let disagreements: Vec<_> = report.disagreements().collect();
+    assert_eq!(disagreements.len(), 1);
+    let (ch, sector) = disagreements[0];
+    assert_eq!(ch, DiskCh::new(TEST_QUIRK_CYLINDER, 0));
+    assert_eq!(
+        DiskChs::from(sector.chsn),
+        DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR)
+    );
+    assert_eq!(sector.agreement, SectorAgreement::OneGood { good: 1 });
+}
+
+#[test]
+fn test_verify_consistent_bad_sector() {
+    init();
+
+    let first = TestImage::BadDataCrc.generate().unwrap();
+    let second = TestImage::BadDataCrc.generate().unwrap();
+    let report = first.verify_dump(&second).unwrap();
+
+    // A sector written bad reads the same way in both captures, so the dump still verifies.
+    assert!(report.is_verified());
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let sector = report
+        .tracks
+        .iter()
+        .flat_map(|t| &t.sectors)
+        .find(|s| DiskChs::from(s.chsn) == chs)
+        .unwrap();
+    assert_eq!(sector.agreement, SectorAgreement::BothBad { identical: true });
+}
+
+#[test]
+fn test_verify_missing_tracks() {
+    init();
+
+    let first = standard(StandardFormat::PcFloppy360);
+    let second = standard(StandardFormat::PcFloppy720);
+    let report = first.verify_dump(&second).unwrap();
+
+    assert!(!report.is_verified());
+    assert_eq!(report.tracks.len(), 160);
+
+    let track = report.tracks.iter().find(|t| t.ch == DiskCh::new(40, 0)).unwrap();
+    assert_eq!(track.present, [false, true]);
+    assert!(!track.is_verified());
+    assert!(track
+        .sectors
+        .iter()
+        .all(|s| s.agreement == SectorAgreement::Missing { found: 1 }));
+}
@@ -0,0 +1,71 @@
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::{DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+fn build_image() -> DiskImage {
+    ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap()
+}
+
+fn found(image: &mut DiskImage) -> Vec<(&'static str, DiskChs, usize)> {
+    image
+        .scan_viruses()
+        .unwrap()
+        .iter()
+        .map(|m| (m.signature.name, m.chs, m.offset))
+        .collect()
+}
+
+#[test]
+fn test_virus_scan_and_clean() {
+    init();
+    let mut image = build_image();
+    assert!(found(&mut image).is_empty());
+    let clean_boot = image.read_lba(0).unwrap();
+
+    // Stoned overwrites the BPB with its own code.
+    let mut stoned = vec![0xF6; 512];
+    stoned[0..5].copy_from_slice(&[0xEA, 0x05, 0x00, 0xC0, 0x07]);
+    stoned[0x18A..0x1A0].copy_from_slice(b"Your PC is now Stoned!");
+    image.write_lba(0, &stoned).unwrap();
+
+    // Michelangelo checks the date; hide it in the root directory.
+    let mut michelangelo = vec![0; 512];
+    michelangelo[0x40..0x44].copy_from_slice(&[0xB4, 0x04, 0xCD, 0x1A]);
+    michelangelo[0x50..0x54].copy_from_slice(&[0x81, 0xFA, 0x06, 0x03]);
+    image.write_lba(10, &michelangelo).unwrap();
+
+    // Half of a signature is not a match.
+    let mut partial = vec![0; 512];
+    partial[0x40..0x44].copy_from_slice(&[0xB4, 0x04, 0xCD, 0x1A]);
+    image.write_lba(11, &partial).unwrap();
+
+    // Sectors beyond cylinder 0 are not scanned.
+    let mut form = vec![0; 512];
+    form[0x100..0x11E].copy_from_slice(b"The FORM-Virus sends greetings");
+    image.write_lba(20, &form).unwrap();
+
+    assert_eq!(
+        found(&mut image),
+        vec![
+            ("Stoned", DiskChs::new(0, 0, 1), 0x18A),
+            ("Michelangelo", DiskChs::new(0, 1, 2), 0x40),
+        ]
+    );
+
+    let removed = image.clean_boot_sector().unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].signature.name, "Stoned");
+    assert_eq!(image.read_lba(0).unwrap(), clean_boot);
+    assert_eq!(found(&mut image).len(), 1);
+
+    // A clean boot sector is left alone.
+    assert!(image.clean_boot_sector().unwrap().is_empty());
+}
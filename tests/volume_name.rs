@@ -0,0 +1,63 @@
+use fluxfox::testutil::TestImage;
+use fluxfox::{DiskImage, StandardFormat};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Dump `image` to a raw sector image and load it back.
+fn reload(image: &mut DiskImage) -> DiskImage {
+    let sector_ct = image.nominal_geometry().unwrap().get_sector_count() as usize;
+    let raw: Vec<u8> = (0..sector_ct).flat_map(|lba| image.read_lba(lba).unwrap()).collect();
+    DiskImage::load(&mut Cursor::new(raw)).unwrap()
+}
+
+#[test]
+fn test_fat_volume_name() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert_eq!(reload(&mut image).volume_name(), None);
+
+    image.set_volume_label("fluxfox").unwrap();
+    assert_eq!(image.volume_name(), Some("FLUXFOX"));
+    assert_eq!(reload(&mut image).volume_name(), Some("FLUXFOX"));
+
+    // Without a root directory label entry, the label in the extended BPB is used.
+    let mut root = image.read_lba(5).unwrap();
+    root[0] = 0xE5;
+    image.write_lba(5, &root).unwrap();
+    assert_eq!(reload(&mut image).volume_name(), Some("FLUXFOX"));
+
+    // A label only in the root directory, as written by DOS versions before 4.0.
+    image.set_volume_label("").unwrap();
+    let mut root = image.read_lba(5).unwrap();
+    root[0..12].copy_from_slice(b"OLD DOS    \x08");
+    image.write_lba(5, &root).unwrap();
+    assert_eq!(reload(&mut image).volume_name(), Some("OLD DOS"));
+}
+
+#[test]
+fn test_amiga_volume_name() {
+    init();
+
+    // An AmigaDOS boot block, with the root block in the middle of the disk.
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy720).generate().unwrap();
+    let mut boot = vec![0; 512];
+    boot[0..4].copy_from_slice(b"DOS\0");
+    image.write_lba(0, &boot).unwrap();
+
+    let mut root = vec![0; 512];
+    root[3] = 2;
+    root[511] = 1;
+    root[432] = 9;
+    root[433..442].copy_from_slice(b"Workbench");
+    image.write_lba(720, &root).unwrap();
+    assert_eq!(reload(&mut image).volume_name(), Some("Workbench"));
+
+    // Not a root block.
+    root[511] = 2;
+    image.write_lba(720, &root).unwrap();
+    assert_eq!(reload(&mut image).volume_name(), None);
+}
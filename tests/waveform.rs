@@ -0,0 +1,70 @@
+use fluxfox::flux::FluxRevolution;
+use fluxfox::testutil::TestImage;
+use fluxfox::waveform::{render, WaveformOptions};
+use fluxfox::{DiskCh, DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_waveform_render() {
+    init();
+
+    // Four transitions a second apart, sampled once a second.
+    let flux = FluxRevolution::new(vec![1, 1, 1, 1], 1.0, 4);
+    let options = WaveformOptions {
+        sample_rate: 1,
+        slowdown: 1.0,
+        amplitude: 1000,
+    };
+    assert_eq!(render(&flux, &options), vec![1000, -1000, 1000, -1000]);
+
+    // A transition halfway through a sample averages out to zero.
+    let flux = FluxRevolution::new(vec![3, 1], 0.5, 4);
+    assert_eq!(render(&flux, &options), vec![1000, 0]);
+}
+
+#[test]
+fn test_waveform_export_wav() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+    let options = WaveformOptions {
+        slowdown: 10.0,
+        ..WaveformOptions::default()
+    };
+
+    let mut wav = Vec::new();
+    image.export_track_wav(ch, &options, &mut wav).unwrap();
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), options.sample_rate);
+    assert_eq!(&wav[36..40], b"data");
+
+    // One revolution of 2us bitcells, slowed down.
+    let bitcell_ct = image.get_track_ch(ch).unwrap().bitcell_ct();
+    let duration = bitcell_ct as f64 * 0.000002 * options.slowdown;
+    let sample_ct = (duration * options.sample_rate as f64).round() as usize;
+    let data_len = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+    assert!((data_len / 2).abs_diff(sample_ct) <= 1);
+    assert_eq!(wav.len(), 44 + data_len);
+
+    let samples: Vec<i16> = wav[44..].chunks(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect();
+    assert!(samples.iter().all(|s| s.abs() <= options.amplitude));
+    assert!(samples.iter().any(|&s| s > 0) && samples.iter().any(|&s| s < 0));
+}
+
+#[test]
+fn test_waveform_bad_options() {
+    init();
+
+    let image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let options = WaveformOptions {
+        slowdown: 0.0,
+        ..WaveformOptions::default()
+    };
+    let result = image.export_track_wav(DiskCh::new(0, 0), &options, Vec::new());
+    assert!(matches!(result, Err(DiskImageError::ParameterError)));
+}
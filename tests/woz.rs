@@ -0,0 +1,185 @@
+use fluxfox::bitstream::gcr::{GcrCodec, GCR_ADDRESS_PROLOGUE, GCR_DATA_PROLOGUE, GCR_EPILOGUE};
+use fluxfox::util::crc32;
+use fluxfox::{DiskCh, DiskDataEncoding, DiskDataRate, DiskImage, DiskImageFormat, QuarterTrack};
+use std::io::Cursor;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// The number of bitcells in each generated track, close to a real 5.25" track.
+const TRACK_BITS: usize = 51_200;
+const VOLUME: u8 = 254;
+
+fn push_byte(bits: &mut Vec<bool>, byte: u8) {
+    bits.extend((0..8).rev().map(|i| byte & (1 << i) != 0));
+}
+
+fn push_sync(bits: &mut Vec<bool>, count: usize) {
+    for _ in 0..count {
+        push_byte(bits, 0xFF);
+        bits.extend([false, false]);
+    }
+}
+
+fn sector_data(track: u8) -> [u8; 256] {
+    let mut data = [0u8; 256];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i as u8).wrapping_mul(3) ^ track;
+    }
+    data
+}
+
+/// Build a GCR track holding a single 6-and-2 sector, returning its bytes and bit count.
+fn build_track(track: u8) -> (Vec<u8>, usize) {
+    let mut bits = Vec::new();
+    push_sync(&mut bits, 16);
+    for nibble in GCR_ADDRESS_PROLOGUE {
+        push_byte(&mut bits, nibble);
+    }
+    for value in [VOLUME, track, 0, VOLUME ^ track] {
+        for nibble in GcrCodec::encode_44(value) {
+            push_byte(&mut bits, nibble);
+        }
+    }
+    for nibble in GCR_EPILOGUE.iter().chain(&[0xFF]) {
+        push_byte(&mut bits, *nibble);
+    }
+    push_sync(&mut bits, 6);
+    for nibble in GCR_DATA_PROLOGUE
+        .iter()
+        .chain(GcrCodec::encode_62(&sector_data(track)).iter())
+        .chain(GCR_EPILOGUE.iter())
+    {
+        push_byte(&mut bits, *nibble);
+    }
+    while bits.len() < TRACK_BITS {
+        bits.push(true);
+    }
+    bits.truncate(TRACK_BITS);
+
+    let bytes = bits
+        .chunks(8)
+        .map(|c| c.iter().fold(0u8, |acc, &b| (acc << 1) | b as u8))
+        .collect();
+    (bytes, TRACK_BITS)
+}
+
+/// Build a WOZ v2 image of 5.25" tracks. `tmap` maps quarter-track positions to indices into
+/// `tracks`.
+fn build_woz(tmap: &[(usize, u8)], tracks: &[(Vec<u8>, usize)]) -> Vec<u8> {
+    let mut woz = Vec::new();
+    woz.extend_from_slice(b"WOZ2");
+    woz.extend_from_slice(&[0xFF, 0x0A, 0x0D, 0x0A]);
+    woz.extend_from_slice(&[0; 4]);
+
+    let mut info = vec![0u8; 60];
+    info[0] = 2; // version
+    info[1] = 1; // 5.25"
+    info[2] = 1; // write protected
+    info[5..37].copy_from_slice(&[b' '; 32]);
+    info[37] = 1; // sides
+    info[39] = 32; // 4us bit timing
+    woz.extend_from_slice(b"INFO");
+    woz.extend_from_slice(&(info.len() as u32).to_le_bytes());
+    woz.extend_from_slice(&info);
+
+    let mut map = [0xFFu8; 160];
+    for &(q, trk) in tmap {
+        map[q] = trk;
+    }
+    woz.extend_from_slice(b"TMAP");
+    woz.extend_from_slice(&160u32.to_le_bytes());
+    woz.extend_from_slice(&map);
+
+    let blocks_per_track = TRACK_BITS.div_ceil(8).div_ceil(512);
+    woz.extend_from_slice(b"TRKS");
+    woz.extend_from_slice(&((160 * 8 + tracks.len() * blocks_per_track * 512) as u32).to_le_bytes());
+    let first_block = (woz.len() + 160 * 8) / 512;
+    for i in 0..160 {
+        let (block, count, bit_ct) = match tracks.get(i) {
+            Some((_, bit_ct)) => (first_block + i * blocks_per_track, blocks_per_track, *bit_ct),
+            None => (0, 0, 0),
+        };
+        woz.extend_from_slice(&(block as u16).to_le_bytes());
+        woz.extend_from_slice(&(count as u16).to_le_bytes());
+        woz.extend_from_slice(&(bit_ct as u32).to_le_bytes());
+    }
+    assert_eq!(woz.len(), first_block * 512);
+    for (bytes, _) in tracks {
+        let mut block = bytes.clone();
+        block.resize(blocks_per_track * 512, 0);
+        woz.extend_from_slice(&block);
+    }
+
+    let crc = crc32(&woz[12..]);
+    woz[8..12].copy_from_slice(&crc.to_le_bytes());
+    woz
+}
+
+/// Three whole tracks, with each whole track also mapped to its neighboring quarter-tracks, and a
+/// separately captured half-track at 1.5.
+fn build_test_woz() -> Vec<u8> {
+    let tracks = vec![build_track(0), build_track(1), build_track(2), build_track(0x15)];
+    let tmap = [(0, 0), (1, 0), (3, 1), (4, 1), (5, 1), (6, 3), (7, 2), (8, 2), (9, 2)];
+    build_woz(&tmap, &tracks)
+}
+
+#[test]
+fn test_woz_load() {
+    init();
+    let woz = build_test_woz();
+    let image = DiskImage::load(&mut Cursor::new(woz)).unwrap();
+    assert_eq!(image.source_format(), Some(DiskImageFormat::WozImage));
+    assert!(image.load_warnings().is_empty());
+
+    let descriptor = image.image_format();
+    assert_eq!(descriptor.geometry, DiskCh::new(3, 1));
+    assert!(matches!(descriptor.data_encoding, DiskDataEncoding::Gcr));
+    assert_eq!(descriptor.data_rate, DiskDataRate::Rate125Kbps);
+    assert_eq!(descriptor.write_protect, Some(true));
+
+    let track = image.get_track_ch(DiskCh::new(2, 0)).unwrap();
+    assert!(matches!(track.encoding(), DiskDataEncoding::Gcr));
+    assert_eq!(track.bitcell_ct(), TRACK_BITS);
+
+    // Only the half-track captured apart from its neighbors is loaded as a sub-track.
+    assert_eq!(image.sub_track_positions(0), vec![QuarterTrack::new(1, 2)]);
+}
+
+#[test]
+fn test_woz_read_nibbles() {
+    init();
+    let woz = build_test_woz();
+    let image = DiskImage::load(&mut Cursor::new(woz)).unwrap();
+
+    for c in 0..3u8 {
+        let nibbles = image.read_track_raw(DiskCh::new(c as u16, 0)).unwrap();
+        let addr = nibbles
+            .windows(3)
+            .position(|w| w == GCR_ADDRESS_PROLOGUE)
+            .expect("address prologue not found");
+        let track = GcrCodec::decode_44([nibbles[addr + 5], nibbles[addr + 6]]);
+        assert_eq!(track, c);
+
+        let data = nibbles
+            .windows(3)
+            .position(|w| w == GCR_DATA_PROLOGUE)
+            .expect("data prologue not found");
+        let (sector, checksum_ok) = GcrCodec::decode_62(&nibbles[data + 3..]).unwrap();
+        assert!(checksum_ok);
+        assert_eq!(sector, sector_data(c));
+    }
+}
+
+#[test]
+fn test_woz_bad_crc() {
+    init();
+    let mut woz = build_test_woz();
+    woz[8] ^= 0xFF;
+
+    let image = DiskImage::load(&mut Cursor::new(woz)).unwrap();
+    let warnings = image.load_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].message.contains("CRC"));
+}
@@ -0,0 +1,94 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::testutil::{track_stream, TestImage, TEST_QUIRK_CYLINDER, TEST_QUIRK_SECTOR};
+use fluxfox::{DiskCh, DiskChs, DiskImageError, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_write_bits_wraps_index() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let ch = DiskCh::new(0, 0);
+    let len = image.get_track_ch(ch).unwrap().bitcell_ct();
+    let pattern: Vec<bool> = (0..32).map(|i| i % 3 == 0).collect();
+
+    // Start a write 16 bitcells before the index, so that it continues past it.
+    let mut writer = image.write_bits(ch, len * 2 - 16).unwrap();
+    assert_eq!(writer.position(), len - 16);
+    writer.write_bits(pattern.iter().copied());
+    assert_eq!(writer.bits_written(), 32);
+    assert_eq!(writer.position(), 16);
+    assert_eq!(writer.finish().unwrap(), 16);
+
+    let track = image.get_track_ch(ch).unwrap();
+    assert_eq!(track.splice(), Some(16));
+    let written: Vec<bool> = track.bits_iter(len - 16).take(32).collect();
+    assert_eq!(written, pattern);
+}
+
+#[test]
+fn test_write_bits_rescans_on_end() {
+    init();
+
+    // Write a sector on one copy of the image, then record the resulting track onto another copy
+    // bit by bit.
+    let chs = DiskChs::new(0, 0, 1);
+    let mut source = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    source
+        .write_sector(chs, None, &[0x55; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+    let bits = track_stream(&source, DiskCh::new(0, 0)).unwrap().bits().clone();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    {
+        let mut writer = image.write_bits(DiskCh::new(0, 0), 0).unwrap();
+        for bit in bits.iter() {
+            writer.write_bit(bit);
+        }
+        // Dropping the channel ends the write.
+    }
+
+    let result = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert_eq!(result.read_buf, vec![0x55; 512]);
+    assert_eq!(image.get_track_ch(DiskCh::new(0, 0)).unwrap().splice(), Some(0));
+}
+
+#[test]
+fn test_write_bits_clears_weak_bits() {
+    init();
+
+    let mut image = TestImage::WeakBits.generate().unwrap();
+    let ch = DiskCh::new(TEST_QUIRK_CYLINDER, 0);
+    let chs = DiskChs::new(TEST_QUIRK_CYLINDER, 0, TEST_QUIRK_SECTOR);
+    let weak_mask = |image: &mut fluxfox::DiskImage| {
+        image
+            .read_sector(chs, None, RwSectorScope::DataOnly, false)
+            .unwrap()
+            .weak_mask
+    };
+    assert!(weak_mask(&mut image).is_some());
+
+    // Record a clean copy of the track over it.
+    let clean = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    let bits = track_stream(&clean, ch).unwrap().bits().clone();
+    let mut writer = image.write_bits(ch, 0).unwrap();
+    writer.write_bits(bits.iter());
+    writer.finish().unwrap();
+
+    assert!(weak_mask(&mut image).is_none());
+}
+
+#[test]
+fn test_write_bits_errors() {
+    init();
+
+    let mut image = TestImage::Standard(StandardFormat::PcFloppy360).generate().unwrap();
+    assert!(matches!(
+        image.write_bits(DiskCh::new(40, 0), 0),
+        Err(DiskImageError::SeekError)
+    ));
+    assert_eq!(image.get_track_ch(DiskCh::new(0, 0)).unwrap().splice(), None);
+}
@@ -0,0 +1,55 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::{DiskChs, DiskDataResolution, DiskImage, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Write sector `chs` as deleted and then as normal data, checking the mark and data read back.
+fn write_deleted_and_back(image: &mut DiskImage, chs: DiskChs) {
+    image
+        .write_sector(chs, None, &[0xD5; 512], RwSectorScope::DataOnly, true, false)
+        .unwrap();
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(rsr.deleted_mark);
+    assert!(!rsr.data_crc_error);
+    assert_eq!(&rsr.read_buf[rsr.data_idx..rsr.data_idx + 512], &[0xD5; 512]);
+
+    image
+        .write_sector(chs, None, &[0x5D; 512], RwSectorScope::DataOnly, false, false)
+        .unwrap();
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, false).unwrap();
+    assert!(!rsr.deleted_mark);
+    assert!(!rsr.data_crc_error);
+    assert_eq!(&rsr.read_buf[rsr.data_idx..rsr.data_idx + 512], &[0x5D; 512]);
+}
+
+#[test]
+fn test_write_sector_deleted_bitstream() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+    write_deleted_and_back(&mut image, DiskChs::new(3, 1, 4));
+
+    // Neighboring sectors are unaffected.
+    let rsr = image
+        .read_sector(DiskChs::new(3, 1, 5), None, RwSectorScope::DataOnly, false)
+        .unwrap();
+    assert!(!rsr.deleted_mark && !rsr.data_crc_error);
+}
+
+#[test]
+fn test_write_sector_deleted_bytestream() {
+    init();
+
+    let buf = std::fs::read("tests/images/Transylvania.img").unwrap();
+    let mut image = DiskImage::load(&mut std::io::Cursor::new(buf)).unwrap();
+    assert!(matches!(image.resolution(), DiskDataResolution::ByteStream));
+    write_deleted_and_back(&mut image, DiskChs::new(3, 1, 4));
+}
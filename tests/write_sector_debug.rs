@@ -0,0 +1,47 @@
+use fluxfox::diskimage::RwSectorScope;
+use fluxfox::image_builder::ImageBuilder;
+use fluxfox::{DiskChs, DiskDataResolution, StandardFormat};
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_write_sector_debug_overflow() {
+    init();
+
+    let mut image = ImageBuilder::new()
+        .with_resolution(DiskDataResolution::BitStream)
+        .with_standard_format(StandardFormat::PcFloppy360)
+        .with_formatted()
+        .build()
+        .unwrap();
+
+    let chs = DiskChs::new(1, 0, 1);
+    let write_data = vec![0xA5; 600];
+
+    // Write 1024 bytes into a 512 byte sector, padding the buffer out with 0xF6.
+    image
+        .write_sector_debug(chs, Some(3), &write_data, 0xF6, false)
+        .unwrap();
+
+    // Reading the sector at its header size should find the CRC missing.
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, true).unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(&rsr.read_buf[0..512], &write_data[0..512]);
+
+    // Underfill the sector. A short write should also leave the sector with a bad CRC.
+    let chs = DiskChs::new(2, 0, 1);
+    image.write_sector_debug(chs, None, &[0x5A; 256], 0x00, false).unwrap();
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, true).unwrap();
+    assert!(rsr.data_crc_error);
+    assert_eq!(&rsr.read_buf[0..256], &[0x5A; 256]);
+
+    // Writing the exact size restores a good sector.
+    image
+        .write_sector_debug(chs, Some(2), &[0x11; 16], 0x22, false)
+        .unwrap();
+    let rsr = image.read_sector(chs, None, RwSectorScope::DataOnly, true).unwrap();
+    assert!(!rsr.data_crc_error);
+    assert_eq!(&rsr.read_buf[16..512], &[0x22; 496]);
+}